@@ -0,0 +1,49 @@
+//! Search latency across metrics, quantization and index kinds on the
+//! seeded synthetic corpus of `eval::bench`; `spo-crystal bench` reports
+//! the same matrix with recall and memory
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use spo_crystal::eval::bench::{BenchMatrix, Corpus, IndexKind};
+use spo_crystal::index::{CrystalIndex, Quantization};
+use spo_crystal::metadata::Metadata;
+use spo_crystal::search::Metric;
+use spo_crystal::shard::ShardedIndex;
+
+fn bench_matrix(c: &mut Criterion) {
+    let corpus = Corpus::synthetic(20_000, 32, 256, 7).unwrap();
+    let matrix = BenchMatrix::new().with_metrics(&[Metric::Cosine, Metric::Euclidean]).with_quantizations(&[Quantization::None, Quantization::Int8]);
+    
+    let mut group = c.benchmark_group("32 queries x 20k x 256d");
+    group.sample_size(10);
+    for config in matrix.configs() {
+        let template = CrystalIndex::new(corpus.dims()).with_metric(config.metric).with_quantization(config.quantization);
+        let name = format!("{}/{}/{}", config.metric.as_str(), config.quantization.as_str(), config.index.label());
+        match config.index {
+            IndexKind::Sharded { shards } => {
+                let mut sharded = ShardedIndex::new("", &template, shards);
+                for (id, v) in corpus.vectors.iter().enumerate() {
+                    sharded.add(id as u64, v, Metadata::new()).unwrap();
+                }
+                group.bench_function(BenchmarkId::new(name, config.k), |b| {
+                    b.iter(|| corpus.queries.iter().map(|q| sharded.search(q, config.k).unwrap()).collect::<Vec<_>>())
+                });
+            }
+            kind => {
+                let mut index = template;
+                for (id, v) in corpus.vectors.iter().enumerate() {
+                    index.add(id as u64, v).unwrap();
+                }
+                let exhaustive = kind == IndexKind::Exhaustive;
+                group.bench_function(BenchmarkId::new(name, config.k), |b| {
+                    b.iter(|| corpus.queries.iter()
+                        .map(|q| if exhaustive { index.search_exhaustive(q, config.k) } else { index.search(q, config.k) })
+                        .collect::<Vec<_>>())
+                });
+            }
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_matrix);
+criterion_main!(benches);
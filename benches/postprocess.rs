@@ -0,0 +1,31 @@
+//! Post-processing a 2048 x 1024 response batch, sequential vs rayon
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use spo_crystal::index::Quantization;
+use spo_crystal::postprocess::PostProcess;
+
+fn batch(n: usize, dims: usize) -> Vec<Vec<f32>> {
+    (0..n)
+        .map(|i| (0..dims).map(|d| ((i * dims + d) as f32 * 0.37).sin() * 1.01).collect())
+        .collect()
+}
+
+fn bench_postprocess(c: &mut Criterion) {
+    let mut group = c.benchmark_group("postprocess int8");
+    group.sample_size(10);
+    for n in [64, 512, 2048] {
+        let vectors = batch(n, 1024);
+        let config = PostProcess::new().with_quantization(Quantization::Int8);
+        group.throughput(Throughput::Elements(n as u64));
+        for (name, threshold) in [("sequential", usize::MAX), ("parallel", 0)] {
+            let config = config.with_parallel_threshold(threshold);
+            group.bench_function(format!("{} {}", name, n), |b| {
+                b.iter_batched_ref(|| vectors.clone(), |v| config.apply(v), BatchSize::LargeInput)
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_postprocess);
+criterion_main!(benches);
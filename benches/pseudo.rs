@@ -0,0 +1,42 @@
+//! Offline embedding throughput on a 1MB document: the deprecated legacy
+//! scheme against `PseudoEmbedder`, at 1024 dims both
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use spo_crystal::pseudo::PseudoEmbedder;
+
+fn document(bytes: usize) -> String {
+    let words = ["the", "crystal", "stores", "subject", "predicate", "object", "triples", "Ada", "Jan",
+                 "résumé", "fingerprint", "quorum", "field", "resonance", "query,", "and", "of"];
+    let mut text = String::with_capacity(bytes + 16);
+    let mut i = 0usize;
+    while text.len() < bytes {
+        text.push_str(words[(i * 7 + i / 3) % words.len()]);
+        text.push(if i % 11 == 10 { '\n' } else { ' ' });
+        i += 1;
+    }
+    text
+}
+
+fn bench_pseudo_1mb(c: &mut Criterion) {
+    let text = document(1 << 20);
+    let embedder = PseudoEmbedder::new(1024);
+    let mut buffer = vec![0.0f32; 1024];
+    
+    let mut group = c.benchmark_group("pseudo-embed 1MB");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.bench_function("legacy generate_pseudo_embedding", |b| {
+        #[allow(deprecated)]
+        b.iter(|| spo_crystal::jina_api::generate_pseudo_embedding(&text))
+    });
+    group.bench_function("PseudoEmbedder::embed", |b| {
+        b.iter(|| embedder.embed(&text))
+    });
+    group.bench_function("PseudoEmbedder::generate_into", |b| {
+        b.iter(|| embedder.generate_into(&mut buffer, &text))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_pseudo_1mb);
+criterion_main!(benches);
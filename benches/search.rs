@@ -0,0 +1,59 @@
+//! Multi-query search throughput: per-query `top_k` vs `top_k_batch`;
+//! `CrystalIndex::search` with early abandoning vs `search_exhaustive`
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::prelude::*;
+use spo_crystal::index::CrystalIndex;
+use spo_crystal::search::{top_k, top_k_batch};
+
+fn random_vectors(n: usize, dims: usize, seed: u64) -> Vec<Vec<f32>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..n).map(|_| (0..dims).map(|_| rng.gen_range(-1.0..1.0)).collect()).collect()
+}
+
+fn bench_multi_query(c: &mut Criterion) {
+    let corpus = random_vectors(10_000, 256, 1);
+    let queries = random_vectors(64, 256, 2);
+    
+    let mut group = c.benchmark_group("64 queries x 10k x 256d");
+    group.sample_size(10);
+    group.bench_function("top_k per query", |b| {
+        b.iter(|| queries.iter().map(|q| top_k(q, &corpus, 10)).collect::<Vec<_>>())
+    });
+    group.bench_function("top_k_batch", |b| {
+        b.iter(|| top_k_batch(&queries, &corpus, 10))
+    });
+    group.finish();
+}
+
+/// Unit vectors whose component i has scale exp(-4 i / dims), front-loaded like Matryoshka embeddings
+fn front_loaded(n: usize, dims: usize, seed: u64) -> Vec<Vec<f32>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..n).map(|_| {
+        let mut v: Vec<f32> = (0..dims).map(|i| rng.gen_range(-1.0..1.0f32) * (-4.0 * i as f32 / dims as f32).exp()).collect();
+        spo_crystal::search::normalize(&mut v);
+        v
+    }).collect()
+}
+
+fn bench_early_abandon(c: &mut Criterion) {
+    let dims = 128;
+    let mut index = CrystalIndex::new(dims);
+    for (id, v) in front_loaded(1_000_000, dims, 3).iter().enumerate() {
+        index.add(id as u64, v).unwrap();
+    }
+    let queries = front_loaded(8, dims, 4);
+    
+    let mut group = c.benchmark_group("k=10 over 1M x 128d");
+    group.sample_size(10);
+    group.bench_function("search_exhaustive", |b| {
+        b.iter(|| queries.iter().map(|q| index.search_exhaustive(q, 10)).collect::<Vec<_>>())
+    });
+    group.bench_function("search (early abandon)", |b| {
+        b.iter(|| queries.iter().map(|q| index.search(q, 10)).collect::<Vec<_>>())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_multi_query, bench_early_abandon);
+criterion_main!(benches);
@@ -0,0 +1,24 @@
+//! With the `ffi` feature, writes the C header for `src/ffi.rs` to
+//! `include/spo_crystal.h`
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "ffi")]
+    ffi_header();
+}
+
+#[cfg(feature = "ffi")]
+fn ffi_header() {
+    let root = std::path::PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    let config = cbindgen::Config::from_file(root.join("cbindgen.toml")).expect("cbindgen.toml");
+    // ffi.rs alone: the header needs only its items, and parsing the whole
+    // crate would run `cargo metadata` from inside the build
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(root.join("src/ffi.rs"))
+        .generate()
+        .expect("cbindgen failed on src/ffi.rs")
+        .write_to_file(root.join("include/spo_crystal.h"));
+}
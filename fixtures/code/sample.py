@@ -0,0 +1,25 @@
+import math
+
+
+# Distance helpers
+def euclidean(a, b):
+    total = 0.0
+
+    for x, y in zip(a, b):
+        total += (x - y) ** 2
+    return math.sqrt(total)
+
+
+class Point:
+    """A 2D point."""
+
+    def __init__(self, x, y):
+        self.x = x
+        self.y = y
+
+    def norm(self):
+        return math.hypot(self.x, self.y)
+
+
+def braces():
+    return "{ not a block"
@@ -0,0 +1,34 @@
+use std::collections::HashMap;
+
+/// Word counts for `text`
+///
+/// Splits on whitespace.
+pub fn word_counts(text: &str) -> HashMap<&str, usize> {
+    let mut counts = HashMap::new();
+
+    for word in text.split_whitespace() {
+        *counts.entry(word).or_insert(0) += 1;
+    }
+    counts
+}
+
+// Brace characters inside literals must not confuse the splitter
+fn braces() -> (char, &'static str) {
+    let open = '{';
+
+    (open, "}}} not a close")
+}
+
+pub struct Counter {
+    total: usize,
+}
+
+impl Counter {
+    pub fn add(&mut self, n: usize) {
+        self.total += n;
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+}
@@ -0,0 +1,110 @@
+#ifndef SPO_CRYSTAL_H
+#define SPO_CRYSTAL_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result of an FFI call
+ */
+typedef enum SpoStatus {
+  SPO_STATUS_OK = 0,
+  /**
+   * A required pointer argument was null
+   */
+  SPO_STATUS_NULL_POINTER = 1,
+  /**
+   * A string argument was not UTF-8
+   */
+  SPO_STATUS_INVALID_UTF8 = 2,
+  /**
+   * The output buffer is too small; `out_len` holds the floats needed
+   */
+  SPO_STATUS_BUFFER_TOO_SMALL = 3,
+  /**
+   * Embedding failed; `spo_last_error_message` says why
+   */
+  SPO_STATUS_EMBED_FAILED = 4,
+  /**
+   * A Rust panic was caught at the boundary
+   */
+  SPO_STATUS_PANIC = 5,
+} SpoStatus;
+
+/**
+ * Opaque client handle
+ */
+typedef struct SpoClient SpoClient;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * New client calling the Jina API with `api_key`; an empty key embeds
+ * offline. Null if `api_key` is null or not UTF-8.
+ *
+ * # Safety
+ * `api_key` is null or a NUL-terminated string.
+ */
+struct SpoClient *spo_client_new(const char *api_key);
+
+/**
+ * Free a client from `spo_client_new`; null is ignored
+ *
+ * # Safety
+ * `client` is null or came from `spo_client_new` and is not used again.
+ */
+void spo_client_free(struct SpoClient *client);
+
+/**
+ * Embed `text` into the `*out_len` floats at `out_ptr`
+ *
+ * # Safety
+ * `client` is a live client, `text` a NUL-terminated string, and `out_ptr`
+ * points to at least `*out_len` writable floats.
+ */
+enum SpoStatus spo_embed(struct SpoClient *client,
+                         const char *text,
+                         float *out_ptr,
+                         size_t *out_len);
+
+/**
+ * Embed `count` texts into `out_ptr`, one row after another in input order
+ *
+ * # Safety
+ * As `spo_embed`, with `texts` pointing to `count` NUL-terminated strings.
+ */
+enum SpoStatus spo_embed_batch(struct SpoClient *client,
+                               const char *const *texts,
+                               size_t count,
+                               float *out_ptr,
+                               size_t *out_len);
+
+/**
+ * Cosine similarity of two `len`-float vectors; NaN if either is null
+ *
+ * # Safety
+ * `a` and `b` are null or point to `len` floats each.
+ */
+float spo_cosine(const float *a, const float *b, size_t len);
+
+/**
+ * Why the client's most recent call failed; null after a success. The
+ * string belongs to the client and stays valid until its next call.
+ *
+ * # Safety
+ * `client` is null or a live client.
+ */
+const char *spo_last_error_message(const struct SpoClient *client);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SPO_CRYSTAL_H */
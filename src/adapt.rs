@@ -0,0 +1,285 @@
+//! Linear adapters between embedding spaces
+//!
+//! After a model migration, queries embedded by the new model can search an
+//! index the old model built once a `LinearAdapter` maps them across. `fit`
+//! learns `W` minimizing `Σ |W·source − target|² + λ|W|²` over paired
+//! embeddings of the same texts, by the normal equations
+//! `(XᵀX + λI) Wᵀ = XᵀY`: sums accumulate in f64 and the system is solved
+//! by Cholesky factorization. Without a ridge term there must be at least
+//! as many pairs as source dims; with one, the fit is always determined.
+//!
+//! `fit_with_holdout` sets a seeded share of the pairs aside and reports
+//! the mean cosine between their mapped sources and true targets, the
+//! number to check before trusting the adapter. The map is only as good as
+//! the two spaces are linearly related: a stopgap for a transition, not a
+//! replacement for re-embedding.
+//!
+//! Adapters save as an f64 `embeddings` container, one row per target
+//! dim, so a loaded adapter maps exactly as the saved one did.
+
+use std::fmt;
+
+use rayon::prelude::*;
+
+use crate::embeddings::Embeddings;
+use crate::sample::reservoir;
+use crate::search::cosine;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AdaptError {
+    /// `transform` input with other dims than the adapter's source space
+    DimensionMismatch { expected: usize, got: usize },
+    /// Pair `pair` has `(source, target)` dims `got`, unlike the first pair's `expected`
+    PairMismatch { pair: usize, expected: (usize, usize), got: (usize, usize) },
+    /// Too few pairs to determine the map without a ridge term
+    Underdetermined { pairs: usize, dims: usize },
+    /// The source vectors are linearly dependent; a ridge term resolves it
+    Singular,
+    /// Ridge lambda negative or not finite
+    InvalidRidge(f64),
+}
+
+impl fmt::Display for AdaptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdaptError::DimensionMismatch { expected, got } => write!(f, "Dimension mismatch: expected {}, got {}", expected, got),
+            AdaptError::PairMismatch { pair, expected, got } => {
+                write!(f, "Pair {} maps {} to {} dims, others {} to {}", pair, got.0, got.1, expected.0, expected.1)
+            }
+            AdaptError::Underdetermined { pairs, dims } => {
+                write!(f, "{} pairs cannot determine a map from {} dims; add pairs or a ridge term", pairs, dims)
+            }
+            AdaptError::Singular => write!(f, "Source vectors are linearly dependent; add a ridge term"),
+            AdaptError::InvalidRidge(lambda) => write!(f, "Ridge lambda must be finite and non-negative, got {}", lambda),
+        }
+    }
+}
+
+impl std::error::Error for AdaptError {}
+
+impl From<AdaptError> for String {
+    fn from(e: AdaptError) -> Self { e.to_string() }
+}
+
+/// Embeddings of one text by the source and target models
+pub type Pair = (Vec<f32>, Vec<f32>);
+
+/// How well an adapter maps pairs it was not fitted on
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AdapterQuality {
+    pub pairs: usize,
+    /// Mean cosine between mapped sources and their targets
+    pub mean_cosine: f32,
+    pub min_cosine: f32,
+}
+
+/// A learned linear map from one embedding space to another
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinearAdapter {
+    /// `out_dims` rows of `in_dims`
+    weights: Vec<Vec<f64>>,
+    in_dims: usize,
+}
+
+impl LinearAdapter {
+    /// Ridge regression of targets on sources over `pairs`
+    pub fn fit(pairs: &[Pair], ridge_lambda: f64) -> Result<Self, AdaptError> {
+        if !ridge_lambda.is_finite() || ridge_lambda < 0.0 {
+            return Err(AdaptError::InvalidRidge(ridge_lambda));
+        }
+        let dims = pairs.first().map_or((0, 0), |(x, y)| (x.len(), y.len()));
+        if let Some(pair) = pairs.iter().position(|(x, y)| (x.len(), y.len()) != dims) {
+            return Err(AdaptError::PairMismatch { pair, expected: dims, got: (pairs[pair].0.len(), pairs[pair].1.len()) });
+        }
+        let (d, m) = dims;
+        if pairs.is_empty() || (ridge_lambda == 0.0 && pairs.len() < d) {
+            return Err(AdaptError::Underdetermined { pairs: pairs.len(), dims: d });
+        }
+        
+        // Columns of X and Y, so each entry of XᵀX and XᵀY is one dot product
+        let column = |i: usize, target: bool| -> Vec<f64> {
+            pairs.iter().map(|(x, y)| if target { y[i] } else { x[i] } as f64).collect()
+        };
+        let xs: Vec<Vec<f64>> = (0..d).into_par_iter().map(|i| column(i, false)).collect();
+        let ys: Vec<Vec<f64>> = (0..m).into_par_iter().map(|i| column(i, true)).collect();
+        let dot = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f64>();
+        let mut gram: Vec<Vec<f64>> = (0..d).into_par_iter()
+            .map(|i| (0..=i).map(|j| dot(&xs[i], &xs[j]) + if i == j { ridge_lambda } else { 0.0 }).collect())
+            .collect();
+        let cross: Vec<Vec<f64>> = (0..d).into_par_iter().map(|i| ys.iter().map(|y| dot(&xs[i], y)).collect()).collect();
+        
+        cholesky(&mut gram)?;
+        let solved = solve_cholesky(&gram, cross);
+        let weights = (0..m).map(|j| solved.iter().map(|row| row[j]).collect()).collect();
+        Ok(Self { weights, in_dims: d })
+    }
+    
+    /// `fit` on all but a seeded `holdout` fraction of `pairs`, with the
+    /// quality measured on the pairs held out
+    pub fn fit_with_holdout(pairs: &[Pair], ridge_lambda: f64, holdout: f64, seed: u64)
+                            -> Result<(Self, AdapterQuality), AdaptError> {
+        let held = ((pairs.len() as f64 * holdout.clamp(0.0, 1.0)).round() as usize).min(pairs.len().saturating_sub(1));
+        let mut is_held = vec![false; pairs.len()];
+        reservoir(0..pairs.len(), held, seed).into_iter().for_each(|i| is_held[i] = true);
+        let (test, train): (Vec<usize>, Vec<usize>) = (0..pairs.len()).partition(|&i| is_held[i]);
+        let pick = |ids: Vec<usize>| -> Vec<_> { ids.into_iter().map(|i| pairs[i].clone()).collect() };
+        let adapter = Self::fit(&pick(train), ridge_lambda)?;
+        let quality = adapter.evaluate(&pick(test))?;
+        Ok((adapter, quality))
+    }
+    
+    /// Cosines between mapped sources and targets over `pairs`; zero for no pairs
+    pub fn evaluate(&self, pairs: &[Pair]) -> Result<AdapterQuality, AdaptError> {
+        let cosines = pairs.par_iter().enumerate().map(|(pair, (x, y))| {
+            if y.len() != self.out_dims() {
+                return Err(AdaptError::PairMismatch { pair, expected: (self.in_dims, self.out_dims()), got: (x.len(), y.len()) });
+            }
+            Ok(cosine(&self.transform(x)?, y))
+        }).collect::<Result<Vec<f32>, AdaptError>>()?;
+        if cosines.is_empty() {
+            return Ok(AdapterQuality { pairs: 0, mean_cosine: 0.0, min_cosine: 0.0 });
+        }
+        Ok(AdapterQuality {
+            pairs: cosines.len(),
+            mean_cosine: cosines.iter().sum::<f32>() / cosines.len() as f32,
+            min_cosine: cosines.iter().cloned().fold(f32::INFINITY, f32::min),
+        })
+    }
+    
+    pub fn in_dims(&self) -> usize { self.in_dims }
+    
+    pub fn out_dims(&self) -> usize { self.weights.len() }
+    
+    /// `W`, one row per target dim
+    pub fn weights(&self) -> &[Vec<f64>] { &self.weights }
+    
+    /// `query` mapped into the target space
+    pub fn transform(&self, query: &[f32]) -> Result<Vec<f32>, AdaptError> {
+        if query.len() != self.in_dims {
+            return Err(AdaptError::DimensionMismatch { expected: self.in_dims, got: query.len() });
+        }
+        Ok(self.weights.iter().map(|row| row.iter().zip(query).map(|(w, &x)| w * x as f64).sum::<f64>() as f32).collect())
+    }
+    
+    /// `transform` of every query, in parallel and in input order
+    pub fn transform_batch(&self, queries: &[Vec<f32>]) -> Result<Vec<Vec<f32>>, AdaptError> {
+        queries.par_iter().map(|q| self.transform(q)).collect()
+    }
+    
+    /// Write the weights as an f64 container, replacing the file
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        Embeddings::F64(self.weights.clone()).save(path)
+    }
+    
+    pub fn load(path: &str) -> Result<Self, String> {
+        match Embeddings::load(path)? {
+            Embeddings::F64(weights) if !weights.is_empty() => Ok(Self { in_dims: weights[0].len(), weights }),
+            Embeddings::F64(_) => Err(format!("{} holds no adapter weights", path)),
+            Embeddings::F32(_) => Err(format!("{} holds f32 embeddings, not adapter weights", path)),
+        }
+    }
+}
+
+/// Factor the lower triangle `a` (row `i` holds columns `0..=i`) in place
+/// into `L` with `a = LLᵀ`
+fn cholesky(a: &mut [Vec<f64>]) -> Result<(), AdaptError> {
+    for i in 0..a.len() {
+        let scale = a[i][i].abs().max(f64::MIN_POSITIVE);
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| a[i][k] * a[j][k]).sum();
+            if i == j {
+                let pivot = a[i][i] - sum;
+                // Relative to the diagonal: rounding leaves dependent columns a tiny positive pivot
+                if pivot.is_nan() || pivot <= scale * 1e-12 {
+                    return Err(AdaptError::Singular);
+                }
+                a[i][i] = pivot.sqrt();
+            } else {
+                a[i][j] = (a[i][j] - sum) / a[j][j];
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `X` with `LLᵀX = b`, one right-hand side per column of `b`
+fn solve_cholesky(l: &[Vec<f64>], mut b: Vec<Vec<f64>>) -> Vec<Vec<f64>> {
+    let n = l.len();
+    for i in 0..n {
+        for k in 0..i {
+            let (done, rest) = b.split_at_mut(i);
+            rest[0].iter_mut().zip(&done[k]).for_each(|(x, y)| *x -= l[i][k] * y);
+        }
+        b[i].iter_mut().for_each(|x| *x /= l[i][i]);
+    }
+    for i in (0..n).rev() {
+        let (head, tail) = b.split_at_mut(i + 1);
+        for (row, solved) in l[i + 1..].iter().zip(tail.iter()) {
+            head[i].iter_mut().zip(solved).for_each(|(x, y)| *x -= row[i] * y);
+        }
+        b[i].iter_mut().for_each(|x| *x /= l[i][i]);
+    }
+    b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::SampleRng;
+    
+    /// Pairs `(x, Wx + noise)` for a random `W` from 24 to 16 dims
+    fn linear_pairs(n: usize, noise: f64, seed: u64) -> (Vec<Vec<f64>>, Vec<Pair>) {
+        let mut rng = SampleRng::new(seed);
+        let w: Vec<Vec<f64>> = (0..16).map(|_| (0..24).map(|_| rng.unit() - 0.5).collect()).collect();
+        let pairs = (0..n).map(|_| {
+            let x: Vec<f32> = (0..24).map(|_| (rng.unit() - 0.5) as f32).collect();
+            let y = w.iter().map(|row| {
+                (row.iter().zip(&x).map(|(a, &b)| a * b as f64).sum::<f64>() + noise * (rng.unit() - 0.5)) as f32
+            }).collect();
+            (x, y)
+        }).collect();
+        (w, pairs)
+    }
+    
+    #[test]
+    fn test_recovers_linear_map_with_holdout_quality() {
+        let (w, pairs) = linear_pairs(400, 0.01, 7);
+        let (adapter, quality) = LinearAdapter::fit_with_holdout(&pairs, 1e-6, 0.25, 3).unwrap();
+        assert_eq!((adapter.in_dims(), adapter.out_dims(), quality.pairs), (24, 16, 100));
+        assert!(quality.mean_cosine > 0.999 && quality.min_cosine > 0.99, "{:?}", quality);
+        let error = adapter.weights().iter().flatten().zip(w.iter().flatten()).map(|(a, b)| (a - b).abs()).fold(0.0, f64::max);
+        assert!(error < 0.01, "largest weight error {}", error);
+        
+        // A strong ridge term shrinks the map toward zero
+        let shrunk = LinearAdapter::fit(&pairs, 1e4).unwrap();
+        assert!(shrunk.weights().iter().flatten().all(|x| x.abs() < 0.1));
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("v3-to-v4.adapter").to_str().unwrap().to_string();
+        adapter.save(&path).unwrap();
+        let loaded = LinearAdapter::load(&path).unwrap();
+        assert_eq!(loaded, adapter);
+        assert_eq!(loaded.transform_batch(&[pairs[0].0.clone()]).unwrap(), [adapter.transform(&pairs[0].0).unwrap()]);
+    }
+    
+    #[test]
+    fn test_typed_errors() {
+        let (_, pairs) = linear_pairs(40, 0.0, 1);
+        assert_eq!(LinearAdapter::fit(&pairs[..10], 0.0).unwrap_err(), AdaptError::Underdetermined { pairs: 10, dims: 24 });
+        assert!(LinearAdapter::fit(&pairs[..10], 0.1).is_ok());
+        assert_eq!(LinearAdapter::fit(&[], 0.1).unwrap_err(), AdaptError::Underdetermined { pairs: 0, dims: 0 });
+        assert_eq!(LinearAdapter::fit(&pairs, -1.0).unwrap_err(), AdaptError::InvalidRidge(-1.0));
+        
+        let mut mixed = pairs.clone();
+        mixed[5].1.pop();
+        assert_eq!(LinearAdapter::fit(&mixed, 0.1).unwrap_err(), AdaptError::PairMismatch { pair: 5, expected: (24, 16), got: (24, 15) });
+        
+        // Enough pairs, but every source repeats one of two directions
+        let dependent: Vec<_> = (0..40).map(|i| pairs[i % 2].clone()).collect();
+        assert_eq!(LinearAdapter::fit(&dependent, 0.0).unwrap_err(), AdaptError::Singular);
+        
+        let adapter = LinearAdapter::fit(&pairs, 0.0).unwrap();
+        let err = adapter.transform(&[1.0; 23]).unwrap_err();
+        assert_eq!(err.to_string(), "Dimension mismatch: expected 24, got 23");
+    }
+}
@@ -0,0 +1,187 @@
+//! Matching records between two corpora by embedding similarity
+//!
+//! `match_corpora` embeds both sides, finds each left record's best
+//! `candidates` right records with `search::top_k_batch` (blocks of
+//! queries against the corpus, never the full cross matrix) and keeps the
+//! pairs scoring at least `threshold`. `GreedyOneToOne` then takes pairs
+//! best first, skipping any whose left or right record is already
+//! matched; `TopK` keeps up to k matches per left record.
+//!
+//! Greedy matching only sees each record's top `candidates`: a left record
+//! whose candidates were all taken stays unmatched even if a weaker
+//! partner exists further down. Raise `candidates` to trade time for that.
+
+use crate::jina_api::{EmbedOptions, Task};
+use crate::provider::{EmbedError, EmbeddingProvider};
+use crate::search::top_k_batch;
+
+/// How matched pairs are chosen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// Each record in at most one pair, best pairs first
+    GreedyOneToOne,
+    /// Up to k right records per left record; right records may repeat
+    TopK(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlignOptions {
+    /// Lowest cosine similarity a pair may have
+    pub threshold: f32,
+    pub strategy: Strategy,
+    /// Right records considered per left record
+    pub candidates: usize,
+    pub embed_options: EmbedOptions,
+}
+
+impl Default for AlignOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 0.8,
+            strategy: Strategy::GreedyOneToOne,
+            candidates: 10,
+            embed_options: EmbedOptions::default().with_task(Task::TextMatching),
+        }
+    }
+}
+
+impl AlignOptions {
+    pub fn new() -> Self { Self::default() }
+    
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+    
+    pub fn with_strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+    
+    pub fn with_candidates(mut self, n: usize) -> Self {
+        self.candidates = n.max(1);
+        self
+    }
+    
+    pub fn with_embed_options(mut self, options: EmbedOptions) -> Self {
+        self.embed_options = options;
+        self
+    }
+}
+
+/// One matched pair by id
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Match {
+    pub left: String,
+    pub right: String,
+    pub score: f32,
+}
+
+/// Matched pairs, best first, and the ids left without a match
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Alignment {
+    pub matches: Vec<Match>,
+    pub unmatched_left: Vec<String>,
+    pub unmatched_right: Vec<String>,
+}
+
+/// Match `(id, text)` records of `left` to those of `right`
+pub fn match_corpora<P: EmbeddingProvider + ?Sized>(left: &[(&str, &str)], right: &[(&str, &str)], provider: &P,
+                                                    options: &AlignOptions) -> Result<Alignment, EmbedError> {
+    let embed = |records: &[(&str, &str)]| -> Result<Vec<Vec<f32>>, EmbedError> {
+        if records.is_empty() {
+            return Ok(Vec::new());
+        }
+        let texts: Vec<&str> = records.iter().map(|(_, text)| *text).collect();
+        let embeddings = provider.embed_batch_with(&texts, &options.embed_options)?;
+        if embeddings.len() != texts.len() {
+            return Err(EmbedError::Mismatch { expected: texts.len(), got: embeddings.len() });
+        }
+        Ok(embeddings)
+    };
+    let (left_vectors, right_vectors) = (embed(left)?, embed(right)?);
+    let k = match options.strategy {
+        Strategy::GreedyOneToOne => options.candidates,
+        Strategy::TopK(k) => k,
+    };
+    
+    // (left, right, score) above the threshold, best first
+    let mut pairs: Vec<(usize, usize, f32)> = top_k_batch(&left_vectors, &right_vectors, k).into_iter()
+        .enumerate()
+        .flat_map(|(l, hits)| hits.into_iter().map(move |(r, score)| (l, r, score)))
+        .filter(|&(_, _, score)| score >= options.threshold)
+        .collect();
+    pairs.sort_by(|a, b| b.2.total_cmp(&a.2).then(a.0.cmp(&b.0)).then(a.1.cmp(&b.1)));
+    
+    let mut left_used = vec![false; left.len()];
+    let mut right_used = vec![false; right.len()];
+    let mut matches = Vec::new();
+    for (l, r, score) in pairs {
+        if options.strategy == Strategy::GreedyOneToOne && (left_used[l] || right_used[r]) {
+            continue;
+        }
+        left_used[l] = true;
+        right_used[r] = true;
+        matches.push(Match { left: left[l].0.to_string(), right: right[r].0.to_string(), score });
+    }
+    let unmatched = |records: &[(&str, &str)], used: &[bool]| -> Vec<String> {
+        records.iter().zip(used).filter(|(_, &used)| !used).map(|((id, _), _)| id.to_string()).collect()
+    };
+    Ok(Alignment { unmatched_left: unmatched(left, &left_used), unmatched_right: unmatched(right, &right_used), matches })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pseudo::PseudoEmbedder;
+    
+    const VENDOR_A: &[(&str, &str)] = &[
+        ("a1", "apple iphone 15 pro 128gb black"),
+        ("a2", "samsung galaxy s24 ultra 256gb"),
+        ("a3", "sony wh-1000xm5 wireless headphones"),
+        ("a4", "apple iphone 15 pro 128gb black refurbished"),
+        ("a5", "garden hose 30m green"),
+    ];
+    const VENDOR_B: &[(&str, &str)] = &[
+        ("b1", "iphone 15 pro black 128gb apple"),
+        ("b2", "galaxy s24 ultra samsung 256gb"),
+        ("b3", "sony wh-1000xm5 headphones wireless noise cancelling"),
+        ("b4", "espresso machine stainless steel"),
+    ];
+    
+    #[test]
+    fn test_greedy_is_one_to_one_by_descending_score() {
+        let provider = PseudoEmbedder::new(512);
+        let options = AlignOptions::new().with_threshold(0.5);
+        let alignment = match_corpora(VENDOR_A, VENDOR_B, &provider, &options).unwrap();
+        let pairs: Vec<(&str, &str)> = alignment.matches.iter().map(|m| (m.left.as_str(), m.right.as_str())).collect();
+        // a1 and a4 both want b1; the closer a1 gets it and a4 is left over
+        assert_eq!(pairs, [("a1", "b1"), ("a2", "b2"), ("a3", "b3")]);
+        assert!(alignment.matches.windows(2).all(|w| w[0].score >= w[1].score));
+        assert_eq!(alignment.unmatched_left, ["a4", "a5"]);
+        assert_eq!(alignment.unmatched_right, ["b4"]);
+        
+        // TopK lets b1 match both
+        let top = match_corpora(VENDOR_A, VENDOR_B, &provider, &options.clone().with_strategy(Strategy::TopK(1))).unwrap();
+        let to_b1: Vec<&str> = top.matches.iter().filter(|m| m.right == "b1").map(|m| m.left.as_str()).collect();
+        assert_eq!(to_b1.len(), 2);
+        assert!(to_b1.contains(&"a1") && to_b1.contains(&"a4"));
+    }
+    
+    #[test]
+    fn test_threshold_bounds_every_match() {
+        let provider = PseudoEmbedder::new(512);
+        let all = match_corpora(VENDOR_A, VENDOR_B, &provider, &AlignOptions::new().with_threshold(-1.0)).unwrap();
+        // With no threshold every right record finds a partner
+        assert_eq!(all.matches.len(), VENDOR_B.len());
+        let cut = all.matches[1].score;
+        let strict = match_corpora(VENDOR_A, VENDOR_B, &provider, &AlignOptions::new().with_threshold(cut)).unwrap();
+        assert!(strict.matches.iter().all(|m| m.score >= cut));
+        assert_eq!(strict.matches, all.matches[..2]);
+        assert_eq!(strict.unmatched_left.len() + strict.matches.len(), VENDOR_A.len());
+        
+        let empty = match_corpora(&[], VENDOR_B, &provider, &AlignOptions::new()).unwrap();
+        assert_eq!((empty.matches.len(), empty.unmatched_right.len()), (0, VENDOR_B.len()));
+    }
+}
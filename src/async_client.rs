@@ -0,0 +1,526 @@
+//! Async Jina client for hosts without blocking I/O
+//!
+//! WebAssembly hosts (browsers, Cloudflare Workers) can neither open a
+//! `TcpStream` nor spawn `curl`, so on wasm32 `JinaClient` has no HTTP
+//! transport. `AsyncJinaClient` builds the same requests and hands them to
+//! an `AsyncTransport` the host supplies:
+//! - `FetchTransport` (`wasm` feature, wasm32 only) calls the global `fetch`
+//! - any `Fn(HttpRequest) -> impl Future<Output = Result<HttpResponse, JinaError>>`
+//!   closure is a transport too, which is how tests serve responses
+//!
+//! Requests go out one after another, at most `max_batch_size` texts each
+//! (`async_stream` overlaps them),
+//! and are not retried, deduplicated or cached; there is no timer to back
+//! off with on wasm32. Without a transport the client embeds offline with
+//! `PseudoEmbedder`, like `JinaClient::new`.
+//!
+//! `embed_batch_call` takes `CallOptions`. A deadline is checked on the
+//! client's clock before each request, whose `HttpRequest::timeout` becomes
+//! the time left, so the future resolves by the deadline whenever the
+//! transport enforces timeouts (`FetchTransport` aborts the fetch). wasm32
+//! has no `Instant`; set `timeout` there instead.
+//!
+//! Identical requests in flight at once, such as many tasks embedding one
+//! hot string, are sent once: the others await that response
+//! (`coalesce::AsyncSingleFlight`), error included.
+//!
+//! `CallOptions::hedge_after` trims tail latency on interactive queries:
+//! given a timer (`with_timer`), a request still unanswered after the delay
+//! is sent once more, and whichever response arrives first is used; the
+//! other future is dropped, which aborts it where the transport supports
+//! that. A transport error on one waits for the other. Only embedding
+//! requests are hedged, since they change nothing upstream, and `stats()`
+//! counts the hedges.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+
+use crate::coalesce::AsyncSingleFlight;
+use crate::document::LongInputStrategy;
+use crate::error::JinaError;
+use crate::hash::{sha256, ContentKey};
+use crate::jina_api::{parse_jina_response, write_request_body, CallOptions, ClientStats, EmbedOptions, JINA_API_URL,
+                      JINA_EMBED_ENDPOINT, JINA_MODEL, MAX_BATCH_SIZE};
+use crate::pseudo::PseudoEmbedder;
+use crate::async_stream::AsyncTimer;
+use crate::tokens::Approximate;
+use crate::transport::{check_status, Clock, Deadline, HttpRequest, HttpResponse, SystemClock};
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub use fetch::FetchTransport;
+
+/// A pending response; not `Send`, since JavaScript futures are not
+pub type SendFuture<'a> = Pin<Box<dyn Future<Output = Result<HttpResponse, JinaError>> + 'a>>;
+
+/// Sends one request without blocking; errors only when no HTTP response was received
+pub trait AsyncTransport {
+    fn send(&self, request: HttpRequest) -> SendFuture<'_>;
+    
+    fn label(&self) -> &'static str { "custom" }
+}
+
+impl<F, Fut> AsyncTransport for F
+where
+    F: Fn(HttpRequest) -> Fut,
+    Fut: Future<Output = Result<HttpResponse, JinaError>> + 'static,
+{
+    fn send(&self, request: HttpRequest) -> SendFuture<'_> { Box::pin(self(request)) }
+}
+
+pub struct AsyncJinaClient {
+    api_key: String,
+    model: String,
+    base_url: String,
+    pub(crate) max_batch_size: usize,
+    timeout: Option<Duration>,
+    transport: Option<Box<dyn AsyncTransport>>,
+    /// For `embed_stream` batch windows
+    pub(crate) timer: Option<Box<dyn AsyncTimer>>,
+    clock: Arc<dyn Clock>,
+    /// Request bodies in flight, by digest
+    flights: AsyncSingleFlight<ContentKey, Result<Vec<Vec<f32>>, JinaError>>,
+    requests: AtomicU64,
+    texts_sent: AtomicU64,
+    hedges: AtomicU64,
+}
+
+impl AsyncJinaClient {
+    pub fn new(api_key: &str) -> Self {
+        crate::error::register_secret(api_key);
+        Self {
+            api_key: api_key.to_string(),
+            model: JINA_MODEL.to_string(),
+            base_url: JINA_API_URL.to_string(),
+            max_batch_size: MAX_BATCH_SIZE,
+            timeout: None,
+            transport: None,
+            timer: None,
+            clock: Arc::new(SystemClock),
+            flights: AsyncSingleFlight::new(),
+            requests: AtomicU64::new(0),
+            texts_sent: AtomicU64::new(0),
+            hedges: AtomicU64::new(0),
+        }
+    }
+    
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+    
+    /// Send requests to `url` (e.g. a proxy) instead of `https://api.jina.ai`
+    pub fn with_base_url(mut self, url: &str) -> Self {
+        self.base_url = url.trim_end_matches('/').to_string();
+        self
+    }
+    
+    /// Call the Jina API through `transport` instead of the offline embedder
+    pub fn with_transport(mut self, transport: impl AsyncTransport + 'static) -> Self {
+        self.transport = Some(Box::new(transport));
+        self
+    }
+    
+    pub fn with_max_batch_size(mut self, n: usize) -> Self {
+        self.max_batch_size = n.clamp(1, MAX_BATCH_SIZE);
+        self
+    }
+    
+    /// Per-request timeout, passed to the transport in `HttpRequest::timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    
+    /// Check `CallOptions::deadline` against `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+    
+    pub fn is_online(&self) -> bool { self.transport.is_some() }
+    
+    /// Whether embeddings are pseudo-embeddings, as without a transport
+    pub fn is_offline(&self) -> bool { !self.is_online() }
+    
+    /// Requests sent, hedges included; this client has no cache
+    pub fn stats(&self) -> ClientStats {
+        ClientStats {
+            requests: self.requests.load(Ordering::Relaxed),
+            texts_sent: self.texts_sent.load(Ordering::Relaxed),
+            cache_hits: 0,
+            hedges: self.hedges.load(Ordering::Relaxed),
+        }
+    }
+    
+    pub async fn embed(&self, text: &str) -> Result<Vec<f32>, JinaError> {
+        let embeddings = self.embed_batch(&[text]).await?;
+        embeddings.into_iter().next().ok_or(JinaError::Mismatch { expected: 1, got: 0 })
+    }
+    
+    pub async fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, JinaError> {
+        self.embed_batch_with(texts, &EmbedOptions::default()).await
+    }
+    
+    /// Embeddings in input order; late chunking sends all texts in one request
+    pub async fn embed_batch_with(&self, texts: &[&str], options: &EmbedOptions) -> Result<Vec<Vec<f32>>, JinaError> {
+        self.embed_batch_call(texts, options, &CallOptions::default()).await
+    }
+    
+    /// `embed_batch_with` under `call`'s timeout, deadline and hedge delay;
+    /// its retries only bound hedging and its tag is ignored, as this
+    /// client neither retries nor has hooks. Hedging needs `with_timer`.
+    pub async fn embed_batch_call(&self, texts: &[&str], options: &EmbedOptions, call: &CallOptions)
+                                  -> Result<Vec<Vec<f32>>, JinaError> {
+        call.validate()?;
+        if options.dims() == 0 {
+            return Err(JinaError::InvalidInput("Embedding dimensions must be non-zero".to_string()));
+        }
+        let strategy = match options.long_inputs {
+            LongInputStrategy::Explicit => None,
+            LongInputStrategy::Reject => Some("Reject"),
+            LongInputStrategy::Truncate => Some("Truncate"),
+            LongInputStrategy::ChunkAndPool { .. } => Some("ChunkAndPool"),
+        };
+        if let Some(strategy) = strategy {
+            return Err(JinaError::InvalidInput(format!("LongInputStrategy::{} needs JinaClient", strategy)));
+        }
+        let cleaned = options.prepare(texts, &Approximate);
+        let texts: Vec<&str> = cleaned.as_ref().map_or_else(|| texts.to_vec(), |c| c.iter().map(String::as_str).collect());
+        let Some(transport) = &self.transport else {
+            if options.late_chunking {
+                return Err(JinaError::InvalidInput("late chunking needs the Jina API (with_transport)".to_string()));
+            }
+            return Ok(PseudoEmbedder::new(options.dims()).embed_batch(&texts));
+        };
+        
+        let hedge_after = call.hedge_after.filter(|_| call.max_retries != Some(0));
+        if hedge_after.is_some() && self.timer.is_none() {
+            return Err(JinaError::InvalidInput("hedge_after needs a timer (with_timer)".to_string()));
+        }
+        let batch_size = if options.late_chunking { texts.len().max(1) } else { self.max_batch_size };
+        let mut out = Vec::with_capacity(texts.len());
+        let deadline = call.deadline.map(|at| Deadline { at, clock: self.clock.as_ref() });
+        for chunk in texts.chunks(batch_size) {
+            let timeout = call.timeout.or(self.timeout);
+            let timeout = match &deadline {
+                Some(deadline) => {
+                    let left = deadline.remaining()?;
+                    Some(timeout.map_or(left, |t| t.min(left)))
+                }
+                None => timeout,
+            };
+            let mut body = String::new();
+            write_request_body(&mut body, &self.model, chunk, options);
+            let send = || {
+                let request = HttpRequest {
+                    method: "POST",
+                    url: format!("{}{}", self.base_url, JINA_EMBED_ENDPOINT),
+                    headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+                    body: body.clone().into_bytes(),
+                    timeout,
+                }.bearer(Some(&self.api_key));
+                async move {
+                    self.texts_sent.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                    let response = check_status(self.send(transport.as_ref(), request, hedge_after).await?)?;
+                    let embeddings = parse_jina_response(&response.body, options.dims())?;
+                    if embeddings.len() != chunk.len() {
+                        return Err(JinaError::Mismatch { expected: chunk.len(), got: embeddings.len() });
+                    }
+                    Ok(embeddings)
+                }
+            };
+            out.extend(self.flights.run(sha256(body.as_bytes()), send).await?);
+        }
+        Ok(out)
+    }
+    
+    /// `request`'s response, from a hedge sent `hedge_after` in if that answers first
+    async fn send(&self, transport: &dyn AsyncTransport, request: HttpRequest, hedge_after: Option<Duration>)
+                  -> Result<HttpResponse, JinaError> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let hedged = hedge_after.zip(self.timer.as_ref()).filter(|_| request.url.ends_with(JINA_EMBED_ENDPOINT));
+        let Some((delay, timer)) = hedged else { return transport.send(request).await };
+        let mut attempts = vec![transport.send(request.clone())];
+        let mut wait = Some(timer.sleep(delay));
+        let mut failed = None;
+        std::future::poll_fn(|context| {
+            let mut i = 0;
+            while i < attempts.len() {
+                match attempts[i].as_mut().poll(context) {
+                    Poll::Ready(Ok(response)) => return Poll::Ready(Ok(response)),
+                    Poll::Ready(Err(e)) => {
+                        drop(attempts.remove(i));
+                        failed.get_or_insert(e);
+                    }
+                    Poll::Pending => i += 1,
+                }
+            }
+            // A failed first attempt is not hedged: it answered
+            if attempts.is_empty() {
+                return Poll::Ready(Err(failed.take().unwrap()));
+            }
+            if wait.as_mut().is_some_and(|w| w.as_mut().poll(context).is_ready()) {
+                wait = None;
+                if failed.is_none() {
+                    self.requests.fetch_add(1, Ordering::Relaxed);
+                    self.hedges.fetch_add(1, Ordering::Relaxed);
+                    attempts.push(transport.send(request.clone()));
+                    // Poll the hedge on the next turn
+                    context.waker().wake_by_ref();
+                }
+            }
+            Poll::Pending
+        }).await
+    }
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+mod fetch {
+    use js_sys::{Array, Function, Promise, Reflect, Uint8Array};
+    use wasm_bindgen::{JsCast, JsValue};
+    use wasm_bindgen_futures::JsFuture;
+    use web_sys::{AbortSignal, Headers, Request, RequestInit, Response};
+    
+    use super::{AsyncTransport, SendFuture};
+    use crate::error::JinaError;
+    use crate::transport::{HttpRequest, HttpResponse};
+    
+    /// `AsyncTransport` over the global `fetch` of a browser, web worker or Cloudflare Worker
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct FetchTransport;
+    
+    impl FetchTransport {
+        pub fn new() -> Self { Self }
+    }
+    
+    impl AsyncTransport for FetchTransport {
+        fn send(&self, request: HttpRequest) -> SendFuture<'_> { Box::pin(fetch(request)) }
+        
+        fn label(&self) -> &'static str { "fetch" }
+    }
+    
+    fn describe(value: JsValue) -> String {
+        value.as_string()
+            .or_else(|| value.dyn_ref::<js_sys::Error>().map(|e| String::from(e.message())))
+            .unwrap_or_else(|| format!("{:?}", value))
+    }
+    
+    async fn fetch(request: HttpRequest) -> Result<HttpResponse, JinaError> {
+        let invalid = |e: JsValue| JinaError::InvalidInput(describe(e));
+        let headers = Headers::new().map_err(invalid)?;
+        for (name, value) in &request.headers {
+            headers.set(name, value).map_err(invalid)?;
+        }
+        let init = RequestInit::new();
+        init.set_method(request.method);
+        init.set_headers(&headers);
+        if !request.body.is_empty() {
+            init.set_body(&Uint8Array::from(request.body.as_slice()));
+        }
+        if let Some(timeout) = request.timeout {
+            init.set_signal(Some(&AbortSignal::timeout_with_u32(timeout.as_millis().min(u32::MAX as u128) as u32)));
+        }
+        let js_request = Request::new_with_str_and_init(&request.url, &init).map_err(invalid)?;
+        
+        // Windows, workers and Cloudflare Workers all keep `fetch` on the global object
+        let global = js_sys::global();
+        let fetch: Function = Reflect::get(&global, &JsValue::from_str("fetch")).ok()
+            .and_then(|f| f.dyn_into().ok())
+            .ok_or_else(|| JinaError::Transport("No global fetch() in this host".to_string()))?;
+        let promise: Promise = fetch.call1(&global, &js_request).map_err(|e| JinaError::Transport(describe(e)))?.unchecked_into();
+        // fetch rejects only when no response arrived: network, CORS and timeout failures look alike
+        let response: Response = JsFuture::from(promise).await.map_err(|e| JinaError::Transport(describe(e)))?.unchecked_into();
+        
+        let mut headers = Vec::new();
+        if let Ok(Some(entries)) = js_sys::try_iter(&response.headers()) {
+            for entry in entries.flatten() {
+                let pair = Array::from(&entry);
+                if let (Some(name), Some(value)) = (pair.get(0).as_string(), pair.get(1).as_string()) {
+                    headers.push((name, value));
+                }
+            }
+        }
+        let text = response.text().map_err(|e| JinaError::Transport(describe(e)))?;
+        let body = JsFuture::from(text).await.map_err(|e| JinaError::Transport(describe(e)))?.as_string().unwrap_or_default();
+        Ok(HttpResponse { status: response.status(), headers, body })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll, Waker};
+    
+    /// Poll to completion; the test transports are ready at once
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let mut context = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+    
+    #[test]
+    fn test_batches_go_through_the_async_transport() {
+        let seen: Arc<Mutex<Vec<(String, usize)>>> = Arc::default();
+        let log = seen.clone();
+        let client = AsyncJinaClient::new("key").with_base_url("https://proxy.example/").with_max_batch_size(2)
+            .with_transport(move |request: HttpRequest| {
+                let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                let inputs = body["input"].as_array().unwrap().len();
+                log.lock().unwrap().push((request.url.clone(), inputs));
+                assert!(request.headers.contains(&("Authorization".to_string(), "Bearer key".to_string())));
+                let data: Vec<_> = (0..inputs).map(|i| serde_json::json!({ "index": i, "embedding": [i as f32, 1.0] })).collect();
+                let body = serde_json::json!({ "data": data }).to_string();
+                async move { Ok(HttpResponse { status: 200, headers: Vec::new(), body }) }
+            });
+        let options = EmbedOptions::default().with_dimensions(2);
+        let embeddings = block_on(client.embed_batch_with(&["a", "b", "c"], &options)).unwrap();
+        assert_eq!(embeddings, [vec![0.0, 1.0], vec![1.0, 1.0], vec![0.0, 1.0]]);
+        let url = "https://proxy.example/v1/embeddings".to_string();
+        assert_eq!(*seen.lock().unwrap(), [(url.clone(), 2), (url, 1)]);
+        assert!(block_on(client.embed_batch(&[])).unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_errors_and_offline_fallback() {
+        let client = AsyncJinaClient::new("key").with_transport(|_: HttpRequest| async {
+            Ok(HttpResponse { status: 401, headers: Vec::new(), body: r#"{"detail":"bad key"}"#.to_string() })
+        });
+        assert!(matches!(block_on(client.embed("x")), Err(JinaError::Api { status: 401, .. })));
+        let down = AsyncJinaClient::new("key").with_transport(|_: HttpRequest| async {
+            Err(JinaError::Transport("fetch failed".to_string()))
+        });
+        assert_eq!(block_on(down.embed("x")), Err(JinaError::Transport("fetch failed".to_string())));
+        
+        let offline = AsyncJinaClient::new("");
+        assert!(!offline.is_online());
+        assert_eq!(block_on(offline.embed("hello")).unwrap(), PseudoEmbedder::new(1024).embed("hello"));
+        let late = EmbedOptions::default().with_late_chunking();
+        assert!(matches!(block_on(offline.embed_batch_with(&["a"], &late)), Err(JinaError::InvalidInput(_))));
+    }
+    
+    #[test]
+    fn test_identical_requests_in_flight_are_sent_once() {
+        /// Pending on its first poll, like a response still on the way
+        struct Later(Option<Result<HttpResponse, JinaError>>, bool);
+        impl Future for Later {
+            type Output = Result<HttpResponse, JinaError>;
+            fn poll(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Self::Output> {
+                if std::mem::replace(&mut self.1, true) { Poll::Ready(self.0.take().unwrap()) } else { Poll::Pending }
+            }
+        }
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let log = sent.clone();
+        let client = AsyncJinaClient::new("key").with_transport(move |request: HttpRequest| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            let mut log = log.lock().unwrap();
+            log.push(body["input"][0].as_str().unwrap().to_string());
+            let response = match log.len() {
+                1 => HttpResponse { status: 503, headers: Vec::new(), body: "busy".to_string() },
+                _ => HttpResponse { status: 200, headers: Vec::new(), body: r#"{"data":[{"index":0,"embedding":[1.0,0.0]}]}"#.to_string() },
+            };
+            Later(Some(Ok(response)), false)
+        });
+        let options = EmbedOptions::default().with_dimensions(2);
+        // Poll every call in turn until all are done
+        let join = |texts: &[&str]| {
+            let mut calls: Vec<_> = texts.iter().map(|t| Some(Box::pin(client.embed_batch_with(std::slice::from_ref(t), &options)))).collect();
+            let mut results = vec![None; texts.len()];
+            let mut context = Context::from_waker(Waker::noop());
+            while results.iter().any(Option::is_none) {
+                for (call, result) in calls.iter_mut().zip(&mut results) {
+                    if let Some(Poll::Ready(output)) = call.as_mut().map(|c| c.as_mut().poll(&mut context)) {
+                        *result = Some(output);
+                        *call = None;
+                    }
+                }
+            }
+            results.into_iter().map(Option::unwrap).collect::<Vec<_>>()
+        };
+        
+        // The failure reaches every caller, and is not kept
+        let failed = join(&["hot"; 5]);
+        assert!(failed.iter().all(|r| matches!(r, Err(JinaError::Api { status: 503, .. }))));
+        let results = join(&["hot", "hot", "cold", "hot"]);
+        assert!(results.iter().all(|r| r.as_ref().unwrap() == &[vec![1.0, 0.0]]));
+        assert_eq!(*sent.lock().unwrap(), ["hot", "hot", "cold"]);
+    }
+    
+    #[test]
+    fn test_deadline_becomes_request_timeouts() {
+        let clock = Arc::new(crate::mock::ManualClock::new());
+        let (time, seen) = (clock.clone(), Arc::new(Mutex::new(Vec::new())));
+        let log = seen.clone();
+        let client = AsyncJinaClient::new("key").with_max_batch_size(1).with_timeout(Duration::from_secs(1)).with_clock(clock.clone())
+            .with_transport(move |request: HttpRequest| {
+                log.lock().unwrap().push(request.timeout.unwrap());
+                time.advance(Duration::from_millis(600));
+                let body = r#"{"data":[{"index":0,"embedding":[1.0,0.0]}]}"#.to_string();
+                async move { Ok(HttpResponse { status: 200, headers: Vec::new(), body }) }
+            });
+        let options = EmbedOptions::default().with_dimensions(2);
+        let call = CallOptions::default().with_deadline(clock.now() + Duration::from_millis(1500));
+        let result = block_on(client.embed_batch_call(&["a", "b", "c", "d"], &options, &call));
+        assert_eq!(result, Err(JinaError::DeadlineExceeded));
+        let millis: Vec<u128> = seen.lock().unwrap().iter().map(Duration::as_millis).collect();
+        assert_eq!(millis, [1000, 900, 300]);
+    }
+    
+    #[test]
+    fn test_hedge_answers_for_a_stalled_first_attempt() {
+        /// Never answers; records being dropped
+        struct Stalled(Arc<Mutex<bool>>);
+        impl Future for Stalled {
+            type Output = Result<HttpResponse, JinaError>;
+            fn poll(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Self::Output> { Poll::Pending }
+        }
+        impl Drop for Stalled {
+            fn drop(&mut self) { *self.0.lock().unwrap() = true; }
+        }
+        let (sent, dropped) = (Arc::new(Mutex::new(0)), Arc::new(Mutex::new(false)));
+        let (count, flag) = (sent.clone(), dropped.clone());
+        let transport = move |_: HttpRequest| -> SendFuture<'static> {
+            let mut sent = count.lock().unwrap();
+            *sent += 1;
+            if *sent == 1 {
+                return Box::pin(Stalled(flag.clone()));
+            }
+            let body = format!(r#"{{"data":[{{"index":0,"embedding":[{}.0,0.0]}}]}}"#, *sent);
+            Box::pin(async move { Ok(HttpResponse { status: 200, headers: Vec::new(), body }) })
+        };
+        // Fires on its second poll
+        let timer = |_: Duration| {
+            let mut fired = false;
+            std::future::poll_fn(move |_| if std::mem::replace(&mut fired, true) { Poll::Ready(()) } else { Poll::Pending })
+        };
+        let client = AsyncJinaClient::new("key").with_transport(transport).with_timer(timer);
+        let options = EmbedOptions::default().with_dimensions(2);
+        let call = CallOptions::default().with_hedge_after(Duration::from_millis(200));
+        assert_eq!(block_on(client.embed_batch_call(&["slow"], &options, &call)).unwrap(), [vec![2.0, 0.0]]);
+        assert!(*dropped.lock().unwrap());
+        assert_eq!(*sent.lock().unwrap(), 2);
+        let stats = client.stats();
+        assert_eq!((stats.requests, stats.hedges, stats.texts_sent), (2, 1, 1));
+        
+        // A first attempt answering in time is not hedged
+        assert_eq!(block_on(client.embed_batch_call(&["fast"], &options, &call)).unwrap(), [vec![3.0, 0.0]]);
+        assert_eq!(client.stats().hedges, 1);
+        
+        // Hedging needs a timer, and no retry budget turns it off
+        let untimed = AsyncJinaClient::new("key").with_transport(|_: HttpRequest| async {
+            Ok(HttpResponse { status: 200, headers: Vec::new(), body: r#"{"data":[{"index":0,"embedding":[1.0,0.0]}]}"#.to_string() })
+        });
+        assert!(matches!(block_on(untimed.embed_batch_call(&["a"], &options, &call)), Err(JinaError::InvalidInput(_))));
+        assert!(block_on(untimed.embed_batch_call(&["a"], &options, &call.clone().with_max_retries(0))).is_ok());
+        let zero = CallOptions::default().with_hedge_after(Duration::ZERO);
+        assert!(matches!(block_on(client.embed_batch_call(&["a"], &options, &zero)), Err(JinaError::InvalidInput(_))));
+    }
+}
@@ -0,0 +1,366 @@
+//! `Stream` of embeddings over `AsyncJinaClient`
+//!
+//! `AsyncJinaClient::embed_stream` is the async `stream::embed_stream`: it
+//! embeds texts as an input `Stream` yields them and yields `(input index,
+//! vector)` in input order. A batch of up to `batch_size` texts goes out
+//! when it is full, when the input ends, and otherwise as soon as the input
+//! has nothing ready, or, given a `window` and a client timer
+//! (`with_timer`), once the window since its first text has passed.
+//!
+//! Up to `max_in_flight` requests are outstanding at once, counting finished
+//! ones whose vectors wait on an earlier batch, and the input is only polled
+//! while another could go out. A slow consumer or a slow API thus slows the
+//! producer instead of filling a buffer: the stream holds at most
+//! `max_in_flight + 1` batches of texts or vectors.
+//!
+//! The first error is yielded in place of its batch and ends the stream.
+//! Dropping the stream drops the requests in flight, which is how an
+//! `AsyncTransport` learns to abort them, and reads no more input.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_core::Stream;
+
+use crate::async_client::AsyncJinaClient;
+use crate::error::JinaError;
+use crate::jina_api::EmbedOptions;
+
+/// A pending sleep
+pub type TimerFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+/// Host timer for `AsyncStreamConfig::window`; any `Fn(Duration) -> impl Future`,
+/// such as `tokio::time::sleep`, is one
+pub trait AsyncTimer {
+    fn sleep(&self, duration: Duration) -> TimerFuture;
+}
+
+impl<F, Fut> AsyncTimer for F
+where
+    F: Fn(Duration) -> Fut,
+    Fut: Future<Output = ()> + 'static,
+{
+    fn sleep(&self, duration: Duration) -> TimerFuture { Box::pin(self(duration)) }
+}
+
+type BatchFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<Vec<f32>>, JinaError>> + 'a>>;
+
+struct Batch<'a> {
+    /// Input position of its first text
+    start: usize,
+    request: BatchFuture<'a>,
+    done: Option<Result<Vec<Vec<f32>>, JinaError>>,
+}
+
+/// How `AsyncJinaClient::embed_stream` batches and overlaps its requests
+#[derive(Clone, Debug)]
+pub struct AsyncStreamConfig {
+    /// Texts per request, at most the client's `max_batch_size`
+    pub batch_size: usize,
+    /// Requests outstanding at once
+    pub max_in_flight: usize,
+    /// Longest a partial batch waits for more texts; needs `with_timer`
+    pub window: Option<Duration>,
+    pub options: EmbedOptions,
+}
+
+impl Default for AsyncStreamConfig {
+    fn default() -> Self { Self { batch_size: 64, max_in_flight: 4, window: None, options: EmbedOptions::default() } }
+}
+
+/// Stream returned by `AsyncJinaClient::embed_stream`
+pub struct AsyncEmbedStream<'a, S> {
+    client: &'a AsyncJinaClient,
+    config: AsyncStreamConfig,
+    /// `None` once it has ended, or the stream has
+    input: Option<Pin<Box<S>>>,
+    /// Texts of the batch being gathered
+    pending: Vec<String>,
+    window: Option<TimerFuture>,
+    /// Input position of the next text read
+    next: usize,
+    in_flight: VecDeque<Batch<'a>>,
+    ready: VecDeque<(usize, Vec<f32>)>,
+    finished: bool,
+}
+
+impl AsyncJinaClient {
+    /// Sleep with `timer` for `AsyncStreamConfig::window`
+    pub fn with_timer(mut self, timer: impl AsyncTimer + 'static) -> Self {
+        self.timer = Some(Box::new(timer));
+        self
+    }
+    
+    /// Embed the texts of `input` as they come, yielding `(input index, vector)` in input order.
+    ///
+    /// Nothing is read or sent until the stream is first polled.
+    pub fn embed_stream<S: Stream<Item = String>>(&self, input: S, config: AsyncStreamConfig) -> AsyncEmbedStream<'_, S> {
+        AsyncEmbedStream {
+            client: self,
+            config,
+            input: Some(Box::pin(input)),
+            pending: Vec::new(),
+            window: None,
+            next: 0,
+            in_flight: VecDeque::new(),
+            ready: VecDeque::new(),
+            finished: false,
+        }
+    }
+}
+
+impl<S: Stream<Item = String>> AsyncEmbedStream<'_, S> {
+    /// Read texts while a request could go out; true once one has
+    fn fill(&mut self, cx: &mut Context<'_>) -> bool {
+        while self.in_flight.len() < self.config.max_in_flight.max(1) {
+            let Some(input) = &mut self.input else { return self.send() };
+            match input.as_mut().poll_next(cx) {
+                Poll::Ready(Some(text)) => {
+                    if self.pending.is_empty() {
+                        self.window = self.config.window.zip(self.client.timer.as_ref()).map(|(window, timer)| timer.sleep(window));
+                    }
+                    self.pending.push(text);
+                    if self.pending.len() >= self.config.batch_size.clamp(1, self.client.max_batch_size) {
+                        return self.send();
+                    }
+                }
+                Poll::Ready(None) => self.input = None,
+                Poll::Pending => {
+                    let waited = self.window.as_mut().is_none_or(|window| window.as_mut().poll(cx).is_ready());
+                    return waited && self.send();
+                }
+            }
+        }
+        false
+    }
+    
+    /// Send the gathered texts, if any
+    fn send(&mut self) -> bool {
+        if self.pending.is_empty() {
+            return false;
+        }
+        let texts = std::mem::take(&mut self.pending);
+        self.window = None;
+        let start = self.next;
+        self.next += texts.len();
+        let (client, options) = (self.client, self.config.options.clone());
+        let request = Box::pin(async move {
+            let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+            client.embed_batch_with(&texts, &options).await
+        });
+        self.in_flight.push_back(Batch { start, request, done: None });
+        true
+    }
+    
+    fn finish(&mut self) {
+        self.finished = true;
+        self.input = None;
+        self.pending.clear();
+        self.window = None;
+        self.in_flight.clear();
+    }
+}
+
+impl<S: Stream<Item = String>> Stream for AsyncEmbedStream<'_, S> {
+    type Item = Result<(usize, Vec<f32>), JinaError>;
+    
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(item) = this.ready.pop_front() {
+                return Poll::Ready(Some(Ok(item)));
+            }
+            if this.finished {
+                return Poll::Ready(None);
+            }
+            for batch in this.in_flight.iter_mut().filter(|batch| batch.done.is_none()) {
+                if let Poll::Ready(result) = batch.request.as_mut().poll(cx) {
+                    batch.done = Some(result);
+                }
+            }
+            // Finished requests are handed on in input order
+            if this.in_flight.front().is_some_and(|batch| batch.done.is_some()) {
+                let batch = this.in_flight.pop_front().unwrap();
+                match batch.done.unwrap() {
+                    Ok(vectors) => this.ready.extend((batch.start..).zip(vectors)),
+                    Err(e) => {
+                        this.finish();
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                }
+                continue;
+            }
+            if !this.fill(cx) {
+                if this.input.is_none() && this.in_flight.is_empty() {
+                    this.finished = true;
+                    return Poll::Ready(None);
+                }
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::transport::{HttpRequest, HttpResponse};
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use tokio::time::{sleep, Instant, Sleep};
+    
+    /// `texts`, each `every[i]` after the last, counting how many were taken
+    struct Paced {
+        texts: Box<dyn Iterator<Item = String>>,
+        every: Vec<Duration>,
+        delay: Option<Pin<Box<Sleep>>>,
+        taken: Rc<Cell<usize>>,
+    }
+    
+    impl Paced {
+        fn new(texts: impl IntoIterator<Item = String> + 'static, every: Vec<Duration>) -> (Self, Rc<Cell<usize>>) {
+            let taken = Rc::new(Cell::new(0));
+            (Self { texts: Box::new(texts.into_iter()), every, delay: None, taken: taken.clone() }, taken)
+        }
+    }
+    
+    impl Stream for Paced {
+        type Item = String;
+        
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<String>> {
+            let taken = self.taken.get();
+            let every = self.every.get(taken).or(self.every.last()).copied().unwrap_or_default();
+            if !every.is_zero() {
+                let delay = self.delay.get_or_insert_with(|| Box::pin(sleep(every)));
+                if delay.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                self.delay = None;
+            }
+            let text = self.texts.next();
+            self.taken.set(taken + text.is_some() as usize);
+            Poll::Ready(text)
+        }
+    }
+    
+    fn next<S: Stream + Unpin>(stream: &mut S) -> impl Future<Output = Option<S::Item>> + '_ {
+        std::future::poll_fn(move |cx| Pin::new(&mut *stream).poll_next(cx))
+    }
+    
+    fn run<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().enable_time().start_paused(true).build().unwrap().block_on(future)
+    }
+    
+    fn small_batches() -> AsyncStreamConfig {
+        AsyncStreamConfig { batch_size: 3, options: EmbedOptions::default().with_dimensions(2), ..AsyncStreamConfig::default() }
+    }
+    
+    /// What the test transport saw: batches of text numbers, requests open
+    /// now and the most open at once
+    #[derive(Default)]
+    struct Served {
+        batches: RefCell<Vec<Vec<usize>>>,
+        open: Cell<usize>,
+        peak: Cell<usize>,
+    }
+    
+    /// Serves `t<i>` as `[i, 1]`, batch `n` after `latency(n)`
+    fn serving(latency: impl Fn(usize) -> Duration + 'static) -> (AsyncJinaClient, Rc<Served>) {
+        let served = Rc::new(Served::default());
+        let log = served.clone();
+        let client = AsyncJinaClient::new("key").with_transport(move |request: HttpRequest| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            let texts: Vec<usize> = body["input"].as_array().unwrap().iter().map(|t| t.as_str().unwrap()[1..].parse().unwrap()).collect();
+            let data: Vec<_> = texts.iter().enumerate()
+                .map(|(index, &t)| serde_json::json!({ "index": index, "embedding": [t as f32, 1.0] })).collect();
+            let delay = latency(log.batches.borrow().len());
+            log.batches.borrow_mut().push(texts);
+            log.open.set(log.open.get() + 1);
+            log.peak.set(log.peak.get().max(log.open.get()));
+            let log = log.clone();
+            async move {
+                sleep(delay).await;
+                log.open.set(log.open.get() - 1);
+                Ok(HttpResponse { status: 200, headers: Vec::new(), body: serde_json::json!({ "data": data }).to_string() })
+            }
+        });
+        (client, served)
+    }
+    
+    #[test]
+    fn test_batches_by_count_and_window_in_input_order() {
+        let ms = Duration::from_millis;
+        // The first request is the slowest, so the next one finishes first
+        let (client, served) = serving(move |n| if n == 0 { ms(300) } else { ms(10) });
+        let client = client.with_timer(sleep);
+        let config = AsyncStreamConfig { max_in_flight: 2, window: Some(ms(50)), ..small_batches() };
+        // t0 t1, a pause past the window, t2..t6 close together, then t7 alone
+        let every = vec![ms(10), ms(10), ms(200), ms(10), ms(10), ms(10), ms(10), ms(200)];
+        let (input, _) = Paced::new((0..8).map(|i| format!("t{}", i)), every);
+        let elapsed = run(async {
+            let started = Instant::now();
+            let mut stream = client.embed_stream(input, config);
+            let mut out = Vec::new();
+            while let Some(item) = next(&mut stream).await {
+                out.push(item.unwrap());
+            }
+            assert_eq!(out, (0..8).map(|i| (i, vec![i as f32, 1.0])).collect::<Vec<_>>());
+            started.elapsed()
+        });
+        assert_eq!(*served.batches.borrow(), [vec![0, 1], vec![2, 3, 4], vec![5, 6], vec![7]]);
+        assert_eq!(served.peak.get(), 2);
+        assert!(elapsed >= ms(360));
+        
+        // Without a window a batch goes out whenever the input has nothing ready
+        let (client, served) = serving(move |_| ms(10));
+        let (input, _) = Paced::new((0..4).map(|i| format!("t{}", i)), vec![ms(0), ms(0), ms(20), ms(20)]);
+        let count = run(async {
+            let mut stream = client.embed_stream(input, AsyncStreamConfig { max_in_flight: 1, ..small_batches() });
+            let mut count = 0;
+            while next(&mut stream).await.is_some() {
+                count += 1;
+            }
+            count
+        });
+        assert_eq!(count, 4);
+        assert_eq!(*served.batches.borrow(), [vec![0, 1], vec![2], vec![3]]);
+    }
+    
+    #[test]
+    fn test_back_pressure_and_drop_cancel_requests() {
+        let ms = Duration::from_millis;
+        let (client, served) = serving(move |_| ms(100));
+        let (input, taken) = Paced::new((0..).map(|i| format!("t{}", i)), vec![ms(0)]);
+        run(async {
+            let mut stream = client.embed_stream(input, AsyncStreamConfig { max_in_flight: 2, ..small_batches() });
+            for i in 0..6 {
+                assert_eq!(next(&mut stream).await.unwrap().unwrap().0, i);
+            }
+            // An endless input is read only as fast as results are taken
+            assert_eq!(taken.get(), 6);
+            sleep(ms(1000)).await;
+            assert_eq!(taken.get(), 6);
+            
+            assert!(tokio::time::timeout(ms(50), next(&mut stream)).await.is_err());
+            assert_eq!((taken.get(), served.open.get()), (12, 2));
+            drop(stream);
+            sleep(ms(1000)).await;
+        });
+        // The two requests in flight never finished, and nothing more was read or sent
+        assert_eq!((taken.get(), served.open.get(), served.batches.borrow().len()), (12, 2, 4));
+        
+        // An error ends the stream in its batch's place
+        let failing = AsyncJinaClient::new("key").with_transport(|_: HttpRequest| async {
+            Err(JinaError::Transport("connection reset".to_string()))
+        });
+        let (input, _) = Paced::new(["a".to_string(), "b".to_string()], vec![ms(0)]);
+        let mut stream = failing.embed_stream(input, small_batches());
+        run(async {
+            assert_eq!(next(&mut stream).await, Some(Err(JinaError::Transport("connection reset".to_string()))));
+            assert_eq!(next(&mut stream).await, None);
+        });
+    }
+}
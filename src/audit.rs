@@ -0,0 +1,306 @@
+//! JSONL audit trail of what a client sent
+//!
+//! `JinaClient::with_audit_log` appends one JSON object per HTTP attempt,
+//! retries included:
+//!
+//! ```text
+//! {"ts_ms":1760400000000,"url":"https://api.jina.ai/v1/embeddings","model":"jina-embeddings-v3",
+//!  "attempt":1,"status":200,"error":null,"bytes_sent":118,"bytes_received":20480,
+//!  "latency_ms":212.4,"input_sha256":["4f4b…"],"usage":{"prompt_tokens":3,"total_tokens":3}}
+//! ```
+//!
+//! Input texts appear only as SHA-256 hex digests, in request order; the
+//! API key and other headers are never seen. Each line is written with one
+//! `write_all` and flushed under a lock, so clients sharing an `AuditLog`
+//! never interleave partial lines, and a file opened in append mode does
+//! not tear lines between processes on local file systems.
+//!
+//! If a write fails the log disables itself: the error is kept for
+//! `error()`, handed once to the `with_on_error` callback, and later
+//! attempts go unlogged while requests carry on.
+
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::sync::{Mutex, OnceLock};
+
+use crate::hash::{sha256, to_hex};
+use crate::provider::Usage;
+use crate::transport::Attempt;
+
+/// Request fields whose strings are hashed into `input_sha256`
+const TEXT_FIELDS: [&str; 4] = ["input", "query", "documents", "content"];
+
+/// One logged attempt
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AuditEntry {
+    /// Unix time in milliseconds; 0 where the platform has no clock (wasm32)
+    pub ts_ms: u64,
+    pub url: String,
+    pub model: Option<String>,
+    pub attempt: u32,
+    /// `None` when no response arrived
+    pub status: Option<u16>,
+    /// Transport error of an attempt without a response
+    pub error: Option<String>,
+    pub bytes_sent: usize,
+    pub bytes_received: usize,
+    pub latency_ms: f64,
+    /// SHA-256 of each input text; non-text inputs hash as their JSON
+    pub input_sha256: Vec<String>,
+    pub usage: Option<Usage>,
+    /// `CallOptions::tag` of the call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+type OnError = Box<dyn Fn(&str) + Send + Sync>;
+
+/// Append-only JSONL sink for `AuditEntry` lines
+pub struct AuditLog {
+    /// `None` once a write has failed
+    sink: Mutex<Option<Box<dyn Write + Send>>>,
+    error: OnceLock<String>,
+    on_error: Option<OnError>,
+}
+
+impl AuditLog {
+    /// Append to `path`, creating it
+    pub fn open(path: &str) -> Result<Self, String> {
+        let file = OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|e| format!("Cannot open audit log {}: {}", path, e))?;
+        Ok(Self::to_writer(file))
+    }
+    
+    pub fn to_writer(writer: impl Write + Send + 'static) -> Self {
+        Self { sink: Mutex::new(Some(Box::new(writer))), error: OnceLock::new(), on_error: None }
+    }
+    
+    /// Call `f` with the error of the write that disables the log
+    pub fn with_on_error(mut self, f: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.on_error = Some(Box::new(f));
+        self
+    }
+    
+    /// Why the log stopped writing, if it has
+    pub fn error(&self) -> Option<&str> { self.error.get().map(String::as_str) }
+    
+    pub fn is_enabled(&self) -> bool { self.error.get().is_none() }
+    
+    /// Append `entry` as one line
+    pub fn record(&self, entry: &AuditEntry) {
+        let mut line = serde_json::to_string(entry).expect("audit entries always serialize");
+        line.push('\n');
+        let mut sink = self.sink.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(writer) = sink.as_mut() else { return };
+        if let Err(e) = writer.write_all(line.as_bytes()).and_then(|()| writer.flush()) {
+            *sink = None;
+            let message = format!("Audit log disabled after a failed write: {}", e);
+            if let Some(on_error) = &self.on_error {
+                on_error(&message);
+            }
+            let _ = self.error.set(message);
+        }
+    }
+    
+    pub(crate) fn record_attempt(&self, attempt: &Attempt<'_>) {
+        if self.is_enabled() {
+            self.record(&AuditEntry::from_attempt(attempt));
+        }
+    }
+}
+
+impl AuditEntry {
+    pub(crate) fn from_attempt(attempt: &Attempt<'_>) -> Self {
+        let request = request_json(attempt.body);
+        let (status, error, bytes_received, usage) = match attempt.result {
+            Ok(response) => {
+                let usage = serde_json::from_str::<serde_json::Value>(&response.body).ok()
+                    .and_then(|body| serde_json::from_value(body.get("usage")?.clone()).ok());
+                (Some(response.status), None, response.body.len(), usage)
+            }
+            Err(e) => (None, Some(e.to_string()), 0, None),
+        };
+        Self {
+            ts_ms: unix_ms(),
+            url: attempt.url.to_string(),
+            model: request["model"].as_str().map(str::to_string),
+            attempt: attempt.attempt,
+            status,
+            error,
+            bytes_sent: attempt.body.len(),
+            bytes_received,
+            latency_ms: attempt.total_ms,
+            input_sha256: input_hashes(&request),
+            usage,
+            tag: attempt.tag.map(str::to_string),
+        }
+    }
+}
+
+/// The request body as JSON, gunzipped if need be; `Null` if it is not JSON
+fn request_json(body: &[u8]) -> serde_json::Value {
+    let mut unzipped = Vec::new();
+    let body = if body.starts_with(&[0x1f, 0x8b]) && flate2::read::GzDecoder::new(body).read_to_end(&mut unzipped).is_ok() {
+        &unzipped[..]
+    } else {
+        body
+    };
+    serde_json::from_slice(body).unwrap_or(serde_json::Value::Null)
+}
+
+fn input_hashes(request: &serde_json::Value) -> Vec<String> {
+    let mut hashes = Vec::new();
+    for field in TEXT_FIELDS {
+        let items = match &request[field] {
+            serde_json::Value::Null => continue,
+            serde_json::Value::Array(items) => items.iter().collect(),
+            item => vec![item],
+        };
+        for item in items {
+            let text = match item {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            hashes.push(to_hex(&sha256(text.as_bytes())));
+        }
+    }
+    hashes
+}
+
+/// Unix time in milliseconds, 0 on wasm32
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn unix_ms() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn unix_ms() -> u64 { 0 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::JinaError;
+    use crate::jina_api::{CallOptions, EmbedOptions, JinaClient};
+    use crate::transport::{HttpRequest, HttpResponse, RetryPolicy};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    
+    /// Two-dimensional vectors for each input, with usage
+    fn ok_body(request: &HttpRequest) -> String {
+        let inputs = request_json(&request.body)["input"].as_array().map_or(0, Vec::len);
+        let data: Vec<_> = (0..inputs).map(|i| serde_json::json!({"index": i, "embedding": [0.6, 0.8]})).collect();
+        serde_json::json!({"data": data, "usage": {"prompt_tokens": 4, "total_tokens": 4}}).to_string()
+    }
+    
+    fn embed(client: &JinaClient, texts: &[&str]) -> Result<Vec<Vec<f32>>, JinaError> {
+        client.embed_batch_full(texts, &EmbedOptions::default().with_dimensions(2)).map(|r| r.embeddings)
+    }
+    
+    /// Writer into shared memory that fails once `limit` bytes are written
+    #[derive(Clone)]
+    struct Memory {
+        bytes: Arc<Mutex<Vec<u8>>>,
+        limit: usize,
+    }
+    
+    impl Write for Memory {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let mut bytes = self.bytes.lock().unwrap();
+            if bytes.len() + buf.len() > self.limit {
+                return Err(std::io::Error::other("disk full"));
+            }
+            bytes.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        
+        fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+    }
+    
+    fn memory(limit: usize) -> Memory { Memory { bytes: Arc::default(), limit } }
+    
+    fn lines(memory: &Memory) -> Vec<AuditEntry> {
+        String::from_utf8(memory.bytes.lock().unwrap().clone()).unwrap()
+            .lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+    
+    /// Client answering 503 to every `fail_every`-th attempt, else `OK_BODY`
+    fn audited(log: Arc<AuditLog>, fail_every: usize) -> JinaClient {
+        let calls = AtomicUsize::new(0);
+        JinaClient::new("secret-key")
+            .with_retry(RetryPolicy { max_retries: 1, base_delay: Duration::ZERO, max_delay: Duration::ZERO })
+            .with_transport(move |request: &HttpRequest| {
+                let n = calls.fetch_add(1, Ordering::Relaxed) + 1;
+                let (status, body) = if n.is_multiple_of(fail_every) { (503, "busy".to_string()) } else { (200, ok_body(request)) };
+                Ok(HttpResponse { status, headers: Vec::new(), body })
+            })
+            .with_audit_log(log)
+    }
+    
+    #[test]
+    fn test_lines_hold_hashes_not_text() {
+        let sink = memory(usize::MAX);
+        let log = Arc::new(AuditLog::to_writer(sink.clone()));
+        let client = audited(log.clone(), 1 << 30);
+        embed(&client, &["confidential memo"]).unwrap();
+        
+        let entries = lines(&sink);
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!((entry.url.as_str(), entry.model.as_deref()), ("https://api.jina.ai/v1/embeddings", Some("jina-embeddings-v3")));
+        assert_eq!((entry.attempt, entry.status, entry.error.as_deref()), (1, Some(200), None));
+        assert_eq!(entry.input_sha256, [to_hex(&sha256(b"confidential memo"))]);
+        assert_eq!(entry.usage, Some(Usage { prompt_tokens: 4, total_tokens: 4 }));
+        assert!(entry.bytes_received > 0);
+        assert!(entry.bytes_sent > 0 && entry.ts_ms > 0);
+        let raw = String::from_utf8(sink.bytes.lock().unwrap().clone()).unwrap();
+        assert!(!raw.contains("confidential") && !raw.contains("secret-key"));
+        
+        // Failed attempts are logged too, one line per retry
+        let sink = memory(usize::MAX);
+        let client = audited(Arc::new(AuditLog::to_writer(sink.clone())), 1);
+        assert!(matches!(embed(&client, &["x"]), Err(JinaError::Api { status: 503, .. })));
+        let entries = lines(&sink);
+        assert_eq!(entries.iter().map(|e| (e.attempt, e.status)).collect::<Vec<_>>(), [(1, Some(503)), (2, Some(503))]);
+        assert!(entries.iter().all(|e| e.tag.is_none()));
+        
+        // Per-call tags label that call's lines only
+        let sink = memory(usize::MAX);
+        let client = audited(Arc::new(AuditLog::to_writer(sink.clone())), 1 << 30);
+        let tagged = CallOptions::default().with_tag("nightly");
+        client.embed_batch_call(&["y"], &EmbedOptions::default().with_dimensions(2), &tagged).unwrap();
+        embed(&client, &["z"]).unwrap();
+        let tags: Vec<_> = lines(&sink).iter().map(|e| e.tag.clone()).collect();
+        assert_eq!(tags, [Some("nightly".to_string()), None]);
+        
+        // Clients sharing a log from many threads write whole lines
+        let sink = memory(usize::MAX);
+        let log = Arc::new(AuditLog::to_writer(sink.clone()));
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                let client = audited(log.clone(), 3);
+                scope.spawn(move || for _ in 0..25 { embed(&client, &["a", "b"]).unwrap(); });
+            }
+        });
+        let entries = lines(&sink);
+        assert!(entries.len() >= 100);
+        assert!(entries.iter().all(|e| e.input_sha256.len() == 2));
+    }
+    
+    #[test]
+    fn test_failed_write_disables_once() {
+        let sink = memory(600);
+        let reported: Arc<Mutex<Vec<String>>> = Arc::default();
+        let seen = reported.clone();
+        let log = Arc::new(AuditLog::to_writer(sink.clone()).with_on_error(move |e| seen.lock().unwrap().push(e.to_string())));
+        let client = audited(log.clone(), 1 << 30);
+        for _ in 0..10 {
+            embed(&client, &["text"]).unwrap();
+        }
+        let written = lines(&sink).len();
+        assert!((1..10).contains(&written));
+        assert!(!log.is_enabled());
+        assert_eq!(reported.lock().unwrap().len(), 1);
+        assert!(log.error().unwrap().contains("disk full"));
+    }
+}
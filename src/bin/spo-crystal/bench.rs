@@ -0,0 +1,62 @@
+//! `spo-crystal bench`: search speed, memory and recall over a synthetic corpus
+
+use clap::{Arg, ArgMatches, Command};
+use spo_crystal::eval::bench::{run, BenchMatrix, Corpus, IndexKind};
+use spo_crystal::index::Quantization;
+use spo_crystal::search::Metric;
+
+use crate::{count_arg, Failure};
+
+pub fn command() -> Command {
+    Command::new("bench")
+        .about("Benchmark index configurations on seeded pseudo-embeddings")
+        .arg(count_arg("vectors").value_name("N").default_value("10000").help("Corpus size"))
+        .arg(count_arg("queries").value_name("N").default_value("100"))
+        .arg(count_arg("dims").value_name("N").default_value("256"))
+        .arg(Arg::new("seed").long("seed").value_name("SEED").value_parser(clap::value_parser!(u64)).default_value("0"))
+        .arg(Arg::new("metric").long("metric").value_name("METRICS").value_delimiter(',')
+            .value_parser(["cosine", "dot", "euclidean"]).default_value("cosine"))
+        .arg(Arg::new("quantize").long("quantize").value_name("MODES").value_delimiter(',')
+            .value_parser(["none", "int8"]).default_value("none,int8"))
+        .arg(Arg::new("index").long("index").value_name("KINDS").value_delimiter(',').default_value("exhaustive,flat,sharded-4")
+            .help("exhaustive, flat or sharded-N, comma separated"))
+        .arg(Arg::new("k").long("k").value_name("KS").value_delimiter(',').value_parser(clap::value_parser!(usize)).default_value("10"))
+        .arg(Arg::new("format").long("format").value_name("FORMAT").value_parser(["json", "csv"]).default_value("json"))
+        .after_help("Every combination of --metric, --quantize, --index and --k is run; recall is against exhaustive f32 search.")
+}
+
+fn index_kind(label: &str) -> Result<IndexKind, Failure> {
+    match label {
+        "exhaustive" => Ok(IndexKind::Exhaustive),
+        "flat" => Ok(IndexKind::Flat),
+        _ => match label.strip_prefix("sharded-").and_then(|n| n.parse().ok()) {
+            Some(shards) if shards > 0 => Ok(IndexKind::Sharded { shards }),
+            _ => Err(Failure::usage(format!("Unknown index kind {} (exhaustive, flat or sharded-N)", label))),
+        },
+    }
+}
+
+pub fn run_bench(matches: &ArgMatches) -> Result<(), Failure> {
+    let count = |name: &str| *matches.get_one::<usize>(name).unwrap();
+    if count("vectors") == 0 || count("queries") == 0 || count("dims") == 0 {
+        return Err(Failure::usage("--vectors, --queries and --dims must be at least 1"));
+    }
+    let metrics = matches.get_many::<String>("metric").unwrap().map(|m| m.parse::<Metric>()).collect::<Result<Vec<_>, _>>()?;
+    let quantizations: Vec<Quantization> = matches.get_many::<String>("quantize").unwrap()
+        .map(|q| if q == "int8" { Quantization::Int8 } else { Quantization::None })
+        .collect();
+    let indexes = matches.get_many::<String>("index").unwrap().map(|i| index_kind(i)).collect::<Result<Vec<_>, _>>()?;
+    let ks: Vec<usize> = matches.get_many::<usize>("k").unwrap().copied().collect();
+    if ks.contains(&0) {
+        return Err(Failure::usage("--k must be at least 1"));
+    }
+    
+    let corpus = Corpus::synthetic(count("vectors"), count("queries"), count("dims"), *matches.get_one::<u64>("seed").unwrap())?;
+    let matrix = BenchMatrix::new().with_metrics(&metrics).with_quantizations(&quantizations).with_indexes(&indexes).with_ks(&ks);
+    let report = run(&corpus, &matrix)?;
+    match matches.get_one::<String>("format").unwrap().as_str() {
+        "csv" => print!("{}", report.to_csv()),
+        _ => println!("{}", report.to_json()),
+    }
+    Ok(())
+}
@@ -0,0 +1,173 @@
+//! `spo-crystal embed`: texts in, embeddings out
+//!
+//! Output is written batch by batch, so an interrupted run leaves a valid
+//! prefix that `--resume` picks up from.
+
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use serde::Serialize;
+use serde_json::Value;
+use spo_crystal::index::CrystalIndex;
+
+use crate::input::{id_key, index_id, read_file, Record};
+use crate::{client, count_arg, embed_options, is_auth_error, Failure, EXIT_PARTIAL};
+
+pub fn command() -> Command {
+    Command::new("embed")
+        .about("Embed texts into JSONL {id, embedding} records or a CrystalIndex file")
+        .arg(Arg::new("input").value_name("FILE")
+            .help("One text per line, or JSONL with a `text` and optional `id` field; stdin if omitted or -"))
+        .arg(Arg::new("input-format").long("input-format").value_name("FORMAT")
+            .value_parser(["auto", "lines", "jsonl"]).default_value("auto")
+            .help("auto: JSONL for .jsonl and .ndjson files, lines otherwise"))
+        .arg(Arg::new("out").long("out").short('o').value_name("PATH").help("Output file; stdout if omitted (JSONL only)"))
+        .arg(Arg::new("format").long("format").value_name("FORMAT")
+            .value_parser(["jsonl", "index"]).default_value("jsonl")
+            .help("jsonl: one {id, embedding} per line; index: a CrystalIndex file (numeric ids, fields as metadata)"))
+        .arg(count_arg("batch-size").value_name("N").default_value("64").help("Texts per request"))
+        .arg(Arg::new("resume").long("resume").action(ArgAction::SetTrue)
+            .help("Keep the records already in --out and embed only the rest"))
+        .arg(Arg::new("quiet").long("quiet").short('q').action(ArgAction::SetTrue).help("No progress on stderr"))
+}
+
+/// Serialized directly rather than through `Value`, which would widen the floats to f64 digits
+#[derive(Serialize)]
+struct EmbeddingLine<'a> {
+    id: &'a Value,
+    embedding: &'a [f32],
+    /// Pseudo-embeddings, as from `--backend offline`
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    synthetic: bool,
+}
+
+/// Where embeddings are written
+enum Sink {
+    Jsonl { out: Box<dyn Write>, done: HashSet<String> },
+    /// `index` is created from the first batch unless resuming an existing file
+    Index { path: String, index: Option<Box<CrystalIndex>> },
+}
+
+impl Sink {
+    fn open(format: &str, out: Option<&str>, resume: bool) -> Result<Sink, Failure> {
+        if format == "index" {
+            let path = out.ok_or_else(|| Failure::usage("--format index needs --out"))?;
+            let index = match resume && Path::new(path).exists() {
+                true => Some(Box::new(CrystalIndex::load(path)?)),
+                false => None,
+            };
+            return Ok(Sink::Index { path: path.to_string(), index });
+        }
+        let Some(path) = out else {
+            if resume {
+                return Err(Failure::usage("--resume needs --out"));
+            }
+            return Ok(Sink::Jsonl { out: Box::new(io::stdout()), done: HashSet::new() });
+        };
+        let done = if resume { resume_jsonl(path)? } else { HashSet::new() };
+        let file = OpenOptions::new().create(true).append(resume).write(true).truncate(!resume).open(path)
+            .map_err(|e| format!("Cannot open {}: {}", path, e))?;
+        Ok(Sink::Jsonl { out: Box::new(file), done })
+    }
+    
+    fn contains(&self, record: &Record) -> bool {
+        match self {
+            Sink::Jsonl { done, .. } => done.contains(&id_key(&record.id)),
+            Sink::Index { index, .. } => {
+                index.as_ref().is_some_and(|index| index_id(&record.id).is_some_and(|id| index.contains(id)))
+            }
+        }
+    }
+    
+    fn write(&mut self, records: &[&Record], embeddings: Vec<Vec<f32>>, synthetic: bool) -> Result<(), Failure> {
+        match self {
+            Sink::Jsonl { out, .. } => {
+                let mut lines = String::new();
+                for (record, embedding) in records.iter().zip(embeddings) {
+                    let line = EmbeddingLine { id: &record.id, embedding: &embedding, synthetic };
+                    lines.push_str(&serde_json::to_string(&line).unwrap());
+                    lines.push('\n');
+                }
+                out.write_all(lines.as_bytes()).and_then(|_| out.flush()).map_err(|e| format!("Write failed: {}", e))?;
+            }
+            Sink::Index { path, index } => {
+                let fresh = index.is_none();
+                let dims = embeddings.first().map_or(0, Vec::len);
+                let index = index.get_or_insert_with(|| Box::new(CrystalIndex::new(dims)));
+                for (record, embedding) in records.iter().zip(embeddings) {
+                    index.add_with_metadata(index_id(&record.id).unwrap_or_default(), &embedding, record.metadata.clone())?;
+                }
+                if fresh { index.save(path)? } else { index.save_incremental(path)? }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Ids already written to `path`, dropping a torn last line
+fn resume_jsonl(path: &str) -> Result<HashSet<String>, Failure> {
+    let Ok(existing) = fs::read_to_string(path) else { return Ok(HashSet::new()) };
+    let complete = existing.rfind('\n').map_or(0, |i| i + 1);
+    if complete < existing.len() {
+        let file = OpenOptions::new().write(true).open(path).map_err(|e| format!("Cannot open {}: {}", path, e))?;
+        file.set_len(complete as u64).map_err(|e| format!("Cannot truncate {}: {}", path, e))?;
+    }
+    Ok(existing[..complete].lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|value| value["embedding"].is_array())
+        .filter_map(|value| value.get("id").map(id_key))
+        .collect())
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Failure> {
+    let batch_size = *matches.get_one::<usize>("batch-size").unwrap();
+    if batch_size == 0 {
+        return Err(Failure::usage("--batch-size must be at least 1"));
+    }
+    let options = embed_options(matches, None)?;
+    let format = matches.get_one::<String>("format").unwrap().as_str();
+    let quiet = matches.get_flag("quiet");
+    let client = client(matches)?.with_max_batch_size(batch_size);
+    
+    let records = read_file(matches.get_one::<String>("input").map(String::as_str),
+                            matches.get_one::<String>("input-format").unwrap())?;
+    if format == "index" {
+        if let Some(record) = records.iter().find(|r| index_id(&r.id).is_none()) {
+            return Err(format!("--format index needs numeric ids, got {}", record.id).into());
+        }
+    }
+    
+    let mut sink = Sink::open(format, matches.get_one::<String>("out").map(String::as_str), matches.get_flag("resume"))?;
+    let pending: Vec<&Record> = records.iter().filter(|r| !sink.contains(r)).collect();
+    let skipped = records.len() - pending.len();
+    let (mut embedded, mut failed) = (0, 0);
+    for batch in pending.chunks(batch_size) {
+        let texts: Vec<&str> = batch.iter().map(|r| r.text.as_str()).collect();
+        match client.embed_batch_full(&texts, &options) {
+            Ok(response) => {
+                sink.write(batch, response.embeddings, response.backend.is_synthetic())?;
+                embedded += batch.len();
+            }
+            Err(e) if is_auth_error(&e) => return Err(e.into()),
+            Err(e) => {
+                eprintln!("spo-crystal: {} records from id {} failed: {}", batch.len(), id_key(&batch[0].id), e);
+                failed += batch.len();
+            }
+        }
+        if !quiet {
+            eprintln!("embedded {}/{}", embedded + failed, pending.len());
+        }
+    }
+    
+    if !quiet {
+        eprintln!("{} embedded, {} already in output, {} failed, {} cache hits",
+                  embedded, skipped, failed, client.stats().cache_hits);
+    }
+    if failed > 0 {
+        return Err(Failure::new(EXIT_PARTIAL, format!("{} of {} records failed; rerun with --resume to retry them", failed, pending.len())));
+    }
+    Ok(())
+}
@@ -0,0 +1,182 @@
+//! `spo-crystal index build|info|add|remove`: CrystalIndex files
+
+use clap::{value_parser, Arg, ArgMatches, Command};
+use spo_crystal::index::{CrystalIndex, Quantization};
+use spo_crystal::jina_api::{EmbedOptions, JinaClient};
+use spo_crystal::search::Metric;
+
+use crate::input::{index_id, read_file, Record};
+use crate::{backend_args, client, count_arg, embed_options, read_input, Failure};
+
+pub fn command() -> Command {
+    let corpus = || Arg::new("corpus").required(true).value_name("CORPUS")
+        .help("Lines or JSONL with a `text` and numeric `id` field; - for stdin");
+    let input_format = || Arg::new("input-format").long("input-format").value_name("FORMAT")
+        .value_parser(["auto", "lines", "jsonl"]).default_value("auto")
+        .help("auto: JSONL for .jsonl and .ndjson files, lines otherwise");
+    let path = || Arg::new("path").required(true).value_name("INDEX");
+    Command::new("index")
+        .about("Build, inspect and update CrystalIndex files")
+        .subcommand_required(true)
+        .subcommand(Command::new("build")
+            .about("Embed a corpus into a new index file")
+            .arg(corpus())
+            .arg(Arg::new("out").long("out").short('o').required(true).value_name("PATH"))
+            .arg(input_format())
+            .arg(Arg::new("type").long("type").value_name("TYPE").value_parser(["flat"]).default_value("flat")
+                .help("flat: exact search"))
+            .arg(Arg::new("quantize").long("quantize").value_name("MODE")
+                .value_parser(["none", "int8"]).default_value("none")
+                .help("int8: store vectors as int8 plus a scale, about 4x smaller"))
+            .arg(Arg::new("metric").long("metric").value_name("METRIC")
+                .value_parser(["cosine", "dot", "euclidean"]).default_value("cosine")
+                .help("How search ranks: dot and euclidean take vector norms into account"))
+            .arg(count_arg("batch-size").value_name("N").default_value("64").help("Texts per request"))
+            .args(backend_args()))
+        .subcommand(Command::new("info")
+            .about("Print an index's size, dimensions, quantization, metric and sample ids")
+            .arg(path())
+            .arg(Arg::new("sample").long("sample").value_name("N").value_parser(value_parser!(usize)).default_value("5")))
+        .subcommand(Command::new("add")
+            .about("Embed a corpus into an existing index, replacing entries with the same id")
+            .arg(path())
+            .arg(corpus())
+            .arg(input_format())
+            .arg(count_arg("batch-size").value_name("N").default_value("64").help("Texts per request"))
+            .args(backend_args()))
+        .subcommand(Command::new("remove")
+            .about("Remove entries from an index")
+            .arg(path())
+            .arg(Arg::new("ids").required_unless_present("ids-file").num_args(1..).value_name("ID")
+                .value_parser(value_parser!(u64)))
+            .arg(Arg::new("ids-file").long("ids-file").value_name("FILE").help("One id per line; - for stdin")))
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Failure> {
+    match matches.subcommand() {
+        Some(("build", m)) => build(m),
+        Some(("info", m)) => info(m),
+        Some(("add", m)) => add(m),
+        Some(("remove", m)) => remove(m),
+        _ => unreachable!("subcommand_required"),
+    }
+}
+
+/// Load `path`, naming the file in errors
+pub fn load(path: &str) -> Result<CrystalIndex, Failure> {
+    CrystalIndex::load(path).map_err(|e| Failure::from(format!("Cannot load index: {}", e)))
+}
+
+/// `options` sized to the index: `--dimensions` must agree with it
+pub fn options_for(index: &CrystalIndex, options: EmbedOptions) -> Result<EmbedOptions, Failure> {
+    match options.dimensions {
+        Some(dims) if dims != index.dims() => {
+            Err(Failure::usage(format!("--dimensions {} does not match the index ({})", dims, index.dims())))
+        }
+        _ => Ok(options.with_dimensions(index.dims())),
+    }
+}
+
+/// Corpus records, which must have numeric ids
+fn read_corpus(matches: &ArgMatches) -> Result<Vec<Record>, Failure> {
+    let records = read_file(matches.get_one::<String>("corpus").map(String::as_str),
+                            matches.get_one::<String>("input-format").unwrap())?;
+    if let Some(record) = records.iter().find(|r| index_id(&r.id).is_none()) {
+        return Err(format!("Index ids must be numeric, got {}", record.id).into());
+    }
+    Ok(records)
+}
+
+/// Embeddings of `records` in batches of `--batch-size`
+fn embed_records(matches: &ArgMatches, client: &JinaClient, records: &[Record], options: &EmbedOptions)
+                 -> Result<Vec<Vec<f32>>, Failure> {
+    let batch_size = *matches.get_one::<usize>("batch-size").unwrap();
+    if batch_size == 0 {
+        return Err(Failure::usage("--batch-size must be at least 1"));
+    }
+    let texts: Vec<&str> = records.iter().map(|r| r.text.as_str()).collect();
+    let mut embeddings = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(batch_size) {
+        embeddings.extend(client.embed_batch_full(batch, options)?.embeddings);
+        eprintln!("embedded {}/{}", embeddings.len(), texts.len());
+    }
+    Ok(embeddings)
+}
+
+fn build(matches: &ArgMatches) -> Result<(), Failure> {
+    let quantization = match matches.get_one::<String>("quantize").unwrap().as_str() {
+        "int8" => Quantization::Int8,
+        _ => Quantization::None,
+    };
+    let metric: Metric = matches.get_one::<String>("metric").unwrap().parse()?;
+    let options = embed_options(matches, None)?;
+    let client = client(matches)?;
+    let records = read_corpus(matches)?;
+    let embeddings = embed_records(matches, &client, &records, &options)?;
+    
+    let dims = embeddings.first().map_or(options.dims(), Vec::len);
+    let mut index = CrystalIndex::new(dims).with_quantization(quantization).with_metric(metric);
+    for (record, embedding) in records.iter().zip(&embeddings) {
+        let id = index_id(&record.id).unwrap();
+        index.remove(id);
+        index.add_with_metadata(id, embedding, record.metadata.clone())?;
+    }
+    index.compact();
+    let out = matches.get_one::<String>("out").unwrap();
+    index.save(out)?;
+    eprintln!("{}: {} entries, {} dims, {}", out, index.len(), index.dims(), quantization.as_str());
+    Ok(())
+}
+
+fn info(matches: &ArgMatches) -> Result<(), Failure> {
+    let path = matches.get_one::<String>("path").unwrap();
+    let index = load(path)?;
+    let size = std::fs::metadata(path).map_err(|e| format!("Cannot stat {}: {}", path, e))?.len();
+    let sample: Vec<String> = index.ids().take(*matches.get_one::<usize>("sample").unwrap()).map(|id| id.to_string()).collect();
+    println!("path:          {}", path);
+    println!("entries:       {}", index.len());
+    println!("tombstones:    {}", index.tombstones());
+    println!("dimensions:    {}", index.dims());
+    println!("quantization:  {}", index.quantization().as_str());
+    println!("metric:        {}", index.metric().as_str());
+    println!("disk size:     {} bytes", size);
+    println!("sample ids:    {}", sample.join(", "));
+    Ok(())
+}
+
+fn add(matches: &ArgMatches) -> Result<(), Failure> {
+    let path = matches.get_one::<String>("path").unwrap();
+    let mut index = load(path)?;
+    let options = options_for(&index, embed_options(matches, None)?)?;
+    let client = client(matches)?;
+    let records = read_corpus(matches)?;
+    let embeddings = embed_records(matches, &client, &records, &options)?;
+    
+    let mut replaced = 0;
+    for (record, embedding) in records.iter().zip(&embeddings) {
+        let id = index_id(&record.id).unwrap();
+        replaced += index.remove(id) as usize;
+        index.add_with_metadata(id, embedding, record.metadata.clone())?;
+    }
+    index.save_incremental(path)?;
+    eprintln!("{}: added {}, replaced {}, {} entries", path, records.len() - replaced, replaced, index.len());
+    Ok(())
+}
+
+fn remove(matches: &ArgMatches) -> Result<(), Failure> {
+    let path = matches.get_one::<String>("path").unwrap();
+    let mut ids: Vec<u64> = matches.get_many::<u64>("ids").into_iter().flatten().copied().collect();
+    if let Some(file) = matches.get_one::<String>("ids-file") {
+        for line in read_input(Some(file))?.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            ids.push(line.parse().map_err(|_| format!("Index ids must be numeric, got {}", line))?);
+        }
+    }
+    let mut index = load(path)?;
+    let missing: Vec<String> = ids.iter().filter(|&&id| !index.remove(id)).map(u64::to_string).collect();
+    index.save_incremental(path)?;
+    if !missing.is_empty() {
+        eprintln!("spo-crystal: not in the index: {}", missing.join(", "));
+    }
+    eprintln!("{}: removed {}, {} entries", path, ids.len() - missing.len(), index.len());
+    Ok(())
+}
@@ -0,0 +1,79 @@
+//! Text records read from lines or JSONL files
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+use serde_json::{json, Value};
+use spo_crystal::metadata::Metadata;
+
+use crate::Failure;
+
+/// One input text, the id it is written under and its metadata
+pub struct Record {
+    pub id: Value,
+    pub text: String,
+    /// `text` plus a JSONL record's other string, number and bool fields
+    pub metadata: Metadata,
+}
+
+/// Ids compare as strings, so `7` and `"7"` are the same record
+pub fn id_key(id: &Value) -> String {
+    id.as_str().map_or_else(|| id.to_string(), str::to_string)
+}
+
+pub fn index_id(id: &Value) -> Option<u64> {
+    id.as_u64().or_else(|| id.as_str().and_then(|s| s.parse().ok()))
+}
+
+/// Lines become records with their line index as id; blank lines are skipped
+pub fn read_records(reader: impl BufRead, jsonl: bool) -> Result<Vec<Record>, Failure> {
+    let mut records = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| format!("Read failed: {}", e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !jsonl {
+            let metadata = Metadata::new().with("text", line.as_str());
+            records.push(Record { id: json!(i), text: line, metadata });
+            continue;
+        }
+        let value: Value = serde_json::from_str(&line).map_err(|e| format!("line {}: {}", i + 1, e))?;
+        let text = value["text"].as_str().ok_or_else(|| format!("line {}: no `text` string field", i + 1))?;
+        let id = match value.get("id") {
+            None => json!(i),
+            Some(id @ (Value::String(_) | Value::Number(_))) => id.clone(),
+            Some(_) => return Err(format!("line {}: `id` must be a string or number", i + 1).into()),
+        };
+        let mut metadata = Metadata::new();
+        for (key, field) in value.as_object().into_iter().flatten().filter(|(key, _)| *key != "id") {
+            let inserted = match field {
+                Value::String(s) => metadata.insert(key, s.as_str()),
+                Value::Bool(b) => metadata.insert(key, *b),
+                Value::Number(n) => metadata.insert(key, n.as_f64().unwrap_or_default()),
+                _ => Ok(()),
+            };
+            inserted.map_err(|e| format!("line {}: {}", i + 1, e))?;
+        }
+        records.push(Record { id, text: text.to_string(), metadata });
+    }
+    Ok(records)
+}
+
+/// Records of `path` (stdin for `None` and `-`); `format` is auto, lines or jsonl
+///
+/// auto reads `.jsonl` and `.ndjson` files as JSONL and everything else as lines.
+pub fn read_file(path: Option<&str>, format: &str) -> Result<Vec<Record>, Failure> {
+    let path = path.filter(|path| *path != "-");
+    let jsonl = match format {
+        "auto" => path.is_some_and(|path| path.ends_with(".jsonl") || path.ends_with(".ndjson")),
+        format => format == "jsonl",
+    };
+    match path {
+        Some(path) => {
+            let file = File::open(path).map_err(|e| format!("Cannot open {}: {}", path, e))?;
+            read_records(BufReader::new(file), jsonl)
+        }
+        None => read_records(io::stdin().lock(), jsonl),
+    }
+}
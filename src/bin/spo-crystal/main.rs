@@ -0,0 +1,179 @@
+//! `spo-crystal` command line tool
+//!
+//! - `embed`: embed a lines / JSONL file or stdin into JSONL or a `CrystalIndex`
+//! - `sim`: cosine similarity of two texts
+//! - `matrix`: pairwise similarity of the lines of a file, as a table or CSV
+//! - `search`: top-k hits against a saved index or a corpus embedded on the fly
+//! - `index build|info|add|remove`: create, inspect and update index files
+//! - `bench`: search speed, memory and recall of index configurations, as JSON or CSV
+//!
+//! Exit codes: 0 success, 1 failure, 2 bad usage, 3 authentication
+//! rejected (or no API key), 4 some records failed, 5 no search hits.
+
+mod bench;
+mod embed;
+mod index;
+mod input;
+mod search;
+mod sim;
+
+use std::fmt;
+use std::process::ExitCode;
+
+use clap::{value_parser, Arg, ArgMatches, Command};
+use spo_crystal::error::JinaError;
+use spo_crystal::jina_api::{EmbedOptions, JinaClient, Task};
+use spo_crystal::transport::{CurlTransport, PlainHttpTransport};
+
+pub const EXIT_FAILURE: u8 = 1;
+pub const EXIT_USAGE: u8 = 2;
+pub const EXIT_AUTH: u8 = 3;
+pub const EXIT_PARTIAL: u8 = 4;
+pub const EXIT_NO_HITS: u8 = 5;
+
+/// Why a command stopped, and the exit code that reports it
+#[derive(Debug)]
+pub struct Failure {
+    pub code: u8,
+    pub message: String,
+}
+
+impl Failure {
+    pub fn new(code: u8, message: impl Into<String>) -> Self { Self { code, message: message.into() } }
+    
+    pub fn usage(message: impl Into<String>) -> Self { Self::new(EXIT_USAGE, message) }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.write_str(&self.message) }
+}
+
+impl From<JinaError> for Failure {
+    fn from(e: JinaError) -> Self {
+        let code = if is_auth_error(&e) { EXIT_AUTH } else { EXIT_FAILURE };
+        Failure::new(code, e.to_string())
+    }
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self { Failure::new(EXIT_FAILURE, message) }
+}
+
+/// The API rejected the key; retrying other inputs cannot succeed
+pub fn is_auth_error(e: &JinaError) -> bool {
+    match e {
+        JinaError::Api { status, .. } => matches!(status, 401 | 403),
+        JinaError::Route { source, .. } => is_auth_error(source),
+        _ => false,
+    }
+}
+
+/// `--backend`, `--model`, `--url`, `--dimensions` and `--task`, shared by every command that embeds
+fn backend_args() -> [Arg; 5] {
+    [
+        Arg::new("backend").long("backend").value_name("BACKEND")
+            .value_parser(["offline", "curl", "native"]).default_value("curl")
+            .help("offline: deterministic pseudo-embeddings, no API key; curl or native: the Jina API"),
+        Arg::new("model").long("model").value_name("MODEL").help("Jina embedding model [default: jina-embeddings-v3]"),
+        Arg::new("url").long("url").value_name("URL")
+            .help("API base URL; the native backend needs an http:// URL, e.g. a local proxy"),
+        count_arg("dimensions").value_name("N").help("Output dimensions (Matryoshka truncation)"),
+        Arg::new("task").long("task").value_name("TASK").help("Task adapter, e.g. retrieval.passage"),
+    ]
+}
+
+/// `EmbedOptions` from `--task` (else `default_task`) and `--dimensions`
+pub fn embed_options(matches: &ArgMatches, default_task: Option<Task>) -> Result<EmbedOptions, Failure> {
+    let mut options = EmbedOptions::default();
+    let task = match matches.get_one::<String>("task") {
+        Some(task) => Some(task.parse::<Task>().map_err(|e| Failure::usage(e.to_string()))?),
+        None => default_task,
+    };
+    if let Some(task) = task {
+        options = options.with_task(task);
+    }
+    if let Some(&dims) = matches.get_one::<usize>("dimensions") {
+        if dims == 0 {
+            return Err(Failure::usage("--dimensions must be at least 1"));
+        }
+        options = options.with_dimensions(dims);
+    }
+    Ok(options)
+}
+
+/// Client for `--backend`; online backends read the key from `JINA_API_KEY`
+pub fn client(matches: &ArgMatches) -> Result<JinaClient, Failure> {
+    let backend = matches.get_one::<String>("backend").map(String::as_str).unwrap_or("curl");
+    let url = matches.get_one::<String>("url");
+    let api_key = match backend {
+        "offline" => String::new(),
+        _ => std::env::var("JINA_API_KEY")
+            .ok()
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| Failure::new(EXIT_AUTH, "JINA_API_KEY is not set (use --backend offline to embed without it)"))?,
+    };
+    let mut client = JinaClient::new(&api_key).with_cache();
+    if let Some(model) = matches.get_one::<String>("model") {
+        client = client.with_model(model);
+    }
+    if let Some(url) = url {
+        client = client.with_base_url(url);
+    }
+    Ok(match backend {
+        "offline" => client,
+        "native" => {
+            if !url.is_some_and(|u| u.starts_with("http://")) {
+                return Err(Failure::usage("--backend native speaks plain HTTP only and needs --url http://..."));
+            }
+            client.with_transport(PlainHttpTransport::new())
+        }
+        _ => client.with_transport(CurlTransport::new()),
+    })
+}
+
+fn cli() -> Command {
+    Command::new("spo-crystal")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Embed, compare and search text with Jina embeddings")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .subcommand(embed::command().args(backend_args()))
+        .subcommand(sim::sim_command().args(backend_args()))
+        .subcommand(sim::matrix_command().args(backend_args()))
+        .subcommand(search::command().args(backend_args()))
+        .subcommand(index::command())
+        .subcommand(bench::command())
+}
+
+/// Contents of `path`, or of stdin for `None` and `-`
+pub fn read_input(path: Option<&str>) -> Result<String, Failure> {
+    match path.filter(|path| *path != "-") {
+        Some(path) => std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e).into()),
+        None => std::io::read_to_string(std::io::stdin()).map_err(|e| format!("Cannot read stdin: {}", e).into()),
+    }
+}
+
+/// `usize` flag
+pub fn count_arg(name: &'static str) -> Arg {
+    Arg::new(name).long(name).value_parser(value_parser!(usize))
+}
+
+fn main() -> ExitCode {
+    let matches = cli().get_matches();
+    let result = match matches.subcommand() {
+        Some(("embed", m)) => embed::run(m),
+        Some(("sim", m)) => sim::run_sim(m),
+        Some(("matrix", m)) => sim::run_matrix(m),
+        Some(("search", m)) => search::run(m),
+        Some(("index", m)) => index::run(m),
+        Some(("bench", m)) => bench::run_bench(m),
+        _ => unreachable!("subcommand_required"),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            eprintln!("spo-crystal: {}", failure);
+            ExitCode::from(failure.code)
+        }
+    }
+}
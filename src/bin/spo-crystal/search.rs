@@ -0,0 +1,277 @@
+//! `spo-crystal search`: top-k hits for queries against an index or a corpus file
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use serde::Serialize;
+use serde_json::{json, Value};
+use spo_crystal::hash;
+use spo_crystal::index::CrystalIndex;
+use spo_crystal::jina_api::{EmbedOptions, JinaClient, Task};
+use spo_crystal::metadata::{MetaValue, Metadata};
+use spo_crystal::search::top_k;
+
+use crate::index;
+use crate::input::read_file;
+use crate::{client, count_arg, embed_options, read_input, Failure, EXIT_NO_HITS};
+
+/// Characters of each hit's text shown in the table view
+const SNIPPET_CHARS: usize = 80;
+
+pub fn command() -> Command {
+    Command::new("search")
+        .about("Print the top-k hits for queries against a saved CrystalIndex or a corpus file")
+        .arg(Arg::new("query").value_name("QUERY").conflicts_with("queries"))
+        .arg(Arg::new("queries").long("queries").value_name("FILE").help("One query per line"))
+        .arg(Arg::new("index").long("index").value_name("PATH")
+            .conflicts_with("corpus").required_unless_present("corpus").help("CrystalIndex file, e.g. from `embed --format index`"))
+        .arg(Arg::new("corpus").long("corpus").value_name("FILE")
+            .help("Lines or JSONL with a `text` and optional `id` field, embedded on the fly"))
+        .arg(Arg::new("corpus-format").long("corpus-format").value_name("FORMAT")
+            .value_parser(["auto", "lines", "jsonl"]).default_value("auto")
+            .help("auto: JSONL for .jsonl and .ndjson files, lines otherwise"))
+        .arg(Arg::new("cache").long("cache").value_name("PATH").requires("corpus")
+            .help("Keep corpus embeddings in this file, so later runs only embed new texts"))
+        .arg(Arg::new("k").short('k').long("top-k").value_name("N").value_parser(value_parser!(usize)).default_value("10"))
+        .arg(Arg::new("min-score").long("min-score").value_name("SCORE").value_parser(value_parser!(f32))
+            .help("Drop hits scoring below this; exit 5 if no query has a hit left"))
+        .arg(Arg::new("filter").long("filter").value_name("KEY=VALUE").action(ArgAction::Append).requires("index")
+            .help("Only index entries whose metadata field KEY equals VALUE (repeatable)"))
+        .arg(Arg::new("rerank").long("rerank").action(ArgAction::SetTrue)
+            .help("Rescore the top candidates with the Jina reranker (offline: pseudo-embedding cosine)"))
+        .arg(count_arg("candidates").value_name("N").help("Candidates passed to --rerank [default: 4 x k]"))
+        .arg(Arg::new("snippets").long("snippets").action(ArgAction::SetTrue).help("Show each hit's text"))
+        .arg(Arg::new("format").long("format").value_name("FORMAT")
+            .value_parser(["table", "jsonl"]).default_value("table"))
+}
+
+struct Hit {
+    id: Value,
+    score: f32,
+    text: Option<String>,
+}
+
+/// Where hits come from
+enum Source {
+    Index(Box<CrystalIndex>),
+    Corpus { ids: Vec<Value>, texts: Vec<String>, vectors: Vec<Vec<f32>> },
+}
+
+impl Source {
+    fn search(&self, query: &[f32], k: usize, filters: &[(String, String)]) -> Vec<Hit> {
+        match self {
+            Source::Index(index) => {
+                let filter = |metadata: &Metadata| filters.iter().all(|(key, value)| field_equals(metadata, key, value));
+                index.search_filtered(query, k, (!filters.is_empty()).then_some(&filter as _))
+                    .into_iter()
+                    .map(|(id, score)| Hit {
+                        id: json!(id),
+                        score,
+                        text: index.metadata(id).and_then(|m| m.get_str("text")).map(str::to_string),
+                    })
+                    .collect()
+            }
+            Source::Corpus { ids, texts, vectors } => top_k(query, vectors, k).into_iter()
+                .map(|(i, score)| Hit { id: ids[i].clone(), score, text: Some(texts[i].clone()) })
+                .collect(),
+        }
+    }
+}
+
+/// Compare `value` as whatever type the field holds
+fn field_equals(metadata: &Metadata, key: &str, value: &str) -> bool {
+    match metadata.get(key) {
+        Some(MetaValue::Str(s)) => s == value,
+        Some(MetaValue::Num(n)) => value.parse::<f64>().is_ok_and(|v| v == *n),
+        Some(MetaValue::Bool(b)) => value.parse::<bool>().is_ok_and(|v| v == *b),
+        None => false,
+    }
+}
+
+fn parse_filters(matches: &ArgMatches) -> Result<Vec<(String, String)>, Failure> {
+    matches.get_many::<String>("filter").into_iter().flatten()
+        .map(|filter| match filter.split_once('=') {
+            Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+            _ => Err(Failure::usage(format!("--filter needs KEY=VALUE, got {}", filter))),
+        })
+        .collect()
+}
+
+/// Layout of `--cache` lines; lines of other versions are ignored and re-embedded
+const CACHE_VERSION: u32 = 2;
+
+/// One line of a `--cache` file
+#[derive(Serialize)]
+struct CacheEntry<'a> {
+    version: u32,
+    /// Hex `content_key` of the model label, options and text
+    key: &'a str,
+    text: &'a str,
+    embedding: &'a [f32],
+}
+
+/// Model label of `--cache` keys: embeddings are only reused from the same backend and model
+fn cache_model(matches: &ArgMatches) -> String {
+    let backend = matches.get_one::<String>("backend").map_or("curl", String::as_str);
+    format!("{}/{}",
+            if backend == "offline" { "offline" } else { "jina" },
+            matches.get_one::<String>("model").map_or("", String::as_str))
+}
+
+/// Embed `texts`, reusing and extending the `cache` file if given
+fn embed_corpus(client: &JinaClient, texts: &[&str], options: &EmbedOptions, cache: Option<(&str, String)>)
+                -> Result<Vec<Vec<f32>>, Failure> {
+    let Some((path, model)) = cache else {
+        return Ok(client.embed_batch_full(texts, options)?.embeddings);
+    };
+    let key = |text: &str| hash::to_hex(&hash::content_key(&model, options, text));
+    let existing = fs::read_to_string(path).unwrap_or_default();
+    let mut cached: HashMap<String, Vec<f32>> = existing.lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|entry| entry["version"] == CACHE_VERSION)
+        .filter_map(|entry| Some((entry["key"].as_str()?.to_string(), serde_json::from_value(entry["embedding"].clone()).ok()?)))
+        .collect();
+    
+    let mut missing: Vec<&str> = texts.iter().copied().filter(|t| !cached.contains_key(&key(t))).collect();
+    missing.sort_unstable();
+    missing.dedup();
+    if !missing.is_empty() {
+        let embeddings = client.embed_batch_full(&missing, options)?.embeddings;
+        // A torn last line from an interrupted run must not swallow the first new entry
+        let mut lines = if existing.is_empty() || existing.ends_with('\n') { String::new() } else { "\n".to_string() };
+        for (text, embedding) in missing.iter().zip(embeddings) {
+            let key = key(text);
+            let entry = CacheEntry { version: CACHE_VERSION, key: &key, text, embedding: &embedding };
+            lines.push_str(&serde_json::to_string(&entry).unwrap());
+            lines.push('\n');
+            cached.insert(key, embedding);
+        }
+        OpenOptions::new().create(true).append(true).open(path)
+            .and_then(|mut file| file.write_all(lines.as_bytes()))
+            .map_err(|e| format!("Cannot write {}: {}", path, e))?;
+    }
+    Ok(texts.iter().map(|t| cached[&key(t)].clone()).collect())
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Failure> {
+    let queries: Vec<String> = match (matches.get_one::<String>("query"), matches.get_one::<String>("queries")) {
+        (Some(query), _) => vec![query.clone()],
+        (None, Some(path)) => read_input(Some(path))?.lines().map(str::trim).filter(|q| !q.is_empty()).map(str::to_string).collect(),
+        (None, None) => Vec::new(),
+    };
+    if queries.is_empty() {
+        return Err(Failure::usage("search needs a QUERY or a --queries FILE with at least one line"));
+    }
+    let k = *matches.get_one::<usize>("k").unwrap();
+    if k == 0 {
+        return Err(Failure::usage("-k must be at least 1"));
+    }
+    let rerank = matches.get_flag("rerank");
+    let candidates = matches.get_one::<usize>("candidates").copied().unwrap_or(4 * k).max(k);
+    let filters = parse_filters(matches)?;
+    let client = client(matches)?;
+    
+    let mut query_options = embed_options(matches, Some(Task::RetrievalQuery))?;
+    let source = match matches.get_one::<String>("index") {
+        Some(path) => {
+            let index = index::load(path)?;
+            query_options = index::options_for(&index, query_options)?;
+            // Against a schema: an unknown field or a value of the wrong type matches nothing
+            if let Some(schema) = index.schema() {
+                for (key, value) in &filters {
+                    schema.parse_value(key, value).map_err(|e| Failure::usage(format!("--filter {}={}: {}", key, value, e)))?;
+                }
+            }
+            Source::Index(Box::new(index))
+        }
+        None => {
+            let records = read_file(matches.get_one::<String>("corpus").map(String::as_str),
+                                    matches.get_one::<String>("corpus-format").unwrap())?;
+            let options = embed_options(matches, Some(Task::RetrievalPassage))?;
+            let texts: Vec<&str> = records.iter().map(|r| r.text.as_str()).collect();
+            let cache = matches.get_one::<String>("cache").map(|path| (path.as_str(), cache_model(matches)));
+            let vectors = embed_corpus(&client, &texts, &options, cache)?;
+            Source::Corpus {
+                ids: records.iter().map(|r| r.id.clone()).collect(),
+                texts: records.iter().map(|r| r.text.clone()).collect(),
+                vectors,
+            }
+        }
+    };
+    
+    let query_texts: Vec<&str> = queries.iter().map(String::as_str).collect();
+    let query_vectors = client.embed_batch_full(&query_texts, &query_options)?.embeddings;
+    let min_score = matches.get_one::<f32>("min-score").copied().unwrap_or(f32::NEG_INFINITY);
+    let jsonl = matches.get_one::<String>("format").unwrap() == "jsonl";
+    let snippets = matches.get_flag("snippets");
+    let mut total = 0;
+    for (query, vector) in queries.iter().zip(&query_vectors) {
+        let mut hits = source.search(vector, if rerank { candidates } else { k }, &filters);
+        if rerank {
+            hits = rerank_hits(&client, query, hits, k)?;
+        }
+        hits.retain(|hit| hit.score >= min_score);
+        hits.truncate(k);
+        total += hits.len();
+        print!("{}", if jsonl { format_jsonl(query, &hits, snippets) } else { format_table(query, &hits, snippets) });
+    }
+    if total == 0 {
+        return Err(Failure::new(EXIT_NO_HITS, "no hits"));
+    }
+    Ok(())
+}
+
+/// The `k` best of `hits` by reranker score
+fn rerank_hits(client: &JinaClient, query: &str, hits: Vec<Hit>, k: usize) -> Result<Vec<Hit>, Failure> {
+    let documents: Vec<&str> = hits.iter()
+        .map(|hit| hit.text.as_deref().ok_or_else(|| format!("--rerank needs texts, but hit {} has no `text` metadata", hit.id)))
+        .collect::<Result<_, _>>()?;
+    let reranked = client.rerank(query, &documents, Some(k.min(documents.len().max(1))))?;
+    Ok(reranked.into_iter()
+        .map(|r| Hit { id: hits[r.index].id.clone(), score: r.score, text: hits[r.index].text.clone() })
+        .collect())
+}
+
+#[derive(Serialize)]
+struct HitLine<'a> {
+    query: &'a str,
+    rank: usize,
+    id: &'a Value,
+    score: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<&'a str>,
+}
+
+fn format_jsonl(query: &str, hits: &[Hit], snippets: bool) -> String {
+    hits.iter().enumerate()
+        .map(|(rank, hit)| {
+            let line = HitLine {
+                query,
+                rank: rank + 1,
+                id: &hit.id,
+                score: hit.score,
+                text: hit.text.as_deref().filter(|_| snippets),
+            };
+            format!("{}\n", serde_json::to_string(&line).unwrap())
+        })
+        .collect()
+}
+
+/// `query: ...`, then one `rank  id  score  [snippet]` row per hit
+fn format_table(query: &str, hits: &[Hit], snippets: bool) -> String {
+    let ids: Vec<String> = hits.iter().map(|hit| crate::input::id_key(&hit.id)).collect();
+    let width = ids.iter().map(String::len).max().unwrap_or(0);
+    let mut out = format!("query: {}\n", query);
+    for (rank, (hit, id)) in hits.iter().zip(&ids).enumerate() {
+        out.push_str(&format!("{:>4}  {:<width$}  {:.4}", rank + 1, id, hit.score));
+        if let (true, Some(text)) = (snippets, &hit.text) {
+            let snippet: String = text.chars().take(SNIPPET_CHARS).collect();
+            let ellipsis = if text.chars().count() > SNIPPET_CHARS { "..." } else { "" };
+            out.push_str(&format!("  {}{}", snippet.replace('\n', " "), ellipsis));
+        }
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,122 @@
+//! `spo-crystal sim` and `spo-crystal matrix`: cosine similarity of texts
+
+use std::fmt::Write as _;
+
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use spo_crystal::jina_api::Task;
+use spo_crystal::search::cosine;
+
+use crate::{client, embed_options, read_input, Failure};
+
+/// Line separating the two texts when both come from stdin
+const STDIN_SEPARATOR: &str = "---";
+
+/// Characters of each text shown in the table view
+const LABEL_CHARS: usize = 40;
+
+pub fn sim_command() -> Command {
+    Command::new("sim")
+        .about("Print the cosine similarity of two texts")
+        .arg(Arg::new("a").required(true).value_name("A").help("Text, file with --files, or - for stdin"))
+        .arg(Arg::new("b").required(true).value_name("B").help("Text, file with --files, or - for stdin"))
+        .arg(Arg::new("files").long("files").short('f').action(ArgAction::SetTrue).help("Read A and B from files"))
+        .after_help("With `- -`, stdin holds both texts separated by a line containing only ---.")
+}
+
+pub fn matrix_command() -> Command {
+    Command::new("matrix")
+        .about("Print the pairwise cosine similarity of the lines of a file")
+        .arg(Arg::new("input").value_name("FILE").help("One text per line; stdin if omitted or -"))
+        .arg(Arg::new("format").long("format").value_name("FORMAT")
+            .value_parser(["table", "csv"]).default_value("table"))
+}
+
+/// Both texts of `sim`, reading stdin at most once
+fn sim_texts(matches: &ArgMatches) -> Result<(String, String), Failure> {
+    let a = matches.get_one::<String>("a").unwrap();
+    let b = matches.get_one::<String>("b").unwrap();
+    if a == "-" && b == "-" {
+        let stdin = read_input(None)?;
+        let Some((first, second)) = stdin.split_once(&format!("\n{}\n", STDIN_SEPARATOR)) else {
+            return Err(Failure::usage(format!("`sim - -` needs a {} line between the two texts", STDIN_SEPARATOR)));
+        };
+        return Ok((first.to_string(), second.to_string()));
+    }
+    let operand = |arg: &String| match (arg.as_str(), matches.get_flag("files")) {
+        ("-", _) => read_input(None),
+        (path, true) => read_input(Some(path)),
+        (text, false) => Ok(text.to_string()),
+    };
+    Ok((operand(a)?, operand(b)?))
+}
+
+pub fn run_sim(matches: &ArgMatches) -> Result<(), Failure> {
+    let (a, b) = sim_texts(matches)?;
+    let options = embed_options(matches, Some(Task::TextMatching))?;
+    let embeddings = client(matches)?.embed_batch_full(&[a.trim(), b.trim()], &options)?.embeddings;
+    println!("{:.6}", cosine(&embeddings[0], &embeddings[1]));
+    Ok(())
+}
+
+pub fn run_matrix(matches: &ArgMatches) -> Result<(), Failure> {
+    let input = read_input(matches.get_one::<String>("input").map(String::as_str))?;
+    let texts: Vec<&str> = input.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    if texts.is_empty() {
+        return Err("No texts to compare".to_string().into());
+    }
+    let options = embed_options(matches, Some(Task::TextMatching))?;
+    let embeddings = client(matches)?.embed_batch_full(&texts, &options)?.embeddings;
+    let scores: Vec<Vec<f32>> = embeddings.iter()
+        .map(|a| embeddings.iter().map(|b| cosine(a, b)).collect())
+        .collect();
+    match matches.get_one::<String>("format").unwrap().as_str() {
+        "csv" => print!("{}", csv(&texts, &scores)),
+        _ => print!("{}", table(&texts, &scores)),
+    }
+    Ok(())
+}
+
+/// Header row of texts, then one row per text: its text, then its scores
+fn csv(texts: &[&str], scores: &[Vec<f32>]) -> String {
+    let mut out = String::from("text");
+    for text in texts {
+        write!(out, ",{}", csv_field(text)).unwrap();
+    }
+    out.push('\n');
+    for (text, row) in texts.iter().zip(scores) {
+        out.push_str(&csv_field(text));
+        for score in row {
+            write!(out, ",{:.6}", score).unwrap();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Scores in columns numbered by line, each row followed by its (shortened) text
+fn table(texts: &[&str], scores: &[Vec<f32>]) -> String {
+    let width = (texts.len() - 1).to_string().len();
+    let mut out = format!("{:width$}", "");
+    for j in 0..texts.len() {
+        write!(out, " {:>6}", j).unwrap();
+    }
+    out.push('\n');
+    for (i, (text, row)) in texts.iter().zip(scores).enumerate() {
+        write!(out, "{:>width$}", i).unwrap();
+        for score in row {
+            write!(out, " {:>6.3}", score).unwrap();
+        }
+        let label: String = text.chars().take(LABEL_CHARS).collect();
+        let ellipsis = if text.chars().count() > LABEL_CHARS { "..." } else { "" };
+        writeln!(out, "  {}{}", label, ellipsis).unwrap();
+    }
+    out
+}
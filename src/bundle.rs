@@ -0,0 +1,446 @@
+//! One-file bundles of a workspace's artifacts
+//!
+//! `export` packs any of an index file, a `JinaCache` persistence file, a
+//! `TripleStore` (its index and sidecars), a `TemplateRegistry` JSON file
+//! and a `pipeline::sync_directory` manifest into one gzipped tar archive,
+//! readable with `tar xzf`. Its first member, `bundle.json`, lists every
+//! other member with its kind, size and SHA-256, and the `Provenance` of
+//! the indexes. Any subset can be bundled; an index alone is a bundle.
+//!
+//! `import` checks the whole archive before writing anything to the
+//! workspace: a bundle version newer than `BUNDLE_VERSION`, a member
+//! missing from or not listed in the manifest, or a size or digest that
+//! does not match fails the import. Members stream to temporary files as
+//! they are hashed, so a bundle is never held in memory. It also refuses,
+//! unless told to `Override`, indexes whose provenances disagree, whether
+//! two in the bundle or one in the bundle and the index it would replace;
+//! replacing an index, a missing provenance disagrees with any. Members
+//! are then unpacked to `<workspace>/<kind>/<file name>`, byte for byte,
+//! and their paths returned as `BundleContents`.
+
+use std::fs;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::hash::{sha256, to_hex, Sha256};
+use crate::index::CrystalIndex;
+use crate::io::{atomic_write, create_temp};
+use crate::provenance::{Provenance, ProvenanceCheck};
+use crate::triple_store::{ALIASES_SUFFIX, SIDECAR_SUFFIX, TEMPLATES_SUFFIX};
+
+/// Bundle layout version this crate writes and the newest it reads
+pub const BUNDLE_VERSION: u32 = 1;
+/// Name of the manifest member
+pub const MANIFEST_NAME: &str = "bundle.json";
+
+/// Artifact files of a workspace, each optional
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BundleContents {
+    /// A `CrystalIndex::save` file
+    pub index: Option<PathBuf>,
+    /// A `JinaCache::with_persistence` file
+    pub cache: Option<PathBuf>,
+    /// The path given to `TripleStore::save`; its sidecars come along
+    pub triples: Option<PathBuf>,
+    /// A `TemplateRegistry::to_json` file
+    pub templates: Option<PathBuf>,
+    /// A `pipeline::sync_directory` manifest
+    pub manifest: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtifactKind {
+    Index,
+    Cache,
+    Triples,
+    Templates,
+    Manifest,
+}
+
+impl ArtifactKind {
+    fn dir(self) -> &'static str {
+        match self {
+            ArtifactKind::Index => "index",
+            ArtifactKind::Cache => "cache",
+            ArtifactKind::Triples => "triples",
+            ArtifactKind::Templates => "templates",
+            ArtifactKind::Manifest => "manifest",
+        }
+    }
+}
+
+/// One member of a bundle
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ArtifactEntry {
+    pub kind: ArtifactKind,
+    /// Member name, `<kind>/<file name>`; the first of a kind is its main file
+    pub path: String,
+    pub size: u64,
+    /// Hex SHA-256 of the member
+    pub sha256: String,
+    /// Of index files that record one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+}
+
+/// Contents of `bundle.json`
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BundleManifest {
+    pub version: u32,
+    /// Version of the crate that wrote the bundle
+    pub crate_version: String,
+    pub artifacts: Vec<ArtifactEntry>,
+}
+
+/// Write the artifacts named in `contents` to a bundle at `path`
+pub fn export(path: impl AsRef<Path>, contents: &BundleContents) -> Result<BundleManifest, String> {
+    let mut files: Vec<(ArtifactKind, PathBuf, Option<Provenance>)> = Vec::new();
+    if let Some(index) = &contents.index {
+        files.push((ArtifactKind::Index, index.clone(), index_provenance(index)?));
+    }
+    if let Some(cache) = &contents.cache {
+        files.push((ArtifactKind::Cache, cache.clone(), None));
+    }
+    if let Some(triples) = &contents.triples {
+        files.push((ArtifactKind::Triples, triples.clone(), index_provenance(triples)?));
+        for suffix in [SIDECAR_SUFFIX, ALIASES_SUFFIX, TEMPLATES_SUFFIX] {
+            let mut sidecar = triples.clone().into_os_string();
+            sidecar.push(suffix);
+            let sidecar = PathBuf::from(sidecar);
+            if sidecar.exists() {
+                files.push((ArtifactKind::Triples, sidecar, None));
+            }
+        }
+    }
+    for (kind, file) in [(ArtifactKind::Templates, &contents.templates), (ArtifactKind::Manifest, &contents.manifest)] {
+        files.extend(file.iter().map(|f| (kind, f.clone(), None)));
+    }
+    
+    let mut artifacts = Vec::with_capacity(files.len());
+    let mut members = Vec::with_capacity(files.len() + 1);
+    for (kind, file, provenance) in files {
+        let bytes = fs::read(&file).map_err(|e| format!("Cannot read {}: {}", file.display(), e))?;
+        let name = file.file_name().and_then(|n| n.to_str())
+            .ok_or_else(|| format!("{} has no UTF-8 file name", file.display()))?;
+        let member = format!("{}/{}", kind.dir(), name);
+        if members.iter().any(|(m, _)| *m == member) {
+            return Err(format!("Two artifacts would be bundled as {}", member));
+        }
+        artifacts.push(ArtifactEntry { kind, path: member.clone(), size: bytes.len() as u64, sha256: to_hex(&sha256(&bytes)), provenance });
+        members.push((member, bytes));
+    }
+    let manifest = BundleManifest { version: BUNDLE_VERSION, crate_version: env!("CARGO_PKG_VERSION").to_string(), artifacts };
+    let json = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+    members.insert(0, (MANIFEST_NAME.to_string(), json));
+    
+    let path = path.as_ref();
+    atomic_write(path, |file| {
+        let mut gz = GzEncoder::new(file, Compression::default());
+        write_tar(&mut gz, &members)?;
+        gz.finish()?.flush()
+    }).map_err(|e| format!("Write failed for {}: {}", path.display(), e))?;
+    Ok(manifest)
+}
+
+/// Check the bundle at `path` and unpack it into `workspace`
+pub fn import(path: impl AsRef<Path>, workspace: impl AsRef<Path>, check: ProvenanceCheck) -> Result<BundleContents, String> {
+    let path = path.as_ref();
+    let file = fs::File::open(path).map_err(|e| format!("Cannot read bundle {}: {}", path.display(), e))?;
+    let mut tar = TarReader(GzDecoder::new(BufReader::new(file)));
+    let (name, size) = tar.next_member().map_err(|e| format!("Cannot read bundle {}: {}", path.display(), e))?
+        .filter(|(name, _)| name == MANIFEST_NAME)
+        .ok_or_else(|| format!("{} is not a bundle: it does not start with {}", path.display(), MANIFEST_NAME))?;
+    let mut json = Vec::new();
+    tar.copy_member(&name, size, &mut json)?;
+    let manifest: BundleManifest = serde_json::from_slice(&json).map_err(|e| format!("Invalid {}: {}", MANIFEST_NAME, e))?;
+    if manifest.version > BUNDLE_VERSION {
+        return Err(format!("Bundle version {} (from spo-crystal {}) is newer than the {} this build reads",
+                           manifest.version, manifest.crate_version, BUNDLE_VERSION));
+    }
+    
+    // Each member to a temporary file, checked against its entry on the way
+    let mut staged = Staged(Vec::with_capacity(manifest.artifacts.len()));
+    let mut members = 0;
+    while let Some((name, size)) = tar.next_member()? {
+        let Some(entry) = manifest.artifacts.get(members) else {
+            tar.copy_member(&name, size, &mut std::io::sink())?;
+            members += 1;
+            continue;
+        };
+        members += 1;
+        if name != entry.path || !valid_member(&name, entry.kind) {
+            return Err(format!("Bundle member {} is not the {} its manifest lists", name, entry.path));
+        }
+        if size != entry.size {
+            return Err(format!("Artifact {} is {} bytes, its manifest says {}", name, size, entry.size));
+        }
+        let (temp, file) = create_temp(&std::env::temp_dir(), "spo-crystal-import")
+            .map_err(|e| format!("Cannot stage {}: {}", name, e))?;
+        staged.0.push(temp);
+        let mut hashing = Hashing { out: std::io::BufWriter::new(file), hasher: Sha256::new() };
+        tar.copy_member(&name, size, &mut hashing)?;
+        hashing.out.flush().map_err(|e| format!("Cannot stage {}: {}", name, e))?;
+        let digest = to_hex(&hashing.hasher.finalize());
+        if digest != entry.sha256 {
+            return Err(format!("Artifact {} has SHA-256 {}, its manifest says {}", name, digest, entry.sha256));
+        }
+    }
+    if members != manifest.artifacts.len() {
+        return Err(format!("Bundle has {} artifacts but its manifest lists {}", members, manifest.artifacts.len()));
+    }
+    
+    let workspace = workspace.as_ref();
+    if check == ProvenanceCheck::Enforce {
+        let describe = |p: &Option<Provenance>| p.as_ref().map_or("no provenance".to_string(), Provenance::to_string);
+        let indexes = index_entries(&manifest.artifacts);
+        let recorded: Vec<&ArtifactEntry> = indexes.iter().copied().filter(|a| a.provenance.is_some()).collect();
+        if let Some(pair) = recorded.windows(2).find(|w| w[0].provenance != w[1].provenance) {
+            return Err(format!("Bundled {} ({}) and {} ({}) have different provenance",
+                               pair[0].path, describe(&pair[0].provenance), pair[1].path, describe(&pair[1].provenance)));
+        }
+        for entry in &indexes {
+            let existing = workspace.join(&entry.path);
+            if !existing.exists() {
+                continue;
+            }
+            let current = index_provenance(&existing)?;
+            if current != entry.provenance {
+                return Err(format!("{} holds {}, the bundle {}; import with Override to replace it",
+                                   existing.display(), describe(&current), describe(&entry.provenance)));
+            }
+        }
+    }
+    
+    let mut contents = BundleContents::default();
+    for (temp, entry) in staged.0.iter().zip(&manifest.artifacts) {
+        let target = workspace.join(&entry.path);
+        fs::create_dir_all(target.parent().unwrap()).map_err(|e| format!("Cannot create {}: {}", target.display(), e))?;
+        atomic_write(&target, |file| std::io::copy(&mut fs::File::open(temp)?, file).map(|_| ()))
+            .map_err(|e| format!("Write failed for {}: {}", target.display(), e))?;
+        let slot = match entry.kind {
+            ArtifactKind::Index => &mut contents.index,
+            ArtifactKind::Cache => &mut contents.cache,
+            ArtifactKind::Triples => &mut contents.triples,
+            ArtifactKind::Templates => &mut contents.templates,
+            ArtifactKind::Manifest => &mut contents.manifest,
+        };
+        slot.get_or_insert(target);
+    }
+    Ok(contents)
+}
+
+/// The index files of a bundle: the main file of `Index` and of `Triples`
+fn index_entries(artifacts: &[ArtifactEntry]) -> Vec<&ArtifactEntry> {
+    [ArtifactKind::Index, ArtifactKind::Triples].iter()
+        .filter_map(|&kind| artifacts.iter().find(|a| a.kind == kind))
+        .collect()
+}
+
+/// Staged members, removed when the import ends
+struct Staged(Vec<PathBuf>);
+
+impl Drop for Staged {
+    fn drop(&mut self) {
+        for path in &self.0 {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Writes through to `out`, hashing what it writes
+struct Hashing<W> {
+    out: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for Hashing<W> {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        let n = self.out.write(bytes)?;
+        self.hasher.update(&bytes[..n]);
+        Ok(n)
+    }
+    
+    fn flush(&mut self) -> std::io::Result<()> { self.out.flush() }
+}
+
+fn index_provenance(path: &Path) -> Result<Option<Provenance>, String> {
+    let index = CrystalIndex::load(&path.to_string_lossy())?;
+    Ok(index.provenance().cloned())
+}
+
+/// `<kind>/<file name>` with nothing that could leave the kind's directory
+fn valid_member(name: &str, kind: ArtifactKind) -> bool {
+    name.strip_prefix(kind.dir()).and_then(|rest| rest.strip_prefix('/'))
+        .is_some_and(|file| !file.is_empty() && file != "." && file != ".." && !file.contains(['/', '\\']))
+}
+
+const BLOCK: usize = 512;
+
+/// Regular-file ustar members, dated 1970 so equal contents archive equally
+fn write_tar(out: &mut impl Write, members: &[(String, Vec<u8>)]) -> std::io::Result<()> {
+    for (name, bytes) in members {
+        if name.len() > 100 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("member name {} is over 100 bytes", name)));
+        }
+        let mut header = [0u8; BLOCK];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..108].copy_from_slice(b"0000644\0");
+        header[108..116].copy_from_slice(b"0000000\0");
+        header[116..124].copy_from_slice(b"0000000\0");
+        header[124..136].copy_from_slice(format!("{:011o}\0", bytes.len()).as_bytes());
+        header[136..148].copy_from_slice(b"00000000000\0");
+        header[156] = b'0';
+        header[257..265].copy_from_slice(b"ustar\x0000");
+        header[148..156].fill(b' ');
+        let sum: u32 = header.iter().map(|&b| b as u32).sum();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+        out.write_all(&header)?;
+        out.write_all(bytes)?;
+        out.write_all(&[0; BLOCK][..(BLOCK - bytes.len() % BLOCK) % BLOCK])?;
+    }
+    out.write_all(&[0; 2 * BLOCK])
+}
+
+/// Regular-file members of a tar stream, read in order
+struct TarReader<R>(R);
+
+impl<R: Read> TarReader<R> {
+    /// Name and size of the next member, `None` at the end of the archive
+    fn next_member(&mut self) -> Result<Option<(String, u64)>, String> {
+        let octal = |field: &[u8]| -> Result<u64, String> {
+            let text = std::str::from_utf8(field).map_err(|_| "Bundle has a corrupt tar header".to_string())?;
+            u64::from_str_radix(text.trim_matches(|c: char| c == '\0' || c == ' '), 8).map_err(|_| "Bundle has a corrupt tar header".to_string())
+        };
+        let mut header = [0u8; BLOCK];
+        self.0.read_exact(&mut header).map_err(|e| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => "Bundle ends inside a tar header".to_string(),
+            _ => e.to_string(),
+        })?;
+        if header.iter().all(|&b| b == 0) {
+            return Ok(None);
+        }
+        let sum: u64 = header.iter().enumerate().map(|(i, &b)| if (148..156).contains(&i) { b' ' as u64 } else { b as u64 }).sum();
+        if octal(&header[148..156])? != sum {
+            return Err("Bundle has a tar header with a wrong checksum".to_string());
+        }
+        if header[156] != b'0' && header[156] != 0 {
+            return Err(format!("Bundle member of type {:?} is not a regular file", header[156] as char));
+        }
+        let end = header[..100].iter().position(|&b| b == 0).unwrap_or(100);
+        let name = String::from_utf8(header[..end].to_vec()).map_err(|_| "Bundle member name is not UTF-8".to_string())?;
+        Ok(Some((name, octal(&header[124..136])?)))
+    }
+    
+    /// Copy the `size` bytes of member `name` to `out` and skip its padding
+    fn copy_member(&mut self, name: &str, size: u64, out: &mut impl Write) -> Result<(), String> {
+        let padded = size.div_ceil(BLOCK as u64) * BLOCK as u64;
+        let copied = std::io::copy(&mut self.0.by_ref().take(size), out).map_err(|e| format!("Cannot unpack {}: {}", name, e))?;
+        let skipped = std::io::copy(&mut self.0.by_ref().take(padded - size), &mut std::io::sink())
+            .map_err(|e| format!("Cannot unpack {}: {}", name, e))?;
+        if copied + skipped != padded {
+            return Err(format!("Bundle ends inside {}", name));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn path(dir: &tempfile::TempDir, name: &str) -> PathBuf { dir.path().join(name) }
+    
+    /// The bundle's tar, `edit`ed, gzipped back in place
+    fn tamper(bundle: &Path, edit: impl FnOnce(&mut Vec<u8>)) {
+        let mut tar = Vec::new();
+        GzDecoder::new(fs::File::open(bundle).unwrap()).read_to_end(&mut tar).unwrap();
+        edit(&mut tar);
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&tar).unwrap();
+        fs::write(bundle, gz.finish().unwrap()).unwrap();
+    }
+    
+    fn replace(tar: &mut [u8], from: &str, to: &str) {
+        assert_eq!(from.len(), to.len());
+        let at = tar.windows(from.len()).position(|w| w == from.as_bytes()).unwrap();
+        tar[at..at + to.len()].copy_from_slice(to.as_bytes());
+    }
+    
+    fn read_tar(tar: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+        let mut reader = TarReader(tar);
+        let mut members = Vec::new();
+        while let Some((name, size)) = reader.next_member()? {
+            let mut bytes = Vec::new();
+            reader.copy_member(&name, size, &mut bytes)?;
+            members.push((name, bytes));
+        }
+        Ok(members)
+    }
+    
+    #[test]
+    fn test_tar_roundtrip() {
+        let members = vec![
+            ("a.json".to_string(), b"{}".to_vec()),
+            ("index/empty".to_string(), Vec::new()),
+            ("cache/block".to_string(), vec![7; BLOCK]),
+        ];
+        let mut tar = Vec::new();
+        write_tar(&mut tar, &members).unwrap();
+        assert_eq!(tar.len(), BLOCK * (1 + 1 + 1 + 1 + 1 + 2));
+        assert_eq!(read_tar(&tar).unwrap(), members);
+        // Equal members give equal archives
+        let mut again = Vec::new();
+        write_tar(&mut again, &members).unwrap();
+        assert_eq!(tar, again);
+        for cut in [100, BLOCK + 1, 3 * BLOCK] {
+            assert!(read_tar(&tar[..cut]).is_err());
+        }
+        tar[124] ^= 1;
+        assert!(read_tar(&tar).unwrap_err().contains("checksum"));
+        assert!(write_tar(&mut Vec::new(), &[("x".repeat(101), Vec::new())]).is_err());
+        assert!(valid_member("cache/file.bin", ArtifactKind::Cache));
+        assert!(!valid_member("cache/../index/x", ArtifactKind::Cache) && !valid_member("index/x", ArtifactKind::Cache));
+    }
+    
+    #[test]
+    fn test_tampered_bundles_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(path(&dir, "templates.json"), r#"{"default":"{s} {p} {o}"}"#).unwrap();
+        fs::write(path(&dir, "cache.bin"), b"cached fingerprints").unwrap();
+        let contents = BundleContents {
+            cache: Some(path(&dir, "cache.bin")),
+            templates: Some(path(&dir, "templates.json")),
+            ..BundleContents::default()
+        };
+        let bundle = path(&dir, "state.bundle");
+        let manifest = export(&bundle, &contents).unwrap();
+        let digest = manifest.artifacts[0].sha256.clone();
+        let pristine = fs::read(&bundle).unwrap();
+        let workspace = path(&dir, "workspace");
+        
+        tamper(&bundle, |tar| replace(tar, "cached", "CACHED"));
+        let err = import(&bundle, &workspace, ProvenanceCheck::Enforce).unwrap_err();
+        assert!(err.contains("cache/cache.bin has SHA-256") && err.contains(&digest), "{}", err);
+        assert!(!workspace.exists());
+        
+        fs::write(&bundle, &pristine).unwrap();
+        tamper(&bundle, |tar| replace(tar, "\"version\": 1", "\"version\": 7"));
+        assert!(import(&bundle, &workspace, ProvenanceCheck::Enforce).unwrap_err().contains("Bundle version 7"));
+        
+        fs::write(&bundle, &pristine).unwrap();
+        tamper(&bundle, |tar| replace(tar, "cache/cache.bin\"", "cache/../ab.bin\""));
+        assert!(import(&bundle, &workspace, ProvenanceCheck::Override).unwrap_err().contains("is not the cache/../ab.bin"));
+        
+        fs::write(&bundle, &pristine).unwrap();
+        let imported = import(&bundle, &workspace, ProvenanceCheck::Enforce).unwrap();
+        assert_eq!(imported.cache, Some(workspace.join("cache/cache.bin")));
+        assert_eq!(fs::read(imported.templates.unwrap()).unwrap(), fs::read(path(&dir, "templates.json")).unwrap());
+        assert_eq!((imported.index, imported.triples, imported.manifest), (None, None, None));
+        assert!(import(path(&dir, "cache.bin"), &workspace, ProvenanceCheck::Enforce).unwrap_err().contains("Cannot read bundle"));
+    }
+}
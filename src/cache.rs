@@ -0,0 +1,281 @@
+//! Cache statistics over time and cache warmup
+//!
+//! Both caches, `JinaClient::with_cache` in memory and the persisted
+//! `jina_cache::JinaCache`, report a `CacheStats` snapshot: how many
+//! entries they hold, roughly how many bytes, the age of the oldest, and the
+//! hit rate of the lookups of the last few minutes as counted by a
+//! `HitWindow`.
+//!
+//! `warm` on either cache embeds the keys it is missing, a batch at a time
+//! on up to `Warmup::concurrency` threads, and reports progress after each
+//! batch. Keys already cached are skipped, so an interrupted or partly
+//! failed warmup resumes by running it again. The client stores each batch
+//! as it finishes while it keeps serving other calls; `JinaCache` stores
+//! (and saves) the batches once warmup is done. Warmup lookups are not
+//! counted in the hit window.
+//!
+//! The client's entries expire by its `CachePolicy`: never by default, as
+//! misses past a TTL, or stale-while-revalidate. Under the last, an entry
+//! past its TTL but within the stale window after it is still served at
+//! once while a background thread embeds its text again. Refreshes go
+//! through the client's single-flight (`coalesce`), so a stale key is sent
+//! once however many calls read it; a failed refresh leaves the stale entry
+//! in place. Past the stale window an entry is a miss.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::audit::unix_ms;
+use crate::jina_api::EmbedOptions;
+use crate::provider::EmbedError;
+
+/// Span of the hit rate in `CacheStats` unless configured otherwise
+pub const DEFAULT_HIT_WINDOW: Duration = Duration::from_secs(300);
+
+/// Buckets a window is split into; lookups expire a bucket at a time
+const BUCKETS: u64 = 60;
+
+/// Snapshot of a cache
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CacheStats {
+    pub entries: usize,
+    /// Estimated bytes of keys, texts and vectors
+    pub bytes: usize,
+    /// Hits over lookups in the hit window; `None` without lookups
+    pub hit_rate_window: Option<f64>,
+    /// `None` for an empty cache; always zero on wasm32, which has no clock
+    pub oldest_entry_age: Option<Duration>,
+}
+
+/// Age of an entry stored at `oldest_ms` (Unix time)
+pub(crate) fn entry_age(oldest_ms: Option<u64>) -> Option<Duration> {
+    oldest_ms.map(|ms| Duration::from_millis(unix_ms().saturating_sub(ms)))
+}
+
+/// When a cached embedding stops being served
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Entries are kept as long as the cache
+    #[default]
+    Forever,
+    /// Entries older than `ttl` are misses
+    Expire { ttl: Duration },
+    /// Entries older than `ttl` are served and refreshed in the background,
+    /// and are misses once older than `ttl + stale_ttl`
+    StaleWhileRevalidate { ttl: Duration, stale_ttl: Duration },
+}
+
+impl CachePolicy {
+    /// Whether an entry of `age` is served, and if so whether it is stale
+    pub(crate) fn serve(&self, age: Duration) -> Option<bool> {
+        match *self {
+            CachePolicy::Forever => Some(false),
+            CachePolicy::Expire { ttl } => (age <= ttl).then_some(false),
+            CachePolicy::StaleWhileRevalidate { ttl, stale_ttl } => (age <= ttl.saturating_add(stale_ttl)).then_some(age > ttl),
+        }
+    }
+}
+
+/// Hits and misses of the lookups over a sliding time window
+pub struct HitWindow {
+    bucket_ms: u64,
+    /// (bucket start, hits, misses), oldest first
+    buckets: Mutex<VecDeque<(u64, u64, u64)>>,
+}
+
+impl Default for HitWindow {
+    fn default() -> Self { Self::new(DEFAULT_HIT_WINDOW) }
+}
+
+impl HitWindow {
+    /// Window over the last `window`, to within a sixtieth of it
+    pub fn new(window: Duration) -> Self {
+        Self { bucket_ms: (window.as_millis() as u64 / BUCKETS).max(1), buckets: Mutex::new(VecDeque::new()) }
+    }
+    
+    pub fn record(&self, hits: u64, misses: u64) { self.record_at(unix_ms(), hits, misses) }
+    
+    /// Hits over lookups in the window; `None` without lookups
+    pub fn rate(&self) -> Option<f64> { self.rate_at(unix_ms()) }
+    
+    pub(crate) fn record_at(&self, now_ms: u64, hits: u64, misses: u64) {
+        if hits + misses == 0 {
+            return;
+        }
+        let start = now_ms - now_ms % self.bucket_ms;
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        match buckets.back_mut() {
+            // A clock stepping back keeps counting into the newest bucket
+            Some(bucket) if bucket.0 >= start => {
+                bucket.1 += hits;
+                bucket.2 += misses;
+            }
+            _ => buckets.push_back((start, hits, misses)),
+        }
+        self.expire(&mut buckets, now_ms);
+    }
+    
+    pub(crate) fn rate_at(&self, now_ms: u64) -> Option<f64> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        self.expire(&mut buckets, now_ms);
+        let (hits, misses) = buckets.iter().fold((0, 0), |(h, m), b| (h + b.1, m + b.2));
+        (hits + misses > 0).then(|| hits as f64 / (hits + misses) as f64)
+    }
+    
+    fn expire(&self, buckets: &mut VecDeque<(u64, u64, u64)>, now_ms: u64) {
+        let cutoff = now_ms.saturating_sub(self.bucket_ms * BUCKETS);
+        while buckets.front().is_some_and(|b| b.0 + self.bucket_ms <= cutoff) {
+            buckets.pop_front();
+        }
+    }
+}
+
+type OnProgress = Box<dyn Fn(WarmProgress) + Send + Sync>;
+
+/// How `warm` sends its batches
+pub struct Warmup {
+    batch_size: usize,
+    concurrency: usize,
+    on_progress: Option<OnProgress>,
+}
+
+impl Default for Warmup {
+    fn default() -> Self { Self { batch_size: 64, concurrency: 4, on_progress: None } }
+}
+
+impl Warmup {
+    /// Embed missing keys `n` at a time (default 64)
+    pub fn with_batch_size(mut self, n: usize) -> Self {
+        self.batch_size = n.max(1);
+        self
+    }
+    
+    /// Send up to `n` batches at once (default 4); 1 sends them from the
+    /// calling thread, as wasm32 needs
+    pub fn with_concurrency(mut self, n: usize) -> Self {
+        self.concurrency = n.max(1);
+        self
+    }
+    
+    /// Call `f` after every batch, one call at a time
+    pub fn with_progress(mut self, f: impl Fn(WarmProgress) + Send + Sync + 'static) -> Self {
+        self.on_progress = Some(Box::new(f));
+        self
+    }
+    
+    /// `from` grouped by options, without repeats, blanks or the keys `cached` holds
+    pub(crate) fn missing(&self, from: impl Iterator<Item = (String, EmbedOptions)>, cached: impl Fn(&str, &EmbedOptions) -> bool,
+                          report: &mut WarmReport) -> Vec<(EmbedOptions, Vec<String>)> {
+        let mut groups: Vec<(EmbedOptions, HashSet<String>, Vec<String>)> = Vec::new();
+        for (text, options) in from {
+            if text.trim().is_empty() {
+                continue;
+            }
+            let group = match groups.iter().position(|g| g.0 == options) {
+                Some(i) => &mut groups[i],
+                None => {
+                    groups.push((options, HashSet::new(), Vec::new()));
+                    groups.last_mut().unwrap()
+                }
+            };
+            if !group.1.insert(text.clone()) {
+                continue;
+            }
+            report.keys += 1;
+            if cached(&text, &group.0) {
+                report.already_cached += 1;
+            } else {
+                group.2.push(text);
+            }
+        }
+        groups.into_iter().map(|(options, _, texts)| (options, texts)).collect()
+    }
+    
+    /// Run `embed` over `groups` in batches; it stores a batch and returns
+    /// how many of its texts it embedded.
+    ///
+    /// A failed batch counts all its texts as failed and does not stop the others.
+    pub(crate) fn run(&self, groups: &[(EmbedOptions, Vec<String>)], report: &mut WarmReport,
+                      embed: impl Fn(&[String], &EmbedOptions) -> Result<usize, EmbedError> + Sync) {
+        let batches: Vec<(&EmbedOptions, &[String])> = groups.iter()
+            .flat_map(|(options, texts)| texts.chunks(self.batch_size).map(move |batch| (options, batch)))
+            .collect();
+        let total = batches.iter().map(|b| b.1.len()).sum();
+        let next = AtomicUsize::new(0);
+        let state = Mutex::new((WarmProgress { done: 0, failed: 0, total }, None));
+        let work = || while let Some(&(options, texts)) = batches.get(next.fetch_add(1, Ordering::Relaxed)) {
+            let result = embed(texts, options);
+            let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+            let (progress, error) = &mut *state;
+            match result {
+                Ok(embedded) => {
+                    progress.done += embedded;
+                    progress.failed += texts.len() - embedded;
+                }
+                Err(e) => {
+                    progress.failed += texts.len();
+                    error.get_or_insert(e);
+                }
+            }
+            if let Some(on_progress) = &self.on_progress {
+                on_progress(*progress);
+            }
+        };
+        let workers = self.concurrency.min(batches.len());
+        if workers <= 1 {
+            work();
+        } else {
+            std::thread::scope(|scope| {
+                for _ in 0..workers {
+                    scope.spawn(work);
+                }
+            });
+        }
+        let (progress, error) = state.into_inner().unwrap_or_else(|e| e.into_inner());
+        report.embedded += progress.done;
+        report.failed += progress.failed;
+        report.error = report.error.take().or(error);
+    }
+}
+
+/// Keys embedded so far out of those a warmup found missing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WarmProgress {
+    pub done: usize,
+    pub failed: usize,
+    pub total: usize,
+}
+
+/// Outcome of a warmup
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WarmReport {
+    /// Distinct non-blank keys given
+    pub keys: usize,
+    pub already_cached: usize,
+    pub embedded: usize,
+    /// Keys left missing; warming again retries them
+    pub failed: usize,
+    /// Error of the first failed batch
+    pub error: Option<EmbedError>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_hit_window_slides() {
+        let window = HitWindow::new(Duration::from_secs(60));
+        assert_eq!(window.rate_at(0), None);
+        window.record_at(1_000, 1, 3);
+        window.record_at(30_000, 4, 0);
+        assert_eq!(window.rate_at(30_000), Some(5.0 / 8.0));
+        // The first lookups age out a minute later, the others half a minute after
+        assert_eq!(window.rate_at(62_000), Some(1.0));
+        assert_eq!(window.rate_at(91_000), None);
+        window.record_at(91_000, 0, 0);
+        assert_eq!(window.rate_at(91_000), None);
+    }
+}
@@ -0,0 +1,843 @@
+//! Splitting text into embeddable chunks
+//!
+//! `chunk_text` is the local chunker: greedy word packing up to a
+//! character budget, with byte offsets back into the source. `Chunking`
+//! picks between it and Jina's segmenter (see `segment`). `sentences`
+//! finds sentence boundaries to cut at; `sliding_window` uses them for
+//! overlapping chunks. `markdown` and `code` cut along document structure.
+
+use std::ops::Range;
+
+use crate::metadata::Metadata;
+use crate::tokens::TokenCounter;
+
+/// A piece of a larger text; `start..end` is its byte range in the source
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Chunk {
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+    /// Set by structure-aware chunkers, e.g. `heading_path` from `markdown`
+    #[serde(default)]
+    pub metadata: Metadata,
+}
+
+impl Chunk {
+    /// The chunk `source[range]`
+    pub fn of(source: &str, range: Range<usize>) -> Self {
+        Chunk { text: source[range.clone()].to_string(), start: range.start, end: range.end, metadata: Metadata::new() }
+    }
+}
+
+/// A chunk and its embedding
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EmbeddedChunk {
+    pub chunk: Chunk,
+    pub embedding: Vec<f32>,
+}
+
+/// Where chunk boundaries come from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Chunking {
+    /// `chunk_text`, no network
+    #[default]
+    Local,
+    /// Jina's `/v1/segment` tokenizer-aware chunks when online, `Local` offline
+    Segmenter,
+    /// `sliding_window` with this much overlap
+    SlidingWindow { overlap: usize },
+    /// `markdown`
+    Markdown,
+    /// `code`, with an optional language hint
+    Code(Option<&'static str>),
+}
+
+impl Chunking {
+    /// Chunks of `text` cut without the network; `Segmenter` falls back to `chunk_text`
+    pub fn chunk_local(&self, text: &str, max_size: usize) -> Vec<Chunk> {
+        match *self {
+            Chunking::SlidingWindow { overlap } => sliding_window(text, max_size, overlap),
+            Chunking::Markdown => markdown(text, max_size),
+            Chunking::Code(language) => code(text, language, max_size),
+            Chunking::Local | Chunking::Segmenter => chunk_text(text, max_size),
+        }
+    }
+}
+
+/// Pack whitespace-separated words into chunks of at most `max_chars` characters
+///
+/// A single word longer than `max_chars` becomes its own chunk. Whitespace
+/// between chunks is dropped; whitespace inside a chunk is kept verbatim.
+pub fn chunk_text(text: &str, max_chars: usize) -> Vec<Chunk> {
+    let max_chars = max_chars.max(1);
+    let mut chunks = Vec::new();
+    let mut current: Option<(usize, usize, usize)> = None;  // (start, end, chars)
+    
+    for (start, word) in word_spans(text) {
+        let end = start + word.len();
+        let word_chars = word.chars().count();
+        current = match current {
+            Some((s, e, chars)) => {
+                let gap = text[e..start].chars().count();
+                if chars + gap + word_chars <= max_chars {
+                    Some((s, end, chars + gap + word_chars))
+                } else {
+                    chunks.push(Chunk::of(text, s..e));
+                    Some((start, end, word_chars))
+                }
+            }
+            None => Some((start, end, word_chars)),
+        };
+    }
+    if let Some((s, e, _)) = current {
+        chunks.push(Chunk::of(text, s..e));
+    }
+    chunks
+}
+
+/// Byte offset and text of each whitespace-separated word
+fn word_spans(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split_whitespace().map(move |w| (w.as_ptr() as usize - text.as_ptr() as usize, w))
+}
+
+/// Lowercased, dot-free words that a following `.` does not end a sentence after
+const ABBREVIATIONS: &[&str] = &[
+    "approx", "cf", "co", "corp", "dept", "dr", "eg", "esp", "est", "etc", "fig", "ie", "inc", "jr",
+    "ltd", "mr", "mrs", "ms", "mt", "no", "nr", "prof", "sr", "st", "vol", "vs", "bzw", "ca", "vgl", "zb",
+];
+
+fn is_terminal(c: char) -> bool { matches!(c, '.' | '!' | '?' | '…' | '。' | '！' | '？' | '｡') }
+
+/// Full-width terminals end a sentence without following whitespace
+fn is_cjk_terminal(c: char) -> bool { matches!(c, '。' | '！' | '？' | '｡') }
+
+fn is_closer(c: char) -> bool { matches!(c, '"' | '\'' | '”' | '’' | '»' | ')' | ']' | '}' | '」' | '』' | '）') }
+
+/// Sentences of `text`, as trimmed slices of it
+///
+/// Splits after `.`, `!`, `?` and `…` followed by whitespace, and after CJK
+/// full stops, keeping closing quotes and brackets with the sentence they
+/// close. Decimal numbers, single-letter initials and common abbreviations
+/// ("e.g.", "Dr.") do not end a sentence. Text without any terminal
+/// punctuation is one sentence; whitespace-only text has none.
+pub fn sentences(text: &str) -> Vec<&str> {
+    sentence_ranges(text).into_iter().map(|r| &text[r]).collect()
+}
+
+/// Byte ranges of `sentences(text)` in `text`
+pub fn sentence_ranges(text: &str) -> Vec<Range<usize>> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i].1;
+        if !is_terminal(c) || (c == '.' && !ends_sentence(text, &chars, i)) {
+            i += 1;
+            continue;
+        }
+        let cjk = is_cjk_terminal(c);
+        let mut j = i + 1;
+        while j < chars.len() && is_terminal(chars[j].1) {
+            j += 1;
+        }
+        while j < chars.len() && is_closer(chars[j].1) {
+            j += 1;
+        }
+        let end = chars.get(j).map_or(text.len(), |&(b, _)| b);
+        if cjk || j == chars.len() || chars[j].1.is_whitespace() {
+            push_trimmed(text, start..end, &mut ranges);
+            start = end;
+        }
+        i = j;
+    }
+    push_trimmed(text, start..text.len(), &mut ranges);
+    ranges
+}
+
+/// Whether the `.` at `chars[i]` can end a sentence
+fn ends_sentence(text: &str, chars: &[(usize, char)], i: usize) -> bool {
+    let before = chars[..i].last().map(|&(_, c)| c);
+    let after = chars.get(i + 1).map(|&(_, c)| c);
+    if before.is_some_and(|c| c.is_ascii_digit()) && after.is_some_and(|c| c.is_ascii_digit()) {
+        return false;
+    }
+    // The word the dot ends, e.g. "e.g" or "Dr"
+    let word_start = chars[..i].iter().rposition(|&(_, c)| c.is_whitespace() || c == '(' || c == '"')
+        .map_or(0, |k| k + 1);
+    let word = &text[chars.get(word_start).map_or(0, |&(b, _)| b)..chars[i].0];
+    let bare: String = word.chars().filter(|&c| c != '.').flat_map(char::to_lowercase).collect();
+    let initial = word.chars().count() == 1 && word.chars().all(char::is_uppercase);
+    // "p.m", "U.S": short letter groups joined by dots
+    let dotted = word.contains('.') && word.split('.').all(|p| (1..=2).contains(&p.chars().count()) && p.chars().all(char::is_alphabetic));
+    !(initial || dotted || ABBREVIATIONS.contains(&bare.as_str()))
+}
+
+fn push_trimmed(text: &str, range: Range<usize>, ranges: &mut Vec<Range<usize>>) {
+    let piece = &text[range.clone()];
+    let trimmed = piece.trim_start();
+    let start = range.start + piece.len() - trimmed.len();
+    let end = start + trimmed.trim_end().len();
+    if start < end {
+        ranges.push(start..end);
+    }
+}
+
+/// Where `truncate_to_budget` cut its text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cut {
+    /// The text already fit
+    None,
+    Sentence,
+    /// Not even the first sentence fit
+    Word,
+    /// Not even the first word fit
+    Char,
+}
+
+/// A prefix of a text that fits a token budget
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Truncated<'a> {
+    pub text: &'a str,
+    /// Bytes cut from the end of the source
+    pub removed: usize,
+    pub cut: Cut,
+}
+
+/// The longest prefix of `text` that `counter` puts at `budget` tokens or
+/// fewer, ending after a whole sentence if any fits, else after a whole
+/// word, else on a char boundary (so possibly empty).
+///
+/// Prefixes are searched by bisection, assuming a longer prefix never
+/// counts fewer tokens, so a single huge sentence costs O(log n) counts
+/// rather than one per word.
+pub fn truncate_to_budget<'a>(text: &'a str, budget: usize, counter: &dyn TokenCounter) -> Truncated<'a> {
+    if counter.count(text) <= budget {
+        return Truncated { text, removed: 0, cut: Cut::None };
+    }
+    let fits = |end: &usize| counter.count(&text[..*end]) <= budget;
+    let sentence_ends: Vec<usize> = sentence_ranges(text).into_iter().map(|r| r.end).collect();
+    let word_ends: Vec<usize> = word_spans(text).map(|(start, word)| start + word.len()).collect();
+    let char_ends: Vec<usize> = text.char_indices().map(|(i, c)| i + c.len_utf8()).collect();
+    let (end, cut) = [(sentence_ends, Cut::Sentence), (word_ends, Cut::Word), (char_ends, Cut::Char)]
+        .into_iter()
+        .find_map(|(ends, cut)| longest_fitting(&ends, fits).map(|end| (end, cut)))
+        .unwrap_or((0, Cut::Char));
+    Truncated { text: &text[..end], removed: text.len() - end, cut }
+}
+
+/// The last of ascending `ends` that `fits`, by bisection
+fn longest_fitting(ends: &[usize], fits: impl Fn(&usize) -> bool) -> Option<usize> {
+    let n = ends.partition_point(fits);
+    n.checked_sub(1).map(|i| ends[i])
+}
+
+/// Overlapping chunks of at most `max_chars` characters, overlapping by about `overlap`
+///
+/// See `sliding_window_by`.
+pub fn sliding_window(text: &str, max_chars: usize, overlap: usize) -> Vec<Chunk> {
+    sliding_window_by(text, max_chars, overlap, &|t: &str| t.chars().count())
+}
+
+/// Overlapping chunks of at most `size` units as measured by `counter`
+/// (e.g. `tokens::Approximate`), each starting with up to `overlap` units
+/// of the previous chunk's tail
+///
+/// Chunks break at sentence boundaries where they can, at whitespace when a
+/// sentence is over `size`, and between characters when a single word is.
+/// Every non-whitespace byte of `text` lands in at least one chunk.
+///
+/// Degenerate inputs: `size` 0 is treated as 1; an `overlap` of `size` or
+/// more still advances every chunk by at least one piece (sentence, word or
+/// character run), so the output is finite; empty or whitespace-only text
+/// yields no chunks; a character alone over `size` is a chunk by itself.
+pub fn sliding_window_by(text: &str, size: usize, overlap: usize, counter: &dyn TokenCounter) -> Vec<Chunk> {
+    let size = size.max(1);
+    let measure = |r: Range<usize>| counter.count(&text[r]);
+    let pieces: Vec<Range<usize>> = sentence_ranges(text).into_iter()
+        .flat_map(|sentence| split_to_fit(text, sentence, size, &measure))
+        .collect();
+    
+    let mut chunks = Vec::new();
+    let mut first = 0;
+    while first < pieces.len() {
+        let start = pieces[first].start;
+        let mut last = first;
+        while last + 1 < pieces.len() && measure(start..pieces[last + 1].end) <= size {
+            last += 1;
+        }
+        let end = pieces[last].end;
+        chunks.push(Chunk::of(text, start..end));
+        if last + 1 == pieces.len() {
+            break;
+        }
+        // Back up from the next piece while the tail fits in `overlap` and
+        // still leaves room for the next piece
+        let mut next = last + 1;
+        let fits = |k: usize| measure(pieces[k].start..end) <= overlap && measure(pieces[k].start..pieces[last + 1].end) <= size;
+        while next - 1 > first && fits(next - 1) {
+            next -= 1;
+        }
+        first = next;
+    }
+    chunks
+}
+
+/// `range` as one piece if it fits `size`, else its words, else runs of characters
+fn split_to_fit(text: &str, range: Range<usize>, size: usize, measure: &dyn Fn(Range<usize>) -> usize) -> Vec<Range<usize>> {
+    if measure(range.clone()) <= size {
+        return vec![range];
+    }
+    let words: Vec<Range<usize>> = word_spans(&text[range.clone()])
+        .map(|(i, w)| range.start + i..range.start + i + w.len())
+        .collect();
+    if words.len() > 1 {
+        return words.into_iter().flat_map(|w| split_to_fit(text, w, size, measure)).collect();
+    }
+    // One word over `size`: longest character runs that fit, at least one char each
+    let mut runs = Vec::new();
+    let mut start = range.start;
+    while start < range.end {
+        let mut end = start + text[start..].chars().next().map_or(1, char::len_utf8);
+        while end < range.end {
+            let next = end + text[end..].chars().next().map_or(1, char::len_utf8);
+            if measure(start..next) > size {
+                break;
+            }
+            end = next;
+        }
+        runs.push(start..end);
+        start = end;
+    }
+    runs
+}
+
+/// Kinds of markdown block `markdown` keeps whole
+#[derive(Clone, Debug, PartialEq)]
+enum Block {
+    Heading(usize, String),
+    /// Fenced code, fences included
+    Fence,
+    Table,
+    Text,
+}
+
+/// Markdown chunks of at most `max_size` characters, cut between blocks
+///
+/// A heading starts a new chunk and stays with the blocks under it; the
+/// `heading_path` metadata holds the enclosing headings, e.g.
+/// "Install > Linux". Fenced code blocks and tables are split (by line)
+/// only when one alone exceeds `max_size`; long paragraphs split at
+/// sentences. YAML front matter (`---` ... `---` at the very start) is not
+/// chunked.
+pub fn markdown(text: &str, max_size: usize) -> Vec<Chunk> {
+    let max_size = max_size.max(1);
+    let chars = |r: &Range<usize>| text[r.clone()].chars().count();
+    let mut chunks = Vec::new();
+    let mut path: Vec<(usize, String)> = Vec::new();
+    let mut pending: Option<Range<usize>> = None;
+    
+    for (block, range) in markdown_blocks(text, front_matter_end(text)) {
+        if let Block::Heading(level, title) = &block {
+            push_section(text, pending.take(), &path, &mut chunks);
+            path.retain(|(l, _)| l < level);
+            path.push((*level, title.clone()));
+        }
+        if let Some(p) = &pending {
+            if chars(&(p.start..range.end)) <= max_size {
+                pending = Some(p.start..range.end);
+                continue;
+            }
+        }
+        push_section(text, pending.take(), &path, &mut chunks);
+        if chars(&range) <= max_size {
+            pending = Some(range);
+            continue;
+        }
+        let pieces = match block {
+            Block::Fence | Block::Table => pack_lines(text, range, max_size),
+            _ => sliding_window(&text[range.clone()], max_size, 0).into_iter()
+                .map(|c| range.start + c.start..range.start + c.end)
+                .collect(),
+        };
+        for piece in pieces {
+            push_section(text, Some(piece), &path, &mut chunks);
+        }
+    }
+    push_section(text, pending, &path, &mut chunks);
+    chunks
+}
+
+fn push_section(text: &str, range: Option<Range<usize>>, path: &[(usize, String)], chunks: &mut Vec<Chunk>) {
+    let Some(range) = range else { return };
+    let mut chunk = Chunk::of(text, range);
+    if !path.is_empty() {
+        let titles: Vec<&str> = path.iter().map(|(_, t)| t.as_str()).collect();
+        chunk.metadata = chunk.metadata.with("heading_path", titles.join(" > "));
+    }
+    chunks.push(chunk);
+}
+
+/// Byte offset where the body starts after any YAML front matter
+fn front_matter_end(text: &str) -> usize {
+    let Some(rest) = text.strip_prefix("---\n").or_else(|| text.strip_prefix("---\r\n")) else { return 0 };
+    let mut offset = text.len() - rest.len();
+    for line in rest.split_inclusive('\n') {
+        offset += line.len();
+        if matches!(line.trim_end(), "---" | "...") {
+            return offset;
+        }
+    }
+    0
+}
+
+/// Blocks of `text[from..]` with their byte ranges, trailing newlines excluded
+fn markdown_blocks(text: &str, from: usize) -> Vec<(Block, Range<usize>)> {
+    let mut lines = Vec::new();
+    let mut offset = from;
+    for line in text[from..].split_inclusive('\n') {
+        lines.push((offset, line.trim_end_matches(['\n', '\r'])));
+        offset += line.len();
+    }
+    
+    let mut blocks = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let (start, line) = lines[i];
+        let trimmed = line.trim_start();
+        let block = if trimmed.is_empty() {
+            i += 1;
+            continue;
+        } else if let Some(fence) = fence_marker(trimmed) {
+            i += 1;
+            while i < lines.len() && !lines[i].1.trim().starts_with(fence) {
+                i += 1;
+            }
+            i = (i + 1).min(lines.len());
+            Block::Fence
+        } else if let Some(level) = heading_level(trimmed) {
+            i += 1;
+            Block::Heading(level, trimmed[level..].trim().trim_end_matches('#').trim().to_string())
+        } else {
+            let table = trimmed.starts_with('|');
+            i += 1;
+            while i < lines.len() {
+                let next = lines[i].1.trim_start();
+                let continues = if table {
+                    next.starts_with('|')
+                } else {
+                    !next.is_empty() && !next.starts_with('|') && fence_marker(next).is_none() && heading_level(next).is_none()
+                };
+                if !continues {
+                    break;
+                }
+                i += 1;
+            }
+            if table { Block::Table } else { Block::Text }
+        };
+        let (last_start, last) = lines[i - 1];
+        blocks.push((block, start + (line.len() - trimmed.len())..last_start + last.len()));
+    }
+    blocks
+}
+
+fn fence_marker(line: &str) -> Option<&'static str> {
+    if line.starts_with("```") {
+        Some("```")
+    } else if line.starts_with("~~~") {
+        Some("~~~")
+    } else {
+        None
+    }
+}
+
+fn heading_level(line: &str) -> Option<usize> {
+    let level = line.bytes().take_while(|&b| b == b'#').count();
+    let rest = &line[level..];
+    ((1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(' '))).then_some(level)
+}
+
+/// Ranges of whole lines of `text[range]` packed up to `max_size` characters;
+/// a line over `max_size` is split between characters
+fn pack_lines(text: &str, range: Range<usize>, max_size: usize) -> Vec<Range<usize>> {
+    let chars = |r: Range<usize>| text[r].chars().count();
+    let mut pieces: Vec<Range<usize>> = Vec::new();
+    let mut offset = range.start;
+    for line in text[range.clone()].split_inclusive('\n') {
+        let line_range = offset..offset + line.trim_end_matches(['\n', '\r']).len();
+        offset += line.len();
+        for part in split_to_fit(text, line_range, max_size, &chars) {
+            match pieces.last_mut() {
+                Some(last) if chars(last.start..part.end) <= max_size => last.end = part.end,
+                _ => pieces.push(part),
+            }
+        }
+    }
+    pieces
+}
+
+/// Line-comment markers for a language hint; unknown or no hint accepts `//` and `#`
+pub(crate) fn comment_markers(language: Option<&str>) -> &'static [&'static str] {
+    match language.map(str::to_ascii_lowercase).as_deref() {
+        Some("python" | "py" | "ruby" | "rb" | "sh" | "bash" | "shell" | "perl" | "r" | "toml" | "yaml" | "yml") => &["#"],
+        Some("sql" | "lua" | "haskell" | "hs" | "ada" | "elm") => &["--"],
+        Some("rust" | "rs" | "c" | "cpp" | "c++" | "cs" | "csharp" | "go" | "java" | "javascript" | "js"
+             | "typescript" | "ts" | "kotlin" | "swift" | "scala" | "zig") => &["//"],
+        _ => &["//", "#"],
+    }
+}
+
+/// Source-code chunks of at most `max_size` characters
+///
+/// Blocks are runs of lines separated by blank lines at bracket depth zero,
+/// where a blank line followed by deeper indentation (a Python body) does
+/// not end the block. A block of only comments attaches to the block after
+/// it. Blocks pack together up to `max_size`; an oversized block splits at
+/// its inner blank lines, then by line. `language` picks the comment syntax
+/// and is recorded as `language` metadata.
+pub fn code(text: &str, language: Option<&str>, max_size: usize) -> Vec<Chunk> {
+    let max_size = max_size.max(1);
+    let markers = comment_markers(language);
+    let chars = |r: Range<usize>| text[r].chars().count();
+    
+    let mut pieces: Vec<Range<usize>> = Vec::new();
+    // Parts of an oversized block pack only with each other
+    let mut sealed = false;
+    for block in code_blocks(text, markers) {
+        let oversized = chars(block.clone()) > max_size;
+        let parts = if oversized {
+            paragraphs(text, block).into_iter()
+                .flat_map(|p| if chars(p.clone()) <= max_size { vec![p] } else { pack_lines(text, p, max_size) })
+                .collect()
+        } else {
+            vec![block]
+        };
+        sealed |= oversized;
+        for part in parts {
+            match pieces.last_mut() {
+                Some(last) if !sealed && chars(last.start..part.end) <= max_size => last.end = part.end,
+                _ => pieces.push(part),
+            }
+            sealed = false;
+        }
+        sealed = oversized;
+    }
+    pieces.into_iter().map(|range| {
+        let mut chunk = Chunk::of(text, range);
+        if let Some(language) = language {
+            chunk.metadata = chunk.metadata.with("language", language);
+        }
+        chunk
+    }).collect()
+}
+
+/// Lines of `text` as (byte range without the newline, indentation)
+fn line_spans(text: &str, range: Range<usize>) -> Vec<(Range<usize>, usize)> {
+    let mut offset = range.start;
+    text[range].split_inclusive('\n').map(|line| {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let span = offset..offset + content.len();
+        offset += line.len();
+        (span, content.len() - content.trim_start().len())
+    }).collect()
+}
+
+fn code_blocks(text: &str, markers: &[&str]) -> Vec<Range<usize>> {
+    let lines = line_spans(text, 0..text.len());
+    let blank = |i: usize| text[lines[i].0.clone()].trim().is_empty();
+    let is_comment = |r: &Range<usize>| text[r.clone()].lines()
+        .all(|l| l.trim().is_empty() || markers.iter().any(|m| l.trim_start().starts_with(m)));
+    
+    let mut blocks: Vec<Range<usize>> = Vec::new();
+    let mut current: Option<(Range<usize>, usize)> = None;  // (range, indentation of first line)
+    let mut depth = 0;
+    for i in 0..lines.len() {
+        let (span, indent) = lines[i].clone();
+        if blank(i) {
+            let next_indent = (i + 1..lines.len()).find(|&j| !blank(j)).map(|j| lines[j].1);
+            if let Some((range, first_indent)) = current.take_if(|_| depth <= 0) {
+                if next_indent.is_some_and(|n| n > first_indent) {
+                    current = Some((range, first_indent));
+                } else {
+                    blocks.push(range);
+                }
+            }
+            continue;
+        }
+        depth += bracket_delta(&text[span.clone()], markers);
+        current = match current {
+            Some((range, first_indent)) => Some((range.start..span.end, first_indent)),
+            None => Some((span, indent)),
+        };
+    }
+    blocks.extend(current.map(|(range, _)| range));
+    
+    // A comment-only block belongs to the code after it
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    let mut pending: Option<usize> = None;
+    for block in blocks {
+        let start = pending.take().unwrap_or(block.start);
+        if is_comment(&block) {
+            pending = Some(start);
+        } else {
+            merged.push(start..block.end);
+        }
+    }
+    if let Some(start) = pending {
+        merged.push(start..text.trim_end().len());
+    }
+    merged
+}
+
+/// Runs of non-blank lines in `text[range]`
+fn paragraphs(text: &str, range: Range<usize>) -> Vec<Range<usize>> {
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    let mut open = false;
+    for (span, _) in line_spans(text, range) {
+        if text[span.clone()].trim().is_empty() {
+            open = false;
+            continue;
+        }
+        match runs.last_mut() {
+            Some(last) if open => last.end = span.end,
+            _ => runs.push(span),
+        }
+        open = true;
+    }
+    runs
+}
+
+/// Change in `{[(` nesting over `line`, skipping string and char literals and trailing comments
+fn bracket_delta(line: &str, markers: &[&str]) -> isize {
+    let quotes_are_strings = !markers.contains(&"//");
+    let bytes = line.as_bytes();
+    let mut delta = 0;
+    let mut in_string: Option<u8> = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        match in_string {
+            Some(_) if b == b'\\' => i += 1,
+            Some(q) if b == q => in_string = None,
+            Some(_) => {}
+            None if markers.iter().any(|m| line[i..].starts_with(m)) => break,
+            None if b == b'"' || (b == b'\'' && quotes_are_strings) => in_string = Some(b),
+            // Char literal: 'x' or '\x'
+            None if b == b'\'' && bytes.get(i + 1) == Some(&b'\\') => i += 3,
+            None if b == b'\'' && line[i + 1..].chars().nth(1) == Some('\'') => {
+                i += 1 + line[i + 1..].chars().next().map_or(0, char::len_utf8);
+            }
+            None if matches!(b, b'{' | b'[' | b'(') => delta += 1,
+            None if matches!(b, b'}' | b']' | b')') => delta -= 1,
+            None => {}
+        }
+        i += 1;
+    }
+    delta
+}
+
+/// Attach byte offsets to chunks cut from `text`, searching forward from the previous chunk
+///
+/// Chunks not found verbatim (the cutter normalized them) get the current
+/// position as an empty range.
+pub(crate) fn locate_chunks(text: &str, pieces: Vec<String>) -> Vec<Chunk> {
+    let mut cursor = 0;
+    pieces.into_iter().map(|piece| {
+        let trimmed = piece.trim();
+        let (start, end) = match text[cursor..].find(trimmed) {
+            Some(i) => (cursor + i, cursor + i + trimmed.len()),
+            None => (cursor, cursor),
+        };
+        cursor = end;
+        Chunk { text: piece, start, end, metadata: Metadata::new() }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokens::Approximate;
+    
+    #[test]
+    fn test_chunk_text_packs_words_with_offsets() {
+        let text = "  the cat  sat on the mat\nand purred loudly ";
+        let chunks = chunk_text(text, 12);
+        assert_eq!(chunks.iter().map(|c| c.text.as_str()).collect::<Vec<_>>(),
+                   vec!["the cat  sat", "on the mat", "and purred", "loudly"]);
+        assert!(chunks.iter().all(|c| text[c.start..c.end] == c.text));
+        assert!(chunks.iter().all(|c| c.text.chars().count() <= 12));
+        
+        assert_eq!(chunk_text("supercalifragilistic ok", 5)[0].text, "supercalifragilistic");
+        assert!(chunk_text(" \n ", 10).is_empty());
+        assert_eq!(chunk_text("héllo wörld", 11).len(), 1);
+    }
+    
+    #[test]
+    fn test_sentences() {
+        assert_eq!(sentences("Dr. Smith arrived at 3.30 p.m. today. He said \"hello.\" Then he left!"),
+                   vec!["Dr. Smith arrived at 3.30 p.m. today.", "He said \"hello.\"", "Then he left!"]);
+        assert_eq!(sentences("Use a model, e.g. jina-embeddings-v3, or ask J. Doe... Really?! Yes"),
+                   vec!["Use a model, e.g. jina-embeddings-v3, or ask J. Doe...", "Really?!", "Yes"]);
+        assert_eq!(sentences("今天天气很好。我们去公园吧！好的"), vec!["今天天气很好。", "我们去公园吧！", "好的"]);
+        assert_eq!(sentences("  no punctuation at all  "), vec!["no punctuation at all"]);
+        assert_eq!(sentences("see example.com (v2.1)."), vec!["see example.com (v2.1)."]);
+        assert!(sentences(" \n ").is_empty());
+        
+        let text = "Één zin. Tweede zin.";
+        let ranges = sentence_ranges(text);
+        assert_eq!(ranges, vec![0..10, 11..22]);
+        assert_eq!(&text[ranges[1].clone()], "Tweede zin.");
+    }
+    
+    #[test]
+    fn test_sliding_window_prefers_sentences() {
+        let text = "One two three. Four five six. Seven eight nine. Ten.";
+        let chunks = sliding_window(text, 40, 15);
+        assert_eq!(chunks.iter().map(|c| c.text.as_str()).collect::<Vec<_>>(),
+                   vec!["One two three. Four five six.", "Four five six. Seven eight nine. Ten."]);
+        assert_eq!((chunks[1].start, chunks[1].end), (15, text.len()));
+        
+        // Oversized single word: split between chars, never inside one
+        let chunks = sliding_window("ääääää", 4, 0);
+        assert_eq!(chunks.iter().map(|c| c.text.as_str()).collect::<Vec<_>>(), vec!["ääää", "ää"]);
+        // Degenerate settings terminate
+        assert_eq!(sliding_window("a b c", 0, 10).len(), 3);
+        assert!(sliding_window("  ", 10, 2).is_empty());
+        let words = |t: &str| t.split_whitespace().count();
+        assert_eq!(sliding_window_by("a b c d e", 2, 1, &words).iter().map(|c| c.text.as_str()).collect::<Vec<_>>(),
+                   vec!["a b", "b c", "c d", "d e"]);
+    }
+    
+    #[test]
+    fn test_truncate_to_budget_prefers_sentences_then_words_then_chars() {
+        let chars = |t: &str| t.chars().count();
+        let text = "First one. Second sentence here. Third.";
+        let whole = truncate_to_budget(text, 100, &chars);
+        assert_eq!((whole.text, whole.removed, whole.cut), (text, 0, Cut::None));
+        let cut = truncate_to_budget(text, 35, &chars);
+        assert_eq!((cut.text, cut.removed, cut.cut), ("First one. Second sentence here.", 7, Cut::Sentence));
+        assert_eq!(truncate_to_budget(text, 31, &chars).text, "First one.");
+        // One huge sentence: whole words, then chars, never splitting a char
+        let cut = truncate_to_budget("Ünïcödé wörds without any end", 14, &chars);
+        assert_eq!((cut.text, cut.cut), ("Ünïcödé wörds", Cut::Word));
+        let cut = truncate_to_budget("Ünïcödé wörds", 4, &chars);
+        assert_eq!((cut.text, cut.removed, cut.cut), ("Ünïc", "ödé wörds".len(), Cut::Char));
+        assert_eq!(truncate_to_budget("日本語の文です。次の文。", 5, &chars).text, "日本語の文");
+        assert_eq!(truncate_to_budget("abc", 0, &chars).text, "");
+        
+        // Every cut fits the estimator's budget
+        let long = "The quick brown fox jumps over the lazy dog. ".repeat(200) + &"x".repeat(5000);
+        for budget in [0, 1, 7, 50, 333, 2000] {
+            let cut = truncate_to_budget(&long, budget, &Approximate);
+            assert!(Approximate.count(cut.text) <= budget, "budget {}", budget);
+            assert_eq!(cut.text.len() + cut.removed, long.len());
+        }
+    }
+    
+    proptest::proptest! {
+        #![proptest_config(crate::proptest_config(128))]
+        
+        #[test]
+        fn prop_sliding_window_covers_with_offsets(text in "[a-zé語 .!?\n]{0,120}", size in 1usize..40, overlap in 0usize..50) {
+            let chunks = sliding_window(&text, size, overlap);
+            let mut covered = vec![false; text.len()];
+            for (i, chunk) in chunks.iter().enumerate() {
+                proptest::prop_assert_eq!(&text[chunk.start..chunk.end], chunk.text.as_str());
+                proptest::prop_assert!(chunk.text.chars().count() <= size, "{:?} over {}", chunk.text, size);
+                if i > 0 {
+                    proptest::prop_assert!(chunk.start > chunks[i - 1].start);
+                }
+                covered[chunk.start..chunk.end].iter_mut().for_each(|c| *c = true);
+            }
+            for (i, c) in text.char_indices() {
+                proptest::prop_assert!(c.is_whitespace() || covered[i], "byte {} of {:?} uncovered", i, text);
+            }
+        }
+    }
+    
+    fn heading_paths(chunks: &[Chunk]) -> Vec<Option<&str>> {
+        chunks.iter().map(|c| c.metadata.get_str("heading_path")).collect()
+    }
+    
+    #[test]
+    fn test_markdown_nested_headings_and_front_matter() {
+        let doc = "---\ntitle: Guide\n---\nIntro text.\n\n# Install\n\nGet it.\n\n## Linux\n\nUse apt.\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n## macOS ##\n\nUse brew.\n\n# Usage\nRun it.\n";
+        let chunks = markdown(doc, 200);
+        assert_eq!(chunks.iter().map(|c| c.text.as_str()).collect::<Vec<_>>(), vec![
+            "Intro text.",
+            "# Install\n\nGet it.",
+            "## Linux\n\nUse apt.\n\n| a | b |\n|---|---|\n| 1 | 2 |",
+            "## macOS ##\n\nUse brew.",
+            "# Usage\nRun it.",
+        ]);
+        assert_eq!(heading_paths(&chunks), vec![None, Some("Install"), Some("Install > Linux"), Some("Install > macOS"), Some("Usage")]);
+        assert!(chunks.iter().all(|c| doc[c.start..c.end] == c.text));
+        
+        // Tight budget: blocks split apart, the table stays whole
+        let chunks = markdown(doc, 30);
+        assert!(chunks.iter().any(|c| c.text == "| a | b |\n|---|---|\n| 1 | 2 |"));
+        assert_eq!(markdown("---\nno closing fence", 100)[0].text, "---\nno closing fence");
+    }
+    
+    #[test]
+    fn test_markdown_keeps_fences_whole_unless_oversized() {
+        let code: String = (0..12).map(|i| format!("let x{} = {};\n", i, i)).collect();
+        let doc = format!("# Code\n\nBefore.\n\n```rust\n{}```\n\nAfter. # not a heading\n", code);
+        let whole = markdown(&doc, 400);
+        assert_eq!(whole.len(), 1);
+        
+        let chunks = markdown(&doc, 60);
+        let fence: Vec<&Chunk> = chunks.iter().filter(|c| c.text.contains("let x")).collect();
+        assert!(fence.len() > 1);
+        assert!(fence[0].text.starts_with("```rust\n"));
+        assert!(fence.iter().all(|c| c.text.chars().count() <= 60 && !c.text.contains("Before")));
+        // Line-based: every piece ends at a line end
+        assert!(fence.iter().all(|c| doc[c.end..].starts_with('\n')));
+        assert!(heading_paths(&chunks).iter().all(|p| *p == Some("Code")));
+    }
+    
+    /// Whether the text from `from` to the next `to` after it lies inside one chunk
+    fn kept_whole(chunks: &[Chunk], source: &str, from: &str, to: &str) -> bool {
+        let start = source.find(from).unwrap();
+        let end = start + source[start..].find(to).unwrap() + to.len();
+        chunks.iter().any(|c| c.start <= start && end <= c.end)
+    }
+    
+    #[test]
+    fn test_code_keeps_rust_functions_whole() {
+        let source = include_str!("../fixtures/code/sample.rs");
+        let chunks = code(source, Some("rust"), 260);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.text.chars().count() <= 260 && source[c.start..c.end] == c.text));
+        assert!(chunks.iter().all(|c| c.metadata.get_str("language") == Some("rust")));
+        assert!(kept_whole(&chunks, source, "/// Word counts", "    counts\n}"));
+        assert!(kept_whole(&chunks, source, "// Brace characters", "not a close\")\n}"));
+        assert!(kept_whole(&chunks, source, "impl Counter", "        self.total\n    }\n}"));
+        
+        // Too small for the impl: split at its blank line, never mid-method
+        let chunks = code(source, Some("rust"), 90);
+        assert!(kept_whole(&chunks, source, "    pub fn add", "n;\n    }"));
+        assert!(chunks.iter().all(|c| c.text.chars().count() <= 90));
+    }
+    
+    #[test]
+    fn test_code_keeps_python_functions_whole() {
+        let source = include_str!("../fixtures/code/sample.py");
+        let chunks = code(source, Some("python"), 200);
+        assert!(kept_whole(&chunks, source, "# Distance helpers", "sqrt(total)"));
+        assert!(kept_whole(&chunks, source, "class Point", "hypot(self.x, self.y)"));
+        assert!(!chunks.iter().any(|c| c.text.contains("import math") && c.text.contains("class Point")));
+        
+        assert_eq!(bracket_delta("x = '{' # {", comment_markers(Some("py"))), 0);
+        assert_eq!(bracket_delta("let c = '{'; f(&'a x) { // {", comment_markers(Some("rust"))), 1);
+        assert_eq!(comment_markers(Some("SQL")), &["--"]);
+    }
+    
+    #[test]
+    fn test_locate_chunks() {
+        let text = "First part. Second part.";
+        let chunks = locate_chunks(text, vec!["First part. ".to_string(), "Second part.".to_string()]);
+        assert_eq!((chunks[0].start, chunks[0].end), (0, 11));
+        assert_eq!(&text[chunks[1].start..chunks[1].end], "Second part.");
+    }
+}
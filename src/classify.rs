@@ -0,0 +1,418 @@
+//! Jina classification (`/v1/classify`)
+//!
+//! Zero-shot classification against caller-supplied labels, or a trained
+//! classifier by id. Results are per input: an input the API could not
+//! classify comes back as an `Err` next to the others instead of failing
+//! the batch. Offline clients classify zero-shot by pseudo-embedding
+//! similarity to the labels.
+//!
+//! `CentroidClassifier` instead learns labels from a few examples each,
+//! with any `EmbeddingProvider`.
+
+use serde::Deserialize;
+use serde_json::json;
+
+use std::io::Write;
+use std::sync::Arc;
+
+use crate::embeddings::Embeddings;
+use crate::error::{truncate_for_display, JinaError, MAX_DISPLAY_CHARS};
+use crate::io::atomic_write;
+use crate::jina_api::JinaClient;
+use crate::provider::{check_dims, EmbeddingProvider};
+use crate::pseudo::PseudoEmbedder;
+use crate::search::{cosine, normalize};
+
+pub const DEFAULT_CLASSIFY_MODEL: &str = "jina-embeddings-v3";
+
+/// Best label for one input, plus the full distribution when the API sends one
+#[derive(Clone, Debug, PartialEq)]
+pub struct Classification {
+    pub input_index: usize,
+    pub label: String,
+    pub score: f32,
+    /// Every label with its score, in label order; just the winner for single-label responses
+    pub all_scores: Vec<(String, f32)>,
+}
+
+/// Per-call classification options
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ClassifyOptions {
+    /// Embedding model for zero-shot classification (default `jina-embeddings-v3`)
+    pub model: Option<String>,
+    /// Trained classifier; labels are not sent when set
+    pub classifier_id: Option<String>,
+}
+
+impl ClassifyOptions {
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = Some(model.to_string());
+        self
+    }
+    
+    pub fn with_classifier(mut self, classifier_id: &str) -> Self {
+        self.classifier_id = Some(classifier_id.to_string());
+        self
+    }
+}
+
+/// One result per input, in input order
+pub type ClassifyResults = Vec<Result<Classification, JinaError>>;
+
+impl JinaClient {
+    /// Zero-shot classify `inputs` into `labels`
+    pub fn classify(&self, inputs: &[&str], labels: &[&str]) -> Result<ClassifyResults, JinaError> {
+        self.classify_with(inputs, labels, &ClassifyOptions::default())
+    }
+    
+    pub fn classify_with(&self, inputs: &[&str], labels: &[&str], options: &ClassifyOptions)
+        -> Result<ClassifyResults, JinaError>
+    {
+        if options.classifier_id.is_none() && labels.is_empty() {
+            return Err(JinaError::InvalidInput("zero-shot classification needs at least one label".to_string()));
+        }
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        match self.post("/v1/classify", &classify_body(inputs, labels, options))? {
+            Some(body) => parse_classify(&body, inputs.len()),
+            None if options.classifier_id.is_some() => {
+                Err(JinaError::InvalidInput("trained classifiers need the Jina API (with_http)".to_string()))
+            }
+            None => Ok(classify_offline(inputs, labels)),
+        }
+    }
+}
+
+fn classify_body(inputs: &[&str], labels: &[&str], options: &ClassifyOptions) -> serde_json::Value {
+    match &options.classifier_id {
+        Some(id) => json!({ "classifier_id": id, "input": inputs }),
+        None => json!({
+            "model": options.model.as_deref().unwrap_or(DEFAULT_CLASSIFY_MODEL),
+            "input": inputs,
+            "labels": labels,
+        }),
+    }
+}
+
+#[derive(Deserialize)]
+struct Response {
+    data: Vec<Item>,
+}
+
+#[derive(Deserialize)]
+struct Item {
+    index: usize,
+    #[serde(default)]
+    prediction: Option<String>,
+    #[serde(default)]
+    score: Option<f32>,
+    #[serde(default)]
+    predictions: Vec<LabelScore>,
+    #[serde(default)]
+    error: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct LabelScore {
+    label: String,
+    score: f32,
+}
+
+fn parse_classify(body: &str, inputs: usize) -> Result<ClassifyResults, JinaError> {
+    let response: Response = serde_json::from_str(body)
+        .map_err(|e| JinaError::Parse(format!("classify response: {}", truncate_for_display(&e.to_string(), MAX_DISPLAY_CHARS))))?;
+    
+    let mut results: Vec<Option<Result<Classification, JinaError>>> = vec![None; inputs];
+    for item in response.data {
+        let index = item.index;
+        let slot = results.get_mut(index)
+            .ok_or_else(|| JinaError::Parse(format!("classify index {} out of range for {} inputs", index, inputs)))?;
+        *slot = Some(classification(item));
+    }
+    Ok(results.into_iter().enumerate()
+        .map(|(i, r)| r.unwrap_or_else(|| Err(JinaError::Parse(format!("no classification for input {}", i)))))
+        .collect())
+}
+
+fn classification(item: Item) -> Result<Classification, JinaError> {
+    if let Some(error) = item.error {
+        let message = error.as_str().map(str::to_string)
+            .or_else(|| error["message"].as_str().map(str::to_string))
+            .unwrap_or_else(|| error.to_string());
+        return Err(JinaError::InvalidInput(message));
+    }
+    
+    let mut all_scores: Vec<(String, f32)> = item.predictions.into_iter().map(|p| (p.label, p.score)).collect();
+    let best = all_scores.iter().max_by(|a, b| a.1.total_cmp(&b.1)).cloned();
+    let (label, score) = match (item.prediction, item.score, best) {
+        (Some(label), Some(score), _) => (label, score),
+        (Some(label), None, _) => {
+            let score = all_scores.iter().find(|(l, _)| *l == label).map_or(1.0, |(_, s)| *s);
+            (label, score)
+        }
+        (None, _, Some(best)) => best,
+        (None, _, None) => return Err(JinaError::Parse(format!("classification {} has no prediction", item.index))),
+    };
+    if all_scores.is_empty() {
+        all_scores.push((label.clone(), score));
+    }
+    Ok(Classification { input_index: item.index, label, score, all_scores })
+}
+
+/// Softmax over scaled cosine similarity to each label
+fn classify_offline(inputs: &[&str], labels: &[&str]) -> ClassifyResults {
+    let embedder = PseudoEmbedder::new(1024);
+    let label_vectors = embedder.embed_batch(labels);
+    inputs.iter().enumerate().map(|(input_index, input)| {
+        let v = embedder.embed(input);
+        let logits: Vec<f32> = label_vectors.iter().map(|l| 10.0 * cosine(&v, l)).collect();
+        let max = logits.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        let exp: Vec<f32> = logits.iter().map(|x| (x - max).exp()).collect();
+        let total: f32 = exp.iter().sum();
+        let all_scores: Vec<(String, f32)> = labels.iter().zip(&exp).map(|(l, e)| (l.to_string(), e / total)).collect();
+        let (label, score) = all_scores.iter().max_by(|a, b| a.1.total_cmp(&b.1)).cloned().unwrap();
+        Ok(Classification { input_index, label, score, all_scores })
+    }).collect()
+}
+
+/// Sidecar next to a saved `CentroidClassifier` holding its labels as JSON
+pub const LABELS_SUFFIX: &str = ".labels.json";
+
+/// Few-shot classifier labelling texts by the nearest per-label centroid
+///
+/// `fit` embeds the examples and averages each label's vectors into a
+/// unit-length centroid; scores are cosine similarities to the centroids.
+pub struct CentroidClassifier {
+    provider: Arc<dyn EmbeddingProvider>,
+    labels: Vec<String>,
+    centroids: Vec<Vec<f32>>,
+    min_margin: f32,
+}
+
+impl CentroidClassifier {
+    /// Fit on `(label, text)` examples; labels keep their first-seen order
+    pub fn fit(provider: impl EmbeddingProvider + 'static, examples: &[(&str, &str)]) -> Result<Self, JinaError> {
+        if examples.is_empty() {
+            return Err(JinaError::InvalidInput("CentroidClassifier needs at least one example".to_string()));
+        }
+        let texts: Vec<&str> = examples.iter().map(|(_, text)| *text).collect();
+        let embeddings = provider.embed_batch(&texts)?;
+        if embeddings.len() != texts.len() {
+            return Err(JinaError::Mismatch { expected: texts.len(), got: embeddings.len() });
+        }
+        check_dims(&embeddings, embeddings[0].len())?;
+        let mut labels: Vec<String> = Vec::new();
+        let mut centroids: Vec<Vec<f32>> = Vec::new();
+        for ((label, _), embedding) in examples.iter().zip(&embeddings) {
+            let i = labels.iter().position(|l| l == label).unwrap_or_else(|| {
+                labels.push(label.to_string());
+                centroids.push(vec![0.0; embedding.len()]);
+                labels.len() - 1
+            });
+            centroids[i].iter_mut().zip(embedding).for_each(|(c, x)| *c += x);
+        }
+        // The mean's direction is the sum's
+        centroids.iter_mut().for_each(|c| normalize(c));
+        Ok(Self { provider: Arc::new(provider), labels, centroids, min_margin: 0.0 })
+    }
+    
+    /// Make `label` abstain unless the best score leads the runner-up by at least `margin`
+    pub fn with_min_margin(mut self, margin: f32) -> Self {
+        self.min_margin = margin;
+        self
+    }
+    
+    pub fn labels(&self) -> &[String] { &self.labels }
+    
+    /// Every label with its score, best first
+    pub fn predict(&self, text: &str) -> Result<Vec<(String, f32)>, JinaError> {
+        Ok(self.predict_batch(&[text])?.pop().unwrap_or_default())
+    }
+    
+    /// `predict` for each text, in one embedding call
+    pub fn predict_batch(&self, texts: &[&str]) -> Result<Vec<Vec<(String, f32)>>, JinaError> {
+        let embeddings = self.provider.embed_batch(texts)?;
+        if embeddings.len() != texts.len() {
+            return Err(JinaError::Mismatch { expected: texts.len(), got: embeddings.len() });
+        }
+        Ok(embeddings.iter().map(|v| self.rank(v)).collect())
+    }
+    
+    /// The best label and its score, or `None` when it does not clear `min_margin`
+    pub fn label(&self, text: &str) -> Result<Option<(String, f32)>, JinaError> {
+        Ok(self.label_batch(&[text])?.pop().flatten())
+    }
+    
+    pub fn label_batch(&self, texts: &[&str]) -> Result<Vec<Option<(String, f32)>>, JinaError> {
+        let ranked = self.predict_batch(texts)?;
+        Ok(ranked.into_iter().map(|mut scores| {
+            let runner_up = scores.get(1).map_or(f32::NEG_INFINITY, |s| s.1);
+            let best = scores.drain(..).next()?;
+            (best.1 - runner_up >= self.min_margin).then_some(best)
+        }).collect())
+    }
+    
+    fn rank(&self, v: &[f32]) -> Vec<(String, f32)> {
+        let mut scores: Vec<(String, f32)> = self.labels.iter().zip(&self.centroids)
+            .map(|(label, centroid)| (label.clone(), cosine(v, centroid)))
+            .collect();
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scores
+    }
+    
+    /// Write the centroids to `path` as an `Embeddings` container and the labels to `path` + `LABELS_SUFFIX`
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        Embeddings::F32(self.centroids.clone()).save(path)?;
+        let labels = format!("{}{}", path, LABELS_SUFFIX);
+        let json = serde_json::to_string(&self.labels).unwrap();
+        atomic_write(&labels, |file| file.write_all(json.as_bytes())).map_err(|e| format!("Write failed for {}: {}", labels, e))
+    }
+    
+    /// Read a classifier written by `save`; `provider` must be the one it was fitted with
+    pub fn load(path: &str, provider: impl EmbeddingProvider + 'static) -> Result<Self, String> {
+        let centroids = Embeddings::load(path)?.into_f32();
+        let labels_path = format!("{}{}", path, LABELS_SUFFIX);
+        let json = std::fs::read_to_string(&labels_path).map_err(|e| format!("Cannot open {}: {}", labels_path, e))?;
+        let labels: Vec<String> = serde_json::from_str(&json).map_err(|e| format!("{}: {}", labels_path, e))?;
+        if labels.len() != centroids.len() {
+            return Err(format!("{} has {} labels for {} centroids", labels_path, labels.len(), centroids.len()));
+        }
+        Ok(Self { provider: Arc::new(provider), labels, centroids, min_margin: 0.0 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{HttpRequest, HttpResponse, RetryPolicy};
+    use std::sync::{Arc, Mutex};
+    
+    const FIXTURE: &str = include_str!("../fixtures/jina/classify.json");
+    
+    #[test]
+    fn test_parse_three_label_fixture() {
+        let results = parse_classify(FIXTURE, 2).unwrap();
+        let second = results[1].as_ref().unwrap();
+        assert_eq!((second.input_index, second.label.as_str(), second.score), (1, "science", 0.6107));
+        assert_eq!(second.all_scores, vec![
+            ("sports".to_string(), 0.0812), ("politics".to_string(), 0.3081), ("science".to_string(), 0.6107),
+        ]);
+        assert_eq!(results[0].as_ref().unwrap().label, "sports");
+        
+        // Single-label items, a per-input error and a missing input
+        let mixed = r#"{"data":[{"index":0,"prediction":"spam","score":0.9},{"index":1,"error":{"message":"input too long"}}]}"#;
+        let results = parse_classify(mixed, 3).unwrap();
+        assert_eq!(results[0].as_ref().unwrap().all_scores, vec![("spam".to_string(), 0.9)]);
+        assert_eq!(results[1], Err(JinaError::InvalidInput("input too long".to_string())));
+        assert!(matches!(results[2], Err(JinaError::Parse(_))));
+        assert!(matches!(parse_classify(FIXTURE, 1), Err(JinaError::Parse(_))));
+    }
+    
+    #[test]
+    fn test_classify_request_body() {
+        let seen: Arc<Mutex<Vec<HttpRequest>>> = Arc::default();
+        let log = seen.clone();
+        let client = JinaClient::new("jina_test")
+            .with_retry(RetryPolicy::none())
+            .with_timeout(std::time::Duration::from_secs(5))
+            .with_transport(move |request: &HttpRequest| {
+                log.lock().unwrap().push(request.clone());
+                Ok(HttpResponse { status: 200, headers: Vec::new(), body: FIXTURE.to_string() })
+            });
+        
+        let labels = ["sports", "politics", "science"];
+        let results = client.classify(&["Cup final tonight", "New exoplanet found"], &labels).unwrap();
+        assert_eq!(results.len(), 2);
+        
+        let request = seen.lock().unwrap()[0].clone();
+        assert_eq!(request.url, "https://api.jina.ai/v1/classify");
+        assert_eq!(request.timeout, Some(std::time::Duration::from_secs(5)));
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body, json!({
+            "model": "jina-embeddings-v3",
+            "input": ["Cup final tonight", "New exoplanet found"],
+            "labels": labels,
+        }));
+        
+        let trained = ClassifyOptions::default().with_classifier("clf-123");
+        assert_eq!(classify_body(&["x"], &[], &trained), json!({"classifier_id": "clf-123", "input": ["x"]}));
+        let other_model = ClassifyOptions::default().with_model("jina-clip-v2");
+        assert_eq!(classify_body(&["x"], &["a"], &other_model)["model"], "jina-clip-v2");
+    }
+    
+    #[test]
+    fn test_classify_offline() {
+        let client = JinaClient::new("test_key");
+        let results = client.classify(&["the cat sat on the mat"], &["stock market", "the cat sat"]).unwrap();
+        let c = results[0].as_ref().unwrap();
+        assert_eq!(c.label, "the cat sat");
+        assert!((c.all_scores.iter().map(|(_, s)| s).sum::<f32>() - 1.0).abs() < 1e-5);
+        assert!(client.classify(&["x"], &[]).is_err());
+    }
+    
+    fn fitted() -> CentroidClassifier {
+        let examples = [
+            ("sports", "the striker scored a goal in the football match"),
+            ("sports", "the team won the football cup final"),
+            ("sports", "a late goal decided the match for the home team"),
+            ("cooking", "simmer the onions and garlic in olive oil"),
+            ("cooking", "bake the bread dough in a hot oven"),
+            ("cooking", "season the soup with salt and fresh garlic"),
+            ("space", "the rocket carried a satellite into orbit"),
+            ("space", "astronomers found a planet orbiting a distant star"),
+            ("space", "the telescope imaged a star forming galaxy"),
+        ];
+        CentroidClassifier::fit(PseudoEmbedder::new(256), &examples).unwrap()
+    }
+    
+    #[test]
+    fn test_centroids_separate_label_groups() {
+        let classifier = fitted();
+        assert_eq!(classifier.labels(), ["sports", "cooking", "space"]);
+        let queries = [
+            ("sports", "who scored the winning goal in the cup match"),
+            ("cooking", "fry the garlic in oil before adding the onions"),
+            ("space", "a new satellite reached orbit around the planet"),
+            ("sports", "the football team lost the final"),
+            ("cooking", "the bread needs a hot oven"),
+            ("space", "the star and its planet seen by the telescope"),
+        ];
+        let texts: Vec<&str> = queries.iter().map(|(_, text)| *text).collect();
+        let ranked = classifier.predict_batch(&texts).unwrap();
+        for ((expected, text), scores) in queries.iter().zip(&ranked) {
+            assert_eq!(scores[0].0, *expected, "{}: {:?}", text, scores);
+            assert_eq!(scores.len(), 3);
+            assert!(scores.windows(2).all(|w| w[0].1 >= w[1].1));
+        }
+        assert_eq!(classifier.predict(texts[0]).unwrap(), ranked[0]);
+        
+        assert!(CentroidClassifier::fit(PseudoEmbedder::new(8), &[]).is_err());
+    }
+    
+    #[test]
+    fn test_save_load_and_abstention() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("topics.emb").to_str().unwrap().to_string();
+        let classifier = fitted();
+        classifier.save(&path).unwrap();
+        let loaded = CentroidClassifier::load(&path, PseudoEmbedder::new(256)).unwrap();
+        assert_eq!(loaded.labels(), classifier.labels());
+        assert_eq!(loaded.centroids, classifier.centroids);
+        let text = "bake the garlic bread";
+        assert_eq!(loaded.predict(text).unwrap(), classifier.predict(text).unwrap());
+        
+        // A margin above the lead turns the guess into an abstention
+        let (label, score) = classifier.label(text).unwrap().unwrap();
+        assert_eq!(label, "cooking");
+        let lead = score - classifier.predict(text).unwrap()[1].1;
+        let strict = classifier.with_min_margin(lead + 0.01);
+        assert_eq!(strict.label(text).unwrap(), None);
+        assert_eq!(strict.label_batch(&["unrelated words entirely", text]).unwrap()[1], None);
+        assert!(strict.with_min_margin(lead - 0.01).label(text).unwrap().is_some());
+        
+        std::fs::write(format!("{}{}", path, LABELS_SUFFIX), r#"["only one"]"#).unwrap();
+        let error = CentroidClassifier::load(&path, PseudoEmbedder::new(256)).err().unwrap();
+        assert!(error.contains("1 labels for 3 centroids"), "{}", error);
+    }
+}
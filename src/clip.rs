@@ -0,0 +1,165 @@
+//! Multimodal inputs and jina-clip embeddings
+//!
+//! `Input` is a text or an image; `JinaClient::embed_clip` embeds a mix of
+//! both into one space with a jina-clip model. Image bytes are checked to
+//! be JPEG, PNG or WebP and under the API's per-image limit before being
+//! base64 encoded. CLIP vectors have the model's own size (768 for
+//! jina-clip-v1), not the text models' 1024.
+
+use base64::Engine;
+use serde_json::json;
+
+use crate::error::JinaError;
+use crate::jina_api::JinaClient;
+use crate::openai::parse_response;
+use crate::provider::check_dims;
+use crate::pseudo::PseudoEmbedder;
+
+pub const DEFAULT_CLIP_MODEL: &str = "jina-clip-v2";
+/// Largest image accepted per input
+pub const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+/// One multimodal input
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+    Text(String),
+    /// Encoded image file (JPEG, PNG or WebP)
+    Image(Vec<u8>),
+    /// Image fetched by the API
+    ImageUrl(String),
+}
+
+impl Input {
+    pub fn text(text: &str) -> Self { Input::Text(text.to_string()) }
+}
+
+/// Output size of a jina-clip model
+pub fn clip_dims(model: &str) -> usize {
+    if model.starts_with("jina-clip-v1") { 768 } else { 1024 }
+}
+
+/// Image format from magic bytes
+fn image_format(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0xff, 0xd8, 0xff, ..] => Some("jpeg"),
+        [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, ..] => Some("png"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("webp"),
+        _ => None,
+    }
+}
+
+impl JinaClient {
+    /// CLIP model, e.g. `jina-clip-v1`
+    pub fn with_clip_model(mut self, model: &str) -> Self {
+        self.clip_model = model.to_string();
+        self
+    }
+    
+    /// Embed texts and images into the CLIP model's shared space
+    ///
+    /// Offline, texts and image bytes get pseudo-embeddings of the model's
+    /// size and image URLs are rejected.
+    pub fn embed_clip(&self, inputs: &[Input]) -> Result<Vec<Vec<f32>>, JinaError> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let body = clip_body(&self.clip_model, inputs)?;
+        let Some(response) = self.post("/v1/embeddings", &body)? else {
+            let embedder = PseudoEmbedder::new(clip_dims(&self.clip_model));
+            return inputs.iter().map(|input| embed_offline(&embedder, input)).collect();
+        };
+        let embeddings = parse_response(&response, inputs.len())?.embeddings;
+        check_dims(&embeddings, clip_dims(&self.clip_model))?;
+        Ok(embeddings)
+    }
+}
+
+fn clip_body(model: &str, inputs: &[Input]) -> Result<serde_json::Value, JinaError> {
+    let items = inputs.iter().enumerate().map(|(index, input)| match input {
+        Input::Text(text) => Ok(json!({ "text": text })),
+        Input::ImageUrl(url) => Ok(json!({ "image": url })),
+        Input::Image(bytes) => {
+            if bytes.len() > MAX_IMAGE_BYTES {
+                return Err(JinaError::InputTooLarge { index, size: bytes.len(), limit: Some(MAX_IMAGE_BYTES) });
+            }
+            if image_format(bytes).is_none() {
+                return Err(JinaError::InvalidInput(format!("input {} is not a JPEG, PNG or WebP image", index)));
+            }
+            Ok(json!({ "image": base64::engine::general_purpose::STANDARD.encode(bytes) }))
+        }
+    }).collect::<Result<Vec<_>, _>>()?;
+    Ok(json!({ "model": model, "input": items }))
+}
+
+fn embed_offline(embedder: &PseudoEmbedder, input: &Input) -> Result<Vec<f32>, JinaError> {
+    match input {
+        Input::Text(text) => Ok(embedder.embed(text)),
+        Input::Image(bytes) => Ok(embedder.embed(&base64::engine::general_purpose::STANDARD.encode(bytes))),
+        Input::ImageUrl(_) => Err(JinaError::InvalidInput("image URLs need the Jina API (with_http)".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{HttpRequest, HttpResponse, RetryPolicy};
+    use std::sync::{Arc, Mutex};
+    
+    const FIXTURE: &str = include_str!("../fixtures/jina/clip.json");
+    const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0, 0, 0, 0x0d];
+    
+    #[test]
+    fn test_clip_request_serialization() {
+        let body = clip_body("jina-clip-v2", &[Input::text("a red shoe"), Input::Image(PNG.to_vec()),
+                                               Input::ImageUrl("https://example.com/shoe.jpg".to_string())]).unwrap();
+        assert_eq!(body, json!({
+            "model": "jina-clip-v2",
+            "input": [
+                { "text": "a red shoe" },
+                { "image": "iVBORw0KGgoAAAAN" },
+                { "image": "https://example.com/shoe.jpg" },
+            ],
+        }));
+        
+        assert_eq!(image_format(b"RIFF\x10\0\0\0WEBPVP8 "), Some("webp"));
+        assert_eq!(image_format(&[0xff, 0xd8, 0xff, 0xe0]), Some("jpeg"));
+        let gif = Input::Image(b"GIF89a".to_vec());
+        assert!(matches!(clip_body("m", &[Input::text("x"), gif]), Err(JinaError::InvalidInput(msg)) if msg.contains("input 1")));
+        
+        let mut huge = PNG.to_vec();
+        huge.resize(MAX_IMAGE_BYTES + 1, 0);
+        assert_eq!(clip_body("m", &[Input::Image(huge)]).unwrap_err(),
+                   JinaError::InputTooLarge { index: 0, size: MAX_IMAGE_BYTES + 1, limit: Some(MAX_IMAGE_BYTES) });
+    }
+    
+    #[test]
+    fn test_clip_sized_vectors_are_accepted() {
+        let seen: Arc<Mutex<Vec<HttpRequest>>> = Arc::default();
+        let log = seen.clone();
+        let client = JinaClient::new("jina_test")
+            .with_clip_model("jina-clip-v1")
+            .with_retry(RetryPolicy::none())
+            .with_transport(move |request: &HttpRequest| {
+                log.lock().unwrap().push(request.clone());
+                Ok(HttpResponse { status: 200, headers: Vec::new(), body: FIXTURE.to_string() })
+            });
+        
+        let embeddings = client.embed_clip(&[Input::text("a red shoe"), Input::Image(PNG.to_vec())]).unwrap();
+        assert_eq!(embeddings.len(), 2);
+        assert!(embeddings.iter().all(|v| v.len() == 768));
+        
+        let request = seen.lock().unwrap()[0].clone();
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["model"], "jina-clip-v1");
+        assert_eq!(request.url, "https://api.jina.ai/v1/embeddings");
+        
+        // Text-model-sized vectors are not what jina-clip-v1 returns
+        let wrong = JinaClient::new("jina_test").with_retry(RetryPolicy::none()).with_transport(|_: &HttpRequest| {
+            Ok(HttpResponse { status: 200, headers: Vec::new(), body: r#"{"data":[{"index":0,"embedding":[0.5,0.5]}]}"#.to_string() })
+        }).with_clip_model("jina-clip-v1");
+        assert!(matches!(wrong.embed_clip(&[Input::text("x")]), Err(JinaError::Mismatch { .. })));
+        
+        let offline = JinaClient::new("test_key").with_clip_model("jina-clip-v1");
+        assert_eq!(offline.embed_clip(&[Input::text("a red shoe")]).unwrap()[0].len(), 768);
+    }
+}
@@ -0,0 +1,243 @@
+//! Single-flight coalescing of concurrent identical requests
+//!
+//! `SingleFlight` lets the first caller for a key lead: it does the work
+//! and `complete`s with the result. Callers arriving for the same key while
+//! it runs follow, blocking until the leader's result is there, and get a
+//! clone of it, error included. Keys are independent, so work for different
+//! keys never waits on each other. A key is forgotten once completed, so a
+//! failure is not kept: the next caller leads again. A leader dropped
+//! without completing (a panic) wakes its followers with nothing, and one
+//! of them leads in its place.
+//!
+//! `AsyncSingleFlight` is the same for the futures of `AsyncJinaClient`,
+//! on a single thread: followers are woken rather than blocked.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+
+enum State<V> {
+    Running,
+    Done(V),
+    Abandoned,
+}
+
+struct Flight<V> {
+    state: Mutex<State<V>>,
+    done: Condvar,
+}
+
+/// In-flight work by key
+pub struct SingleFlight<K, V> {
+    flights: Mutex<HashMap<K, Arc<Flight<V>>>>,
+}
+
+/// What a caller of `SingleFlight::claim` does for its key
+pub enum Claim<'a, K: Eq + Hash + Clone, V: Clone> {
+    /// Nothing in flight and nothing cached: do the work and `complete`
+    Lead(Leader<'a, K, V>),
+    /// Someone else is doing it: `wait` for their result
+    Follow(Follower<V>),
+    /// `claim`'s lookup found the result
+    Ready(V),
+}
+
+pub struct Leader<'a, K: Eq + Hash + Clone, V: Clone> {
+    owner: &'a SingleFlight<K, V>,
+    key: K,
+    flight: Arc<Flight<V>>,
+    completed: bool,
+}
+
+pub struct Follower<V> {
+    flight: Arc<Flight<V>>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Default for SingleFlight<K, V> {
+    fn default() -> Self { Self { flights: Mutex::new(HashMap::new()) } }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> SingleFlight<K, V> {
+    pub fn new() -> Self { Self::default() }
+    
+    /// Lead or follow the work for `key`. `lookup` runs under the same lock
+    /// as completion, so a result the last leader stored before completing
+    /// is found there instead of leading the work again.
+    pub fn claim(&self, key: K, lookup: impl FnOnce() -> Option<V>) -> Claim<'_, K, V> {
+        let mut flights = self.flights.lock().unwrap();
+        if let Some(flight) = flights.get(&key) {
+            return Claim::Follow(Follower { flight: flight.clone() });
+        }
+        if let Some(value) = lookup() {
+            return Claim::Ready(value);
+        }
+        let flight = Arc::new(Flight { state: Mutex::new(State::Running), done: Condvar::new() });
+        flights.insert(key.clone(), flight.clone());
+        Claim::Lead(Leader { owner: self, key, flight, completed: false })
+    }
+    
+    /// Keys with work in flight
+    pub fn in_flight(&self) -> usize { self.flights.lock().unwrap().len() }
+    
+    fn finish(&self, key: &K, flight: &Flight<V>, state: State<V>) {
+        self.flights.lock().unwrap().remove(key);
+        *flight.state.lock().unwrap() = state;
+        flight.done.notify_all();
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Leader<'_, K, V> {
+    /// Hand `value` to every follower and forget the key
+    pub fn complete(mut self, value: V) {
+        self.completed = true;
+        self.owner.finish(&self.key, &self.flight, State::Done(value));
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Drop for Leader<'_, K, V> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.owner.finish(&self.key, &self.flight, State::Abandoned);
+        }
+    }
+}
+
+impl<V: Clone> Follower<V> {
+    /// The leader's result; `None` if it gave up, in which case claim again
+    pub fn wait(self) -> Option<V> {
+        let mut state = self.flight.state.lock().unwrap();
+        loop {
+            match &*state {
+                State::Running => state = self.flight.done.wait(state).unwrap(),
+                State::Done(value) => return Some(value.clone()),
+                State::Abandoned => return None,
+            }
+        }
+    }
+}
+
+struct AsyncFlight<V> {
+    state: State<V>,
+    wakers: Vec<Waker>,
+}
+
+/// `SingleFlight` for futures polled on one thread
+pub struct AsyncSingleFlight<K, V> {
+    flights: RefCell<HashMap<K, Rc<RefCell<AsyncFlight<V>>>>>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Default for AsyncSingleFlight<K, V> {
+    fn default() -> Self { Self { flights: RefCell::new(HashMap::new()) } }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> AsyncSingleFlight<K, V> {
+    pub fn new() -> Self { Self::default() }
+    
+    /// `work`'s output, or that of the identical work already in flight for `key`
+    pub async fn run<F: Future<Output = V>>(&self, key: K, work: impl Fn() -> F) -> V {
+        loop {
+            let running = self.flights.borrow().get(&key).cloned();
+            if let Some(flight) = running {
+                if let Some(value) = (Wait { flight }).await {
+                    return value;
+                }
+                continue;
+            }
+            let flight = Rc::new(RefCell::new(AsyncFlight { state: State::Running, wakers: Vec::new() }));
+            self.flights.borrow_mut().insert(key.clone(), flight.clone());
+            // Wakes the followers with nothing if this future is dropped mid-way
+            let mut guard = AsyncLeader { owner: self, key: key.clone(), flight, value: None };
+            let value = work().await;
+            guard.value = Some(value.clone());
+            return value;
+        }
+    }
+}
+
+struct AsyncLeader<'a, K: Eq + Hash, V> {
+    owner: &'a AsyncSingleFlight<K, V>,
+    key: K,
+    flight: Rc<RefCell<AsyncFlight<V>>>,
+    value: Option<V>,
+}
+
+impl<K: Eq + Hash, V> Drop for AsyncLeader<'_, K, V> {
+    fn drop(&mut self) {
+        self.owner.flights.borrow_mut().remove(&self.key);
+        let mut flight = self.flight.borrow_mut();
+        flight.state = match self.value.take() {
+            Some(value) => State::Done(value),
+            None => State::Abandoned,
+        };
+        for waker in flight.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+struct Wait<V> {
+    flight: Rc<RefCell<AsyncFlight<V>>>,
+}
+
+impl<V: Clone> Future for Wait<V> {
+    type Output = Option<V>;
+    
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<V>> {
+        let mut flight = self.flight.borrow_mut();
+        match &flight.state {
+            State::Running => {
+                flight.wakers.push(cx.waker().clone());
+                Poll::Pending
+            }
+            State::Done(value) => Poll::Ready(Some(value.clone())),
+            State::Abandoned => Poll::Ready(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    
+    #[test]
+    fn test_followers_share_the_result_and_abandonment_hands_over() {
+        let flights: SingleFlight<&str, Result<u32, String>> = SingleFlight::new();
+        let runs = AtomicUsize::new(0);
+        let results: Vec<Result<u32, String>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8).map(|_| scope.spawn(|| loop {
+                match flights.claim("k", || None) {
+                    Claim::Lead(leader) => {
+                        runs.fetch_add(1, Ordering::Relaxed);
+                        std::thread::sleep(Duration::from_millis(50));
+                        leader.complete(Err("upstream down".to_string()));
+                        return Err("upstream down".to_string());
+                    }
+                    Claim::Follow(follower) => if let Some(value) = follower.wait() { return value },
+                    Claim::Ready(value) => return value,
+                }
+            })).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert!(results.iter().all(|r| r.as_ref().is_err_and(|e| e == "upstream down")));
+        assert!(runs.load(Ordering::Relaxed) < 8);
+        assert_eq!(flights.in_flight(), 0);
+        
+        // The failure is not kept, and a found result needs no work
+        assert!(matches!(flights.claim("k", || None), Claim::Lead(_)));
+        assert!(matches!(flights.claim("k", || Some(Ok(1))), Claim::Ready(Ok(1))));
+        
+        // A dropped leader leaves its followers to claim again
+        let Claim::Lead(leader) = flights.claim("k", || None) else { panic!("not leading") };
+        let Claim::Follow(follower) = flights.claim("k", || None) else { panic!("not following") };
+        drop(leader);
+        assert_eq!(follower.wait(), None);
+        assert!(matches!(flights.claim("k", || None), Claim::Lead(_)));
+    }
+}
@@ -0,0 +1,221 @@
+//! Source-code embeddings with Jina's code models
+//!
+//! `JinaClient::embed_code` embeds `CodeSnippet`s with the client's code
+//! model (`with_code_model`, default jina-embeddings-v2-base-code), not its
+//! text model. Snippets over `with_code_chunk_chars` are split with
+//! `chunk::code`, so one snippet can give several vectors. jina-embeddings-v4
+//! is sent the `code.passage` task; the other code models get the language
+//! as a comment on a first line, which they were trained to read.
+//!
+//! Every chunk carries its `language` in its metadata, ready for
+//! `add_checked`. The returned `Provenance` names the code model, its size
+//! (768 for v2-base-code, not the text models' 1024) and a `code` task, so
+//! a prose index created `with_provenance` refuses code vectors unless told
+//! to `Override`, and the other way round.
+
+use serde_json::json;
+
+use crate::chunk::{self, comment_markers, Chunk};
+use crate::error::JinaError;
+use crate::jina_api::JinaClient;
+use crate::openai::parse_response;
+use crate::provenance::Provenance;
+use crate::provider::check_dims;
+use crate::pseudo::PseudoEmbedder;
+
+pub const DEFAULT_CODE_MODEL: &str = "jina-embeddings-v2-base-code";
+/// Longest snippet embedded whole; code needs more tokens per character than prose
+pub const DEFAULT_CODE_CHUNK_CHARS: usize = 8000;
+
+/// Source code and its language, e.g. `rust` or `python`
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CodeSnippet {
+    pub text: String,
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+impl CodeSnippet {
+    pub fn new(text: &str, language: &str) -> Self {
+        Self { text: text.to_string(), language: Some(language.to_string()) }
+    }
+}
+
+/// One chunk of a snippet and its vector
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodeEmbedding {
+    /// Index of the snippet in the input
+    pub snippet: usize,
+    /// Offsets into the snippet's text, and its `language` metadata
+    pub chunk: Chunk,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodeEmbeddings {
+    /// Chunks in input order
+    pub items: Vec<CodeEmbedding>,
+    pub provenance: Provenance,
+}
+
+/// Output size of a Jina code model
+pub fn code_dims(model: &str) -> usize {
+    match model {
+        m if m.starts_with("jina-embeddings-v4") => 2048,
+        m if m.starts_with("jina-code-embeddings-1.5b") => 1536,
+        m if m.starts_with("jina-code-embeddings-0.5b") => 896,
+        _ => 768,
+    }
+}
+
+/// Task sent to, and recorded for, `model`
+fn code_task(model: &str) -> &'static str {
+    if model.starts_with("jina-embeddings-v4") { "code.passage" } else { "code" }
+}
+
+/// `text` as sent to `model`: behind a language comment unless the model takes a task
+fn tagged(model: &str, text: &str, language: Option<&str>) -> String {
+    match language {
+        Some(language) if !model.starts_with("jina-embeddings-v4") => {
+            format!("{} language: {}\n{}", comment_markers(Some(language))[0], language, text)
+        }
+        _ => text.to_string(),
+    }
+}
+
+fn code_body(model: &str, inputs: &[String]) -> serde_json::Value {
+    if model.starts_with("jina-embeddings-v4") {
+        json!({ "model": model, "task": code_task(model), "input": inputs })
+    } else {
+        json!({ "model": model, "input": inputs })
+    }
+}
+
+impl JinaClient {
+    /// Code model, e.g. `jina-embeddings-v4`
+    pub fn with_code_model(mut self, model: &str) -> Self {
+        self.code_model = model.to_string();
+        self
+    }
+    
+    /// Split snippets longer than `n` characters with `chunk::code`
+    pub fn with_code_chunk_chars(mut self, n: usize) -> Self {
+        self.code_chunk_chars = n.max(1);
+        self
+    }
+    
+    /// Embed `snippets` with the code model, chunking oversized ones
+    ///
+    /// Offline, the tagged chunks get pseudo-embeddings of the model's size.
+    pub fn embed_code(&self, snippets: &[CodeSnippet]) -> Result<CodeEmbeddings, JinaError> {
+        let model = self.code_model.as_str();
+        let dims = code_dims(model);
+        let chunks: Vec<(usize, Chunk)> = snippets.iter().enumerate()
+            .flat_map(|(index, snippet)| self.code_chunks(snippet).into_iter().map(move |chunk| (index, chunk)))
+            .collect();
+        let inputs: Vec<String> = chunks.iter()
+            .map(|(index, chunk)| tagged(model, &chunk.text, snippets[*index].language.as_deref()))
+            .collect();
+        
+        let mut embeddings = Vec::with_capacity(inputs.len());
+        for batch in inputs.chunks(self.settings().max_batch_size) {
+            let Some(response) = self.post("/v1/embeddings", &code_body(model, batch))? else {
+                embeddings.extend(PseudoEmbedder::new(dims).embed_batch(&batch.iter().map(String::as_str).collect::<Vec<_>>()));
+                continue;
+            };
+            let vectors = parse_response(&response, batch.len())?.embeddings;
+            check_dims(&vectors, dims)?;
+            embeddings.extend(vectors);
+        }
+        let items = chunks.into_iter().zip(embeddings)
+            .map(|((snippet, chunk), embedding)| CodeEmbedding { snippet, chunk, embedding })
+            .collect();
+        Ok(CodeEmbeddings { items, provenance: Provenance::new(model, dims).with_task(code_task(model)) })
+    }
+    
+    /// The snippet whole, or `chunk::code` pieces if it is too long; empty if blank
+    fn code_chunks(&self, snippet: &CodeSnippet) -> Vec<Chunk> {
+        let language = snippet.language.as_deref();
+        if snippet.text.trim().is_empty() {
+            return Vec::new();
+        }
+        if snippet.text.chars().count() > self.code_chunk_chars {
+            return chunk::code(&snippet.text, language, self.code_chunk_chars);
+        }
+        let mut whole = Chunk::of(&snippet.text, 0..snippet.text.len());
+        if let Some(language) = language {
+            whole.metadata = whole.metadata.with("language", language);
+        }
+        vec![whole]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::{CrystalIndex, IndexError};
+    use crate::provenance::ProvenanceCheck;
+    use crate::transport::{HttpRequest, HttpResponse, RetryPolicy};
+    use std::sync::{Arc, Mutex};
+    
+    const FIXTURE: &str = include_str!("../fixtures/jina/code.json");
+    
+    #[test]
+    fn test_code_request_serialization() {
+        let fn_main = CodeSnippet::new("fn main() {}", "rust");
+        let untagged = CodeSnippet { text: "SELECT 1".to_string(), language: None };
+        let seen: Arc<Mutex<Vec<HttpRequest>>> = Arc::default();
+        let log = seen.clone();
+        let client = JinaClient::new("jina_test").with_retry(RetryPolicy::none()).with_transport(move |request: &HttpRequest| {
+            log.lock().unwrap().push(request.clone());
+            Ok(HttpResponse { status: 200, headers: Vec::new(), body: FIXTURE.to_string() })
+        });
+        let embedded = client.embed_code(&[fn_main.clone(), untagged]).unwrap();
+        assert!(embedded.items.iter().all(|item| item.embedding.len() == 768));
+        assert_eq!(embedded.provenance, Provenance::new("jina-embeddings-v2-base-code", 768).with_task("code"));
+        
+        let body: serde_json::Value = serde_json::from_slice(&seen.lock().unwrap()[0].body).unwrap();
+        assert_eq!(body, json!({
+            "model": "jina-embeddings-v2-base-code",
+            "input": ["// language: rust\nfn main() {}", "SELECT 1"],
+        }));
+        assert_eq!(code_body("jina-embeddings-v4", &[tagged("jina-embeddings-v4", "import os", Some("python"))]),
+                   json!({ "model": "jina-embeddings-v4", "task": "code.passage", "input": ["import os"] }));
+        assert_eq!(tagged(DEFAULT_CODE_MODEL, "x = 1", Some("python")), "# language: python\nx = 1");
+        
+        // Text-model-sized vectors are not what the code model returns
+        let prose = JinaClient::new("jina_test").with_retry(RetryPolicy::none()).with_transport(|_: &HttpRequest| {
+            Ok(HttpResponse { status: 200, headers: Vec::new(), body: r#"{"data":[{"index":0,"embedding":[0.5,0.5]}]}"#.to_string() })
+        });
+        assert!(matches!(prose.embed_code(&[fn_main]), Err(JinaError::Mismatch { expected: 768, got: 2 })));
+    }
+    
+    #[test]
+    fn test_oversized_snippets_chunk_and_stay_apart_from_prose() {
+        let source = include_str!("../fixtures/code/sample.rs");
+        let client = JinaClient::new("test_key").with_code_chunk_chars(260).with_code_model("jina-embeddings-v4");
+        let snippets = [CodeSnippet::new(source, "rust"), CodeSnippet::new("def f():\n    return 1\n", "python")];
+        let embedded = client.embed_code(&snippets).unwrap();
+        let chunks = chunk::code(source, Some("rust"), 260);
+        assert!(chunks.len() > 1);
+        assert_eq!(embedded.items.len(), chunks.len() + 1);
+        for (item, chunk) in embedded.items.iter().zip(&chunks) {
+            assert_eq!((item.snippet, &item.chunk), (0, chunk));
+        }
+        let last = embedded.items.last().unwrap();
+        assert_eq!((last.snippet, last.chunk.metadata.get_str("language")), (1, Some("python")));
+        assert!(embedded.items.iter().all(|item| item.embedding.len() == 2048));
+        assert_eq!(embedded.provenance.task.as_deref(), Some("code.passage"));
+        
+        // A prose index refuses code vectors unless overridden
+        let prose = JinaClient::new("test_key").embed_batch_full(&["a sentence"], &Default::default()).unwrap();
+        let mut index = CrystalIndex::new(prose.embeddings[0].len()).with_provenance(prose.provenance.unwrap());
+        let item = &embedded.items[0];
+        let code = Some(&embedded.provenance);
+        let err = index.add_checked(1, &item.embedding, item.chunk.metadata.clone(), code, ProvenanceCheck::Enforce).unwrap_err();
+        assert!(matches!(err, IndexError::ProvenanceMismatch(_)));
+        let mut code_index = CrystalIndex::new(2048).with_provenance(embedded.provenance.clone());
+        code_index.add_checked(1, &item.embedding, item.chunk.metadata.clone(), code, ProvenanceCheck::Enforce).unwrap();
+        assert!(client.embed_code(&[CodeSnippet::new("  \n", "rust")]).unwrap().items.is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+//! Cohere `/v2/embed` backend
+//!
+//! Sends `{model, texts, input_type, embedding_types}` and reads the
+//! `embeddings.float` arrays back. Cohere's v3+ models require an
+//! `input_type`, so our tasks map onto it: queries become `search_query`,
+//! everything embedded without a task is treated as `search_document`.
+//! Vector size differs per model and is learned from the first response.
+
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::error::{truncate_for_display, JinaError, MAX_DISPLAY_CHARS};
+use crate::jina_api::{EmbedOptions, Task};
+use crate::provider::{check_dims, BackendKind, EmbedError, EmbeddingProvider, EmbeddingResponse, LearnedDims, Usage};
+use crate::transport::{self, check_status, send_with_retry, HttpRequest, RetryPolicy, Transport};
+
+const DEFAULT_URL: &str = "https://api.cohere.com/v2/embed";
+const DEFAULT_MODEL: &str = "embed-english-v3.0";
+const MAX_BATCH_SIZE: usize = 96;  // Cohere per-request texts limit
+
+/// Cohere `input_type` for a task; no task means documents being indexed
+pub fn input_type(task: Option<Task>) -> &'static str {
+    match task {
+        Some(Task::RetrievalQuery) => "search_query",
+        Some(Task::RetrievalPassage) | Some(Task::TextMatching) | None => "search_document",
+        Some(Task::Classification) => "classification",
+        Some(Task::Separation) => "clustering",
+    }
+}
+
+pub struct CohereClient {
+    url: String,
+    api_key: String,
+    model: String,
+    max_batch_size: usize,
+    transport: Arc<dyn Transport>,
+    retry: RetryPolicy,
+    learned_dims: LearnedDims,
+}
+
+impl CohereClient {
+    /// `embed-english-v3.0` on the public API
+    pub fn new(api_key: &str) -> Self {
+        crate::error::register_secret(api_key);
+        Self {
+            url: DEFAULT_URL.to_string(),
+            api_key: api_key.to_string(),
+            model: DEFAULT_MODEL.to_string(),
+            max_batch_size: MAX_BATCH_SIZE,
+            transport: transport::for_url(DEFAULT_URL),
+            retry: RetryPolicy::default(),
+            learned_dims: LearnedDims::default(),
+        }
+    }
+    
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+    
+    /// Full endpoint URL, for proxies or regional deployments
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = url.to_string();
+        self
+    }
+    
+    pub fn with_max_batch_size(mut self, n: usize) -> Self {
+        self.max_batch_size = n.max(1);
+        self
+    }
+    
+    pub fn with_transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Arc::new(transport);
+        self
+    }
+    
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+    
+    /// Embed in requests of at most `max_batch_size`; usage is Cohere's billed input tokens
+    pub fn embed_batch_full(&self, texts: &[&str], options: &EmbedOptions) -> Result<EmbeddingResponse, JinaError> {
+        if options.dimensions == Some(0) {
+            return Err(JinaError::InvalidInput("dimensions must be at least 1".to_string()));
+        }
+        let mut full = EmbeddingResponse::default();
+        for chunk in texts.chunks(self.max_batch_size) {
+            let request = HttpRequest::post_json(self.url.clone(), &self.request_body(chunk, options))
+                .bearer(Some(&self.api_key));
+            let response = check_status(send_with_retry(self.transport.as_ref(), &request, &self.retry)?)?;
+            let parsed = parse_response(&response.body, chunk.len())?;
+            match options.dimensions {
+                Some(dims) => check_dims(&parsed.embeddings, dims)?,
+                None => self.learned_dims.check(&parsed.embeddings)?,
+            }
+            
+            full.embeddings.extend(parsed.embeddings);
+            full.usage.add(&parsed.usage);
+        }
+        Ok(full)
+    }
+    
+    fn request_body(&self, texts: &[&str], options: &EmbedOptions) -> serde_json::Value {
+        let mut body = json!({
+            "model": self.model,
+            "texts": texts,
+            "input_type": input_type(options.task),
+            "embedding_types": ["float"],
+        });
+        // embed-v4 supports shortened vectors
+        if let Some(dims) = options.dimensions {
+            body["output_dimension"] = json!(dims);
+        }
+        body
+    }
+}
+
+impl EmbeddingProvider for CohereClient {
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        Ok(self.embed_batch_full(texts, &EmbedOptions::default())?.embeddings)
+    }
+    
+    fn embed_batch_with(&self, texts: &[&str], options: &EmbedOptions) -> Result<Vec<Vec<f32>>, EmbedError> {
+        Ok(self.embed_batch_full(texts, options)?.embeddings)
+    }
+    
+    /// Size seen in the first response (0 before any)
+    fn dimensions(&self) -> usize { self.learned_dims.get() }
+}
+
+#[derive(Deserialize)]
+struct Response {
+    embeddings: Embeddings,
+    #[serde(default)]
+    meta: Meta,
+}
+
+#[derive(Deserialize)]
+struct Embeddings {
+    float: Vec<Vec<f32>>,
+}
+
+#[derive(Default, Deserialize)]
+struct Meta {
+    #[serde(default)]
+    billed_units: BilledUnits,
+}
+
+#[derive(Default, Deserialize)]
+struct BilledUnits {
+    #[serde(default)]
+    input_tokens: u64,
+}
+
+fn parse_response(body: &str, expected: usize) -> Result<EmbeddingResponse, JinaError> {
+    let response: Response = serde_json::from_str(body)
+        .map_err(|e| JinaError::Parse(format!("Cohere response: {}", truncate_for_display(&e.to_string(), MAX_DISPLAY_CHARS))))?;
+    if response.embeddings.float.len() != expected {
+        return Err(JinaError::Mismatch { expected, got: response.embeddings.float.len() });
+    }
+    let tokens = response.meta.billed_units.input_tokens;
+    Ok(EmbeddingResponse {
+        embeddings: response.embeddings.float,
+        usage: Usage { prompt_tokens: tokens, total_tokens: tokens },
+        diagnostics: None,
+        provenance: None,
+        pooled: Vec::new(),
+        backend: BackendKind::Remote,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::HttpResponse;
+    use std::sync::Mutex;
+    
+    const FIXTURE: &str = include_str!("../fixtures/cohere/embed.json");
+    const FIXTURE_ERROR: &str = include_str!("../fixtures/cohere/error_invalid_request.json");
+    
+    fn serve(status: u16, body: &'static str) -> impl Transport {
+        move |_: &HttpRequest| Ok(HttpResponse { status, headers: Vec::new(), body: body.to_string() })
+    }
+    
+    #[test]
+    fn test_parse_fixtures() {
+        let parsed = parse_response(FIXTURE, 2).unwrap();
+        assert_eq!(parsed.embeddings, vec![vec![0.5, 0.5, -0.5, 0.5], vec![0.0, 0.6, 0.0, -0.8]]);
+        assert_eq!(parsed.usage, Usage { prompt_tokens: 3, total_tokens: 3 });
+        assert_eq!(parse_response(FIXTURE, 1).unwrap_err(), JinaError::Mismatch { expected: 1, got: 2 });
+        
+        // v2 without "float" in embedding_types answers with other keys only
+        assert!(matches!(parse_response(r#"{"embeddings":{"int8":[[1,2]]}}"#, 1), Err(JinaError::Parse(_))));
+        
+        let client = CohereClient::new("key").with_transport(serve(400, FIXTURE_ERROR));
+        assert_eq!(client.embed("x").unwrap_err(), JinaError::Api {
+            status: 400,
+            message: "invalid request: valid input_type must be provided with the provided model".to_string(),
+        });
+    }
+    
+    #[test]
+    fn test_request_serialization() {
+        let seen: Arc<Mutex<Vec<HttpRequest>>> = Arc::default();
+        let log = seen.clone();
+        let client = CohereClient::new("co-test")
+            .with_model("embed-multilingual-v3.0")
+            .with_transport(move |request: &HttpRequest| {
+                log.lock().unwrap().push(request.clone());
+                Ok(HttpResponse { status: 200, headers: Vec::new(), body: FIXTURE.to_string() })
+            });
+        
+        assert_eq!(client.dimensions(), 0);
+        client.embed_batch_with(&["hello", "goodbye"], &EmbedOptions::query()).unwrap();
+        assert_eq!(client.dimensions(), 4);
+        
+        let request = seen.lock().unwrap()[0].clone();
+        assert_eq!(request.url, "https://api.cohere.com/v2/embed");
+        assert!(request.headers.contains(&("Authorization".to_string(), "Bearer co-test".to_string())));
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body, json!({
+            "model": "embed-multilingual-v3.0",
+            "texts": ["hello", "goodbye"],
+            "input_type": "search_query",
+            "embedding_types": ["float"],
+        }));
+        
+        let options = EmbedOptions::passage().with_dimensions(256);
+        let body = client.request_body(&["x"], &options);
+        assert_eq!(body["input_type"], "search_document");
+        assert_eq!(body["output_dimension"], 256);
+        assert_eq!(input_type(None), "search_document");
+    }
+    
+    /// Opt-in: `COHERE_API_KEY=... cargo test cohere_live`
+    #[test]
+    fn test_cohere_live() {
+        let Ok(key) = std::env::var("COHERE_API_KEY") else { return };
+        let client = CohereClient::new(&key);
+        let query = client.embed_batch_with(&["where do cats sleep"], &EmbedOptions::query()).unwrap();
+        assert_eq!(query[0].len(), 1024);
+        assert_eq!(client.dimensions(), 1024);
+    }
+}
@@ -0,0 +1,426 @@
+//! `JinaClient` from a TOML file, reloadable at runtime (`config` feature)
+//!
+//! ```toml
+//! api_key = "${JINA_API_KEY}"
+//! model = "jina-embeddings-v3"
+//! backend = "http"            # or "offline"
+//! dimensions = 1024
+//! task = "retrieval.passage"
+//! timeout_ms = 30000
+//!
+//! [limits]
+//! max_batch_size = 64
+//! curl_parallelism = 4
+//!
+//! [limits.context_tokens]      # context windows the table lacks or gets wrong
+//! "my-finetuned-v3" = 8192
+//!
+//! [retry]
+//! max_retries = 3
+//! base_delay_ms = 250
+//!
+//! [[vector_pipeline]]
+//! stage = "truncate"
+//! dims = 256
+//!
+//! [[vector_pipeline]]
+//! stage = "normalize"
+//! ```
+//!
+//! Every builder option a file can express has a key; hooks, transports,
+//! clocks and token counters stay in code. `${NAME}` in `api_key` or
+//! `base_url` is replaced by that environment variable, which must be set.
+//! Unknown keys are errors, so a typo never silently keeps a default.
+//! `[[vector_pipeline]]` tables are `postprocess::StageSpec`s, in order:
+//! the indexing and the querying side read one file, so their vectors go
+//! through the same stages.
+//!
+//! `JinaClient::reload` re-reads the file. The key, timeouts, retry
+//! policies and `[limits]` apply at once, swapped in together while other
+//! threads keep embedding with the client; each request uses the values in
+//! force when it started. Every other key changes the
+//! vectors or the backend, so a reload changing one is refused, naming all
+//! of them, and nothing of it applies. A file that fails to read or parse
+//! leaves the client as it was too. Watching the file is left to the
+//! caller: call `reload` on a timer or from a file-change notification.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::error::{register_secret, JinaError};
+use crate::jina_api::{EmbedOptions, JinaClient, Settings, Task, DEFAULT_COMPRESSION_THRESHOLD, DEFAULT_CURL_PARALLELISM,
+                      MAX_BATCH_SIZE};
+use crate::postprocess::{StageSpec, VectorPipeline};
+use crate::tokens::ContextLimits;
+use crate::transport::RetryPolicy;
+
+/// Where a client's vectors come from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigBackend {
+    /// The Jina API over HTTPS (`with_http`)
+    #[default]
+    Http,
+    /// Pseudo-embeddings, no key needed
+    Offline,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetryConfig {
+    pub max_retries: Option<u32>,
+    pub base_delay_ms: Option<u64>,
+    pub max_delay_ms: Option<u64>,
+}
+
+impl RetryConfig {
+    /// `base` with the keys this section sets
+    fn policy(&self, base: RetryPolicy) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: self.max_retries.unwrap_or(base.max_retries),
+            base_delay: self.base_delay_ms.map_or(base.base_delay, Duration::from_millis),
+            max_delay: self.max_delay_ms.map_or(base.max_delay, Duration::from_millis),
+        }
+    }
+}
+
+/// Request size and concurrency caps
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_batch_size: Option<usize>,
+    pub max_batch_tokens: Option<usize>,
+    pub curl_parallelism: Option<usize>,
+    /// Gzip request bodies over this many bytes; 0 never compresses
+    pub compression_threshold: Option<usize>,
+    /// Context window per model, over `tokens::MODEL_CONTEXT_TOKENS`
+    #[serde(default)]
+    pub context_tokens: BTreeMap<String, usize>,
+}
+
+/// A parsed config file, environment variables already substituted
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientConfig {
+    #[serde(default)]
+    pub api_key: String,
+    pub model: Option<String>,
+    #[serde(default)]
+    pub backend: ConfigBackend,
+    pub base_url: Option<String>,
+    /// Size callers should request, through `embed_options`
+    pub dimensions: Option<usize>,
+    /// Task callers should request, through `embed_options`
+    pub task: Option<Task>,
+    #[serde(default)]
+    pub cache: bool,
+    #[serde(default)]
+    pub probe: bool,
+    #[serde(default)]
+    pub tolerant_items: bool,
+    pub rerank_model: Option<String>,
+    pub clip_model: Option<String>,
+    pub code_model: Option<String>,
+    pub code_chunk_chars: Option<usize>,
+    pub timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub reader_retry: RetryConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub vector_pipeline: Vec<StageSpec>,
+}
+
+impl ClientConfig {
+    /// Parse `text`, looking `${NAME}`s up with `env`
+    pub fn parse(text: &str, env: impl Fn(&str) -> Option<String>) -> Result<Self, JinaError> {
+        let mut config: ClientConfig = toml::from_str(text).map_err(|e| JinaError::InvalidInput(format!("Invalid config: {}", e)))?;
+        config.api_key = interpolate("api_key", &config.api_key, &env)?;
+        if let Some(url) = &config.base_url {
+            config.base_url = Some(interpolate("base_url", url, &env)?);
+        }
+        if config.backend == ConfigBackend::Http && config.api_key.is_empty() {
+            return Err(JinaError::InvalidInput("Config needs an api_key for the http backend".to_string()));
+        }
+        Ok(config)
+    }
+    
+    /// Read and parse the file at `path` with the process environment
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, JinaError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| JinaError::InvalidInput(format!("Cannot read config {}: {}", path.display(), e)))?;
+        Self::parse(&text, |name| std::env::var(name).ok())
+    }
+    
+    /// `EmbedOptions` of the configured task and dimensions
+    pub fn embed_options(&self) -> EmbedOptions {
+        let mut options = EmbedOptions::default();
+        if let Some(task) = self.task {
+            options = options.with_task(task);
+        }
+        if let Some(dims) = self.dimensions {
+            options = options.with_dimensions(dims);
+        }
+        options
+    }
+    
+    /// A client built with every setting of this config; fails on a
+    /// `vector_pipeline` whose stages do not fit or cannot load
+    pub fn build(&self) -> Result<JinaClient, JinaError> {
+        let mut client = JinaClient::new(&self.api_key);
+        if let Some(model) = &self.model {
+            client = client.with_model(model);
+        }
+        if let Some(url) = &self.base_url {
+            client = client.with_base_url(url);
+        }
+        if self.backend == ConfigBackend::Http {
+            client = client.with_http();
+        }
+        if self.cache {
+            client = client.with_cache();
+        }
+        if self.probe {
+            client = client.with_probe();
+        }
+        if let Some(model) = &self.rerank_model {
+            client = client.with_rerank_model(model);
+        }
+        if let Some(model) = &self.clip_model {
+            client = client.with_clip_model(model);
+        }
+        if let Some(model) = &self.code_model {
+            client = client.with_code_model(model);
+        }
+        if let Some(n) = self.code_chunk_chars {
+            client = client.with_code_chunk_chars(n);
+        }
+        if self.tolerant_items {
+            client = client.with_tolerant_items();
+        }
+        client = client.with_vector_pipeline(VectorPipeline::from_specs(&self.vector_pipeline)?);
+        client.replace_settings(self.runtime_settings());
+        Ok(client)
+    }
+    
+    /// Keys whose value differs in `other` and cannot change at runtime
+    fn restart_only_changes(&self, other: &ClientConfig) -> Vec<&'static str> {
+        let changed = [
+            ("model", self.model != other.model),
+            ("backend", self.backend != other.backend),
+            ("base_url", self.base_url != other.base_url),
+            ("dimensions", self.dimensions != other.dimensions),
+            ("task", self.task != other.task),
+            ("cache", self.cache != other.cache),
+            ("probe", self.probe != other.probe),
+            ("tolerant_items", self.tolerant_items != other.tolerant_items),
+            ("rerank_model", self.rerank_model != other.rerank_model),
+            ("clip_model", self.clip_model != other.clip_model),
+            ("code_model", self.code_model != other.code_model),
+            ("code_chunk_chars", self.code_chunk_chars != other.code_chunk_chars),
+            ("vector_pipeline", self.vector_pipeline != other.vector_pipeline),
+        ];
+        changed.into_iter().filter(|&(_, changed)| changed).map(|(key, _)| key).collect()
+    }
+    
+    /// The settings of this config that may change at runtime
+    fn runtime_settings(&self) -> Settings {
+        register_secret(&self.api_key);
+        let limits = &self.limits;
+        Settings {
+            api_key: self.api_key.clone(),
+            max_batch_size: limits.max_batch_size.unwrap_or(MAX_BATCH_SIZE).clamp(1, MAX_BATCH_SIZE),
+            max_batch_tokens: limits.max_batch_tokens.map_or(usize::MAX, |n| n.max(1)),
+            context_limits: limits.context_tokens.iter().fold(ContextLimits::new(), |limits, (model, &tokens)| limits.with_limit(model, tokens)),
+            retry: self.retry.policy(RetryPolicy::default()),
+            reader_retry: self.reader_retry.policy(crate::reader::reader_retry_policy()),
+            timeout: self.timeout_ms.map(Duration::from_millis),
+            compression_threshold: match limits.compression_threshold {
+                Some(0) => None,
+                Some(bytes) => Some(bytes),
+                None => Some(DEFAULT_COMPRESSION_THRESHOLD),
+            },
+            curl_parallelism: limits.curl_parallelism.unwrap_or(DEFAULT_CURL_PARALLELISM).max(1),
+        }
+    }
+    
+    /// Keys whose value differs in `other` and changes at runtime
+    fn runtime_changes(&self, other: &ClientConfig) -> Vec<&'static str> {
+        let changed = [
+            ("api_key", self.api_key != other.api_key),
+            ("timeout_ms", self.timeout_ms != other.timeout_ms),
+            ("retry", self.retry != other.retry),
+            ("reader_retry", self.reader_retry != other.reader_retry),
+            ("limits", self.limits != other.limits),
+        ];
+        changed.into_iter().filter(|&(_, changed)| changed).map(|(key, _)| key).collect()
+    }
+}
+
+/// `value` with each `${NAME}` replaced by `env(NAME)`
+fn interpolate(key: &str, value: &str, env: &impl Fn(&str) -> Option<String>) -> Result<String, JinaError> {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let end = rest[start..].find('}')
+            .ok_or_else(|| JinaError::InvalidInput(format!("Config {}: unclosed ${{ in {:?}", key, value)))?;
+        let name = &rest[start + 2..start + end];
+        let substitute = env(name).filter(|v| !v.is_empty())
+            .ok_or_else(|| JinaError::InvalidInput(format!("Config {}: environment variable {} is not set", key, name)))?;
+        out.push_str(&rest[..start]);
+        out.push_str(&substitute);
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// The file a client was built from and the config last applied from it
+#[derive(Debug)]
+pub(crate) struct ConfigSource {
+    path: PathBuf,
+    /// Held for a whole `reload`, so reloads apply one at a time
+    applied: Mutex<ClientConfig>,
+}
+
+impl JinaClient {
+    /// Client configured by the TOML file at `path`; `reload` re-reads it
+    pub fn from_config_file(path: impl AsRef<Path>) -> Result<Self, JinaError> {
+        let config = ClientConfig::read(&path)?;
+        let mut client = config.build()?;
+        client.config = Some(ConfigSource { path: path.as_ref().to_path_buf(), applied: Mutex::new(config) });
+        Ok(client)
+    }
+    
+    /// The config last applied, for clients built `from_config_file`
+    pub fn config(&self) -> Option<ClientConfig> { self.config.as_ref().map(|source| source.applied.lock().unwrap().clone()) }
+    
+    /// Re-read the config file and apply what changed; returns the changed
+    /// keys, empty when nothing did.
+    ///
+    /// Fails, changing nothing, when the file cannot be read or parsed or
+    /// changes a key that needs a new client.
+    pub fn reload(&self) -> Result<Vec<&'static str>, JinaError> {
+        let source = self.config.as_ref()
+            .ok_or_else(|| JinaError::InvalidInput("This client was not built from a config file".to_string()))?;
+        let mut applied = source.applied.lock().unwrap();
+        let config = ClientConfig::read(&source.path)?;
+        let restart = applied.restart_only_changes(&config);
+        if !restart.is_empty() {
+            return Err(JinaError::InvalidInput(format!("Config changes {} which cannot change at runtime; nothing was applied",
+                                                       restart.join(", "))));
+        }
+        let changed = applied.runtime_changes(&config);
+        self.replace_settings(config.runtime_settings());
+        *applied = config;
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const CONFIG: &str = r#"
+api_key = "jina_${KEY_SUFFIX}"
+model = "jina-embeddings-v2-base-en"
+dimensions = 512
+task = "retrieval.passage"
+timeout_ms = 1500
+
+[limits]
+max_batch_size = 16
+compression_threshold = 0
+
+[retry]
+max_retries = 1
+"#;
+    
+    fn env(name: &str) -> Option<String> { (name == "KEY_SUFFIX").then(|| "0123456789abcdef".to_string()) }
+    
+    #[test]
+    fn test_parse_interpolates_and_rejects_bad_files() {
+        let config = ClientConfig::parse(CONFIG, env).unwrap();
+        assert_eq!(config.api_key, "jina_0123456789abcdef");
+        assert_eq!((config.dimensions, config.task), (Some(512), Some(Task::RetrievalPassage)));
+        assert_eq!(config.embed_options(), EmbedOptions::passage().with_dimensions(512));
+        assert_eq!(config.retry.policy(RetryPolicy::default()).max_retries, 1);
+        
+        let client = config.build().unwrap();
+        let settings = client.settings();
+        assert_eq!((client.model.as_str(), settings.max_batch_size, settings.timeout),
+                   ("jina-embeddings-v2-base-en", 16, Some(Duration::from_millis(1500))));
+        assert!(client.is_online() && settings.compression_threshold.is_none());
+        
+        let err = ClientConfig::parse(CONFIG, |_| None).unwrap_err().to_string();
+        assert!(err.contains("KEY_SUFFIX is not set"), "{}", err);
+        let err = ClientConfig::parse("api_key = \"k\"\nmodle = \"m\"", env).unwrap_err().to_string();
+        assert!(err.contains("modle"), "{}", err);
+        assert!(ClientConfig::parse("model = \"m\"", env).unwrap_err().to_string().contains("needs an api_key"));
+        assert!(!ClientConfig::parse("backend = \"offline\"", env).unwrap().build().unwrap().is_online());
+        
+        let pipeline = "backend = \"offline\"\n[[vector_pipeline]]\nstage = \"truncate\"\ndims = 256\n[[vector_pipeline]]\nstage = \"normalize\"\n";
+        let client = ClientConfig::parse(pipeline, env).unwrap().build().unwrap();
+        assert_eq!(client.vector_pipeline().unwrap().describe(), "truncate 256 | normalize");
+        let projection = "backend = \"offline\"\n[[vector_pipeline]]\nstage = \"truncate\"\ndims = 128\n[[vector_pipeline]]\nstage = \"project\"\nin_dims = 256\nout_dims = 64\nseed = 1\nmode = \"gaussian\"\n";
+        assert!(ClientConfig::parse(projection, env).unwrap().build().err().unwrap().to_string().contains("takes 256 dims"));
+        let custom = "backend = \"offline\"\nmodel = \"my-model\"\n[limits.context_tokens]\n\"my-model\" = 300\n";
+        assert_eq!(ClientConfig::parse(custom, env).unwrap().build().unwrap().context_limit(), Some(300));
+    }
+    
+    #[test]
+    fn test_reload_applies_runtime_changes_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("client.toml");
+        let write = |text: &str| std::fs::write(&path, text).unwrap();
+        write("backend = \"offline\"\ndimensions = 256\n[retry]\nmax_retries = 2\n");
+        let client = JinaClient::from_config_file(&path).unwrap();
+        assert_eq!(client.reload().unwrap(), Vec::<&str>::new());
+        
+        write("backend = \"offline\"\ndimensions = 256\ntimeout_ms = 800\n[retry]\nmax_retries = 5\n[limits]\nmax_batch_size = 8\n");
+        assert_eq!(client.reload().unwrap(), ["timeout_ms", "retry", "limits"]);
+        let settings = client.settings();
+        assert_eq!((settings.retry.max_retries, settings.timeout, settings.max_batch_size), (5, Some(Duration::from_millis(800)), 8));
+        
+        // Unsafe changes are refused as a whole, with every offending key named
+        write("backend = \"offline\"\ndimensions = 512\nmodel = \"other\"\n[retry]\nmax_retries = 0\n");
+        let err = client.reload().unwrap_err().to_string();
+        assert!(err.contains("model, dimensions") && err.contains("nothing was applied"), "{}", err);
+        write("backend = \"offline\"\n[retry\n");
+        assert!(client.reload().unwrap_err().to_string().contains("Invalid config"));
+        assert_eq!((client.settings().retry.max_retries, client.config().unwrap().dimensions), (5, Some(256)));
+        
+        assert!(JinaClient::new("").reload().unwrap_err().to_string().contains("not built from a config file"));
+    }
+    
+    #[test]
+    fn test_reload_while_embedding() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("client.toml");
+        let limits = |n: usize| format!("backend = \"offline\"\n[limits]\nmax_batch_size = {}\n", n);
+        std::fs::write(&path, limits(2)).unwrap();
+        let client = std::sync::Arc::new(JinaClient::from_config_file(&path).unwrap());
+        let expected = client.embed_batch_full(&["Ada", "Grace", "Alan"], &EmbedOptions::default()).unwrap().embeddings;
+        
+        let embedder = {
+            let client = client.clone();
+            std::thread::spawn(move || {
+                for _ in 0..200 {
+                    let response = client.embed_batch_full(&["Ada", "Grace", "Alan"], &EmbedOptions::default()).unwrap();
+                    assert_eq!(response.embeddings, expected);
+                }
+            })
+        };
+        for n in 1..=50 {
+            std::fs::write(&path, limits(n)).unwrap();
+            client.reload().unwrap();
+            assert_eq!(client.settings().max_batch_size, n);
+        }
+        embedder.join().unwrap();
+    }
+}
@@ -0,0 +1,289 @@
+//! Near-duplicate clusters for review before deletion
+//!
+//! `report` links every pair of records whose embeddings have a cosine of
+//! at least `threshold` and groups the linked records into clusters
+//! (transitively: a ~ b and b ~ c puts a, b and c together). Each cluster
+//! lists its members with every pairwise score and suggests the canonical
+//! record to keep, chosen by a `Canonical` strategy. The report goes out as
+//! JSON or CSV for an editor; once approved, `apply` drops the other
+//! members. Scoring is all pairs, so it suits review-sized sets rather
+//! than whole corpora.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::io::Write;
+
+use crate::io::EmbeddingRecord;
+use crate::metadata::MetaValue;
+use crate::search::cosine;
+
+/// Which member of a cluster to keep
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Canonical {
+    /// The longest text, counted in chars; records without text count as empty
+    #[default]
+    LongestText,
+    /// The greatest value of this metadata field: numbers (epoch times) or
+    /// strings (ISO 8601 dates); members without it lose
+    Newest(String),
+    /// The first id of the list found in the cluster
+    Priority(Vec<String>),
+}
+
+/// One record of a cluster
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Member {
+    pub id: String,
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+/// Cosine between two members of a cluster
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PairScore {
+    pub left: String,
+    pub right: String,
+    pub score: f32,
+}
+
+/// Records linked by near-duplicate pairs, in input order
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DuplicateCluster {
+    /// Id of the suggested record to keep
+    pub canonical: String,
+    pub members: Vec<Member>,
+    /// Every pair of members, linked or not, in member order
+    pub pairs: Vec<PairScore>,
+}
+
+impl DuplicateCluster {
+    /// Ids `apply` removes: every member but the canonical one
+    pub fn duplicates(&self) -> impl Iterator<Item = &str> {
+        self.members.iter().map(|m| m.id.as_str()).filter(move |id| *id != self.canonical)
+    }
+    
+    /// Cosine between members `a` and `b`
+    pub fn score(&self, a: &str, b: &str) -> Option<f32> {
+        self.pairs.iter()
+            .find(|p| (p.left == a && p.right == b) || (p.left == b && p.right == a))
+            .map(|p| p.score)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct DedupReport {
+    pub threshold: f32,
+    /// Clusters of two or more records, ordered by their first member
+    pub clusters: Vec<DuplicateCluster>,
+}
+
+impl DedupReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("dedup reports always serialize")
+    }
+    
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("Cannot parse dedup report: {}", e))
+    }
+    
+    /// One row per member: `cluster,id,canonical,score,text`, where `score`
+    /// is the cosine to the canonical record (empty on the canonical's row)
+    pub fn write_csv(&self, mut out: impl Write) -> std::io::Result<()> {
+        writeln!(out, "cluster,id,canonical,score,text")?;
+        for (n, cluster) in self.clusters.iter().enumerate() {
+            for member in &cluster.members {
+                let canonical = member.id == cluster.canonical;
+                let score = if canonical { None } else { cluster.score(&member.id, &cluster.canonical) };
+                writeln!(out, "{},{},{},{},{}", n, csv_field(&member.id), canonical,
+                         score.map_or(String::new(), |s| s.to_string()), csv_field(member.text.as_deref().unwrap_or("")))?;
+            }
+        }
+        Ok(())
+    }
+    
+    pub fn to_csv(&self) -> String {
+        let mut out = Vec::new();
+        self.write_csv(&mut out).expect("writing to a Vec cannot fail");
+        String::from_utf8(out).expect("the report is UTF-8")
+    }
+}
+
+/// Cluster `records` at `threshold`, keeping the longest text of each cluster
+pub fn report(records: &[EmbeddingRecord], threshold: f32) -> DedupReport {
+    report_with(records, threshold, &Canonical::LongestText)
+}
+
+/// `report` with the canonical record chosen by `canonical`
+pub fn report_with(records: &[EmbeddingRecord], threshold: f32, canonical: &Canonical) -> DedupReport {
+    let n = records.len();
+    let mut scores = vec![0.0f32; n * n];
+    let mut parent: Vec<usize> = (0..n).collect();
+    for i in 0..n {
+        for j in i + 1..n {
+            let score = cosine(&records[i].embedding, &records[j].embedding);
+            scores[i * n + j] = score;
+            if score >= threshold {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+    }
+    
+    // Roots are each cluster's first member, so grouping by root keeps input order
+    let mut groups: Vec<Vec<usize>> = vec![vec![]; n];
+    for i in 0..n {
+        let r = root(&mut parent, i);
+        groups[r].push(i);
+    }
+    let clusters = groups.into_iter()
+        .filter(|g| g.len() > 1)
+        .map(|members| {
+            let pairs = members.iter().enumerate()
+                .flat_map(|(a, &i)| members[a + 1..].iter().map(move |&j| (i, j)))
+                .map(|(i, j)| PairScore { left: records[i].id.clone(), right: records[j].id.clone(), score: scores[i * n + j] })
+                .collect();
+            let keep = choose(canonical, records, &members);
+            DuplicateCluster {
+                canonical: records[keep].id.clone(),
+                members: members.iter().map(|&i| Member { id: records[i].id.clone(), text: records[i].text.clone() }).collect(),
+                pairs,
+            }
+        })
+        .collect();
+    DedupReport { threshold, clusters }
+}
+
+/// Remove every cluster's duplicates from `records`, returning how many
+/// records went. A cluster whose canonical record is not in `records` is
+/// skipped, so no cluster loses all its copies; applying a report twice
+/// removes nothing the second time.
+pub fn apply(report: &DedupReport, records: &mut Vec<EmbeddingRecord>) -> usize {
+    let present: HashSet<&str> = records.iter().map(|r| r.id.as_str()).collect();
+    let remove: HashSet<String> = report.clusters.iter()
+        .filter(|c| present.contains(c.canonical.as_str()))
+        .flat_map(|c| c.duplicates().map(str::to_string))
+        .collect();
+    let before = records.len();
+    records.retain(|r| !remove.contains(&r.id));
+    before - records.len()
+}
+
+fn root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+/// Index of the record to keep among `members`; ties go to the earliest
+fn choose(canonical: &Canonical, records: &[EmbeddingRecord], members: &[usize]) -> usize {
+    let best_by = |cmp: &dyn Fn(usize, usize) -> Ordering| {
+        members.iter().copied().reduce(|best, i| if cmp(i, best) == Ordering::Greater { i } else { best }).unwrap()
+    };
+    match canonical {
+        Canonical::LongestText => {
+            let len = |i: usize| records[i].text.as_deref().map_or(0, |t| t.chars().count());
+            best_by(&|a, b| len(a).cmp(&len(b)))
+        }
+        Canonical::Newest(field) => best_by(&|a, b| {
+            compare_times(records[a].metadata.get(field), records[b].metadata.get(field))
+        }),
+        Canonical::Priority(ids) => ids.iter()
+            .find_map(|id| members.iter().copied().find(|&i| records[i].id == *id))
+            .unwrap_or(members[0]),
+    }
+}
+
+/// Numbers and strings each by value; a missing or other-typed value is oldest
+fn compare_times(a: Option<&MetaValue>, b: Option<&MetaValue>) -> Ordering {
+    match (a, b) {
+        (Some(MetaValue::Num(a)), Some(MetaValue::Num(b))) => a.total_cmp(b),
+        (Some(MetaValue::Str(a)), Some(MetaValue::Str(b))) => a.cmp(b),
+        (Some(MetaValue::Num(_) | MetaValue::Str(_)), _) => Ordering::Greater,
+        (_, Some(MetaValue::Num(_) | MetaValue::Str(_))) => Ordering::Less,
+        _ => Ordering::Equal,
+    }
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Metadata;
+    
+    /// Two clusters along the x and y axes, and one loner
+    fn records() -> Vec<EmbeddingRecord> {
+        let record = |id: &str, text: &str, embedding: Vec<f32>, updated: &str| {
+            EmbeddingRecord::new(id, embedding).with_text(text).with_metadata(Metadata::new().with("updated", updated))
+        };
+        vec![
+            record("a", "Ada, the first programmer", vec![1.0, 0.0, 0.0], "2021-03-01"),
+            record("b", "Ada wrote the \"first\" program", vec![0.99, 0.05, 0.0], "2023-07-12"),
+            record("c", "Jan builds systems", vec![0.0, 1.0, 0.0], "2020-01-01"),
+            record("d", "lonely", vec![0.0, 0.0, 1.0], "2024-01-01"),
+            record("e", "Ada", vec![0.98, 0.0, 0.1], ""),
+            record("f", "Jan builds distributed systems", vec![0.05, 0.99, 0.0], "2019-05-05"),
+        ]
+    }
+    
+    #[test]
+    fn test_clusters_and_canonical_strategies() {
+        let records = records();
+        let report = report(&records, 0.95);
+        let ids = |c: &DuplicateCluster| c.members.iter().map(|m| m.id.clone()).collect::<Vec<_>>();
+        assert_eq!(report.clusters.iter().map(ids).collect::<Vec<_>>(), [vec!["a", "b", "e"], vec!["c", "f"]]);
+        let first = &report.clusters[0];
+        assert_eq!(first.pairs.len(), 3);
+        assert!((first.score("e", "a").unwrap() - cosine(&records[0].embedding, &records[4].embedding)).abs() < 1e-6);
+        
+        let canonicals = |canonical: Canonical| -> Vec<String> {
+            report_with(&records, 0.95, &canonical).clusters.into_iter().map(|c| c.canonical).collect()
+        };
+        assert_eq!(canonicals(Canonical::LongestText), ["b", "f"]);
+        assert_eq!(canonicals(Canonical::Newest("updated".into())), ["b", "c"]);
+        assert_eq!(canonicals(Canonical::Priority(vec!["e".into(), "zzz".into()])), ["e", "c"]);
+        // Missing timestamps lose; ties go to the first member
+        assert_eq!(canonicals(Canonical::Newest("missing".into())), ["a", "c"]);
+        assert!(super::report(&records, 1.01).clusters.is_empty());
+    }
+    
+    #[test]
+    fn test_report_serialization() {
+        let report = report(&records(), 0.95);
+        assert_eq!(DedupReport::from_json(&report.to_json()).unwrap(), report);
+        assert!(DedupReport::from_json("{").unwrap_err().contains("Cannot parse"));
+        
+        let csv = report.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "cluster,id,canonical,score,text");
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[2], "0,b,true,,\"Ada wrote the \"\"first\"\" program\"");
+        let score = report.clusters[0].score("a", "b").unwrap();
+        assert_eq!(lines[1], format!("0,a,false,{},\"Ada, the first programmer\"", score));
+        assert!(lines[5].starts_with("1,f,true,,"));
+    }
+    
+    #[test]
+    fn test_apply_is_idempotent() {
+        let mut records = records();
+        let report = report(&records, 0.95);
+        assert_eq!(apply(&report, &mut records), 3);
+        assert_eq!(records.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), ["b", "d", "f"]);
+        assert_eq!(apply(&report, &mut records), 0);
+        assert_eq!(records.len(), 3);
+        
+        // A cluster whose canonical is gone keeps its remaining copies
+        let mut without_canonical: Vec<EmbeddingRecord> = self::records().into_iter().filter(|r| r.id != "b").collect();
+        assert_eq!(apply(&report, &mut without_canonical), 1);
+        assert!(without_canonical.iter().any(|r| r.id == "a"));
+    }
+}
@@ -0,0 +1,513 @@
+//! One vector for a long document
+//!
+//! `JinaClient::embed_document` chunks a document, embeds the chunks as
+//! passages and pools the chunk vectors. When the client talks to the Jina
+//! API directly and the document fits the model's context window, chunks
+//! are embedded with `late_chunking`, so each chunk vector sees the whole
+//! document. Elsewhere, `Pooling::ContextBlend` approximates that on the
+//! client by mixing the whole-document vector into each chunk vector.
+//!
+//! `LongInputStrategy::ChunkAndPool` does the same inside `embed_batch_full`
+//! for any input over the context budget, leaving the others as they are.
+//!
+//! `embed_fields` embeds a record of several fields (title, body, tags)
+//! with any provider: pooled by field weight, so a long body does not
+//! drown a short title, as one concatenated text, or a vector per field.
+
+use std::collections::BTreeMap;
+
+use crate::chunk::{Chunk, Chunking};
+use crate::error::JinaError;
+use crate::jina_api::{CallOptions, EmbedOptions, JinaClient};
+use crate::provider::{EmbeddingProvider, EmbeddingResponse, Usage};
+use crate::transport::Diagnostics;
+use crate::search::normalize;
+
+/// jina-embeddings-v3 context window, in tokens
+pub const CONTEXT_TOKENS: usize = 8192;
+
+/// How chunk vectors combine into the document vector
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Pooling {
+    /// Mean weighted by each chunk's token count
+    #[default]
+    MeanWeighted,
+    Mean,
+    /// Component-wise maximum
+    Max,
+    /// Client-side late chunking: embed the whole document too and use
+    /// `normalize(alpha * chunk + (1 - alpha) * document)` per chunk; the
+    /// document vector is the whole-document embedding
+    ContextBlend { alpha: f32 },
+}
+
+/// What `JinaClient::embed_batch_full` does with an input over the budget:
+/// `max_input_tokens`, or else the model's window (`JinaClient::context_limit`)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LongInputStrategy {
+    /// Cut it to `max_input_tokens` if set, and otherwise send it whole for
+    /// the API to truncate or refuse
+    #[default]
+    Explicit,
+    /// Fail the call with `JinaError::InputTooLong` before sending anything
+    Reject,
+    /// Cut it to the budget at a sentence boundary, as `max_input_tokens` does
+    Truncate,
+    /// Split it with `chunker` into chunks within the budget (of `4 * budget`
+    /// characters, or `budget` tokens for `Segmenter`, halved until each
+    /// fits), embed them, with `late_chunking` where `embed_document` would,
+    /// and `pool` them, weighted by tokens. Its index goes into
+    /// `EmbeddingResponse::pooled`. `ContextBlend` is refused: it needs the
+    /// whole input embedded. `JinaClient` only, and without a known window
+    /// the budget is `CONTEXT_TOKENS`.
+    ChunkAndPool { chunker: Chunking, pooling: Pooling },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocumentOptions {
+    pub chunker: Chunking,
+    /// Per-chunk budget in the chunker's unit (tokens for `Segmenter`, characters otherwise)
+    pub max_chunk_size: usize,
+    pub pooling: Pooling,
+}
+
+impl Default for DocumentOptions {
+    fn default() -> Self { Self { chunker: Chunking::Local, max_chunk_size: 2000, pooling: Pooling::default() } }
+}
+
+impl DocumentOptions {
+    pub fn with_chunker(mut self, chunker: Chunking) -> Self {
+        self.chunker = chunker;
+        self
+    }
+    
+    pub fn with_max_chunk_size(mut self, n: usize) -> Self {
+        self.max_chunk_size = n;
+        self
+    }
+    
+    pub fn with_pooling(mut self, pooling: Pooling) -> Self {
+        self.pooling = pooling;
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DocumentEmbedding {
+    /// Pooled, unit-length document vector
+    pub vector: Vec<f32>,
+    /// One vector per chunk, in chunk order
+    pub chunk_vectors: Vec<Vec<f32>>,
+    pub chunks: Vec<Chunk>,
+    /// Tokens billed across every request
+    #[serde(default)]
+    pub usage: Usage,
+    /// Unblended vectors behind a `ContextBlend`, for trying other blends
+    #[serde(default)]
+    pub parts: Option<BlendParts>,
+}
+
+/// The two inputs of `blend`, as embedded
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BlendParts {
+    /// Embedding of the whole document as one passage
+    pub document: Vec<f32>,
+    /// Plain per-chunk embeddings
+    pub chunks: Vec<Vec<f32>>,
+}
+
+impl JinaClient {
+    /// Chunk `text`, embed the chunks as passages and pool them into one vector
+    pub fn embed_document(&self, text: &str, options: &DocumentOptions) -> Result<DocumentEmbedding, JinaError> {
+        if options.max_chunk_size == 0 {
+            return Err(JinaError::InvalidInput("max_chunk_size must be at least 1".to_string()));
+        }
+        let chunks = self.chunk(text, options.max_chunk_size, options.chunker)?;
+        if chunks.is_empty() {
+            return Err(JinaError::InvalidInput("document has no text to embed".to_string()));
+        }
+        
+        let texts: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+        if let Pooling::ContextBlend { alpha } = options.pooling {
+            if !(0.0..=1.0).contains(&alpha) {
+                return Err(JinaError::InvalidInput(format!("blend alpha {} is outside 0..=1", alpha)));
+            }
+            let inputs: Vec<&str> = std::iter::once(text).chain(texts.iter().copied()).collect();
+            let response = self.embed_batch_full(&inputs, &EmbedOptions::passage())?;
+            let mut embeddings = response.embeddings.into_iter();
+            let mut document = embeddings.next().unwrap_or_default();
+            normalize(&mut document);
+            let parts = BlendParts { document, chunks: embeddings.collect() };
+            let chunk_vectors = parts.chunks.iter().map(|c| blend(c, &parts.document, alpha)).collect();
+            return Ok(DocumentEmbedding {
+                vector: parts.document.clone(),
+                chunk_vectors,
+                chunks,
+                usage: response.usage,
+                parts: Some(parts),
+            });
+        }
+        
+        let mut embed_options = EmbedOptions::passage();
+        if self.supports_late_chunking() && self.count_tokens(text) <= CONTEXT_TOKENS {
+            embed_options = embed_options.with_late_chunking();
+        }
+        let response = self.embed_batch_full(&texts, &embed_options)?;
+        
+        let weights: Vec<f32> = texts.iter().map(|t| self.count_tokens(t).max(1) as f32).collect();
+        let vector = pool(&response.embeddings, &weights, options.pooling);
+        Ok(DocumentEmbedding { vector, chunk_vectors: response.embeddings, chunks, usage: response.usage, parts: None })
+    }
+}
+
+impl JinaClient {
+    /// `embed_batch_inner` under `LongInputStrategy::ChunkAndPool`, for preprocessed `texts`
+    pub(crate) fn embed_pooling_long(&self, texts: &[&str], options: &EmbedOptions, chunker: Chunking, pooling: Pooling,
+                                     call: &CallOptions, mut diagnostics: Option<&mut Diagnostics>)
+                                     -> Result<EmbeddingResponse, JinaError> {
+        if let Pooling::ContextBlend { .. } = pooling {
+            return Err(JinaError::InvalidInput("ContextBlend cannot pool an input over the context budget".to_string()));
+        }
+        let budget = self.input_budget(options).unwrap_or(CONTEXT_TOKENS).max(1);
+        let long: Vec<usize> = (0..texts.len()).filter(|&i| self.count_tokens(texts[i]) > budget).collect();
+        if long.is_empty() {
+            return self.embed_prepared(texts, options, call, diagnostics);
+        }
+        let short: Vec<&str> = (0..texts.len()).filter(|i| long.binary_search(i).is_err()).map(|i| texts[i]).collect();
+        let mut response = match short.is_empty() {
+            true => EmbeddingResponse {
+                provenance: Some(self.provenance(options)),
+                backend: self.backend_kind(),
+                ..EmbeddingResponse::default()
+            },
+            false => self.embed_prepared(&short, options, call, diagnostics.as_deref_mut())?,
+        };
+        
+        let mut pooled = Vec::with_capacity(long.len());
+        for &index in &long {
+            let chunks = self.chunks_within(texts[index], budget, chunker)?;
+            let pieces: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+            let mut chunk_options = options.clone();
+            chunk_options.late_chunking = self.supports_late_chunking() && self.count_tokens(texts[index]) <= CONTEXT_TOKENS;
+            let part = self.embed_prepared(&pieces, &chunk_options, call, diagnostics.as_deref_mut())?;
+            response.usage.add(&part.usage);
+            let weights: Vec<f32> = pieces.iter().map(|t| self.count_tokens(t).max(1) as f32).collect();
+            pooled.push(pool(&part.embeddings, &weights, pooling));
+        }
+        
+        let got = response.embeddings.len() + pooled.len();
+        if got != texts.len() {
+            return Err(JinaError::Mismatch { expected: texts.len(), got });
+        }
+        let (mut short, mut pooled) = (std::mem::take(&mut response.embeddings).into_iter(), pooled.into_iter());
+        response.embeddings = (0..texts.len())
+            .map(|i| if long.binary_search(&i).is_ok() { pooled.next() } else { short.next() }.unwrap())
+            .collect();
+        response.pooled = long;
+        Ok(response)
+    }
+    
+    /// Chunks of `text` of at most `budget` tokens, unless a single word is longer
+    fn chunks_within(&self, text: &str, budget: usize, chunker: Chunking) -> Result<Vec<Chunk>, JinaError> {
+        let mut size = if chunker == Chunking::Segmenter { budget } else { budget.saturating_mul(4) };
+        loop {
+            let chunks = self.chunk(text, size, chunker)?;
+            if size == 1 || chunks.iter().all(|c| self.count_tokens(&c.text) <= budget) {
+                return Ok(chunks);
+            }
+            size /= 2;
+        }
+    }
+}
+
+/// How `embed_fields` turns fields into vectors
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum FieldStrategy {
+    /// Embed each field and pool with `Pooling::MeanWeighted` by field weight
+    #[default]
+    WeightedPool,
+    /// Embed the fields joined by `separator` as one text; weights are ignored
+    Concatenate { separator: String },
+    /// Embed each field, for field-specific indexes
+    PerField,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FieldEmbedding {
+    /// Unit-length record vector; `None` for `PerField`
+    pub vector: Option<Vec<f32>>,
+    /// Each embedded field's vector by name; empty for `Concatenate`
+    pub fields: BTreeMap<String, Vec<f32>>,
+}
+
+/// Embed a record's `(name, text, weight)` fields with `strategy`.
+///
+/// Blank fields are skipped, which spreads their weight over the others;
+/// at least one field must have text, and weights must be finite and not
+/// negative.
+pub fn embed_fields<P: EmbeddingProvider + ?Sized>(provider: &P, fields: &[(&str, &str, f32)], strategy: &FieldStrategy)
+                                                   -> Result<FieldEmbedding, JinaError> {
+    if let Some((name, _, weight)) = fields.iter().find(|f| !(f.2.is_finite() && f.2 >= 0.0)) {
+        return Err(JinaError::InvalidInput(format!("field {} has weight {}", name, weight)));
+    }
+    let present: Vec<&(&str, &str, f32)> = fields.iter().filter(|f| !f.1.trim().is_empty()).collect();
+    if present.is_empty() {
+        return Err(JinaError::InvalidInput("record has no text to embed".to_string()));
+    }
+    
+    if let FieldStrategy::Concatenate { separator } = strategy {
+        let joined = present.iter().map(|f| f.1).collect::<Vec<_>>().join(separator);
+        let mut vector = provider.embed(&joined)?;
+        normalize(&mut vector);
+        return Ok(FieldEmbedding { vector: Some(vector), fields: BTreeMap::new() });
+    }
+    let texts: Vec<&str> = present.iter().map(|f| f.1).collect();
+    let vectors = provider.embed_batch(&texts)?;
+    if vectors.len() != texts.len() {
+        return Err(JinaError::Mismatch { expected: texts.len(), got: vectors.len() });
+    }
+    let vector = match strategy {
+        FieldStrategy::PerField => None,
+        _ => {
+            let weights: Vec<f32> = present.iter().map(|f| f.2).collect();
+            if weights.iter().sum::<f32>() == 0.0 {
+                return Err(JinaError::InvalidInput("every field with text has weight 0".to_string()));
+            }
+            Some(pool(&vectors, &weights, Pooling::MeanWeighted))
+        }
+    };
+    let fields = present.iter().map(|f| f.0.to_string()).zip(vectors).collect();
+    Ok(FieldEmbedding { vector, fields })
+}
+
+/// `normalize(alpha * chunk + (1 - alpha) * document)`
+pub fn blend(chunk: &[f32], document: &[f32], alpha: f32) -> Vec<f32> {
+    let mut mixed: Vec<f32> = chunk.iter().zip(document).map(|(c, d)| alpha * c + (1.0 - alpha) * d).collect();
+    normalize(&mut mixed);
+    mixed
+}
+
+/// Pool equally sized `vectors` into one unit-length vector
+///
+/// `weights` (one per vector) only matter for `MeanWeighted`.
+pub fn pool(vectors: &[Vec<f32>], weights: &[f32], pooling: Pooling) -> Vec<f32> {
+    let Some(first) = vectors.first() else { return Vec::new() };
+    let mut pooled = match pooling {
+        Pooling::Max => vectors[1..].iter().fold(first.clone(), |mut acc, v| {
+            acc.iter_mut().zip(v).for_each(|(a, x)| *a = a.max(*x));
+            acc
+        }),
+        // Blending pools nothing; average like `Mean` if asked directly
+        Pooling::Mean | Pooling::MeanWeighted | Pooling::ContextBlend { .. } => {
+            let mut sum = vec![0.0; first.len()];
+            for (i, v) in vectors.iter().enumerate() {
+                let w = if pooling == Pooling::Mean { 1.0 } else { weights.get(i).copied().unwrap_or(1.0) };
+                sum.iter_mut().zip(v).for_each(|(s, x)| *s += w * x);
+            }
+            sum
+        }
+    };
+    normalize(&mut pooled);
+    pooled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pseudo::PseudoEmbedder;
+    use crate::search::cosine;
+    use crate::transport::{HttpRequest, HttpResponse, RetryPolicy};
+    use std::sync::{Arc, Mutex};
+    
+    const DOC: &str = "Ada Lovelace wrote the first algorithm. It ran on the Analytical Engine. \
+                       Babbage designed the engine. Nobody built it in their lifetime.";
+    
+    #[test]
+    fn test_pool_math() {
+        let vectors = vec![vec![1.0, 0.0], vec![0.0, 1.0]];
+        let s = std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(pool(&vectors, &[1.0, 1.0], Pooling::Mean), vec![s, s]);
+        assert_eq!(pool(&vectors, &[3.0, 4.0], Pooling::MeanWeighted), vec![0.6, 0.8]);
+        assert_eq!(pool(&vectors, &[3.0, 4.0], Pooling::Mean), vec![s, s]);
+        assert_eq!(pool(&[vec![0.5, -2.0], vec![-1.0, 0.0]], &[], Pooling::Max), vec![1.0, 0.0]);
+        assert!(pool(&[], &[], Pooling::Mean).is_empty());
+    }
+    
+    #[test]
+    fn test_embed_document_offline() {
+        let client = JinaClient::new("test_key");
+        let options = DocumentOptions::default().with_max_chunk_size(60).with_pooling(Pooling::Mean);
+        let doc = client.embed_document(DOC, &options).unwrap();
+        
+        assert_eq!(doc.chunks.len(), 3);
+        assert_eq!(doc.chunk_vectors.len(), 3);
+        assert!(doc.chunks.iter().all(|c| DOC[c.start..c.end] == c.text));
+        // Chunks embed as passages, and the document vector is their normalized mean
+        let passages: Vec<&str> = doc.chunks.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(doc.chunk_vectors, client.embed_batch_full(&passages, &EmbedOptions::passage()).unwrap().embeddings);
+        assert_eq!(doc.vector, pool(&doc.chunk_vectors, &[], Pooling::Mean));
+        assert!(cosine(&doc.vector, &doc.chunk_vectors[0]) > 0.3);
+        assert_eq!(doc.usage, Usage::default());
+        
+        assert!(client.embed_document("  ", &options).is_err());
+    }
+    
+    #[test]
+    fn test_embed_fields() {
+        let embedder = PseudoEmbedder::new(64);
+        let title = "Analytical Engine";
+        let body = "Babbage designed a general purpose mechanical computer with a mill, a store and punched cards \
+                    for programs, and Lovelace wrote notes on how it could compute Bernoulli numbers.";
+        let record = |title_weight| [("title", title, title_weight), ("body", body, 1.0), ("tags", " ", 5.0)];
+        let pooled = |title_weight| embed_fields(&embedder, &record(title_weight), &FieldStrategy::WeightedPool).unwrap();
+        
+        // More title weight pulls the pooled vector toward the title alone
+        let title_vector = embedder.embed(title);
+        let toward_title = |w| cosine(pooled(w).vector.as_ref().unwrap(), &title_vector);
+        assert!(toward_title(3.0) > toward_title(1.0) + 0.1);
+        assert!(toward_title(1.0) > toward_title(0.2) + 0.1);
+        let light = pooled(0.0);
+        assert!(cosine(light.vector.as_ref().unwrap(), &embedder.embed(body)) > 0.999);
+        // The blank tags field is skipped
+        assert_eq!(light.fields.keys().collect::<Vec<_>>(), ["body", "title"]);
+        
+        let per_field = embed_fields(&embedder, &record(1.0), &FieldStrategy::PerField).unwrap();
+        assert_eq!((per_field.vector, &per_field.fields["title"]), (None, &title_vector));
+        let joined = embed_fields(&embedder, &record(1.0), &FieldStrategy::Concatenate { separator: "\n".to_string() }).unwrap();
+        assert_eq!(joined.vector.unwrap(), embedder.embed(&format!("{}\n{}", title, body)));
+        assert!(joined.fields.is_empty());
+        
+        assert!(embed_fields(&embedder, &[("tags", "", 1.0)], &FieldStrategy::WeightedPool).is_err());
+        assert!(embed_fields(&embedder, &[("title", title, -1.0)], &FieldStrategy::WeightedPool).is_err());
+        assert!(embed_fields(&embedder, &[("title", title, 0.0)], &FieldStrategy::WeightedPool).is_err());
+    }
+    
+    #[test]
+    fn test_context_blend() {
+        let s = std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(blend(&[1.0, 0.0], &[0.0, 1.0], 0.5), vec![s, s]);
+        assert_eq!(blend(&[1.0, 0.0], &[0.0, 1.0], 0.0), vec![0.0, 1.0]);
+        
+        let client = JinaClient::new("test_key");
+        let options = |alpha| DocumentOptions::default().with_max_chunk_size(60).with_pooling(Pooling::ContextBlend { alpha });
+        let plain = client.embed_document(DOC, &DocumentOptions::default().with_max_chunk_size(60)).unwrap();
+        
+        let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-6);
+        
+        // alpha = 1 reproduces the plain chunk embeddings
+        let one = client.embed_document(DOC, &options(1.0)).unwrap();
+        assert!(one.chunk_vectors.iter().zip(&plain.chunk_vectors).all(|(b, p)| close(b, p)));
+        
+        let blended = client.embed_document(DOC, &options(0.7)).unwrap();
+        let parts = blended.parts.as_ref().unwrap();
+        assert_eq!(parts.chunks, plain.chunk_vectors);
+        assert!(close(&parts.document, &client.embed_batch_full(&[DOC], &EmbedOptions::passage()).unwrap().embeddings[0]));
+        assert_eq!(blended.vector, parts.document);
+        assert_eq!(blended.chunk_vectors[1], blend(&parts.chunks[1], &parts.document, 0.7));
+        // Blending pulls every chunk toward the document
+        for (b, p) in blended.chunk_vectors.iter().zip(&parts.chunks) {
+            assert!(cosine(b, &parts.document) > cosine(p, &parts.document));
+        }
+        assert!(client.embed_document(DOC, &options(1.5)).is_err());
+    }
+    
+    #[test]
+    fn test_embed_document_late_chunks_online() {
+        let seen: Arc<Mutex<Vec<HttpRequest>>> = Arc::default();
+        let log = seen.clone();
+        let client = JinaClient::new("jina_test")
+            .with_retry(RetryPolicy::none())
+            .with_transport(move |request: &HttpRequest| {
+                log.lock().unwrap().push(request.clone());
+                let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                let data: Vec<serde_json::Value> = (0..body["input"].as_array().unwrap().len())
+                    .map(|i| serde_json::json!({ "index": i, "embedding": vec![i as f32 + 1.0; 1024] }))
+                    .collect();
+                let body = serde_json::json!({ "data": data, "usage": { "total_tokens": 30, "prompt_tokens": 30 } });
+                Ok(HttpResponse { status: 200, headers: Vec::new(), body: body.to_string() })
+            })
+            .with_max_batch_size(1);
+        
+        let doc = client.embed_document(DOC, &DocumentOptions::default().with_max_chunk_size(60)).unwrap();
+        assert_eq!(doc.usage, Usage { prompt_tokens: 30, total_tokens: 30 });
+        // Late chunking sends the whole document in one request despite max_batch_size
+        let requests = seen.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body["late_chunking"], true);
+        assert_eq!(body["task"], "retrieval.passage");
+        assert_eq!(body["input"].as_array().unwrap().len(), 3);
+        drop(requests);
+        
+        // Without late chunking, usage adds up over the split batches
+        let full = client.embed_batch_full(&["a", "b"], &EmbedOptions::passage()).unwrap();
+        assert_eq!(full.usage.total_tokens, 60);
+        assert!(JinaClient::new("test_key").embed_batch_full(&["a"], &EmbedOptions::passage().with_late_chunking()).is_err());
+    }
+    
+    #[test]
+    fn test_long_inputs_chunk_and_pool() {
+        let client = JinaClient::new("test_key");
+        let long = DOC.repeat(12);
+        let strategy = LongInputStrategy::ChunkAndPool { chunker: Chunking::Local, pooling: Pooling::MeanWeighted };
+        let options = EmbedOptions::passage().with_max_input_tokens(60).with_long_inputs(strategy);
+        let response = client.embed_batch_full(&["short", &long, "also short"], &options).unwrap();
+        assert_eq!(response.pooled, [1]);
+        
+        // The same as chunking and pooling by hand, at half of 4 * 60 characters
+        let fits = |chunks: &[Chunk]| chunks.iter().all(|c| client.count_tokens(&c.text) <= 60);
+        assert!(!fits(&Chunking::Local.chunk_local(&long, 240)));
+        let chunks = Chunking::Local.chunk_local(&long, 120);
+        assert!(chunks.len() > 1 && fits(&chunks));
+        let texts: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+        let vectors = client.embed_batch_full(&texts, &EmbedOptions::passage()).unwrap().embeddings;
+        let weights: Vec<f32> = texts.iter().map(|t| client.count_tokens(t) as f32).collect();
+        assert_eq!(response.embeddings[1], pool(&vectors, &weights, Pooling::MeanWeighted));
+        let plain = client.embed_batch_full(&["short", "also short"], &EmbedOptions::passage()).unwrap().embeddings;
+        assert_eq!((&response.embeddings[0], &response.embeddings[2]), (&plain[0], &plain[1]));
+        
+        // By default the long input is cut instead
+        let cut = client.embed_batch_full(&[&long], &EmbedOptions::passage().with_max_input_tokens(60)).unwrap();
+        assert!(cut.pooled.is_empty());
+        let truncated = crate::chunk::truncate_to_budget(&long, 60, &crate::tokens::Approximate).text.to_string();
+        assert_eq!(cut.embeddings[0], client.embed_batch_full(&[&truncated], &EmbedOptions::passage()).unwrap().embeddings[0]);
+        let blend = LongInputStrategy::ChunkAndPool { chunker: Chunking::Local, pooling: Pooling::ContextBlend { alpha: 0.5 } };
+        assert!(client.embed_batch_full(&[&long], &options.clone().with_long_inputs(blend)).is_err());
+    }
+    
+    #[test]
+    fn test_long_inputs_checked_against_the_model_window() {
+        let client = JinaClient::new("test_key").with_context_limit("jina-embeddings-v3", 60);
+        assert_eq!((client.context_limit(), JinaClient::new("").context_limit()), (Some(60), Some(CONTEXT_TOKENS)));
+        let long = DOC.repeat(12);
+        let estimated = client.count_tokens(&long);
+        let texts = ["short", long.as_str()];
+        let with = |strategy| EmbedOptions::passage().with_long_inputs(strategy);
+        
+        let error = client.embed_batch_full(&texts, &with(LongInputStrategy::Reject)).unwrap_err();
+        assert_eq!(error, JinaError::InputTooLong { index: 1, estimated, limit: 60 });
+        assert!(error.to_string().contains("over the model's 60 token context"), "{}", error);
+        // `max_input_tokens` is the budget where set
+        let error = client.embed_batch_full(&["short"], &with(LongInputStrategy::Reject).with_max_input_tokens(1)).unwrap_err();
+        assert!(matches!(error, JinaError::InputTooLong { index: 0, limit: 1, .. }));
+        
+        let truncated = crate::chunk::truncate_to_budget(&long, 60, &crate::tokens::Approximate).text.to_string();
+        let cut = client.embed_batch_full(&texts, &with(LongInputStrategy::Truncate)).unwrap();
+        assert_eq!(cut.embeddings[1], client.embed_batch_full(&[&truncated], &EmbedOptions::passage()).unwrap().embeddings[0]);
+        let strategy = LongInputStrategy::ChunkAndPool { chunker: Chunking::Local, pooling: Pooling::MeanWeighted };
+        assert_eq!(client.embed_batch_full(&texts, &with(strategy)).unwrap().pooled, [1]);
+        // Explicit sends it whole
+        let whole = client.embed_batch_full(&texts, &EmbedOptions::passage()).unwrap();
+        assert_eq!(whole.embeddings[1], client.embed_batch_full(&[&long], &EmbedOptions::passage()).unwrap().embeddings[0]);
+        assert_ne!(whole.embeddings[1], cut.embeddings[1]);
+        
+        // An unknown model is not checked, unless given a fallback
+        let unknown = JinaClient::new("test_key").with_model("acme/embed-large");
+        assert_eq!(unknown.context_limit(), None);
+        assert!(unknown.embed_batch_full(&texts, &with(LongInputStrategy::Reject)).is_ok());
+        let unknown = unknown.with_context_limits(crate::tokens::ContextLimits::new().with_fallback(60));
+        assert!(matches!(unknown.embed_batch_full(&texts, &with(LongInputStrategy::Reject)), Err(JinaError::InputTooLong { .. })));
+        let renamed = JinaClient::new("").with_model("jinaai/jina-clip-v1");
+        assert_eq!(renamed.context_limit(), Some(77));
+    }
+}
@@ -0,0 +1,161 @@
+//! How much embeddings change between two models or option sets
+//!
+//! `compare` embeds one sample of texts under both providers. When the
+//! vectors have the same size, each text gets the cosine between its two
+//! vectors; that is only meaningful when the spaces are related (the same
+//! model at another truncation, say), and two unrelated models score near
+//! 0 however similar their neighborhoods. The neighborhood metric works at
+//! any sizes: each text's `k` nearest other texts within the sample under
+//! either provider, and the share of those that stay the same.
+
+use crate::provider::{EmbedError, EmbeddingProvider};
+use crate::search::{cosine, top_k_batch};
+
+/// One text's drift
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TextDrift {
+    pub text: String,
+    /// Cosine between its two vectors; `None` when the sizes differ
+    #[serde(default)]
+    pub cosine_between_versions: Option<f32>,
+    /// Share of its top-k neighbors common to both versions, 0 to 1
+    pub topk_overlap: f32,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DriftReport {
+    /// Neighbors compared per text: the `k` asked for, at most the sample size less one
+    pub k: usize,
+    pub dimensions_a: usize,
+    pub dimensions_b: usize,
+    /// `None` when the sizes differ
+    #[serde(default)]
+    pub mean_cosine_between_versions: Option<f32>,
+    pub topk_overlap_at_k: f32,
+    pub per_text: Vec<TextDrift>,
+}
+
+impl DriftReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("drift reports always serialize")
+    }
+    
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("Cannot parse drift report: {}", e))
+    }
+}
+
+/// Embed `sample` under both providers and measure the drift at `k` neighbors
+///
+/// Needs at least two texts and `k` of at least 1.
+pub fn compare<A, B>(provider_a: &A, provider_b: &B, sample: &[&str], k: usize) -> Result<DriftReport, EmbedError>
+    where A: EmbeddingProvider + ?Sized, B: EmbeddingProvider + ?Sized {
+    if sample.len() < 2 || k == 0 {
+        return Err(EmbedError::InvalidInput(format!("Drift needs k >= 1 and two or more texts, got k = {} and {}",
+                                                    k, sample.len())));
+    }
+    let (a, b) = (embed_sample(provider_a, sample)?, embed_sample(provider_b, sample)?);
+    let (dimensions_a, dimensions_b) = (a[0].len(), b[0].len());
+    let k = k.min(sample.len() - 1);
+    
+    let neighbors_a = neighbors(&a, k);
+    let neighbors_b = neighbors(&b, k);
+    let per_text: Vec<TextDrift> = sample.iter().enumerate()
+        .map(|(i, text)| {
+            let common = neighbors_a[i].iter().filter(|n| neighbors_b[i].contains(n)).count();
+            TextDrift {
+                text: text.to_string(),
+                cosine_between_versions: (dimensions_a == dimensions_b).then(|| cosine(&a[i], &b[i])),
+                topk_overlap: common as f32 / k as f32,
+            }
+        })
+        .collect();
+    let mean = |values: Vec<f32>| values.iter().sum::<f32>() / values.len() as f32;
+    Ok(DriftReport {
+        k,
+        dimensions_a,
+        dimensions_b,
+        mean_cosine_between_versions: (dimensions_a == dimensions_b)
+            .then(|| mean(per_text.iter().filter_map(|t| t.cosine_between_versions).collect())),
+        topk_overlap_at_k: mean(per_text.iter().map(|t| t.topk_overlap).collect()),
+        per_text,
+    })
+}
+
+fn embed_sample<P: EmbeddingProvider + ?Sized>(provider: &P, sample: &[&str]) -> Result<Vec<Vec<f32>>, EmbedError> {
+    let embeddings = provider.embed_batch(sample)?;
+    if embeddings.len() != sample.len() {
+        return Err(EmbedError::Mismatch { expected: sample.len(), got: embeddings.len() });
+    }
+    Ok(embeddings)
+}
+
+/// Each row's `k` nearest other rows
+fn neighbors(embeddings: &[Vec<f32>], k: usize) -> Vec<Vec<usize>> {
+    top_k_batch(embeddings, embeddings, k + 1).into_iter()
+        .enumerate()
+        .map(|(i, hits)| hits.into_iter().map(|(j, _)| j).filter(|&j| j != i).take(k).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pseudo::PseudoEmbedder;
+    
+    const SAMPLE: [&str; 6] = [
+        "the cat sat on the mat",
+        "a cat sat on a mat",
+        "dogs bark at night",
+        "the dog barked all night",
+        "stock prices fell sharply",
+        "share prices dropped sharply",
+    ];
+    
+    #[test]
+    fn test_report_math_for_reseeded_embedders() {
+        let a = PseudoEmbedder::new(256);
+        let same = compare(&a, &a, &SAMPLE, 2).unwrap();
+        assert_eq!(same.topk_overlap_at_k, 1.0);
+        assert!((same.mean_cosine_between_versions.unwrap() - 1.0).abs() < 1e-5);
+        
+        let b = PseudoEmbedder::new(256).with_seed(7);
+        let report = compare(&a, &b, &SAMPLE, 2).unwrap();
+        let (va, vb) = (a.embed_batch(&SAMPLE), b.embed_batch(&SAMPLE));
+        for (i, drift) in report.per_text.iter().enumerate() {
+            assert_eq!(drift.text, SAMPLE[i]);
+            assert!((drift.cosine_between_versions.unwrap() - cosine(&va[i], &vb[i])).abs() < 1e-6);
+            // Brute force: rank the other texts by cosine under each version
+            let nearest = |v: &[Vec<f32>]| -> Vec<usize> {
+                let mut others: Vec<usize> = (0..SAMPLE.len()).filter(|&j| j != i).collect();
+                others.sort_by(|&x, &y| cosine(&v[i], &v[y]).total_cmp(&cosine(&v[i], &v[x])).then(x.cmp(&y)));
+                others.truncate(2);
+                others
+            };
+            let (na, nb) = (nearest(&va), nearest(&vb));
+            let common = na.iter().filter(|n| nb.contains(n)).count();
+            assert_eq!(drift.topk_overlap, common as f32 / 2.0, "{}", SAMPLE[i]);
+        }
+        let mean = report.per_text.iter().map(|t| t.topk_overlap).sum::<f32>() / SAMPLE.len() as f32;
+        assert!((report.topk_overlap_at_k - mean).abs() < 1e-6);
+        // Unrelated hash spaces: vectors differ, paraphrase neighborhoods mostly survive
+        assert!(report.mean_cosine_between_versions.unwrap().abs() < 0.5);
+        assert!(report.topk_overlap_at_k >= 0.5, "{:?}", report);
+        assert_eq!(DriftReport::from_json(&report.to_json()).unwrap(), report);
+    }
+    
+    #[test]
+    fn test_mismatched_dimensions_skip_cosine() {
+        let report = compare(&PseudoEmbedder::new(64), &PseudoEmbedder::new(128), &SAMPLE, 10).unwrap();
+        assert_eq!((report.dimensions_a, report.dimensions_b, report.k), (64, 128, SAMPLE.len() - 1));
+        assert_eq!(report.mean_cosine_between_versions, None);
+        assert!(report.per_text.iter().all(|t| t.cosine_between_versions.is_none()));
+        // Every other text is a neighbor at k = n - 1, so nothing can drift
+        assert_eq!(report.topk_overlap_at_k, 1.0);
+        assert!(report.to_json().contains("\"mean_cosine_between_versions\": null"));
+        
+        let a = PseudoEmbedder::new(8);
+        assert!(matches!(compare(&a, &a, &SAMPLE[..1], 2), Err(EmbedError::InvalidInput(_))));
+        assert!(matches!(compare(&a, &a, &SAMPLE, 0), Err(EmbedError::InvalidInput(_))));
+    }
+}
@@ -0,0 +1,359 @@
+//! Embedding matrices in f32 or f64 and their binary container
+//!
+//! The container is a 21-byte header — magic `SPOEMB`, version, a dtype
+//! byte (0 = f32, 1 = f64), dims (u32) and count (u64), all little endian —
+//! and the header's CRC32, followed by `count * dims` components in blocks
+//! of `BLOCK_ROWS` vectors, each block followed by its CRC32. The dtype
+//! byte keeps f64 files from being read back as twice as many f32 values;
+//! the checksums catch truncated copies and flipped bits, block by block,
+//! so `load_verified` can skip a damaged block and `io::verify` can name
+//! the vectors it held. Version 01 files, without checksums, still load.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::ops::Range;
+
+use crate::io::{atomic_write, crc32, VerifyMode, VerifyReport};
+
+pub(crate) const MAGIC: &[u8; 6] = b"SPOEMB";
+const VERSION: &[u8; 2] = b"02";
+/// Before checksums
+const UNCHECKSUMMED_VERSION: &[u8; 2] = b"01";
+const HEADER_LEN: usize = 21;
+/// Vectors per checksummed block
+pub const BLOCK_ROWS: usize = 1024;
+
+/// Component type of stored vectors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Dtype {
+    F32 = 0,
+    F64 = 1,
+}
+
+impl Dtype {
+    /// Bytes per component
+    pub fn size(&self) -> usize {
+        match self {
+            Dtype::F32 => 4,
+            Dtype::F64 => 8,
+        }
+    }
+}
+
+/// Widen to f64; exact
+pub fn to_f64(v: &[f32]) -> Vec<f64> {
+    v.iter().map(|&x| x as f64).collect()
+}
+
+/// Narrow to f32, rounding to nearest
+pub fn to_f32(v: &[f64]) -> Vec<f32> {
+    v.iter().map(|&x| x as f32).collect()
+}
+
+/// Vectors of one dtype, all of the same length
+#[derive(Clone, Debug, PartialEq)]
+pub enum Embeddings {
+    F32(Vec<Vec<f32>>),
+    F64(Vec<Vec<f64>>),
+}
+
+impl Embeddings {
+    pub fn dtype(&self) -> Dtype {
+        match self {
+            Embeddings::F32(_) => Dtype::F32,
+            Embeddings::F64(_) => Dtype::F64,
+        }
+    }
+    
+    pub fn len(&self) -> usize {
+        match self {
+            Embeddings::F32(vs) => vs.len(),
+            Embeddings::F64(vs) => vs.len(),
+        }
+    }
+    
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+    
+    /// Components per vector; 0 when empty
+    pub fn dims(&self) -> usize {
+        match self {
+            Embeddings::F32(vs) => vs.first().map_or(0, Vec::len),
+            Embeddings::F64(vs) => vs.first().map_or(0, Vec::len),
+        }
+    }
+    
+    /// The vectors in f32, narrowing f64 ones
+    pub fn into_f32(self) -> Vec<Vec<f32>> {
+        match self {
+            Embeddings::F32(vs) => vs,
+            Embeddings::F64(vs) => vs.iter().map(|v| to_f32(v)).collect(),
+        }
+    }
+    
+    /// The vectors in f64, widening f32 ones
+    pub fn into_f64(self) -> Vec<Vec<f64>> {
+        match self {
+            Embeddings::F32(vs) => vs.iter().map(|v| to_f64(v)).collect(),
+            Embeddings::F64(vs) => vs,
+        }
+    }
+    
+    /// Vector `i` as little-endian bytes
+    fn row_bytes(&self, i: usize) -> Vec<u8> {
+        match self {
+            Embeddings::F32(vs) => vs[i].iter().flat_map(|x| x.to_le_bytes()).collect(),
+            Embeddings::F64(vs) => vs[i].iter().flat_map(|x| x.to_le_bytes()).collect(),
+        }
+    }
+    
+    /// Write the container, replacing the file; checksums are computed as
+    /// the blocks are written
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let dims = self.dims();
+        let lens: Vec<usize> = match self {
+            Embeddings::F32(vs) => vs.iter().map(Vec::len).collect(),
+            Embeddings::F64(vs) => vs.iter().map(Vec::len).collect(),
+        };
+        lens.iter().try_for_each(|&len| check_dims(len, dims))?;
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(VERSION);
+        header.push(self.dtype() as u8);
+        header.extend_from_slice(&(dims as u32).to_le_bytes());
+        header.extend_from_slice(&(self.len() as u64).to_le_bytes());
+        
+        atomic_write(path, |file| {
+            let mut out = BufWriter::new(file);
+            out.write_all(&header)?;
+            out.write_all(&crc32(&header).to_le_bytes())?;
+            let mut block = flate2::Crc::new();
+            for i in 0..self.len() {
+                let bytes = self.row_bytes(i);
+                out.write_all(&bytes)?;
+                block.update(&bytes);
+                if (i + 1) % BLOCK_ROWS == 0 || i + 1 == self.len() {
+                    out.write_all(&block.sum().to_le_bytes())?;
+                    block.reset();
+                }
+            }
+            out.flush()
+        }).map_err(|e| format!("Write failed for {}: {}", path, e))
+    }
+    
+    /// Read a container in the dtype it was written with; any damage is an error
+    pub fn load(path: &str) -> Result<Self, String> {
+        Self::load_verified(path, VerifyMode::Strict).map(|(embeddings, _)| embeddings)
+    }
+    
+    /// Read a container, treating blocks whose checksum fails as `mode` says;
+    /// the report lists the vectors skipped
+    pub fn load_verified(path: &str, mode: VerifyMode) -> Result<(Self, VerifyReport), String> {
+        let file = File::open(path).map_err(|e| format!("Cannot open {}: {}", path, e))?;
+        let mut reader = BufReader::new(file);
+        let header = read_header(&mut reader, mode).map_err(|e| format!("{}: {}", e, path))?;
+        let mut report = VerifyReport { records: header.count, checksummed: header.checksummed, ..VerifyReport::default() };
+        let mut embeddings = match header.dtype {
+            Dtype::F32 => Embeddings::F32(Vec::new()),
+            Dtype::F64 => Embeddings::F64(Vec::new()),
+        };
+        let row = header.dims * header.dtype.size();
+        scan_blocks(&mut reader, &header, mode != VerifyMode::Off, |rows, block| {
+            let Some(block) = block else {
+                if mode == VerifyMode::WarnAndSkip {
+                    report.mark(rows);
+                    return Ok(());
+                }
+                return Err(format!("Vectors {}..{} are damaged or missing", rows.start, rows.end));
+            };
+            let split = (0..rows.len()).map(|i| &block[i * row..(i + 1) * row]);
+            match &mut embeddings {
+                Embeddings::F32(vs) => vs.extend(split.map(|r| {
+                    r.chunks_exact(4).map(|c| f32::from_le_bytes(c.try_into().unwrap())).collect()
+                })),
+                Embeddings::F64(vs) => vs.extend(split.map(|r| {
+                    r.chunks_exact(8).map(|c| f64::from_le_bytes(c.try_into().unwrap())).collect()
+                })),
+            }
+            Ok(())
+        }).map_err(|e| format!("{}: {}", e, path))?;
+        Ok((embeddings, report))
+    }
+}
+
+fn check_dims(got: usize, dims: usize) -> Result<(), String> {
+    if got == dims { Ok(()) } else { Err(format!("Vector has {} dims, expected {}", got, dims)) }
+}
+
+struct Header {
+    dtype: Dtype,
+    dims: usize,
+    count: usize,
+    checksummed: bool,
+}
+
+/// Parse the header, checking its checksum unless `mode` is `Off`
+fn read_header(reader: &mut impl Read, mode: VerifyMode) -> Result<Header, String> {
+    let mut fixed = [0u8; HEADER_LEN];
+    if reader.read_exact(&mut fixed).is_err() || &fixed[..6] != MAGIC {
+        return Err("Not an embeddings file".to_string());
+    }
+    let checksummed = match &fixed[6..8] {
+        version if version == VERSION => true,
+        version if version == UNCHECKSUMMED_VERSION => false,
+        version => return Err(format!("Unsupported embeddings format version {}", String::from_utf8_lossy(version))),
+    };
+    if checksummed {
+        let mut stored = [0u8; 4];
+        reader.read_exact(&mut stored).map_err(|_| "Truncated header".to_string())?;
+        if mode != VerifyMode::Off && u32::from_le_bytes(stored) != crc32(&fixed) {
+            return Err("Header checksum mismatch: dtype, dims or count are damaged".to_string());
+        }
+    }
+    let dtype = match fixed[8] {
+        0 => Dtype::F32,
+        1 => Dtype::F64,
+        other => return Err(format!("Unknown dtype {}", other)),
+    };
+    let dims = u32::from_le_bytes(fixed[9..13].try_into().unwrap()) as usize;
+    let count = u64::from_le_bytes(fixed[13..21].try_into().unwrap()) as usize;
+    if count.checked_mul(dims).and_then(|n| n.checked_mul(dtype.size())).is_none() {
+        return Err(format!("Header claims {} vectors of {} dims, more than fit in memory", count, dims));
+    }
+    Ok(Header { dtype, dims, count, checksummed })
+}
+
+/// Read the blocks after the header in order, handing `visit` each block's
+/// vector range and bytes, or `None` when the file ends inside the block or
+/// (with `check`) its checksum fails. Bytes after the last block are an error.
+fn scan_blocks(reader: &mut impl Read, header: &Header, check: bool,
+               mut visit: impl FnMut(Range<usize>, Option<&[u8]>) -> Result<(), String>) -> Result<(), String> {
+    let row = header.dims * header.dtype.size();
+    let trailer = if header.checksummed { 4 } else { 0 };
+    let mut buf = Vec::new();
+    let mut truncated = false;
+    for start in (0..header.count).step_by(BLOCK_ROWS) {
+        let rows = start..(start + BLOCK_ROWS).min(header.count);
+        if truncated {
+            visit(rows, None)?;
+            continue;
+        }
+        buf.resize(rows.len() * row + trailer, 0);
+        if reader.read_exact(&mut buf).is_err() {
+            truncated = true;
+            visit(rows, None)?;
+            continue;
+        }
+        let (data, stored) = buf.split_at(rows.len() * row);
+        let intact = !check || !header.checksummed || u32::from_le_bytes(stored.try_into().unwrap()) == crc32(data);
+        visit(rows, intact.then_some(data))?;
+    }
+    let mut extra = Vec::new();
+    reader.read_to_end(&mut extra).map_err(|e| format!("Read failed: {}", e))?;
+    if !truncated && !extra.is_empty() {
+        return Err(format!("Expected {} {:?} vectors of {} dims, found {} more bytes", header.count, header.dtype, header.dims, extra.len()));
+    }
+    Ok(())
+}
+
+/// `io::verify` of a container: every block read and checked, one at a time
+pub(crate) fn verify_container(reader: &mut impl Read) -> Result<VerifyReport, String> {
+    let header = read_header(reader, VerifyMode::Strict)?;
+    let mut report = VerifyReport { records: header.count, checksummed: header.checksummed, ..VerifyReport::default() };
+    scan_blocks(reader, &header, true, |rows, block| {
+        if block.is_none() {
+            report.mark(rows);
+        }
+        Ok(())
+    })?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn path(dir: &tempfile::TempDir, name: &str) -> String { dir.path().join(name).to_str().unwrap().to_string() }
+    
+    #[test]
+    fn test_round_trips_both_dtypes() {
+        let dir = tempfile::tempdir().unwrap();
+        let f32s = Embeddings::F32(vec![vec![0.1, -2.5, 3.0], vec![1e-7, 0.0, -0.0]]);
+        let f64s = Embeddings::F64(vec![vec![0.1, std::f64::consts::PI, -1e-300], vec![1.0 / 3.0, 0.0, 2.0]]);
+        for (name, embeddings) in [("f32.emb", &f32s), ("f64.emb", &f64s)] {
+            embeddings.save(&path(&dir, name)).unwrap();
+            let loaded = Embeddings::load(&path(&dir, name)).unwrap();
+            assert_eq!(&loaded, embeddings);
+            assert_eq!((loaded.dtype(), loaded.len(), loaded.dims()), (embeddings.dtype(), 2, 3));
+        }
+        
+        // Empty matrices and zero-length vectors survive too
+        for embeddings in [Embeddings::F64(vec![]), Embeddings::F32(vec![vec![], vec![]])] {
+            embeddings.save(&path(&dir, "empty.emb")).unwrap();
+            assert_eq!(Embeddings::load(&path(&dir, "empty.emb")).unwrap(), embeddings);
+        }
+    }
+    
+    #[test]
+    fn test_dtype_flag_guards_against_misreads() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = path(&dir, "v.emb");
+        Embeddings::F64(vec![vec![0.5, 0.25]]).save(&file).unwrap();
+        let mut bytes = std::fs::read(&file).unwrap();
+        assert_eq!(bytes[8], Dtype::F64 as u8);
+        
+        // Relabelled as f32, the header checksum fails; past it, the data is
+        // the wrong size rather than four garbage values
+        bytes[8] = Dtype::F32 as u8;
+        std::fs::write(&file, &bytes).unwrap();
+        assert!(Embeddings::load(&file).unwrap_err().contains("Header checksum mismatch"));
+        let err = Embeddings::load_verified(&file, VerifyMode::Off).unwrap_err();
+        assert!(err.contains("Expected 1 F32 vectors of 2 dims, found 8 more bytes"), "{}", err);
+        bytes[8] = 7;
+        std::fs::write(&file, &bytes).unwrap();
+        assert!(Embeddings::load_verified(&file, VerifyMode::Off).unwrap_err().contains("Unknown dtype 7"));
+        
+        assert!(Embeddings::F32(vec![vec![1.0], vec![1.0, 2.0]]).save(&file).is_err());
+    }
+    
+    #[test]
+    fn test_damaged_blocks_detected_skipped_and_located() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = path(&dir, "blocks.emb");
+        let vectors: Vec<Vec<f32>> = (0..2500).map(|i| vec![i as f32, 1.0]).collect();
+        Embeddings::F32(vectors.clone()).save(&file).unwrap();
+        let clean = std::fs::read(&file).unwrap();
+        let block = BLOCK_ROWS * 8 + 4;
+        assert_eq!(clean.len(), HEADER_LEN + 4 + 2 * block + 452 * 8 + 4);
+        assert!(crate::io::verify(&file).unwrap().is_ok());
+        
+        // One flipped bit in the second block's data
+        let mut bytes = clean.clone();
+        bytes[HEADER_LEN + 4 + block + 100] ^= 0x10;
+        std::fs::write(&file, &bytes).unwrap();
+        assert!(Embeddings::load(&file).unwrap_err().contains("Vectors 1024..2048 are damaged"));
+        let (loaded, report) = Embeddings::load_verified(&file, VerifyMode::WarnAndSkip).unwrap();
+        assert_eq!((loaded.len(), report.damaged_records()), (1476, 1024));
+        assert_eq!(format!("{:?}", report.damaged), "[1024..2048]");
+        let Embeddings::F32(loaded) = loaded else { unreachable!() };
+        assert_eq!(loaded[1024], vectors[2048]);
+        assert_eq!(format!("{:?}", crate::io::verify(&file).unwrap().damaged), "[1024..2048]");
+        // Off reads the flipped value as it is
+        assert_eq!(Embeddings::load_verified(&file, VerifyMode::Off).unwrap().0.len(), 2500);
+        
+        // A truncated copy loses the blocks it cuts into, even with checks off
+        std::fs::write(&file, &clean[..HEADER_LEN + 4 + block + 10]).unwrap();
+        assert_eq!(format!("{:?}", crate::io::verify(&file).unwrap().damaged), "[1024..2500]");
+        assert!(Embeddings::load_verified(&file, VerifyMode::Off).unwrap_err().contains("Vectors 1024..2048"));
+    }
+    
+    #[test]
+    fn test_conversions() {
+        let v = [0.1f32, -3.75, 1e-30];
+        assert_eq!(to_f32(&to_f64(&v)), v);
+        assert_eq!(to_f64(&v)[1], -3.75);
+        let wide = vec![vec![0.1f64, 0.2]];
+        assert_eq!(Embeddings::F64(wide.clone()).into_f32(), [to_f32(&wide[0])]);
+        assert_eq!(Embeddings::F32(vec![v.to_vec()]).into_f64(), [to_f64(&v)]);
+    }
+}
@@ -0,0 +1,222 @@
+//! Typed errors for embedding backends
+
+use std::fmt;
+use std::sync::RwLock;
+
+use crate::provenance::Provenance;
+use crate::transport::{Diagnostics, REDACTED};
+
+/// Error from an embedding request or backend
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum JinaError {
+    /// Rejected before sending: bad arguments or options
+    InvalidInput(String),
+    /// No connection could be made (refused, unreachable, DNS)
+    Connect(String),
+    /// Connection-level failure after connecting (TLS, reset, timeout, process spawn)
+    Transport(String),
+    /// Backend answered with an error status
+    Api { status: u16, message: String },
+    /// Response body could not be parsed
+    Parse(String),
+    /// Input `index` is `size` bytes, over the backend's `limit` (`None` when the backend did not say)
+    InputTooLarge { index: usize, size: usize, limit: Option<usize> },
+    /// Input `index` counts `estimated` tokens, over the model's context `limit`; never sent
+    InputTooLong { index: usize, estimated: usize, limit: usize },
+    /// Backend returned vectors of the wrong count or size
+    Mismatch { expected: usize, got: usize },
+    /// A named backend of a composite provider failed
+    Route { route: String, source: Box<JinaError> },
+    /// The call's `CallOptions::deadline` passed before it was done
+    DeadlineExceeded,
+    /// Error from code that still reports plain strings
+    Other(String),
+}
+
+/// Error type of `EmbeddingProvider`
+pub type EmbedError = JinaError;
+
+impl fmt::Display for JinaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JinaError::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
+            JinaError::Connect(msg) => write!(f, "Connection failed: {}", msg),
+            JinaError::Transport(msg) => write!(f, "Transport error: {}", msg),
+            JinaError::Api { status, message } => write!(f, "API error {}: {}", status, message),
+            JinaError::Parse(msg) => write!(f, "Parse error: {}", msg),
+            JinaError::InputTooLarge { index, size, limit: None } => write!(f, "Input {} is {} bytes, over the server's limit", index, size),
+            JinaError::InputTooLarge { index, size, limit: Some(limit) } => {
+                write!(f, "Input {} is {} bytes, over the {} byte limit", index, size, limit)
+            }
+            JinaError::InputTooLong { index, estimated, limit } => {
+                write!(f, "Input {} is about {} tokens, over the model's {} token context", index, estimated, limit)
+            }
+            JinaError::Mismatch { expected, got } => write!(f, "Response size mismatch: expected {}, got {}", expected, got),
+            JinaError::Route { route, source } => write!(f, "Route {}: {}", route, source),
+            JinaError::DeadlineExceeded => write!(f, "Deadline exceeded"),
+            JinaError::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for JinaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            JinaError::Route { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<String> for JinaError {
+    fn from(msg: String) -> Self { JinaError::Other(msg) }
+}
+
+impl From<JinaError> for String {
+    fn from(e: JinaError) -> Self { e.to_string() }
+}
+
+/// Failed call with what it cost before failing
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DiagnosedError {
+    pub error: JinaError,
+    pub diagnostics: Box<Diagnostics>,
+}
+
+impl fmt::Display for DiagnosedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (after {} attempts, {:.0} ms)", self.error, self.diagnostics.attempts, self.diagnostics.total_ms)
+    }
+}
+
+impl std::error::Error for DiagnosedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { Some(&self.error) }
+}
+
+impl From<DiagnosedError> for JinaError {
+    fn from(e: DiagnosedError) -> Self { e.error }
+}
+
+/// Why one input of `JinaClient::embed_batch_partial` has no embedding
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum ItemError {
+    /// Blank after preprocessing; never sent
+    Empty,
+    /// Input of `size` bytes, refused as too large even on its own
+    TooLarge { size: usize },
+    /// Named by the server in a validation error for its batch
+    Rejected { status: u16, message: String },
+    /// A `data` entry that is `null` or an `error` object, with the gateway's message
+    Failed { message: String },
+    /// Not embedded before the call's deadline
+    DeadlineExceeded,
+}
+
+/// One input's embedding, or why it has none
+pub type ItemResult = Result<Vec<f32>, ItemError>;
+
+impl fmt::Display for ItemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ItemError::Empty => write!(f, "Input is empty after preprocessing"),
+            ItemError::TooLarge { size } => write!(f, "Input of {} bytes is too large for the backend", size),
+            ItemError::Rejected { status, message } => write!(f, "Input rejected ({}): {}", status, message),
+            ItemError::Failed { message } => write!(f, "Input failed at the backend: {}", message),
+            ItemError::DeadlineExceeded => write!(f, "Input not embedded before the deadline"),
+        }
+    }
+}
+
+impl std::error::Error for ItemError {}
+
+/// Vectors embedded differently from the ones an index holds
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProvenanceMismatch {
+    /// The index's provenance
+    pub expected: Box<Provenance>,
+    /// The vectors' provenance; `None` when unknown
+    pub found: Option<Box<Provenance>>,
+}
+
+impl fmt::Display for ProvenanceMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.found {
+            Some(found) if found.synthetic != self.expected.synthetic => {
+                let kind = |synthetic: bool| if synthetic { "synthetic" } else { "real" };
+                write!(f, "Provenance mismatch: index holds {} vectors ({}), vectors are {} ({}); refusing to mix them",
+                       kind(self.expected.synthetic), self.expected, kind(found.synthetic), found)
+            }
+            Some(found) => write!(f, "Provenance mismatch: index holds {}, vectors are {}", self.expected, found),
+            None => write!(f, "Provenance mismatch: index holds {}, vectors have no provenance", self.expected),
+        }
+    }
+}
+
+impl std::error::Error for ProvenanceMismatch {}
+
+/// Longest server or subprocess text kept in an error message, in chars
+pub const MAX_DISPLAY_CHARS: usize = 200;
+
+/// Keys shorter than this are too likely to occur in ordinary text to mask
+const MIN_SECRET_LEN: usize = 8;
+
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Mask `secret` in every message `truncate_for_display` builds from now on.
+///
+/// Clients register their API keys when given them; keys under 8 bytes are
+/// ignored.
+pub fn register_secret(secret: &str) {
+    if secret.len() < MIN_SECRET_LEN {
+        return;
+    }
+    let mut secrets = SECRETS.write().unwrap_or_else(|e| e.into_inner());
+    if !secrets.iter().any(|s| s == secret) {
+        secrets.push(secret.to_string());
+    }
+}
+
+/// `s` for an error message: registered secrets replaced by `[REDACTED]`,
+/// then cut to its first `max_chars` chars with `…` and the number of chars
+/// left out
+pub fn truncate_for_display(s: &str, max_chars: usize) -> String {
+    let mut shown = s.to_string();
+    for secret in SECRETS.read().unwrap_or_else(|e| e.into_inner()).iter() {
+        if shown.contains(secret.as_str()) {
+            shown = shown.replace(secret.as_str(), REDACTED);
+        }
+    }
+    match shown.char_indices().nth(max_chars) {
+        Some((cut, _)) => {
+            let omitted = shown[cut..].chars().count();
+            shown.truncate(cut);
+            format!("{}… ({} more chars)", shown, omitted)
+        }
+        None => shown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_truncates_multibyte_on_char_boundaries() {
+        assert_eq!(truncate_for_display("", 3), "");
+        assert_eq!(truncate_for_display("日本語", 3), "日本語");
+        assert_eq!(truncate_for_display("日本語です", 3), "日本語… (2 more chars)");
+        assert_eq!(truncate_for_display(&"🦀".repeat(250), 200), format!("{}… (50 more chars)", "🦀".repeat(200)));
+        assert_eq!(truncate_for_display("abc", 0), "… (3 more chars)");
+    }
+    
+    #[test]
+    fn test_registered_secrets_are_masked() {
+        register_secret("jina_error_test_secret");
+        register_secret("short");
+        let message = format!("bad key jina_error_test_secret, short: {}", "x".repeat(20));
+        assert_eq!(truncate_for_display(&message, 40), "bad key [REDACTED], short: xxxxxxxxxxxxx… (7 more chars)");
+        
+        // Masked before cutting, so a cut never leaves a prefix of the key
+        assert_eq!(truncate_for_display("jina_error_test_secret", 4), "[RED… (6 more chars)");
+    }
+}
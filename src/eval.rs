@@ -0,0 +1,266 @@
+//! Retrieval quality against labeled queries: recall@k, MRR and nDCG@k
+//!
+//! `evaluate` runs any search closure `Fn(query text, k) -> ranked ids`
+//! (brute force, an index, hybrid search) over a query set and scores each
+//! ranking against `Judgments`, the relevant ids of every query. Relevance
+//! is binary. Queries without judgments are listed in the report and left
+//! out of the means.
+//!
+//! Judgments load from TSV, `query_id<TAB>doc_id[<TAB>relevance]` with an
+//! optional BEIR-style `query-id` header, or JSONL objects
+//! `{"query_id": …, "doc_id": …, "relevance": …}`; rows with a relevance
+//! of 0 or less are not relevant.
+//!
+//! `bench` measures speed, memory and recall of index configurations.
+
+use std::collections::{BTreeMap, HashSet};
+use std::hash::Hash;
+use std::str::FromStr;
+
+pub mod bench;
+
+/// Relevant ids per query id
+#[derive(Clone, Debug, PartialEq)]
+pub struct Judgments<Id: Eq + Hash> {
+    relevant: BTreeMap<String, HashSet<Id>>,
+}
+
+impl<Id: Eq + Hash> Default for Judgments<Id> {
+    fn default() -> Self { Self { relevant: BTreeMap::new() } }
+}
+
+impl<Id: Eq + Hash> Judgments<Id> {
+    pub fn new() -> Self { Self::default() }
+    
+    /// Mark `docs` relevant to `query`
+    pub fn with(mut self, query: &str, docs: impl IntoIterator<Item = Id>) -> Self {
+        self.relevant.entry(query.to_string()).or_default().extend(docs);
+        self
+    }
+    
+    pub fn relevant(&self, query: &str) -> Option<&HashSet<Id>> { self.relevant.get(query) }
+    
+    /// Number of judged queries
+    pub fn len(&self) -> usize { self.relevant.len() }
+    
+    pub fn is_empty(&self) -> bool { self.relevant.is_empty() }
+}
+
+impl<Id: Eq + Hash + FromStr> Judgments<Id> {
+    /// Parse `query_id<TAB>doc_id[<TAB>relevance]` lines; `#` comments and blank lines are skipped
+    pub fn from_tsv(tsv: &str) -> Result<Self, String> {
+        let mut judgments = Self::new();
+        for (n, line) in tsv.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with('#') || (n == 0 && line.starts_with("query-id")) {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            if !(2..=3).contains(&fields.len()) {
+                return Err(format!("Line {}: expected query id, doc id and optional relevance", n + 1));
+            }
+            let relevance = match fields.get(2) {
+                Some(r) => r.trim().parse::<f64>().map_err(|_| format!("Line {}: bad relevance {:?}", n + 1, r))?,
+                None => 1.0,
+            };
+            judgments.judge(fields[0].trim(), fields[1].trim(), relevance).map_err(|e| format!("Line {}: {}", n + 1, e))?;
+        }
+        Ok(judgments)
+    }
+    
+    /// Parse one `{"query_id", "doc_id", "relevance"}` object per line; ids may be strings or numbers
+    pub fn from_jsonl(jsonl: &str) -> Result<Self, String> {
+        let mut judgments = Self::new();
+        for (n, line) in jsonl.lines().enumerate() {
+            if line.trim().is_empty() { continue; }
+            let row: serde_json::Value = serde_json::from_str(line).map_err(|e| format!("Line {}: {}", n + 1, e))?;
+            let id = |key: &str| match &row[key] {
+                serde_json::Value::String(s) => Ok(s.clone()),
+                serde_json::Value::Number(n) => Ok(n.to_string()),
+                _ => Err(format!("Line {}: missing {}", n + 1, key)),
+            };
+            let relevance = row.get("relevance").map_or(Some(1.0), |r| r.as_f64())
+                .ok_or_else(|| format!("Line {}: relevance is not a number", n + 1))?;
+            judgments.judge(&id("query_id")?, &id("doc_id")?, relevance).map_err(|e| format!("Line {}: {}", n + 1, e))?;
+        }
+        Ok(judgments)
+    }
+    
+    /// `from_jsonl` for `.jsonl`/`.json` files, else `from_tsv`
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+        let result = if path.ends_with(".jsonl") || path.ends_with(".json") { Self::from_jsonl(&text) } else { Self::from_tsv(&text) };
+        result.map_err(|e| format!("{}: {}", path, e))
+    }
+    
+    fn judge(&mut self, query: &str, doc: &str, relevance: f64) -> Result<(), String> {
+        let doc = doc.parse::<Id>().map_err(|_| format!("bad doc id {:?}", doc))?;
+        let docs = self.relevant.entry(query.to_string()).or_default();
+        if relevance > 0.0 {
+            docs.insert(doc);
+        }
+        Ok(())
+    }
+}
+
+/// Scores of one query
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct QueryMetrics {
+    pub query_id: String,
+    /// Relevant ids judged for the query
+    pub relevant: usize,
+    /// Relevant ids among the top k
+    pub retrieved_relevant: usize,
+    pub recall: f64,
+    pub reciprocal_rank: f64,
+    pub ndcg: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EvalReport {
+    pub k: usize,
+    /// Means over judged queries; 0 when there are none
+    pub recall_at_k: f64,
+    pub mrr: f64,
+    pub ndcg_at_k: f64,
+    pub per_query: Vec<QueryMetrics>,
+    /// Query ids without judgments, not scored
+    #[serde(default)]
+    pub unjudged: Vec<String>,
+}
+
+impl EvalReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("eval reports always serialize")
+    }
+    
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("Cannot parse eval report: {}", e))
+    }
+}
+
+/// Share of `relevant` found in the first `k` of `ranked`; 0 when nothing is relevant
+pub fn recall_at_k<Id: Eq + Hash>(ranked: &[Id], relevant: &HashSet<Id>, k: usize) -> f64 {
+    if relevant.is_empty() { return 0.0; }
+    hits(ranked, relevant, k) as f64 / relevant.len() as f64
+}
+
+/// 1 / rank of the first relevant id, 0 when none is ranked
+pub fn reciprocal_rank<Id: Eq + Hash>(ranked: &[Id], relevant: &HashSet<Id>) -> f64 {
+    ranked.iter().position(|id| relevant.contains(id)).map_or(0.0, |i| 1.0 / (i + 1) as f64)
+}
+
+/// Binary-gain DCG of the first `k` of `ranked` over that of an ideal ranking
+pub fn ndcg_at_k<Id: Eq + Hash>(ranked: &[Id], relevant: &HashSet<Id>, k: usize) -> f64 {
+    let discount = |i: usize| 1.0 / ((i + 2) as f64).log2();
+    let ideal: f64 = (0..relevant.len().min(k)).map(discount).sum();
+    if ideal == 0.0 { return 0.0; }
+    let dcg: f64 = ranked.iter().take(k).enumerate()
+        .filter(|(_, id)| relevant.contains(id))
+        .map(|(i, _)| discount(i))
+        .sum();
+    dcg / ideal
+}
+
+/// Run `search(text, k)` for each `(query id, text)` and score it against `judgments`
+pub fn evaluate<Id, S>(queries: &[(&str, &str)], judgments: &Judgments<Id>, k: usize, search: S) -> EvalReport
+    where Id: Eq + Hash, S: Fn(&str, usize) -> Vec<Id> {
+    let mut per_query = Vec::new();
+    let mut unjudged = Vec::new();
+    for &(query_id, text) in queries {
+        let Some(relevant) = judgments.relevant(query_id) else {
+            unjudged.push(query_id.to_string());
+            continue;
+        };
+        let mut ranked = search(text, k);
+        ranked.truncate(k);
+        per_query.push(QueryMetrics {
+            query_id: query_id.to_string(),
+            relevant: relevant.len(),
+            retrieved_relevant: hits(&ranked, relevant, k),
+            recall: recall_at_k(&ranked, relevant, k),
+            reciprocal_rank: reciprocal_rank(&ranked, relevant),
+            ndcg: ndcg_at_k(&ranked, relevant, k),
+        });
+    }
+    let mean = |f: fn(&QueryMetrics) -> f64| {
+        if per_query.is_empty() { 0.0 } else { per_query.iter().map(f).sum::<f64>() / per_query.len() as f64 }
+    };
+    EvalReport {
+        k,
+        recall_at_k: mean(|q| q.recall),
+        mrr: mean(|q| q.reciprocal_rank),
+        ndcg_at_k: mean(|q| q.ndcg),
+        per_query,
+        unjudged,
+    }
+}
+
+/// Distinct relevant ids among the first `k`
+fn hits<Id: Eq + Hash>(ranked: &[Id], relevant: &HashSet<Id>, k: usize) -> usize {
+    let top: HashSet<&Id> = ranked.iter().take(k).filter(|id| relevant.contains(id)).collect();
+    top.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pseudo::PseudoEmbedder;
+    use crate::search::top_k;
+    
+    fn set(ids: &[u64]) -> HashSet<u64> { ids.iter().copied().collect() }
+    
+    #[test]
+    fn test_metrics_match_hand_computed_values() {
+        let ranked = [7, 3, 9, 1, 4];
+        let relevant = set(&[3, 4, 8]);
+        assert!((recall_at_k(&ranked, &relevant, 5) - 2.0 / 3.0).abs() < 1e-12);
+        assert_eq!(recall_at_k(&ranked, &relevant, 1), 0.0);
+        assert_eq!(reciprocal_rank(&ranked, &relevant), 0.5);
+        assert_eq!(reciprocal_rank(&ranked, &set(&[99])), 0.0);
+        
+        // DCG = 1/log2(3) + 1/log2(6); ideal = 1 + 1/log2(3) + 1/2
+        let expected = (1.0 / 3f64.log2() + 1.0 / 6f64.log2()) / (1.0 + 1.0 / 3f64.log2() + 0.5);
+        assert!((ndcg_at_k(&ranked, &relevant, 5) - expected).abs() < 1e-12);
+        // At k = 2 the ideal holds two hits: 1/log2(3) / (1 + 1/log2(3))
+        let expected = (1.0 / 3f64.log2()) / (1.0 + 1.0 / 3f64.log2());
+        assert!((ndcg_at_k(&ranked, &relevant, 2) - expected).abs() < 1e-12);
+        assert_eq!(ndcg_at_k(&[3, 4, 8], &relevant, 3), 1.0);
+        assert_eq!(ndcg_at_k(&ranked, &HashSet::new(), 5), 0.0);
+        
+        let judgments = Judgments::new().with("q1", [3, 4, 8]).with("q2", [1]);
+        let report = evaluate(&[("q1", "a"), ("q2", "b"), ("q3", "c")], &judgments, 5, |_, _| ranked.to_vec());
+        assert_eq!(report.per_query[1].reciprocal_rank, 0.25);
+        assert!((report.mrr - 0.375).abs() < 1e-12);
+        assert!((report.recall_at_k - (2.0 / 3.0 + 1.0) / 2.0).abs() < 1e-12);
+        assert_eq!(report.unjudged, ["q3"]);
+        assert_eq!(EvalReport::from_json(&report.to_json()).unwrap(), report);
+    }
+    
+    #[test]
+    fn test_judgments_load_from_tsv_and_jsonl() {
+        let tsv = "query-id\tcorpus-id\tscore\nq1\t3\t1\nq1\t4\t2\nq1\t5\t0\n# note\nq2\t8\n";
+        let from_tsv = Judgments::<u64>::from_tsv(tsv).unwrap();
+        assert_eq!(from_tsv.relevant("q1"), Some(&set(&[3, 4])));
+        assert_eq!(from_tsv.relevant("q2"), Some(&set(&[8])));
+        let jsonl = "{\"query_id\":\"q1\",\"doc_id\":3}\n{\"query_id\":\"q1\",\"doc_id\":\"4\",\"relevance\":2}\n\
+                     {\"query_id\":\"q1\",\"doc_id\":5,\"relevance\":0}\n{\"query_id\":\"q2\",\"doc_id\":8}\n";
+        assert_eq!(Judgments::<u64>::from_jsonl(jsonl).unwrap(), from_tsv);
+        
+        assert!(Judgments::<u64>::from_tsv("q1\tdoc\n").unwrap_err().contains("Line 1"));
+        assert!(Judgments::<String>::from_tsv("q1\n").unwrap_err().contains("Line 1"));
+        assert!(Judgments::<String>::from_jsonl("{\"doc_id\":1}").unwrap_err().contains("query_id"));
+    }
+    
+    #[test]
+    fn test_offline_smoke_run() {
+        let corpus = ["rust borrow checker", "python garbage collection", "ada lovelace analytical engine"];
+        let pseudo = PseudoEmbedder::new(128);
+        let vectors = pseudo.embed_batch(&corpus);
+        let judgments = Judgments::new().with("rust", [0]).with("ada", [2]);
+        let report = evaluate(&[("rust", "rust borrow checker"), ("ada", "ada lovelace analytical engine")], &judgments, 2,
+                              |text, k| top_k(&pseudo.embed(text), &vectors, k).into_iter().map(|(i, _)| i).collect());
+        assert_eq!((report.mrr, report.recall_at_k, report.ndcg_at_k), (1.0, 1.0, 1.0));
+        assert_eq!(report.per_query.len(), 2);
+    }
+}
@@ -0,0 +1,327 @@
+//! Search benchmarks over a matrix of index configurations
+//!
+//! `run` builds an index per `BenchConfig` of a `BenchMatrix` (metric,
+//! quantization, index kind, k) over a `Corpus`, times every query and
+//! scores recall against brute force: exhaustive f32 search under the same
+//! metric. The `BenchReport` holds throughput, latency percentiles, memory
+//! and recall per configuration, as JSON or CSV.
+//!
+//! `Corpus::synthetic` embeds seeded random texts with the pseudo embedder,
+//! so the same arguments give the same corpus everywhere; `Corpus::new`
+//! takes vectors of your own. Timings are wall clock and vary by machine;
+//! recall and memory do not.
+
+use std::fmt::Write as _;
+use std::time::Instant;
+
+use crate::index::{CrystalIndex, Quantization};
+use crate::pseudo::PseudoEmbedder;
+use crate::sample::SampleRng;
+use crate::search::Metric;
+use crate::shard::ShardedIndex;
+
+/// How the benchmarked index is built and searched
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexKind {
+    /// `CrystalIndex::search_exhaustive`: every row scored in full
+    Exhaustive,
+    /// `CrystalIndex::search`, abandoning rows that cannot make the top k
+    Flat,
+    /// `ShardedIndex` over this many shards, searched in parallel
+    Sharded { shards: usize },
+}
+
+impl IndexKind {
+    pub fn label(&self) -> String {
+        match self {
+            IndexKind::Exhaustive => "exhaustive".to_string(),
+            IndexKind::Flat => "flat".to_string(),
+            IndexKind::Sharded { shards } => format!("sharded-{}", shards),
+        }
+    }
+}
+
+/// One cell of the matrix
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BenchConfig {
+    pub metric: Metric,
+    pub quantization: Quantization,
+    pub index: IndexKind,
+    pub k: usize,
+}
+
+/// Every combination of its metrics, quantizations, index kinds and ks
+#[derive(Clone, Debug, PartialEq)]
+pub struct BenchMatrix {
+    metrics: Vec<Metric>,
+    quantizations: Vec<Quantization>,
+    indexes: Vec<IndexKind>,
+    ks: Vec<usize>,
+}
+
+impl Default for BenchMatrix {
+    fn default() -> Self {
+        Self {
+            metrics: vec![Metric::Cosine],
+            quantizations: vec![Quantization::None, Quantization::Int8],
+            indexes: vec![IndexKind::Exhaustive, IndexKind::Flat, IndexKind::Sharded { shards: 4 }],
+            ks: vec![10],
+        }
+    }
+}
+
+impl BenchMatrix {
+    pub fn new() -> Self { Self::default() }
+    
+    pub fn with_metrics(mut self, metrics: &[Metric]) -> Self {
+        self.metrics = metrics.to_vec();
+        self
+    }
+    
+    pub fn with_quantizations(mut self, quantizations: &[Quantization]) -> Self {
+        self.quantizations = quantizations.to_vec();
+        self
+    }
+    
+    pub fn with_indexes(mut self, indexes: &[IndexKind]) -> Self {
+        self.indexes = indexes.to_vec();
+        self
+    }
+    
+    pub fn with_ks(mut self, ks: &[usize]) -> Self {
+        self.ks = ks.to_vec();
+        self
+    }
+    
+    /// Configurations in run order: metric, then quantization, index and k
+    pub fn configs(&self) -> Vec<BenchConfig> {
+        let mut configs = Vec::new();
+        for &metric in &self.metrics {
+            for &quantization in &self.quantizations {
+                for &index in &self.indexes {
+                    for &k in &self.ks {
+                        configs.push(BenchConfig { metric, quantization, index, k });
+                    }
+                }
+            }
+        }
+        configs
+    }
+}
+
+/// Vectors to index and queries to run against them
+#[derive(Clone, Debug, PartialEq)]
+pub struct Corpus {
+    pub vectors: Vec<Vec<f32>>,
+    pub queries: Vec<Vec<f32>>,
+    /// Pseudo-embedding throughput, for synthetic corpora
+    pub embed_texts_per_sec: Option<f64>,
+}
+
+impl Corpus {
+    /// Fails on an empty corpus or query set, or vectors of different sizes
+    pub fn new(vectors: Vec<Vec<f32>>, queries: Vec<Vec<f32>>) -> Result<Self, String> {
+        let dims = vectors.first().ok_or("The corpus has no vectors")?.len();
+        if queries.is_empty() {
+            return Err("The corpus has no queries".to_string());
+        }
+        if let Some(v) = vectors.iter().chain(&queries).find(|v| v.len() != dims) {
+            return Err(format!("Vector of {} dims in a corpus of {}", v.len(), dims));
+        }
+        Ok(Self { vectors, queries, embed_texts_per_sec: None })
+    }
+    
+    /// `n` pseudo-embedded texts of random words and `queries` texts sharing
+    /// most words with one of them, all from `seed`
+    pub fn synthetic(n: usize, queries: usize, dims: usize, seed: u64) -> Result<Self, String> {
+        if n == 0 {
+            return Err("The corpus has no vectors".to_string());
+        }
+        let mut rng = SampleRng::new(seed);
+        let vocabulary: Vec<String> = (0..512).map(|_| {
+            (0..2 + rng.below(3)).map(|_| ["ka", "lo", "mi", "ne", "ru", "sa", "to", "vi", "ze", "qu"][rng.below(10)]).collect()
+        }).collect();
+        let sentence = |rng: &mut SampleRng| (0..8).map(|_| vocabulary[rng.below(vocabulary.len())].as_str()).collect::<Vec<_>>();
+        let texts: Vec<Vec<&str>> = (0..n).map(|_| sentence(&mut rng)).collect();
+        let query_texts: Vec<String> = (0..queries).map(|_| {
+            let mut words = texts[rng.below(n)].clone();
+            let replaced = rng.below(words.len());
+            words[replaced] = sentence(&mut rng)[0];
+            words.join(" ")
+        }).collect();
+        
+        let embedder = PseudoEmbedder::new(dims);
+        let start = Instant::now();
+        let vectors: Vec<Vec<f32>> = texts.iter().map(|words| embedder.embed(&words.join(" "))).collect();
+        let elapsed = start.elapsed().as_secs_f64();
+        let queries = query_texts.iter().map(|text| embedder.embed(text)).collect();
+        let mut corpus = Self::new(vectors, queries)?;
+        corpus.embed_texts_per_sec = (elapsed > 0.0).then(|| n as f64 / elapsed);
+        Ok(corpus)
+    }
+    
+    pub fn dims(&self) -> usize { self.vectors[0].len() }
+}
+
+/// Measurements of one configuration
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BenchResult {
+    #[serde(flatten)]
+    pub config: BenchConfig,
+    pub build_ms: f64,
+    pub queries_per_sec: f64,
+    /// Per-query latency percentiles, in microseconds
+    pub p50_us: f64,
+    pub p95_us: f64,
+    pub p99_us: f64,
+    /// `IndexStats::bytes_total`, summed over shards
+    pub memory_bytes: usize,
+    /// Mean share of the brute-force top k found
+    pub recall: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BenchReport {
+    pub vectors: usize,
+    pub queries: usize,
+    pub dims: usize,
+    pub embed_texts_per_sec: Option<f64>,
+    pub results: Vec<BenchResult>,
+}
+
+/// Columns of `BenchReport::to_csv`
+pub const CSV_HEADER: &str = "metric,quantization,index,k,build_ms,queries_per_sec,p50_us,p95_us,p99_us,memory_bytes,recall";
+
+impl BenchReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("bench reports always serialize")
+    }
+    
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("Cannot parse bench report: {}", e))
+    }
+    
+    /// `CSV_HEADER`, then one row per result
+    pub fn to_csv(&self) -> String {
+        let mut out = format!("{}\n", CSV_HEADER);
+        for r in &self.results {
+            let c = r.config;
+            writeln!(out, "{},{},{},{},{:.3},{:.1},{:.1},{:.1},{:.1},{},{:.4}", c.metric.as_str(), c.quantization.as_str(),
+                     c.index.label(), c.k, r.build_ms, r.queries_per_sec, r.p50_us, r.p95_us, r.p99_us, r.memory_bytes, r.recall).unwrap();
+        }
+        out
+    }
+}
+
+/// Run every configuration of `matrix` over `corpus`
+pub fn run(corpus: &Corpus, matrix: &BenchMatrix) -> Result<BenchReport, String> {
+    let mut results = Vec::new();
+    let mut baselines: Vec<(Metric, usize, Vec<Vec<u64>>)> = Vec::new();
+    for config in matrix.configs() {
+        if config.k == 0 {
+            return Err("Benchmark k must be at least 1".to_string());
+        }
+        let baseline = match baselines.iter().position(|(m, k, _)| (*m, *k) == (config.metric, config.k)) {
+            Some(i) => &baselines[i].2,
+            None => {
+                let exact = build(corpus, config.metric, Quantization::None)?;
+                let hits = corpus.queries.iter().map(|q| ids(exact.search_exhaustive(q, config.k))).collect();
+                baselines.push((config.metric, config.k, hits));
+                &baselines.last().unwrap().2
+            }
+        };
+        results.push(measure(corpus, config, baseline)?);
+    }
+    Ok(BenchReport {
+        vectors: corpus.vectors.len(),
+        queries: corpus.queries.len(),
+        dims: corpus.dims(),
+        embed_texts_per_sec: corpus.embed_texts_per_sec,
+        results,
+    })
+}
+
+fn build(corpus: &Corpus, metric: Metric, quantization: Quantization) -> Result<CrystalIndex, String> {
+    let mut index = CrystalIndex::new(corpus.dims()).with_metric(metric).with_quantization(quantization);
+    for (id, v) in corpus.vectors.iter().enumerate() {
+        index.add(id as u64, v)?;
+    }
+    Ok(index)
+}
+
+fn ids(hits: Vec<(u64, f32)>) -> Vec<u64> { hits.into_iter().map(|(id, _)| id).collect() }
+
+fn measure(corpus: &Corpus, config: BenchConfig, baseline: &[Vec<u64>]) -> Result<BenchResult, String> {
+    let start = Instant::now();
+    let template = CrystalIndex::new(corpus.dims()).with_metric(config.metric).with_quantization(config.quantization);
+    let (index, sharded) = match config.index {
+        IndexKind::Sharded { shards } => {
+            // Never saved, so the directory is never written
+            let mut sharded = ShardedIndex::new("", &template, shards);
+            for (id, v) in corpus.vectors.iter().enumerate() {
+                sharded.add(id as u64, v, Default::default())?;
+            }
+            (None, Some(sharded))
+        }
+        _ => (Some(build(corpus, config.metric, config.quantization)?), None),
+    };
+    let build_ms = start.elapsed().as_secs_f64() * 1e3;
+    
+    let mut latencies = Vec::with_capacity(corpus.queries.len());
+    let mut found = 0;
+    let start = Instant::now();
+    for (query, exact) in corpus.queries.iter().zip(baseline) {
+        let began = Instant::now();
+        let hits = match (&index, &sharded, config.index) {
+            (Some(index), _, IndexKind::Exhaustive) => index.search_exhaustive(query, config.k),
+            (Some(index), _, _) => index.search(query, config.k),
+            (None, Some(sharded), _) => sharded.search(query, config.k)?,
+            (None, None, _) => unreachable!("one index is built"),
+        };
+        latencies.push(began.elapsed().as_secs_f64() * 1e6);
+        found += hits.iter().filter(|(id, _)| exact.contains(id)).count();
+    }
+    let total = start.elapsed().as_secs_f64();
+    latencies.sort_by(f64::total_cmp);
+    let percentile = |p: f64| latencies[((p * latencies.len() as f64).ceil() as usize).clamp(1, latencies.len()) - 1];
+    let memory_bytes = match (&index, &sharded) {
+        (Some(index), _) => index.stats().bytes_total(),
+        (None, Some(sharded)) => (0..sharded.shard_count()).map(|i| sharded.shard(i).map(|s| s.stats().bytes_total())).sum::<Result<_, _>>()?,
+        (None, None) => 0,
+    };
+    let expected: usize = baseline.iter().map(Vec::len).sum();
+    Ok(BenchResult {
+        config,
+        build_ms,
+        queries_per_sec: if total > 0.0 { corpus.queries.len() as f64 / total } else { 0.0 },
+        p50_us: percentile(0.50),
+        p95_us: percentile(0.95),
+        p99_us: percentile(0.99),
+        memory_bytes,
+        recall: if expected == 0 { 1.0 } else { found as f64 / expected as f64 },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_exact_configurations_have_full_recall() {
+        let corpus = Corpus::synthetic(300, 12, 32, 5).unwrap();
+        let again = Corpus::synthetic(300, 12, 32, 5).unwrap();
+        assert_eq!((again.vectors, again.queries), (corpus.vectors.clone(), corpus.queries.clone()));
+        let matrix = BenchMatrix::new().with_metrics(&[Metric::Cosine, Metric::Euclidean]).with_ks(&[1, 10]);
+        let report = run(&corpus, &matrix).unwrap();
+        assert_eq!(report.results.len(), 2 * 2 * 3 * 2);
+        for r in &report.results {
+            assert!(r.p50_us <= r.p95_us && r.p95_us <= r.p99_us && r.memory_bytes > 300 * 32 * 4, "{:?}", r);
+            if r.config.quantization == Quantization::None {
+                assert_eq!(r.recall, 1.0, "{:?}", r.config);
+            }
+        }
+        assert!(Corpus::new(vec![vec![1.0; 2]], vec![vec![1.0; 3]]).unwrap_err().contains("3 dims"));
+        assert!(run(&corpus, &BenchMatrix::new().with_ks(&[0])).is_err());
+    }
+}
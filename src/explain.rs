@@ -0,0 +1,154 @@
+//! Why two embeddings score as they do
+//!
+//! `cosine_contributions` splits a cosine into its per-dimension terms
+//! `a[i] * b[i] / (|a| |b|)`, which sum to the cosine (the dot product, for
+//! unit vectors), and keeps the largest in magnitude: positive terms pull the
+//! pair together, negative ones apart.
+//!
+//! `shared_features` explains similarity under `PseudoEmbedder`, whose
+//! dimensions are hashed word and 3-gram features. It scores the features
+//! both texts contain in the unhashed feature space, where nothing collides;
+//! at large `dims` the embeddings' cosine is close to the sum over all shared
+//! features, at small `dims` collisions add noise the breakdown does not show.
+//! The features and their weights are the same under every seed and size.
+
+use std::collections::HashMap;
+
+use crate::pseudo::{for_each_feature, KIND_TRIGRAM, KIND_WORD, TRIGRAM_WEIGHT, WORD_WEIGHT};
+use crate::search::norm;
+
+/// One dimension's term of a cosine
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DimContribution {
+    pub dim: usize,
+    pub contribution: f32,
+}
+
+/// The `top_n` dimensions with the largest terms `a[i] * b[i] / (|a| |b|)` by
+/// magnitude, largest first; empty if either vector is all-zero
+pub fn cosine_contributions(a: &[f32], b: &[f32], top_n: usize) -> Vec<DimContribution> {
+    assert_eq!(a.len(), b.len(), "vectors must have the same length");
+    let denom = norm(a) * norm(b);
+    if denom == 0.0 {
+        return Vec::new();
+    }
+    let mut terms: Vec<DimContribution> = a.iter().zip(b).enumerate()
+        .map(|(dim, (x, y))| DimContribution { dim, contribution: x * y / denom })
+        .filter(|c| c.contribution != 0.0)
+        .collect();
+    terms.sort_by(|x, y| y.contribution.abs().total_cmp(&x.contribution.abs()).then(x.dim.cmp(&y.dim)));
+    terms.truncate(top_n);
+    terms
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeatureKind {
+    Word,
+    /// Three `char`s of a word, `<` and `>` marking its ends
+    Trigram,
+}
+
+/// A feature of both texts and its share of their similarity
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SharedFeature {
+    pub feature: String,
+    pub kind: FeatureKind,
+    /// Weight in each text: the kind's weight times the root of its count
+    pub weight_a: f32,
+    pub weight_b: f32,
+    /// `weight_a * weight_b` over the product of the texts' feature norms
+    pub contribution: f32,
+}
+
+/// The `top_n` features `text_a` and `text_b` share under `PseudoEmbedder`,
+/// the largest contribution first
+pub fn shared_features(text_a: &str, text_b: &str, top_n: usize) -> Vec<SharedFeature> {
+    let (a, b) = (feature_weights(text_a), feature_weights(text_b));
+    let norm = |weights: &HashMap<(FeatureKind, String), f32>| weights.values().map(|w| w * w).sum::<f32>().sqrt();
+    let denom = norm(&a) * norm(&b);
+    if denom == 0.0 {
+        return Vec::new();
+    }
+    let mut shared: Vec<SharedFeature> = a.iter()
+        .filter_map(|(key, &weight_a)| b.get(key).map(|&weight_b| SharedFeature {
+            feature: key.1.clone(),
+            kind: key.0,
+            weight_a,
+            weight_b,
+            contribution: weight_a * weight_b / denom,
+        }))
+        .collect();
+    shared.sort_by(|x, y| y.contribution.total_cmp(&x.contribution).then_with(|| x.feature.cmp(&y.feature)));
+    shared.truncate(top_n);
+    shared
+}
+
+/// Distinct features of `text` and their `PseudoEmbedder` weights
+fn feature_weights(text: &str) -> HashMap<(FeatureKind, String), f32> {
+    let mut counts: HashMap<(FeatureKind, String), usize> = HashMap::new();
+    for_each_feature(text, |kind, bytes| {
+        let kind = match kind {
+            KIND_WORD => FeatureKind::Word,
+            KIND_TRIGRAM => FeatureKind::Trigram,
+            _ => unreachable!("unknown feature kind"),
+        };
+        *counts.entry((kind, String::from_utf8_lossy(bytes).into_owned())).or_default() += 1;
+    });
+    counts.into_iter()
+        .map(|(key, count)| {
+            let weight = if key.0 == FeatureKind::Word { WORD_WEIGHT } else { TRIGRAM_WEIGHT };
+            (key, weight * (count as f32).sqrt())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pseudo::PseudoEmbedder;
+    use crate::search::cosine;
+    
+    #[test]
+    fn test_contributions_sum_to_dot_product() {
+        let embedder = PseudoEmbedder::new(64).with_seed(5);
+        let (a, b) = (embedder.embed("the cat sat on the mat"), embedder.embed("a cat lay on a rug"));
+        let all = cosine_contributions(&a, &b, usize::MAX);
+        let sum: f32 = all.iter().map(|c| c.contribution).sum();
+        let dot: f32 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+        assert!((sum - dot).abs() < 1e-5, "sum {} dot {}", sum, dot);
+        assert!(all.windows(2).all(|w| w[0].contribution.abs() >= w[1].contribution.abs()));
+        
+        let top = cosine_contributions(&a, &b, 3);
+        assert_eq!(top, all[..3]);
+        
+        // Opposite signs pull apart and rank by magnitude
+        let against = cosine_contributions(&[3.0, 0.0, 1.0, -2.0], &[1.0, 5.0, 1.0, 2.0], 2);
+        assert_eq!(against.iter().map(|c| c.dim).collect::<Vec<_>>(), [3, 0]);
+        assert!(against[0].contribution < 0.0 && against[1].contribution > 0.0);
+        assert!(cosine_contributions(&a, &[0.0; 64], 3).is_empty());
+        
+        let json = serde_json::to_string(&top[0]).unwrap();
+        assert_eq!(serde_json::from_str::<DimContribution>(&json).unwrap(), top[0]);
+    }
+    
+    #[test]
+    fn test_shared_rare_word_leads() {
+        let (a, b) = ("the zeppelin landed at dawn", "a zeppelin crashed near Oslo");
+        let shared = shared_features(a, b, usize::MAX);
+        assert_eq!((shared[0].feature.as_str(), shared[0].kind), ("zeppelin", FeatureKind::Word));
+        assert!(shared.iter().any(|f| f.feature == "<ze" && f.kind == FeatureKind::Trigram));
+        assert!(shared.iter().all(|f| f.feature != "the" && f.feature != "dawn"));
+        assert_eq!(shared_features(a, b, 2), shared[..2]);
+        assert!(shared_features(a, "quarterly revenue", 5).is_empty());
+        
+        // Without collisions the shared features are the whole cosine
+        let embedder = PseudoEmbedder::new(1 << 20);
+        let sum: f32 = shared.iter().map(|f| f.contribution).sum();
+        let actual = cosine(&embedder.embed(a), &embedder.embed(b));
+        assert!((sum - actual).abs() < 1e-4, "sum {} cosine {}", sum, actual);
+        
+        let json = serde_json::to_value(&shared[0]).unwrap();
+        assert_eq!(json["kind"], "word");
+    }
+}
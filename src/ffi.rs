@@ -0,0 +1,276 @@
+//! C ABI over the embedding client and cosine similarity (`ffi` feature)
+//!
+//! Build a library with `cargo rustc --release --features ffi --lib --crate-type cdylib`
+//! (or `staticlib`); the same build writes `include/spo_crystal.h` with
+//! cbindgen.
+//!
+//! Strings are NUL-terminated UTF-8. Output buffers belong to the caller:
+//! `out_len` holds the buffer's capacity in floats on the way in and the
+//! floats written on the way out, or the floats needed along with
+//! `SPO_STATUS_BUFFER_TOO_SMALL`. The client is the only allocation handed
+//! to C, and `spo_client_free` releases it. Every entry point catches
+//! panics and reports `SPO_STATUS_PANIC`, so no unwind crosses into C. A
+//! client must not be used from two threads at once.
+//!
+//! The client caches embeddings, so calling again with a bigger buffer
+//! after `SPO_STATUS_BUFFER_TOO_SMALL` sends nothing upstream.
+//! `cargo +nightly miri test --features ffi --lib ffi::` runs the tests
+//! under Miri for undefined behavior and leaks.
+
+use std::any::Any;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use crate::jina_api::{EmbedOptions, JinaClient};
+use crate::search::cosine;
+
+/// Result of an FFI call
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpoStatus {
+    Ok = 0,
+    /// A required pointer argument was null
+    NullPointer = 1,
+    /// A string argument was not UTF-8
+    InvalidUtf8 = 2,
+    /// The output buffer is too small; `out_len` holds the floats needed
+    BufferTooSmall = 3,
+    /// Embedding failed; `spo_last_error_message` says why
+    EmbedFailed = 4,
+    /// A Rust panic was caught at the boundary
+    Panic = 5,
+}
+
+/// Opaque client handle
+pub struct SpoClient {
+    client: JinaClient,
+    last_error: Option<CString>,
+}
+
+impl SpoClient {
+    fn new(client: JinaClient) -> Self { Self { client: client.with_cache(), last_error: None } }
+    
+    fn fail(&mut self, status: SpoStatus, message: &str) -> SpoStatus {
+        // An interior NUL would cut the message short in C
+        self.last_error = CString::new(message.replace('\0', "")).ok();
+        status
+    }
+    
+    /// Embed `texts` into `out_ptr`; both pointers non-null, `*out_len` floats writable
+    unsafe fn embed(&mut self, texts: &[&str], out_ptr: *mut f32, out_len: *mut usize) -> SpoStatus {
+        let embeddings = match self.client.embed_batch_full(texts, &EmbedOptions::default()) {
+            Ok(response) => response.embeddings,
+            Err(e) => return self.fail(SpoStatus::EmbedFailed, &e.to_string()),
+        };
+        let needed = embeddings.iter().map(Vec::len).sum();
+        let capacity = *out_len;
+        *out_len = needed;
+        if capacity < needed {
+            return self.fail(SpoStatus::BufferTooSmall, &format!("Output needs {} floats, the buffer holds {}", needed, capacity));
+        }
+        let mut at = out_ptr;
+        for embedding in &embeddings {
+            ptr::copy_nonoverlapping(embedding.as_ptr(), at, embedding.len());
+            at = at.add(embedding.len());
+        }
+        SpoStatus::Ok
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    let detail = panic.downcast_ref::<&str>().copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown payload");
+    format!("Panic: {}", detail)
+}
+
+/// Run `f` on the client with its last error cleared, turning a panic into `Panic`
+unsafe fn with_client(client: *mut SpoClient, f: impl FnOnce(&mut SpoClient) -> SpoStatus) -> SpoStatus {
+    let Some(client) = client.as_mut() else { return SpoStatus::NullPointer };
+    client.last_error = None;
+    match catch_unwind(AssertUnwindSafe(|| f(client))) {
+        Ok(status) => status,
+        Err(panic) => client.fail(SpoStatus::Panic, &panic_message(panic.as_ref())),
+    }
+}
+
+/// Borrow a C string as `&str`
+unsafe fn text<'a>(client: &mut SpoClient, ptr: *const c_char, what: &str) -> Result<&'a str, SpoStatus> {
+    if ptr.is_null() {
+        return Err(client.fail(SpoStatus::NullPointer, &format!("{} is null", what)));
+    }
+    CStr::from_ptr(ptr).to_str()
+        .map_err(|e| client.fail(SpoStatus::InvalidUtf8, &format!("{} is not UTF-8: {}", what, e)))
+}
+
+/// New client calling the Jina API with `api_key`; an empty key embeds
+/// offline. Null if `api_key` is null or not UTF-8.
+///
+/// # Safety
+/// `api_key` is null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn spo_client_new(api_key: *const c_char) -> *mut SpoClient {
+    if api_key.is_null() {
+        return ptr::null_mut();
+    }
+    catch_unwind(|| {
+        let key = CStr::from_ptr(api_key).to_str().ok()?;
+        let client = if key.is_empty() { JinaClient::new("") } else { JinaClient::new(key).with_http() };
+        Some(Box::into_raw(Box::new(SpoClient::new(client))))
+    }).ok().flatten().unwrap_or(ptr::null_mut())
+}
+
+/// Free a client from `spo_client_new`; null is ignored
+///
+/// # Safety
+/// `client` is null or came from `spo_client_new` and is not used again.
+#[no_mangle]
+pub unsafe extern "C" fn spo_client_free(client: *mut SpoClient) {
+    if !client.is_null() {
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(client))));
+    }
+}
+
+/// Embed `text` into the `*out_len` floats at `out_ptr`
+///
+/// # Safety
+/// `client` is a live client, `text` a NUL-terminated string, and `out_ptr`
+/// points to at least `*out_len` writable floats.
+#[no_mangle]
+pub unsafe extern "C" fn spo_embed(client: *mut SpoClient, text: *const c_char, out_ptr: *mut f32,
+                                   out_len: *mut usize) -> SpoStatus {
+    with_client(client, |client| {
+        if out_ptr.is_null() || out_len.is_null() {
+            return client.fail(SpoStatus::NullPointer, "out_ptr or out_len is null");
+        }
+        match self::text(client, text, "text") {
+            Ok(text) => client.embed(&[text], out_ptr, out_len),
+            Err(status) => status,
+        }
+    })
+}
+
+/// Embed `count` texts into `out_ptr`, one row after another in input order
+///
+/// # Safety
+/// As `spo_embed`, with `texts` pointing to `count` NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn spo_embed_batch(client: *mut SpoClient, texts: *const *const c_char, count: usize,
+                                         out_ptr: *mut f32, out_len: *mut usize) -> SpoStatus {
+    with_client(client, |client| {
+        if (texts.is_null() && count > 0) || out_ptr.is_null() || out_len.is_null() {
+            return client.fail(SpoStatus::NullPointer, "texts, out_ptr or out_len is null");
+        }
+        let pointers = if count == 0 { &[][..] } else { std::slice::from_raw_parts(texts, count) };
+        let mut batch = Vec::with_capacity(count);
+        for (i, &pointer) in pointers.iter().enumerate() {
+            match self::text(client, pointer, &format!("texts[{}]", i)) {
+                Ok(text) => batch.push(text),
+                Err(status) => return status,
+            }
+        }
+        client.embed(&batch, out_ptr, out_len)
+    })
+}
+
+/// Cosine similarity of two `len`-float vectors; NaN if either is null
+///
+/// # Safety
+/// `a` and `b` are null or point to `len` floats each.
+#[no_mangle]
+pub unsafe extern "C" fn spo_cosine(a: *const f32, b: *const f32, len: usize) -> f32 {
+    if a.is_null() || b.is_null() {
+        return f32::NAN;
+    }
+    catch_unwind(|| cosine(std::slice::from_raw_parts(a, len), std::slice::from_raw_parts(b, len))).unwrap_or(f32::NAN)
+}
+
+/// Why the client's most recent call failed; null after a success. The
+/// string belongs to the client and stays valid until its next call.
+///
+/// # Safety
+/// `client` is null or a live client.
+#[no_mangle]
+pub unsafe extern "C" fn spo_last_error_message(client: *const SpoClient) -> *const c_char {
+    client.as_ref().and_then(|c| c.last_error.as_ref()).map_or(ptr::null(), |message| message.as_ptr())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::JinaError;
+    use crate::transport::HttpRequest;
+    
+    fn last_error(client: *const SpoClient) -> Option<String> {
+        let message = unsafe { spo_last_error_message(client) };
+        (!message.is_null()).then(|| unsafe { CStr::from_ptr(message) }.to_str().unwrap().to_string())
+    }
+    
+    #[test]
+    fn test_embed_through_the_c_abi() {
+        let client = unsafe { spo_client_new(c"".as_ptr()) };
+        assert!(!client.is_null());
+        let expected = JinaClient::new("").embed_batch_full(&["hello", "world"], &EmbedOptions::default()).unwrap().embeddings;
+        let (hello, world) = (c"hello", c"world");
+        
+        let mut out = vec![0f32; 2048];
+        let mut len = 1024;
+        assert_eq!(unsafe { spo_embed(client, hello.as_ptr(), out.as_mut_ptr(), &mut len) }, SpoStatus::Ok);
+        assert_eq!((&out[..len], last_error(client)), (&expected[0][..], None));
+        
+        // Too small: nothing written, the size needed reported
+        let mut small = [7f32; 4];
+        let mut len = small.len();
+        assert_eq!(unsafe { spo_embed(client, hello.as_ptr(), small.as_mut_ptr(), &mut len) }, SpoStatus::BufferTooSmall);
+        assert_eq!((len, small), (1024, [7.0; 4]));
+        assert!(last_error(client).unwrap().contains("needs 1024 floats"));
+        
+        let texts = [hello.as_ptr(), world.as_ptr()];
+        let mut len = out.len();
+        assert_eq!(unsafe { spo_embed_batch(client, texts.as_ptr(), 2, out.as_mut_ptr(), &mut len) }, SpoStatus::Ok);
+        assert_eq!(out, expected.concat());
+        let similarity = unsafe { spo_cosine(out.as_ptr(), out[1024..].as_ptr(), 1024) };
+        assert!((similarity - cosine(&expected[0], &expected[1])).abs() < 1e-6);
+        let mut len = 0;
+        assert_eq!(unsafe { spo_embed_batch(client, ptr::null(), 0, out.as_mut_ptr(), &mut len) }, SpoStatus::Ok);
+        assert_eq!(len, 0);
+        unsafe { spo_client_free(client) };
+    }
+    
+    #[test]
+    fn test_error_paths_and_caught_panics() {
+        unsafe {
+            assert!(spo_client_new(ptr::null()).is_null());
+            assert!(spo_client_new(c"\xff".as_ptr()).is_null());
+            spo_client_free(ptr::null_mut());
+            assert!(spo_cosine(ptr::null(), ptr::null(), 3).is_nan());
+            assert!(spo_last_error_message(ptr::null()).is_null());
+        }
+        let (mut out, mut len) = ([0f32; 8], 8usize);
+        let text = c"x";
+        assert_eq!(unsafe { spo_embed(ptr::null_mut(), text.as_ptr(), out.as_mut_ptr(), &mut len) }, SpoStatus::NullPointer);
+        
+        let client = Box::into_raw(Box::new(SpoClient::new(JinaClient::new("key").with_transport(|request: &HttpRequest| {
+            if request.body.windows(5).any(|w| w == b"boom!") {
+                panic!("transport exploded");
+            }
+            Err(JinaError::Api { status: 401, message: "bad key".to_string() })
+        }))));
+        let check = |text: &CStr, status: SpoStatus, message: &str| {
+            let mut len = out.len();
+            let mut out = out;
+            assert_eq!(unsafe { spo_embed(client, text.as_ptr(), out.as_mut_ptr(), &mut len) }, status);
+            assert!(last_error(client).unwrap().contains(message), "{:?}", last_error(client));
+        };
+        check(c"hi", SpoStatus::EmbedFailed, "API error 401: bad key");
+        check(c"caf\xc3", SpoStatus::InvalidUtf8, "text is not UTF-8");
+        check(c"boom!", SpoStatus::Panic, "Panic: transport exploded");
+        // The panic left the client usable
+        check(c"hi", SpoStatus::EmbedFailed, "401");
+        let texts = [text.as_ptr(), ptr::null()];
+        assert_eq!(unsafe { spo_embed_batch(client, texts.as_ptr(), 2, out.as_mut_ptr(), &mut len) }, SpoStatus::NullPointer);
+        assert_eq!(last_error(client).as_deref(), Some("texts[1] is null"));
+        unsafe { spo_client_free(client) };
+    }
+}
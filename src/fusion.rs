@@ -0,0 +1,109 @@
+//! Reciprocal Rank Fusion of ranked result lists
+//!
+//! Dense search, binary-prefiltered search and the reranker score on
+//! different scales, so their lists are merged by rank alone: an id at
+//! 1-based rank `r` in a list earns `weight / (k + r)`, summed over the
+//! lists it appears in. The input scores are ignored.
+//!
+//! `k` damps the lead of the very top ranks; `DEFAULT_K` (60) is the value
+//! from Cormack et al. Lists may differ in length and share only some ids.
+//! Ties go to the id with the better best rank, then to the one seen first
+//! (earlier list, earlier position), so the output does not depend on hashing.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// The usual RRF constant
+pub const DEFAULT_K: f32 = 60.0;
+
+/// Fused scores of all ids in `lists`, best first; every list weighs 1
+pub fn rrf<Id: Eq + Hash + Clone>(lists: &[Vec<(Id, f32)>], k: f32) -> Vec<(Id, f32)> {
+    let weighted: Vec<(&[(Id, f32)], f32)> = lists.iter().map(|list| (list.as_slice(), 1.0)).collect();
+    weighted_rrf(&weighted, k)
+}
+
+/// `rrf` with a multiplier per list: `(list, weight)`
+///
+/// A repeated id within one list counts at its first (best) rank only.
+pub fn weighted_rrf<Id: Eq + Hash + Clone>(lists: &[(&[(Id, f32)], f32)], k: f32) -> Vec<(Id, f32)> {
+    struct Fused<Id> {
+        id: Id,
+        score: f32,
+        best_rank: usize,
+    }
+    
+    let mut slots: HashMap<Id, usize> = HashMap::new();
+    let mut fused: Vec<Fused<Id>> = Vec::new();
+    for &(list, weight) in lists {
+        let mut counted = vec![false; fused.len()];
+        for (position, (id, _)) in list.iter().enumerate() {
+            let rank = position + 1;
+            let slot = *slots.entry(id.clone()).or_insert_with(|| {
+                fused.push(Fused { id: id.clone(), score: 0.0, best_rank: rank });
+                fused.len() - 1
+            });
+            counted.resize(fused.len(), false);
+            if counted[slot] { continue; }
+            counted[slot] = true;
+            
+            let entry = &mut fused[slot];
+            entry.score += weight / (k + rank as f32);
+            entry.best_rank = entry.best_rank.min(rank);
+        }
+    }
+    
+    // `fused` is in first-seen order, and the sort is stable
+    fused.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.best_rank.cmp(&b.best_rank)));
+    fused.into_iter().map(|f| (f.id, f.score)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn list(ids: &[&'static str]) -> Vec<(&'static str, f32)> {
+        ids.iter().map(|&id| (id, 0.0)).collect()
+    }
+    
+    fn assert_fused(actual: &[(&str, f32)], expected: &[(&str, f32)]) {
+        assert_eq!(actual.iter().map(|p| p.0).collect::<Vec<_>>(), expected.iter().map(|p| p.0).collect::<Vec<_>>());
+        for ((_, a), (id, e)) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-7, "{}: {} != {}", id, a, e);
+        }
+    }
+    
+    #[test]
+    fn test_standard_formula() {
+        let fused = rrf(&[list(&["a", "b", "c"]), list(&["b", "c", "d"])], DEFAULT_K);
+        assert_fused(&fused, &[
+            ("b", 1.0 / 62.0 + 1.0 / 61.0),
+            ("c", 1.0 / 63.0 + 1.0 / 62.0),
+            ("a", 1.0 / 61.0),
+            ("d", 1.0 / 63.0),
+        ]);
+        
+        // Different lengths, k = 0, a repeat inside one list counted once
+        let fused = rrf(&[list(&["x"]), list(&["y", "x", "z", "x"])], 0.0);
+        assert_fused(&fused, &[("x", 1.0 + 0.5), ("y", 1.0), ("z", 1.0 / 3.0)]);
+        assert!(rrf::<u64>(&[], DEFAULT_K).is_empty());
+        
+        // Equal scores: better best rank first, then first seen
+        let fused = rrf(&[list(&["p", "q"]), list(&["r"]), list(&["q", "p"])], DEFAULT_K);
+        assert_eq!(fused.iter().map(|p| p.0).collect::<Vec<_>>(), ["p", "q", "r"]);
+    }
+    
+    #[test]
+    fn test_shared_ids_outrank_single_list_hits() {
+        // "z" is last everywhere, the others top a single list
+        let lists = [list(&["a", "m", "z"]), list(&["b", "n", "z"]), list(&["c", "o", "z"])];
+        let fused = rrf(&lists, DEFAULT_K);
+        assert_eq!(fused[0].0, "z");
+        
+        // Weights scale each list's contribution; weight 0 drops it
+        let dense: Vec<(u64, f32)> = vec![(1, 0.9), (2, 0.8)];
+        let rerank: Vec<(u64, f32)> = vec![(2, 3.5), (1, -1.0)];
+        assert_eq!(weighted_rrf(&[(&dense, 1.0), (&rerank, 2.0)], DEFAULT_K)[0].0, 2);
+        let fused = weighted_rrf(&[(&dense, 1.0), (&rerank, 0.0)], DEFAULT_K);
+        assert_eq!(fused, [(1, 1.0 / 61.0), (2, 1.0 / 62.0)]);
+    }
+}
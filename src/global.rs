@@ -0,0 +1,134 @@
+//! Process-wide default client for small tools and examples
+//!
+//! `embed` and `embed_batch` share one `JinaClient`, built on first use from
+//! the environment:
+//!
+//! - `SPO_CRYSTAL_OFFLINE=1`: the offline embedder, no key needed
+//! - otherwise `JINA_API_KEY` (required), and optionally `JINA_MODEL` and
+//!   `JINA_BASE_URL`, for a client calling the Jina API over HTTPS
+//!
+//! `configure` installs a client of your own instead; it must run before the
+//! first call and fails after it. A missing key is returned as an error from
+//! every call, never a panic, and stays until the process restarts (or is
+//! avoided by calling `configure` first).
+
+use std::sync::OnceLock;
+
+use crate::error::JinaError;
+use crate::jina_api::{EmbedOptions, JinaClient};
+
+/// Set to `1` to build the global client offline
+pub const OFFLINE_ENV: &str = "SPO_CRYSTAL_OFFLINE";
+
+static GLOBAL: Global = Global::new();
+
+/// Embed `text` with the global client
+pub fn embed(text: &str) -> Result<Vec<f32>, JinaError> {
+    let embeddings = embed_batch(&[text])?;
+    embeddings.into_iter().next().ok_or(JinaError::Mismatch { expected: 1, got: 0 })
+}
+
+/// Embed `texts` with the global client, in input order
+pub fn embed_batch(texts: &[&str]) -> Result<Vec<Vec<f32>>, JinaError> {
+    Ok(GLOBAL.client(env_var)?.embed_batch_full(texts, &EmbedOptions::default())?.embeddings)
+}
+
+/// Use `client` as the global client; an error once any call has built one
+pub fn configure(client: JinaClient) -> Result<(), JinaError> {
+    GLOBAL.configure(client)
+}
+
+/// The global client, built from the environment if nothing configured one
+pub fn client() -> Result<&'static JinaClient, JinaError> {
+    GLOBAL.client(env_var)
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+/// A client set once, by `configure` or from the environment on first use
+struct Global {
+    cell: OnceLock<Result<JinaClient, JinaError>>,
+}
+
+impl Global {
+    const fn new() -> Self { Self { cell: OnceLock::new() } }
+    
+    fn client(&self, env: impl Fn(&str) -> Option<String>) -> Result<&JinaClient, JinaError> {
+        self.cell.get_or_init(|| from_env(env)).as_ref().map_err(Clone::clone)
+    }
+    
+    fn configure(&self, client: JinaClient) -> Result<(), JinaError> {
+        self.cell.set(Ok(client))
+            .map_err(|_| JinaError::InvalidInput("The global client is already initialized; configure it before first use".to_string()))
+    }
+}
+
+/// Client described by the variables `env` returns
+fn from_env(env: impl Fn(&str) -> Option<String>) -> Result<JinaClient, JinaError> {
+    if env(OFFLINE_ENV).as_deref() == Some("1") {
+        return Ok(JinaClient::new(""));
+    }
+    let key = env("JINA_API_KEY").ok_or_else(|| {
+        JinaError::InvalidInput(format!("JINA_API_KEY is not set (set {}=1 for offline embeddings)", OFFLINE_ENV))
+    })?;
+    let mut client = JinaClient::new(&key);
+    if let Some(model) = env("JINA_MODEL") {
+        client = client.with_model(&model);
+    }
+    if let Some(url) = env("JINA_BASE_URL") {
+        client = client.with_base_url(&url);
+    }
+    Ok(client.with_http())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    
+    #[test]
+    fn test_concurrent_first_use_builds_one_client() {
+        let global = Global::new();
+        let builds = AtomicUsize::new(0);
+        let env = |name: &str| {
+            if name == OFFLINE_ENV {
+                builds.fetch_add(1, Ordering::Relaxed);
+            }
+            (name == OFFLINE_ENV).then(|| "1".to_string())
+        };
+        let clients: Vec<usize> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8).map(|_| scope.spawn(|| global.client(env).unwrap() as *const JinaClient as usize)).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert_eq!(builds.load(Ordering::Relaxed), 1);
+        assert!(clients.iter().all(|&c| c == clients[0]));
+        assert!(!global.client(env).unwrap().is_online());
+        
+        let err = global.configure(JinaClient::new("")).unwrap_err();
+        assert!(matches!(err, JinaError::InvalidInput(m) if m.contains("already initialized")));
+    }
+    
+    #[test]
+    fn test_environment_selects_the_client() {
+        let vars = |pairs: &'static [(&'static str, &'static str)]| {
+            move |name: &str| pairs.iter().find(|(n, _)| *n == name).map(|(_, v)| v.to_string())
+        };
+        let missing = Global::new();
+        let Err(err) = missing.client(vars(&[(OFFLINE_ENV, "0")])) else { panic!("built without a key") };
+        assert!(matches!(&err, JinaError::InvalidInput(m) if m.contains("JINA_API_KEY")));
+        // The failure is kept, not retried
+        assert!(matches!(missing.client(vars(&[(OFFLINE_ENV, "1")])), Err(e) if e == err));
+        
+        let online = Global::new();
+        let client = online.client(vars(&[("JINA_API_KEY", "jina_global_test"), ("JINA_MODEL", "jina-embeddings-v2-base-en")])).unwrap();
+        assert!(client.is_online());
+        assert_eq!(client.model, "jina-embeddings-v2-base-en");
+        
+        // A configured client wins over the environment
+        let configured = Global::new();
+        configured.configure(JinaClient::new("").with_model("mine")).unwrap();
+        assert_eq!(configured.client(vars(&[("JINA_API_KEY", "jina_global_test")])).unwrap().model, "mine");
+    }
+}
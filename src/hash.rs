@@ -0,0 +1,215 @@
+//! Stable content hashing for cache keys and ids
+//!
+//! `std`'s `DefaultHasher` may change between Rust releases, so anything
+//! written to disk is keyed with SHA-256 instead (implemented here, checked
+//! against the FIPS 180-4 test vectors). `content_key` hashes the model, a
+//! canonical form of the options and the text, each length-prefixed behind
+//! a versioned domain tag:
+//!
+//! ```text
+//! "spo-crystal/content-key/v1" || u64le(len) model || u64le(len) options || u64le(len) text
+//! ```
+//!
+//! `canonical_options` spells every keyed option in fixed order, so adding
+//! or reordering `EmbedOptions` fields cannot change existing keys; a
+//! change to the layout gets a new tag. `to_hex` and `to_base58` encode
+//! keys for file names and ids.
+
+use crate::jina_api::EmbedOptions;
+
+/// 32-byte SHA-256 digest
+pub type ContentKey = [u8; 32];
+
+/// Domain tag of `content_key`; bump on any change to its input layout
+pub const CONTENT_KEY_TAG: &str = "spo-crystal/content-key/v1";
+
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const H0: [u32; 8] = [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
+
+/// Incremental SHA-256
+#[derive(Clone)]
+pub struct Sha256 {
+    state: [u32; 8],
+    block: [u8; 64],
+    filled: usize,
+    /// Bytes hashed so far
+    length: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self { Self { state: H0, block: [0; 64], filled: 0, length: 0 } }
+}
+
+impl Sha256 {
+    pub fn new() -> Self { Self::default() }
+    
+    pub fn update(&mut self, mut bytes: &[u8]) {
+        self.length += bytes.len() as u64;
+        while !bytes.is_empty() {
+            let take = (64 - self.filled).min(bytes.len());
+            self.block[self.filled..self.filled + take].copy_from_slice(&bytes[..take]);
+            self.filled += take;
+            bytes = &bytes[take..];
+            if self.filled == 64 {
+                compress(&mut self.state, &self.block);
+                self.filled = 0;
+            }
+        }
+    }
+    
+    pub fn finalize(mut self) -> ContentKey {
+        let bits = self.length.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.filled != 56 {
+            self.update(&[0]);
+        }
+        self.update(&bits.to_be_bytes());
+        let mut out = [0u8; 32];
+        for (chunk, word) in out.chunks_exact_mut(4).zip(self.state) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        out
+    }
+}
+
+fn compress(state: &mut [u32; 8], block: &[u8; 64]) {
+    let mut w = [0u32; 64];
+    for (i, chunk) in block.chunks_exact(4).enumerate() {
+        w[i] = u32::from_be_bytes(chunk.try_into().unwrap());
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        (h, g, f, e, d, c, b, a) = (g, f, e, d.wrapping_add(t1), c, b, a, t1.wrapping_add(t2));
+    }
+    for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *s = s.wrapping_add(v);
+    }
+}
+
+pub fn sha256(bytes: &[u8]) -> ContentKey {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    hasher.finalize()
+}
+
+/// The options that change a vector, as JSON with keys in fixed order:
+/// `{"dimensions":…,"late_chunking":…,"task":…}`, unset values `null`.
+///
+/// `preprocess` is left out: keys are taken over the cleaned text.
+pub fn canonical_options(options: &EmbedOptions) -> String {
+    format!(r#"{{"dimensions":{},"late_chunking":{},"task":{}}}"#,
+            options.dimensions.map_or("null".to_string(), |d| d.to_string()),
+            options.late_chunking,
+            options.task.map_or("null".to_string(), |t| format!("\"{}\"", t.as_str())))
+}
+
+/// Key of the vector `model` gives `text` under `options`
+pub fn content_key(model: &str, options: &EmbedOptions, text: &str) -> ContentKey {
+    let mut hasher = Sha256::new();
+    hasher.update(CONTENT_KEY_TAG.as_bytes());
+    for field in [model, &canonical_options(options), text] {
+        hasher.update(&(field.len() as u64).to_le_bytes());
+        hasher.update(field.as_bytes());
+    }
+    hasher.finalize()
+}
+
+/// Lowercase hex, two digits per byte
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Bitcoin-alphabet base58: no `0`, `O`, `I` or `l`; each leading zero byte is a `1`
+pub fn to_base58(bytes: &[u8]) -> String {
+    let zeros = bytes.iter().take_while(|&&b| b == 0).count();
+    // Base-58 digits, least significant first
+    let mut digits: Vec<u8> = Vec::with_capacity(bytes.len() * 138 / 100 + 1);
+    for &byte in &bytes[zeros..] {
+        let mut carry = byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    std::iter::repeat_n('1', zeros)
+        .chain(digits.iter().rev().map(|&d| BASE58_ALPHABET[d as usize] as char))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jina_api::Task;
+    
+    #[test]
+    fn test_sha256_known_vectors() {
+        assert_eq!(to_hex(&sha256(b"")), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(to_hex(&sha256(b"abc")), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(to_hex(&sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+                   "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+        // A million 'a's, fed in uneven pieces
+        let mut hasher = Sha256::new();
+        let a = [b'a'; 1000];
+        for i in 0..1000 {
+            let (x, y) = a.split_at(i % 64);
+            hasher.update(x);
+            hasher.update(y);
+        }
+        assert_eq!(to_hex(&hasher.finalize()), "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0");
+    }
+    
+    #[test]
+    fn test_content_keys_are_pinned() {
+        assert_eq!(canonical_options(&EmbedOptions::default()), r#"{"dimensions":null,"late_chunking":false,"task":null}"#);
+        let options = EmbedOptions::default().with_dimensions(256).with_task(Task::RetrievalPassage);
+        assert_eq!(canonical_options(&options), r#"{"dimensions":256,"late_chunking":false,"task":"retrieval.passage"}"#);
+        // Builder order and preprocessing do not change the key
+        let reordered = EmbedOptions::passage().with_dimensions(256).with_preprocess(crate::preprocess::Pipeline::html());
+        assert_eq!(content_key("m", &options, "t"), content_key("m", &reordered, "t"));
+        
+        assert_eq!(to_hex(&content_key("jina-embeddings-v3", &EmbedOptions::default(), "Ada")),
+                   "4f4b4e4c887d75fbed98f51ceddb7ab6e6294c0ab856ad9a506a29ff4d885b90");
+        assert_eq!(to_hex(&content_key("jina-embeddings-v3", &options, "Ada")),
+                   "3ebe465a1cefdf8c4ee1ef999620fab6398fd44e7d1add6bfc1370a6c27e5744");
+        // Length prefixes keep field boundaries apart
+        assert_ne!(content_key("ab", &options, "c"), content_key("a", &options, "bc"));
+    }
+    
+    #[test]
+    fn test_encodings() {
+        assert_eq!(to_hex(&[0x00, 0x0f, 0xa5, 0xff]), "000fa5ff");
+        assert_eq!(to_base58(b""), "");
+        assert_eq!(to_base58(b"hello world"), "StV1DL6CwTryKyV");
+        assert_eq!(to_base58(&[0, 0, 0x28, 0x7f, 0xb4, 0xcd]), "11233QC4");
+        let key = sha256(b"abc");
+        assert_eq!(to_base58(&key), "DYu3G8aGTMBW1WrTw76zxQJQU4DHLw9MLyy7peG4LKkY");
+    }
+}
@@ -0,0 +1,177 @@
+//! String ids for `CrystalIndex` entries
+//!
+//! Documents elsewhere are keyed by UUIDs, paths or URLs; the index wants
+//! dense u64 ids. An `IdMap` assigns each external key the lowest id free,
+//! so ids stay dense as entries come and go. `CrystalIndex::add_keyed`,
+//! `remove_keyed` and `search_keyed` take and return keys, and the index
+//! keeps the map in its file next to the entries.
+//!
+//! A removed entry's id goes back to the map only at `compact()`, once its
+//! tombstoned row is gone: until then no new key can take it. Adding a key
+//! already present fails with `IndexError::DuplicateKey` unless the map was
+//! built `with_upsert`, in which case the entry is replaced under its id.
+
+use std::collections::{BTreeSet, HashMap};
+
+/// External key ↔ dense internal id
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IdMap {
+    upsert: bool,
+    ids: HashMap<String, u64>,
+    keys: HashMap<u64, String>,
+    /// Lowest id never assigned
+    next: u64,
+    /// Ids free to assign again, lowest first
+    free: BTreeSet<u64>,
+    /// Ids of removed keys, free once the index compacts
+    released: Vec<u64>,
+}
+
+impl IdMap {
+    pub fn new() -> Self { Self::default() }
+    
+    /// Replace the entry of a key added again instead of failing
+    pub fn with_upsert(mut self) -> Self {
+        self.upsert = true;
+        self
+    }
+    
+    pub fn upserts(&self) -> bool { self.upsert }
+    
+    pub fn len(&self) -> usize { self.ids.len() }
+    
+    pub fn is_empty(&self) -> bool { self.ids.is_empty() }
+    
+    /// Internal id of `key`
+    pub fn id(&self, key: &str) -> Option<u64> { self.ids.get(key).copied() }
+    
+    /// External key of internal `id`
+    pub fn key(&self, id: u64) -> Option<&str> { self.keys.get(&id).map(String::as_str) }
+    
+    /// A fresh id for `key`, the lowest neither assigned nor `taken`
+    pub(crate) fn assign(&mut self, key: &str, taken: impl Fn(u64) -> bool) -> u64 {
+        let id = match self.free.iter().copied().find(|&id| !taken(id)) {
+            Some(id) => {
+                self.free.remove(&id);
+                id
+            }
+            None => {
+                while taken(self.next) {
+                    self.next += 1;
+                }
+                self.next += 1;
+                self.next - 1
+            }
+        };
+        self.insert(key, id);
+        id
+    }
+    
+    /// Record `key` under `id`, as loaded from a file
+    pub(crate) fn insert(&mut self, key: &str, id: u64) {
+        if let Some(old) = self.keys.insert(id, key.to_string()) {
+            self.ids.remove(&old);
+        }
+        self.ids.insert(key.to_string(), id);
+        self.free.remove(&id);
+        self.next = self.next.max(id + 1);
+    }
+    
+    /// Forget the key of `id`, holding the id back until `reclaim`
+    pub(crate) fn release(&mut self, id: u64) -> Option<String> {
+        let key = self.keys.remove(&id)?;
+        self.ids.remove(&key);
+        self.released.push(id);
+        Some(key)
+    }
+    
+    /// Make released ids assignable, once no row holds them
+    pub(crate) fn reclaim(&mut self) {
+        self.free.extend(self.released.drain(..));
+    }
+    
+    /// Heap bytes, estimated
+    pub(crate) fn bytes(&self) -> usize {
+        let keys: usize = self.keys.values().map(String::capacity).sum();
+        2 * keys + (self.ids.capacity() + self.keys.capacity()) * (size_of::<String>() + size_of::<u64>())
+            + (self.free.len() + self.released.capacity()) * size_of::<u64>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::{CrystalIndex, IndexError};
+    use crate::metadata::Metadata;
+    
+    fn axis(i: usize) -> Vec<f32> {
+        let mut v = vec![0.0; 4];
+        v[i % 4] = 1.0;
+        v
+    }
+    
+    #[test]
+    fn test_keys_cycle_through_dense_ids() {
+        let mut index = CrystalIndex::new(4);
+        for (i, key) in ["doc/a", "doc/b", "doc/c"].iter().enumerate() {
+            assert_eq!(index.add_keyed(key, &axis(i), Metadata::new()).unwrap(), i as u64);
+        }
+        assert_eq!(index.add_keyed("doc/b", &axis(3), Metadata::new()),
+                   Err(IndexError::DuplicateKey("doc/b".to_string())));
+        assert_eq!(index.search_keyed(&axis(1), 1, None), [("doc/b".to_string(), 1.0)]);
+        
+        // A removed key's id is held back until compaction
+        assert!(index.remove_keyed("doc/b") && !index.remove_keyed("doc/b"));
+        assert_eq!(index.add_keyed("doc/d", &axis(3), Metadata::new()).unwrap(), 3);
+        assert_eq!(index.add_keyed("doc/b", &axis(1), Metadata::new()).unwrap(), 4);
+        assert!(index.remove_keyed("doc/b"));
+        index.compact();
+        // Entries added by plain id keep theirs
+        index.add(1, &axis(2)).unwrap();
+        assert_eq!(index.add_keyed("doc/e", &axis(0), Metadata::new()).unwrap(), 4);
+        assert_eq!(index.id_map().unwrap().key(4), Some("doc/e"));
+        assert!(index.search_keyed(&axis(1), 5, None).iter().all(|(key, _)| key != "doc/b"));
+        let hits = index.search_keyed(&axis(2), 2, None);
+        assert_eq!(hits[..2], [("1".to_string(), 1.0), ("doc/c".to_string(), 1.0)]);
+        
+        let mut upserting = CrystalIndex::new(4).with_id_map(IdMap::new().with_upsert());
+        upserting.add_keyed("x", &axis(0), Metadata::new()).unwrap();
+        assert_eq!(upserting.add_keyed("x", &axis(1), Metadata::new().with("v", 2i64)).unwrap(), 0);
+        assert_eq!((upserting.len(), upserting.get(0).unwrap()), (1, axis(1).as_slice()));
+        assert_eq!(upserting.search_keyed(&axis(0), 5, None), [("x".to_string(), 0.0)]);
+        assert!(upserting.add_keyed("x", &[1.0], Metadata::new()).is_err());
+        assert_eq!(upserting.metadata(0).unwrap().get_num("v"), Some(2.0));
+    }
+    
+    #[test]
+    fn test_mapping_persists_and_reused_ids_stay_dead() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keyed.idx");
+        let path = path.to_str().unwrap();
+        let mut index = CrystalIndex::new(4);
+        index.add_keyed("a", &axis(0), Metadata::new()).unwrap();
+        index.add_keyed("b", &axis(1), Metadata::new()).unwrap();
+        index.save(path).unwrap();
+        index.remove_keyed("a");
+        index.add_keyed("c", &axis(2), Metadata::new()).unwrap();
+        index.save_incremental(path).unwrap();
+        
+        let mut loaded = CrystalIndex::load(path).unwrap();
+        let map = loaded.id_map().unwrap();
+        assert_eq!((map.len(), map.id("b"), map.id("c"), map.key(0)), (2, Some(1), Some(2), None));
+        assert_eq!(loaded.search_keyed(&axis(2), 1, None), [("c".to_string(), 1.0)]);
+        
+        // "a"'s id goes to "d" after compaction; "a"'s vector never comes back
+        index.compact();
+        assert_eq!(index.add_keyed("d", &axis(3), Metadata::new()).unwrap(), 0);
+        index.save_incremental(path).unwrap();
+        loaded = CrystalIndex::load(path).unwrap();
+        for index in [&index, &loaded] {
+            let hits = index.search_keyed(&axis(0), 3, None);
+            assert!(hits.iter().all(|(key, score)| key != "a" && *score < 0.5), "{:?}", hits);
+            assert_eq!(index.search_keyed(&axis(3), 1, None), [("d".to_string(), 1.0)]);
+        }
+        loaded.save(path).unwrap();
+        assert_eq!(CrystalIndex::load(path).unwrap().id_map().unwrap().key(0), Some("d"));
+    }
+}
@@ -0,0 +1,1939 @@
+//! Crystal Index: dense embedding store with cosine search
+//!
+//! An index created `with_metric` ranks by dot product or Euclidean
+//! distance instead (see `search::Metric`); the metric is saved with it.
+//!
+//! Persistence is an append-only update log:
+//! 1. `save(path)` writes a base snapshot of all live vectors
+//! 2. `save_incremental(path)` appends the adds/removes since the last save
+//! 3. `load(path)` replays snapshot + increments
+//!
+//! Every increment is length-prefixed and checksummed, so a torn write
+//! at the end of the file is detected and ignored on load, and cut off
+//! by the next `save_incremental` before it appends.
+//! `remove(id)` leaves a tombstone that search skips until `compact()`.
+//! `stats()` reports live rows, tombstones and the memory they hold.
+//!
+//! Entries carry typed `Metadata`; `search_filtered` applies a filter
+//! before scoring so excluded entries never cost a dot product.
+//! `search_with` takes `SearchOptions`: its `min_score` drops hits after
+//! the filter and ranking, so it can return fewer than `k` hits or none,
+//! and its `expansion` searches a second time with the query moved toward
+//! the first hits.
+//!
+//! With `Quantization::Int8` vectors are stored on disk as int8 plus a
+//! scale (about 4x smaller) and rounded the same way in memory, so search
+//! results do not change across a save and load.
+//!
+//! Entries added `add_with_sparse` also keep a `SparseVector`, written as an
+//! extra op after the snapshot and in increments; `search_hybrid` ranks by
+//! `sparse::hybrid_score` of the dense and sparse cosines.
+//!
+//! An index created `with_provenance` records how its vectors were embedded,
+//! in memory and in its file. `add_checked`, `add_response` and
+//! `search_checked` refuse vectors of another provenance with a typed
+//! `ProvenanceMismatch` unless passed `ProvenanceCheck::Override`.
+//!
+//! One created `with_schema` validates the metadata of every add against a
+//! `MetadataSchema` saved in its file; `check_filter` validates the fields a
+//! filter reads, and `migrate_schema` moves live entries to a new schema.
+//!
+//! Entries can also be keyed by strings (`add_keyed`, `search_keyed`):
+//! an `id_map::IdMap` assigns them dense ids and is saved with the index.
+//!
+//! One created `with_query_cache` keeps recent hit lists in a
+//! `query_cache::QueryCache`, dropped whenever the index's `generation`
+//! moves on: every add, remove, schema migration and compaction bumps it.
+//!
+//! `search`, `search_filtered` and `search_with` score in blocks of
+//! `CAP_BLOCK` dimensions, keeping only the best `k` so far. Each row holds
+//! the norms of its remaining blocks, which bound what the rest of its dot
+//! product can add; once that bound cannot lift a row past the current
+//! k-th score, the row is abandoned. Models that front-load their vectors
+//! (Matryoshka embeddings such as jina-embeddings-v3) abandon most rows a
+//! few blocks in. Results are exactly those of `search_exhaustive`: kept
+//! rows' dot products are summed in the same order, and the bound allows
+//! for float rounding. Small corpora or large `k` take the exhaustive path.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+
+use crate::error::ProvenanceMismatch;
+use crate::id_map::IdMap;
+use crate::io::atomic_write;
+use crate::metadata::Metadata;
+use crate::provenance::{Provenance, ProvenanceCheck};
+use crate::provider::EmbeddingResponse;
+use crate::query_cache::{QueryCache, QueryCacheStats};
+use crate::quantize::Int8Vector;
+use crate::schema::{MetadataSchema, SchemaError, SchemaMigration};
+use crate::search::{dot, norm, score_order, Hit, Metric, SearchOptions};
+use crate::sparse::{hybrid_score, sparse_cosine, SparseVector};
+
+const SNAPSHOT_MAGIC: &[u8; 6] = b"SPOIDX";
+const FORMAT_VERSION: &[u8; 2] = b"06";
+/// Before metrics: always cosine, a schema after the provenance; still written by cosine indexes with a schema
+const COSINE_VERSION: &[u8; 2] = b"05";
+/// Before schemas: nothing after the provenance; still written by cosine indexes without a schema
+const UNSCHEMED_VERSION: &[u8; 2] = b"04";
+/// Before provenance: no provenance flag after the mode byte
+const UNPROVENANCED_VERSION: &[u8; 2] = b"03";
+/// Before quantization: no mode byte, always f32
+const LEGACY_VERSION: &[u8; 2] = b"02";
+const INCREMENT_TAG: u8 = b'I';
+const OP_ADD: u8 = 1;
+const OP_REMOVE: u8 = 2;
+/// Sparse vector of the live entry with this id
+const OP_SPARSE: u8 = 3;
+/// External key of the live entry with this id
+const OP_KEY: u8 = 4;
+
+/// Dimensions scored between early-abandon checks
+pub const CAP_BLOCK: usize = 32;
+/// Below this many live rows per hit, searches score every row in full
+const PRUNE_MIN_ROWS_PER_K: usize = 16;
+
+/// Change recorded since the last save
+#[derive(Clone)]
+enum LogOp {
+    Add(u64),
+    Remove(u64),
+}
+
+/// Search filter over entry metadata
+pub type Filter<'a> = &'a dyn Fn(&Metadata) -> bool;
+
+/// How vectors are stored
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Quantization {
+    #[default]
+    None,
+    /// `quantize::Int8Vector`: one f32 scale and one i8 per component
+    Int8,
+}
+
+impl Quantization {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Quantization::None => "none",
+            Quantization::Int8 => "int8",
+        }
+    }
+    
+    /// `v` as it reads back from disk
+    pub(crate) fn round(self, v: &[f32]) -> Vec<f32> {
+        match self {
+            Quantization::None => v.to_vec(),
+            Quantization::Int8 => Int8Vector::quantize(v).dequantize(),
+        }
+    }
+    
+    fn encoded_len(self, dims: usize) -> usize {
+        match self {
+            Quantization::None => dims * 4,
+            Quantization::Int8 => 4 + dims,
+        }
+    }
+    
+    fn encode(self, v: &[f32], out: &mut Vec<u8>) {
+        match self {
+            Quantization::None => for x in v { out.extend_from_slice(&x.to_le_bytes()); },
+            Quantization::Int8 => {
+                let q = Int8Vector::quantize(v);
+                out.extend_from_slice(&q.scale.to_le_bytes());
+                out.extend(q.values.iter().map(|&x| x as u8));
+            }
+        }
+    }
+    
+    fn decode(self, bytes: &[u8], pos: usize, dims: usize) -> Vec<f32> {
+        match self {
+            Quantization::None => read_f32s(bytes, pos, dims),
+            Quantization::Int8 => Int8Vector {
+                scale: f32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap()),
+                values: bytes[pos + 4..pos + 4 + dims].iter().map(|&x| x as i8).collect(),
+            }.dequantize(),
+        }
+    }
+}
+
+/// Size and memory use of a `CrystalIndex`
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct IndexStats {
+    /// Live entries
+    pub vectors: usize,
+    /// Removed rows held until `compact()`
+    pub tombstones: usize,
+    pub dimension: usize,
+    /// Allocated vector and norm storage, tombstoned rows included; vectors
+    /// are f32 in memory whatever the quantization on disk
+    pub bytes_vectors: usize,
+    /// Graph links; always 0, this index is flat
+    pub bytes_graph: usize,
+    /// Ids, flags, the id map, metadata and sparse vectors, estimated
+    pub bytes_other: usize,
+    pub quantization: Quantization,
+}
+
+impl IndexStats {
+    pub fn bytes_total(&self) -> usize { self.bytes_vectors + self.bytes_graph + self.bytes_other }
+}
+
+/// Error from the provenance-checked index calls
+#[derive(Clone, Debug, PartialEq)]
+pub enum IndexError {
+    ProvenanceMismatch(ProvenanceMismatch),
+    /// Metadata the index's schema refuses
+    Schema(SchemaError),
+    /// Dimension mismatch, duplicate id or a wrong number of ids
+    Rejected(String),
+    /// `add_keyed` of a key already present, without `IdMap::with_upsert`
+    DuplicateKey(String),
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexError::ProvenanceMismatch(e) => write!(f, "{}", e),
+            IndexError::Schema(e) => write!(f, "{}", e),
+            IndexError::Rejected(msg) => write!(f, "{}", msg),
+            IndexError::DuplicateKey(key) => write!(f, "Key {:?} already present", key),
+        }
+    }
+}
+
+impl std::error::Error for IndexError {}
+
+impl From<ProvenanceMismatch> for IndexError {
+    fn from(e: ProvenanceMismatch) -> Self { IndexError::ProvenanceMismatch(e) }
+}
+
+impl From<SchemaError> for IndexError {
+    fn from(e: SchemaError) -> Self { IndexError::Schema(e) }
+}
+
+impl From<IndexError> for String {
+    fn from(e: IndexError) -> Self { e.to_string() }
+}
+
+/// Flat vector index keyed by u64 ids
+#[derive(Clone)]
+pub struct CrystalIndex {
+    dims: usize,
+    quantization: Quantization,
+    metric: Metric,
+    provenance: Option<Provenance>,
+    schema: Option<MetadataSchema>,
+    
+    /// Row-major vector storage, `dims` floats per row
+    vectors: Vec<f32>,
+    ids: Vec<u64>,
+    norms: Vec<f32>,
+    /// Per row, the norms of its dims from each block boundary after the first on
+    caps: Vec<f32>,
+    metadata: Vec<Metadata>,
+    sparse: Vec<Option<SparseVector>>,
+    
+    /// Tombstone flags (false = removed, skipped by search)
+    live: Vec<bool>,
+    
+    /// id → row of the live entry
+    rows: HashMap<u64, usize>,
+    
+    /// Changes not yet written by `save` / `save_incremental`
+    pending: Vec<LogOp>,
+    
+    /// Bumped by every change to what a search can return
+    generation: u64,
+    query_cache: Option<QueryCache>,
+    /// External keys of entries added `add_keyed`
+    id_map: Option<IdMap>,
+    /// File last saved or loaded, and where its last complete block ends
+    log_end: Option<(String, u64)>,
+}
+
+impl CrystalIndex {
+    pub fn new(dims: usize) -> Self {
+        Self {
+            dims,
+            quantization: Quantization::None,
+            metric: Metric::Cosine,
+            provenance: None,
+            schema: None,
+            vectors: Vec::new(),
+            ids: Vec::new(),
+            norms: Vec::new(),
+            caps: Vec::new(),
+            metadata: Vec::new(),
+            sparse: Vec::new(),
+            live: Vec::new(),
+            rows: HashMap::new(),
+            pending: Vec::new(),
+            generation: 0,
+            query_cache: None,
+            id_map: None,
+            log_end: None,
+        }
+    }
+    
+    /// Store vectors as `quantization`; vectors already added are rounded to it
+    pub fn with_quantization(mut self, quantization: Quantization) -> Self {
+        self.quantization = quantization;
+        for row in 0..self.ids.len() {
+            let rounded = quantization.round(self.row(row));
+            self.norms[row] = norm(&rounded);
+            let per_row = self.caps_per_row();
+            self.caps[row * per_row..(row + 1) * per_row].copy_from_slice(&suffix_norms(&rounded));
+            self.vectors[row * self.dims..(row + 1) * self.dims].copy_from_slice(&rounded);
+        }
+        self.generation += 1;
+        self
+    }
+    
+    /// Rank by `metric` rather than cosine
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+    
+    /// Answer repeated unfiltered and named-filter searches from `cache`
+    pub fn with_query_cache(mut self, cache: QueryCache) -> Self {
+        self.query_cache = Some(cache);
+        self
+    }
+    
+    /// Record how this index's vectors are embedded, for the checked calls and the file
+    pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = Some(provenance);
+        self
+    }
+    
+    /// Validate the metadata of entries added from now on against `schema`;
+    /// entries already added are moved to a schema with `migrate_schema`
+    pub fn with_schema(mut self, schema: MetadataSchema) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+    
+    /// Key entries through `map`, e.g. one `with_upsert`; an index makes
+    /// its own on the first `add_keyed` otherwise
+    pub fn with_id_map(mut self, map: IdMap) -> Self {
+        self.id_map = Some(map);
+        self
+    }
+    
+    pub fn dims(&self) -> usize { self.dims }
+    
+    pub fn quantization(&self) -> Quantization { self.quantization }
+    
+    pub fn metric(&self) -> Metric { self.metric }
+    
+    pub fn provenance(&self) -> Option<&Provenance> { self.provenance.as_ref() }
+    
+    pub fn schema(&self) -> Option<&MetadataSchema> { self.schema.as_ref() }
+    
+    pub fn id_map(&self) -> Option<&IdMap> { self.id_map.as_ref() }
+    
+    /// Changes so far to what a search can return; cached hit lists are
+    /// only served under the generation they were found in
+    pub fn generation(&self) -> u64 { self.generation }
+    
+    /// `None` without `with_query_cache`
+    pub fn query_cache_stats(&self) -> Option<QueryCacheStats> { self.query_cache.as_ref().map(QueryCache::stats) }
+    
+    /// Whether a filter reading `fields` reads only fields of the schema; any
+    /// fields pass an index without one
+    pub fn check_filter<'a>(&self, fields: impl IntoIterator<Item = &'a str>) -> Result<(), SchemaError> {
+        self.schema.as_ref().map_or(Ok(()), |schema| schema.check_filter(fields))
+    }
+    
+    /// Whether vectors of provenance `found` may be added or searched for.
+    ///
+    /// An index without a provenance accepts anything; one with a provenance
+    /// refuses other and unknown provenances unless `check` is `Override`.
+    pub fn check_provenance(&self, found: Option<&Provenance>, check: ProvenanceCheck) -> Result<(), ProvenanceMismatch> {
+        match &self.provenance {
+            Some(expected) if check == ProvenanceCheck::Enforce && found != Some(expected) => {
+                Err(ProvenanceMismatch { expected: Box::new(expected.clone()), found: found.cloned().map(Box::new) })
+            }
+            _ => Ok(()),
+        }
+    }
+    
+    /// Why a query of provenance `found` may score on another scale than
+    /// this index's vectors, if it may.
+    ///
+    /// Cosine ignores norms; dot products and distances do not, so under
+    /// those a query normalized differently from the index is flagged, as is
+    /// one of unknown provenance against an index with one.
+    pub fn metric_warning(&self, found: Option<&Provenance>) -> Option<String> {
+        let expected = self.provenance.as_ref()?;
+        if self.metric == Metric::Cosine {
+            return None;
+        }
+        let describe = |normalized: bool| if normalized { "normalized" } else { "unnormalized" };
+        match found {
+            None => Some(format!("Index ranks by {} over {} vectors; the query's normalization is unknown",
+                                 self.metric.as_str(), describe(expected.normalized))),
+            Some(found) if found.normalized != expected.normalized => {
+                Some(format!("Index ranks by {} over {} vectors, the query is {}",
+                             self.metric.as_str(), describe(expected.normalized), describe(found.normalized)))
+            }
+            Some(_) => None,
+        }
+    }
+    
+    /// Number of live (non-removed) vectors
+    pub fn len(&self) -> usize { self.rows.len() }
+    
+    pub fn is_empty(&self) -> bool { self.rows.is_empty() }
+    
+    /// Number of removed rows still held until `compact()`
+    pub fn tombstones(&self) -> usize { self.ids.len() - self.rows.len() }
+    
+    pub fn contains(&self, id: u64) -> bool { self.rows.contains_key(&id) }
+    
+    pub fn stats(&self) -> IndexStats {
+        IndexStats {
+            vectors: self.len(),
+            tombstones: self.tombstones(),
+            dimension: self.dims,
+            bytes_vectors: (self.vectors.capacity() + self.norms.capacity() + self.caps.capacity()) * size_of::<f32>(),
+            bytes_graph: 0,
+            bytes_other: self.bytes_other(),
+            quantization: self.quantization,
+        }
+    }
+    
+    /// Live ids in insertion order
+    pub fn ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.ids.iter().zip(&self.live).filter(|(_, &live)| live).map(|(&id, _)| id)
+    }
+    
+    pub fn get(&self, id: u64) -> Option<&[f32]> {
+        self.rows.get(&id).map(|&row| self.row(row))
+    }
+    
+    pub fn metadata(&self, id: u64) -> Option<&Metadata> {
+        self.rows.get(&id).map(|&row| &self.metadata[row])
+    }
+    
+    pub fn sparse(&self, id: u64) -> Option<&SparseVector> {
+        self.rows.get(&id).and_then(|&row| self.sparse[row].as_ref())
+    }
+    
+    /// Add a vector; fails on dimension mismatch or if `id` is already live
+    pub fn add(&mut self, id: u64, vector: &[f32]) -> Result<(), String> {
+        self.add_with_metadata(id, vector, Metadata::new())
+    }
+    
+    /// Add a vector with metadata for filtered search; fails as well if the
+    /// schema refuses `metadata`
+    pub fn add_with_metadata(&mut self, id: u64, vector: &[f32], metadata: Metadata) -> Result<(), String> {
+        self.insert(id, vector, metadata).map_err(String::from)
+    }
+    
+    fn insert(&mut self, id: u64, vector: &[f32], metadata: Metadata) -> Result<(), IndexError> {
+        if self.rows.contains_key(&id) {
+            return Err(IndexError::Rejected(format!("Id {} already present", id)));
+        }
+        self.check_entry(vector, &metadata)?;
+        self.push_row(id, &self.quantization.round(vector), metadata);
+        self.pending.push(LogOp::Add(id));
+        Ok(())
+    }
+    
+    fn check_entry(&self, vector: &[f32], metadata: &Metadata) -> Result<(), IndexError> {
+        if vector.len() != self.dims {
+            return Err(IndexError::Rejected(format!("Dimension mismatch: expected {}, got {}", self.dims, vector.len())));
+        }
+        if let Some(schema) = &self.schema {
+            schema.validate(metadata)?;
+        }
+        Ok(())
+    }
+    
+    /// Add a vector under the external `key`, returning the id it gets.
+    ///
+    /// A key already present fails with `DuplicateKey`, or with an upserting
+    /// `IdMap` replaces that entry's vector and metadata under the same id.
+    pub fn add_keyed(&mut self, key: &str, vector: &[f32], metadata: Metadata) -> Result<u64, IndexError> {
+        self.check_entry(vector, &metadata)?;
+        let map = self.id_map.get_or_insert_default();
+        let id = match map.id(key) {
+            Some(_) if !map.upserts() => return Err(IndexError::DuplicateKey(key.to_string())),
+            Some(id) => {
+                if let Some(row) = self.rows.remove(&id) {
+                    self.live[row] = false;
+                }
+                id
+            }
+            None => map.assign(key, |id| self.rows.contains_key(&id)),
+        };
+        self.push_row(id, &self.quantization.round(vector), metadata);
+        self.pending.push(LogOp::Add(id));
+        Ok(id)
+    }
+    
+    /// `remove` by external key
+    pub fn remove_keyed(&mut self, key: &str) -> bool {
+        match self.id_map.as_ref().and_then(|map| map.id(key)) {
+            Some(id) => self.remove(id),
+            None => false,
+        }
+    }
+    
+    /// `search_filtered` with hits by external key; entries added by plain
+    /// id come back as that id in decimal
+    pub fn search_keyed(&self, query: &[f32], k: usize, filter: Option<Filter>) -> Vec<(String, f32)> {
+        self.search_filtered(query, k, filter).into_iter()
+            .map(|(id, score)| (self.id_map.as_ref().and_then(|map| map.key(id)).map_or_else(|| id.to_string(), str::to_string), score))
+            .collect()
+    }
+    
+    /// `add_with_metadata` with a sparse vector for `search_hybrid`
+    pub fn add_with_sparse(&mut self, id: u64, vector: &[f32], sparse: SparseVector, metadata: Metadata) -> Result<(), String> {
+        self.add_with_metadata(id, vector, metadata)?;
+        let row = self.rows[&id];
+        self.sparse[row] = Some(sparse);
+        self.generation += 1;
+        Ok(())
+    }
+    
+    /// `add_with_metadata` for a vector embedded under `provenance`
+    pub fn add_checked(&mut self, id: u64, vector: &[f32], metadata: Metadata, provenance: Option<&Provenance>,
+                       check: ProvenanceCheck) -> Result<(), IndexError> {
+        self.check_provenance(provenance, check)?;
+        self.insert(id, vector, metadata)
+    }
+    
+    /// Add `response.embeddings` under `ids`, checking the response's provenance.
+    ///
+    /// Nothing is added unless every vector can be.
+    pub fn add_response(&mut self, ids: &[u64], response: &EmbeddingResponse, check: ProvenanceCheck) -> Result<(), IndexError> {
+        self.check_provenance(response.provenance.as_ref(), check)?;
+        if ids.len() != response.embeddings.len() {
+            return Err(IndexError::Rejected(format!("{} ids for {} vectors", ids.len(), response.embeddings.len())));
+        }
+        if let Some(schema) = &self.schema {
+            schema.validate(&Metadata::new())?;
+        }
+        let mut seen = HashSet::new();
+        for (&id, vector) in ids.iter().zip(&response.embeddings) {
+            if vector.len() != self.dims {
+                return Err(IndexError::Rejected(format!("Dimension mismatch: expected {}, got {}", self.dims, vector.len())));
+            }
+            if self.rows.contains_key(&id) || !seen.insert(id) {
+                return Err(IndexError::Rejected(format!("Id {} already present", id)));
+            }
+        }
+        for (&id, vector) in ids.iter().zip(&response.embeddings) {
+            self.insert(id, vector, Metadata::new())?;
+        }
+        Ok(())
+    }
+    
+    /// Move every live entry to `schema`, renaming fields and filling in
+    /// defaults as `migration` says; returns the number of entries changed.
+    ///
+    /// A field `schema` requires that the current schema did not must be
+    /// renamed to or given a default. Nothing changes unless every entry
+    /// then fits `schema`. The next save must be a full `save`.
+    pub fn migrate_schema(&mut self, schema: MetadataSchema, migration: &SchemaMigration) -> Result<usize, SchemaError> {
+        migration.check(self.schema.as_ref(), &schema)?;
+        let mut changed = Vec::new();
+        for row in (0..self.ids.len()).filter(|&row| self.live[row]) {
+            let migrated = migration.apply(&self.metadata[row]);
+            schema.validate(&migrated).map_err(|e| SchemaError::Entry { id: self.ids[row], error: Box::new(e) })?;
+            if migrated != self.metadata[row] {
+                changed.push((row, migrated));
+            }
+        }
+        let count = changed.len();
+        for (row, metadata) in changed {
+            self.metadata[row] = metadata;
+            self.pending.push(LogOp::Add(self.ids[row]));
+        }
+        self.schema = Some(schema);
+        self.generation += 1;
+        Ok(count)
+    }
+    
+    /// Tombstone a vector; returns false if `id` was not live
+    pub fn remove(&mut self, id: u64) -> bool {
+        match self.rows.remove(&id) {
+            Some(row) => {
+                self.live[row] = false;
+                if let Some(map) = &mut self.id_map {
+                    map.release(id);
+                }
+                self.pending.push(LogOp::Remove(id));
+                self.generation += 1;
+                true
+            }
+            None => false,
+        }
+    }
+    
+    /// Top-k live vectors by cosine similarity (or the index's metric), best first
+    pub fn search(&self, query: &[f32], k: usize) -> Vec<(u64, f32)> {
+        self.search_filtered(query, k, None)
+    }
+    
+    /// `search` for a query embedded under `provenance`
+    pub fn search_checked(&self, query: &[f32], k: usize, provenance: Option<&Provenance>, check: ProvenanceCheck)
+                          -> Result<Vec<(u64, f32)>, ProvenanceMismatch> {
+        self.check_provenance(provenance, check)?;
+        Ok(self.search(query, k))
+    }
+    
+    /// `search` scoring every live vector in full, never abandoning one.
+    ///
+    /// Returns the same hits; for benchmarks and checks of the pruned path.
+    pub fn search_exhaustive(&self, query: &[f32], k: usize) -> Vec<(u64, f32)> {
+        self.rank(query, k, |_| true, |_, cosine| cosine)
+    }
+    
+    /// Top-k among entries whose metadata passes `filter` (checked before scoring)
+    pub fn search_filtered(&self, query: &[f32], k: usize, filter: Option<Filter>) -> Vec<(u64, f32)> {
+        match filter {
+            None => self.cached(query, k, None, || self.rank_pruned(query, k, |_| true)),
+            Some(_) => self.rank_pruned(query, k, |row| self.passes(row, filter)),
+        }
+    }
+    
+    /// `search_filtered` with `filter` cached under `name`, which must
+    /// stand for this one filter for as long as the index lives
+    pub fn search_filtered_as(&self, query: &[f32], k: usize, filter: Filter, name: &str) -> Vec<(u64, f32)> {
+        self.cached(query, k, Some(name), || self.rank_pruned(query, k, |row| filter(&self.metadata[row])))
+    }
+    
+    fn cached(&self, query: &[f32], k: usize, filter: Option<&str>, search: impl FnOnce() -> Vec<(u64, f32)>) -> Vec<(u64, f32)> {
+        match &self.query_cache {
+            Some(cache) => cache.get_or_search(self.generation, query, k, filter, search),
+            None => search(),
+        }
+    }
+    
+    /// `search_filtered` over entries whose id and metadata pass `keep`
+    pub(crate) fn search_where(&self, query: &[f32], k: usize, keep: impl Fn(u64, &Metadata) -> bool) -> Vec<(u64, f32)> {
+        self.rank_pruned(query, k, |row| keep(self.ids[row], &self.metadata[row]))
+    }
+    
+    /// Top-k by `hybrid_score(dense cosine, sparse cosine, alpha)`, best first.
+    ///
+    /// Entries without a sparse vector score 0 on the sparse side; `alpha` 1
+    /// ranks as `search_filtered`, 0 by the sparse cosine alone. Under another
+    /// `Metric` the dense side is that metric's similarity instead.
+    pub fn search_hybrid(&self, query: &[f32], sparse: &SparseVector, alpha: f32, k: usize, filter: Option<Filter>)
+                         -> Vec<(u64, f32)> {
+        self.rank(query, k, |row| self.passes(row, filter), |row, cosine| {
+            let sparse_sim = self.sparse[row].as_ref().map_or(0.0, |s| sparse_cosine(sparse, s));
+            hybrid_score(cosine, sparse_sim, alpha)
+        })
+    }
+    
+    fn passes(&self, row: usize, filter: Option<Filter>) -> bool {
+        filter.is_none_or(|f| f(&self.metadata[row]))
+    }
+    
+    /// Top-k live rows passing `keep` by `score(row, dense cosine)`
+    fn rank(&self, query: &[f32], k: usize, keep: impl Fn(usize) -> bool, score: impl Fn(usize, f32) -> f32) -> Vec<(u64, f32)> {
+        if query.len() != self.dims || k == 0 { return vec![]; }
+        
+        let query_norm = norm(query);
+        let mut results: Vec<(u64, f32)> = (0..self.ids.len())
+            .filter(|&row| self.live[row])
+            .filter(|&row| keep(row))
+            .map(|row| {
+                let sim = self.metric.similarity_with_norms(query, self.row(row), query_norm, self.norms[row]);
+                (self.ids[row], score(row, sim))
+            })
+            .collect();
+        
+        results.sort_by(|a, b| score_order(a.1, b.1).then(a.0.cmp(&b.0)));
+        results.truncate(k);
+        results
+    }
+    
+    /// `rank` by the plain similarity, abandoning rows that cannot reach the top k
+    fn rank_pruned(&self, query: &[f32], k: usize, keep: impl Fn(usize) -> bool) -> Vec<(u64, f32)> {
+        if query.len() != self.dims || self.caps_per_row() == 0 || self.rows.len() < k.saturating_mul(PRUNE_MIN_ROWS_PER_K) {
+            return self.rank(query, k, keep, |_, sim| sim);
+        }
+        
+        let query_norm = norm(query);
+        let query_caps = suffix_norms(query);
+        // Rounding in the partial sums, the caps and the full dot product stays well inside this, in cosine
+        let slack = 4.0 * self.dims as f32 * f32::EPSILON;
+        let mut top: BinaryHeap<Worst> = BinaryHeap::with_capacity(k + 1);
+        for row in (0..self.ids.len()).filter(|&row| self.live[row] && keep(row)) {
+            let denom = query_norm * self.norms[row];
+            let sim = if denom > 0.0 || self.metric != Metric::Cosine {
+                let floor = (top.len() == k).then(|| self.dot_floor(top.peek().unwrap().0 .1, query_norm, self.norms[row], slack));
+                match (self.bounded_dot(query, &query_caps, row, floor), self.metric) {
+                    (None, _) => continue,
+                    (Some(dot), Metric::Cosine) => dot / denom,
+                    (Some(dot), Metric::Dot) => dot,
+                    (Some(_), Metric::Euclidean) => self.metric.similarity_with_norms(query, self.row(row), query_norm, self.norms[row]),
+                }
+            } else {
+                0.0
+            };
+            let hit = Worst((self.ids[row], sim));
+            if top.len() < k {
+                top.push(hit);
+            } else if hit < *top.peek().unwrap() {
+                top.pop();
+                top.push(hit);
+            }
+        }
+        let mut results: Vec<(u64, f32)> = top.into_vec().into_iter().map(|w| w.0).collect();
+        results.sort_by(|a, b| score_order(a.1, b.1).then(a.0.cmp(&b.0)));
+        results
+    }
+    
+    /// Least dot product with which a row of norm `row_norm` could score
+    /// `threshold`, lowered by `slack` (relative to the norms) for rounding
+    fn dot_floor(&self, threshold: f32, query_norm: f32, row_norm: f32, slack: f32) -> f32 {
+        match self.metric {
+            Metric::Cosine => (threshold - slack) * (query_norm * row_norm),
+            Metric::Dot => threshold - slack * (query_norm * row_norm),
+            // distance <= -threshold, with |q - r|² = |q|² + |r|² - 2 q·r
+            Metric::Euclidean => (query_norm * query_norm + row_norm * row_norm - threshold * threshold) / 2.0
+                - slack * (query_norm + row_norm) * (query_norm + row_norm),
+        }
+    }
+    
+    /// `dot(query, row)`, summed in the same order, or `None` once the sum so
+    /// far plus the caps of the remaining blocks falls below `floor`
+    fn bounded_dot(&self, query: &[f32], query_caps: &[f32], row: usize, floor: Option<f32>) -> Option<f32> {
+        let vector = self.row(row);
+        let per_row = self.caps_per_row();
+        let caps = &self.caps[row * per_row..(row + 1) * per_row];
+        // `Iterator::sum` of f32 folds from -0.0
+        let mut sum = -0.0f32;
+        for (block, (q, v)) in query.chunks(CAP_BLOCK).zip(vector.chunks(CAP_BLOCK)).enumerate() {
+            for (x, y) in q.iter().zip(v) {
+                sum += x * y;
+            }
+            if let (Some(floor), Some(cap)) = (floor, caps.get(block)) {
+                if sum + query_caps[block] * cap < floor {
+                    return None;
+                }
+            }
+        }
+        Some(sum)
+    }
+    
+    fn caps_per_row(&self) -> usize { self.dims.div_ceil(CAP_BLOCK).saturating_sub(1) }
+    
+    /// `search_filtered` under `options`: at most `k` hits, none below
+    /// `min_score`; with an `expansion`, of the expanded query
+    pub fn search_with(&self, query: &[f32], options: &SearchOptions, filter: Option<Filter>) -> Vec<Hit> {
+        options.apply_with(options.ranked(query, |q, n| self.search_filtered(q, n, filter), |id| self.get(id)), self.metric)
+    }
+    
+    /// Drop tombstoned rows from memory and release spare capacity; returns
+    /// the bytes of `stats().bytes_total()` reclaimed. The next `save` writes
+    /// a fresh snapshot.
+    ///
+    /// Takes `&mut self`, so an index shared behind a lock is compacted under
+    /// the write lock while searches wait.
+    pub fn compact(&mut self) -> usize {
+        let before = self.stats().bytes_total();
+        let mut compacted = CrystalIndex::new(self.dims);
+        compacted.quantization = self.quantization;
+        compacted.metric = self.metric;
+        compacted.provenance = self.provenance.take();
+        compacted.schema = self.schema.take();
+        for row in 0..self.ids.len() {
+            if self.live[row] {
+                compacted.push_row(self.ids[row], self.row(row), self.metadata[row].clone());
+                *compacted.sparse.last_mut().unwrap() = self.sparse[row].take();
+            }
+        }
+        compacted.pending = std::mem::take(&mut self.pending);
+        compacted.generation = self.generation + 1;
+        compacted.query_cache = self.query_cache.take();
+        // No row holds a removed entry's id any more
+        compacted.id_map = self.id_map.take().map(|mut map| {
+            map.reclaim();
+            map
+        });
+        compacted.shrink_to_fit();
+        *self = compacted;
+        before.saturating_sub(self.stats().bytes_total())
+    }
+    
+    fn shrink_to_fit(&mut self) {
+        self.vectors.shrink_to_fit();
+        self.ids.shrink_to_fit();
+        self.norms.shrink_to_fit();
+        self.caps.shrink_to_fit();
+        self.metadata.shrink_to_fit();
+        self.sparse.shrink_to_fit();
+        self.live.shrink_to_fit();
+        self.rows.shrink_to_fit();
+    }
+    
+    /// Heap bytes besides vectors; metadata counts at its encoded size
+    fn bytes_other(&self) -> usize {
+        let metadata: usize = self.metadata.iter().map(|m| m.encoded_len()).sum();
+        let sparse: usize = self.sparse.iter().flatten().map(|s| s.len() * 8).sum();
+        self.ids.capacity() * size_of::<u64>()
+            + self.live.capacity()
+            + self.rows.capacity() * (size_of::<u64>() + size_of::<usize>())
+            + self.metadata.capacity() * size_of::<Metadata>() + metadata
+            + self.sparse.capacity() * size_of::<Option<SparseVector>>() + sparse
+            + self.id_map.as_ref().map_or(0, IdMap::bytes)
+    }
+    
+    /// Write a full snapshot of live vectors, atomically replacing the file
+    pub fn save(&mut self, path: &str) -> Result<(), String> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.len() * (8 + self.quantization.encoded_len(self.dims)));
+        bytes.extend_from_slice(SNAPSHOT_MAGIC);
+        let version = match (self.metric, &self.schema) {
+            (Metric::Cosine, None) => UNSCHEMED_VERSION,
+            (Metric::Cosine, Some(_)) => COSINE_VERSION,
+            _ => FORMAT_VERSION,
+        };
+        bytes.extend_from_slice(version);
+        bytes.extend_from_slice(&(self.dims as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.len() as u64).to_le_bytes());
+        bytes.push(self.quantization as u8);
+        match &self.provenance {
+            Some(provenance) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&provenance.to_bytes()?);
+            }
+            None => bytes.push(0),
+        }
+        if version == FORMAT_VERSION {
+            bytes.push(self.metric as u8);
+            bytes.push(self.schema.is_some() as u8);
+        }
+        if let Some(schema) = &self.schema {
+            let json = schema.to_json();
+            bytes.extend_from_slice(&(json.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(json.as_bytes());
+        }
+        for row in 0..self.ids.len() {
+            if !self.live[row] { continue; }
+            bytes.extend_from_slice(&self.ids[row].to_le_bytes());
+            self.quantization.encode(self.row(row), &mut bytes);
+            bytes.extend_from_slice(&self.metadata[row].to_bytes()?);
+        }
+        let mut extra = Vec::new();
+        for row in (0..self.ids.len()).filter(|&row| self.live[row]) {
+            self.write_sparse(row, &mut extra);
+            self.write_key(row, &mut extra);
+        }
+        if !extra.is_empty() {
+            bytes.extend_from_slice(&increment_block(&extra));
+        }
+        
+        atomic_write(path, |file| file.write_all(&bytes)).map_err(|e| format!("Write failed for {}: {}", path, e))?;
+        self.pending.clear();
+        self.log_end = Some((path.to_string(), bytes.len() as u64));
+        Ok(())
+    }
+    
+    /// Append changes since the last save as one checksummed increment,
+    /// synced before returning; a crash mid-append leaves a torn block that
+    /// `load` drops, and the next append cuts off before writing
+    pub fn save_incremental(&mut self, path: &str) -> Result<(), String> {
+        let header = read_header(path)?;
+        if header.dims != self.dims {
+            return Err(format!("Index file has {} dims, index has {}", header.dims, self.dims));
+        }
+        if header.quantization != self.quantization {
+            return Err(format!("Index file stores {} vectors, index has {}",
+                               header.quantization.as_str(), self.quantization.as_str()));
+        }
+        if header.metric != self.metric {
+            return Err(format!("Index file ranks by {}, index by {}", header.metric.as_str(), self.metric.as_str()));
+        }
+        if header.provenance != self.provenance {
+            let describe = |p: &Option<Provenance>| p.as_ref().map_or("no provenance".to_string(), |p| p.to_string());
+            return Err(format!("Index file records {}, index has {}", describe(&header.provenance), describe(&self.provenance)));
+        }
+        if header.schema != self.schema {
+            return Err(format!("Index file has another metadata schema than the index; save a snapshot of {}", path));
+        }
+        if self.pending.is_empty() { return Ok(()); }
+        
+        let mut payload = Vec::new();
+        for op in &self.pending {
+            match op {
+                LogOp::Add(id) => {
+                    // Added then removed before this save: the remove op follows
+                    let (vector, metadata) = match self.rows.get(id) {
+                        Some(&row) => (self.row(row).to_vec(), self.metadata[row].clone()),
+                        None => (vec![0.0; self.dims], Metadata::new()),
+                    };
+                    payload.push(OP_ADD);
+                    payload.extend_from_slice(&id.to_le_bytes());
+                    self.quantization.encode(&vector, &mut payload);
+                    payload.extend_from_slice(&metadata.to_bytes()?);
+                    if let Some(&row) = self.rows.get(id) {
+                        self.write_sparse(row, &mut payload);
+                        self.write_key(row, &mut payload);
+                    }
+                }
+                LogOp::Remove(id) => {
+                    payload.push(OP_REMOVE);
+                    payload.extend_from_slice(&id.to_le_bytes());
+                }
+            }
+        }
+        
+        let mut file = OpenOptions::new().read(true).write(true).open(path)
+            .map_err(|e| format!("Cannot open {}: {}", path, e))?;
+        let len = file.metadata().map_err(|e| format!("Cannot open {}: {}", path, e))?.len();
+        // Bytes past the last complete block are a torn append: blocks written after them would never replay
+        let end = match &self.log_end {
+            Some((logged, end)) if logged == path && *end == len => len,
+            _ => {
+                let mut bytes = Vec::new();
+                file.read_to_end(&mut bytes).map_err(|e| format!("Read failed: {}", e))?;
+                log_end(&bytes).map_err(|e| format!("{}: {}", e, path))? as u64
+            }
+        };
+        let block = increment_block(&payload);
+        let written = (|| {
+            if end < len {
+                file.set_len(end)?;
+                file.sync_data()?;
+            }
+            file.seek(SeekFrom::Start(end))?;
+            file.write_all(&block)?;
+            file.sync_data()
+        })();
+        written.map_err(|e| format!("Write failed: {}", e))?;
+        self.pending.clear();
+        self.log_end = Some((path.to_string(), end + block.len() as u64));
+        Ok(())
+    }
+    
+    /// Load a snapshot and replay every complete increment after it
+    pub fn load(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Cannot open {}: {}", path, e))?;
+        let mut bytes = Vec::new();
+        BufReader::new(file).read_to_end(&mut bytes).map_err(|e| format!("Read failed: {}", e))?;
+        
+        let header = parse_header(&bytes).map_err(|e| format!("{}: {}", e, path))?;
+        let mut index = CrystalIndex::new(header.dims);
+        index.quantization = header.quantization;
+        index.metric = header.metric;
+        index.provenance = header.provenance;
+        index.schema = header.schema;
+        let mut pos = header.len;
+        let vector_len = 8 + header.quantization.encoded_len(header.dims);
+        
+        for _ in 0..header.count {
+            if bytes.len() < pos + vector_len {
+                return Err(format!("Truncated snapshot in {}", path));
+            }
+            let id = read_u64(&bytes, pos);
+            let vector = header.quantization.decode(&bytes, pos + 8, header.dims);
+            let (metadata, used) = Metadata::from_bytes(&bytes[pos + vector_len..])
+                .ok_or_else(|| format!("Truncated snapshot in {}", path))?;
+            index.push_row(id, &vector, metadata);
+            pos += vector_len + used;
+        }
+        
+        // Replay increments; a torn or corrupt block ends the log
+        while let Some((payload, end)) = next_block(&bytes, pos) {
+            index.replay(payload)?;
+            pos = end;
+        }
+        
+        index.log_end = Some((path.to_string(), pos as u64));
+        Ok(index)
+    }
+    
+    fn replay(&mut self, payload: &[u8]) -> Result<(), String> {
+        let mut pos = 0;
+        while pos < payload.len() {
+            let op = payload[pos];
+            if payload.len() < pos + 9 { return Err("Malformed increment".to_string()); }
+            let id = read_u64(payload, pos + 1);
+            pos += 9;
+            
+            match op {
+                OP_ADD => {
+                    let vector_len = self.quantization.encoded_len(self.dims);
+                    if payload.len() < pos + vector_len {
+                        return Err("Malformed increment".to_string());
+                    }
+                    let vector = self.quantization.decode(payload, pos, self.dims);
+                    pos += vector_len;
+                    let (metadata, used) = Metadata::from_bytes(&payload[pos..])
+                        .ok_or("Malformed increment")?;
+                    pos += used;
+                    if let Some(&row) = self.rows.get(&id) { self.live[row] = false; }
+                    self.push_row(id, &vector, metadata);
+                }
+                OP_REMOVE => {
+                    if let Some(row) = self.rows.remove(&id) { self.live[row] = false; }
+                    if let Some(map) = &mut self.id_map { map.release(id); }
+                }
+                OP_SPARSE => {
+                    let (sparse, used) = SparseVector::from_bytes(&payload[pos..]).ok_or("Malformed increment")?;
+                    pos += used;
+                    if let Some(&row) = self.rows.get(&id) { self.sparse[row] = Some(sparse); }
+                }
+                OP_KEY => {
+                    if payload.len() < pos + 4 { return Err("Malformed increment".to_string()); }
+                    let len = u32::from_le_bytes(payload[pos..pos+4].try_into().unwrap()) as usize;
+                    let key = payload.get(pos + 4..pos + 4 + len).and_then(|key| std::str::from_utf8(key).ok())
+                        .ok_or("Malformed increment")?;
+                    pos += 4 + len;
+                    if self.rows.contains_key(&id) { self.id_map.get_or_insert_default().insert(key, id); }
+                }
+                _ => return Err(format!("Unknown increment op {}", op)),
+            }
+        }
+        Ok(())
+    }
+    
+    fn push_row(&mut self, id: u64, vector: &[f32], metadata: Metadata) {
+        self.generation += 1;
+        self.rows.insert(id, self.ids.len());
+        self.ids.push(id);
+        self.norms.push(norm(vector));
+        self.caps.extend(suffix_norms(vector));
+        self.metadata.push(metadata);
+        self.sparse.push(None);
+        self.live.push(true);
+        self.vectors.extend_from_slice(vector);
+    }
+    
+    /// OP_SPARSE for `row`, if it has a sparse vector
+    fn write_sparse(&self, row: usize, out: &mut Vec<u8>) {
+        if let Some(sparse) = &self.sparse[row] {
+            out.push(OP_SPARSE);
+            out.extend_from_slice(&self.ids[row].to_le_bytes());
+            sparse.to_bytes(out);
+        }
+    }
+    
+    /// OP_KEY for `row`, if it was added by key
+    fn write_key(&self, row: usize, out: &mut Vec<u8>) {
+        if let Some(key) = self.id_map.as_ref().and_then(|map| map.key(self.ids[row])) {
+            out.push(OP_KEY);
+            out.extend_from_slice(&self.ids[row].to_le_bytes());
+            out.extend_from_slice(&(key.len() as u32).to_le_bytes());
+            out.extend_from_slice(key.as_bytes());
+        }
+    }
+    
+    #[inline]
+    fn row(&self, row: usize) -> &[f32] {
+        &self.vectors[row * self.dims..(row + 1) * self.dims]
+    }
+}
+
+/// Norms of `v` from each `CAP_BLOCK` boundary after the first to its end
+fn suffix_norms(v: &[f32]) -> Vec<f32> {
+    let mut caps: Vec<f32> = v.chunks(CAP_BLOCK).skip(1).map(|block| dot(block, block)).collect();
+    let mut rest = 0.0;
+    for cap in caps.iter_mut().rev() {
+        rest += *cap;
+        *cap = rest.sqrt();
+    }
+    caps
+}
+
+/// A hit ordered so the heap's greatest is the worst: lower score, then higher id
+#[derive(PartialEq)]
+struct Worst((u64, f32));
+
+impl Eq for Worst {}
+
+impl PartialOrd for Worst {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for Worst {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // As `rank` sorts: 0.0 and -0.0 tie, NaN is worst
+        score_order(self.0 .1, other.0 .1).then(self.0 .0.cmp(&other.0 .0))
+    }
+}
+
+/// Magic, version, dims, count, quantization; version 04 continues with
+/// a provenance flag byte and, when set, the encoded `Provenance`; version
+/// 05 then with the u32 length and JSON of the `MetadataSchema`, and 06
+/// with a metric byte and a schema flag byte before the schema
+const HEADER_LEN: usize = 21;
+const LEGACY_HEADER_LEN: usize = 20;
+
+struct Header {
+    /// Bytes before the first vector
+    len: usize,
+    dims: usize,
+    count: usize,
+    quantization: Quantization,
+    metric: Metric,
+    provenance: Option<Provenance>,
+    schema: Option<MetadataSchema>,
+}
+
+fn read_header(path: &str) -> Result<Header, String> {
+    let file = File::open(path).map_err(|e| format!("Cannot open {}: {}", path, e))?;
+    let prefix = (HEADER_LEN + 1 + Provenance::MAX_ENCODED_LEN + 4) as u64;
+    let mut bytes = Vec::with_capacity(prefix as usize);
+    let mut reader = BufReader::new(file);
+    reader.by_ref().take(prefix).read_to_end(&mut bytes).map_err(|e| format!("Read failed: {}", e))?;
+    // A schema longer than the prefix: read on for its length
+    if let Some(end) = schema_end(&bytes).filter(|&end| end > bytes.len()) {
+        reader.take((end - bytes.len()) as u64).read_to_end(&mut bytes).map_err(|e| format!("Read failed: {}", e))?;
+    }
+    parse_header(&bytes).map_err(|e| format!("{}: {}", e, path))
+}
+
+fn parse_header(bytes: &[u8]) -> Result<Header, String> {
+    if bytes.len() < LEGACY_HEADER_LEN || &bytes[..6] != SNAPSHOT_MAGIC {
+        return Err("Not an index snapshot".to_string());
+    }
+    let version = &bytes[6..8];
+    let current = [FORMAT_VERSION, COSINE_VERSION, UNSCHEMED_VERSION, UNPROVENANCED_VERSION].iter().any(|v| version == *v);
+    let (len, quantization) = match version {
+        version if version == LEGACY_VERSION => (LEGACY_HEADER_LEN, Quantization::None),
+        _ if current && bytes.len() >= HEADER_LEN => match bytes[20] {
+            0 => (HEADER_LEN, Quantization::None),
+            1 => (HEADER_LEN, Quantization::Int8),
+            mode => return Err(format!("Unknown quantization mode {}", mode)),
+        },
+        _ if current => return Err("Truncated index header".to_string()),
+        version => return Err(format!("Unsupported index format version {} (this build reads 02 to {})",
+                                      String::from_utf8_lossy(version),
+                                      String::from_utf8_lossy(FORMAT_VERSION))),
+    };
+    let provenanced = version == FORMAT_VERSION || version == COSINE_VERSION || version == UNSCHEMED_VERSION;
+    let (len, provenance) = match provenanced.then(|| bytes.get(len)) {
+        None => (len, None),
+        Some(Some(0)) => (len + 1, None),
+        Some(Some(1)) => {
+            let (provenance, used) = Provenance::from_bytes(&bytes[len + 1..]).ok_or("Truncated index header")?;
+            (len + 1 + used, Some(provenance))
+        }
+        Some(Some(flag)) => return Err(format!("Unknown provenance flag {}", flag)),
+        Some(None) => return Err("Truncated index header".to_string()),
+    };
+    let (len, metric, schemed) = match version {
+        version if version == FORMAT_VERSION => {
+            let metric = match bytes.get(len) {
+                Some(0) => Metric::Cosine,
+                Some(1) => Metric::Dot,
+                Some(2) => Metric::Euclidean,
+                Some(metric) => return Err(format!("Unknown metric {}", metric)),
+                None => return Err("Truncated index header".to_string()),
+            };
+            match bytes.get(len + 1) {
+                Some(&flag @ (0 | 1)) => (len + 2, metric, flag == 1),
+                Some(flag) => return Err(format!("Unknown schema flag {}", flag)),
+                None => return Err("Truncated index header".to_string()),
+            }
+        }
+        version if version == COSINE_VERSION => (len, Metric::Cosine, true),
+        _ => (len, Metric::Cosine, false),
+    };
+    let (len, schema) = match schemed {
+        false => (len, None),
+        true => {
+            let end = schema_end_at(bytes, len).filter(|&end| end <= bytes.len()).ok_or("Truncated index header")?;
+            let json = std::str::from_utf8(&bytes[len + 4..end]).map_err(|_| "Metadata schema is not UTF-8")?;
+            (end, Some(MetadataSchema::from_json(json)?))
+        }
+    };
+    Ok(Header {
+        len,
+        dims: u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize,
+        count: read_u64(bytes, 12) as usize,
+        quantization,
+        metric,
+        provenance,
+        schema,
+    })
+}
+
+/// End of the schema of a version 05 or 06 header, from its length field
+fn schema_end(bytes: &[u8]) -> Option<usize> {
+    let version = bytes.get(6..8)?;
+    if version != FORMAT_VERSION && version != COSINE_VERSION || bytes.len() <= HEADER_LEN { return None; }
+    let mut start = match bytes[HEADER_LEN] {
+        1 => HEADER_LEN + 1 + Provenance::from_bytes(&bytes[HEADER_LEN + 1..])?.1,
+        _ => HEADER_LEN + 1,
+    };
+    if version == FORMAT_VERSION {
+        if *bytes.get(start + 1)? != 1 { return None; }
+        start += 2;
+    }
+    schema_end_at(bytes, start)
+}
+
+fn schema_end_at(bytes: &[u8], start: usize) -> Option<usize> {
+    let len = u32::from_le_bytes(bytes.get(start..start + 4)?.try_into().unwrap()) as usize;
+    start.checked_add(4 + len)
+}
+
+/// Post-filter for approximate searchers that cannot filter while scoring.
+///
+/// `search(n)` returns up to `n` best-first hits. Starts at 4k candidates,
+/// keeps those passing `keep`, and doubles the fetch until k survive or the
+/// searcher returns fewer than requested (corpus exhausted).
+pub fn search_overfetch<S, K>(k: usize, search: S, keep: K) -> Vec<(u64, f32)>
+where
+    S: FnMut(usize) -> Vec<(u64, f32)>,
+    K: Fn(u64) -> bool,
+{
+    overfetch(k, search, keep, |_| true)
+}
+
+/// `search_overfetch` under `options`. Escalation also stops once a fetch
+/// reaches hits below `min_score`, since every later candidate scores lower.
+pub fn search_overfetch_with<S, K>(options: &SearchOptions, search: S, keep: K) -> Vec<Hit>
+where
+    S: FnMut(usize) -> Vec<(u64, f32)>,
+    K: Fn(u64) -> bool,
+{
+    options.apply(overfetch(options.k, search, keep, |cosine| options.passes(cosine)))
+}
+
+fn overfetch<S, K, P>(k: usize, mut search: S, keep: K, passes: P) -> Vec<(u64, f32)>
+where
+    S: FnMut(usize) -> Vec<(u64, f32)>,
+    K: Fn(u64) -> bool,
+    P: Fn(f32) -> bool,
+{
+    if k == 0 { return vec![]; }
+    
+    let mut fetch = k.saturating_mul(4);
+    loop {
+        let candidates = search(fetch);
+        let exhausted = candidates.len() < fetch || fetch == usize::MAX
+            || candidates.last().is_some_and(|&(_, cosine)| !passes(cosine));
+        let mut kept: Vec<(u64, f32)> = candidates.into_iter().filter(|(id, _)| keep(*id)).collect();
+        
+        if kept.len() >= k || exhausted {
+            kept.truncate(k);
+            return kept;
+        }
+        fetch = fetch.saturating_mul(2);
+    }
+}
+
+/// The payload of the complete, intact increment at `pos`, and where it ends
+fn next_block(bytes: &[u8], pos: usize) -> Option<(&[u8], usize)> {
+    if bytes.get(pos) != Some(&INCREMENT_TAG) || bytes.len() < pos + 5 { return None; }
+    let len = u32::from_le_bytes(bytes[pos+1..pos+5].try_into().unwrap()) as usize;
+    let end = pos + 5 + len;
+    if bytes.len() < end + 4 { return None; }
+    
+    let payload = &bytes[pos+5..end];
+    let stored = u32::from_le_bytes(bytes[end..end+4].try_into().unwrap());
+    (checksum(payload) == stored).then_some((payload, end + 4))
+}
+
+/// Where the last complete increment of an index file ends
+fn log_end(bytes: &[u8]) -> Result<usize, String> {
+    let header = parse_header(bytes)?;
+    let vector_len = 8 + header.quantization.encoded_len(header.dims);
+    let mut pos = header.len;
+    for _ in 0..header.count {
+        let metadata = bytes.get(pos + vector_len..).and_then(Metadata::from_bytes).ok_or("Truncated snapshot")?;
+        pos += vector_len + metadata.1;
+    }
+    while let Some((_, end)) = next_block(bytes, pos) {
+        pos = end;
+    }
+    Ok(pos)
+}
+
+/// Tag, payload length, payload, checksum
+fn increment_block(payload: &[u8]) -> Vec<u8> {
+    let mut block = Vec::with_capacity(payload.len() + 9);
+    block.push(INCREMENT_TAG);
+    block.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    block.extend_from_slice(payload);
+    block.extend_from_slice(&checksum(payload).to_le_bytes());
+    block
+}
+
+fn read_u64(bytes: &[u8], pos: usize) -> u64 {
+    u64::from_le_bytes(bytes[pos..pos+8].try_into().unwrap())
+}
+
+fn read_f32s(bytes: &[u8], pos: usize, n: usize) -> Vec<f32> {
+    bytes[pos..pos + n * 4]
+        .chunks_exact(4)
+        .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+        .collect()
+}
+
+/// FNV-1a over the increment payload
+fn checksum(bytes: &[u8]) -> u32 {
+    let mut h = 0x811c9dc5u32;
+    for &b in bytes {
+        h ^= b as u32;
+        h = h.wrapping_mul(0x01000193);
+    }
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::cosine_score;
+    
+    fn vec3(x: f32, y: f32, z: f32) -> Vec<f32> { vec![x, y, z] }
+    
+    #[test]
+    fn test_pruned_search_matches_exhaustive() {
+        use rand::prelude::*;
+        let mut rng = StdRng::seed_from_u64(11);
+        for (dims, decay, quantization) in [(40, 0.0, Quantization::None), (256, 0.0, Quantization::None),
+                                            (256, 12.0, Quantization::None), (300, 6.0, Quantization::Int8)] {
+            // Component i drawn with scale exp(-decay * i / dims): front-loaded like Matryoshka vectors
+            let draw = |rng: &mut StdRng| -> Vec<f32> {
+                (0..dims).map(|i| rng.gen_range(-1.0..1.0f32) * (-decay * i as f32 / dims as f32).exp()).collect()
+            };
+            let mut index = CrystalIndex::new(dims).with_quantization(quantization);
+            for id in 0..2000u64 {
+                let v = draw(&mut rng);
+                index.add_with_metadata(id * 7 % 2003, &v, Metadata::new().with("even", (id % 2 == 0) as u8 as f64)).unwrap();
+            }
+            // Exact ties across ids, tombstones and an all-zero row
+            let twin = index.get(14).unwrap().to_vec();
+            index.add(5000, &twin).unwrap();
+            index.add(4999, &twin).unwrap();
+            index.add(6000, &vec![0.0; dims]).unwrap();
+            for id in (0..300).step_by(3) {
+                index.remove(id);
+            }
+            
+            let mut queries: Vec<Vec<f32>> = (0..12).map(|_| draw(&mut rng)).collect();
+            queries.push(twin.clone());
+            queries.push(vec![0.0; dims]);
+            let even = |m: &Metadata| m.get_num("even") == Some(1.0);
+            for query in &queries {
+                for k in [1, 3, 10, 100, 1000] {
+                    assert_eq!(index.search(query, k), index.search_exhaustive(query, k), "dims {} k {}", dims, k);
+                    assert_eq!(index.search_filtered(query, k, Some(&even)),
+                               index.rank(query, k, |row| index.passes(row, Some(&even)), |_, c| c));
+                }
+            }
+            let hits = index.search(&twin, 3);
+            assert_eq!(hits.iter().map(|h| h.0).collect::<Vec<_>>(), [14, 4999, 5000]);
+        }
+    }
+    
+    fn temp_path(name: &str) -> String {
+        let dir = std::env::temp_dir().join("spo_crystal_index_tests");
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(name).to_string_lossy().to_string()
+    }
+    
+    fn ids(index: &CrystalIndex, query: &[f32]) -> Vec<u64> {
+        index.search(query, 10).into_iter().map(|(id, _)| id).collect()
+    }
+    
+    #[test]
+    fn test_search_skips_tombstones() {
+        let mut index = CrystalIndex::new(3);
+        index.add(1, &vec3(1.0, 0.0, 0.0)).unwrap();
+        index.add(2, &vec3(0.9, 0.1, 0.0)).unwrap();
+        index.add(3, &vec3(0.0, 1.0, 0.0)).unwrap();
+        
+        assert_eq!(index.search(&vec3(1.0, 0.0, 0.0), 1)[0].0, 1);
+        assert!(index.remove(1));
+        assert!(!index.remove(1));
+        assert_eq!(index.search(&vec3(1.0, 0.0, 0.0), 1)[0].0, 2);
+        assert_eq!(index.tombstones(), 1);
+        
+        index.compact();
+        assert_eq!(index.tombstones(), 0);
+        assert_eq!(ids(&index, &vec3(1.0, 0.0, 0.0)), vec![2, 3]);
+        
+        assert!(index.add(2, &vec3(0.0, 0.0, 1.0)).is_err());
+        assert!(index.add(4, &[1.0]).is_err());
+    }
+    
+    #[test]
+    fn test_incremental_log_roundtrip() {
+        let path = temp_path("roundtrip.idx");
+        let mut index = CrystalIndex::new(3);
+        index.add(1, &vec3(1.0, 0.0, 0.0)).unwrap();
+        index.add(2, &vec3(0.0, 1.0, 0.0)).unwrap();
+        index.save(&path).unwrap();
+        
+        // Increment 1: add + remove
+        index.add(3, &vec3(0.0, 0.0, 1.0)).unwrap();
+        index.remove(1);
+        index.save_incremental(&path).unwrap();
+        
+        let loaded = CrystalIndex::load(&path).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert!(!loaded.contains(1));
+        assert_eq!(loaded.get(3).unwrap(), &vec3(0.0, 0.0, 1.0)[..]);
+        
+        // Increment 2 on the reloaded index: re-add a removed id, add-then-remove
+        let mut loaded = loaded;
+        loaded.add(1, &vec3(0.5, 0.5, 0.0)).unwrap();
+        loaded.add(4, &vec3(1.0, 1.0, 1.0)).unwrap();
+        loaded.remove(4);
+        loaded.save_incremental(&path).unwrap();
+        
+        let reloaded = CrystalIndex::load(&path).unwrap();
+        assert_eq!(ids(&reloaded, &vec3(1.0, 1.0, 0.0)), ids(&loaded, &vec3(1.0, 1.0, 0.0)));
+        assert!(!reloaded.contains(4));
+        assert_eq!(reloaded.get(1).unwrap(), &vec3(0.5, 0.5, 0.0)[..]);
+        
+        // Compact + full save rewrites a single snapshot
+        let mut compacted = reloaded;
+        compacted.compact();
+        compacted.save(&path).unwrap();
+        let size = std::fs::metadata(&path).unwrap().len() as usize;
+        // Header, no-provenance flag, three rows of id, vector and empty metadata
+        assert_eq!(size, HEADER_LEN + 1 + 3 * (8 + 3 * 4 + 2));
+        assert_eq!(CrystalIndex::load(&path).unwrap().len(), 3);
+    }
+    
+    fn lang_year(lang: &str, year: i64) -> Metadata {
+        Metadata::new().with("lang", lang).with("year", year)
+    }
+    
+    #[test]
+    fn test_filtered_search_excludes_items() {
+        let mut index = CrystalIndex::new(3);
+        index.add_with_metadata(1, &vec3(1.0, 0.0, 0.0), lang_year("en", 2022)).unwrap();
+        index.add_with_metadata(2, &vec3(0.9, 0.1, 0.0), lang_year("de", 2019)).unwrap();
+        index.add_with_metadata(3, &vec3(0.8, 0.2, 0.0), lang_year("de", 2021)).unwrap();
+        index.add_with_metadata(4, &vec3(0.0, 1.0, 0.0), lang_year("de", 2023)).unwrap();
+        index.add(5, &vec3(1.0, 0.0, 0.0)).unwrap();
+        
+        let recent_de = |m: &Metadata| {
+            m.get_str("lang") == Some("de") && m.get_num("year").is_some_and(|y| y >= 2020.0)
+        };
+        let hits = index.search_filtered(&vec3(1.0, 0.0, 0.0), 10, Some(&recent_de));
+        assert_eq!(hits.iter().map(|h| h.0).collect::<Vec<_>>(), vec![3, 4]);
+        
+        // Metadata survives snapshot + increment
+        let path = temp_path("metadata.idx");
+        index.save(&path).unwrap();
+        index.add_with_metadata(6, &vec3(0.7, 0.3, 0.0), lang_year("de", 2024)).unwrap();
+        index.save_incremental(&path).unwrap();
+        let loaded = CrystalIndex::load(&path).unwrap();
+        assert_eq!(loaded.metadata(2), Some(&lang_year("de", 2019)));
+        let hits = loaded.search_filtered(&vec3(1.0, 0.0, 0.0), 10, Some(&recent_de));
+        assert_eq!(hits.iter().map(|h| h.0).collect::<Vec<_>>(), vec![3, 6, 4]);
+    }
+    
+    #[test]
+    fn test_overfetch_escalation_terminates() {
+        let mut index = CrystalIndex::new(3);
+        for id in 0..100u64 {
+            let x = id as f32 / 100.0;
+            index.add_with_metadata(id, &vec3(1.0 - x, x, 0.0), Metadata::new().with("even", id % 2 == 0)).unwrap();
+        }
+        let query = vec3(1.0, 0.0, 0.0);
+        let keep = |id: u64| index.metadata(id).and_then(|m| m.get_bool("even")) == Some(true);
+        
+        // Matches the exact pre-filtered search
+        let mut fetches = Vec::new();
+        let hits = search_overfetch(5, |n| { fetches.push(n); index.search(&query, n) }, keep);
+        let even = |m: &Metadata| m.get_bool("even") == Some(true);
+        assert_eq!(hits, index.search_filtered(&query, 5, Some(&even)));
+        assert_eq!(fetches, vec![20]);
+        
+        // Nothing passes: escalates until the corpus is exhausted, then stops
+        let mut fetches = Vec::new();
+        let hits = search_overfetch(5, |n| { fetches.push(n); index.search(&query, n) }, |_| false);
+        assert!(hits.is_empty());
+        assert_eq!(fetches, vec![20, 40, 80, 160]);
+        
+        // Sparse matches: escalation returns only kept ids
+        let hits = search_overfetch(3, |n| index.search(&query, n), |id| id >= 95);
+        assert_eq!(hits.iter().map(|h| h.0).collect::<Vec<_>>(), vec![95, 96, 97]);
+    }
+    
+    #[test]
+    fn test_min_score_with_filter_and_overfetch() {
+        let mut index = CrystalIndex::new(3);
+        for id in 0..100u64 {
+            let x = id as f32 / 100.0;
+            index.add_with_metadata(id, &vec3(1.0 - x, x, 0.0), Metadata::new().with("tenth", id.is_multiple_of(10))).unwrap();
+        }
+        let query = vec3(1.0, 0.0, 0.0);
+        let tenth = |m: &Metadata| m.get_bool("tenth") == Some(true);
+        
+        // Fewer than k survive the filter and threshold together
+        let options = SearchOptions::top(8).with_min_score(0.9);
+        let hits = index.search_with(&query, &options, Some(&tenth));
+        let expected: Vec<u64> = index.search_filtered(&query, 100, Some(&tenth)).into_iter()
+            .filter(|&(_, cosine)| cosine >= 0.9)
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(hits.iter().map(|h| h.id).collect::<Vec<_>>(), expected);
+        assert_eq!(expected, [0, 10, 20, 30]);
+        assert!(hits.iter().all(|h| h.normalized.is_none() && h.score() == h.cosine));
+        assert!(index.search_with(&query, &SearchOptions::top(8).with_min_score(1.5), None).is_empty());
+        
+        // Normalized scores threshold on cosine_score: 0.95 there is cosine 0.9
+        let normalized = index.search_with(&query, &SearchOptions::top(8).with_min_score(0.95).with_normalized(true), Some(&tenth));
+        assert_eq!(normalized.iter().map(|h| h.id).collect::<Vec<_>>(), expected);
+        assert!(normalized.iter().all(|h| h.normalized == Some(cosine_score(h.cosine))));
+        
+        // Over-fetching stops at the first fetch reaching below the threshold
+        let keep = |id: u64| id.is_multiple_of(10);
+        let mut fetches = Vec::new();
+        let hits = search_overfetch_with(&SearchOptions::top(5).with_min_score(0.9),
+                                         |n| { fetches.push(n); index.search(&query, n) }, keep);
+        assert_eq!(hits.iter().map(|h| h.id).collect::<Vec<_>>(), expected);
+        assert_eq!(fetches, vec![20, 40]);
+        let mut fetches = Vec::new();
+        search_overfetch_with(&SearchOptions::top(5), |n| { fetches.push(n); index.search(&query, n) }, keep);
+        assert_eq!(fetches, vec![20, 40, 80]);
+    }
+    
+    #[test]
+    fn test_torn_increment_ignored() {
+        let path = temp_path("torn.idx");
+        let mut index = CrystalIndex::new(3);
+        index.add(1, &vec3(1.0, 0.0, 0.0)).unwrap();
+        index.save(&path).unwrap();
+        let snapshot_len = std::fs::metadata(&path).unwrap().len();
+        
+        index.add(2, &vec3(0.0, 1.0, 0.0)).unwrap();
+        index.save_incremental(&path).unwrap();
+        let good_len = std::fs::metadata(&path).unwrap().len();
+        
+        index.add(3, &vec3(0.0, 0.0, 1.0)).unwrap();
+        index.remove(1);
+        index.save_incremental(&path).unwrap();
+        
+        // A crash at any byte of either increment loses only the torn one,
+        // and what is appended after it replays
+        let full = std::fs::read(&path).unwrap();
+        let torn = temp_path("torn_at.idx");
+        for cut in snapshot_len..full.len() as u64 {
+            std::fs::write(&torn, &full[..cut as usize]).unwrap();
+            let mut loaded = CrystalIndex::load(&torn).unwrap();
+            let expected: &[u64] = if cut < good_len { &[1] } else { &[1, 2] };
+            assert_eq!(loaded.ids().collect::<Vec<_>>(), expected, "cut at {}", cut);
+            
+            loaded.add(9, &vec3(1.0, 1.0, 0.0)).unwrap();
+            loaded.remove(1);
+            loaded.save_incremental(&torn).unwrap();
+            let reloaded = CrystalIndex::load(&torn).unwrap();
+            assert_eq!(reloaded.ids().collect::<Vec<_>>(), [&expected[1..], &[9]].concat(), "appended after cut at {}", cut);
+        }
+        
+        // Simulate a crash halfway through the second increment
+        let full_len = full.len() as u64;
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(good_len + (full_len - good_len) / 2).unwrap();
+        
+        let loaded = CrystalIndex::load(&path).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert!(loaded.contains(1) && loaded.contains(2) && !loaded.contains(3));
+        
+        // Corrupted (not just short) increment is ignored too
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.truncate(good_len as usize);
+        let last = bytes.len() - 5;
+        bytes[last] ^= 0xFF;
+        std::fs::write(&path, &bytes).unwrap();
+        assert_eq!(CrystalIndex::load(&path).unwrap().len(), 1);
+    }
+    
+    #[test]
+    fn test_degenerate_vectors_rank_last_without_panicking() {
+        // A NaN entry scores NaN by dot product; enough rows for the pruned path too
+        let mut index = CrystalIndex::new(40).with_metric(Metric::Dot);
+        let axis = |i: usize| { let mut v = vec![0.0; 40]; v[i % 40] = 1.0; v };
+        for id in 0..40 {
+            index.add(id, &axis(id as usize)).unwrap();
+        }
+        index.add(40, &[0.0; 40]).unwrap();
+        index.add(41, &[f32::NAN; 40]).unwrap();
+        let hits = index.search(&axis(3), 42);
+        assert_eq!((hits[0].0, hits.len()), (3, 42));
+        assert!(hits[41].1.is_nan());
+        assert_eq!(index.search(&axis(3), 1), [(3, 1.0)]);
+        assert_eq!(index.search_filtered(&axis(3), 2, Some(&|_: &Metadata| true))[0].0, 3);
+        assert_eq!(index.search(&[f32::NAN; 40], 5).len(), 5);
+        
+        let shared = crate::shared_index::SharedIndex::new(index);
+        let mut batch = shared.batch();
+        batch.add(42, &[f32::NAN; 40]).unwrap();
+        batch.add(43, &axis(4)).unwrap();
+        batch.commit().unwrap();
+        let hits = shared.snapshot().search(&axis(4), 44);
+        assert_eq!(hits[..2].iter().map(|h| h.0).collect::<Vec<_>>(), [4, 43]);
+        assert!(hits[42].1.is_nan() && hits[43].1.is_nan());
+    }
+    
+    #[test]
+    fn test_append_after_torn_increment_replays() {
+        let path = temp_path("torn_append.idx");
+        let mut index = CrystalIndex::new(3);
+        index.add(1, &vec3(1.0, 0.0, 0.0)).unwrap();
+        index.add(2, &vec3(0.0, 1.0, 0.0)).unwrap();
+        index.save(&path).unwrap();
+        index.add(3, &vec3(0.0, 0.0, 1.0)).unwrap();
+        index.save_incremental(&path).unwrap();
+        let good_len = std::fs::metadata(&path).unwrap().len();
+        index.add(4, &vec3(1.0, 1.0, 0.0)).unwrap();
+        index.save_incremental(&path).unwrap();
+        let full_len = std::fs::metadata(&path).unwrap().len();
+        
+        // A crash halfway through the last append, then reopen and append twice
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(good_len + (full_len - good_len) / 2).unwrap();
+        let mut reopened = CrystalIndex::load(&path).unwrap();
+        assert!(reopened.contains(3) && !reopened.contains(4));
+        reopened.add(5, &vec3(0.0, 1.0, 1.0)).unwrap();
+        reopened.remove(1);
+        reopened.save_incremental(&path).unwrap();
+        reopened.add(6, &vec3(1.0, 0.0, 1.0)).unwrap();
+        reopened.save_incremental(&path).unwrap();
+        let loaded = CrystalIndex::load(&path).unwrap();
+        assert_eq!(loaded.ids().collect::<HashSet<_>>(), HashSet::from([2, 3, 5, 6]));
+        
+        // A writer whose idea of the file is stale rescans it rather than trusting its offset
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(&[INCREMENT_TAG, 9, 0]).unwrap();
+        index.add(7, &vec3(1.0, 1.0, 1.0)).unwrap();
+        index.save_incremental(&path).unwrap();
+        let loaded = CrystalIndex::load(&path).unwrap();
+        assert_eq!(loaded.ids().collect::<HashSet<_>>(), HashSet::from([2, 3, 5, 6, 7]));
+    }
+    
+    #[test]
+    fn test_int8_roundtrip() {
+        let path = temp_path("int8.idx");
+        let vector = |i: u64| (0..64).map(|j| ((i * 31 + j * 7) % 17) as f32 / 17.0 - 0.5).collect::<Vec<f32>>();
+        let mut plain = CrystalIndex::new(64);
+        for id in 0..20 { plain.add(id, &vector(id)).unwrap(); }
+        let mut index = CrystalIndex::new(64).with_quantization(Quantization::Int8);
+        for id in 0..10 { index.add(id, &vector(id)).unwrap(); }
+        index.save(&path).unwrap();
+        for id in 10..20 { index.add(id, &vector(id)).unwrap(); }
+        index.save_incremental(&path).unwrap();
+        
+        let loaded = CrystalIndex::load(&path).unwrap();
+        assert_eq!((loaded.quantization(), loaded.len()), (Quantization::Int8, 20));
+        for id in [3, 15] {
+            let q = Int8Vector::quantize(&vector(id));
+            let got = loaded.get(id).unwrap();
+            assert!(got.iter().zip(&vector(id)).all(|(a, b)| (a - b).abs() <= q.max_error() + 1e-6));
+            assert_eq!(got, index.get(id).unwrap());
+        }
+        assert_eq!(loaded.search(&vector(7), 3), index.search(&vector(7), 3));
+        assert_eq!(loaded.search(&vector(7), 1)[0].0, plain.search(&vector(7), 1)[0].0);
+        assert_eq!(loaded.ids().collect::<Vec<_>>(), (0..20).collect::<Vec<_>>());
+        
+        let plain_path = temp_path("int8_plain.idx");
+        plain.save(&plain_path).unwrap();
+        let size = |p: &str| std::fs::metadata(p).unwrap().len() as f64;
+        let mut compacted = loaded;
+        compacted.save(&path).unwrap();
+        assert!(size(&path) < size(&plain_path) / 3.0);
+        
+        // Increments must match the file's storage
+        plain.add(20, &vector(20)).unwrap();
+        assert!(plain.save_incremental(&path).unwrap_err().contains("int8"));
+    }
+    
+    #[test]
+    fn test_corrupt_files_error_readably() {
+        let path = temp_path("corrupt.idx");
+        let mut index = CrystalIndex::new(3);
+        index.add_with_metadata(1, &vec3(1.0, 0.0, 0.0), lang_year("en", 2022)).unwrap();
+        index.add(2, &vec3(0.0, 1.0, 0.0)).unwrap();
+        index.save(&path).unwrap();
+        index.add(3, &vec3(0.0, 0.0, 1.0)).unwrap();
+        index.save_incremental(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        
+        // Version 02 files (no quantization byte) still load
+        let mut legacy = bytes[..HEADER_LEN - 1].to_vec();
+        legacy[6..8].copy_from_slice(LEGACY_VERSION);
+        legacy.extend_from_slice(&bytes[HEADER_LEN + 1..]);
+        std::fs::write(&path, &legacy).unwrap();
+        let loaded = CrystalIndex::load(&path).unwrap();
+        assert_eq!((loaded.len(), loaded.quantization()), (3, Quantization::None));
+        
+        let mut future = bytes.clone();
+        future[6..8].copy_from_slice(b"09");
+        std::fs::write(&path, &future).unwrap();
+        let err = CrystalIndex::load(&path).err().unwrap();
+        assert!(err.starts_with("Unsupported index format version 09") && err.ends_with(&path), "{}", err);
+        std::fs::write(&path, b"definitely not an index").unwrap();
+        assert!(CrystalIndex::load(&path).err().unwrap().starts_with("Not an index snapshot"));
+        
+        // Any truncation or flipped byte loads or errors, never panics
+        for cut in 0..bytes.len() {
+            std::fs::write(&path, &bytes[..cut]).unwrap();
+            let _ = CrystalIndex::load(&path);
+            let mut flipped = bytes.clone();
+            flipped[cut] ^= 0xA5;
+            std::fs::write(&path, &flipped).unwrap();
+            let _ = CrystalIndex::load(&path);
+        }
+    }
+    
+    #[test]
+    fn test_provenance_mismatch_and_override() {
+        use crate::jina_api::{EmbedOptions, JinaClient};
+        
+        let client = JinaClient::new("");
+        let passages = EmbedOptions::passage().with_dimensions(8);
+        let mut index = CrystalIndex::new(8).with_provenance(client.provenance(&passages));
+        let response = client.embed_batch_full(&["Ada", "Grace"], &passages).unwrap();
+        assert_eq!(response.provenance.as_ref(), index.provenance());
+        index.add_response(&[1, 2], &response, ProvenanceCheck::Enforce).unwrap();
+        
+        // A query embedded for another task is refused unless overridden
+        let query = client.embed_batch_full(&["Ada"], &EmbedOptions::query().with_dimensions(8)).unwrap();
+        let err = index.search_checked(&query.embeddings[0], 1, query.provenance.as_ref(), ProvenanceCheck::Enforce).unwrap_err();
+        assert_eq!(err.expected.task.as_deref(), Some("retrieval.passage"));
+        assert_eq!(err.found.unwrap().task.as_deref(), Some("retrieval.query"));
+        let hits = index.search_checked(&query.embeddings[0], 1, query.provenance.as_ref(), ProvenanceCheck::Override).unwrap();
+        assert_eq!(hits, index.search(&query.embeddings[0], 1));
+        
+        // Other dimensions, other models and unknown provenance are refused before anything is added
+        let sized = client.embed_batch_full(&["Jan"], &EmbedOptions::passage().with_dimensions(4)).unwrap();
+        assert!(matches!(index.add_response(&[3], &sized, ProvenanceCheck::Enforce), Err(IndexError::ProvenanceMismatch(_))));
+        let other = Provenance::new("jina-embeddings-v2-base-en", 8).with_task("retrieval.passage");
+        assert!(matches!(index.add_checked(3, &response.embeddings[0], Metadata::new(), Some(&other), ProvenanceCheck::Enforce),
+                         Err(IndexError::ProvenanceMismatch(ProvenanceMismatch { found: Some(_), .. }))));
+        let err = index.add_checked(3, &response.embeddings[0], Metadata::new(), None, ProvenanceCheck::Enforce).unwrap_err();
+        assert!(err.to_string().ends_with("vectors have no provenance"), "{}", err);
+        assert_eq!(index.len(), 2);
+        index.add_checked(3, &response.embeddings[0], Metadata::new(), None, ProvenanceCheck::Override).unwrap();
+        assert!(matches!(index.add_response(&[4, 4], &response, ProvenanceCheck::Enforce), Err(IndexError::Rejected(_))));
+        assert_eq!(index.len(), 3);
+        
+        // Indexes without a provenance take anything
+        let mut plain = CrystalIndex::new(4);
+        plain.add_response(&[1], &sized, ProvenanceCheck::Enforce).unwrap();
+        assert!(plain.search_checked(&sized.embeddings[0], 1, None, ProvenanceCheck::Enforce).is_ok());
+    }
+    
+    #[test]
+    fn test_provenance_persists() {
+        let path = temp_path("provenance.idx");
+        let provenance = Provenance::new("jina-embeddings-v3", 3).with_task("retrieval.passage");
+        let mut index = CrystalIndex::new(3).with_provenance(provenance.clone());
+        index.add(1, &vec3(1.0, 0.0, 0.0)).unwrap();
+        index.save(&path).unwrap();
+        index.add(2, &vec3(0.0, 1.0, 0.0)).unwrap();
+        index.save_incremental(&path).unwrap();
+        let mut loaded = CrystalIndex::load(&path).unwrap();
+        assert_eq!((loaded.provenance(), loaded.len()), (Some(&provenance), 2));
+        loaded.compact();
+        assert_eq!(loaded.provenance(), Some(&provenance));
+        
+        // Increments must come from an index of the same provenance
+        let mut other = CrystalIndex::new(3).with_provenance(provenance.clone().with_normalized(true));
+        other.add(3, &vec3(0.0, 0.0, 1.0)).unwrap();
+        assert!(other.save_incremental(&path).unwrap_err().contains("normalized"));
+        let mut unknown = CrystalIndex::new(3);
+        unknown.add(3, &vec3(0.0, 0.0, 1.0)).unwrap();
+        assert!(unknown.save_incremental(&path).unwrap_err().contains("index has no provenance"));
+        
+        // Version 03 files (no provenance flag) load without one
+        let mut plain = CrystalIndex::new(3);
+        plain.add(1, &vec3(1.0, 0.0, 0.0)).unwrap();
+        plain.save(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let mut v03 = bytes[..HEADER_LEN].to_vec();
+        v03[6..8].copy_from_slice(UNPROVENANCED_VERSION);
+        v03.extend_from_slice(&bytes[HEADER_LEN + 1..]);
+        std::fs::write(&path, &v03).unwrap();
+        let loaded = CrystalIndex::load(&path).unwrap();
+        assert_eq!((loaded.provenance(), loaded.get(1)), (None, Some(&vec3(1.0, 0.0, 0.0)[..])));
+    }
+    
+    #[test]
+    fn test_hybrid_search_reorders_and_persists() {
+        let keyword = SparseVector::from_pairs([(42, 1.0)]);
+        let mut index = CrystalIndex::new(2);
+        index.add_with_sparse(1, &[1.0, 0.0], SparseVector::from_pairs([(7, 1.0)]), Metadata::new()).unwrap();
+        index.add_with_sparse(2, &[0.8, 0.6], keyword.clone(), Metadata::new()).unwrap();
+        index.add(3, &[0.0, 1.0]).unwrap();
+        
+        let query = [1.0, 0.0];
+        assert_eq!(ids(&index, &query), [1, 2, 3]);
+        let hybrid: Vec<u64> = index.search_hybrid(&query, &keyword, 0.5, 3, None).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(hybrid, [2, 1, 3]);
+        // alpha 1 is dense-only ranking
+        assert_eq!(index.search_hybrid(&query, &keyword, 1.0, 3, None), index.search(&query, 3));
+        
+        // Sparse vectors survive snapshots, increments and compaction
+        let path = temp_path("sparse.idx");
+        index.save(&path).unwrap();
+        index.add_with_sparse(4, &[0.6, 0.8], keyword.clone(), Metadata::new()).unwrap();
+        index.remove(1);
+        index.save_incremental(&path).unwrap();
+        let mut loaded = CrystalIndex::load(&path).unwrap();
+        loaded.compact();
+        assert_eq!((loaded.sparse(1), loaded.sparse(2), loaded.sparse(3)), (None, Some(&keyword), None));
+        assert_eq!(loaded.sparse(4), Some(&keyword));
+        
+        // Without sparse vectors the file is unchanged
+        let mut plain = CrystalIndex::new(2);
+        plain.add(1, &[1.0, 0.0]).unwrap();
+        plain.save(&path).unwrap();
+        // Header, provenance flag, id, vector, metadata
+        assert_eq!(std::fs::metadata(&path).unwrap().len() as usize, HEADER_LEN + 1 + 8 + 8 + Metadata::new().encoded_len());
+    }
+    
+    #[test]
+    fn test_stats_and_compaction() {
+        let path = temp_path("compaction.idx");
+        let mut index = CrystalIndex::new(3);
+        for id in 0..200u64 {
+            let x = id as f32;
+            index.add_with_metadata(id, &vec3(x.sin(), x.cos(), 1.0), lang_year("en", id as i64)).unwrap();
+        }
+        index.save(&path).unwrap();
+        for id in (0..200u64).filter(|id| id % 4 != 0) {
+            index.remove(id);
+        }
+        index.save_incremental(&path).unwrap();
+        let logged = std::fs::metadata(&path).unwrap().len();
+        
+        let stats = index.stats();
+        assert_eq!((stats.vectors, stats.tombstones, stats.dimension, stats.bytes_graph), (50, 150, 3, 0));
+        assert!(stats.bytes_vectors >= 200 * 4 * 4);
+        assert_eq!(stats.quantization, Quantization::None);
+        
+        let queries = [vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 1.0), vec3(-1.0, 0.5, 0.2)];
+        let before: Vec<_> = queries.iter().map(|q| index.search(q, 10)).collect();
+        let reclaimed = index.compact();
+        let compacted = index.stats();
+        assert_eq!((compacted.vectors, compacted.tombstones), (50, 0));
+        assert_eq!(reclaimed, stats.bytes_total() - compacted.bytes_total());
+        assert!(compacted.bytes_vectors < stats.bytes_vectors && reclaimed > 0);
+        assert_eq!(index.compact(), 0);
+        
+        // The compacted snapshot is smaller than snapshot plus log and searches the same
+        index.save(&path).unwrap();
+        assert!(std::fs::metadata(&path).unwrap().len() < logged);
+        let loaded = CrystalIndex::load(&path).unwrap();
+        assert_eq!((loaded.stats().vectors, loaded.stats().tombstones), (50, 0));
+        for (query, expected) in queries.iter().zip(&before) {
+            assert_eq!(&loaded.search(query, 10), expected);
+        }
+    }
+    
+    #[test]
+    fn test_metrics_rank_prune_and_persist() {
+        use rand::prelude::*;
+        // q = (1, 0, 0): cosine prefers 1 then 2, dot the long 2, distance the near 3
+        let mut index = CrystalIndex::new(3);
+        index.add(1, &vec3(1.0, 0.0, 0.0)).unwrap();
+        index.add(2, &vec3(3.0, 0.3, 0.0)).unwrap();
+        index.add(3, &vec3(0.6, 0.6, 0.0)).unwrap();
+        let order = |index: &CrystalIndex| index.search(&vec3(1.0, 0.0, 0.0), 3).into_iter().map(|h| h.0).collect::<Vec<_>>();
+        assert_eq!(order(&index), [1, 2, 3]);
+        let dot = index.clone().with_metric(Metric::Dot);
+        assert_eq!(order(&dot), [2, 1, 3]);
+        let euclidean = index.clone().with_metric(Metric::Euclidean);
+        assert_eq!(order(&euclidean), [1, 3, 2]);
+        // Negated distances: a min_score of -1 keeps hits within distance 1
+        let hits = euclidean.search_with(&vec3(1.0, 0.0, 0.0), &SearchOptions::top(3).with_min_score(-1.0), None);
+        assert_eq!(hits.iter().map(|h| (h.id, h.cosine)).collect::<Vec<_>>(), [(1, 0.0), (3, -(0.52f32.sqrt()))]);
+        
+        let mut rng = StdRng::seed_from_u64(5);
+        for metric in [Metric::Dot, Metric::Euclidean] {
+            let mut index = CrystalIndex::new(96).with_metric(metric);
+            for id in 0..1000u64 {
+                let scale = rng.gen_range(0.1..4.0f32);
+                index.add(id, &(0..96).map(|_| rng.gen_range(-1.0..1.0f32) * scale).collect::<Vec<_>>()).unwrap();
+            }
+            index.add(2000, &vec![0.0; 96]).unwrap();
+            for _ in 0..10 {
+                let query: Vec<f32> = (0..96).map(|_| rng.gen_range(-2.0..2.0f32)).collect();
+                for k in [1, 10, 50] {
+                    assert_eq!(index.search(&query, k), index.search_exhaustive(&query, k), "{:?} k {}", metric, k);
+                }
+            }
+            
+            let path = temp_path(&format!("{}.idx", metric.as_str()));
+            index.save(&path).unwrap();
+            let loaded = CrystalIndex::load(&path).unwrap();
+            assert_eq!(loaded.metric(), metric);
+            let err = CrystalIndex::new(96).save_incremental(&path).unwrap_err();
+            assert!(err.contains(&format!("ranks by {}", metric.as_str())), "{}", err);
+            std::fs::remove_file(&path).ok();
+        }
+        
+        let provenance = Provenance::new("m", 3);
+        let dot = dot.with_provenance(provenance.clone());
+        assert!(dot.metric_warning(Some(&provenance)).is_none());
+        assert!(dot.metric_warning(None).unwrap().contains("unknown"));
+        let unnormalized = provenance.clone().with_normalized(!provenance.normalized);
+        assert!(dot.metric_warning(Some(&unnormalized)).is_some());
+        assert!(index.with_provenance(provenance).metric_warning(Some(&unnormalized)).is_none());
+    }
+    
+    #[test]
+    fn test_schema_validates_persists_and_migrates() {
+        use crate::schema::{FieldType, MetadataSchema, SchemaError, SchemaMigration};
+        
+        let path = temp_path("schema.idx");
+        // Enough fields that the schema outgrows `read_header`'s first read
+        let mut schema = MetadataSchema::new().required("lang", FieldType::String).required("year", FieldType::Int);
+        for i in 0..40 {
+            schema = schema.optional(&format!("note_{}", i), FieldType::String);
+        }
+        let mut index = CrystalIndex::new(3).with_schema(schema.clone());
+        index.add_with_metadata(1, &vec3(1.0, 0.0, 0.0), lang_year("en", 2022)).unwrap();
+        let err = index.add_with_metadata(2, &vec3(0.0, 1.0, 0.0), Metadata::new().with("lang", "de")).unwrap_err();
+        assert_eq!(err, "Missing required field 'year'");
+        let err = index.add_checked(2, &vec3(0.0, 1.0, 0.0), lang_year("de", 2021).with("langg", "de"), None,
+                                    ProvenanceCheck::Override).unwrap_err();
+        assert!(matches!(&err, IndexError::Schema(SchemaError::UnknownField { field, .. }) if field == "langg"), "{}", err);
+        assert_eq!(index.len(), 1);
+        assert!(index.check_filter(["lang"]).is_ok());
+        assert_eq!(index.check_filter(["langg"]).unwrap_err().to_string(), "Unknown field 'langg' (did you mean 'lang'?)");
+        assert!(CrystalIndex::new(3).check_filter(["langg"]).is_ok());
+        
+        // The schema survives a save, increments and compaction
+        index.save(&path).unwrap();
+        index.add_with_metadata(2, &vec3(0.0, 1.0, 0.0), lang_year("de", 2021)).unwrap();
+        index.save_incremental(&path).unwrap();
+        let mut loaded = CrystalIndex::load(&path).unwrap();
+        assert_eq!((loaded.schema(), loaded.len()), (Some(&schema), 2));
+        assert!(loaded.add(3, &vec3(0.0, 0.0, 1.0)).is_err());
+        loaded.compact();
+        assert_eq!(loaded.schema(), Some(&schema));
+        let mut unschemed = CrystalIndex::new(3);
+        unschemed.add(3, &vec3(0.0, 0.0, 1.0)).unwrap();
+        assert!(unschemed.save_incremental(&path).unwrap_err().contains("metadata schema"));
+        
+        // Migrating: a new required field needs a default, and nothing changes until every entry fits
+        let venues = FieldType::Enum(vec!["acl".to_string(), "sigir".to_string()]);
+        let next = MetadataSchema::new().required("language", FieldType::String).required("year", FieldType::Int)
+            .required("venue", venues);
+        let renamed = SchemaMigration::new().with_rename("lang", "language");
+        assert_eq!(loaded.migrate_schema(next.clone(), &renamed), Err(SchemaError::NoDefault("venue".to_string())));
+        let err = loaded.migrate_schema(next.clone(), &SchemaMigration::new().with_default("venue", "acl").with_default("language", "en"))
+            .unwrap_err();
+        assert!(matches!(&err, SchemaError::Entry { error, .. } if matches!(**error, SchemaError::UnknownField { .. })));
+        assert_eq!(loaded.schema(), Some(&schema));
+        assert_eq!(loaded.migrate_schema(next.clone(), &renamed.with_default("venue", "acl")), Ok(2));
+        assert_eq!(loaded.metadata(2).unwrap(), &Metadata::new().with("language", "de").with("year", 2021).with("venue", "acl"));
+        loaded.save(&path).unwrap();
+        assert_eq!(CrystalIndex::load(&path).unwrap().schema(), Some(&next));
+    }
+}
@@ -0,0 +1,774 @@
+//! Embedding records for vector databases and data platforms
+//!
+//! `export_qdrant_points` writes Qdrant upsert bodies and
+//! `export_pgvector_copy` writes rows for Postgres `COPY`; both stream
+//! records straight to a writer. With the `arrow` feature,
+//! `write_parquet` stores records under the schema
+//! `id: Utf8, text: Utf8 (nullable), embedding: FixedSizeList<Float32, dims>,
+//! metadata: Utf8` with the metadata as a JSON object, so data platforms
+//! read the vectors as a native list column. Records go out
+//! `ROW_GROUP_ROWS` at a time, one row group each, and `read_parquet_with`
+//! streams them back a row group at a time, so neither side holds more
+//! than one group's columns in memory.
+//!
+//! Every file this crate persists (indexes, caches, stores, containers,
+//! exports) is written with `atomic_write`: into a temporary file beside
+//! the target, synced, then renamed over it, so a crash leaves either the
+//! old file or the new one, never a torn one. Append-only logs (index
+//! increments) cannot be renamed into place; their records are
+//! length-prefixed and checksummed instead: a torn last record is
+//! dropped on load and cut off before the next append.
+//!
+//! Text exports write vector components at an `ExportPrecision`. `Full`
+//! keeps each f32's shortest round-tripping form; the reduced forms round
+//! each component, then write the rounded f32 the same way, so every file
+//! reads back with any float parser. For unit vectors, cosines from
+//! `SignificantDigits(n)` stay within `10^(1-n)` of the originals and from
+//! `FixedDecimals(n)` within `10^-n * sqrt(dims)`. `Full` already writes
+//! no more than 9 significant digits, so 6 save about a seventh of a dense
+//! 1024-dim export.
+//!
+//! `export_jsonl` writes records one JSON object per line behind a header
+//! line, each line carrying the CRC32 of the rest of it; `read_jsonl` reads
+//! them back under a `VerifyMode`, and `verify` checks such a file, or an
+//! `embeddings` container, without keeping any vectors, reporting which
+//! records are damaged. Header-less JSONL reads as unchecksummed.
+//!
+//! `EmbeddingRecord::from_response` flags each record's metadata
+//! `synthetic` or not, so exported pseudo-embeddings stay recognizable in
+//! the Qdrant payloads, Postgres rows and Parquet files they end up in.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::metadata::Metadata;
+use crate::provider::EmbeddingResponse;
+
+#[cfg(feature = "arrow")]
+pub use parquet_file::{read_parquet, read_parquet_with, write_parquet, ROW_GROUP_ROWS};
+
+/// Metadata field `EmbeddingRecord::from_response` sets for pseudo-embeddings
+pub const SYNTHETIC_FIELD: &str = "synthetic";
+
+/// `format` of the `export_jsonl` header line
+pub const JSONL_FORMAT: &str = "spo-crystal-records";
+
+/// Points per Qdrant upsert line
+pub const QDRANT_BATCH_POINTS: usize = 256;
+/// Bytes per Qdrant upsert line at most, well under the server's 32 MiB default (unless one point is larger)
+pub const QDRANT_BATCH_BYTES: usize = 8 << 20;
+
+/// How text exports write vector components
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExportPrecision {
+    /// Shortest form that reads back as the same f32
+    #[default]
+    Full,
+    /// Rounded to this many significant digits, at least 1
+    SignificantDigits(u8),
+    /// Rounded to this many digits after the point
+    FixedDecimals(u8),
+}
+
+impl ExportPrecision {
+    /// `x` rounded to this precision; non-finite values are an error
+    pub fn round(self, x: f32) -> Result<f32, String> {
+        if !x.is_finite() {
+            return Err(format!("Cannot export non-finite value {}", x));
+        }
+        let rounded = match self {
+            ExportPrecision::Full => return Ok(x),
+            ExportPrecision::SignificantDigits(0) => return Err("SignificantDigits needs at least 1 digit".to_string()),
+            ExportPrecision::SignificantDigits(n) => format!("{:.*e}", n as usize - 1, x),
+            ExportPrecision::FixedDecimals(n) => format!("{:.*}", n as usize, x),
+        };
+        rounded.parse().map_err(|e| format!("Cannot read back {}: {}", rounded, e))
+    }
+    
+    /// `x` rounded to this precision in its shortest round-tripping form
+    pub fn format(self, x: f32) -> Result<String, String> {
+        self.round(x).map(|r| r.to_string())
+    }
+}
+
+/// What reading does with records whose checksum fails
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VerifyMode {
+    /// Any damaged record is an error
+    #[default]
+    Strict,
+    /// Damaged records are left out and listed in the `VerifyReport`
+    WarnAndSkip,
+    /// Checksums are not compared; records that cannot be parsed are still errors
+    Off,
+}
+
+/// The records a read or `verify` found damaged
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Records the file holds, damaged ones included
+    pub records: usize,
+    /// Damaged record ranges, in order and merged where adjacent
+    pub damaged: Vec<Range<usize>>,
+    /// False for files written without checksums, where only parse errors
+    /// and truncation can be found
+    pub checksummed: bool,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool { self.damaged.is_empty() }
+    
+    pub fn damaged_records(&self) -> usize { self.damaged.iter().map(|r| r.len()).sum() }
+    
+    pub(crate) fn mark(&mut self, rows: Range<usize>) {
+        match self.damaged.last_mut() {
+            Some(last) if last.end == rows.start => last.end = rows.end,
+            _ => self.damaged.push(rows),
+        }
+    }
+}
+
+/// One embedded text with its id and metadata
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EmbeddingRecord {
+    pub id: String,
+    #[serde(default)]
+    pub text: Option<String>,
+    pub embedding: Vec<f32>,
+    #[serde(default)]
+    pub metadata: Metadata,
+}
+
+impl EmbeddingRecord {
+    pub fn new(id: &str, embedding: Vec<f32>) -> Self {
+        Self { id: id.to_string(), text: None, embedding, metadata: Metadata::new() }
+    }
+    
+    /// One record per vector of `response`, under `ids`, each with the
+    /// `SYNTHETIC_FIELD` metadata flag of its backend
+    pub fn from_response(ids: &[&str], response: &EmbeddingResponse) -> Result<Vec<Self>, String> {
+        if ids.len() != response.embeddings.len() {
+            return Err(format!("{} ids for {} vectors", ids.len(), response.embeddings.len()));
+        }
+        let synthetic = response.backend.is_synthetic();
+        Ok(ids.iter().zip(&response.embeddings)
+            .map(|(id, embedding)| Self::new(id, embedding.clone()).with_metadata(Metadata::new().with(SYNTHETIC_FIELD, synthetic)))
+            .collect())
+    }
+    
+    pub fn with_text(mut self, text: &str) -> Self {
+        self.text = Some(text.to_string());
+        self
+    }
+    
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
+/// Write `records` as NDJSON Qdrant upsert bodies, `{"points":[{id, vector, payload}, ..]}`
+/// per line, each within `QDRANT_BATCH_POINTS` and `QDRANT_BATCH_BYTES`
+///
+/// Ids must be unsigned integers or UUIDs, as Qdrant requires. The payload
+/// holds the metadata fields plus `text` when the record has one.
+/// Records are checked as they stream: one with non-finite components or
+/// with other dims than the first stops the export with an error.
+pub fn export_qdrant_points<'a>(writer: impl Write, records: impl IntoIterator<Item = &'a EmbeddingRecord>)
+                                -> Result<(), String> {
+    export_qdrant_points_with(writer, records, ExportPrecision::Full)
+}
+
+/// `export_qdrant_points` with the vectors written at `precision`
+pub fn export_qdrant_points_with<'a>(mut writer: impl Write, records: impl IntoIterator<Item = &'a EmbeddingRecord>,
+                                     precision: ExportPrecision) -> Result<(), String> {
+    let mut dims = None;
+    let mut batch = String::new();
+    let mut points = 0;
+    let flush = |writer: &mut dyn Write, batch: &mut String, points: &mut usize| -> Result<(), String> {
+        if *points > 0 {
+            batch.push_str("]}\n");
+            writer.write_all(batch.as_bytes()).map_err(|e| format!("Write failed: {}", e))?;
+        }
+        batch.clear();
+        *points = 0;
+        Ok(())
+    };
+    for (i, record) in records.into_iter().enumerate() {
+        check_vector(i, record, &mut dims)?;
+        let id = match record.id.parse::<u64>() {
+            Ok(n) => n.to_string(),
+            Err(_) if is_uuid(&record.id) => serde_json::Value::from(record.id.as_str()).to_string(),
+            Err(_) => return Err(format!("Record {} ({}): Qdrant ids must be unsigned integers or UUIDs", i, record.id)),
+        };
+        let mut payload = record.metadata.to_json_map();
+        if let Some(text) = &record.text {
+            payload.insert("text".to_string(), text.as_str().into());
+        }
+        let vector = vector_literal(&record.embedding, precision).map_err(|e| format!("Record {} ({}): {}", i, record.id, e))?;
+        let point = format!(r#"{{"id":{},"vector":{},"payload":{}}}"#, id, vector, serde_json::Value::Object(payload));
+        if points == QDRANT_BATCH_POINTS || (points > 0 && batch.len() + point.len() + 3 > QDRANT_BATCH_BYTES) {
+            flush(&mut writer, &mut batch, &mut points)?;
+        }
+        batch.push_str(if points == 0 { r#"{"points":["# } else { "," });
+        batch.push_str(&point);
+        points += 1;
+    }
+    flush(&mut writer, &mut batch, &mut points)?;
+    writer.flush().map_err(|e| format!("Write failed: {}", e))
+}
+
+/// Write `records` as tab-separated rows for `COPY <table> (id, text, embedding, metadata) FROM STDIN`
+///
+/// This is `COPY`'s text format: the embedding is a pgvector `[x,y,z]`
+/// literal, the metadata a JSON object for a `jsonb` column, a missing text
+/// is `\N`, and backslashes, tabs, newlines and carriage returns in fields
+/// are escaped. Records are checked as for `export_qdrant_points`.
+pub fn export_pgvector_copy<'a>(writer: impl Write, records: impl IntoIterator<Item = &'a EmbeddingRecord>)
+                                -> Result<(), String> {
+    export_pgvector_copy_with(writer, records, ExportPrecision::Full)
+}
+
+/// `export_pgvector_copy` with the vectors written at `precision`
+pub fn export_pgvector_copy_with<'a>(mut writer: impl Write, records: impl IntoIterator<Item = &'a EmbeddingRecord>,
+                                     precision: ExportPrecision) -> Result<(), String> {
+    let mut dims = None;
+    let mut line = String::new();
+    for (i, record) in records.into_iter().enumerate() {
+        check_vector(i, record, &mut dims)?;
+        line.clear();
+        push_copy_field(&mut line, &record.id);
+        line.push('\t');
+        match &record.text {
+            Some(text) => push_copy_field(&mut line, text),
+            None => line.push_str("\\N"),
+        }
+        line.push('\t');
+        line.push_str(&vector_literal(&record.embedding, precision).map_err(|e| format!("Record {} ({}): {}", i, record.id, e))?);
+        line.push('\t');
+        push_copy_field(&mut line, &record.metadata.to_json());
+        line.push('\n');
+        writer.write_all(line.as_bytes()).map_err(|e| format!("Write failed: {}", e))?;
+    }
+    writer.flush().map_err(|e| format!("Write failed: {}", e))
+}
+
+/// Write `records` as checksummed JSONL: a header line
+/// `{"format":"spo-crystal-records","version":1,"crc32":".."}`, then one
+/// `EmbeddingRecord` object per line, each with a `crc32` field (eight hex
+/// digits) over the line as it reads without that field. Records are
+/// checked as for `export_qdrant_points` and written as they stream.
+pub fn export_jsonl<'a>(mut writer: impl Write, records: impl IntoIterator<Item = &'a EmbeddingRecord>) -> Result<(), String> {
+    let header = format!(r#"{{"format":"{}","version":1}}"#, JSONL_FORMAT);
+    writer.write_all(sealed(&header).as_bytes()).map_err(|e| format!("Write failed: {}", e))?;
+    let mut dims = None;
+    for (i, record) in records.into_iter().enumerate() {
+        check_vector(i, record, &mut dims)?;
+        let json = serde_json::to_string(record).map_err(|e| format!("Record {} ({}): {}", i, record.id, e))?;
+        writer.write_all(sealed(&json).as_bytes()).map_err(|e| format!("Write failed: {}", e))?;
+    }
+    writer.flush().map_err(|e| format!("Write failed: {}", e))
+}
+
+/// Read JSONL records as `export_jsonl` writes them, treating lines whose
+/// checksum fails, and a last line cut short, as `mode` says. A file
+/// without the header line is read as unchecksummed JSONL.
+pub fn read_jsonl(reader: impl BufRead, mode: VerifyMode) -> Result<(Vec<EmbeddingRecord>, VerifyReport), String> {
+    let mut records = Vec::new();
+    let report = scan_jsonl(reader, mode, |_, record| {
+        records.push(serde_json::from_str(record)?);
+        Ok(())
+    })?;
+    Ok((records, report))
+}
+
+/// Check an `embeddings` container or an `export_jsonl` file at `path`
+/// record by record, without keeping the vectors. Damage is reported, not
+/// an error; a file that is neither, or whose header is damaged, is.
+pub fn verify(path: &str) -> Result<VerifyReport, String> {
+    let file = File::open(path).map_err(|e| format!("Cannot open {}: {}", path, e))?;
+    let mut reader = BufReader::new(file);
+    let start = reader.fill_buf().map_err(|e| format!("Read failed: {}", e))?;
+    if start.starts_with(crate::embeddings::MAGIC) {
+        return crate::embeddings::verify_container(&mut reader).map_err(|e| format!("{}: {}", e, path));
+    }
+    scan_jsonl(reader, VerifyMode::WarnAndSkip, |_, record| {
+        serde_json::from_str::<serde::de::IgnoredAny>(record).map(|_| ())
+    }).map_err(|e| format!("{}: {}", e, path))
+}
+
+/// Walk the record lines of a JSONL file, handing `visit` each one with
+/// its checksum field removed, and marking or failing on damaged ones per `mode`
+fn scan_jsonl(mut reader: impl BufRead, mode: VerifyMode,
+              mut visit: impl FnMut(usize, &str) -> serde_json::Result<()>) -> Result<VerifyReport, String> {
+    let mut report = VerifyReport::default();
+    let header = format!(r#"{{"format":"{}","#, JSONL_FORMAT);
+    let mut line = String::new();
+    let mut first = true;
+    let mut i = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).map_err(|e| format!("Read failed at record {}: {}", i, e))? == 0 {
+            break;
+        }
+        let text = line.trim_end_matches(['\n', '\r']);
+        if std::mem::take(&mut first) && text.starts_with(&header) {
+            if mode != VerifyMode::Off && unsealed(text).is_none() {
+                return Err("Header checksum mismatch".to_string());
+            }
+            report.checksummed = true;
+            continue;
+        }
+        if text.trim().is_empty() {
+            continue;
+        }
+        let record = match (report.checksummed, mode) {
+            (false, _) => Some(text.to_string()),
+            (true, VerifyMode::Off) => Some(split_seal(text).map_or_else(|| text.to_string(), |(json, _)| json)),
+            (true, _) => unsealed(text),
+        };
+        let damage = match record {
+            Some(record) => visit(i, &record).err().map(|e| e.to_string()),
+            None => Some("checksum mismatch".to_string()),
+        };
+        if let Some(e) = damage {
+            if mode != VerifyMode::WarnAndSkip {
+                return Err(format!("Record {} is damaged: {}", i, e));
+            }
+            report.mark(i..i + 1);
+        }
+        i += 1;
+    }
+    report.records = i;
+    Ok(report)
+}
+
+/// `json`, an object, as one line with its `crc32` field appended
+fn sealed(json: &str) -> String {
+    format!("{},\"crc32\":\"{:08x}\"}}\n", &json[..json.len() - 1], crc32(json.as_bytes()))
+}
+
+/// A sealed line's object without its `crc32` field, and the stored checksum
+fn split_seal(line: &str) -> Option<(String, u32)> {
+    const SEAL: usize = r#","crc32":"00000000"}"#.len();
+    let (body, seal) = line.split_at_checked(line.len().checked_sub(SEAL)?)?;
+    let stored = seal.strip_prefix(r#","crc32":""#)?.strip_suffix(r#""}"#)?;
+    Some((format!("{}}}", body), u32::from_str_radix(stored, 16).ok()?))
+}
+
+/// A sealed line's object, if its checksum holds
+fn unsealed(line: &str) -> Option<String> {
+    split_seal(line).filter(|(json, stored)| crc32(json.as_bytes()) == *stored).map(|(json, _)| json)
+}
+
+/// CRC32 (IEEE), as gzip and zip use
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = flate2::Crc::new();
+    crc.update(bytes);
+    crc.sum()
+}
+
+/// Replace `path` with what `write` writes, atomically.
+///
+/// `write` fills a new file in the same directory, which is synced and
+/// renamed over `path` (on Windows, `ReplaceFileW` if the rename is
+/// refused). If `write` or any step fails, `path` is untouched and the
+/// temporary file removed.
+pub fn atomic_write(path: impl AsRef<Path>, write: impl FnOnce(&mut File) -> std::io::Result<()>) -> std::io::Result<()> {
+    let path = path.as_ref();
+    let name = path.file_name()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{} is not a file path", path.display())))?;
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let (temp, mut file) = create_temp(dir, &name.to_string_lossy())?;
+    let written = write(&mut file).and_then(|()| file.sync_all());
+    drop(file);
+    if let Err(e) = written.and_then(|()| replace(&temp, path)) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e);
+    }
+    sync_dir(dir);
+    Ok(())
+}
+
+/// `.name.<n>.tmp` in `dir`, created new; `n` is unique in this process
+/// and skips names other processes hold
+pub(crate) fn create_temp(dir: &Path, name: &str) -> std::io::Result<(std::path::PathBuf, File)> {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    loop {
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let temp = dir.join(format!(".{}.{}-{}.tmp", name, process_id(), n));
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn process_id() -> u32 { std::process::id() }
+
+#[cfg(target_arch = "wasm32")]
+fn process_id() -> u32 { 0 }
+
+#[cfg(not(windows))]
+fn replace(from: &Path, to: &Path) -> std::io::Result<()> { std::fs::rename(from, to) }
+
+/// `rename` replaces existing files on Windows too, but not one another
+/// process holds open without delete sharing; `ReplaceFileW` can
+#[cfg(windows)]
+fn replace(from: &Path, to: &Path) -> std::io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn ReplaceFileW(replaced: *const u16, replacement: *const u16, backup: *const u16, flags: u32,
+                        exclude: *mut std::ffi::c_void, reserved: *mut std::ffi::c_void) -> i32;
+    }
+    const REPLACEFILE_IGNORE_MERGE_ERRORS: u32 = 0x2;
+    
+    let Err(renamed) = std::fs::rename(from, to) else { return Ok(()) };
+    if !to.exists() {
+        return Err(renamed);
+    }
+    let wide = |p: &Path| p.as_os_str().encode_wide().chain(Some(0)).collect::<Vec<u16>>();
+    let (to_w, from_w) = (wide(to), wide(from));
+    // SAFETY: both paths are NUL-terminated and outlive the call; the optional pointers are null
+    let ok = unsafe {
+        ReplaceFileW(to_w.as_ptr(), from_w.as_ptr(), std::ptr::null(), REPLACEFILE_IGNORE_MERGE_ERRORS,
+                     std::ptr::null_mut(), std::ptr::null_mut())
+    };
+    if ok != 0 { Ok(()) } else { Err(std::io::Error::last_os_error()) }
+}
+
+/// Make the rename durable; directories cannot be synced on every platform
+fn sync_dir(dir: &Path) {
+    #[cfg(unix)]
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    #[cfg(not(unix))]
+    let _ = dir;
+}
+
+/// Refuse non-finite components and dims other than the first record's
+fn check_vector(i: usize, record: &EmbeddingRecord, dims: &mut Option<usize>) -> Result<(), String> {
+    let expected = *dims.get_or_insert(record.embedding.len());
+    if record.embedding.len() != expected {
+        return Err(format!("Record {} ({}) has {} dims, expected {}", i, record.id, record.embedding.len(), expected));
+    }
+    if record.embedding.iter().any(|x| !x.is_finite()) {
+        return Err(format!("Record {} ({}) has a non-finite component", i, record.id));
+    }
+    Ok(())
+}
+
+/// `[x,y,z]` with each component at `precision`
+fn vector_literal(v: &[f32], precision: ExportPrecision) -> Result<String, String> {
+    use std::fmt::Write as _;
+    let mut out = String::with_capacity(v.len() * 10 + 2);
+    out.push('[');
+    for (i, &x) in v.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(out, "{}", precision.round(x)?);
+    }
+    out.push(']');
+    Ok(out)
+}
+
+/// `field` escaped for `COPY`'s text format
+fn push_copy_field(out: &mut String, field: &str) {
+    for c in field.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// 8-4-4-4-12 hex digits
+fn is_uuid(s: &str) -> bool {
+    s.len() == 36 && s.char_indices().all(|(i, c)| match i {
+        8 | 13 | 18 | 23 => c == '-',
+        _ => c.is_ascii_hexdigit(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn exported(records: &[EmbeddingRecord]) -> Result<String, String> {
+        let mut out = Vec::new();
+        export_qdrant_points(&mut out, records)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+    
+    #[test]
+    fn test_qdrant_batches_and_ids() {
+        let records: Vec<EmbeddingRecord> = (0..QDRANT_BATCH_POINTS * 2 + 10)
+            .map(|i| EmbeddingRecord::new(&i.to_string(), vec![i as f32, 0.5]))
+            .collect();
+        let lines: Vec<serde_json::Value> = exported(&records).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        let sizes: Vec<usize> = lines.iter().map(|l| l["points"].as_array().unwrap().len()).collect();
+        assert_eq!(sizes, [QDRANT_BATCH_POINTS, QDRANT_BATCH_POINTS, 10]);
+        assert_eq!(lines[2]["points"][9], serde_json::json!({ "id": 521, "vector": [521, 0.5], "payload": {} }));
+        assert_eq!(exported(&[]).unwrap(), "");
+        
+        // Ids Qdrant cannot take are refused, as are vectors it cannot store
+        let uuid = EmbeddingRecord::new("0f8fad5b-d9cb-469f-a165-70867728950e", vec![1.0]);
+        assert!(exported(std::slice::from_ref(&uuid)).unwrap().contains(r#""id":"0f8fad5b-d9cb-469f-a165-70867728950e""#));
+        let named = EmbeddingRecord::new("doc-1", vec![1.0]);
+        assert_eq!(exported(&[uuid.clone(), named]).unwrap_err(), "Record 1 (doc-1): Qdrant ids must be unsigned integers or UUIDs");
+        let nan = EmbeddingRecord::new("7", vec![f32::NAN]);
+        assert_eq!(exported(&[nan]).unwrap_err(), "Record 0 (7) has a non-finite component");
+        let wide = EmbeddingRecord::new("8", vec![1.0, 2.0]);
+        assert_eq!(exported(&[uuid, wide]).unwrap_err(), "Record 1 (8) has 2 dims, expected 1");
+    }
+    
+    #[test]
+    fn test_precision_rounds_and_refuses_non_finite() {
+        let x = 0.12345679_f32;
+        assert_eq!(ExportPrecision::Full.format(x).unwrap(), "0.12345679");
+        assert_eq!(ExportPrecision::SignificantDigits(3).format(x).unwrap(), "0.123");
+        assert_eq!(ExportPrecision::SignificantDigits(3).format(-98765.4).unwrap(), "-98800");
+        assert_eq!(ExportPrecision::SignificantDigits(2).format(1.5e-9).unwrap(), "0.0000000015");
+        assert_eq!(ExportPrecision::FixedDecimals(2).format(x).unwrap(), "0.12");
+        assert_eq!(ExportPrecision::FixedDecimals(2).format(-0.001).unwrap(), "-0");
+        assert_eq!(ExportPrecision::FixedDecimals(0).format(2.5).unwrap(), "2");
+        
+        for precision in [ExportPrecision::Full, ExportPrecision::SignificantDigits(6), ExportPrecision::FixedDecimals(4)] {
+            assert!(precision.format(f32::NAN).unwrap_err().contains("non-finite"));
+            assert!(precision.format(f32::NEG_INFINITY).is_err());
+        }
+        assert!(ExportPrecision::SignificantDigits(0).format(x).is_err());
+    }
+    
+    #[test]
+    fn test_jsonl_checksums_locate_damaged_records() {
+        let records: Vec<EmbeddingRecord> = (0..5)
+            .map(|i| EmbeddingRecord::new(&format!("doc-{}", i), vec![i as f32, 0.5]).with_text("naïve \"text\""))
+            .collect();
+        let mut out = Vec::new();
+        export_jsonl(&mut out, &records).unwrap();
+        let clean = String::from_utf8(out).unwrap();
+        assert!(clean.starts_with(r#"{"format":"spo-crystal-records","version":1,"crc32":""#));
+        let (read, report) = read_jsonl(clean.as_bytes(), VerifyMode::Strict).unwrap();
+        assert_eq!((read, report.records, report.checksummed, report.is_ok()), (records.clone(), 5, true, true));
+        
+        // A changed digit in record 2 still parses, but fails its checksum
+        let damaged = clean.replacen("[2.0,0.5]", "[3.0,0.5]", 1);
+        assert!(read_jsonl(damaged.as_bytes(), VerifyMode::Strict).unwrap_err().contains("Record 2 is damaged: checksum mismatch"));
+        let (read, report) = read_jsonl(damaged.as_bytes(), VerifyMode::WarnAndSkip).unwrap();
+        assert_eq!((read.len(), report.damaged_records()), (4, 1));
+        assert_eq!(format!("{:?}", report.damaged), "[2..3]");
+        assert_eq!(read_jsonl(damaged.as_bytes(), VerifyMode::Off).unwrap().0[2].embedding, [3.0, 0.5]);
+        
+        // verify reads from disk, and a torn last record fails too
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("records.jsonl").to_str().unwrap().to_string();
+        std::fs::write(&path, &damaged[..damaged.len() - 10]).unwrap();
+        let report = verify(&path).unwrap();
+        assert_eq!((report.records, report.damaged), (5, vec![2..3, 4..5]));
+        std::fs::write(&path, clean.replacen("version", "versiom", 1)).unwrap();
+        assert!(verify(&path).unwrap_err().contains("Header checksum mismatch"));
+        
+        // Plain JSONL has no checksums to fail, only lines that do not parse
+        let plain = format!("{}\n{{\"id\":\n", serde_json::to_string(&records[0]).unwrap());
+        std::fs::write(&path, plain).unwrap();
+        let report = verify(&path).unwrap();
+        assert!(!report.checksummed);
+        assert_eq!(format!("{:?}", report.damaged), "[1..2]");
+    }
+    
+    #[test]
+    fn test_atomic_write_keeps_old_file_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("artifact.bin");
+        atomic_write(&path, |f| f.write_all(b"first")).unwrap();
+        atomic_write(&path, |f| f.write_all(b"second")).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        
+        // A write that fails halfway leaves the old contents and no temporary file
+        let failed = atomic_write(&path, |f| {
+            f.write_all(b"thi")?;
+            Err(std::io::Error::other("disk full"))
+        });
+        assert_eq!(failed.unwrap_err().to_string(), "disk full");
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        let names: Vec<_> = std::fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(names, ["artifact.bin"]);
+        assert!(atomic_write(dir.path().join("missing").join("x.bin"), |f| f.write_all(b"x")).is_err());
+    }
+    
+    #[test]
+    fn test_copy_fields_escape() {
+        let mut out = String::new();
+        push_copy_field(&mut out, "a\tb\nc\rd\\e \"q\" 'q'");
+        assert_eq!(out, r#"a\tb\nc\rd\\e "q" 'q'"#);
+        assert_eq!(vector_literal(&[0.1, -2.0, 1e-7], ExportPrecision::Full).unwrap(), "[0.1,-2,0.0000001]");
+        assert!(!is_uuid("0f8fad5b-d9cb-469f-a165-70867728950") && !is_uuid("0f8fad5bxd9cb-469f-a165-70867728950e"));
+    }
+}
+
+#[cfg(feature = "arrow")]
+mod parquet_file {
+    use std::fs::File;
+    use std::sync::Arc;
+    
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Float32Type;
+    use arrow_array::{Array, ArrayRef, FixedSizeListArray, Float32Array, RecordBatch, StringArray};
+    use arrow_schema::{DataType, Field, Schema};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use parquet::arrow::ArrowWriter;
+    use parquet::basic::Compression;
+    use parquet::file::properties::WriterProperties;
+    
+    use super::{atomic_write, EmbeddingRecord};
+    use crate::metadata::Metadata;
+    
+    /// Records per row group, and per batch when reading
+    pub const ROW_GROUP_ROWS: usize = 4096;
+    
+    fn schema(dims: usize) -> Result<Arc<Schema>, String> {
+        let dims = i32::try_from(dims).map_err(|_| format!("{} dims do not fit a Parquet list", dims))?;
+        let item = Arc::new(Field::new("item", DataType::Float32, false));
+        Ok(Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Utf8, false),
+            Field::new("text", DataType::Utf8, true),
+            Field::new("embedding", DataType::FixedSizeList(item, dims), false),
+            Field::new("metadata", DataType::Utf8, false),
+        ])))
+    }
+    
+    /// Write `records` to `path`, replacing the file
+    ///
+    /// Every embedding must have the first one's length; a mismatch is
+    /// reported before anything is written.
+    pub fn write_parquet(path: &str, records: &[EmbeddingRecord]) -> Result<(), String> {
+        let dims = records.first().map_or(0, |r| r.embedding.len());
+        if let Some((i, record)) = records.iter().enumerate().find(|(_, r)| r.embedding.len() != dims) {
+            return Err(format!("Record {} ({}) has {} dims, expected {}", i, record.id, record.embedding.len(), dims));
+        }
+        let schema = schema(dims)?;
+        
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let batches = records.chunks(ROW_GROUP_ROWS).map(|group| record_batch(&schema, dims, group));
+        atomic_write(path, |file| {
+            let failed = |e: parquet::errors::ParquetError| std::io::Error::other(e.to_string());
+            let mut writer = ArrowWriter::try_new(file, schema.clone(), Some(properties)).map_err(failed)?;
+            for batch in batches {
+                writer.write(&batch.map_err(std::io::Error::other)?).map_err(failed)?;
+                writer.flush().map_err(failed)?;
+            }
+            writer.close().map_err(failed).map(|_| ())
+        }).map_err(|e| format!("Write failed for {}: {}", path, e))
+    }
+    
+    fn record_batch(schema: &Arc<Schema>, dims: usize, records: &[EmbeddingRecord]) -> Result<RecordBatch, String> {
+        let ids = StringArray::from_iter_values(records.iter().map(|r| r.id.as_str()));
+        let texts: StringArray = records.iter().map(|r| r.text.as_deref()).collect();
+        let values = Float32Array::from_iter_values(records.iter().flat_map(|r| r.embedding.iter().copied()));
+        let DataType::FixedSizeList(item, _) = schema.field(2).data_type() else { unreachable!() };
+        let embeddings = FixedSizeListArray::try_new(item.clone(), dims as i32, Arc::new(values), None)
+            .map_err(|e| format!("Cannot build embedding column: {}", e))?;
+        let metadata = StringArray::from_iter_values(records.iter().map(|r| r.metadata.to_json()));
+        let columns: Vec<ArrayRef> = vec![Arc::new(ids), Arc::new(texts), Arc::new(embeddings), Arc::new(metadata)];
+        RecordBatch::try_new(schema.clone(), columns).map_err(|e| format!("Cannot build row group: {}", e))
+    }
+    
+    /// Read every record of a file written by `write_parquet`
+    pub fn read_parquet(path: &str) -> Result<Vec<EmbeddingRecord>, String> {
+        let mut records = Vec::new();
+        read_parquet_with(path, |record| records.push(record))?;
+        Ok(records)
+    }
+    
+    /// `read_parquet`, handing records to `f` as each batch is decoded
+    pub fn read_parquet_with(path: &str, mut f: impl FnMut(EmbeddingRecord)) -> Result<(), String> {
+        let file = File::open(path).map_err(|e| format!("Cannot open {}: {}", path, e))?;
+        let failed = |e: &dyn std::fmt::Display| format!("Read failed: {}: {}", e, path);
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+            .and_then(|builder| builder.with_batch_size(ROW_GROUP_ROWS).build())
+            .map_err(|e| failed(&e))?;
+        for batch in reader {
+            let batch = batch.map_err(|e| failed(&e))?;
+            read_batch(&batch, &mut f).map_err(|e| format!("{}: {}", e, path))?;
+        }
+        Ok(())
+    }
+    
+    fn read_batch(batch: &RecordBatch, f: &mut impl FnMut(EmbeddingRecord)) -> Result<(), String> {
+        let column = |name: &str| batch.column_by_name(name).ok_or_else(|| format!("Missing column {}", name));
+        let strings = |name: &str| column(name)?.as_string_opt::<i32>().ok_or_else(|| format!("Column {} is not Utf8", name));
+        let (ids, texts, metadata) = (strings("id")?, strings("text")?, strings("metadata")?);
+        let embeddings = column("embedding")?.as_fixed_size_list_opt()
+            .ok_or("Column embedding is not a fixed-size list")?;
+        for row in 0..batch.num_rows() {
+            let embedding = embeddings.value(row);
+            let embedding = embedding.as_primitive_opt::<Float32Type>().ok_or("Column embedding does not hold Float32")?;
+            f(EmbeddingRecord {
+                id: ids.value(row).to_string(),
+                text: texts.is_valid(row).then(|| texts.value(row).to_string()),
+                embedding: embedding.values().to_vec(),
+                metadata: Metadata::from_json(metadata.value(row))?,
+            });
+        }
+        Ok(())
+    }
+    
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        
+        fn records(n: usize, dims: usize) -> Vec<EmbeddingRecord> {
+            (0..n).map(|i| {
+                let record = EmbeddingRecord::new(&format!("doc-{}", i), (0..dims).map(|d| (i * dims + d) as f32 * 0.5).collect())
+                    .with_metadata(Metadata::new().with("n", i as f64).with("even", i % 2 == 0));
+                if i % 3 == 0 { record } else { record.with_text(&format!("text {}", i)) }
+            }).collect()
+        }
+        
+        #[test]
+        fn test_round_trip_over_several_row_groups() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("records.parquet").to_str().unwrap().to_string();
+            let written = records(ROW_GROUP_ROWS * 2 + 5, 3);
+            write_parquet(&path, &written).unwrap();
+            
+            let file = File::open(&path).unwrap();
+            let builder = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+            assert_eq!(builder.metadata().num_row_groups(), 3);
+            assert_eq!(builder.schema().as_ref(), schema(3).unwrap().as_ref());
+            let mut streamed = 0;
+            read_parquet_with(&path, |_| streamed += 1).unwrap();
+            assert_eq!(streamed, written.len());
+            assert_eq!(read_parquet(&path).unwrap(), written);
+            
+            write_parquet(&path, &[]).unwrap();
+            assert_eq!(read_parquet(&path).unwrap(), []);
+        }
+        
+        #[test]
+        fn test_ragged_dims_are_refused_before_writing() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("ragged.parquet");
+            let mut ragged = records(4, 3);
+            ragged[2].embedding.push(1.0);
+            let error = write_parquet(path.to_str().unwrap(), &ragged).unwrap_err();
+            assert_eq!(error, "Record 2 (doc-2) has 4 dims, expected 3");
+            assert!(!path.exists());
+        }
+    }
+}
@@ -2,83 +2,1513 @@
 //! 
 //! Actual API integration for jina-embeddings-v3
 
-use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
 
-const JINA_API_URL: &str = "api.jina.ai";
-const JINA_EMBED_ENDPOINT: &str = "/v1/embeddings";
+use crate::audit::{unix_ms, AuditLog};
+use crate::cache::{entry_age, CachePolicy, CacheStats, HitWindow, WarmReport, Warmup};
+use crate::chunk::truncate_to_budget;
+use crate::coalesce::{Claim, SingleFlight};
+use crate::document::LongInputStrategy;
+use crate::embeddings::to_f64;
+use crate::error::{register_secret, truncate_for_display, DiagnosedError, ItemError, ItemResult, JinaError, MAX_DISPLAY_CHARS};
+use crate::hash::{content_key, ContentKey};
+use crate::offline_queue::OfflineQueue;
+use crate::postprocess::{PostProcess, VectorPipeline};
+use crate::preprocess::Pipeline;
+use crate::probe::Capabilities;
+use crate::provenance::Provenance;
+use crate::provider::{BackendKind, EmbedError, EmbeddingProvider, EmbeddingResponse, Usage};
+use crate::pseudo::PseudoEmbedder;
+use crate::sparse::SparseVector;
+use crate::tokens::{pack, Approximate, ContextLimits, TokenCounter};
+use crate::transport::{self, check_status, send_until, send_with_hooks, Clock, Deadline, Diagnostics, Hooks, HttpRequest, HttpResponse,
+                       RetryPolicy, SystemClock, Transport, BUFFERS};
+
+pub(crate) const JINA_API_URL: &str = "https://api.jina.ai";
+pub(crate) const JINA_EMBED_ENDPOINT: &str = "/v1/embeddings";
+pub(crate) const JINA_MODEL: &str = "jina-embeddings-v3";
+pub(crate) const MAX_BATCH_SIZE: usize = 2048;  // Jina per-request input limit
+pub(crate) const DEFAULT_DIMS: usize = 1024;
+/// Request bodies larger than this are gzipped unless compression is off
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 64 << 10;
+/// curl processes a multi-batch call runs at once by default
+pub const DEFAULT_CURL_PARALLELISM: usize = 2;
+
+/// Task adapter selecting how jina-embeddings-v3 encodes the input
+///
+/// Serializes as its API name, e.g. `"retrieval.query"`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum Task {
+    #[serde(rename = "retrieval.query")]
+    RetrievalQuery,
+    #[serde(rename = "retrieval.passage")]
+    RetrievalPassage,
+    #[serde(rename = "text-matching")]
+    TextMatching,
+    #[serde(rename = "classification")]
+    Classification,
+    #[serde(rename = "separation")]
+    Separation,
+}
+
+impl Task {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Task::RetrievalQuery => "retrieval.query",
+            Task::RetrievalPassage => "retrieval.passage",
+            Task::TextMatching => "text-matching",
+            Task::Classification => "classification",
+            Task::Separation => "separation",
+        }
+    }
+}
+
+impl std::str::FromStr for Task {
+    type Err = JinaError;
+    
+    /// Parse the API name, e.g. `retrieval.query`
+    fn from_str(s: &str) -> Result<Self, JinaError> {
+        [Task::RetrievalQuery, Task::RetrievalPassage, Task::TextMatching, Task::Classification, Task::Separation]
+            .into_iter()
+            .find(|task| task.as_str() == s)
+            .ok_or_else(|| JinaError::InvalidInput(format!("unknown task {}", s)))
+    }
+}
+
+/// Per-request embedding options
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EmbedOptions {
+    pub task: Option<Task>,
+    /// Output size (Matryoshka truncation); model default (1024) if unset
+    pub dimensions: Option<usize>,
+    /// Inputs are consecutive chunks of one document, embedded in its context
+    ///
+    /// The Jina API only; the batch goes out as one request, without dedup or caching.
+    pub late_chunking: bool,
+    /// Cleanup applied to every input before dedup, caching and sending
+    pub preprocess: Option<Pipeline>,
+    /// Inputs longer than this (by the client's token counter) are cut at a
+    /// sentence boundary after preprocessing, before the API truncates them
+    pub max_input_tokens: Option<usize>,
+    /// What `embed_batch_full` does with inputs over that budget, or over
+    /// the model's context window without one
+    pub long_inputs: LongInputStrategy,
+}
+
+impl EmbedOptions {
+    /// Options for search queries (retrieval.query)
+    pub fn query() -> Self { Self { task: Some(Task::RetrievalQuery), ..Self::default() } }
+    
+    /// Options for documents being searched (retrieval.passage)
+    pub fn passage() -> Self { Self { task: Some(Task::RetrievalPassage), ..Self::default() } }
+    
+    pub fn with_task(mut self, task: Task) -> Self {
+        self.task = Some(task);
+        self
+    }
+    
+    pub fn with_dimensions(mut self, dims: usize) -> Self {
+        self.dimensions = Some(dims);
+        self
+    }
+    
+    pub fn with_late_chunking(mut self) -> Self {
+        self.late_chunking = true;
+        self
+    }
+    
+    pub fn with_preprocess(mut self, pipeline: Pipeline) -> Self {
+        self.preprocess = Some(pipeline);
+        self
+    }
+    
+    /// Cut inputs to `tokens` with `chunk::truncate_to_budget`
+    pub fn with_max_input_tokens(mut self, tokens: usize) -> Self {
+        self.max_input_tokens = Some(tokens);
+        self
+    }
+    
+    /// Embed long inputs as `strategy` says instead of cutting them
+    pub fn with_long_inputs(mut self, strategy: LongInputStrategy) -> Self {
+        self.long_inputs = strategy;
+        self
+    }
+    
+    /// Output size these options produce
+    pub fn dims(&self) -> usize { self.dimensions.unwrap_or(DEFAULT_DIMS) }
+    
+    /// `texts` after `preprocess` and `max_input_tokens`; `None` if neither is set
+    pub(crate) fn prepare(&self, texts: &[&str], counter: &dyn TokenCounter) -> Option<Vec<String>> {
+        if self.preprocess.is_none() && self.max_input_tokens.is_none() {
+            return None;
+        }
+        Some(texts.iter().map(|&t| {
+            let cleaned = self.preprocess.as_ref().map_or_else(|| t.to_string(), |pipeline| pipeline.apply(t));
+            match self.max_input_tokens {
+                Some(budget) => truncate_to_budget(&cleaned, budget, counter).text.to_string(),
+                None => cleaned,
+            }
+        }).collect())
+    }
+}
+
+/// Overrides of the client's request settings for one call.
+///
+/// A field set here wins over the client's builder setting, which wins over
+/// the transport's own default; the client itself is left unchanged, so
+/// calls with different overrides can share it. Cached texts are served
+/// without a request whatever the overrides. Backends (`with_backend`) and
+/// the offline embedder send no HTTP requests and ignore them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CallOptions {
+    /// Per-request timeout, as `JinaClient::with_timeout`
+    pub timeout: Option<Duration>,
+    /// Retries after the first attempt, keeping the client's delays
+    pub max_retries: Option<u32>,
+    /// Label for this call's requests in attempt hooks and the audit log
+    pub tag: Option<String>,
+    /// When the whole call must be done by, on the client's clock
+    /// (`with_clock`), across retries, bisection and sub-batches
+    pub deadline: Option<Instant>,
+    /// Send a second, identical request once the first has not answered in
+    /// this long (`AsyncJinaClient` only; `JinaClient` ignores it, as a
+    /// blocking attempt cannot be cancelled)
+    pub hedge_after: Option<Duration>,
+}
+
+impl CallOptions {
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    
+    pub fn with_max_retries(mut self, n: u32) -> Self {
+        self.max_retries = Some(n);
+        self
+    }
+    
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_string());
+        self
+    }
+    
+    /// Fail with `JinaError::DeadlineExceeded` rather than send or retry
+    /// past `deadline`; each attempt's timeout shrinks to the time left.
+    /// `embed_batch_partial_call` returns what was done by then, the inputs
+    /// not embedded failing with `ItemError::DeadlineExceeded`.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+    
+    /// Hedge each request not answered within `delay`, about the p95
+    /// latency: the first response of the two wins and the other is
+    /// dropped. At most one hedge per request, and it spends one of the
+    /// call's retries, so `with_max_retries(0)` turns hedging off.
+    pub fn with_hedge_after(mut self, delay: Duration) -> Self {
+        self.hedge_after = Some(delay);
+        self
+    }
+    
+    pub(crate) fn validate(&self) -> Result<(), JinaError> {
+        if self.timeout == Some(Duration::ZERO) {
+            return Err(JinaError::InvalidInput("Call timeout must be non-zero".to_string()));
+        }
+        if self.hedge_after == Some(Duration::ZERO) {
+            return Err(JinaError::InvalidInput("Hedge delay must be non-zero".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Request counters, for checking batching and cache effectiveness
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ClientStats {
+    /// Upstream requests sent
+    pub requests: u64,
+    /// Texts sent upstream (after dedup and cache hits)
+    pub texts_sent: u64,
+    /// Texts served from the client cache
+    pub cache_hits: u64,
+    /// Hedge requests sent after a slow first attempt, counted in `requests` too
+    pub hedges: u64,
+}
+
+struct Cached {
+    vector: Vec<f32>,
+    /// Unix time in ms, for `cache_stats`
+    inserted_ms: u64,
+    /// On the client's clock, for the `CachePolicy`
+    stored: Instant,
+}
+
+type MemoryCache = HashMap<ContentKey, Cached>;
+
+/// Settings a config `reload` may change while calls run; readers take a
+/// snapshot and a reload swaps in a whole new one
+#[derive(Clone, Debug)]
+pub(crate) struct Settings {
+    pub(crate) api_key: String,
+    pub(crate) max_batch_size: usize,
+    pub(crate) max_batch_tokens: usize,
+    pub(crate) context_limits: ContextLimits,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) reader_retry: RetryPolicy,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) compression_threshold: Option<usize>,
+    pub(crate) curl_parallelism: usize,
+}
 
 pub struct JinaClient {
-    api_key: String,
+    settings: RwLock<Arc<Settings>>,
+    pub(crate) model: String,
+    pub(crate) base_url: String,
+    pub(crate) tokens: Arc<dyn TokenCounter>,
+    cache: Option<Arc<Mutex<MemoryCache>>>,
+    cache_policy: CachePolicy,
+    /// Background refreshes running, for `wait_for_refreshes`
+    refreshes: Arc<(Mutex<usize>, Condvar)>,
+    hit_window: HitWindow,
+    pub(crate) backend: Option<Arc<dyn EmbeddingProvider>>,
+    transport: Option<Arc<dyn Transport>>,
+    post_process: Option<PostProcess>,
+    vector_pipeline: Option<VectorPipeline>,
+    hooks: Hooks,
+    /// Set once the server answers a gzipped request with 415
+    gzip_refused: AtomicBool,
+    /// `null` and `error` entries in `data` fail their input, not the batch
+    tolerant_items: bool,
+    pub(crate) rerank_model: String,
+    pub(crate) clip_model: String,
+    pub(crate) code_model: String,
+    pub(crate) code_chunk_chars: usize,
+    /// Set by the first successful `probe`
+    pub(crate) capabilities: OnceLock<Capabilities>,
+    pub(crate) auto_probe: bool,
+    requests: AtomicU64,
+    texts_sent: AtomicU64,
+    cache_hits: AtomicU64,
+    clock: Arc<dyn Clock>,
+    /// Texts being embedded by some call, for others to wait on
+    flights: Arc<SingleFlight<ContentKey, Result<ItemResult, JinaError>>>,
+    /// Where `embed_or_queue` puts texts during an outage
+    pub(crate) offline_queue: Option<Arc<OfflineQueue>>,
+    /// The file `from_config_file` read, for `reload`
+    #[cfg(feature = "config")]
+    pub(crate) config: Option<crate::config::ConfigSource>,
 }
 
 impl JinaClient {
     pub fn new(api_key: &str) -> Self {
-        Self { api_key: api_key.to_string() }
+        register_secret(api_key);
+        let settings = Settings {
+            api_key: api_key.to_string(),
+            max_batch_size: MAX_BATCH_SIZE,
+            max_batch_tokens: usize::MAX,
+            context_limits: ContextLimits::new(),
+            retry: RetryPolicy::default(),
+            reader_retry: crate::reader::reader_retry_policy(),
+            timeout: None,
+            compression_threshold: Some(DEFAULT_COMPRESSION_THRESHOLD),
+            curl_parallelism: DEFAULT_CURL_PARALLELISM,
+        };
+        Self {
+            settings: RwLock::new(Arc::new(settings)),
+            model: JINA_MODEL.to_string(),
+            base_url: JINA_API_URL.to_string(),
+            tokens: Arc::new(Approximate),
+            cache: None,
+            cache_policy: CachePolicy::Forever,
+            refreshes: Arc::default(),
+            hit_window: HitWindow::default(),
+            backend: None,
+            transport: None,
+            post_process: None,
+            vector_pipeline: None,
+            hooks: Hooks::default(),
+            gzip_refused: AtomicBool::new(false),
+            tolerant_items: false,
+            rerank_model: crate::rerank::DEFAULT_RERANK_MODEL.to_string(),
+            clip_model: crate::clip::DEFAULT_CLIP_MODEL.to_string(),
+            code_model: crate::code::DEFAULT_CODE_MODEL.to_string(),
+            code_chunk_chars: crate::code::DEFAULT_CODE_CHUNK_CHARS,
+            capabilities: OnceLock::new(),
+            auto_probe: false,
+            requests: AtomicU64::new(0),
+            texts_sent: AtomicU64::new(0),
+            cache_hits: AtomicU64::new(0),
+            clock: Arc::new(SystemClock),
+            flights: Arc::new(SingleFlight::new()),
+            offline_queue: None,
+            #[cfg(feature = "config")]
+            config: None,
+        }
+    }
+    
+    /// The runtime settings now in force; hold one snapshot for a whole request
+    pub(crate) fn settings(&self) -> Arc<Settings> { self.settings.read().unwrap().clone() }
+    
+    pub(crate) fn settings_mut(&mut self) -> &mut Settings { Arc::make_mut(self.settings.get_mut().unwrap()) }
+    
+    /// Swap in `settings` at once; calls already running keep their snapshot
+    #[cfg(feature = "config")]
+    pub(crate) fn replace_settings(&self, settings: Settings) { *self.settings.write().unwrap() = Arc::new(settings); }
+    
+    /// Keep embeddings in memory, keyed by options + text; concurrent
+    /// misses for one key share a single upstream request
+    pub fn with_cache(mut self) -> Self {
+        self.cache = Some(Arc::new(Mutex::new(HashMap::new())));
+        self
+    }
+    
+    /// Expire cached embeddings by `policy` (default `CachePolicy::Forever`),
+    /// on the client's clock (`with_clock`); see `cache`
+    pub fn with_cache_policy(mut self, policy: CachePolicy) -> Self {
+        self.cache_policy = policy;
+        self
+    }
+    
+    /// Report the cache hit rate over the last `window` (default `cache::DEFAULT_HIT_WINDOW`)
+    pub fn with_hit_window(mut self, window: Duration) -> Self {
+        self.hit_window = HitWindow::new(window);
+        self
+    }
+    
+    /// Send deduplicated, uncached batches to `backend` instead of the Jina API
+    pub fn with_backend(mut self, backend: impl EmbeddingProvider + 'static) -> Self {
+        self.backend = Some(Arc::new(backend));
+        self
+    }
+    
+    /// Call the Jina API over HTTPS instead of the offline embedder
+    ///
+    /// Honors `SPO_CRYSTAL_RECORD` (see `replay`).
+    pub fn with_http(mut self) -> Self {
+        self.transport = Some(transport::for_url(&self.embeddings_url()));
+        self
+    }
+    
+    /// Embedding model, e.g. `jina-embeddings-v2-base-en`; defaults to jina-embeddings-v3
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+    
+    /// Send requests to `url` (e.g. a proxy) instead of `https://api.jina.ai`
+    ///
+    /// Set it before `with_http`, which picks the transport from the URL scheme.
+    pub fn with_base_url(mut self, url: &str) -> Self {
+        self.base_url = url.trim_end_matches('/').to_string();
+        self
+    }
+    
+    /// Call the Jina API through `transport` (e.g. a `ReplayTransport`)
+    pub fn with_transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+    
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.settings_mut().retry = retry;
+        self
+    }
+    
+    /// Time `CallOptions::deadline` and its retry delays with `clock`
+    /// (e.g. a `mock::ManualClock`) instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+    
+    /// Per-request timeout, overriding the transport's default
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.settings_mut().timeout = Some(timeout);
+        self
+    }
+    
+    /// Post-process every response batch before it is cached or returned
+    pub fn with_post_process(mut self, post_process: PostProcess) -> Self {
+        self.post_process = Some(post_process);
+        self
+    }
+    
+    /// Run every returned vector through `pipeline`, after `with_post_process`
+    /// and before caching; `dimensions()` and provenance report its output
+    pub fn with_vector_pipeline(mut self, pipeline: VectorPipeline) -> Self {
+        self.vector_pipeline = Some(pipeline).filter(|p| !p.is_empty());
+        self
+    }
+    
+    pub fn vector_pipeline(&self) -> Option<&VectorPipeline> { self.vector_pipeline.as_ref() }
+    
+    /// Run `hook` on the calling thread before every attempt of every request,
+    /// with the attempt number; changes apply to that attempt only and the
+    /// `Authorization` value shows as `transport::REDACTED`
+    pub fn with_request_hook(mut self, hook: impl Fn(&mut HttpRequest, u32) + Send + Sync + 'static) -> Self {
+        self.hooks = self.hooks.with_request(hook);
+        self
+    }
+    
+    /// Run `hook` on the calling thread after every attempt that got a response
+    pub fn with_response_hook(mut self, hook: impl Fn(&HttpResponse, u32) + Send + Sync + 'static) -> Self {
+        self.hooks = self.hooks.with_response(hook);
+        self
+    }
+    
+    /// Append an `audit::AuditEntry` line to `log` for every attempt
+    pub fn with_audit_log(mut self, log: Arc<AuditLog>) -> Self {
+        self.hooks = self.hooks.with_attempt(move |attempt| log.record_attempt(attempt));
+        self
+    }
+    
+    /// Show request hooks the real `Authorization` value
+    pub fn with_auth_visible_to_hooks(mut self) -> Self {
+        self.hooks = self.hooks.with_auth_visible();
+        self
+    }
+    
+    /// Gzip embedding request bodies over `bytes`
+    pub fn with_compression_threshold(mut self, bytes: usize) -> Self {
+        self.settings_mut().compression_threshold = Some(bytes);
+        self
+    }
+    
+    /// Always send request bodies uncompressed
+    pub fn without_compression(mut self) -> Self {
+        self.settings_mut().compression_threshold = None;
+        self
+    }
+    
+    /// Run up to `n` curl processes at once when a call needs several
+    /// requests; other transports always send one request at a time
+    pub fn with_curl_parallelism(mut self, n: usize) -> Self {
+        self.settings_mut().curl_parallelism = n.max(1);
+        self
+    }
+    
+    /// Accept responses whose `data` has `null` or `{"error": ...}` entries
+    /// (some OpenAI-compatible gateways send them for inputs they failed):
+    /// `embed_batch_partial` gives those inputs `ItemError::Failed` with the
+    /// gateway's message, other calls fail naming the entry. Off, such a
+    /// response is a protocol error.
+    pub fn with_tolerant_items(mut self) -> Self {
+        self.tolerant_items = true;
+        self
+    }
+    
+    /// Split batches larger than `n` texts into several requests
+    pub fn with_max_batch_size(mut self, n: usize) -> Self {
+        self.settings_mut().max_batch_size = n.clamp(1, MAX_BATCH_SIZE);
+        self
+    }
+    
+    /// Also split batches so no request carries more than `n` tokens
+    pub fn with_max_batch_tokens(mut self, n: usize) -> Self {
+        self.settings_mut().max_batch_tokens = n.max(1);
+        self
+    }
+    
+    /// Count tokens with `counter` (e.g. an exact `tokens::Tokenizer`) instead of `tokens::estimate`
+    pub fn with_token_counter(mut self, counter: impl TokenCounter + 'static) -> Self {
+        self.tokens = Arc::new(counter);
+        self
+    }
+    
+    pub fn count_tokens(&self, text: &str) -> usize { self.tokens.count(text) }
+    
+    /// Check inputs against `limits` instead of `tokens::MODEL_CONTEXT_TOKENS` alone
+    pub fn with_context_limits(mut self, limits: ContextLimits) -> Self {
+        self.settings_mut().context_limits = limits;
+        self
+    }
+    
+    /// Take `tokens` as the context window of `model`
+    pub fn with_context_limit(mut self, model: &str, tokens: usize) -> Self {
+        let settings = self.settings_mut();
+        settings.context_limits = std::mem::take(&mut settings.context_limits).with_limit(model, tokens);
+        self
+    }
+    
+    /// Context window of the client's model, in tokens: an override, else
+    /// what the probe found, else the table's; `None` when nothing is known
+    pub fn context_limit(&self) -> Option<usize> {
+        let settings = self.settings();
+        settings.context_limits.overridden(&self.model)
+            .or_else(|| self.capabilities.get().and_then(|c| c.max_input_tokens))
+            .or_else(|| settings.context_limits.limit(&self.model))
+    }
+    
+    /// Tokens an input may have under `options`: `max_input_tokens`, else the context window
+    pub(crate) fn input_budget(&self, options: &EmbedOptions) -> Option<usize> {
+        options.max_input_tokens.or_else(|| self.context_limit())
+    }
+    
+    pub fn stats(&self) -> ClientStats {
+        ClientStats {
+            requests: self.requests.load(Ordering::Relaxed),
+            texts_sent: self.texts_sent.load(Ordering::Relaxed),
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            hedges: 0,
+        }
+    }
+    
+    /// The cache's size, age and recent hit rate; empty without `with_cache`
+    pub fn cache_stats(&self) -> CacheStats {
+        let Some(cache) = &self.cache else { return CacheStats::default() };
+        let cache = cache.lock().unwrap();
+        CacheStats {
+            entries: cache.len(),
+            bytes: cache.values().map(|c| size_of::<ContentKey>() + size_of::<u64>() + 4 * c.vector.len()).sum(),
+            hit_rate_window: self.hit_window.rate(),
+            oldest_entry_age: entry_age(cache.values().map(|c| c.inserted_ms).min()),
+        }
+    }
+    
+    /// Block until the background refreshes of stale entries started so far are done
+    pub fn wait_for_refreshes(&self) {
+        let (running, done) = &*self.refreshes;
+        let mut running = running.lock().unwrap();
+        while *running > 0 {
+            running = done.wait(running).unwrap();
+        }
+    }
+    
+    /// The vector cached under `key` if the policy still serves it, and whether it is stale
+    fn lookup(&self, cache: &MemoryCache, key: &ContentKey) -> Option<(Vec<f32>, bool)> {
+        let cached = cache.get(key)?;
+        let stale = self.cache_policy.serve(self.clock.now().saturating_duration_since(cached.stored))?;
+        Some((cached.vector.clone(), stale))
+    }
+    
+    /// Embed and cache each of `from` (text and options) the cache lacks;
+    /// see `cache` for resuming and concurrent use.
+    ///
+    /// Fails only without `with_cache`; failed batches are in the report.
+    /// Late-chunked embeddings depend on their batch and are never cached,
+    /// so such keys fail.
+    pub fn warm(&self, from: impl Iterator<Item = (String, EmbedOptions)>, warmup: &Warmup) -> Result<WarmReport, JinaError> {
+        let Some(cache) = &self.cache else {
+            return Err(JinaError::InvalidInput("Nothing to warm without a cache (with_cache)".to_string()));
+        };
+        let mut report = WarmReport::default();
+        let prepared = |text: &str, options: &EmbedOptions| {
+            options.prepare(&[text], self.tokens.as_ref()).and_then(|mut p| p.pop()).unwrap_or_else(|| text.to_string())
+        };
+        let groups = warmup.missing(from, |text, options| {
+            self.lookup(&cache.lock().unwrap(), &content_key(&self.model, options, &prepared(text, options))).is_some()
+        }, &mut report);
+        warmup.run(&groups, &mut report, |texts, options| {
+            if options.late_chunking {
+                return Err(JinaError::InvalidInput("Late-chunked embeddings are not cached".to_string()));
+            }
+            self.check_capabilities(options)?;
+            let texts: Vec<String> = texts.iter().map(|t| prepared(t, options)).filter(|t| !t.trim().is_empty()).collect();
+            let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+            Ok(self.embed_items(&texts, options, &CallOptions::default(), None, false)?.items.iter().filter(|item| item.is_ok()).count())
+        });
+        Ok(report)
     }
     
     /// Get embedding for single text
+    #[deprecated(since = "0.2.0", note = "fails with a String; use `EmbeddingProvider::embed(&client, text)` (in `prelude`), \
+                                          which fails with a `JinaError`")]
     pub fn embed(&self, text: &str) -> Result<Vec<f32>, String> {
-        let embeddings = self.embed_batch(&[text])?;
-        embeddings.into_iter().next().ok_or("No embedding returned".to_string())
+        Ok(EmbeddingProvider::embed(self, text)?)
     }
     
     /// Get embeddings for batch of texts (more efficient)
+    #[deprecated(since = "0.2.0", note = "fails with a String; use `embed_batch_full(texts, &EmbedOptions::default())`, \
+                                          whose `embeddings` these are, or `EmbeddingProvider::embed_batch(&client, texts)`")]
     pub fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
-        // Build JSON request body
-        let input_json: String = texts.iter()
-            .map(|t| format!("\"{}\"", t.replace("\"", "\\\"")))
-            .collect::<Vec<_>>()
-            .join(",");
-        
-        let body = format!(r#"{{"model":"jina-embeddings-v3","input":[{}]}}"#, input_json);
-        
-        // HTTP request (simplified - in production use reqwest or similar)
-        let request = format!(
-            "POST {} HTTP/1.1\r\n\
-             Host: {}\r\n\
-             Authorization: Bearer {}\r\n\
-             Content-Type: application/json\r\n\
-             Content-Length: {}\r\n\
-             Connection: close\r\n\
-             \r\n\
-             {}",
-            JINA_EMBED_ENDPOINT,
-            JINA_API_URL,
-            self.api_key,
-            body.len(),
-            body
-        );
+        Ok(self.embed_batch_full(texts, &EmbedOptions::default())?.embeddings)
+    }
+    
+    /// Batch embed with options.
+    ///
+    /// Duplicate texts are sent once, cached texts are not sent at all, and
+    /// the rest go out in requests of at most `max_batch_size` texts and
+    /// `max_batch_tokens` tokens. A request the server refuses as too large
+    /// is halved until the pieces fit; a text too large on its own fails the
+    /// call with `InputTooLarge` once the others are embedded (and cached).
+    /// Results are returned in input order.
+    #[deprecated(since = "0.2.0", note = "fails with a String; use `embed_batch_full(texts, options)`, whose `embeddings` \
+                                          these are, or `EmbeddingProvider::embed_batch_with(&client, texts, options)`")]
+    pub fn embed_batch_with(&self, texts: &[&str], options: &EmbedOptions) -> Result<Vec<Vec<f32>>, String> {
+        Ok(self.embed_batch_full(texts, options)?.embeddings)
+    }
+    
+    /// Embeddings of `texts` in input order, with the token usage the Jina
+    /// API reported across all requests; batching as `embed_batch_with`
+    pub fn embed_batch_full(&self, texts: &[&str], options: &EmbedOptions) -> Result<EmbeddingResponse, JinaError> {
+        self.embed_batch_inner(texts, options, &CallOptions::default(), None)
+    }
+    
+    /// `embed_batch_full` with `call` overriding the client's timeout and retries
+    pub fn embed_batch_call(&self, texts: &[&str], options: &EmbedOptions, call: &CallOptions)
+                            -> Result<EmbeddingResponse, JinaError> {
+        call.validate()?;
+        self.embed_batch_inner(texts, options, call, None)
+    }
+    
+    /// `embed_batch_full` with timings, attempts and byte counts in
+    /// `EmbeddingResponse::diagnostics`, or in the error when the call fails
+    pub fn embed_batch_diagnosed(&self, texts: &[&str], options: &EmbedOptions) -> Result<EmbeddingResponse, DiagnosedError> {
+        let mut diagnostics = Diagnostics::default();
+        match self.embed_batch_inner(texts, options, &CallOptions::default(), Some(&mut diagnostics)) {
+            Ok(response) => Ok(EmbeddingResponse { diagnostics: Some(diagnostics), ..response }),
+            Err(error) => Err(DiagnosedError { error, diagnostics: Box::new(diagnostics) }),
+        }
+    }
+    
+    /// `embed_batch_with` in f64, with API values parsed straight from their
+    /// decimal text rather than through f32.
+    ///
+    /// The cache and post-processing hold f32 and are skipped; backends and
+    /// the offline embedder produce f32, which is widened.
+    pub fn embed_batch_f64(&self, texts: &[&str], options: &EmbedOptions) -> Result<Vec<Vec<f64>>, JinaError> {
+        let Some(transport) = self.transport.as_deref().filter(|_| self.backend.is_none()) else {
+            let embeddings = self.embed_batch_full(texts, options)?.embeddings;
+            return Ok(embeddings.iter().map(|v| to_f64(v)).collect());
+        };
+        self.check_capabilities(options)?;
+        if options.dims() == 0 {
+            return Err(JinaError::InvalidInput("Embedding dimensions must be non-zero".to_string()));
+        }
+        let cleaned = options.prepare(texts, self.tokens.as_ref());
+        let texts: Vec<&str> = cleaned.as_ref().map_or_else(|| texts.to_vec(), |c| c.iter().map(String::as_str).collect());
+        let mut out = Vec::with_capacity(texts.len());
+        for batch in pack(&texts, self.tokens.as_ref(), self.batch_limit(), self.settings().max_batch_tokens) {
+            let chunk = &texts[batch];
+            self.requests.fetch_add(1, Ordering::Relaxed);
+            self.texts_sent.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            let response = self.post_embeddings(transport, chunk, options, &CallOptions::default(), None)?;
+            let parsed = parse_jina_response_f64(&response.body, self.response_dims(options));
+            BUFFERS.give(response.body.into_bytes());
+            let embeddings = parsed?;
+            if embeddings.len() != chunk.len() {
+                return Err(JinaError::Mismatch { expected: chunk.len(), got: embeddings.len() });
+            }
+            out.extend(embeddings);
+        }
+        Ok(out)
+    }
+    
+    fn embed_batch_inner(&self, texts: &[&str], options: &EmbedOptions, call: &CallOptions, diagnostics: Option<&mut Diagnostics>)
+                         -> Result<EmbeddingResponse, JinaError> {
+        self.check_capabilities(options)?;
+        if let LongInputStrategy::ChunkAndPool { chunker, pooling } = options.long_inputs {
+            // Long inputs are chunked, not cut
+            let cleaned = EmbedOptions { max_input_tokens: None, ..options.clone() }.prepare(texts, self.tokens.as_ref());
+            let texts: Vec<&str> = cleaned.as_ref().map_or_else(|| texts.to_vec(), |c| c.iter().map(String::as_str).collect());
+            return self.embed_pooling_long(&texts, options, chunker, pooling, call, diagnostics);
+        }
+        let budget = self.input_budget(options);
+        let cleaned = match (options.long_inputs, budget) {
+            (LongInputStrategy::Reject, _) => EmbedOptions { max_input_tokens: None, ..options.clone() }.prepare(texts, self.tokens.as_ref()),
+            (LongInputStrategy::Truncate, Some(budget)) => {
+                EmbedOptions { max_input_tokens: Some(budget), ..options.clone() }.prepare(texts, self.tokens.as_ref())
+            }
+            _ => options.prepare(texts, self.tokens.as_ref()),
+        };
+        let texts: Vec<&str> = cleaned.as_ref().map_or_else(|| texts.to_vec(), |c| c.iter().map(String::as_str).collect());
+        if let (LongInputStrategy::Reject, Some(limit)) = (options.long_inputs, budget) {
+            for (index, text) in texts.iter().enumerate() {
+                let estimated = self.count_tokens(text);
+                if estimated > limit {
+                    return Err(JinaError::InputTooLong { index, estimated, limit });
+                }
+            }
+        }
+        self.embed_prepared(&texts, options, call, diagnostics)
+    }
+    
+    /// `embed_batch_inner` for preprocessed `texts`
+    pub(crate) fn embed_prepared(&self, texts: &[&str], options: &EmbedOptions, call: &CallOptions,
+                                 diagnostics: Option<&mut Diagnostics>) -> Result<EmbeddingResponse, JinaError> {
+        if options.late_chunking {
+            if !self.supports_late_chunking() {
+                return Err(JinaError::InvalidInput("late chunking needs the Jina API (with_http)".to_string()));
+            }
+            let response = self.request_batch(texts, options, call, diagnostics)?;
+            return Ok(EmbeddingResponse { provenance: Some(self.provenance(options)), backend: self.backend_kind(), ..response });
+        }
+        
+        let Bisected { items, usage } = self.embed_items(texts, options, call, diagnostics, true)?;
+        // Texts the server refused; the rest are embedded and cached
+        let embeddings = items.into_iter()
+            .enumerate()
+            .map(|(index, item)| item.map_err(|e| match e {
+                ItemError::TooLarge { size } => JinaError::InputTooLarge { index, size, limit: None },
+                ItemError::Rejected { status, message } => JinaError::Api { status, message },
+                ItemError::Empty => JinaError::InvalidInput(format!("Input {} is empty", index)),
+                ItemError::Failed { message } => JinaError::Parse(format!("data entry {} failed: {}", index, message)),
+                ItemError::DeadlineExceeded => JinaError::DeadlineExceeded,
+            }))
+            .collect::<Result<_, _>>()?;
+        Ok(EmbeddingResponse {
+            embeddings,
+            usage,
+            diagnostics: None,
+            provenance: Some(self.provenance(options)),
+            pooled: Vec::new(),
+            backend: self.backend_kind(),
+        })
+    }
+    
+    /// Embeddings in input order, with an `ItemError` for each input that has
+    /// none, rather than failing the call for them.
+    ///
+    /// Inputs blank after preprocessing are not sent. A batch the server
+    /// refuses in a validation error naming some of its inputs (such as
+    /// FastAPI's `"loc":["body","input",3]`) is sent again without them, and
+    /// the oversized are bisected out as in `embed_batch_with`. Transport
+    /// errors, and refusals that name no input, still fail the whole call.
+    /// Late chunking embeds the batch as one request, all or nothing.
+    pub fn embed_batch_partial(&self, texts: &[&str], options: &EmbedOptions)
+                               -> Result<Vec<Result<Vec<f32>, ItemError>>, JinaError> {
+        self.embed_batch_partial_call(texts, options, &CallOptions::default())
+    }
+    
+    /// `embed_batch_partial` with `call` overriding the client's timeout and retries
+    pub fn embed_batch_partial_call(&self, texts: &[&str], options: &EmbedOptions, call: &CallOptions)
+                                    -> Result<Vec<Result<Vec<f32>, ItemError>>, JinaError> {
+        call.validate()?;
+        if options.late_chunking {
+            return Ok(self.embed_batch_inner(texts, options, call, None)?.embeddings.into_iter().map(Ok).collect());
+        }
+        self.check_capabilities(options)?;
+        let cleaned = options.prepare(texts, self.tokens.as_ref());
+        let texts: Vec<&str> = cleaned.as_ref().map_or_else(|| texts.to_vec(), |c| c.iter().map(String::as_str).collect());
+        let sent: Vec<&str> = texts.iter().copied().filter(|t| !t.trim().is_empty()).collect();
+        let mut items = self.embed_items(&sent, options, call, None, true)?.items.into_iter();
+        Ok(texts.iter()
+            .map(|t| if t.trim().is_empty() { Err(ItemError::Empty) } else { items.next().unwrap() })
+            .collect())
+    }
+    
+    /// Each of `texts` (preprocessed) embedded or refused, in input order.
+    ///
+    /// Duplicates are sent once and cached texts not at all, and with a cache
+    /// texts another call is sending wait for its result (`coalesce`); an error is
+    /// returned once everything that succeeded is cached, except that texts
+    /// not embedded by the deadline get `ItemError::DeadlineExceeded`.
+    /// Lookups go into the stats if `counted`.
+    fn embed_items(&self, texts: &[&str], options: &EmbedOptions, call: &CallOptions, diagnostics: Option<&mut Diagnostics>,
+                   counted: bool) -> Result<Bisected, JinaError> {
+        // Dedup: first occurrence of each text gets a slot
+        let mut slots: HashMap<&str, usize> = HashMap::new();
+        let mut unique: Vec<&str> = Vec::new();
+        let positions: Vec<usize> = texts.iter()
+            .map(|t| *slots.entry(t).or_insert_with(|| { unique.push(t); unique.len() - 1 }))
+            .collect();
+        
+        if options.dims() == 0 {
+            return Err(JinaError::InvalidInput("Embedding dimensions must be non-zero".to_string()));
+        }
+        
+        // Stale entries are served now and embedded again in the background
+        let mut stale = Vec::new();
+        let mut items: Vec<Option<Result<Vec<f32>, ItemError>>> = match &self.cache {
+            Some(cache) => {
+                let cache = cache.lock().unwrap();
+                unique.iter().map(|t| {
+                    let (vector, is_stale) = self.lookup(&cache, &content_key(&self.model, options, t))?;
+                    if is_stale {
+                        stale.push(t.to_string());
+                    }
+                    Some(Ok(vector))
+                }).collect()
+            }
+            None => vec![None; unique.len()],
+        };
+        if !stale.is_empty() {
+            self.revalidate(stale, options);
+        }
+        let hits = items.iter().filter(|v| v.is_some()).count();
+        if counted {
+            self.cache_hits.fetch_add(hits as u64, Ordering::Relaxed);
+            if self.cache.is_some() {
+                self.hit_window.record(hits as u64, (unique.len() - hits) as u64);
+            }
+        }
+        
+        let mut usage = Usage::default();
+        let mut pending: Vec<usize> = (0..unique.len()).filter(|&i| items[i].is_none()).collect();
+        let mut diagnostics = diagnostics;
+        let mut late_hits = 0;
+        let mut timed_out = false;
+        // With a cache, texts another call is embedding are waited for rather than sent again
+        while !pending.is_empty() {
+            let (mut leaders, mut followers) = (Vec::new(), Vec::new());
+            let mut leading = Vec::with_capacity(pending.len());
+            for i in pending.drain(..) {
+                if self.cache.is_none() {
+                    leading.push(i);
+                    continue;
+                }
+                let key = content_key(&self.model, options, unique[i]);
+                let cached = || Some(Ok(Ok(self.lookup(&self.cache.as_ref()?.lock().unwrap(), &key)?.0)));
+                match self.flights.claim(key, cached) {
+                    Claim::Lead(leader) => {
+                        leading.push(i);
+                        leaders.push((i, leader));
+                    }
+                    Claim::Follow(follower) => followers.push((i, follower)),
+                    Claim::Ready(item) => {
+                        items[i] = Some(item.unwrap());
+                        late_hits += 1;
+                    }
+                }
+            }
+            let (sent, error) = self.request_missing(&leading, &unique, &mut items, options, call, diagnostics.as_deref_mut());
+            usage.add(&sent);
+            for (i, leader) in leaders {
+                leader.complete(items[i].clone().ok_or_else(|| error.clone().unwrap()));
+            }
+            // Whatever succeeded is cached before the first error is returned
+            match error {
+                Some(JinaError::DeadlineExceeded) => {
+                    timed_out = true;
+                    break;
+                }
+                Some(error) => return Err(error),
+                None => {}
+            }
+            for (i, follower) in followers {
+                match follower.wait() {
+                    Some(Ok(item)) => items[i] = Some(item),
+                    // The leader gave up, or ran out of its own call's time: try again
+                    None | Some(Err(JinaError::DeadlineExceeded)) => pending.push(i),
+                    Some(Err(error)) => return Err(error),
+                }
+            }
+        }
+        if counted && late_hits > 0 {
+            self.cache_hits.fetch_add(late_hits, Ordering::Relaxed);
+        }
+        if timed_out {
+            for item in items.iter_mut().filter(|item| item.is_none()) {
+                *item = Some(Err(ItemError::DeadlineExceeded));
+            }
+        }
+        
+        // Each vector moves to its last position; only duplicates earlier on are cloned
+        let mut remaining = vec![0usize; unique.len()];
+        for &i in &positions {
+            remaining[i] += 1;
+        }
+        let items = positions.into_iter()
+            .map(|i| {
+                remaining[i] -= 1;
+                if remaining[i] == 0 { items[i].take() } else { items[i].clone() }.unwrap()
+            })
+            .collect();
+        Ok(Bisected { items, usage })
+    }
+    
+    /// Send `unique[i]` for each of `missing`, caching and filling in their
+    /// items; on an error, the items of batches never sent stay `None`
+    fn request_missing(&self, missing: &[usize], unique: &[&str], items: &mut [Option<ItemResult>], options: &EmbedOptions,
+                       call: &CallOptions, diagnostics: Option<&mut Diagnostics>) -> (Usage, Option<JinaError>) {
+        let mut usage = Usage::default();
+        let missing_texts: Vec<&str> = missing.iter().map(|&i| unique[i]).collect();
+        let batches: Vec<&[usize]> = pack(&missing_texts, self.tokens.as_ref(), self.batch_limit(), self.settings().max_batch_tokens)
+            .into_iter()
+            .map(|batch| &missing[batch])
+            .collect();
+        let batch_texts: Vec<Vec<&str>> = batches.iter().map(|chunk| chunk.iter().map(|&i| unique[i]).collect()).collect();
+        let (results, error) = self.request_batches(&batch_texts, options, call, diagnostics);
+        for ((chunk, chunk_texts), bisected) in batches.iter().zip(&batch_texts).zip(results) {
+            // Batches after a failure were never sent
+            let Some(bisected) = bisected else { continue };
+            usage.add(&bisected.usage);
+            
+            if let Some(cache) = &self.cache {
+                let mut cache = cache.lock().unwrap();
+                let (inserted_ms, stored) = (unix_ms(), self.clock.now());
+                for (text, item) in chunk_texts.iter().zip(&bisected.items) {
+                    if let Ok(embedding) = item {
+                        cache.insert(content_key(&self.model, options, text), Cached { vector: embedding.clone(), inserted_ms, stored });
+                    }
+                }
+            }
+            for (&i, item) in chunk.iter().zip(bisected.items) {
+                items[i] = Some(item);
+            }
+        }
+        (usage, error)
+    }
+    
+    /// Embed `texts` again on a background thread, each unless it is being
+    /// embedded already or was refreshed meanwhile. wasm32 has no threads:
+    /// there the refresh is done before the call returns.
+    fn revalidate(&self, texts: Vec<String>, options: &EmbedOptions) {
+        let refresher = self.refresher();
+        let options = options.clone();
+        *self.refreshes.0.lock().unwrap() += 1;
+        let refresh = move || {
+            let unique: Vec<&str> = texts.iter().map(String::as_str).collect();
+            let mut leaders = Vec::new();
+            let mut leading = Vec::new();
+            for (i, text) in unique.iter().enumerate() {
+                let key = content_key(&refresher.model, &options, text);
+                let fresh = || {
+                    let (vector, stale) = refresher.lookup(&refresher.cache.as_ref()?.lock().unwrap(), &key)?;
+                    (!stale).then_some(Ok(Ok(vector)))
+                };
+                // Followers are other refreshes or misses: nothing to wait for
+                if let Claim::Lead(leader) = refresher.flights.claim(key, fresh) {
+                    leading.push(i);
+                    leaders.push((i, leader));
+                }
+            }
+            let mut items = vec![None; unique.len()];
+            // A failed refresh stores nothing, so the stale entry stays
+            let (_, error) = refresher.request_missing(&leading, &unique, &mut items, &options, &CallOptions::default(), None);
+            for (i, leader) in leaders {
+                leader.complete(items[i].clone().ok_or_else(|| error.clone().unwrap()));
+            }
+            let (running, done) = &*refresher.refreshes;
+            *running.lock().unwrap() -= 1;
+            done.notify_all();
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        std::thread::spawn(refresh);
+        #[cfg(target_arch = "wasm32")]
+        refresh();
+    }
+    
+    /// A client sending requests as this one does, into the same cache and
+    /// single-flight, for background refreshes; its requests are not in
+    /// this client's `stats`
+    fn refresher(&self) -> JinaClient {
+        JinaClient {
+            settings: RwLock::new(self.settings()),
+            model: self.model.clone(),
+            base_url: self.base_url.clone(),
+            tokens: self.tokens.clone(),
+            cache: self.cache.clone(),
+            cache_policy: self.cache_policy,
+            refreshes: self.refreshes.clone(),
+            hit_window: HitWindow::default(),
+            backend: self.backend.clone(),
+            transport: self.transport.clone(),
+            post_process: self.post_process,
+            vector_pipeline: self.vector_pipeline.clone(),
+            hooks: self.hooks.clone(),
+            gzip_refused: AtomicBool::new(self.gzip_refused.load(Ordering::Relaxed)),
+            tolerant_items: self.tolerant_items,
+            rerank_model: self.rerank_model.clone(),
+            clip_model: self.clip_model.clone(),
+            code_model: self.code_model.clone(),
+            code_chunk_chars: self.code_chunk_chars,
+            capabilities: self.capabilities.clone(),
+            auto_probe: false,
+            requests: AtomicU64::new(0),
+            texts_sent: AtomicU64::new(0),
+            cache_hits: AtomicU64::new(0),
+            clock: self.clock.clone(),
+            flights: self.flights.clone(),
+            offline_queue: None,
+            #[cfg(feature = "config")]
+            config: None,
+        }
+    }
+    
+    /// Provenance of the vectors this client returns for `options`.
+    ///
+    /// The model is `backend` for `with_backend` clients and `offline` for the
+    /// offline embedder; pseudo-embeddings are `synthetic`.
+    pub fn provenance(&self, options: &EmbedOptions) -> Provenance {
+        let model = match (&self.backend, &self.transport) {
+            (Some(_), _) => "backend",
+            (None, Some(_)) => self.model.as_str(),
+            (None, None) => "offline",
+        };
+        let (dims, normalized) = self.output_shape(options);
+        let mut provenance = Provenance::new(model, dims).with_normalized(normalized).with_synthetic(self.is_offline());
+        if let Some(task) = options.task {
+            provenance = provenance.with_task(task.as_str());
+        }
+        if let Some(pipeline) = &self.vector_pipeline {
+            provenance = provenance.with_pipeline(&pipeline.provenance_tag());
+        }
+        provenance
+    }
+    
+    /// Whether requests go to the Jina API rather than the offline embedder
+    pub fn is_online(&self) -> bool { self.transport.is_some() }
+    
+    /// What produces this client's vectors
+    pub fn backend_kind(&self) -> BackendKind {
+        match (&self.backend, &self.transport) {
+            (Some(backend), _) if backend.is_synthetic() => BackendKind::Offline,
+            (Some(_), _) => BackendKind::Provider,
+            (None, Some(_)) => BackendKind::Remote,
+            (None, None) => BackendKind::Offline,
+        }
+    }
+    
+    /// Whether this client's vectors are pseudo-embeddings: no transport, or
+    /// a synthetic backend
+    pub fn is_offline(&self) -> bool { self.backend_kind().is_synthetic() }
+    
+    /// Whether embeddings come from the Jina API itself, which can late-chunk
+    pub fn supports_late_chunking(&self) -> bool { self.backend.is_none() && self.is_online() }
+    
+    /// POST `body` to a Jina endpoint such as `/v1/rerank`; `None` when offline
+    pub(crate) fn post(&self, endpoint: &str, body: &serde_json::Value) -> Result<Option<String>, JinaError> {
+        let request = HttpRequest::post_json(format!("{}{}", self.base_url, endpoint), body);
+        self.send(request, &self.settings().retry)
+    }
+    
+    /// Send with the client's key and timeout; `None` when offline
+    pub(crate) fn send(&self, mut request: HttpRequest, retry: &RetryPolicy) -> Result<Option<String>, JinaError> {
+        let Some(transport) = &self.transport else { return Ok(None) };
+        let settings = self.settings();
+        request = request.bearer(Some(&settings.api_key));
+        request.timeout = settings.timeout;
+        let response = check_status(send_with_hooks(transport.as_ref(), &request, retry, &self.hooks)?)?;
+        Ok(Some(response.body))
+    }
+    
+    /// Send once, without retries, with the client's key and hooks; the raw
+    /// response whatever its status
+    pub(crate) fn send_once(&self, mut request: HttpRequest, timeout: Duration) -> Result<HttpResponse, JinaError> {
+        let Some(transport) = &self.transport else {
+            return Err(JinaError::InvalidInput("Offline clients send no requests".to_string()));
+        };
+        request = request.bearer(Some(&self.settings().api_key));
+        request.timeout = Some(timeout);
+        send_with_hooks(transport.as_ref(), &request, &RetryPolicy::none(), &self.hooks)
+    }
+    
+    /// Size of the vectors the server returns: the probed default without `dimensions`
+    /// Dimensions and normalization of the vectors returned for `options`,
+    /// after post-processing and the vector pipeline
+    fn output_shape(&self, options: &EmbedOptions) -> (usize, bool) {
+        let dims = match (&self.backend, options.dimensions) {
+            (Some(backend), None) => backend.dimensions(),
+            _ => self.response_dims(options),
+        };
+        let dims = self.post_process.map_or(dims, |p| p.output_dims(dims));
+        let normalized = self.post_process.is_some_and(|p| p.normalizes());
+        match &self.vector_pipeline {
+            Some(pipeline) => (pipeline.reported_dims(dims), pipeline.normalizes(normalized)),
+            None => (dims, normalized),
+        }
+    }
+    
+    pub(crate) fn response_dims(&self, options: &EmbedOptions) -> usize {
+        options.dimensions.unwrap_or_else(|| self.capabilities.get().map_or(DEFAULT_DIMS, |c| c.default_dims))
+    }
+    
+    /// `max_batch_size`, or the probed server limit if lower
+    pub(crate) fn batch_limit(&self) -> usize {
+        let max_batch_size = self.settings().max_batch_size;
+        self.capabilities.get().map_or(max_batch_size, |c| c.max_batch.min(max_batch_size))
+    }
+    
+    pub(crate) fn embeddings_url(&self) -> String {
+        format!("{}{}", self.base_url, JINA_EMBED_ENDPOINT)
+    }
+    
+    /// `request_bisecting` for each batch, in order of `batches`, with up to
+    /// `curl_parallelism` requests in flight on the curl transport.
+    ///
+    /// Returns what each batch got, `None` for batches not sent after a
+    /// failure, and the error of the earliest failed batch. Each worker sends
+    /// one batch at a time, retries included, so no more than
+    /// `curl_parallelism` curl processes ever run; on an error the workers
+    /// start no new batches but finish, and reap, the ones in flight.
+    fn request_batches(&self, batches: &[Vec<&str>], options: &EmbedOptions, call: &CallOptions,
+                       mut diagnostics: Option<&mut Diagnostics>) -> (Vec<Option<Bisected>>, Option<JinaError>) {
+        let curl = self.backend.is_none() && self.transport.as_ref().is_some_and(|t| t.label() == "curl");
+        let workers = if curl { self.settings().curl_parallelism.min(batches.len()) } else { 1 };
+        if workers <= 1 {
+            let mut results = Vec::with_capacity(batches.len());
+            for texts in batches {
+                let mut bisected = Bisected::default();
+                if let Err(e) = self.request_bisecting(texts, options, call, diagnostics.as_deref_mut(), &mut bisected) {
+                    results.resize_with(batches.len(), || None);
+                    return (results, Some(e));
+                }
+                results.push(Some(bisected));
+            }
+            return (results, None);
+        }
         
-        // Connect via TLS would require rustls/native-tls
-        // For now, return placeholder that matches API structure
-        // In production, use: reqwest::blocking::Client
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let timed = diagnostics.is_some();
+        // Each batch's outcome, with the diagnostics of its requests
+        type Slot = Mutex<Option<Result<(Bisected, Diagnostics), JinaError>>>;
+        let slots: Vec<Slot> = batches.iter().map(|_| Mutex::new(None)).collect();
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| while !failed.load(Ordering::Relaxed) {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(texts) = batches.get(i) else { break };
+                    let mut bisected = Bisected::default();
+                    let mut local = Diagnostics::default();
+                    let result = self.request_bisecting(texts, options, call, timed.then_some(&mut local), &mut bisected);
+                    if result.is_err() {
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                    *slots[i].lock().unwrap() = Some(result.map(|()| (bisected, local)));
+                });
+            }
+        });
         
-        // Placeholder: generate deterministic embeddings from text
-        Ok(texts.iter().map(|t| generate_pseudo_embedding(t)).collect())
+        let mut error = None;
+        let results = slots.into_iter()
+            .map(|slot| match slot.into_inner().unwrap() {
+                Some(Ok((bisected, local))) => {
+                    if let Some(diagnostics) = diagnostics.as_deref_mut() {
+                        diagnostics.merge(&local);
+                    }
+                    Some(bisected)
+                }
+                Some(Err(e)) => {
+                    error.get_or_insert(e);
+                    None
+                }
+                None => None,
+            })
+            .collect();
+        (results, error)
+    }
+    
+    /// `request_batch`, halving any sub-batch the server refuses as too large
+    /// until single texts, and resending without the inputs a validation
+    /// error names; those get their `ItemError`.
+    ///
+    /// Each half is an ordinary request under the retry policy; a refused
+    /// batch of n texts costs at most 2n - 1 requests.
+    fn request_bisecting(&self, texts: &[&str], options: &EmbedOptions, call: &CallOptions,
+                         mut diagnostics: Option<&mut Diagnostics>, out: &mut Bisected) -> Result<(), JinaError> {
+        if texts.is_empty() {
+            return Ok(());
+        }
+        if let Some(deadline) = self.deadline(call) {
+            deadline.remaining()?;
+        }
+        match self.request_items(texts, options, call, diagnostics.as_deref_mut()) {
+            Ok(sent) => {
+                if sent.items.len() != texts.len() {
+                    return Err(JinaError::Mismatch { expected: texts.len(), got: sent.items.len() });
+                }
+                out.usage.add(&sent.usage);
+                out.items.extend(sent.items);
+                Ok(())
+            }
+            Err(JinaError::Api { status: status @ (400 | 422), message }) if !offending_inputs(&message, texts.len()).is_empty() => {
+                let offenders = offending_inputs(&message, texts.len());
+                let too_large = is_payload_too_large(&JinaError::Api { status, message: message.clone() });
+                let rest: Vec<&str> = (0..texts.len()).filter(|i| !offenders.contains(i)).map(|i| texts[i]).collect();
+                let mut sent = Bisected::default();
+                self.request_bisecting(&rest, options, call, diagnostics, &mut sent)?;
+                out.usage.add(&sent.usage);
+                let mut sent = sent.items.into_iter();
+                for (i, text) in texts.iter().enumerate() {
+                    out.items.push(match offenders.contains(&i) {
+                        true if too_large => Err(ItemError::TooLarge { size: text.len() }),
+                        true => Err(ItemError::Rejected { status, message: message.clone() }),
+                        false => sent.next().unwrap(),
+                    });
+                }
+                Ok(())
+            }
+            Err(e) if is_payload_too_large(&e) && texts.len() > 1 => {
+                let half = texts.len().div_ceil(2);
+                self.request_bisecting(&texts[..half], options, call, diagnostics.as_deref_mut(), out)?;
+                self.request_bisecting(&texts[half..], options, call, diagnostics, out)
+            }
+            Err(e) if is_payload_too_large(&e) => {
+                out.items.push(Err(ItemError::TooLarge { size: texts[0].len() }));
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+    
+    /// `request_batch`, except that `with_tolerant_items` API clients fail
+    /// only the inputs of `null` and `error` entries in `data`
+    fn request_items(&self, texts: &[&str], options: &EmbedOptions, call: &CallOptions, diagnostics: Option<&mut Diagnostics>)
+                     -> Result<Bisected, JinaError> {
+        let Some(transport) = self.transport.as_deref().filter(|_| self.tolerant_items && self.backend.is_none()) else {
+            let response = self.request_batch(texts, options, call, diagnostics)?;
+            return Ok(Bisected { items: response.embeddings.into_iter().map(Ok).collect(), usage: response.usage });
+        };
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.texts_sent.fetch_add(texts.len() as u64, Ordering::Relaxed);
+        let response = self.post_embeddings(transport, texts, options, call, diagnostics)?;
+        let parsed = parse_jina_items(&response.body, self.response_dims(options), texts.len());
+        let usage = parse_usage(&response.body);
+        BUFFERS.give(response.body.into_bytes());
+        let mut items = parsed?;
+        if self.post_process.is_some() || self.vector_pipeline.is_some() {
+            let mut vectors: Vec<Vec<f32>> = items.iter_mut().flatten().map(std::mem::take).collect();
+            self.process(&mut vectors)?;
+            for (item, vector) in items.iter_mut().flatten().zip(vectors) {
+                *item = vector;
+            }
+        }
+        Ok(Bisected { items, usage })
+    }
+    
+    /// One upstream request for at most `max_batch_size` texts, post-processed
+    fn request_batch(&self, texts: &[&str], options: &EmbedOptions, call: &CallOptions, diagnostics: Option<&mut Diagnostics>)
+                     -> Result<EmbeddingResponse, JinaError> {
+        let mut response = self.fetch_batch(texts, options, call, diagnostics)?;
+        self.process(&mut response.embeddings)?;
+        Ok(response)
+    }
+    
+    /// `with_post_process`, then `with_vector_pipeline`
+    fn process(&self, vectors: &mut [Vec<f32>]) -> Result<(), JinaError> {
+        if let Some(post_process) = &self.post_process {
+            post_process.apply(vectors);
+        }
+        match &self.vector_pipeline {
+            Some(pipeline) => pipeline.apply(vectors),
+            None => Ok(()),
+        }
+    }
+    
+    fn fetch_batch(&self, texts: &[&str], options: &EmbedOptions, call: &CallOptions, diagnostics: Option<&mut Diagnostics>)
+                   -> Result<EmbeddingResponse, JinaError> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.texts_sent.fetch_add(texts.len() as u64, Ordering::Relaxed);
+        let start = diagnostics.is_some().then(Instant::now);
+        let local = |label: &str, diagnostics: Option<&mut Diagnostics>| {
+            if let (Some(diagnostics), Some(start)) = (diagnostics, start) {
+                diagnostics.record_call(label, transport::millis(start.elapsed()));
+            }
+        };
+        if let Some(backend) = &self.backend {
+            let embeddings = backend.embed_batch_with(texts, options);
+            local("provider", diagnostics);
+            return Ok(EmbeddingResponse { embeddings: embeddings?, backend: self.backend_kind(), ..EmbeddingResponse::default() });
+        }
+        
+        let Some(transport) = &self.transport else {
+            // Offline: deterministic embeddings from text
+            let embeddings = PseudoEmbedder::new(options.dims()).embed_batch(texts);
+            local("offline", diagnostics);
+            return Ok(EmbeddingResponse { embeddings, backend: self.backend_kind(), ..EmbeddingResponse::default() });
+        };
+        let response = self.post_embeddings(transport.as_ref(), texts, options, call, diagnostics)?;
+        let parsed = parse_jina_response(&response.body, self.response_dims(options));
+        let usage = parse_usage(&response.body);
+        BUFFERS.give(response.body.into_bytes());
+        Ok(EmbeddingResponse { embeddings: parsed?, usage, diagnostics: None, provenance: None, pooled: Vec::new(), backend: self.backend_kind() })
+    }
+    
+    /// Send one embeddings request; the response body is a pooled buffer to
+    /// hand back once parsed
+    fn post_embeddings(&self, transport: &dyn Transport, texts: &[&str], options: &EmbedOptions, call: &CallOptions,
+                       diagnostics: Option<&mut Diagnostics>) -> Result<HttpResponse, JinaError> {
+        let settings = self.settings();
+        let mut body = String::from_utf8(BUFFERS.take()).unwrap_or_default();
+        write_request_body(&mut body, &self.model, texts, options);
+        let request = HttpRequest {
+            method: "POST",
+            url: self.embeddings_url(),
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: body.into_bytes(),
+            timeout: call.timeout.or(settings.timeout),
+        }.bearer(Some(&settings.api_key));
+        let retry = match call.max_retries {
+            Some(max_retries) => &RetryPolicy { max_retries, ..settings.retry },
+            None => &settings.retry,
+        };
+        let tagged;
+        let hooks = match &call.tag {
+            Some(tag) => {
+                tagged = self.hooks.clone().with_tag(tag);
+                &tagged
+            }
+            None => &self.hooks,
+        };
+        let mut diagnostics = diagnostics;
+        let compress = settings.compression_threshold.is_some_and(|threshold| request.body.len() > threshold)
+            && !self.gzip_refused.load(Ordering::Relaxed);
+        let sent = if compress {
+            let gzipped = request.clone().gzip();
+            match send_until(transport, &gzipped, retry, hooks, diagnostics.as_deref_mut(), self.deadline(call)) {
+                // Servers without gzip support say so once; later requests go plain
+                Ok(response) if response.status == 415 => {
+                    self.gzip_refused.store(true, Ordering::Relaxed);
+                    None
+                }
+                sent => Some(sent),
+            }
+        } else {
+            None
+        };
+        let sent = sent.unwrap_or_else(|| send_until(transport, &request, retry, hooks, diagnostics, self.deadline(call)));
+        BUFFERS.give(request.body);
+        check_status(sent?)
+    }
+    
+    fn deadline(&self, call: &CallOptions) -> Option<Deadline<'_>> {
+        call.deadline.map(|at| Deadline { at, clock: self.clock.as_ref() })
     }
 }
 
+/// Vectors of a batch in input order, or why a text has none
+#[derive(Default)]
+struct Bisected {
+    items: Vec<Result<Vec<f32>, ItemError>>,
+    usage: Usage,
+}
+
+/// Sorted, distinct positions below `len` that a validation message names
+/// as `input[3]`, `input.3`, `inputs[3]` or FastAPI's `"input",3`
+fn offending_inputs(message: &str, len: usize) -> Vec<usize> {
+    let mut found = Vec::new();
+    for (at, _) in message.match_indices("input") {
+        let rest = message[at + 5..].strip_prefix('s').unwrap_or(&message[at + 5..]);
+        let rest = rest.trim_start_matches(['"', '\\']).trim_start_matches(['[', '.', ',', ' ']);
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if let Ok(i) = rest[..digits].parse::<usize>() {
+            if i < len && !found.contains(&i) {
+                found.push(i);
+            }
+        }
+    }
+    found.sort_unstable();
+    found
+}
+
+/// 413, or the 400/422 body the API sends for an oversized request
+fn is_payload_too_large(error: &JinaError) -> bool {
+    match error {
+        JinaError::Api { status: 413, .. } => true,
+        JinaError::Api { status: 400 | 422, message } => {
+            let message = message.to_ascii_lowercase();
+            message.contains("too large") || message.contains("too long")
+        }
+        _ => false,
+    }
+}
+
+impl EmbeddingProvider for JinaClient {
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        Ok(JinaClient::embed_batch_full(self, texts, &EmbedOptions::default())?.embeddings)
+    }
+    
+    fn embed_batch_with(&self, texts: &[&str], options: &EmbedOptions) -> Result<Vec<Vec<f32>>, EmbedError> {
+        Ok(JinaClient::embed_batch_full(self, texts, options)?.embeddings)
+    }
+    
+    fn dimensions(&self) -> usize { self.provenance(&EmbedOptions::default()).dimensions }
+    
+    fn is_synthetic(&self) -> bool { self.is_offline() }
+    
+    fn embed_sparse(&self, texts: &[&str]) -> Result<Vec<SparseVector>, EmbedError> {
+        JinaClient::embed_sparse_with(self, texts, &EmbedOptions::default())
+    }
+}
+
+/// Append the JSON request body for /v1/embeddings to `out`
+pub(crate) fn write_request_body(out: &mut String, model: &str, texts: &[&str], options: &EmbedOptions) {
+    write_typed_request_body(out, model, texts, options, None);
+}
+
+/// `write_request_body` asking for `embedding_type` (e.g. `"sparse"`) when given
+pub(crate) fn write_typed_request_body(out: &mut String, model: &str, texts: &[&str], options: &EmbedOptions,
+                                       embedding_type: Option<&str>) {
+    use std::fmt::Write;
+    let _ = write!(out, r#"{{"model":"{}""#, model);
+    if let Some(task) = options.task {
+        let _ = write!(out, r#","task":"{}""#, task.as_str());
+    }
+    if let Some(dims) = options.dimensions {
+        let _ = write!(out, r#","dimensions":{}"#, dims);
+    }
+    if options.late_chunking {
+        out.push_str(r#","late_chunking":true"#);
+    }
+    if let Some(embedding_type) = embedding_type {
+        let _ = write!(out, r#","embedding_type":"{}""#, embedding_type);
+    }
+    out.push_str(r#","input":["#);
+    for (i, text) in texts.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        push_json_string(out, text);
+    }
+    out.push_str("]}");
+}
+
+/// Quote and escape a string as a JSON string literal
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    push_json_string(&mut out, s);
+    out
+}
+
+/// `json_string`, appended to `out`
+fn push_json_string(out: &mut String, s: &str) {
+    use std::fmt::Write;
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(out, "\\u{:04x}", c as u32); }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
 /// Generate deterministic pseudo-embedding for testing
 /// Replace with actual API call in production
-fn generate_pseudo_embedding(text: &str) -> Vec<f32> {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
-    
-    let mut embedding = vec![0.0f32; 1024];
+///
+/// Legacy byte-window scheme, kept bit-for-bit for one more release. It
+/// hashes every 3-byte window separately, which is slow on long texts;
+/// `PseudoEmbedder` is the faster, locality-preserving, versioned
+/// offline embedder.
+#[deprecated(since = "0.2.0", note = "slow per-window hashing; use `PseudoEmbedder::new(1024).embed(text)`, \
+                                      or `generate_into` to reuse a buffer (different vectors)")]
+pub fn generate_pseudo_embedding(text: &str) -> Vec<f32> {
+    #[allow(deprecated)]
+    generate_pseudo_embedding_dims(text, DEFAULT_DIMS)
+}
+
+/// Pseudo-embedding of `dims` (> 0) dimensions, L2 normalized; the legacy
+/// scheme of `generate_pseudo_embedding`
+#[deprecated(since = "0.2.0", note = "slow per-window hashing; use `PseudoEmbedder::new(dims).embed(text)`, \
+                                      or `generate_into` to reuse a buffer (different vectors)")]
+pub fn generate_pseudo_embedding_dims(text: &str, dims: usize) -> Vec<f32> {
+    let mut embedding = vec![0.0f32; dims];
     
     // Create deterministic values based on text content
     let bytes = text.as_bytes();
     
+    let mut message = Vec::with_capacity(8 + 3 + 8);
     for (i, window) in bytes.windows(3.min(bytes.len())).enumerate() {
-        let mut hasher = DefaultHasher::new();
-        window.hash(&mut hasher);
-        (i as u64).hash(&mut hasher);
-        let h = hasher.finish();
+        // What `DefaultHasher` fed `window` then `i as u64` on 64-bit targets
+        message.clear();
+        message.extend_from_slice(&(window.len() as u64).to_le_bytes());
+        message.extend_from_slice(window);
+        message.extend_from_slice(&(i as u64).to_le_bytes());
+        let h = sip13_zero_key(&message);
         
         // Spread across embedding dimensions
         for j in 0..16 {
-            let idx = ((h >> (j * 4)) as usize + i * 17) % 1024;
+            let idx = ((h >> (j * 4)) as usize + i * 17) % dims;
             let sign = if (h >> (j + 48)) & 1 == 0 { 1.0 } else { -1.0 };
             embedding[idx] += sign * 0.1;
         }
@@ -86,7 +1516,7 @@ fn generate_pseudo_embedding(text: &str) -> Vec<f32> {
     
     // Add character-level features
     for (i, &byte) in bytes.iter().enumerate() {
-        let idx = (byte as usize * 4 + i) % 1024;
+        let idx = (byte as usize * 4 + i) % dims;
         embedding[idx] += 0.05;
     }
     
@@ -99,21 +1529,66 @@ fn generate_pseudo_embedding(text: &str) -> Vec<f32> {
     embedding
 }
 
+/// SipHash-1-3 of `bytes` under the zero key, as `DefaultHasher::new()` has
+/// computed it so far; written out so the legacy vectors cannot change
+fn sip13_zero_key(bytes: &[u8]) -> u64 {
+    let mut v = [0x736f6d6570736575u64, 0x646f72616e646f6d, 0x6c7967656e657261, 0x7465646279746573];
+    let round = |v: &mut [u64; 4]| {
+        v[0] = v[0].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(13) ^ v[0];
+        v[0] = v[0].rotate_left(32);
+        v[2] = v[2].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(16) ^ v[2];
+        v[0] = v[0].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(21) ^ v[0];
+        v[2] = v[2].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(17) ^ v[2];
+        v[2] = v[2].rotate_left(32);
+    };
+    let mut compress = |m: u64| {
+        v[3] ^= m;
+        round(&mut v);
+        v[0] ^= m;
+    };
+    let mut words = bytes.chunks_exact(8);
+    for word in words.by_ref() {
+        compress(u64::from_le_bytes(word.try_into().unwrap()));
+    }
+    let mut last = (bytes.len() as u64) << 56;
+    for (i, &b) in words.remainder().iter().enumerate() {
+        last |= (b as u64) << (8 * i);
+    }
+    compress(last);
+    v[2] ^= 0xff;
+    for _ in 0..3 {
+        round(&mut v);
+    }
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+}
+
+/// Locality-preserving pseudo-embedding: texts sharing vocabulary score as similar
+///
+/// Same as `PseudoEmbedder::new(dims).embed(text)`.
+pub fn generate_pseudo_embedding_v2(text: &str, dims: usize) -> Vec<f32> {
+    PseudoEmbedder::new(dims).embed(text)
+}
+
 /// Real Jina API call using curl (shell out)
 /// This works in environments where we can't use TLS directly
 pub fn jina_embed_curl(api_key: &str, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
     use std::process::Command;
     
+    register_secret(api_key);
     // Build JSON
     let input_json: String = texts.iter()
-        .map(|t| format!("\"{}\"", t.replace("\"", "\\\"").replace("\n", "\\n")))
+        .map(|t| json_string(t))
         .collect::<Vec<_>>()
         .join(",");
     
-    let body = format!(r#"{{"model":"jina-embeddings-v3","input":[{}],"dimensions":1024}}"#, input_json);
+    let body = format!(r#"{{"model":"{}","input":[{}],"dimensions":1024}}"#, JINA_MODEL, input_json);
     
     let output = Command::new("curl")
-        .args(&[
+        .args([
             "-s",
             "-X", "POST",
             "https://api.jina.ai/v1/embeddings",
@@ -125,63 +1600,311 @@ pub fn jina_embed_curl(api_key: &str, texts: &[&str]) -> Result<Vec<Vec<f32>>, S
         .map_err(|e| format!("curl failed: {}", e))?;
     
     if !output.status.success() {
-        return Err(format!("API error: {}", String::from_utf8_lossy(&output.stderr)));
+        return Err(format!("API error: {}", truncate_for_display(&String::from_utf8_lossy(&output.stderr), MAX_DISPLAY_CHARS)));
     }
     
     let response = String::from_utf8_lossy(&output.stdout);
     
     // Parse embeddings from JSON response
     // Response format: {"data":[{"embedding":[...]},...],...}
-    parse_jina_response(&response)
+    parse_jina_response(&response, DEFAULT_DIMS)
+}
+
+/// `usage` of a /v1/embeddings response; zero when absent
+pub(crate) fn parse_usage(json: &str) -> Usage {
+    #[derive(serde::Deserialize)]
+    struct Body {
+        #[serde(default)]
+        usage: Usage,
+    }
+    serde_json::from_str::<Body>(json).map_or_else(|_| Usage::default(), |b| b.usage)
+}
+
+/// Embeddings of `dims` components from a /v1/embeddings response body.
+///
+/// Only `data[*].embedding` is read; everything else is skipped by a small
+/// JSON walker that tracks strings and escapes, so words and brackets inside
+/// string values are never taken for structure. Vectors shorter than
+/// `dims` are left out; longer ones are truncated.
+pub(crate) fn parse_jina_response(json: &str, dims: usize) -> Result<Vec<Vec<f32>>, String> {
+    parse_embeddings(json, dims)
+}
+
+/// Each of `expected` inputs' entry in a /v1/embeddings response: its
+/// vector, or `ItemError::Failed` for an entry that is `null`, carries an
+/// `error` or has no vector of `dims` components.
+///
+/// Entries go to their `index`, or to their position in `data` without one.
+fn parse_jina_items(json: &str, dims: usize, expected: usize) -> Result<Vec<ItemResult>, JinaError> {
+    let failed = |message: &str| Err(ItemError::Failed { message: truncate_for_display(message, MAX_DISPLAY_CHARS) });
+    let mut entries: Vec<(Option<usize>, ItemResult)> = Vec::new();
+    let mut found = false;
+    let walked = Scanner::new(json).members(|scan, key| match key {
+        "data" => {
+            found = true;
+            scan.elements(|scan| {
+                if scan.peek() == Some(b'n') {
+                    scan.scalar()?;
+                    entries.push((None, failed("null entry")));
+                    return Ok(());
+                }
+                let (mut index, mut vector, mut error) = (None, None, None);
+                scan.members(|scan, key| match key {
+                    "index" => {
+                        let token = scan.scalar()?;
+                        index = Some(token.parse().map_err(|_| format!("Bad data index {:?}", token))?);
+                        Ok(())
+                    }
+                    "embedding" if scan.peek() == Some(b'[') => scan.vector(dims).map(|v| vector = v),
+                    "error" => match scan.peek() {
+                        Some(b'"') => scan.string().map(|m| error = Some(m)),
+                        Some(b'{') => scan.members(|scan, key| match key {
+                            "message" if scan.peek() == Some(b'"') => scan.string().map(|m| error = Some(m)),
+                            _ => scan.skip_value(),
+                        }),
+                        _ => scan.skip_value().map(|()| error = Some("error entry")),
+                    },
+                    _ => scan.skip_value(),
+                })?;
+                entries.push((index, match (error, vector) {
+                    (Some(message), _) => failed(message),
+                    (None, Some(vector)) => Ok(vector),
+                    (None, None) => Err(ItemError::Failed { message: format!("no embedding of {} components", dims) }),
+                }));
+                Ok(())
+            })
+        }
+        _ => scan.skip_value(),
+    });
+    if let Err(e) = walked {
+        return Err(JinaError::Parse(error_message(json).map_or(e, |m| format!("Jina API error: {}", m))));
+    }
+    if !found {
+        return Err(JinaError::Parse(error_message(json).map_or_else(|| "No data field".to_string(), |m| format!("Jina API error: {}", m))));
+    }
+    if entries.len() != expected {
+        return Err(JinaError::Mismatch { expected, got: entries.len() });
+    }
+    let mut items: Vec<Option<ItemResult>> = vec![None; expected];
+    for (position, (index, item)) in entries.into_iter().enumerate() {
+        let index = index.unwrap_or(position);
+        let slot = items.get_mut(index)
+            .ok_or_else(|| JinaError::Parse(format!("data index {} out of range for {} inputs", index, expected)))?;
+        if slot.replace(item).is_some() {
+            return Err(JinaError::Parse(format!("duplicate data index {}", index)));
+        }
+    }
+    // Exactly `expected` entries with distinct in-range indices fill every slot
+    Ok(items.into_iter().flatten().collect())
+}
+
+/// `parse_jina_response` in f64, each value parsed from its decimal text
+fn parse_jina_response_f64(json: &str, dims: usize) -> Result<Vec<Vec<f64>>, String> {
+    parse_embeddings(json, dims)
+}
+
+/// Float type vectors are parsed into
+trait Component: std::str::FromStr + Copy {
+    fn finite(self) -> bool;
 }
 
-fn parse_jina_response(json: &str) -> Result<Vec<Vec<f32>>, String> {
-    let mut embeddings = Vec::new();
+impl Component for f32 {
+    fn finite(self) -> bool { self.is_finite() }
+}
+
+impl Component for f64 {
+    fn finite(self) -> bool { self.is_finite() }
+}
+
+fn parse_embeddings<T: Component>(json: &str, dims: usize) -> Result<Vec<Vec<T>>, String> {
+    let api_error = || error_message(json).map(|m| format!("Jina API error: {}", m));
+    let mut embeddings = None;
+    let walked = Scanner::new(json).members(|scan, key| match key {
+        "data" => {
+            let mut out = Vec::new();
+            scan.elements(|scan| {
+                let mut vector = None;
+                scan.members(|scan, key| match key {
+                    "embedding" => scan.vector(dims).map(|v| vector = v),
+                    _ => scan.skip_value(),
+                })?;
+                out.extend(vector);
+                Ok(())
+            })?;
+            embeddings = Some(out);
+            Ok(())
+        }
+        _ => scan.skip_value(),
+    });
+    if let Err(e) = walked {
+        return Err(api_error().unwrap_or(e));
+    }
+    match embeddings {
+        None => Err(api_error().unwrap_or_else(|| "No data field".to_string())),
+        Some(embeddings) if embeddings.is_empty() => Err(api_error().unwrap_or_else(|| {
+            format!("Failed to parse embeddings from: {}", truncate_for_display(json, MAX_DISPLAY_CHARS))
+        })),
+        Some(embeddings) => Ok(embeddings),
+    }
+}
+
+/// `error.message` of an error body, escapes left as they are, shortened for display
+fn error_message(json: &str) -> Option<String> {
+    let mut message = None;
+    let _ = Scanner::new(json).members(|scan, key| match key {
+        "error" if scan.peek() == Some(b'{') => scan.members(|scan, key| match key {
+            "message" if scan.peek() == Some(b'"') => scan.string().map(|m| message = Some(m)),
+            _ => scan.skip_value(),
+        }),
+        _ => scan.skip_value(),
+    });
+    message.map(|m| truncate_for_display(m, MAX_DISPLAY_CHARS))
+}
+
+/// Nesting beyond this is rejected rather than recursed into
+const MAX_JSON_DEPTH: usize = 64;
+
+/// Forward-only JSON walker over objects, arrays, strings and scalars.
+///
+/// Positions only ever stop at ASCII bytes outside strings, so every slice
+/// it returns is on char boundaries.
+struct Scanner<'a> {
+    json: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(json: &'a str) -> Self { Self { json, pos: 0, depth: 0 } }
+    
+    /// Next byte after whitespace
+    fn peek(&mut self) -> Option<u8> {
+        let bytes = self.json.as_bytes();
+        while bytes.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+        bytes.get(self.pos).copied()
+    }
     
-    // Find "data" array
-    let data_start = json.find("\"data\"").ok_or("No data field")?;
-    let array_start = json[data_start..].find('[').ok_or("No data array")? + data_start;
+    fn eat(&mut self, byte: u8) -> bool {
+        let found = self.peek() == Some(byte);
+        self.pos += found as usize;
+        found
+    }
     
-    // Find each embedding array
-    let mut pos = array_start;
-    while let Some(emb_start) = json[pos..].find("\"embedding\"") {
-        let emb_pos = pos + emb_start;
-        let arr_start = json[emb_pos..].find('[').ok_or("No embedding array")? + emb_pos;
-        let arr_end = json[arr_start..].find(']').ok_or("No embedding end")? + arr_start;
-        
-        let arr_str = &json[arr_start+1..arr_end];
-        let values: Vec<f32> = arr_str
-            .split(',')
-            .filter_map(|s| s.trim().parse().ok())
-            .collect();
-        
-        if values.len() >= 1024 {
-            embeddings.push(values[..1024].to_vec());
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.eat(byte) { Ok(()) } else { Err(self.malformed()) }
+    }
+    
+    fn malformed(&self) -> String {
+        if self.pos >= self.json.len() {
+            "Truncated response".to_string()
+        } else {
+            format!("Malformed response at byte {}", self.pos)
         }
-        
-        pos = arr_end + 1;
     }
     
-    if embeddings.is_empty() {
-        // Try to extract error message
-        if let Some(err_start) = json.find("\"error\"") {
-            let msg_start = json[err_start..].find("\"message\"").unwrap_or(0) + err_start;
-            let quote1 = json[msg_start..].find(':').unwrap_or(0) + msg_start + 1;
-            let quote2 = json[quote1..].find('"').unwrap_or(0) + quote1 + 1;
-            let quote3 = json[quote2..].find('"').unwrap_or(100) + quote2;
-            return Err(format!("Jina API error: {}", &json[quote2..quote3]));
+    /// Raw contents of a string, without the quotes
+    fn string(&mut self) -> Result<&'a str, String> {
+        self.expect(b'"')?;
+        let bytes = self.json.as_bytes();
+        let start = self.pos;
+        while let Some(&b) = bytes.get(self.pos) {
+            match b {
+                b'\\' => self.pos += 2,
+                b'"' => {
+                    self.pos += 1;
+                    return Ok(&self.json[start..self.pos - 1]);
+                }
+                _ => self.pos += 1,
+            }
         }
-        return Err(format!("Failed to parse embeddings from: {}...", &json[..200.min(json.len())]));
+        self.pos = self.json.len();
+        Err(self.malformed())
     }
     
-    Ok(embeddings)
+    /// A number, `true`, `false` or `null`, unchecked
+    fn scalar(&mut self) -> Result<&'a str, String> {
+        self.peek();
+        let start = self.pos;
+        let bytes = self.json.as_bytes();
+        while bytes.get(self.pos).is_some_and(|b| !b",]}\"".contains(b) && !b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+        if self.pos == start || !self.json.is_char_boundary(self.pos) {
+            return Err(self.malformed());
+        }
+        Ok(&self.json[start..self.pos])
+    }
+    
+    fn nested(&mut self, open: u8, close: u8, mut item: impl FnMut(&mut Self) -> Result<(), String>) -> Result<(), String> {
+        self.expect(open)?;
+        self.depth += 1;
+        if self.depth > MAX_JSON_DEPTH {
+            return Err(format!("Response nested deeper than {} levels", MAX_JSON_DEPTH));
+        }
+        if !self.eat(close) {
+            loop {
+                item(self)?;
+                if !self.eat(b',') {
+                    self.expect(close)?;
+                    break;
+                }
+            }
+        }
+        self.depth -= 1;
+        Ok(())
+    }
+    
+    /// Walk an object, handing each key to `member`, which must consume the value
+    fn members(&mut self, mut member: impl FnMut(&mut Self, &'a str) -> Result<(), String>) -> Result<(), String> {
+        self.nested(b'{', b'}', |scan| {
+            let key = scan.string()?;
+            scan.expect(b':')?;
+            member(scan, key)
+        })
+    }
+    
+    fn elements(&mut self, element: impl FnMut(&mut Self) -> Result<(), String>) -> Result<(), String> {
+        self.nested(b'[', b']', element)
+    }
+    
+    fn skip_value(&mut self) -> Result<(), String> {
+        match self.peek() {
+            Some(b'"') => self.string().map(drop),
+            Some(b'{') => self.members(|scan, _| scan.skip_value()),
+            Some(b'[') => self.elements(Self::skip_value),
+            _ => self.scalar().map(drop),
+        }
+    }
+    
+    /// Array of finite numbers, allocated at `dims`; `None` when shorter
+    fn vector<T: Component>(&mut self, dims: usize) -> Result<Option<Vec<T>>, String> {
+        let mut values = Vec::with_capacity(dims);
+        let mut count = 0;
+        self.elements(|scan| {
+            let token = scan.scalar()?;
+            let value: T = token.parse().map_err(|_| format!("Bad embedding value {:?}", token))?;
+            if !value.finite() {
+                return Err("Non-finite value in embedding".to_string());
+            }
+            if values.len() < dims {
+                values.push(value);
+            }
+            count += 1;
+            Ok(())
+        })?;
+        Ok((count >= dims).then_some(values))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mock::MockProvider;
     
     #[test]
+    #[allow(deprecated)]
     fn test_pseudo_embedding() {
         let e1 = generate_pseudo_embedding("Ada");
         let e2 = generate_pseudo_embedding("Ada");
@@ -200,4 +1923,779 @@ mod tests {
         let norm: f32 = e1.iter().map(|x| x * x).sum::<f32>().sqrt();
         assert!((norm - 1.0).abs() < 0.01);
     }
+    
+    #[test]
+    #[allow(deprecated)]
+    fn test_pseudo_embedding_dims() {
+        for dims in [128, 256, 1024] {
+            let e = generate_pseudo_embedding_dims("Ada loves Jan", dims);
+            assert_eq!(e.len(), dims);
+            assert_eq!(e, generate_pseudo_embedding_dims("Ada loves Jan", dims));
+            let norm: f32 = e.iter().map(|x| x * x).sum::<f32>().sqrt();
+            assert!((norm - 1.0).abs() < 1e-5);
+        }
+        assert_eq!(generate_pseudo_embedding_dims("Ada", 1024), generate_pseudo_embedding("Ada"));
+        // Locked until the legacy scheme is removed
+        let bits = |text| generate_pseudo_embedding_dims(text, 8).iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits("the cat sat"), [0x3dbec50d, 0x3f0f13ca, 0xbe6e764f, 0x3e6e7652, 0xbe0f13c9, 0xbf1b001b, 0x3e8f13c9, 0x3ea6ec6b]);
+        assert_eq!(bits("Ada"), [0x00000000, 0xbe01030a, 0xbf01030a, 0x3e81030a, 0xbe01030a, 0xbe81030a, 0xbe01030a, 0xbf418490]);
+        // The inlined hash is what DefaultHasher gave when the vectors were pinned
+        #[cfg(target_pointer_width = "64")]
+        for (window, i) in [(&b"the"[..], 0u64), (b"Ad", 1), (b"", 7)] {
+            use std::hash::{Hash, Hasher};
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            window.hash(&mut hasher);
+            i.hash(&mut hasher);
+            let message = [&(window.len() as u64).to_le_bytes()[..], window, &i.to_le_bytes()].concat();
+            assert_eq!(sip13_zero_key(&message), hasher.finish());
+        }
+        
+        // Offline backend follows EmbedOptions, and caches per size
+        let client = JinaClient::new("test_key").with_cache();
+        let small = client.embed_batch_full(&["Ada"], &EmbedOptions::query().with_dimensions(256)).unwrap().embeddings;
+        assert_eq!(small[0].len(), 256);
+        assert_eq!(client.embed_batch_full(&["Ada"], &EmbedOptions::query()).unwrap().embeddings[0].len(), 1024);
+        assert!(client.embed_batch_full(&["Ada"], &EmbedOptions::default().with_dimensions(0)).is_err());
+    }
+    
+    #[test]
+    fn test_pseudo_embedding_v2_locality() {
+        let sim = |a: &str, b: &str| -> f32 {
+            let (a, b) = (generate_pseudo_embedding_v2(a, 1024), generate_pseudo_embedding_v2(b, 1024));
+            a.iter().zip(&b).map(|(x, y)| x * y).sum()
+        };
+        
+        let similar = sim("the cat sat on the mat", "a cat sat on a mat");
+        let reordered = sim("Ada loves Jan", "Jan loves Ada!");
+        let unrelated = sim("the cat sat on the mat", "quarterly revenue grew sharply");
+        
+        assert!(similar > 0.55, "similar = {}", similar);
+        assert!(unrelated.abs() < 0.1, "unrelated = {}", unrelated);
+        assert!(similar - unrelated > 0.5);
+        // Bag of features: word order and edge punctuation don't matter
+        assert!(reordered > 0.99, "reordered = {}", reordered);
+        
+        // Deterministic, unit norm, zero only for whitespace
+        let e = generate_pseudo_embedding_v2("the cat sat", 256);
+        assert_eq!(e, generate_pseudo_embedding_v2("the cat sat", 256));
+        assert!((e.iter().map(|x| x * x).sum::<f32>().sqrt() - 1.0).abs() < 1e-5);
+        assert!(generate_pseudo_embedding_v2(" \n\t", 64).iter().all(|&x| x == 0.0));
+        assert!(generate_pseudo_embedding_v2("!!", 64).iter().any(|&x| x != 0.0));
+    }
+    
+    #[test]
+    fn test_batch_split_dedup_and_cache() {
+        let mock = Arc::new(MockProvider::new(2)
+            .with_vector("e f", vec![1.0, 0.0])
+            .with_default(vec![0.0, 1.0])
+            .fail_on_call(4, JinaError::Api { status: 503, message: "down".to_string() }));
+        let client = JinaClient::new("test_key").with_max_batch_size(2).with_cache().with_backend(mock.clone());
+        
+        let texts = ["a b", "c d", "a b", "e f", "g h", "c d"];
+        let embeddings = client.embed_batch_full(&texts, &EmbedOptions::default()).unwrap().embeddings;
+        assert_eq!(embeddings.len(), 6);
+        assert_eq!(embeddings[3], vec![1.0, 0.0]);
+        assert_eq!(embeddings[5], vec![0.0, 1.0]);
+        
+        // 4 unique texts in batches of 2, first occurrences in order
+        assert_eq!(mock.calls(), vec![vec!["a b", "c d"], vec!["e f", "g h"]]);
+        assert_eq!(client.stats(), ClientStats { requests: 2, texts_sent: 4, cache_hits: 0, hedges: 0 });
+        
+        // Cached under the same options only
+        client.embed_batch_full(&["a b", "e f"], &EmbedOptions::default()).unwrap();
+        assert_eq!(mock.call_count(), 2);
+        assert_eq!(client.stats().cache_hits, 2);
+        client.embed_batch_full(&["a b"], &EmbedOptions::query()).unwrap();
+        assert_eq!(mock.calls()[2], vec!["a b"]);
+        
+        // A failed request caches nothing
+        assert_eq!(client.embed_batch_full(&["i j"], &EmbedOptions::default()).unwrap_err().to_string(), "API error 503: down");
+        client.embed_batch_full(&["i j"], &EmbedOptions::default()).unwrap();
+        assert_eq!(mock.calls()[4], vec!["i j"]);
+    }
+    
+    #[test]
+    fn test_warm_resumes_and_serves_meanwhile() {
+        let mock = Arc::new(MockProvider::new(2)
+            .with_default(vec![0.0, 1.0])
+            .fail_on_call(2, JinaError::Api { status: 503, message: "down".to_string() }));
+        let client = JinaClient::new("test_key").with_cache().with_backend(mock.clone());
+        let options = EmbedOptions::default().with_dimensions(2);
+        let keys: Vec<(String, EmbedOptions)> = (0..10).map(|i| (format!("text {}", i), options.clone())).collect();
+        let progress: Arc<Mutex<Vec<usize>>> = Arc::default();
+        let seen = progress.clone();
+        let warmup = Warmup::default().with_batch_size(3).with_concurrency(1).with_progress(move |p| seen.lock().unwrap().push(p.done + p.failed));
+        
+        // The second batch fails; warming again sends only its texts
+        let report = client.warm(keys.clone().into_iter(), &warmup).unwrap();
+        assert_eq!((report.keys, report.embedded, report.failed), (10, 7, 3));
+        assert!(matches!(report.error, Some(JinaError::Api { status: 503, .. })));
+        assert_eq!(*progress.lock().unwrap(), [3, 6, 9, 10]);
+        let report = client.warm(keys.clone().into_iter(), &warmup).unwrap();
+        assert_eq!((report.already_cached, report.embedded, report.failed), (7, 3, 0));
+        assert_eq!(mock.calls()[4], ["text 3", "text 4", "text 5"]);
+        assert_eq!(client.stats().cache_hits, 0);
+        
+        // Served meanwhile from other threads: all hits, nothing sent
+        let calls = mock.call_count();
+        let texts: Vec<&str> = keys.iter().map(|(t, _)| t.as_str()).collect();
+        std::thread::scope(|scope| {
+            scope.spawn(|| client.warm(keys.clone().into_iter(), &Warmup::default().with_batch_size(1)).unwrap());
+            scope.spawn(|| client.embed_batch_full(&texts, &options).unwrap());
+        });
+        assert_eq!(mock.call_count(), calls);
+        let stats = client.cache_stats();
+        assert_eq!((stats.entries, stats.hit_rate_window), (10, Some(1.0)));
+        assert_eq!(stats.bytes, 10 * (32 + 8 + 2 * 4));
+        assert!(stats.oldest_entry_age.is_some());
+        assert!(JinaClient::new("test_key").warm(keys.into_iter(), &warmup).is_err());
+        assert_eq!(JinaClient::new("test_key").cache_stats(), CacheStats::default());
+    }
+    
+    #[test]
+    fn test_stale_entries_are_served_while_one_refresh_runs() {
+        use crate::mock::ManualClock;
+        let clock = Arc::new(ManualClock::new());
+        let error = JinaError::Api { status: 503, message: "busy".to_string() };
+        let mock = Arc::new(MockProvider::new(2).with_default(vec![1.0, 0.0]).with_latency(Duration::from_millis(300))
+            .fail_on_call(4, error));
+        let minute = Duration::from_secs(60);
+        let client = JinaClient::new("test_key").with_cache().with_backend(mock.clone()).with_clock(clock.clone())
+            .with_cache_policy(CachePolicy::StaleWhileRevalidate { ttl: minute, stale_ttl: minute });
+        let expected = vec![vec![1.0, 0.0]; 2];
+        assert_eq!(client.embed_batch_full(&["a", "b"], &EmbedOptions::default()).unwrap().embeddings, expected);
+        
+        // Past the TTL: served at once, every time, while a single refresh runs
+        clock.advance(Duration::from_secs(90));
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(client.embed_batch_full(&["a", "b"], &EmbedOptions::default()).unwrap().embeddings, expected);
+        }
+        assert!(start.elapsed() < Duration::from_millis(150), "{:?}", start.elapsed());
+        client.wait_for_refreshes();
+        assert_eq!(mock.calls(), [["a", "b"], ["a", "b"]]);
+        assert_eq!(client.stats().cache_hits, 6);
+        
+        // The refreshed entries are fresh although the first ones would be gone by now
+        clock.advance(Duration::from_secs(50));
+        client.embed_batch_full(&["a", "b"], &EmbedOptions::default()).unwrap();
+        client.wait_for_refreshes();
+        assert_eq!(mock.call_count(), 2);
+        
+        // Past the stale window an entry is a miss
+        clock.advance(Duration::from_secs(200));
+        let start = Instant::now();
+        assert_eq!(client.embed_batch_full(&["a"], &EmbedOptions::default()).unwrap().embeddings, expected[..1]);
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert_eq!(mock.call_count(), 3);
+        
+        // A failed refresh keeps the stale entry, and the next read tries again
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(client.embed_batch_full(&["a"], &EmbedOptions::default()).unwrap().embeddings, expected[..1]);
+        client.wait_for_refreshes();
+        assert_eq!(client.embed_batch_full(&["a"], &EmbedOptions::default()).unwrap().embeddings, expected[..1]);
+        client.wait_for_refreshes();
+        assert_eq!(mock.call_count(), 5);
+        assert_eq!(client.flights.in_flight(), 0);
+    }
+    
+    #[test]
+    fn test_concurrent_misses_send_each_text_once() {
+        use rand::prelude::*;
+        let mock = Arc::new(MockProvider::new(2).with_default(vec![1.0, 0.0]).with_latency(Duration::from_millis(20)));
+        let client = JinaClient::new("test_key").with_cache().with_backend(mock.clone());
+        let start = Instant::now();
+        std::thread::scope(|scope| {
+            for t in 0..32 {
+                let client = &client;
+                scope.spawn(move || {
+                    let mut rng = StdRng::seed_from_u64(t);
+                    for _ in 0..5 {
+                        let texts: Vec<String> = (0..3).map(|_| format!("hot {}", rng.gen_range(0..10))).collect();
+                        let refs: Vec<&str> = texts.iter().map(String::as_str).collect();
+                        assert_eq!(client.embed_batch_full(&refs, &EmbedOptions::default()).unwrap().embeddings.len(), 3);
+                    }
+                });
+            }
+        });
+        let mut sent: Vec<String> = mock.calls().into_iter().flatten().collect();
+        sent.sort();
+        assert_eq!(sent, (0..10).map(|i| format!("hot {}", i)).collect::<Vec<_>>());
+        // Distinct texts went out side by side, not one 20ms call after another
+        assert!(start.elapsed() < Duration::from_millis(200), "{:?}", start.elapsed());
+        assert_eq!(client.flights.in_flight(), 0);
+        
+        // Everyone waiting on a failed request gets its error; the next call retries
+        let error = JinaError::Api { status: 503, message: "busy".to_string() };
+        let mock = Arc::new(MockProvider::new(2).with_default(vec![1.0, 0.0]).with_latency(Duration::from_millis(200))
+            .fail_on_call(1, error.clone()));
+        let client = JinaClient::new("test_key").with_cache().with_backend(mock.clone());
+        let barrier = std::sync::Barrier::new(8);
+        let results: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8).map(|_| scope.spawn(|| {
+                barrier.wait();
+                client.embed_batch_full(&["hot"], &EmbedOptions::default()).map(|r| r.embeddings)
+            })).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert!(results.iter().all(|r| *r == Err(error.clone())), "{:?}", results);
+        assert_eq!(mock.call_count(), 1);
+        assert_eq!(client.embed_batch_full(&["hot"], &EmbedOptions::default()).unwrap().embeddings, [[1.0, 0.0]]);
+        assert_eq!(mock.call_count(), 2);
+    }
+    
+    #[test]
+    fn test_call_options_override_per_call() {
+        // Every attempt fails with 503; record each one's first input and timeout
+        let seen = Arc::new(Mutex::new(Vec::<(String, Option<Duration>)>::new()));
+        let record = seen.clone();
+        let client = JinaClient::new("jina_test")
+            .with_timeout(Duration::from_secs(60))
+            .with_retry(RetryPolicy { max_retries: 5, base_delay: Duration::ZERO, max_delay: Duration::ZERO })
+            .with_transport(move |request: &HttpRequest| {
+                let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                record.lock().unwrap().push((body["input"][0].as_str().unwrap().to_string(), request.timeout));
+                Ok(HttpResponse { status: 503, headers: Vec::new(), body: "busy".to_string() })
+            });
+        let interactive = CallOptions::default().with_timeout(Duration::from_secs(5)).with_max_retries(0).with_tag("query");
+        let background = CallOptions::default().with_max_retries(2);
+        let options = EmbedOptions::default().with_dimensions(2);
+        
+        std::thread::scope(|scope| {
+            for i in 0..4 {
+                let (client, options) = (&client, &options);
+                let (interactive, background) = (&interactive, &background);
+                scope.spawn(move || {
+                    assert!(client.embed_batch_call(&[&format!("query {}", i)], options, interactive).is_err());
+                    assert!(client.embed_batch_partial_call(&[&format!("job {}", i)], options, background).is_err());
+                    assert!(client.embed_batch_full(&[&format!("default {}", i)], options).is_err());
+                });
+            }
+        });
+        let seen = seen.lock().unwrap();
+        for i in 0..4 {
+            let attempts = |text: String| seen.iter().filter(|(t, _)| *t == text).map(|(_, timeout)| *timeout).collect::<Vec<_>>();
+            assert_eq!(attempts(format!("query {}", i)), [Some(Duration::from_secs(5))]);
+            assert_eq!(attempts(format!("job {}", i)), [Some(Duration::from_secs(60)); 3]);
+            assert_eq!(attempts(format!("default {}", i)), [Some(Duration::from_secs(60)); 6]);
+        }
+        assert_eq!(client.settings().retry.max_retries, 5);
+        assert_eq!(client.settings().timeout, Some(Duration::from_secs(60)));
+        
+        let zero = CallOptions::default().with_timeout(Duration::ZERO);
+        assert!(matches!(client.embed_batch_call(&["x"], &options, &zero), Err(JinaError::InvalidInput(_))));
+    }
+    
+    #[test]
+    fn test_deadline_spans_sub_batches() {
+        // Each request takes 800ms of virtual time, or its timeout if shorter
+        let clock = Arc::new(crate::mock::ManualClock::new());
+        let time = clock.clone();
+        let client = JinaClient::new("jina_test")
+            .with_max_batch_size(2)
+            .with_clock(clock.clone())
+            .with_retry(RetryPolicy { max_retries: 3, base_delay: Duration::from_millis(50), ..RetryPolicy::default() })
+            .with_transport(move |request: &HttpRequest| {
+                let latency = Duration::from_millis(800);
+                let timeout = request.timeout.unwrap();
+                time.advance(latency.min(timeout));
+                if timeout < latency {
+                    return Err(JinaError::Transport("timed out".to_string()));
+                }
+                let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                let data: Vec<_> = (0..body["input"].as_array().unwrap().len())
+                    .map(|i| serde_json::json!({ "index": i, "embedding": [1.0, 0.0] }))
+                    .collect();
+                Ok(HttpResponse { status: 200, headers: Vec::new(), body: serde_json::json!({ "data": data }).to_string() })
+            });
+        let options = EmbedOptions::default().with_dimensions(2);
+        let texts = ["a", "b", "c", "d", "e", "f"];
+        
+        let start = clock.now();
+        let call = CallOptions::default().with_deadline(start + Duration::from_secs(2));
+        let items = client.embed_batch_partial_call(&texts, &options, &call).unwrap();
+        assert!(items[..4].iter().all(|item| item.is_ok()));
+        assert_eq!(items[4..], [Err(ItemError::DeadlineExceeded), Err(ItemError::DeadlineExceeded)]);
+        assert_eq!(clock.now() - start, Duration::from_secs(2));
+        assert_eq!(client.stats().requests, 3);
+        
+        let call = CallOptions::default().with_deadline(clock.now() + Duration::from_secs(2));
+        assert_eq!(client.embed_batch_call(&texts, &options, &call).unwrap_err(), JinaError::DeadlineExceeded);
+        // Already past: nothing is sent
+        assert_eq!(client.embed_batch_call(&["g"], &options, &CallOptions::default().with_deadline(clock.now())).unwrap_err(),
+                   JinaError::DeadlineExceeded);
+        assert_eq!(client.stats().requests, 6);
+    }
+    
+    #[test]
+    fn test_batches_split_on_token_budget() {
+        let mock = Arc::new(MockProvider::new(2).with_default(vec![1.0, 0.0]));
+        let words = |t: &str| t.split_whitespace().count();
+        let client = JinaClient::new("test_key").with_max_batch_tokens(4).with_token_counter(words).with_backend(mock.clone());
+        
+        client.embed_batch_full(&["a b", "c d", "e f g h i", "j"], &EmbedOptions::default()).unwrap();
+        assert_eq!(mock.calls(), vec![vec!["a b", "c d"], vec!["e f g h i"], vec!["j"]]);
+        assert_eq!(client.count_tokens("one two three"), 3);
+    }
+    
+    #[test]
+    fn test_preprocess_before_dedup_and_cache() {
+        let mock = Arc::new(MockProvider::new(2).with_default(vec![1.0, 0.0]));
+        let client = JinaClient::new("test_key").with_cache().with_backend(mock.clone());
+        let options = EmbedOptions::passage().with_preprocess(Pipeline::html());
+        
+        client.embed_batch_full(&["<p>Ada  Lovelace</p>", "Ada Lovelace"], &options).unwrap();
+        client.embed_batch_full(&["<b>Ada</b> Lovelace"], &options).unwrap();
+        assert_eq!(mock.calls(), vec![vec!["Ada Lovelace"]]);
+        assert_eq!(client.stats().cache_hits, 1);
+        
+        // Cut to the budget at a sentence boundary, after preprocessing
+        let words = |t: &str| t.split_whitespace().count();
+        let mock = Arc::new(MockProvider::new(2).with_default(vec![1.0, 0.0]));
+        let client = JinaClient::new("test_key").with_token_counter(words).with_backend(mock.clone());
+        let options = options.with_max_input_tokens(4);
+        client.embed_batch_full(&["<b>One</b> two. Three four. Five.", "Short."], &options).unwrap();
+        assert_eq!(mock.calls(), vec![vec!["One two. Three four.", "Short."]]);
+    }
+    
+    #[test]
+    fn test_request_body() {
+        fn request_body(model: &str, texts: &[&str], options: &EmbedOptions) -> String {
+            let mut body = String::new();
+            write_request_body(&mut body, model, texts, options);
+            body
+        }
+        let body = request_body(JINA_MODEL, &["say \"hi\"\n", "back\\slash"], &EmbedOptions::passage());
+        assert_eq!(body, r#"{"model":"jina-embeddings-v3","task":"retrieval.passage","input":["say \"hi\"\n","back\\slash"]}"#);
+        
+        let body = request_body("jina-embeddings-v2-base-en", &["x"], &EmbedOptions::default().with_dimensions(256));
+        assert_eq!(body, r#"{"model":"jina-embeddings-v2-base-en","dimensions":256,"input":["x"]}"#);
+    }
+    
+    #[test]
+    fn test_hooks_run_per_attempt_and_mutate_requests() {
+        let sent: Arc<Mutex<Vec<HttpRequest>>> = Arc::default();
+        let log = sent.clone();
+        let hooked: Arc<Mutex<Vec<(u32, String)>>> = Arc::default();
+        let (requests, responses) = (hooked.clone(), hooked.clone());
+        let client = JinaClient::new("jina_secret")
+            .with_retry(RetryPolicy { base_delay: Duration::ZERO, ..RetryPolicy::default() })
+            .with_transport(move |request: &HttpRequest| {
+                let mut log = log.lock().unwrap();
+                log.push(request.clone());
+                let body = if log.len() == 1 { "{}" } else { r#"{"data":[{"embedding":[1.0,0.0]}]}"# };
+                Ok(HttpResponse { status: if log.len() == 1 { 503 } else { 200 }, headers: Vec::new(), body: body.to_string() })
+            })
+            .with_request_hook(move |request, attempt| {
+                let auth = request.headers.iter().find(|(n, _)| n == "Authorization").unwrap().1.clone();
+                requests.lock().unwrap().push((attempt, auth));
+                request.headers.push(("X-Body-Size".to_string(), request.body.len().to_string()));
+            })
+            .with_response_hook(move |response, attempt| responses.lock().unwrap().push((attempt, response.status.to_string())));
+        client.embed_batch_full(&["Ada"], &EmbedOptions::default().with_dimensions(2)).unwrap();
+        
+        let redacted = transport::REDACTED.to_string();
+        assert_eq!(*hooked.lock().unwrap(), [(1, redacted.clone()), (1, "503".to_string()), (2, redacted), (2, "200".to_string())]);
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 2);
+        for request in sent.iter() {
+            let header = |name: &str| request.headers.iter().filter(|(n, _)| n == name).map(|(_, v)| v.as_str()).collect::<Vec<_>>();
+            // Each attempt starts from the original request; the real key is restored
+            assert_eq!(header("X-Body-Size"), [request.body.len().to_string()]);
+            assert_eq!(header("Authorization"), ["Bearer jina_secret"]);
+        }
+    }
+    
+    #[test]
+    fn test_diagnostics_attempts_bytes_and_backend() {
+        let sent: Arc<Mutex<Vec<usize>>> = Arc::default();
+        let log = sent.clone();
+        let ok = r#"{"data":[{"embedding":[1.0,0.0]}]}"#;
+        let client = JinaClient::new("jina_test")
+            .with_retry(RetryPolicy { base_delay: Duration::ZERO, ..RetryPolicy::default() })
+            .with_transport(move |request: &HttpRequest| {
+                let mut log = log.lock().unwrap();
+                log.push(request.body.len());
+                let (status, body) = match log.len() { 1 => (503, "busy"), 2 => (200, ok), _ => (422, "bad") };
+                Ok(HttpResponse { status, headers: Vec::new(), body: body.to_string() })
+            });
+        let options = EmbedOptions::default().with_dimensions(2);
+        let response = client.embed_batch_diagnosed(&["Ada"], &options).unwrap();
+        let diagnostics = response.diagnostics.unwrap();
+        assert_eq!((diagnostics.attempts, diagnostics.backend.as_str()), (2, "custom"));
+        assert_eq!(diagnostics.bytes_sent, 2 * sent.lock().unwrap()[0] as u64);
+        assert_eq!(diagnostics.bytes_received, ("busy".len() + ok.len()) as u64);
+        assert_eq!(diagnostics.connect_ms, None);
+        
+        // Failures carry what was spent; the lean path reports nothing
+        let error = client.embed_batch_diagnosed(&["Jan"], &options).unwrap_err();
+        assert!(matches!(error.error, JinaError::Api { status: 422, .. }));
+        assert_eq!((error.diagnostics.attempts, error.diagnostics.bytes_received), (1, 3));
+        let offline = JinaClient::new("test_key");
+        assert_eq!(offline.embed_batch_full(&["Ada"], &options).unwrap().diagnostics, None);
+        let diagnostics = offline.embed_batch_diagnosed(&["Ada"], &options).unwrap().diagnostics.unwrap();
+        assert_eq!((diagnostics.attempts, diagnostics.backend.as_str(), diagnostics.bytes_sent), (1, "offline", 0));
+    }
+    
+    /// Refuses bodies over `limit` bytes with 413; embeds each text as `[len, first byte]`
+    fn size_limited(limit: usize, sizes: Arc<Mutex<Vec<usize>>>) -> impl Transport {
+        move |request: &HttpRequest| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            let input: Vec<&str> = body["input"].as_array().unwrap().iter().map(|t| t.as_str().unwrap()).collect();
+            sizes.lock().unwrap().push(input.len());
+            if request.body.len() > limit {
+                return Ok(HttpResponse { status: 413, headers: Vec::new(), body: "Payload Too Large".to_string() });
+            }
+            let data: Vec<String> = input.iter()
+                .map(|t| format!(r#"{{"embedding":[{},{}]}}"#, t.len(), t.as_bytes()[0]))
+                .collect();
+            Ok(HttpResponse { status: 200, headers: Vec::new(), body: format!(r#"{{"data":[{}]}}"#, data.join(",")) })
+        }
+    }
+    
+    #[test]
+    fn test_payload_too_large_bisects_in_order() {
+        let sizes: Arc<Mutex<Vec<usize>>> = Arc::default();
+        let client = JinaClient::new("jina_test").with_transport(size_limited(200, sizes.clone()));
+        let options = EmbedOptions::default().with_dimensions(2);
+        let texts: Vec<String> = (0..8).map(|i| format!("{}{}", (b'a' + i) as char, "x".repeat(30))).collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let embeddings = client.embed_batch_full(&texts, &options).unwrap().embeddings;
+        let expected: Vec<Vec<f32>> = texts.iter().map(|t| vec![t.len() as f32, t.as_bytes()[0] as f32]).collect();
+        assert_eq!(embeddings, expected);
+        // 8 → 4 + 4, each of which fits
+        assert_eq!(*sizes.lock().unwrap(), [8, 4, 4]);
+        
+        // A text too large alone is refused; its neighbours are still embedded and cached
+        sizes.lock().unwrap().clear();
+        let client = JinaClient::new("jina_test").with_cache().with_transport(size_limited(200, sizes.clone()));
+        let huge = format!("h{}", "y".repeat(300));
+        let mixed = [texts[0], texts[1], &huge, texts[2]];
+        let error = client.embed_batch_full(&mixed, &options).unwrap_err();
+        assert_eq!(error, JinaError::InputTooLarge { index: 2, size: huge.len(), limit: None });
+        assert_eq!(error.to_string(), format!("Input 2 is {} bytes, over the server's limit", huge.len()));
+        assert_eq!(*sizes.lock().unwrap(), [4, 2, 2, 1, 1]);
+        sizes.lock().unwrap().clear();
+        assert_eq!(client.embed_batch_full(&[texts[2], texts[0]], &options).unwrap().embeddings, [expected[2].clone(), expected[0].clone()]);
+        assert!(sizes.lock().unwrap().is_empty());
+        
+        // The API's error body for oversized requests splits too; other errors do not
+        assert!(is_payload_too_large(&JinaError::Api { status: 400, message: "Input text is too long".to_string() }));
+        assert!(!is_payload_too_large(&JinaError::Api { status: 400, message: "Invalid task".to_string() }));
+        assert!(!is_payload_too_large(&JinaError::Api { status: 503, message: "too large".to_string() }));
+    }
+    
+    #[test]
+    fn test_partial_batch_reports_per_item() {
+        // Names the first input containing "bad" the way FastAPI does, refuses "huge" in a
+        // batch with 413, cannot reach the server for "down"; embeds as `[len, first byte]`
+        let sizes: Arc<Mutex<Vec<usize>>> = Arc::default();
+        let seen = sizes.clone();
+        let transport = move |request: &HttpRequest| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            let input: Vec<&str> = body["input"].as_array().unwrap().iter().map(|t| t.as_str().unwrap()).collect();
+            seen.lock().unwrap().push(input.len());
+            let respond = |status, body: String| Ok(HttpResponse { status, headers: Vec::new(), body });
+            if input.contains(&"down") {
+                return Err(JinaError::Connect("Could not connect".to_string()));
+            }
+            if let Some(i) = input.iter().position(|t| t.starts_with("bad")) {
+                return respond(422, format!(r#"{{"detail":[{{"loc":["body","input",{}],"msg":"invalid text"}}]}}"#, i));
+            }
+            if input.iter().any(|t| t.starts_with("huge")) {
+                return respond(413, "Payload Too Large".to_string());
+            }
+            let data: Vec<String> = input.iter().map(|t| format!(r#"{{"embedding":[{},{}]}}"#, t.len(), t.as_bytes()[0])).collect();
+            respond(200, format!(r#"{{"data":[{}]}}"#, data.join(",")))
+        };
+        let client = JinaClient::new("jina_test").with_cache().with_retry(RetryPolicy::none()).with_transport(transport);
+        let options = EmbedOptions::default().with_dimensions(2);
+        
+        let texts = ["a0", "bad1", "a2", "   ", "bad4", "huge5", "a6"];
+        let results = client.embed_batch_partial(&texts, &options).unwrap();
+        assert_eq!(results.len(), texts.len());
+        for (text, result) in texts.iter().zip(&results) {
+            match &text[..1] {
+                "a" => assert_eq!(result, &Ok(vec![text.len() as f32, b'a' as f32])),
+                "b" => assert!(matches!(result, Err(ItemError::Rejected { status: 422, message }) if message.contains("invalid text"))),
+                "h" => assert_eq!(result, &Err(ItemError::TooLarge { size: 5 })),
+                _ => assert_eq!(result, &Err(ItemError::Empty)),
+            }
+        }
+        // Resent without each named input, then bisected around the oversized one
+        assert_eq!(*sizes.lock().unwrap(), [6, 5, 4, 2, 2, 1, 1]);
+        
+        // The all-or-nothing call embeds and caches the rest, then fails on the rejected input
+        sizes.lock().unwrap().clear();
+        let error = client.embed_batch_full(&["a7", "bad8"], &options).unwrap_err();
+        assert!(matches!(error, JinaError::Api { status: 422, .. }), "{:?}", error);
+        assert_eq!(client.embed_batch_full(&["a7"], &options).unwrap().embeddings, [[2.0, b'a' as f32]]);
+        assert_eq!(*sizes.lock().unwrap(), [2, 1]);
+        
+        // Transport failures still fail the whole call
+        assert!(matches!(client.embed_batch_partial(&["a9", "down"], &options), Err(JinaError::Connect(_))));
+        assert_eq!(offending_inputs(r#"input[3] and inputs.0, "input", 9 and input[12]"#, 10), [0, 3, 9]);
+        assert!(offending_inputs("inputs must be strings", 10).is_empty());
+    }
+    
+    #[test]
+    fn test_failed_data_entries_only_when_tolerant() {
+        let serve = |body: &'static str| move |_: &HttpRequest| Ok(HttpResponse { status: 200, headers: Vec::new(), body: body.to_string() });
+        let null_item = include_str!("../fixtures/openai/embeddings_null_item.json");
+        let error_item = include_str!("../fixtures/openai/embeddings_error_item.json");
+        let options = EmbedOptions::default().with_dimensions(2);
+        
+        // Strict: a protocol error, not a misaligned batch
+        let strict = JinaClient::new("jina_test").with_retry(RetryPolicy::none()).with_transport(serve(null_item));
+        assert!(strict.embed_batch_partial(&["a", "b", "c"], &options).is_err());
+        let strict = JinaClient::new("jina_test").with_retry(RetryPolicy::none()).with_transport(serve(error_item));
+        assert_eq!(strict.embed_batch_partial(&["a", "b"], &options).unwrap_err(), JinaError::Mismatch { expected: 2, got: 1 });
+        
+        let tolerant = JinaClient::new("jina_test").with_tolerant_items().with_transport(serve(null_item));
+        let results = tolerant.embed_batch_partial(&["a", "b", "c"], &options).unwrap();
+        assert_eq!(results, [Ok(vec![0.6, 0.8]), Err(ItemError::Failed { message: "null entry".to_string() }), Ok(vec![0.0, 1.0])]);
+        let error = tolerant.embed_batch_full(&["a", "b", "c"], &options).unwrap_err();
+        assert_eq!(error, JinaError::Parse("data entry 1 failed: null entry".to_string()));
+        
+        // Entries go by their index; the gateway's message is kept
+        let tolerant = JinaClient::new("jina_test").with_tolerant_items().with_transport(serve(error_item));
+        let results = tolerant.embed_batch_partial(&["too long", "short"], &options).unwrap();
+        assert!(matches!(&results[0], Err(ItemError::Failed { message }) if message.contains("maximum context length")));
+        assert_eq!(results[1], Ok(vec![1.0, 0.0]));
+        
+        assert_eq!(parse_jina_items(r#"{"data":[{"index":0,"error":"quota"}]}"#, 2, 1).unwrap(),
+                   [Err(ItemError::Failed { message: "quota".to_string() })]);
+        assert!(matches!(parse_jina_items(r#"{"data":[{"index":3,"embedding":[1,0]}]}"#, 2, 1), Err(JinaError::Parse(_))));
+        assert_eq!(parse_jina_items(r#"{"error":{"message":"no"}}"#, 2, 1).unwrap_err(), JinaError::Parse("Jina API error: no".to_string()));
+    }
+    
+    #[test]
+    fn test_large_bodies_are_gzipped_until_refused() {
+        // Logs (gzipped, decoded JSON) per request; a server without gzip answers 415
+        let server = |accepts_gzip: bool, log: Arc<Mutex<Vec<(bool, serde_json::Value)>>>| move |request: &HttpRequest| {
+            let body = serde_json::from_slice(&request.decoded_body().unwrap()).unwrap();
+            log.lock().unwrap().push((request.is_gzipped(), body));
+            if request.is_gzipped() && !accepts_gzip {
+                return Ok(HttpResponse { status: 415, headers: Vec::new(), body: "Unsupported Media Type".to_string() });
+            }
+            Ok(HttpResponse { status: 200, headers: Vec::new(), body: r#"{"data":[{"embedding":[1,0]}]}"#.to_string() })
+        };
+        let options = EmbedOptions::default().with_dimensions(2);
+        let long = "word ".repeat(100);
+        let mut expected = String::new();
+        write_request_body(&mut expected, JINA_MODEL, &[&long], &options);
+        let expected: serde_json::Value = serde_json::from_str(&expected).unwrap();
+        
+        let log: Arc<Mutex<Vec<(bool, serde_json::Value)>>> = Arc::default();
+        let client = JinaClient::new("jina_test").with_compression_threshold(100).with_transport(server(true, log.clone()));
+        client.embed_batch_full(&[&long], &options).unwrap();
+        client.embed_batch_full(&["short"], &options).unwrap();
+        let sent = std::mem::take(&mut *log.lock().unwrap());
+        assert_eq!(sent[0], (true, expected.clone()));
+        assert!(!sent[1].0);
+        
+        // After one 415 the request is resent plain, and so is every later one
+        let client = JinaClient::new("jina_test").with_compression_threshold(100).with_transport(server(false, log.clone()));
+        assert_eq!(client.embed_batch_full(&[&long], &options).unwrap().embeddings, [[1.0, 0.0]]);
+        client.embed_batch_full(&[&format!("{} again", long)], &options).unwrap();
+        let gzipped: Vec<bool> = std::mem::take(&mut *log.lock().unwrap()).into_iter().map(|(g, _)| g).collect();
+        assert_eq!(gzipped, [true, false, false]);
+        
+        let client = JinaClient::new("jina_test").with_compression_threshold(100).without_compression()
+            .with_transport(server(false, log.clone()));
+        client.embed_batch_full(&[&long], &options).unwrap();
+        assert_eq!(*log.lock().unwrap(), [(false, expected)]);
+    }
+    
+    /// Stands in for curl: embeds the one input `n` as `[n, 0]` after 300ms, or
+    /// fails like a refused connection on `fail`; notes how many copies are live
+    #[cfg(unix)]
+    const FAKE_CURL: &str = r#"#!/bin/sh
+dir=$(dirname "$0")
+touch "$dir/live.$$"
+ls "$dir" | grep -c '^live\.' >> "$dir/counts"
+input=$(sed -n 's/.*"input":\["\([^"]*\)"\].*/\1/p')
+sleep 0.3
+rm "$dir/live.$$"
+if [ "$input" = fail ]; then echo "Could not connect" >&2; exit 7; fi
+printf 'HTTP/1.1 200 OK\r\n\r\n{"data":[{"embedding":[%s,0]}]}' "$input"
+"#;
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_curl_batches_run_in_parallel_and_reassemble() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("curl");
+        std::fs::write(&script, FAKE_CURL).unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let client = |n: usize| JinaClient::new("jina_test").with_cache().with_max_batch_size(1)
+            .with_curl_parallelism(n).with_transport(transport::CurlTransport::new().with_program(&script));
+        let counts = || -> Vec<usize> {
+            let counts = std::fs::read_to_string(dir.path().join("counts")).unwrap_or_default();
+            std::fs::remove_file(dir.path().join("counts")).ok();
+            counts.lines().map(|l| l.parse().unwrap()).collect()
+        };
+        let live = || std::fs::read_dir(dir.path()).unwrap().filter(|e| {
+            e.as_ref().unwrap().file_name().to_string_lossy().starts_with("live.")
+        }).count();
+        let options = EmbedOptions::default().with_dimensions(2);
+        
+        // Six 300ms batches three at a time take two rounds, and come back in order
+        let texts = ["1", "2", "3", "4", "5", "6"];
+        let start = Instant::now();
+        let embeddings = client(3).embed_batch_full(&texts, &options).unwrap().embeddings;
+        assert!(start.elapsed() < Duration::from_millis(1500), "{:?}", start.elapsed());
+        let expected: Vec<Vec<f32>> = (1..=6).map(|n| vec![n as f32, 0.0]).collect();
+        assert_eq!(embeddings, expected);
+        let seen = counts();
+        assert_eq!(seen.len(), 6);
+        assert!(seen.iter().all(|&n| n <= 3), "{:?}", seen);
+        
+        // A failed batch stops new ones; those in flight are reaped, those done are cached
+        let client = client(2).with_retry(RetryPolicy::none());
+        let error = client.embed_batch_full(&["1", "fail", "3", "4", "5", "6"], &options).unwrap_err().to_string();
+        assert!(error.contains("Could not connect"), "{}", error);
+        assert_eq!(live(), 0);
+        let seen = counts();
+        assert!(seen.len() < 6 && seen.iter().all(|&n| n <= 2), "{:?}", seen);
+        assert_eq!(client.embed_batch_full(&["1"], &options).unwrap().embeddings, [[1.0, 0.0]]);
+        assert!(counts().is_empty());
+    }
+    
+    #[test]
+    fn test_f64_path_agrees_with_f32() {
+        let fixture = include_str!("../fixtures/jina/parser/ok_two_items.json");
+        let client = JinaClient::new("jina_test").with_transport(move |_: &HttpRequest| {
+            Ok(HttpResponse { status: 200, headers: Vec::new(), body: fixture.to_string() })
+        });
+        let options = EmbedOptions::default().with_dimensions(4);
+        let narrow = client.embed_batch_full(&["Ada", "Jan"], &options).unwrap().embeddings;
+        let wide = client.embed_batch_f64(&["Ada", "Jan"], &options).unwrap();
+        for (n, w) in narrow.iter().flatten().zip(wide.iter().flatten()) {
+            assert!((*n as f64 - w).abs() <= f32::EPSILON as f64 * w.abs(), "{} {}", n, w);
+        }
+        // Parsed from the text, not widened from f32
+        assert_eq!(wide[1][0], 1e-3);
+        assert_ne!(wide[1][0], 1e-3f32 as f64);
+        
+        let offline = JinaClient::new("test_key");
+        let expected = offline.embed_batch_full(&["Ada"], &options).unwrap().embeddings;
+        assert_eq!(offline.embed_batch_f64(&["Ada"], &options).unwrap(), [to_f64(&expected[0])]);
+    }
+    
+    #[test]
+    fn test_task_from_str() {
+        assert_eq!("retrieval.query".parse::<Task>().unwrap(), Task::RetrievalQuery);
+        assert_eq!("text-matching".parse::<Task>().unwrap(), Task::TextMatching);
+        assert!(matches!("retrieval".parse::<Task>(), Err(JinaError::InvalidInput(_))));
+    }
+    
+    /// Parser outcome for `body`: consistent vectors or a message, never a panic
+    fn check_parse(body: &str, dims: usize) -> Result<(), String> {
+        match parse_jina_response(body, dims) {
+            Ok(vectors) => {
+                assert!(!vectors.is_empty(), "Ok without vectors for {:?}", body);
+                for v in &vectors {
+                    assert_eq!(v.len(), dims, "wrong size for {:?}", body);
+                    assert!(v.iter().all(|x| x.is_finite()), "non-finite value for {:?}", body);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                assert!(!e.is_empty(), "empty error for {:?}", body);
+                Err(e)
+            }
+        }
+    }
+    
+    /// `fixtures/jina/parser`: `ok_*` files parse into 4-d vectors, `err_*` files fail
+    #[test]
+    fn test_parser_corpus() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/jina/parser");
+        let mut checked = 0;
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_str().unwrap().to_string();
+            let body = std::fs::read_to_string(&path).unwrap();
+            let parsed = check_parse(&body, 4);
+            assert_eq!(parsed.is_ok(), name.starts_with("ok_"), "{}: {:?}", name, parsed);
+            checked += 1;
+        }
+        assert!(checked >= 10);
+        
+        let message = check_parse(include_str!("../fixtures/jina/parser/err_multibyte_message.json"), 4).unwrap_err();
+        assert_eq!(message, "Jina API error: Ungültige Eingabe: 入力が長すぎます 🚫");
+        assert_eq!(parse_jina_response(include_str!("../fixtures/jina/parser/ok_two_items.json"), 2).unwrap(),
+                   vec![vec![0.5, -0.5], vec![1e-3, 0.25]]);
+        
+        // Crafted against substring scanning: "embedding" as a value ahead of
+        // the next array, and brackets inside strings
+        assert_eq!(parse_jina_response(include_str!("../fixtures/jina/parser/ok_embedding_key_first.json"), 4).unwrap(),
+                   vec![vec![0.1, 0.2, 0.3, 0.4], vec![0.5, 0.6, 0.7, 0.8]]);
+        assert_eq!(parse_jina_response(include_str!("../fixtures/jina/parser/ok_brackets_in_strings.json"), 4).unwrap(),
+                   vec![vec![0.1, 0.2, 0.3, 0.4]]);
+        assert_eq!(check_parse(include_str!("../fixtures/jina/parser/err_fools_substring_scan.json"), 4).unwrap_err(),
+                   "Jina API error: invalid embedding_type [8,8,8,8]");
+        let deep = format!(r#"{{"model":{}"#, "[".repeat(100_000));
+        assert_eq!(check_parse(&deep, 4).unwrap_err(), format!("Response nested deeper than {} levels", MAX_JSON_DEPTH));
+        
+        // Regression: a multibyte char across byte 200 of an unparseable body used to panic the message
+        let empty = format!(r#"{{"data":[],"note":"{}"}}"#, "日".repeat(300));
+        assert!(!empty.is_char_boundary(200));
+        let message = check_parse(&empty, 4).unwrap_err();
+        assert!(message.ends_with("… (121 more chars)"), "{}", message);
+        let long = format!(r#"{{"error":{{"message":"{}"}}}}"#, "🚫".repeat(500));
+        assert_eq!(check_parse(&long, 4).unwrap_err(), format!("Jina API error: {}… (300 more chars)", "🚫".repeat(200)));
+    }
+    
+    #[test]
+    fn test_large_response_allocates_final_vectors_only() {
+        let dims = 1024;
+        let row: Vec<String> = (0..dims + 8).map(|i| format!("{:.6}", i as f32 * 1e-4 - 0.05)).collect();
+        let item = format!(r#"{{"object":"embedding","index":0,"embedding":[{}]}}"#, row.join(","));
+        let body = format!(r#"{{"model":"jina-embeddings-v3","data":[{}]}}"#, vec![item; 256].join(","));
+        assert!(body.len() > 2_000_000);
+        
+        let embeddings = parse_jina_response(&body, dims).unwrap();
+        assert_eq!(embeddings.len(), 256);
+        // Each vector is allocated once at its final size: no growth slack, no copy
+        assert!(embeddings.iter().all(|v| v.len() == dims && v.capacity() == dims));
+        assert_eq!(embeddings[0][1], 1e-4 - 0.05);
+    }
+    
+    /// One byte-level edit of a body: truncate, insert or delete
+    fn mutate(body: &str, op: u8, at: usize, text: &str) -> String {
+        let at = body.floor_char_boundary(at % (body.len() + 1));
+        match op % 3 {
+            0 => body[..at].to_string(),
+            1 => format!("{}{}{}", &body[..at], text, &body[at..]),
+            _ => {
+                let end = body.floor_char_boundary((at + text.len()).min(body.len()));
+                format!("{}{}", &body[..at], &body[end..])
+            }
+        }
+    }
+    
+    proptest::proptest! {
+        #![proptest_config(crate::proptest_config(512))]
+        
+        #[test]
+        fn prop_parser_never_panics(seed in 0usize..3, edits in proptest::collection::vec((0u8..3, 0usize..400, "\\PC{0,6}|[\\[\\]{}\":,.eE0-9-]{1,4}|\"embedding\""), 1..4),
+                                    dims in 1usize..8) {
+            let seeds = [include_str!("../fixtures/jina/parser/ok_two_items.json"),
+                         include_str!("../fixtures/jina/parser/ok_embedding_in_strings.json"),
+                         include_str!("../fixtures/jina/parser/err_multibyte_message.json")];
+            let mut body = seeds[seed].to_string();
+            for (op, at, text) in &edits {
+                body = mutate(&body, *op, *at, text);
+            }
+            let _ = check_parse(&body, dims);
+        }
+        
+        #[test]
+        fn prop_parser_arbitrary_text(body in "\\PC{0,300}", dims in 1usize..8) {
+            let _ = check_parse(&body, dims);
+        }
+    }
 }
@@ -7,17 +7,39 @@
 //!
 //! For typical knowledge graphs with repeated entities,
 //! this reduces Jina API calls by 90%+
+//!
+//! Exact entries are keyed by `hash::content_key` of the model, options
+//! and text, so an exact hit never comes from another model. The
+//! persistence file starts with a magic and a format version; files of
+//! another version load as empty and are rewritten on the next save.
 
 use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
+use std::fs::File;
 use std::io::{BufReader, BufWriter, Read, Write};
-use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::audit::unix_ms;
+use crate::cache::{self, HitWindow, WarmReport, Warmup};
+use crate::hash::{self, ContentKey};
+use crate::io::atomic_write;
+use crate::jina_api::{EmbedOptions, JINA_MODEL};
+use crate::provider::{EmbedError, EmbeddingProvider};
+use crate::pseudo::PseudoEmbedder;
 
 // Same fingerprint structure as main.rs
 const N: usize = 10_000;
 const N64: usize = 157;
+#[allow(dead_code)]
 const NEAR_THRESHOLD: u32 = 1500;  // 0.15 * 10000 = 15% Hamming distance
 
+/// Leading bytes of a persistence file
+const MAGIC: &[u8; 6] = b"SPOFPC";
+/// Bumped whenever the entry layout changes
+const FORMAT_VERSION: u16 = 2;
+/// Model name keying offline pseudo-embeddings
+const OFFLINE_MODEL: &str = "offline";
+
 #[repr(align(64))]
 #[derive(Clone)]
 pub struct Fingerprint {
@@ -45,8 +67,11 @@ impl Fingerprint {
         // Method: threshold at median, then expand to 10K bits
         // Each of 1024 dimensions maps to ~10 bits
         let mut sorted: Vec<f32> = embedding.to_vec();
-        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
-        let median = sorted[512];
+        sorted.sort_by(f32::total_cmp);
+        let median = match sorted.get(sorted.len() / 2) {
+            Some(&m) => m,
+            None => return fp,
+        };
         
         for (i, &val) in embedding.iter().enumerate() {
             let base_bit = i * 10;  // 1024 * 10 = 10240 > 10000, so we wrap
@@ -91,27 +116,42 @@ impl Fingerprint {
 /// Cache entry with original text and fingerprint
 #[derive(Clone)]
 struct CacheEntry {
+    /// `hash::content_key` of the model, options and text
+    key: ContentKey,
     text: String,
     fingerprint: Fingerprint,
+    #[allow(dead_code)]
     jina_embedding: Option<Vec<f32>>,  // Keep original for precision if needed
+    /// Unix time in ms it was embedded, or its file saved for loaded entries
+    inserted_ms: u64,
 }
 
 /// Jina embedding cache with sparse API usage
 pub struct JinaCache {
-    /// Exact match lookup
-    exact: HashMap<String, CacheEntry>,
+    /// Exact match lookup, by content key
+    exact: HashMap<ContentKey, CacheEntry>,
     
     /// All entries for near-match search (could use a proper ANN index)
     entries: Vec<CacheEntry>,
     
     /// API key
+    #[allow(dead_code)]
     api_key: String,
     
     /// Statistics
     pub stats: CacheStats,
     
+    /// Recent hits and misses, for `cache_stats`
+    window: HitWindow,
+    
     /// Persistence path
     cache_path: Option<String>,
+    
+    /// Embedding backend for misses; offline pseudo-embeddings if unset
+    provider: Option<Box<dyn EmbeddingProvider>>,
+    
+    /// Model name for content keys; see `model`
+    model: Option<String>,
 }
 
 #[derive(Default, Clone)]
@@ -141,10 +181,45 @@ impl JinaCache {
             entries: Vec::new(),
             api_key: api_key.to_string(),
             stats: CacheStats::default(),
+            window: HitWindow::default(),
             cache_path: None,
+            provider: None,
+            model: None,
         }
     }
     
+    /// Embed cache misses with `provider` (e.g. a `JinaClient`)
+    pub fn with_provider(mut self, provider: impl EmbeddingProvider + 'static) -> Self {
+        self.provider = Some(Box::new(provider));
+        self
+    }
+    
+    /// Key entries by `model` rather than the default (see `model`)
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = Some(model.to_string());
+        self
+    }
+    
+    /// Model entries are keyed by: the one set by `with_model`, else
+    /// `jina-embeddings-v3` with a provider and `offline` without one
+    pub fn model(&self) -> &str {
+        match (&self.model, &self.provider) {
+            (Some(model), _) => model,
+            (None, Some(_)) => JINA_MODEL,
+            (None, None) => OFFLINE_MODEL,
+        }
+    }
+    
+    fn key(&self, text: &str, options: &EmbedOptions) -> ContentKey {
+        hash::content_key(self.model(), options, text)
+    }
+    
+    /// Report the hit rate over the last `window` (default `cache::DEFAULT_HIT_WINDOW`)
+    pub fn with_hit_window(mut self, window: Duration) -> Self {
+        self.window = HitWindow::new(window);
+        self
+    }
+    
     pub fn with_persistence(mut self, path: &str) -> Self {
         self.cache_path = Some(path.to_string());
         self.load_from_disk();
@@ -156,34 +231,39 @@ impl JinaCache {
         self.stats.total_lookups += 1;
         
         // 1. Exact match
-        if let Some(entry) = self.exact.get(text) {
+        let key = self.key(text, &EmbedOptions::default());
+        if let Some(entry) = self.exact.get(&key) {
             self.stats.exact_hits += 1;
+            self.window.record(1, 0);
             return Ok(entry.fingerprint.clone());
         }
         
         // 2. Near match (linear scan - could use ANN for large caches)
-        let query_lower = text.to_lowercase();
         for entry in &self.entries {
             // Quick string similarity check first
             if string_similar(&entry.text, text) {
                 self.stats.near_hits += 1;
+                self.window.record(1, 0);
                 return Ok(entry.fingerprint.clone());
             }
         }
         
         // 3. API call needed
         self.stats.api_calls += 1;
+        self.window.record(0, 1);
         let embedding = self.call_jina_api(text)?;
         let fingerprint = Fingerprint::from_jina_embedding(&embedding);
         
         // Cache it
         let entry = CacheEntry {
+            key,
             text: text.to_string(),
             fingerprint: fingerprint.clone(),
             jina_embedding: Some(embedding),
+            inserted_ms: unix_ms(),
         };
         
-        self.exact.insert(text.to_string(), entry.clone());
+        self.exact.insert(key, entry.clone());
         self.entries.push(entry);
         
         // Persist
@@ -203,7 +283,7 @@ impl JinaCache {
         for (i, text) in texts.iter().enumerate() {
             self.stats.total_lookups += 1;
             
-            if let Some(entry) = self.exact.get(*text) {
+            if let Some(entry) = self.exact.get(&self.key(text, &EmbedOptions::default())) {
                 self.stats.exact_hits += 1;
                 results.push((i, entry.fingerprint.clone()));
             } else {
@@ -223,22 +303,27 @@ impl JinaCache {
             }
         }
         
+        self.window.record((texts.len() - to_fetch.len()) as u64, to_fetch.len() as u64);
+        
         // Batch API call for misses
         if !to_fetch.is_empty() {
             let texts_to_fetch: Vec<&str> = to_fetch.iter().map(|(_, t)| *t).collect();
             let embeddings = self.call_jina_api_batch(&texts_to_fetch)?;
             
-            for ((i, text), embedding) in to_fetch.into_iter().zip(embeddings.into_iter()) {
+            for ((i, text), embedding) in to_fetch.into_iter().zip(embeddings) {
                 self.stats.api_calls += 1;
                 let fingerprint = Fingerprint::from_jina_embedding(&embedding);
                 
+                let key = self.key(text, &EmbedOptions::default());
                 let entry = CacheEntry {
+                    key,
                     text: text.to_string(),
                     fingerprint: fingerprint.clone(),
                     jina_embedding: Some(embedding),
+                    inserted_ms: unix_ms(),
                 };
                 
-                self.exact.insert(text.to_string(), entry.clone());
+                self.exact.insert(key, entry.clone());
                 self.entries.push(entry);
                 results.push((i, fingerprint));
             }
@@ -249,12 +334,62 @@ impl JinaCache {
         Ok(results.into_iter().map(|(_, fp)| fp).collect())
     }
     
+    /// Entries, their size and age, and the recent hit rate
+    pub fn cache_stats(&self) -> cache::CacheStats {
+        cache::CacheStats {
+            entries: self.exact.len(),
+            bytes: self.exact.values()
+                .map(|e| e.text.len() + N64 * 8 + e.jina_embedding.as_ref().map_or(0, |v| 4 * v.len()))
+                .sum(),
+            hit_rate_window: self.window.rate(),
+            oldest_entry_age: cache::entry_age(self.exact.values().map(|e| e.inserted_ms).min()),
+        }
+    }
+    
+    /// Embed with `provider` and store each text of `from` without an exact
+    /// entry; see `cache` for resuming. Saves once, at the end.
+    ///
+    /// Entries are keyed by their options, so only texts warmed with the
+    /// default options are exact hits for `get_fingerprint`.
+    pub fn warm(&mut self, from: impl Iterator<Item = (String, EmbedOptions)>, provider: &dyn EmbeddingProvider,
+                warmup: &Warmup) -> WarmReport {
+        let mut report = WarmReport::default();
+        let groups = warmup.missing(from, |text, options| self.exact.contains_key(&self.key(text, options)), &mut report);
+        let embedded: Mutex<Vec<(ContentKey, String, Vec<f32>)>> = Mutex::new(Vec::new());
+        warmup.run(&groups, &mut report, |texts, options| {
+            let refs: Vec<&str> = texts.iter().map(String::as_str).collect();
+            let vectors = provider.embed_batch_with(&refs, options)?;
+            if vectors.len() != texts.len() {
+                return Err(EmbedError::Mismatch { expected: texts.len(), got: vectors.len() });
+            }
+            embedded.lock().unwrap().extend(texts.iter().zip(vectors).map(|(text, v)| (self.key(text, options), text.clone(), v)));
+            Ok(texts.len())
+        });
+        
+        let now = unix_ms();
+        for (key, text, embedding) in embedded.into_inner().unwrap() {
+            let entry = CacheEntry {
+                key,
+                text,
+                fingerprint: Fingerprint::from_jina_embedding(&embedding),
+                jina_embedding: Some(embedding),
+                inserted_ms: now,
+            };
+            self.exact.insert(key, entry.clone());
+            self.entries.push(entry);
+        }
+        if report.embedded > 0 && self.cache_path.is_some() {
+            self.save_to_disk();
+        }
+        report
+    }
+    
     /// Find near matches in cache (for debugging/analysis)
     pub fn find_near_matches(&self, text: &str, threshold: f64) -> Vec<(String, f64)> {
         let mut matches = Vec::new();
         
         // Get fingerprint for query (without caching)
-        if let Some(entry) = self.exact.get(text) {
+        if let Some(entry) = self.exact.get(&self.key(text, &EmbedOptions::default())) {
             for other in &self.entries {
                 let sim = entry.fingerprint.similarity(&other.fingerprint);
                 if sim >= threshold && other.text != text {
@@ -263,51 +398,71 @@ impl JinaCache {
             }
         }
         
-        matches.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        matches.sort_by(|a, b| b.1.total_cmp(&a.1));
         matches
     }
     
     fn call_jina_api(&self, text: &str) -> Result<Vec<f32>, String> {
-        // Placeholder - implement actual API call
-        // For now, generate deterministic pseudo-embedding
-        Ok(pseudo_embedding(text))
+        match &self.provider {
+            Some(provider) => Ok(provider.embed(text)?),
+            // No backend configured: deterministic pseudo-embedding
+            None => Ok(pseudo_embedding(text)),
+        }
     }
     
     fn call_jina_api_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
-        // Placeholder - implement actual batch API call
-        Ok(texts.iter().map(|t| pseudo_embedding(t)).collect())
+        match &self.provider {
+            Some(provider) => Ok(provider.embed_batch(texts)?),
+            None => Ok(texts.iter().map(|t| pseudo_embedding(t)).collect()),
+        }
     }
     
+    /// Replace the cache file atomically; a failed save keeps the last one
     fn save_to_disk(&self) {
         if let Some(ref path) = self.cache_path {
-            if let Ok(file) = File::create(path) {
+            let _ = atomic_write(path, |file| {
                 let mut writer = BufWriter::new(file);
                 
-                // Simple format: count, then (text_len, text, fingerprint_bytes) for each
+                // Magic, version and count, then (key, text_len, text, fingerprint_bytes) for each
+                writer.write_all(MAGIC)?;
+                writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
                 let count = self.entries.len() as u32;
-                let _ = writer.write_all(&count.to_le_bytes());
+                writer.write_all(&count.to_le_bytes())?;
                 
                 for entry in &self.entries {
+                    writer.write_all(&entry.key)?;
                     let text_bytes = entry.text.as_bytes();
                     let text_len = text_bytes.len() as u32;
-                    let _ = writer.write_all(&text_len.to_le_bytes());
-                    let _ = writer.write_all(text_bytes);
-                    let _ = writer.write_all(&entry.fingerprint.to_bytes());
+                    writer.write_all(&text_len.to_le_bytes())?;
+                    writer.write_all(text_bytes)?;
+                    writer.write_all(&entry.fingerprint.to_bytes())?;
                 }
-            }
+                writer.flush()
+            });
         }
     }
     
     fn load_from_disk(&mut self) {
         if let Some(ref path) = self.cache_path {
             if let Ok(file) = File::open(path) {
+                let saved_ms = file.metadata().and_then(|m| m.modified()).ok()
+                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map_or_else(unix_ms, |d| d.as_millis() as u64);
                 let mut reader = BufReader::new(file);
                 
+                // Older or unknown formats are a miss; the next save replaces them
+                let mut header = [0u8; 8];
+                if reader.read_exact(&mut header).is_err() { return; }
+                if &header[..6] != MAGIC || u16::from_le_bytes([header[6], header[7]]) != FORMAT_VERSION { return; }
+                
                 let mut count_bytes = [0u8; 4];
                 if reader.read_exact(&mut count_bytes).is_err() { return; }
                 let count = u32::from_le_bytes(count_bytes) as usize;
                 
                 for _ in 0..count {
+                    let mut key = [0u8; 32];
+                    if reader.read_exact(&mut key).is_err() { break; }
+                    
                     let mut len_bytes = [0u8; 4];
                     if reader.read_exact(&mut len_bytes).is_err() { break; }
                     let text_len = u32::from_le_bytes(len_bytes) as usize;
@@ -321,11 +476,13 @@ impl JinaCache {
                     
                     if let Some(fingerprint) = Fingerprint::from_bytes(&fp_bytes) {
                         let entry = CacheEntry {
-                            text: text.clone(),
+                            key,
+                            text,
                             fingerprint,
                             jina_embedding: None,
+                            inserted_ms: saved_ms,
                         };
-                        self.exact.insert(text, entry.clone());
+                        self.exact.insert(key, entry.clone());
                         self.entries.push(entry);
                     }
                 }
@@ -335,6 +492,8 @@ impl JinaCache {
     
     pub fn len(&self) -> usize { self.entries.len() }
     
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+    
     pub fn print_stats(&self) {
         println!("JinaCache Statistics:");
         println!("  Entries:      {}", self.entries.len());
@@ -363,9 +522,11 @@ fn string_similar(a: &str, b: &str) -> bool {
     // One is prefix/suffix of other
     if a_lower.starts_with(&b_lower) || b_lower.starts_with(&a_lower) { return true; }
     
-    // Levenshtein distance <= 2 for short strings
+    // Levenshtein distance <= 2 for short strings (<= 1 below 6 chars,
+    // otherwise any two 3-letter words would match)
     if a.len() <= 10 && b.len() <= 10 {
-        if levenshtein(&a_lower, &b_lower) <= 2 { return true; }
+        let max_edits = if a.len().min(b.len()) < 6 { 1 } else { 2 };
+        if levenshtein(&a_lower, &b_lower) <= max_edits { return true; }
     }
     
     false
@@ -377,8 +538,8 @@ fn levenshtein(a: &str, b: &str) -> usize {
     
     let mut dp = vec![vec![0usize; b.len() + 1]; a.len() + 1];
     
-    for i in 0..=a.len() { dp[i][0] = i; }
-    for j in 0..=b.len() { dp[0][j] = j; }
+    for (i, row) in dp.iter_mut().enumerate() { row[0] = i; }
+    for (j, cell) in dp[0].iter_mut().enumerate() { *cell = j; }
     
     for i in 1..=a.len() {
         for j in 1..=b.len() {
@@ -394,32 +555,7 @@ fn levenshtein(a: &str, b: &str) -> usize {
 
 /// Pseudo-embedding for testing (replace with actual Jina API call)
 fn pseudo_embedding(text: &str) -> Vec<f32> {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
-    
-    let mut embedding = vec![0.0f32; 1024];
-    
-    // Deterministic pseudo-random based on text
-    for (i, chunk) in text.as_bytes().chunks(4).enumerate() {
-        let mut hasher = DefaultHasher::new();
-        chunk.hash(&mut hasher);
-        i.hash(&mut hasher);
-        let h = hasher.finish();
-        
-        for j in 0..8 {
-            let idx = (i * 8 + j) % 1024;
-            let val = ((h >> (j * 8)) & 0xFF) as f32 / 255.0 - 0.5;
-            embedding[idx] += val;
-        }
-    }
-    
-    // Normalize
-    let norm: f32 = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
-    if norm > 0.0 {
-        for x in &mut embedding { *x /= norm; }
-    }
-    
-    embedding
+    PseudoEmbedder::new(1024).embed(text)
 }
 
 #[cfg(test)]
@@ -463,4 +599,99 @@ mod tests {
         assert_eq!(cache.stats.near_hits, 1);
         assert_eq!(cache.stats.api_calls, 1);  // Only one API call
     }
-}
+    
+    #[test]
+    fn test_cache_uses_provider_for_misses() {
+        use crate::mock::MockProvider;
+        use std::sync::Arc;
+        
+        let ada = crate::pseudo::PseudoEmbedder::new(256).embed("Ada");
+        let provider = Arc::new(MockProvider::new(256).with_vector("Ada", ada.clone()).with_default(vec![0.5; 256]));
+        let mut cache = JinaCache::new("test_key").with_provider(provider.clone());
+        
+        let fps = cache.get_fingerprints_batch(&["Ada", "Jan"]).unwrap();
+        cache.get_fingerprint("Jan").unwrap();
+        assert_eq!(provider.calls(), vec![vec!["Ada", "Jan"]]);
+        assert_eq!(cache.stats.api_calls, 2);
+        assert_eq!(fps[0].data, Fingerprint::from_jina_embedding(&ada).data);
+    }
+    
+    #[test]
+    fn test_torn_cache_file_keeps_whole_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fingerprints.bin").to_string_lossy().to_string();
+        let mut cache = JinaCache::new("test_key").with_persistence(&path);
+        for text in ["Ada", "Babbage", "Lovelace"] {
+            cache.get_fingerprint(text).unwrap();
+        }
+        let full = std::fs::read(&path).unwrap();
+        let entry = |text: &str| 32 + 4 + text.len() + N64 * 8;
+        let header = MAGIC.len() + 2 + 4;
+        assert_eq!(full.len(), header + entry("Ada") + entry("Babbage") + entry("Lovelace"));
+        
+        // Saves replace the file whole; a file cut anywhere still loads its complete entries
+        // and its next save keeps them along with the new entry
+        for cut in (0..full.len()).step_by(7) {
+            std::fs::write(&path, &full[..cut]).unwrap();
+            let complete = [header + entry("Ada"), header + entry("Ada") + entry("Babbage")].iter().filter(|&&end| cut >= end).count();
+            let mut reopened = JinaCache::new("test_key").with_persistence(&path);
+            assert_eq!(reopened.len(), complete, "cut at {}", cut);
+            reopened.get_fingerprint("Jan").unwrap();
+            assert_eq!(JinaCache::new("test_key").with_persistence(&path).len(), complete + 1, "saved after cut at {}", cut);
+        }
+        let names: Vec<_> = std::fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(names, ["fingerprints.bin"]);
+    }
+    
+    #[test]
+    fn test_old_format_and_other_model_miss() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fingerprints.bin").to_string_lossy().to_string();
+        
+        // Version 1 had no header: count, then (text_len, text, fingerprint)
+        let mut old = 1u32.to_le_bytes().to_vec();
+        old.extend_from_slice(&3u32.to_le_bytes());
+        old.extend_from_slice(b"Ada");
+        old.extend_from_slice(&Fingerprint::zero().to_bytes());
+        std::fs::write(&path, &old).unwrap();
+        let mut cache = JinaCache::new("test_key").with_persistence(&path);
+        assert!(cache.is_empty());
+        cache.get_fingerprint("Ada").unwrap();
+        assert_eq!(cache.stats.api_calls, 1);
+        assert!(std::fs::read(&path).unwrap().starts_with(MAGIC));
+        
+        let mut reopened = JinaCache::new("test_key").with_persistence(&path);
+        reopened.get_fingerprint("Ada").unwrap();
+        assert_eq!(reopened.stats.exact_hits, 1);
+        
+        // The same text under another model has no exact entry
+        let provider = crate::mock::MockProvider::new(8).with_default(vec![0.25; 8]);
+        let mut other = JinaCache::new("test_key").with_model("pseudo-2").with_persistence(&path);
+        let report = other.warm(std::iter::once(("Ada".to_string(), EmbedOptions::default())), &provider, &Warmup::default());
+        assert_eq!((report.already_cached, report.embedded, other.len()), (0, 1, 2));
+    }
+    
+    #[test]
+    fn test_warm_leaves_no_misses() {
+        use crate::mock::MockProvider;
+        
+        let provider = MockProvider::new(8).with_default(vec![0.25; 8]);
+        let mut cache = JinaCache::new("test_key");
+        cache.get_fingerprint("Ada").unwrap();
+        let keys = ["Ada", "Jan", "loves", "creates", "Jan", " "].map(|t| (t.to_string(), EmbedOptions::default()));
+        let report = cache.warm(keys.into_iter(), &provider, &Warmup::default().with_batch_size(2).with_concurrency(2));
+        assert_eq!(report, WarmReport { keys: 4, already_cached: 1, embedded: 3, failed: 0, error: None });
+        assert_eq!(provider.calls().concat().len(), 3);
+        
+        let before = cache.stats.api_calls;
+        cache.get_fingerprints_batch(&["Ada", "Jan", "loves", "creates"]).unwrap();
+        assert_eq!(cache.stats.api_calls, before);
+        let stats = cache.cache_stats();
+        assert_eq!(stats.entries, 4);
+        assert!(stats.bytes >= 4 * N64 * 8 + 3 * 8 * 4);
+        // One miss for "Ada" then four hits; warmup lookups are not counted
+        assert_eq!(stats.hit_rate_window, Some(0.8));
+        assert!(stats.oldest_entry_age.is_some());
+        assert_eq!(JinaCache::new("test_key").cache_stats().oldest_entry_age, None);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,229 @@
+//! Coarse language detection from scripts and common words
+//!
+//! `detect` first counts letters per script. Kana, Hangul, Cyrillic, Greek,
+//! Arabic, Hebrew, Devanagari and Thai each decide the language outright;
+//! Han without kana is taken as Chinese. Latin text is scored against
+//! short profiles of frequent words and telltale letters for English,
+//! German, French, Spanish, Italian, Portuguese and Dutch.
+//!
+//! Accuracy is coarse: a full sentence in one of those languages is
+//! usually right, a few words often are not, closely related languages
+//! (Spanish and Portuguese, Dutch and German) are confused on short input,
+//! and Cyrillic is always `ru`. `confidence` says how much to trust a guess;
+//! texts with no letters are `UNDETERMINED` at 0.
+//!
+//! `Route::detected_language` routes on the guess, and `pick` chooses a
+//! per-language value such as a `Pipeline` or a chunk size.
+
+/// BCP 47 tag for text whose language is unknown
+pub const UNDETERMINED: &str = "und";
+
+/// A language tag such as `"de"` and how sure `detect` is, from 0 to 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LangGuess {
+    pub lang: &'static str,
+    pub confidence: f32,
+}
+
+impl LangGuess {
+    const NONE: LangGuess = LangGuess { lang: UNDETERMINED, confidence: 0.0 };
+    
+    /// Chinese, Japanese or Korean
+    pub fn is_cjk(&self) -> bool { matches!(self.lang, "zh" | "ja" | "ko") }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Script {
+    Latin,
+    Cyrillic,
+    Greek,
+    Arabic,
+    Hebrew,
+    Devanagari,
+    Thai,
+    Han,
+    Kana,
+    Hangul,
+}
+
+const SCRIPTS: [Script; 10] = [
+    Script::Latin, Script::Cyrillic, Script::Greek, Script::Arabic, Script::Hebrew,
+    Script::Devanagari, Script::Thai, Script::Han, Script::Kana, Script::Hangul,
+];
+
+impl Script {
+    fn of(c: char) -> Option<Script> {
+        Some(match c {
+            _ if c.is_ascii_alphabetic() => Script::Latin,
+            '\u{00c0}'..='\u{024f}' | '\u{1e00}'..='\u{1eff}' if c.is_alphabetic() => Script::Latin,
+            '\u{0370}'..='\u{03ff}' | '\u{1f00}'..='\u{1fff}' => Script::Greek,
+            '\u{0400}'..='\u{052f}' => Script::Cyrillic,
+            '\u{0590}'..='\u{05ff}' => Script::Hebrew,
+            '\u{0600}'..='\u{06ff}' | '\u{0750}'..='\u{077f}' => Script::Arabic,
+            '\u{0900}'..='\u{097f}' => Script::Devanagari,
+            '\u{0e00}'..='\u{0e7f}' => Script::Thai,
+            '\u{3040}'..='\u{30ff}' | '\u{31f0}'..='\u{31ff}' | '\u{ff66}'..='\u{ff9f}' => Script::Kana,
+            '\u{1100}'..='\u{11ff}' | '\u{3130}'..='\u{318f}' | '\u{ac00}'..='\u{d7af}' => Script::Hangul,
+            '\u{3400}'..='\u{9fff}' | '\u{f900}'..='\u{faff}' | '\u{20000}'..='\u{2fa1f}' => Script::Han,
+            _ => return None,
+        })
+    }
+}
+
+/// Frequent words per Latin-script language
+const PROFILES: &[(&str, &[&str])] = &[
+    ("en", &["the", "and", "of", "to", "is", "in", "that", "it", "was", "for", "with", "on", "are", "this", "be",
+             "by", "not", "you", "have", "from", "at", "but", "they", "his", "her", "which", "an", "or", "were",
+             "has", "we", "what", "there", "a"]),
+    ("de", &["der", "die", "das", "und", "ist", "nicht", "ein", "eine", "zu", "den", "von", "mit", "sich", "des",
+             "auf", "für", "im", "dem", "auch", "es", "als", "wird", "ich", "sie", "wir", "aber", "noch", "nach",
+             "bei", "werden", "war", "hat", "oder", "sind", "über"]),
+    ("fr", &["le", "la", "les", "et", "est", "des", "une", "un", "du", "que", "qui", "dans", "pour", "pas", "sur",
+             "au", "avec", "ce", "il", "elle", "sont", "à", "nous", "vous", "mais", "ou", "par", "plus", "été",
+             "cette", "je", "aux"]),
+    ("es", &["el", "la", "los", "las", "y", "es", "en", "de", "que", "un", "una", "por", "con", "para", "no",
+             "se", "del", "al", "lo", "como", "más", "pero", "su", "sus", "está", "son", "muy", "ya", "también",
+             "fue", "hay", "yo"]),
+    ("it", &["il", "lo", "la", "gli", "le", "e", "è", "di", "che", "un", "una", "per", "non", "con", "del",
+             "della", "dei", "sono", "si", "nel", "alla", "anche", "come", "più", "ma", "questo", "ha", "da",
+             "uno", "ci", "io", "sul", "ho", "mentre", "ancora", "molto", "tutto"]),
+    ("pt", &["o", "a", "os", "as", "e", "é", "de", "do", "da", "que", "um", "uma", "para", "com", "não", "em",
+             "no", "na", "dos", "das", "se", "por", "mais", "mas", "foi", "são", "ao", "está", "também", "muito",
+             "você", "eu"]),
+    ("nl", &["de", "het", "een", "en", "is", "van", "dat", "niet", "in", "op", "te", "zijn", "met", "voor", "die",
+             "er", "maar", "ook", "als", "bij", "aan", "om", "wordt", "nog", "wat", "dan", "hij", "ze", "deze",
+             "was", "heeft", "ik"]),
+];
+
+/// Letters that point to some profiles, and how strongly
+const LETTERS: &[(char, &[&str], f32)] = &[
+    ('ß', &["de"], 2.0), ('ä', &["de"], 1.0), ('ö', &["de"], 1.0), ('ü', &["de"], 1.0),
+    ('ñ', &["es"], 2.0), ('¿', &["es"], 2.0), ('¡', &["es"], 2.0),
+    ('ã', &["pt"], 2.0), ('õ', &["pt"], 2.0),
+    ('œ', &["fr"], 2.0), ('ê', &["fr", "pt"], 0.5), ('è', &["fr", "it"], 0.5), ('ç', &["fr", "pt"], 0.5),
+    ('ì', &["it"], 1.0), ('ò', &["it"], 1.0),
+];
+
+/// Guess the language of `text`; never fails, whatever the input
+pub fn detect(text: &str) -> LangGuess {
+    let mut counts = [0usize; SCRIPTS.len()];
+    for c in text.chars() {
+        if let Some(script) = Script::of(c) {
+            counts[SCRIPTS.iter().position(|&s| s == script).unwrap()] += 1;
+        }
+    }
+    let letters: usize = counts.iter().sum();
+    if letters == 0 {
+        return LangGuess::NONE;
+    }
+    let count = |script: Script| counts[SCRIPTS.iter().position(|&s| s == script).unwrap()];
+    let share = |n: usize| n as f32 / letters as f32;
+    
+    // Japanese mixes Han with kana, so the two count together; any real
+    // share of kana makes it Japanese
+    let (kana, han) = (count(Script::Kana), count(Script::Han));
+    let (dominant, n) = SCRIPTS.iter().map(|&s| (s, count(s)))
+        .map(|(s, n)| if matches!(s, Script::Han | Script::Kana) { (s, kana + han) } else { (s, n) })
+        .max_by_key(|&(_, n)| n)
+        .unwrap();
+    let (lang, certainty) = match dominant {
+        Script::Han | Script::Kana if kana * 10 >= n => ("ja", 0.9),
+        Script::Han | Script::Kana => ("zh", 0.9),
+        Script::Latin => return latin(text, share(n)),
+        Script::Hangul => ("ko", 0.95),
+        Script::Greek => ("el", 0.95),
+        Script::Hebrew => ("he", 0.9),
+        Script::Thai => ("th", 0.95),
+        // Scripts shared by several languages
+        Script::Cyrillic => ("ru", 0.6),
+        Script::Arabic => ("ar", 0.6),
+        Script::Devanagari => ("hi", 0.7),
+    };
+    LangGuess { lang, confidence: certainty * share(n) }
+}
+
+/// Score Latin text against the word profiles and telltale letters
+fn latin(text: &str, share: f32) -> LangGuess {
+    let lower = text.to_lowercase();
+    let mut scores = [0f32; PROFILES.len()];
+    for word in lower.split(|c: char| !c.is_alphabetic()).filter(|w| !w.is_empty()) {
+        for (score, (_, words)) in scores.iter_mut().zip(PROFILES) {
+            if words.contains(&word) {
+                *score += 1.0;
+            }
+        }
+    }
+    for c in lower.chars() {
+        for &(_, langs, weight) in LETTERS.iter().filter(|(letter, ..)| *letter == c) {
+            for (score, (lang, _)) in scores.iter_mut().zip(PROFILES) {
+                if langs.contains(lang) {
+                    *score += weight;
+                }
+            }
+        }
+    }
+    
+    let mut ranked: Vec<(usize, f32)> = scores.iter().copied().enumerate().collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let (best, top) = ranked[0];
+    let second = ranked[1].1;
+    if top == 0.0 {
+        return LangGuess::NONE;
+    }
+    // A clear lead over the runner-up and enough evidence behind it
+    let margin = (top - second) / top;
+    let evidence = (top / 4.0).min(1.0);
+    LangGuess { lang: PROFILES[best].0, confidence: share * (0.5 + 0.5 * margin) * evidence }
+}
+
+/// The value paired with `text`'s detected language, or `default`
+///
+/// e.g. a `Pipeline` without lowercasing for `"de"`, or a smaller chunk
+/// size for CJK languages.
+pub fn pick<'a, T>(text: &str, choices: &'a [(&str, T)], default: &'a T) -> &'a T {
+    let lang = detect(text).lang;
+    choices.iter().find(|(l, _)| *l == lang).map_or(default, |(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_fixture_sentences_meet_accuracy_bar() {
+        let sentences: Vec<(&str, &str)> = include_str!("../fixtures/lang/sentences.tsv").lines()
+            .filter_map(|l| l.split_once('\t'))
+            .collect();
+        let languages: std::collections::BTreeSet<&str> = sentences.iter().map(|(lang, _)| *lang).collect();
+        assert!(languages.len() >= 8, "{:?}", languages);
+        let wrong: Vec<(&str, &str, LangGuess)> = sentences.iter()
+            .map(|&(lang, text)| (lang, text, detect(text)))
+            .filter(|(lang, _, guess)| guess.lang != *lang)
+            .collect();
+        // The documented bar: at least 90% of full sentences right
+        assert!(wrong.len() * 10 <= sentences.len(), "{} of {} wrong: {:?}", wrong.len(), sentences.len(), wrong);
+        for (_, text) in &sentences {
+            let guess = detect(text);
+            assert!(guess.confidence > 0.0 && guess.confidence <= 1.0, "{} {:?}", text, guess);
+        }
+    }
+    
+    #[test]
+    fn test_inputs_without_letters_are_undetermined() {
+        for text in ["", "   ", "12345 67.89", "🎉🚀😀", "!!! ??? ...", "👍🏽 2024", "\u{200b}\u{feff}"] {
+            assert_eq!(detect(text), LangGuess::NONE, "{:?}", text);
+        }
+        // Letters the profiles do not know are a guess of nothing, not a panic
+        assert_eq!(detect("xyzzy qwrtp").lang, UNDETERMINED);
+        assert!(detect("東京は日本の首都です").is_cjk());
+        // Mixed scripts never panic
+        for mixed in ["abc 東京", "東京 abc", "Москва abc", "x1 ☃ 우"] {
+            let guess = detect(mixed);
+            assert!((0.0..1.0).contains(&guess.confidence), "{} {:?}", mixed, guess);
+        }
+        
+        let sizes = [("ja", 200), ("zh", 200)];
+        assert_eq!(*pick("これは日本語の文です", &sizes, &1000), 200);
+        assert_eq!(*pick("This is an English sentence.", &sizes, &1000), 1000);
+    }
+}
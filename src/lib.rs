@@ -0,0 +1,161 @@
+//! SPO Crystal library
+//!
+//! - `provider`: `EmbeddingProvider` trait over embedding backends
+//! - `error`: typed backend errors
+//! - `jina_api`: Jina embedding client (curl shell-out + offline pseudo-embeddings)
+//! - `jina_cache`: fingerprint cache with sparse API usage
+//! - `ffi`: C ABI for embedding and cosine similarity (`ffi` feature)
+//! - `id_map`: string keys for index entries, mapped to dense ids
+//! - `index`: persisted vector index with incremental updates
+//! - `io`: Qdrant and pgvector exports, Parquet files (`arrow` feature) of embedding records
+//! - `adapt`: ridge-fitted `LinearAdapter` maps between embedding spaces
+//! - `align`: matching records between two corpora by embedding similarity
+//! - `audit`: JSONL audit log of requests, with text hashes only
+//! - `bundle`: export and import of a workspace's artifacts as one archive
+//! - `cache`: `CacheStats` with sliding-window hit rates, and cache warmup
+//! - `async_client`: `AsyncJinaClient` over host-supplied async transports (`fetch` on wasm32)
+//! - `async_stream`: back-pressured `AsyncJinaClient::embed_stream` over async `Stream`s
+//! - `chunk`: local chunker and chunking strategies
+//! - `clip`: multimodal `Input` and jina-clip embeddings
+//! - `coalesce`: single-flight `SingleFlight` sharing one result between identical concurrent calls
+//! - `code`: `CodeSnippet` embeddings with the jina code models
+//! - `document`: chunk-embed-pool `embed_document` and weighted multi-field `embed_fields`
+//! - `drift`: neighborhood and vector drift between two providers
+//! - `explain`: per-dimension cosine terms and shared pseudo-embedder features
+//! - `eval`: recall@k, MRR and nDCG@k of search closures against labeled queries; `eval::bench` index benchmarks
+//! - `dedup`: near-duplicate cluster reports and their approved removal
+//! - `embeddings`: f32/f64 embedding matrices and their binary container
+//! - `fusion`: Reciprocal Rank Fusion of ranked result lists
+//! - `global`: lazily built process-wide client behind `spo_crystal::embed`
+//! - `hash`: SHA-256 content keys for caches and ids, hex and base58
+//! - `classify`: Jina classification endpoint
+//! - `cohere`: Cohere embed API backend
+//! - `config`: `JinaClient::from_config_file` TOML configs and their runtime `reload` (`config` feature)
+//! - `mock`: scripted `MockProvider` and virtual `ManualClock` for tests (`test-util` feature)
+//! - `ops`: weighted sums, analogies and Rocchio expansion of embeddings
+//! - `openai`: OpenAI-compatible embeddings backend
+//! - `ollama`: local Ollama embeddings backend
+//! - `offline_queue`: durable queue of embeds failed in an outage, replayed later
+//! - `tei`: Hugging Face Text Embeddings Inference backend
+//! - `transport`: HTTP transports, retries and status mapping
+//! - `lang`: coarse language detection from scripts and common words
+//! - `metadata`: typed metadata for filtered index search
+//! - `migrate`: `CrystalIndex::reembed` for model migrations, resumable from a checkpoint
+//! - `pipeline`: `embed_files` over directory trees and their incremental `sync_directory`
+//! - `postprocess`: renormalization, truncation and int8 rounding of response batches
+//! - `prelude`: versioned re-exports of the canonical client, provider, index and error types
+//! - `preprocess`: HTML stripping, text normalization and learned boilerplate removal pipelines
+//! - `probe`: backend capability discovery and client-side option checks
+//! - `provenance`: model/option fingerprints checked by the index
+//! - `pseudo`: deterministic, seedable offline embedder
+//! - `quantize`: int8 scalar quantization
+//! - `query_cache`: TTL and LRU bounded search-result cache of a `CrystalIndex`
+//! - `relations`: per-predicate offsets and object prediction
+//! - `reader`: Jina Reader URL fetching and `embed_url`
+//! - `replay`: record/replay transports over fixture files
+//! - `response_cache`: `TransportCache`, a TTL and LRU cache of whole responses
+//! - `reduce`: seeded Gaussian and sparse random projection to fewer dimensions
+//! - `rerank`: Jina reranker endpoint
+//! - `routing`: provider routing texts to backends by length or language
+//! - `shard`: `ShardedIndex` over shard files, lazily loaded and searched in parallel
+//! - `shared_index`: snapshot-isolated index for concurrent search during writes
+//! - `self_test`: startup connectivity self-test with a serializable report
+//! - `schema`: typed `MetadataSchema` validation of index metadata
+//! - `sample`: deterministic seeded reservoir, stratified and k-means++ sampling
+//! - `segment`: Jina segmenter endpoint
+//! - `sparse`: sparse lexical vectors, their scorers and hybrid scores
+//! - `triple_store`: similarity-searchable `TripleStore` of facts
+//! - `triples`: subject–predicate–object facts and `embed_triple`
+//! - `tokens`: token estimation and token-budgeted batch packing
+//! - `stream`: lazy, batch-bounded `JinaClient::embed_stream` over text iterators
+//! - `search`: brute-force cosine search and one-call semantic search
+//! - `viz`: 2D projections of embeddings as SVG/HTML scatter plots
+//! - `worker`: background `EmbeddingWorker` batching jobs from a channel
+
+pub mod adapt;
+pub mod align;
+pub mod async_client;
+pub mod async_stream;
+pub mod audit;
+pub mod bundle;
+pub mod cache;
+pub mod chunk;
+pub mod classify;
+pub mod clip;
+pub mod coalesce;
+pub mod code;
+pub mod cohere;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod dedup;
+pub mod document;
+pub mod drift;
+pub mod embeddings;
+pub mod error;
+pub mod eval;
+pub mod explain;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fusion;
+pub mod global;
+pub mod hash;
+pub mod id_map;
+pub mod index;
+pub mod io;
+pub mod jina_api;
+pub mod jina_cache;
+pub mod lang;
+pub mod metadata;
+pub mod migrate;
+#[cfg(any(test, feature = "test-util"))]
+pub mod mock;
+pub mod offline_queue;
+pub mod ollama;
+pub mod openai;
+pub mod ops;
+pub mod pipeline;
+pub mod postprocess;
+pub mod prelude;
+pub mod preprocess;
+pub mod probe;
+pub mod provenance;
+pub mod provider;
+pub mod pseudo;
+pub mod quantize;
+pub mod query_cache;
+pub mod reader;
+pub mod reduce;
+pub mod relations;
+pub mod replay;
+pub mod response_cache;
+pub mod rerank;
+pub mod routing;
+pub mod sample;
+pub mod schema;
+pub mod search;
+pub mod segment;
+pub mod self_test;
+pub mod shard;
+pub mod shared_index;
+pub mod sparse;
+pub mod stream;
+pub mod tei;
+pub mod tokens;
+pub mod transport;
+pub mod triple_store;
+pub mod triples;
+pub mod viz;
+pub mod worker;
+
+pub use global::{embed, embed_batch};
+
+/// Property-test settings: bounded cases, fixed seed, no regression files
+#[cfg(test)]
+pub(crate) fn proptest_config(cases: u32) -> proptest::test_runner::Config {
+    proptest::test_runner::Config {
+        cases,
+        rng_seed: proptest::test_runner::RngSeed::Fixed(0x5EED),
+        failure_persistence: None,
+        ..Default::default()
+    }
+}
@@ -0,0 +1,299 @@
+//! Typed metadata attached to index entries
+//!
+//! A small ordered map of string/number/bool values, so search filters
+//! can compare fields directly instead of parsing JSON per candidate.
+//!
+//! The binary encoding has u16 field counts and key lengths and u32 string
+//! lengths; `insert` refuses fields past them, and `to_bytes` fails on any
+//! (deserialized) metadata that holds one, rather than wrapping.
+
+use std::collections::BTreeMap;
+
+const TAG_STR: u8 = 0;
+const TAG_NUM: u8 = 1;
+const TAG_BOOL: u8 = 2;
+
+/// Longest key, in bytes
+pub const MAX_KEY_LEN: usize = u16::MAX as usize;
+/// Most fields
+pub const MAX_FIELDS: usize = u16::MAX as usize;
+/// Longest string value, in bytes
+pub const MAX_STR_LEN: usize = u32::MAX as usize;
+
+/// Serializes as the bare JSON string, number or bool
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum MetaValue {
+    Str(String),
+    Num(f64),
+    Bool(bool),
+}
+
+impl From<&str> for MetaValue {
+    fn from(s: &str) -> Self { MetaValue::Str(s.to_string()) }
+}
+
+impl From<String> for MetaValue {
+    fn from(s: String) -> Self { MetaValue::Str(s) }
+}
+
+impl From<f64> for MetaValue {
+    fn from(n: f64) -> Self { MetaValue::Num(n) }
+}
+
+impl From<i64> for MetaValue {
+    fn from(n: i64) -> Self { MetaValue::Num(n as f64) }
+}
+
+impl From<bool> for MetaValue {
+    fn from(b: bool) -> Self { MetaValue::Bool(b) }
+}
+
+/// Serializes as a JSON object of its fields, like `to_json`
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct Metadata {
+    fields: BTreeMap<String, MetaValue>,
+}
+
+impl Metadata {
+    pub fn new() -> Self { Self::default() }
+    
+    /// Builder-style insert: `Metadata::new().with("lang", "de").with("year", 2021)`;
+    /// panics where `insert` fails
+    pub fn with(mut self, key: &str, value: impl Into<MetaValue>) -> Self {
+        if let Err(e) = self.insert(key, value) {
+            panic!("{}", e);
+        }
+        self
+    }
+    
+    /// Set `key`; fails past `MAX_KEY_LEN`, `MAX_STR_LEN` or `MAX_FIELDS`
+    pub fn insert(&mut self, key: &str, value: impl Into<MetaValue>) -> Result<(), String> {
+        let value = value.into();
+        check_field(key, &value)?;
+        if self.fields.len() >= MAX_FIELDS && !self.fields.contains_key(key) {
+            return Err(format!("Metadata holds at most {} fields", MAX_FIELDS));
+        }
+        self.fields.insert(key.to_string(), value);
+        Ok(())
+    }
+    
+    /// `insert` without the limits; `to_bytes` refuses what does not fit
+    pub(crate) fn set(&mut self, key: &str, value: MetaValue) {
+        self.fields.insert(key.to_string(), value);
+    }
+    
+    pub fn remove(&mut self, key: &str) -> Option<MetaValue> { self.fields.remove(key) }
+    
+    pub fn get(&self, key: &str) -> Option<&MetaValue> { self.fields.get(key) }
+    
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        match self.fields.get(key) {
+            Some(MetaValue::Str(s)) => Some(s),
+            _ => None,
+        }
+    }
+    
+    pub fn get_num(&self, key: &str) -> Option<f64> {
+        match self.fields.get(key) {
+            Some(MetaValue::Num(n)) => Some(*n),
+            _ => None,
+        }
+    }
+    
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.fields.get(key) {
+            Some(MetaValue::Bool(b)) => Some(*b),
+            _ => None,
+        }
+    }
+    
+    pub fn len(&self) -> usize { self.fields.len() }
+    
+    pub fn is_empty(&self) -> bool { self.fields.is_empty() }
+    
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &MetaValue)> {
+        self.fields.iter().map(|(k, v)| (k.as_str(), v))
+    }
+    
+    /// Serialize: u16 count, then (u16 key_len, key, u8 tag, value) per field
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let count = u16::try_from(self.fields.len())
+            .map_err(|_| format!("Metadata has {} fields, at most {} fit", self.fields.len(), MAX_FIELDS))?;
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        bytes.extend_from_slice(&count.to_le_bytes());
+        for (key, value) in &self.fields {
+            check_field(key, value)?;
+            bytes.extend_from_slice(&(key.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(key.as_bytes());
+            match value {
+                MetaValue::Str(s) => {
+                    bytes.push(TAG_STR);
+                    bytes.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                    bytes.extend_from_slice(s.as_bytes());
+                }
+                MetaValue::Num(n) => {
+                    bytes.push(TAG_NUM);
+                    bytes.extend_from_slice(&n.to_le_bytes());
+                }
+                MetaValue::Bool(b) => {
+                    bytes.push(TAG_BOOL);
+                    bytes.push(*b as u8);
+                }
+            }
+        }
+        Ok(bytes)
+    }
+    
+    /// Length of `to_bytes`
+    pub fn encoded_len(&self) -> usize {
+        2 + self.fields.iter().map(|(key, value)| 3 + key.len() + match value {
+            MetaValue::Str(s) => 4 + s.len(),
+            MetaValue::Num(_) => 8,
+            MetaValue::Bool(_) => 1,
+        }).sum::<usize>()
+    }
+    
+    /// A JSON object of the fields; non-finite numbers become `null`
+    pub fn to_json(&self) -> String {
+        serde_json::Value::Object(self.to_json_map()).to_string()
+    }
+    
+    pub(crate) fn to_json_map(&self) -> serde_json::Map<String, serde_json::Value> {
+        self.fields.iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    MetaValue::Str(s) => serde_json::Value::from(s.as_str()),
+                    MetaValue::Num(n) => serde_json::Value::from(*n),
+                    MetaValue::Bool(b) => serde_json::Value::from(*b),
+                };
+                (key.clone(), value)
+            })
+            .collect()
+    }
+    
+    /// Parse a JSON object of string, number and bool fields
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let value: serde_json::Value = serde_json::from_str(json).map_err(|e| format!("Invalid metadata JSON: {}", e))?;
+        let serde_json::Value::Object(fields) = value else {
+            return Err("Metadata JSON is not an object".to_string());
+        };
+        let mut meta = Metadata::new();
+        for (key, value) in fields {
+            let value = match value {
+                serde_json::Value::String(s) => MetaValue::Str(s),
+                serde_json::Value::Number(n) => MetaValue::Num(n.as_f64().unwrap_or(f64::NAN)),
+                serde_json::Value::Bool(b) => MetaValue::Bool(b),
+                other => return Err(format!("Metadata field {} is not a string, number or bool: {}", key, other)),
+            };
+            meta.insert(&key, value)?;
+        }
+        Ok(meta)
+    }
+    
+    /// Deserialize from the start of `bytes`; returns the metadata and bytes consumed
+    pub fn from_bytes(bytes: &[u8]) -> Option<(Self, usize)> {
+        let mut pos = 0;
+        let count = u16::from_le_bytes(take(bytes, &mut pos, 2)?.try_into().ok()?) as usize;
+        let mut meta = Metadata::new();
+        
+        for _ in 0..count {
+            let key_len = u16::from_le_bytes(take(bytes, &mut pos, 2)?.try_into().ok()?) as usize;
+            let key = std::str::from_utf8(take(bytes, &mut pos, key_len)?).ok()?.to_string();
+            let value = match take(bytes, &mut pos, 1)?[0] {
+                TAG_STR => {
+                    let len = u32::from_le_bytes(take(bytes, &mut pos, 4)?.try_into().ok()?) as usize;
+                    MetaValue::Str(std::str::from_utf8(take(bytes, &mut pos, len)?).ok()?.to_string())
+                }
+                TAG_NUM => MetaValue::Num(f64::from_le_bytes(take(bytes, &mut pos, 8)?.try_into().ok()?)),
+                TAG_BOOL => MetaValue::Bool(take(bytes, &mut pos, 1)?[0] != 0),
+                _ => return None,
+            };
+            meta.fields.insert(key, value);
+        }
+        
+        Some((meta, pos))
+    }
+}
+
+/// Whether `key` and `value` fit the binary encoding
+fn check_field(key: &str, value: &MetaValue) -> Result<(), String> {
+    if key.len() > MAX_KEY_LEN {
+        return Err(format!("Metadata key of {} bytes is over the {} byte limit", key.len(), MAX_KEY_LEN));
+    }
+    match value {
+        MetaValue::Str(s) if s.len() > MAX_STR_LEN => {
+            Err(format!("Metadata value of {} is {} bytes, over the {} byte limit", key, s.len(), MAX_STR_LEN))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn take<'a>(bytes: &'a [u8], pos: &mut usize, n: usize) -> Option<&'a [u8]> {
+    let slice = bytes.get(*pos..*pos + n)?;
+    *pos += n;
+    Some(slice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_typed_access_and_bytes_roundtrip() {
+        let meta = Metadata::new()
+            .with("lang", "de")
+            .with("year", 2021)
+            .with("draft", false)
+            .with("title", "Grüße");
+        
+        assert_eq!(meta.get_str("lang"), Some("de"));
+        assert_eq!(meta.get_num("year"), Some(2021.0));
+        assert_eq!(meta.get_bool("draft"), Some(false));
+        assert_eq!(meta.get_num("lang"), None);
+        
+        let mut bytes = meta.to_bytes().unwrap();
+        assert_eq!(bytes.len(), meta.encoded_len());
+        bytes.extend_from_slice(b"trailing");
+        let (decoded, used) = Metadata::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, meta);
+        assert_eq!(&bytes[used..], b"trailing");
+        
+        assert!(Metadata::from_bytes(&bytes[..used - 1]).is_none());
+    }
+    
+    #[test]
+    fn test_json_roundtrip() {
+        let meta = Metadata::new().with("lang", "de").with("year", 2021).with("draft", false).with("title", "\"Grüße\"");
+        assert_eq!(meta.to_json(), r#"{"draft":false,"lang":"de","title":"\"Grüße\"","year":2021.0}"#);
+        assert_eq!(Metadata::from_json(&meta.to_json()).unwrap(), meta);
+        assert_eq!(Metadata::from_json(r#"{"year":2021}"#).unwrap().get_num("year"), Some(2021.0));
+        assert_eq!(Metadata::from_json("{}").unwrap(), Metadata::new());
+        assert!(Metadata::from_json("[1]").unwrap_err().contains("not an object"));
+        assert!(Metadata::from_json(r#"{"tags":["a"]}"#).unwrap_err().contains("tags"));
+    }
+    
+    #[test]
+    fn test_lengths_at_the_encoding_limits() {
+        let longest = "k".repeat(MAX_KEY_LEN);
+        let meta = Metadata::new().with(&longest, "v");
+        let bytes = meta.to_bytes().unwrap();
+        assert_eq!(Metadata::from_bytes(&bytes).unwrap(), (meta, bytes.len()));
+        
+        let over = "k".repeat(MAX_KEY_LEN + 1);
+        let mut meta = Metadata::new();
+        assert!(meta.insert(&over, "v").unwrap_err().contains("65536 bytes"));
+        assert!(Metadata::from_json(&format!(r#"{{"{}":1}}"#, over)).is_err());
+        // Deserialized past the checks, it fails to encode rather than wrapping
+        let parsed: Metadata = serde_json::from_str(&format!(r#"{{"{}":1}}"#, over)).unwrap();
+        assert!(parsed.to_bytes().is_err());
+        
+        for i in 0..MAX_FIELDS {
+            meta.insert(&i.to_string(), true).unwrap();
+        }
+        assert!(meta.insert("one more", true).is_err());
+        meta.insert("0", false).unwrap();
+        assert_eq!(Metadata::from_bytes(&meta.to_bytes().unwrap()).unwrap().0.len(), MAX_FIELDS);
+    }
+}
@@ -0,0 +1,240 @@
+//! Re-embedding an index under a new model
+//!
+//! `CrystalIndex::reembed` embeds every live entry again, builds the new
+//! index beside the old one and swaps it in only once every entry is done,
+//! keeping ids, metadata and sparse vectors. The index stores no texts, so
+//! the caller looks each one up from its id and metadata.
+//!
+//! With a `checkpoint_path` the new index is saved there as it grows (a
+//! snapshot, then an increment per round of batches). A failed or cancelled
+//! run leaves the original untouched and the checkpoint in place; running
+//! again with the same target provenance picks up where it stopped. The
+//! checkpoint is deleted after the swap. Save the migrated index with
+//! `save`: its provenance no longer matches the old file's.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::index::CrystalIndex;
+use crate::jina_api::EmbedOptions;
+use crate::metadata::Metadata;
+use crate::provenance::Provenance;
+use crate::provider::EmbeddingProvider;
+
+/// How `reembed` batches its requests
+#[derive(Clone, Debug)]
+pub struct ReembedConfig {
+    /// Texts per request
+    pub batch_size: usize,
+    /// Requests in flight at once; 1 sends them from the calling thread
+    pub parallelism: usize,
+    /// Where the new index is saved between rounds, to resume from
+    pub checkpoint_path: Option<String>,
+    /// Set to stop after the requests in flight
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Default for ReembedConfig {
+    fn default() -> Self { Self { batch_size: 64, parallelism: 1, checkpoint_path: None, cancel: None } }
+}
+
+/// What a completed `reembed` did
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReembedReport {
+    /// Entries embedded by this run
+    pub reembedded: usize,
+    /// Entries taken from the checkpoint
+    pub resumed: usize,
+}
+
+impl CrystalIndex {
+    /// Replace every vector with `provider`'s embedding of the entry's text
+    /// under `options`, recording `provenance` (whose dimensions the new
+    /// vectors must have). `text` gives each entry's text from its id and
+    /// metadata.
+    ///
+    /// On error or cancellation the index is unchanged.
+    pub fn reembed(&mut self, provider: &dyn EmbeddingProvider, options: &EmbedOptions, provenance: Provenance,
+                   text: impl Fn(u64, &Metadata) -> Option<String> + Sync, config: &ReembedConfig)
+                   -> Result<ReembedReport, String> {
+        let checkpoint = config.checkpoint_path.as_deref();
+        let mut fresh = match checkpoint.filter(|path| std::path::Path::new(path).exists()) {
+            Some(path) => {
+                let loaded = CrystalIndex::load(path)?;
+                if loaded.provenance() != Some(&provenance) {
+                    let recorded = loaded.provenance().map_or("no provenance".to_string(), |p| p.to_string());
+                    return Err(format!("Checkpoint {} records {}, not {}", path, recorded, provenance));
+                }
+                loaded
+            }
+            None => {
+                let mut fresh = CrystalIndex::new(provenance.dimensions)
+                    .with_quantization(self.quantization())
+                    .with_metric(self.metric())
+                    .with_provenance(provenance);
+                if let Some(schema) = self.schema() {
+                    fresh = fresh.with_schema(schema.clone());
+                }
+                if let Some(path) = checkpoint {
+                    fresh.save(path)?;
+                }
+                fresh
+            }
+        };
+        // Entries removed from the original since the checkpoint go too
+        let stale: Vec<u64> = fresh.ids().filter(|&id| !self.contains(id)).collect();
+        for id in stale {
+            fresh.remove(id);
+        }
+        let mut report = ReembedReport { reembedded: 0, resumed: fresh.len() };
+        
+        let todo: Vec<u64> = self.ids().filter(|&id| !fresh.contains(id)).collect();
+        let batches: Vec<&[u64]> = todo.chunks(config.batch_size.max(1)).collect();
+        for round in batches.chunks(config.parallelism.max(1)) {
+            if config.cancel.as_ref().is_some_and(|c| c.load(Ordering::Relaxed)) {
+                return Err(format!("Re-embedding cancelled after {} of {} entries",
+                                   report.resumed + report.reembedded, self.len()));
+            }
+            let embed = |ids: &[u64]| self.embed_entries(ids, provider, options, &text);
+            let vectors: Vec<Result<Vec<Vec<f32>>, String>> = if round.len() == 1 {
+                vec![embed(round[0])]
+            } else {
+                std::thread::scope(|scope| {
+                    let handles: Vec<_> = round.iter().map(|&ids| scope.spawn(move || embed(ids))).collect();
+                    handles.into_iter().map(|h| h.join().unwrap()).collect()
+                })
+            };
+            for (&ids, vectors) in round.iter().zip(vectors) {
+                for (&id, vector) in ids.iter().zip(vectors?) {
+                    let metadata = self.metadata(id).cloned().unwrap_or_default();
+                    match self.sparse(id) {
+                        Some(sparse) => fresh.add_with_sparse(id, &vector, sparse.clone(), metadata),
+                        None => fresh.add_with_metadata(id, &vector, metadata),
+                    }.map_err(|e| format!("Id {}: {}", id, e))?;
+                }
+                report.reembedded += ids.len();
+            }
+            if let Some(path) = checkpoint {
+                fresh.save_incremental(path)?;
+            }
+        }
+        
+        // Rows of stale entries
+        fresh.compact();
+        *self = fresh;
+        if let Some(path) = checkpoint {
+            let _ = std::fs::remove_file(path);
+        }
+        Ok(report)
+    }
+    
+    fn embed_entries(&self, ids: &[u64], provider: &dyn EmbeddingProvider, options: &EmbedOptions,
+                     text: &(impl Fn(u64, &Metadata) -> Option<String> + Sync)) -> Result<Vec<Vec<f32>>, String> {
+        let texts = ids.iter()
+            .map(|&id| text(id, self.metadata(id).unwrap()).ok_or_else(|| format!("No text for id {}", id)))
+            .collect::<Result<Vec<String>, String>>()?;
+        let refs: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let vectors = provider.embed_batch_with(&refs, options).map_err(|e| e.to_string())?;
+        if vectors.len() != ids.len() {
+            return Err(format!("Provider returned {} vectors for {} texts", vectors.len(), ids.len()));
+        }
+        Ok(vectors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pseudo::PseudoEmbedder;
+    use std::sync::atomic::AtomicUsize;
+    
+    const TEXTS: [&str; 7] = ["alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta"];
+    
+    fn temp_path(name: &str) -> String {
+        let dir = std::env::temp_dir().join("spo_crystal_migrate_tests");
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(name).to_string_lossy().to_string()
+    }
+    
+    /// Index of `TEXTS` under seed 1, ids 10, 20, ..., text in the metadata
+    fn old_index() -> CrystalIndex {
+        let old = PseudoEmbedder::new(16).with_seed(1);
+        let mut index = CrystalIndex::new(16).with_provenance(Provenance::new("pseudo-1", 16));
+        for (i, text) in TEXTS.iter().enumerate() {
+            let metadata = Metadata::new().with("text", *text).with("rank", i as f64);
+            index.add_with_metadata(10 * (i as u64 + 1), &old.embed(text), metadata).unwrap();
+        }
+        index
+    }
+    
+    fn stored_text(_: u64, metadata: &Metadata) -> Option<String> { metadata.get_str("text").map(str::to_string) }
+    
+    #[test]
+    fn test_migrates_between_seeded_embedders() {
+        let mut index = old_index();
+        index.remove(30);
+        let new = PseudoEmbedder::new(8).with_seed(2);
+        let target = Provenance::new("pseudo-2", 8).with_task("retrieval.passage");
+        let config = ReembedConfig { batch_size: 2, parallelism: 3, ..ReembedConfig::default() };
+        let report = index.reembed(&new, &EmbedOptions::default(), target.clone(), stored_text, &config).unwrap();
+        
+        assert_eq!(report, ReembedReport { reembedded: 6, resumed: 0 });
+        assert_eq!(index.ids().collect::<Vec<_>>(), [10, 20, 40, 50, 60, 70]);
+        assert_eq!(index.provenance().map(Provenance::fingerprint), Some(target.fingerprint()));
+        assert_eq!((index.dims(), index.tombstones()), (8, 0));
+        assert_eq!(index.get(40).unwrap(), new.embed("delta"));
+        assert_eq!(index.metadata(50).unwrap().get_num("rank"), Some(4.0));
+        assert_eq!(index.search(&new.embed("zeta"), 1)[0].0, 60);
+        
+        // A missing text or a wrong size fails without touching the index
+        let before = index.get(10).unwrap().to_vec();
+        let err = index.reembed(&new, &EmbedOptions::default(), target.clone(), |id, _| (id != 20).then(|| "x".to_string()), &config);
+        assert_eq!(err.unwrap_err(), "No text for id 20");
+        let err = index.reembed(&new, &EmbedOptions::default(), Provenance::new("pseudo-2", 4), stored_text, &config);
+        assert!(err.unwrap_err().contains("Dimension mismatch"));
+        assert_eq!((index.get(10).unwrap(), index.provenance()), (&before[..], Some(&target)));
+    }
+    
+    #[test]
+    fn test_cancelled_run_resumes_from_checkpoint() {
+        let path = temp_path("checkpoint.idx");
+        let _ = std::fs::remove_file(&path);
+        let mut index = old_index();
+        let original = index.get(70).unwrap().to_vec();
+        let new = PseudoEmbedder::new(8).with_seed(2);
+        let target = Provenance::new("pseudo-2", 8);
+        let cancel = Arc::new(AtomicBool::new(false));
+        let config = ReembedConfig { batch_size: 2, checkpoint_path: Some(path.clone()), cancel: Some(cancel.clone()), ..ReembedConfig::default() };
+        
+        // Cancelled during the second batch: the first two are checkpointed, the index is untouched
+        let lookups = AtomicUsize::new(0);
+        let cancelling = |id, metadata: &Metadata| {
+            if lookups.fetch_add(1, Ordering::Relaxed) == 2 {
+                cancel.store(true, Ordering::Relaxed);
+            }
+            stored_text(id, metadata)
+        };
+        let err = index.reembed(&new, &EmbedOptions::default(), target.clone(), cancelling, &config).unwrap_err();
+        assert_eq!(err, "Re-embedding cancelled after 4 of 7 entries");
+        assert_eq!((index.provenance().unwrap().model.as_str(), index.get(70).unwrap()), ("pseudo-1", &original[..]));
+        assert_eq!(CrystalIndex::load(&path).unwrap().len(), 4);
+        
+        // Another target does not resume from it
+        let other = index.reembed(&new, &EmbedOptions::default(), Provenance::new("pseudo-3", 8), stored_text, &config);
+        assert!(other.unwrap_err().contains("records pseudo-2"));
+        
+        cancel.store(false, Ordering::Relaxed);
+        let lookups = AtomicUsize::new(0);
+        let counted = |id, metadata: &Metadata| {
+            lookups.fetch_add(1, Ordering::Relaxed);
+            stored_text(id, metadata)
+        };
+        let report = index.reembed(&new, &EmbedOptions::default(), target.clone(), counted, &config).unwrap();
+        assert_eq!(report, ReembedReport { reembedded: 3, resumed: 4 });
+        assert_eq!(lookups.load(Ordering::Relaxed), 3);
+        assert_eq!(index.ids().collect::<Vec<_>>(), [10, 20, 30, 40, 50, 60, 70]);
+        assert_eq!(index.get(10).unwrap(), new.embed("alpha"));
+        assert_eq!(index.provenance(), Some(&target));
+        assert!(!std::path::Path::new(&path).exists());
+    }
+}
@@ -0,0 +1,145 @@
+//! Scripted embedding provider for tests (`test-util` feature)
+//!
+//! `MockProvider` answers from a text-to-vector map with an optional
+//! default, can fail on chosen calls, records every batch it receives and
+//! can sleep per call to exercise timeout paths. `ManualClock` is a
+//! `transport::Clock` that only moves when told to, for deadline tests that
+//! sleep for real nowhere. Downstream crates enable them with
+//! `spo-crystal = { features = ["test-util"] }` in dev-dependencies.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::error::JinaError;
+use crate::provider::{EmbedError, EmbeddingProvider};
+use crate::transport::Clock;
+
+pub struct MockProvider {
+    dims: usize,
+    vectors: HashMap<String, Vec<f32>>,
+    default: Option<Vec<f32>>,
+    failures: HashMap<usize, JinaError>,
+    latency: Option<Duration>,
+    calls: Mutex<Vec<Vec<String>>>,
+}
+
+impl MockProvider {
+    /// Provider of `dims`-sized vectors; texts without a vector fail until one is set
+    pub fn new(dims: usize) -> Self {
+        Self {
+            dims,
+            vectors: HashMap::new(),
+            default: None,
+            failures: HashMap::new(),
+            latency: None,
+            calls: Mutex::new(Vec::new()),
+        }
+    }
+    
+    /// Vector returned for exactly `text`
+    pub fn with_vector(mut self, text: &str, vector: Vec<f32>) -> Self {
+        assert_eq!(vector.len(), self.dims, "mock vector for {:?} has the wrong size", text);
+        self.vectors.insert(text.to_string(), vector);
+        self
+    }
+    
+    /// Vector returned for texts without their own
+    pub fn with_default(mut self, vector: Vec<f32>) -> Self {
+        assert_eq!(vector.len(), self.dims, "mock default vector has the wrong size");
+        self.default = Some(vector);
+        self
+    }
+    
+    /// Fail the `n`th call (1-based) with `error`; the call is still recorded
+    pub fn fail_on_call(mut self, n: usize, error: JinaError) -> Self {
+        self.failures.insert(n, error);
+        self
+    }
+    
+    /// Sleep this long in every call before answering
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = Some(latency);
+        self
+    }
+    
+    /// Texts of every batch received so far, in call order
+    pub fn calls(&self) -> Vec<Vec<String>> { self.calls.lock().unwrap().clone() }
+    
+    pub fn call_count(&self) -> usize { self.calls.lock().unwrap().len() }
+}
+
+impl EmbeddingProvider for MockProvider {
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        let call = {
+            let mut calls = self.calls.lock().unwrap();
+            calls.push(texts.iter().map(|t| t.to_string()).collect());
+            calls.len()
+        };
+        if let Some(latency) = self.latency {
+            std::thread::sleep(latency);
+        }
+        if let Some(error) = self.failures.get(&call) {
+            return Err(error.clone());
+        }
+        
+        texts.iter()
+            .map(|t| self.vectors.get(*t).or(self.default.as_ref()).cloned()
+                .ok_or_else(|| JinaError::InvalidInput(format!("no mock vector for {:?}", t))))
+            .collect()
+    }
+    
+    fn dimensions(&self) -> usize { self.dims }
+}
+
+/// Virtual time: `sleep` advances it at once, as does `advance`
+pub struct ManualClock {
+    now: Mutex<Instant>,
+}
+
+impl ManualClock {
+    /// Clock starting at the current system time
+    pub fn new() -> Self { Self { now: Mutex::new(Instant::now()) } }
+    
+    pub fn advance(&self, by: Duration) { *self.now.lock().unwrap() += by; }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self { Self::new() }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant { *self.now.lock().unwrap() }
+    
+    fn sleep(&self, duration: Duration) { self.advance(duration) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_scripted_answers_failures_and_calls() {
+        let mock = MockProvider::new(2)
+            .with_vector("Ada", vec![1.0, 0.0])
+            .with_default(vec![0.0, 1.0])
+            .fail_on_call(2, JinaError::Api { status: 429, message: "slow down".to_string() });
+        
+        assert_eq!(mock.embed_batch(&["Ada", "Jan"]).unwrap(), vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+        assert_eq!(mock.embed("Ada").unwrap_err(), JinaError::Api { status: 429, message: "slow down".to_string() });
+        assert_eq!(mock.embed("Ada").unwrap(), vec![1.0, 0.0]);
+        assert_eq!(mock.calls(), vec![vec!["Ada", "Jan"], vec!["Ada"], vec!["Ada"]]);
+        
+        let strict = MockProvider::new(2).with_vector("Ada", vec![1.0, 0.0]);
+        assert!(matches!(strict.embed_batch(&["Ada", "Jan"]), Err(JinaError::InvalidInput(_))));
+    }
+    
+    #[test]
+    fn test_latency_is_applied_per_call() {
+        let mock = MockProvider::new(1).with_default(vec![1.0]).with_latency(Duration::from_millis(20));
+        let start = std::time::Instant::now();
+        mock.embed_batch(&["a", "b"]).unwrap();
+        mock.embed("c").unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+}
@@ -0,0 +1,449 @@
+//! Durable queue of embed requests that failed during an outage
+//!
+//! A client `with_offline_queue` answers `embed_or_queue` calls that fail
+//! with a transport error, a timeout, 429 or a retryable 5xx by appending
+//! their texts to the queue file and returning `EmbedOutcome::Queued` with
+//! the texts' ids, so writes keep flowing while the API is unreachable.
+//! `OfflineQueue::replay` later embeds the queued texts in batches and hands
+//! each vector to a callback for the caller's store.
+//!
+//! The file holds one JSON record per line, appended and synced per call.
+//! A record's id is the hex `content_key` of the model, options and text.
+//! Texts are stored raw, or through the caller's `Sealer` when one is set.
+//! Lines that do not parse (a torn append, a hand edit) are skipped and
+//! dropped by the next replay; an append after a torn one starts on a new
+//! line, so the records it adds are not lost with it. Options other than
+//! the task, dimensions and late chunking are not stored;
+//! `ReplayConfig::options` supplies them. Replay only embeds records of the
+//! model it is given and leaves the others queued.
+//!
+//! One `OfflineQueue` serializes its own appends and replays; two processes
+//! must not share a queue file.
+
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use base64::Engine;
+
+use crate::error::JinaError;
+use crate::hash::{content_key, to_hex};
+use crate::io::atomic_write;
+use crate::jina_api::{EmbedOptions, JinaClient};
+use crate::provider::{EmbeddingProvider, EmbeddingResponse};
+use crate::transport::{Clock, RetryPolicy, SystemClock};
+
+/// Layout of queue records; records of other versions are skipped
+const RECORD_VERSION: u32 = 1;
+
+/// Encryption of queued texts at rest, supplied by the caller
+pub trait Sealer: Send + Sync {
+    fn seal(&self, text: &str) -> Vec<u8>;
+    /// `None` if `sealed` does not open (another key, tampering)
+    fn open(&self, sealed: &[u8]) -> Option<String>;
+}
+
+/// What `embed_or_queue` did with its texts
+#[derive(Clone, Debug, PartialEq)]
+pub enum EmbedOutcome {
+    Embedded(Box<EmbeddingResponse>),
+    /// The API was unreachable; the ids of the queued texts, in input order
+    Queued(Vec<String>),
+}
+
+/// A queued text embedded by `replay`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayedEmbedding {
+    pub id: String,
+    /// Model the text was queued for, and embedded with
+    pub model: String,
+    pub text: String,
+    pub options: EmbedOptions,
+    pub vector: Vec<f32>,
+}
+
+/// How `replay` batches and retries
+#[derive(Clone, Debug)]
+pub struct ReplayConfig {
+    /// Texts per request
+    pub batch_size: usize,
+    /// Retries of a failed batch before replay stops
+    pub retry: RetryPolicy,
+    /// Options besides task, dimensions and late chunking, e.g. `preprocess`
+    pub options: EmbedOptions,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self { Self { batch_size: 64, retry: RetryPolicy::default(), options: EmbedOptions::default() } }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReplayReport {
+    /// Texts embedded and handed to the callback
+    pub delivered: usize,
+    /// Texts still queued
+    pub remaining: usize,
+    /// Unparseable lines dropped from the file
+    pub skipped: usize,
+    /// Texts queued for another model, left queued (counted in `remaining`)
+    pub mismatched: usize,
+    /// Why replay stopped early, if it did; the batch it failed on stays queued
+    pub stopped: Option<JinaError>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+struct Record {
+    version: u32,
+    id: String,
+    model: String,
+    task: Option<String>,
+    dimensions: Option<usize>,
+    #[serde(default)]
+    late_chunking: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    /// Base64 of the `Sealer`'s output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sealed: Option<String>,
+}
+
+impl Record {
+    fn options(&self, base: &EmbedOptions) -> Option<EmbedOptions> {
+        Some(EmbedOptions {
+            task: self.task.as_deref().map(str::parse).transpose().ok()?,
+            dimensions: self.dimensions,
+            late_chunking: self.late_chunking,
+            ..base.clone()
+        })
+    }
+}
+
+pub struct OfflineQueue {
+    path: PathBuf,
+    sealer: Option<Arc<dyn Sealer>>,
+    clock: Arc<dyn Clock>,
+    lock: Mutex<()>,
+}
+
+impl OfflineQueue {
+    /// Queue kept in the file at `path`, created by the first append
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self { path: path.as_ref().to_path_buf(), sealer: None, clock: Arc::new(SystemClock), lock: Mutex::new(()) }
+    }
+    
+    /// Store texts as `sealer.seal` gives them instead of raw
+    pub fn with_sealer(mut self, sealer: impl Sealer + 'static) -> Self {
+        self.sealer = Some(Arc::new(sealer));
+        self
+    }
+    
+    /// Sleep between replay retries with `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+    
+    pub fn path(&self) -> &Path { &self.path }
+    
+    /// Append `texts` as embedded by `model` under `options`; returns their ids
+    pub fn push(&self, model: &str, texts: &[&str], options: &EmbedOptions) -> Result<Vec<String>, String> {
+        let mut lines = String::new();
+        let mut ids = Vec::with_capacity(texts.len());
+        for text in texts {
+            let id = to_hex(&content_key(model, options, text));
+            let (text, sealed) = match &self.sealer {
+                Some(sealer) => (None, Some(base64::engine::general_purpose::STANDARD.encode(sealer.seal(text)))),
+                None => (Some(text.to_string()), None),
+            };
+            let record = Record {
+                version: RECORD_VERSION,
+                id: id.clone(),
+                model: model.to_string(),
+                task: options.task.map(|t| t.as_str().to_string()),
+                dimensions: options.dimensions,
+                late_chunking: options.late_chunking,
+                text,
+                sealed,
+            };
+            lines.push_str(&serde_json::to_string(&record).unwrap());
+            lines.push('\n');
+            ids.push(id);
+        }
+        
+        let _guard = self.lock.lock().unwrap();
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(&self.path)
+            .map_err(|e| format!("Cannot open {}: {}", self.path.display(), e))?;
+        // A crash mid-append leaves a record without its newline: end it, so
+        // only the torn record is skipped and not the first one appended now
+        let torn = (|| {
+            if file.seek(SeekFrom::End(0))? == 0 { return Ok(false); }
+            let mut last = [0u8];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            Ok::<_, std::io::Error>(last[0] != b'\n')
+        })().map_err(|e| format!("Cannot read {}: {}", self.path.display(), e))?;
+        if torn {
+            lines.insert(0, '\n');
+        }
+        file.write_all(lines.as_bytes()).and_then(|()| file.sync_data())
+            .map_err(|e| format!("Write failed for {}: {}", self.path.display(), e))?;
+        Ok(ids)
+    }
+    
+    /// Queued texts, not counting unparseable lines
+    pub fn len(&self) -> Result<usize, String> {
+        let _guard = self.lock.lock().unwrap();
+        Ok(self.read()?.0.len())
+    }
+    
+    pub fn is_empty(&self) -> Result<bool, String> { self.len().map(|n| n == 0) }
+    
+    /// Embed the queued texts of `model` with `provider`, which must embed
+    /// with that model, oldest first, passing each vector to `deliver`;
+    /// delivered texts leave the queue. Texts of other models stay queued.
+    ///
+    /// A batch that still fails after `config.retry` stops the replay and
+    /// stays queued with everything after it, as do texts the sealer cannot
+    /// open. Errors only if the queue file cannot be read or rewritten.
+    pub fn replay(&self, model: &str, provider: &dyn EmbeddingProvider, config: &ReplayConfig,
+                  mut deliver: impl FnMut(ReplayedEmbedding)) -> Result<ReplayReport, String> {
+        let _guard = self.lock.lock().unwrap();
+        let (records, skipped) = self.read()?;
+        let mut report = ReplayReport { skipped, ..ReplayReport::default() };
+        let mut kept = Vec::new();
+        let mut opened = Vec::new();
+        for (position, record) in records.into_iter().enumerate() {
+            if record.model != model {
+                report.mismatched += 1;
+                kept.push((position, record));
+                continue;
+            }
+            match self.open(&record, &config.options) {
+                Some((text, options)) => opened.push(((position, record), text, options)),
+                None => kept.push((position, record)),
+            }
+        }
+        
+        // Consecutive records of the same options go out together
+        let mut rest = opened.as_slice();
+        while let Some((_, _, options)) = rest.first() {
+            let run = rest.iter().take_while(|(_, _, o)| o == options).count().min(config.batch_size.max(1));
+            let (batch, after) = rest.split_at(run);
+            if report.stopped.is_some() {
+                kept.extend(batch.iter().map(|(record, _, _)| record.clone()));
+                rest = after;
+                continue;
+            }
+            let texts: Vec<&str> = batch.iter().map(|(_, text, _)| text.as_str()).collect();
+            match self.embed_with_retry(provider, &texts, options, &config.retry) {
+                Ok(vectors) => {
+                    for ((record, text, options), vector) in batch.iter().zip(vectors) {
+                        deliver(ReplayedEmbedding {
+                            id: record.1.id.clone(),
+                            model: record.1.model.clone(),
+                            text: text.clone(),
+                            options: options.clone(),
+                            vector,
+                        });
+                    }
+                    report.delivered += batch.len();
+                }
+                Err(e) => {
+                    report.stopped = Some(e);
+                    kept.extend(batch.iter().map(|(record, _, _)| record.clone()));
+                }
+            }
+            rest = after;
+        }
+        
+        report.remaining = kept.len();
+        if report.delivered > 0 || report.skipped > 0 {
+            kept.sort_by_key(|&(position, _)| position);
+            self.rewrite(kept.iter().map(|(_, record)| record))?;
+        }
+        Ok(report)
+    }
+    
+    fn embed_with_retry(&self, provider: &dyn EmbeddingProvider, texts: &[&str], options: &EmbedOptions,
+                        retry: &RetryPolicy) -> Result<Vec<Vec<f32>>, JinaError> {
+        let mut attempt = 0;
+        loop {
+            let result = provider.embed_batch_with(texts, options).and_then(|vectors| match vectors.len() == texts.len() {
+                true => Ok(vectors),
+                false => Err(JinaError::Mismatch { expected: texts.len(), got: vectors.len() }),
+            });
+            match result {
+                Err(e) if attempt < retry.max_retries && is_outage(&e) => {
+                    self.clock.sleep(retry.delay(attempt));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+    
+    /// Text and options of `record`, unless it is sealed and will not open
+    fn open(&self, record: &Record, base: &EmbedOptions) -> Option<(String, EmbedOptions)> {
+        let text = match (&record.text, &record.sealed, &self.sealer) {
+            (Some(text), _, _) => text.clone(),
+            (None, Some(sealed), Some(sealer)) => sealer.open(&base64::engine::general_purpose::STANDARD.decode(sealed).ok()?)?,
+            _ => return None,
+        };
+        Some((text, record.options(base)?))
+    }
+    
+    /// Parsed records and the number of lines skipped
+    fn read(&self) -> Result<(Vec<Record>, usize), String> {
+        let bytes = match std::fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+            Err(e) => return Err(format!("Cannot read {}: {}", self.path.display(), e)),
+        };
+        let mut records = Vec::new();
+        let mut skipped = 0;
+        for line in bytes.split(|&b| b == b'\n').filter(|line| !line.is_empty()) {
+            match serde_json::from_slice::<Record>(line) {
+                Ok(record) if record.version == RECORD_VERSION && (record.text.is_some() || record.sealed.is_some()) => records.push(record),
+                _ => skipped += 1,
+            }
+        }
+        Ok((records, skipped))
+    }
+    
+    fn rewrite<'a>(&self, records: impl Iterator<Item = &'a Record>) -> Result<(), String> {
+        atomic_write(&self.path, |file| {
+            for record in records {
+                writeln!(file, "{}", serde_json::to_string(record).unwrap())?;
+            }
+            Ok(())
+        }).map_err(|e| format!("Write failed for {}: {}", self.path.display(), e))
+    }
+}
+
+/// Whether `error` means the API could not be reached, rather than refused the request
+pub fn is_outage(error: &JinaError) -> bool {
+    match error {
+        JinaError::Connect(_) | JinaError::Transport(_) | JinaError::DeadlineExceeded => true,
+        JinaError::Api { status, .. } => crate::transport::retryable_status(*status),
+        JinaError::Route { source, .. } => is_outage(source),
+        _ => false,
+    }
+}
+
+impl JinaClient {
+    /// Queue the texts of `embed_or_queue` calls that fail with an outage
+    pub fn with_offline_queue(mut self, queue: Arc<OfflineQueue>) -> Self {
+        self.offline_queue = Some(queue);
+        self
+    }
+    
+    /// `embed_batch_full`, or with an offline queue and an outage, the texts queued.
+    ///
+    /// Errors that are not outages, and failures to append to the queue, are returned.
+    pub fn embed_or_queue(&self, texts: &[&str], options: &EmbedOptions) -> Result<EmbedOutcome, JinaError> {
+        match (self.embed_batch_full(texts, options), &self.offline_queue) {
+            (Err(e), Some(queue)) if is_outage(&e) => Ok(EmbedOutcome::Queued(queue.push(&self.model, texts, options)?)),
+            (result, _) => result.map(|response| EmbedOutcome::Embedded(Box::new(response))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jina_api::Task;
+    use crate::mock::MockProvider;
+    use std::time::Duration;
+    
+    
+    fn quick_retries(n: u32) -> ReplayConfig {
+        let retry = RetryPolicy { max_retries: n, base_delay: Duration::from_millis(1), max_delay: Duration::from_millis(1) };
+        ReplayConfig { batch_size: 2, retry, ..ReplayConfig::default() }
+    }
+    
+    #[test]
+    fn test_outage_queues_and_replay_drains() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = Arc::new(OfflineQueue::new(dir.path().join("outage.jsonl")));
+        let down = MockProvider::new(2).with_default(vec![1.0, 0.0]).fail_on_call(1, JinaError::Connect("offline".to_string()))
+            .fail_on_call(2, JinaError::Api { status: 400, message: "bad input".to_string() });
+        let client = JinaClient::new("test_key").with_backend(down).with_offline_queue(queue.clone());
+        let options = EmbedOptions::passage().with_dimensions(2);
+        
+        let Ok(EmbedOutcome::Queued(ids)) = client.embed_or_queue(&["Ada", "Grace", "Alan"], &options) else { panic!("not queued") };
+        assert_eq!(ids[0], to_hex(&content_key(&client.model, &options, "Ada")));
+        assert_eq!(queue.len(), Ok(3));
+        // A refused request is an error, not an outage
+        assert!(matches!(client.embed_or_queue(&["Jan"], &options), Err(JinaError::Api { status: 400, .. })));
+        assert!(matches!(client.embed_or_queue(&["Jan"], &options), Ok(EmbedOutcome::Embedded(_))));
+        queue.push(&client.model, &["Edsger"], &EmbedOptions::query()).unwrap();
+        
+        // Still down for one retry, then back: batches of 2 within each run of options
+        let mock = MockProvider::new(2).with_default(vec![0.0, 1.0]).fail_on_call(1, JinaError::Transport("reset".to_string()));
+        let mut delivered = Vec::new();
+        let report = queue.replay(&client.model, &mock, &quick_retries(1), |e| delivered.push(e)).unwrap();
+        assert_eq!(report, ReplayReport { delivered: 4, remaining: 0, skipped: 0, mismatched: 0, stopped: None });
+        assert_eq!(delivered.iter().map(|e| e.text.as_str()).collect::<Vec<_>>(), ["Ada", "Grace", "Alan", "Edsger"]);
+        assert_eq!((delivered[0].id.as_str(), delivered[0].model.as_str()), (ids[0].as_str(), client.model.as_str()));
+        assert_eq!((delivered[1].options.task, delivered[3].options.task), (Some(Task::RetrievalPassage), Some(Task::RetrievalQuery)));
+        assert_eq!(mock.calls().iter().map(Vec::len).collect::<Vec<_>>(), [2, 2, 1, 1]);
+        assert_eq!(queue.len(), Ok(0));
+    }
+    
+    struct Xor(u8);
+    
+    impl Sealer for Xor {
+        fn seal(&self, text: &str) -> Vec<u8> { text.bytes().map(|b| b ^ self.0).collect() }
+        fn open(&self, sealed: &[u8]) -> Option<String> { String::from_utf8(sealed.iter().map(|b| b ^ self.0).collect()).ok() }
+    }
+    
+    #[test]
+    fn test_corrupt_lines_skipped_and_failed_batches_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sealed.jsonl");
+        let queue = OfflineQueue::new(&path).with_sealer(Xor(0x5a));
+        let options = EmbedOptions::default();
+        queue.push("m", &["secret one", "secret two", "secret three"], &options).unwrap();
+        let raw = std::fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("secret"));
+        // A bad line, then a record torn before its newline by a crash mid-append
+        std::fs::write(&path, format!("{}not json\n{{\"version\":1,\"id\":\"tor", raw)).unwrap();
+        assert_eq!(queue.len(), Ok(3));
+        queue.push("m", &["secret four"], &options).unwrap();
+        assert_eq!(queue.len(), Ok(4));
+        
+        // The second batch fails for good: it stays queued, the bad lines go
+        let error = JinaError::Connect("offline".to_string());
+        let mock = MockProvider::new(1).with_default(vec![1.0]).fail_on_call(2, error.clone()).fail_on_call(3, error.clone());
+        let mut delivered = Vec::new();
+        let report = queue.replay("m", &mock, &quick_retries(1), |e| delivered.push(e.text)).unwrap();
+        assert_eq!(report, ReplayReport { delivered: 2, remaining: 2, skipped: 2, mismatched: 0, stopped: Some(error) });
+        assert_eq!(delivered, ["secret one", "secret two"]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+        
+        // Without the sealer the text cannot be read, so it stays
+        let unsealed = OfflineQueue::new(&path);
+        assert_eq!(unsealed.replay("m", &mock, &quick_retries(0), |_| panic!("opened")).unwrap().remaining, 2);
+        let report = queue.replay("m", &mock, &quick_retries(0), |e| delivered.push(e.text)).unwrap();
+        assert_eq!((report.delivered, report.remaining), (2, 0));
+        assert_eq!(delivered[2..], ["secret three", "secret four"]);
+    }
+    
+    #[test]
+    fn test_replay_keeps_other_models() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = OfflineQueue::new(dir.path().join("models.jsonl"));
+        let options = EmbedOptions::default();
+        queue.push("model-a", &["Ada"], &options).unwrap();
+        queue.push("model-b", &["Grace", "Alan"], &options).unwrap();
+        
+        let mock = MockProvider::new(1).with_default(vec![1.0]);
+        let mut delivered = Vec::new();
+        let report = queue.replay("model-b", &mock, &quick_retries(0), |e| delivered.push((e.model, e.text))).unwrap();
+        assert_eq!(report, ReplayReport { delivered: 2, remaining: 1, skipped: 0, mismatched: 1, stopped: None });
+        assert_eq!(delivered, [("model-b".to_string(), "Grace".to_string()), ("model-b".to_string(), "Alan".to_string())]);
+        let report = queue.replay("model-a", &mock, &quick_retries(0), |_| {}).unwrap();
+        assert_eq!((report.delivered, report.remaining, report.mismatched), (1, 0, 0));
+    }
+}
@@ -0,0 +1,189 @@
+//! Ollama local embeddings backend
+//!
+//! POSTs `{model, input}` to `<host>/api/embed` and reads the `embeddings`
+//! array back. Ollama has no auth and speaks plain HTTP, so the default
+//! transport is `PlainHttpTransport`; a refused connection becomes a
+//! `Connect` error asking whether Ollama is running.
+
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::error::{truncate_for_display, JinaError, MAX_DISPLAY_CHARS};
+use crate::provider::{BackendKind, EmbedError, EmbeddingProvider, EmbeddingResponse, LearnedDims, Usage};
+use crate::transport::{self, check_status, send_with_retry, HttpRequest, RetryPolicy, Transport};
+
+const DEFAULT_HOST: &str = "http://localhost:11434";
+const DEFAULT_MODEL: &str = "nomic-embed-text";
+
+pub struct OllamaClient {
+    host: String,
+    model: String,
+    transport: Arc<dyn Transport>,
+    retry: RetryPolicy,
+    dims: LearnedDims,
+}
+
+impl Default for OllamaClient {
+    fn default() -> Self { Self::new() }
+}
+
+impl OllamaClient {
+    /// `nomic-embed-text` on `http://localhost:11434`
+    pub fn new() -> Self {
+        Self {
+            host: DEFAULT_HOST.to_string(),
+            model: DEFAULT_MODEL.to_string(),
+            transport: transport::for_url(DEFAULT_HOST),
+            retry: RetryPolicy::default(),
+            dims: LearnedDims::default(),
+        }
+    }
+    
+    /// Base URL such as `http://gpu-box:11434`; `https://` hosts go through curl
+    pub fn with_host(mut self, host: &str) -> Self {
+        self.host = host.trim_end_matches('/').to_string();
+        self.transport = transport::for_url(&self.host);
+        self
+    }
+    
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+    
+    pub fn with_transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Arc::new(transport);
+        self
+    }
+    
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+    
+    /// Embed all texts in one request; usage is Ollama's `prompt_eval_count`
+    pub fn embed_batch_full(&self, texts: &[&str]) -> Result<EmbeddingResponse, JinaError> {
+        let body = json!({ "model": self.model, "input": texts });
+        let request = HttpRequest::post_json(format!("{}/api/embed", self.host), &body);
+        
+        let response = send_with_retry(self.transport.as_ref(), &request, &self.retry)
+            .map_err(|e| match e {
+                JinaError::Connect(msg) => JinaError::Connect(format!("{} (is Ollama running? try `ollama serve`)", msg)),
+                other => other,
+            })?;
+        let parsed = parse_response(&check_status(response)?.body, texts.len())?;
+        self.dims.check(&parsed.embeddings)?;
+        Ok(parsed)
+    }
+}
+
+impl EmbeddingProvider for OllamaClient {
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        Ok(self.embed_batch_full(texts)?.embeddings)
+    }
+    
+    /// Size seen in the first response (0 before any)
+    fn dimensions(&self) -> usize { self.dims.get() }
+}
+
+#[derive(Deserialize)]
+struct Response {
+    embeddings: Vec<Vec<f32>>,
+    #[serde(default)]
+    prompt_eval_count: u64,
+}
+
+fn parse_response(body: &str, expected: usize) -> Result<EmbeddingResponse, JinaError> {
+    let response: Response = serde_json::from_str(body)
+        .map_err(|e| JinaError::Parse(format!("Ollama response: {}", truncate_for_display(&e.to_string(), MAX_DISPLAY_CHARS))))?;
+    if response.embeddings.len() != expected {
+        return Err(JinaError::Mismatch { expected, got: response.embeddings.len() });
+    }
+    let tokens = response.prompt_eval_count;
+    Ok(EmbeddingResponse {
+        embeddings: response.embeddings,
+        usage: Usage { prompt_tokens: tokens, total_tokens: tokens },
+        diagnostics: None,
+        provenance: None,
+        pooled: Vec::new(),
+        backend: BackendKind::Remote,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+    
+    /// One-shot-per-connection HTTP server answering with chunked bodies; returns request bodies
+    fn mock_ollama(responses: Vec<(u16, String)>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let mut bodies = Vec::new();
+            for (status, body) in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" { break; }
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut request_body = vec![0; length];
+                reader.read_exact(&mut request_body).unwrap();
+                bodies.push(String::from_utf8(request_body).unwrap());
+                
+                write!(stream, "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\n\r\n", status).unwrap();
+                for chunk in body.as_bytes().chunks(7) {
+                    write!(stream, "{:x}\r\n", chunk.len()).unwrap();
+                    stream.write_all(chunk).unwrap();
+                    write!(stream, "\r\n").unwrap();
+                }
+                write!(stream, "0\r\n\r\n").unwrap();
+            }
+            bodies
+        });
+        (host, handle)
+    }
+    
+    #[test]
+    fn test_embed_against_mock_server() {
+        let ok = r#"{"model":"nomic-embed-text","embeddings":[[0.1,0.2,0.3],[0.4,0.5,0.6]],"total_duration":14143917,"load_duration":1019500,"prompt_eval_count":8}"#;
+        let missing = r#"{"error":"model \"nope\" not found, try pulling it first"}"#;
+        let (host, server) = mock_ollama(vec![(200, ok.to_string()), (404, missing.to_string())]);
+        
+        let client = OllamaClient::new().with_host(&host);
+        let full = client.embed_batch_full(&["Ada", "Jan"]).unwrap();
+        assert_eq!(full.embeddings, vec![vec![0.1, 0.2, 0.3], vec![0.4, 0.5, 0.6]]);
+        assert_eq!(full.usage.prompt_tokens, 8);
+        assert_eq!(client.dimensions(), 3);
+        
+        let err = client.with_model("nope").embed("x").unwrap_err();
+        assert_eq!(err, JinaError::Api { status: 404, message: "model \"nope\" not found, try pulling it first".to_string() });
+        
+        let bodies = server.join().unwrap();
+        let first: serde_json::Value = serde_json::from_str(&bodies[0]).unwrap();
+        assert_eq!(first, json!({"model": "nomic-embed-text", "input": ["Ada", "Jan"]}));
+    }
+    
+    #[test]
+    fn test_connection_refused_is_explained() {
+        // Bind then drop to get a port nothing listens on
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let client = OllamaClient::new()
+            .with_host(&format!("http://127.0.0.1:{}", port))
+            .with_retry(RetryPolicy::none());
+        match client.embed("x") {
+            Err(JinaError::Connect(msg)) => assert!(msg.contains("is Ollama running?"), "{}", msg),
+            other => panic!("expected a connect error, got {:?}", other),
+        }
+    }
+}
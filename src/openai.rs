@@ -0,0 +1,385 @@
+//! OpenAI-compatible `/v1/embeddings` backend
+//!
+//! Covers gateways speaking the OpenAI shape (LiteLLM, vLLM, Azure OpenAI):
+//! `{model, input, encoding_format, dimensions}` in, `data[]` with an
+//! `index` per vector plus `usage` out. Vectors are returned in input order
+//! whatever order `data` lists them in, and the vector size seen in the
+//! first response becomes `dimensions()` unless one was requested.
+//!
+//! Some gateways answer a failed input with a `null` entry or an entry
+//! holding an `error` instead of an `embedding`. `embed_batch_partial`
+//! turns those into `ItemError::Failed`; everything else fails the call.
+
+use std::sync::Arc;
+
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::error::{truncate_for_display, ItemError, ItemResult, JinaError, MAX_DISPLAY_CHARS};
+use crate::provider::{check_dims, BackendKind, EmbedError, EmbeddingProvider, EmbeddingResponse, LearnedDims, Usage};
+use crate::transport::{self, check_status, send_with_retry, HttpRequest, RetryPolicy, Transport};
+
+const MAX_BATCH_SIZE: usize = 2048;  // OpenAI per-request input limit
+
+/// Wire format for returned vectors
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EncodingFormat {
+    #[default]
+    Float,
+    /// Little-endian f32 bytes, base64 encoded: about a quarter of the JSON size
+    Base64,
+}
+
+impl EncodingFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EncodingFormat::Float => "float",
+            EncodingFormat::Base64 => "base64",
+        }
+    }
+}
+
+pub struct OpenAiCompatClient {
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+    encoding_format: EncodingFormat,
+    dimensions: Option<usize>,
+    max_batch_size: usize,
+    headers: Vec<(String, String)>,
+    transport: Arc<dyn Transport>,
+    retry: RetryPolicy,
+    learned_dims: LearnedDims,
+}
+
+impl OpenAiCompatClient {
+    /// Client for `<base_url>/embeddings`, e.g. `https://api.openai.com/v1`
+    pub fn new(base_url: &str, model: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: None,
+            model: model.to_string(),
+            encoding_format: EncodingFormat::default(),
+            dimensions: None,
+            max_batch_size: MAX_BATCH_SIZE,
+            headers: Vec::new(),
+            transport: transport::for_url(base_url),
+            retry: RetryPolicy::default(),
+            learned_dims: LearnedDims::default(),
+        }
+    }
+    
+    /// Sent as `Authorization: Bearer <key>`; gateways without auth need none
+    pub fn with_api_key(mut self, key: &str) -> Self {
+        crate::error::register_secret(key);
+        self.api_key = Some(key.to_string());
+        self
+    }
+    
+    /// Request shortened vectors (models that support `dimensions`)
+    pub fn with_dimensions(mut self, dims: usize) -> Self {
+        self.dimensions = Some(dims);
+        self
+    }
+    
+    pub fn with_encoding_format(mut self, format: EncodingFormat) -> Self {
+        self.encoding_format = format;
+        self
+    }
+    
+    /// Extra header on every request, e.g. Azure's `api-key`
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+    
+    pub fn with_max_batch_size(mut self, n: usize) -> Self {
+        self.max_batch_size = n.max(1);
+        self
+    }
+    
+    pub fn with_transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Arc::new(transport);
+        self
+    }
+    
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+    
+    /// Embed in requests of at most `max_batch_size`; usage is summed over requests.
+    ///
+    /// A `null` or `error` entry in `data` fails the call, naming the entry.
+    pub fn embed_batch_full(&self, texts: &[&str]) -> Result<EmbeddingResponse, JinaError> {
+        let mut full = EmbeddingResponse::default();
+        for chunk in texts.chunks(self.max_batch_size) {
+            let (items, usage) = self.request(chunk)?;
+            full.embeddings.extend(strict(items)?);
+            full.usage.add(&usage);
+        }
+        Ok(full)
+    }
+    
+    /// Embeddings in input order, with `ItemError::Failed` and the gateway's
+    /// message for each input that came back as a `null` or `error` entry
+    pub fn embed_batch_partial(&self, texts: &[&str]) -> Result<Vec<ItemResult>, JinaError> {
+        let mut out = Vec::with_capacity(texts.len());
+        for chunk in texts.chunks(self.max_batch_size) {
+            out.extend(self.request(chunk)?.0);
+        }
+        Ok(out)
+    }
+    
+    /// One request's entries, the vectors checked against `dimensions`
+    fn request(&self, texts: &[&str]) -> Result<(Vec<ItemResult>, Usage), JinaError> {
+        let mut request = HttpRequest::post_json(format!("{}/embeddings", self.base_url), &self.request_body(texts))
+            .bearer(self.api_key.as_deref());
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        
+        let response = check_status(send_with_retry(self.transport.as_ref(), &request, &self.retry)?)?;
+        let (items, usage) = parse_items(&response.body, texts.len())?;
+        let vectors: Vec<Vec<f32>> = items.iter().flatten().cloned().collect();
+        match self.dimensions {
+            Some(dims) => check_dims(&vectors, dims)?,
+            None => self.learned_dims.check(&vectors)?,
+        }
+        Ok((items, usage))
+    }
+    
+    fn request_body(&self, texts: &[&str]) -> serde_json::Value {
+        let mut body = json!({
+            "model": self.model,
+            "input": texts,
+            "encoding_format": self.encoding_format.as_str(),
+        });
+        if let Some(dims) = self.dimensions {
+            body["dimensions"] = json!(dims);
+        }
+        body
+    }
+}
+
+impl EmbeddingProvider for OpenAiCompatClient {
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        Ok(self.embed_batch_full(texts)?.embeddings)
+    }
+    
+    /// Requested size, else the size seen in the first response (0 before any)
+    fn dimensions(&self) -> usize {
+        self.dimensions.unwrap_or_else(|| self.learned_dims.get())
+    }
+}
+
+#[derive(Deserialize)]
+struct Response {
+    /// `null` for inputs some gateways failed
+    data: Vec<Option<Item>>,
+    #[serde(default)]
+    usage: Usage,
+}
+
+#[derive(Deserialize)]
+struct Item {
+    #[serde(default)]
+    index: Option<usize>,
+    #[serde(default)]
+    embedding: Option<Vector>,
+    #[serde(default)]
+    error: Option<Failure>,
+}
+
+/// `error` of a failed entry: an object with a `message`, a string, or anything else
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Failure {
+    Object { message: String },
+    Text(String),
+    Other(serde_json::Value),
+}
+
+impl Failure {
+    fn into_message(self) -> String {
+        match self {
+            Failure::Object { message } | Failure::Text(message) => message,
+            Failure::Other(value) => value.to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Vector {
+    Float(Vec<f32>),
+    Base64(String),
+}
+
+/// Parse a response for `expected` inputs, ordering vectors by `data[].index`;
+/// a failed entry is a parse error
+///
+/// Jina's multimodal endpoints answer in this shape too.
+pub(crate) fn parse_response(body: &str, expected: usize) -> Result<EmbeddingResponse, JinaError> {
+    let (items, usage) = parse_items(body, expected)?;
+    Ok(EmbeddingResponse { embeddings: strict(items)?, usage, diagnostics: None, provenance: None, pooled: Vec::new(), backend: BackendKind::Remote })
+}
+
+/// Each input's vector or failure, by `data[].index`: an entry without an
+/// index (such as `null`) is the input at its position in `data`
+fn parse_items(body: &str, expected: usize) -> Result<(Vec<ItemResult>, Usage), JinaError> {
+    let response: Response = serde_json::from_str(body)
+        .map_err(|e| JinaError::Parse(format!("OpenAI-style response: {}", truncate_for_display(&e.to_string(), MAX_DISPLAY_CHARS))))?;
+    if response.data.len() != expected {
+        return Err(JinaError::Mismatch { expected, got: response.data.len() });
+    }
+    
+    let failed = |message: String| Err(ItemError::Failed { message });
+    let mut items: Vec<Option<ItemResult>> = vec![None; expected];
+    for (position, item) in response.data.into_iter().enumerate() {
+        let (index, item) = match item {
+            None => (position, failed("null entry".to_string())),
+            Some(Item { index, error: Some(error), .. }) => (index.unwrap_or(position), failed(truncate_for_display(&error.into_message(), MAX_DISPLAY_CHARS))),
+            Some(Item { index, embedding: None, .. }) => (index.unwrap_or(position), failed("no embedding".to_string())),
+            Some(Item { index, embedding: Some(vector), .. }) => (index.unwrap_or(position), Ok(match vector {
+                Vector::Float(v) => v,
+                Vector::Base64(s) => decode_base64_f32(&s)?,
+            })),
+        };
+        let slot = items.get_mut(index)
+            .ok_or_else(|| JinaError::Parse(format!("data index {} out of range for {} inputs", index, expected)))?;
+        if slot.replace(item).is_some() {
+            return Err(JinaError::Parse(format!("duplicate data index {}", index)));
+        }
+    }
+    
+    // Exactly `expected` items with distinct in-range indices fill every slot
+    Ok((items.into_iter().flatten().collect(), response.usage))
+}
+
+/// The vectors of `items`, or an error naming the first failed entry
+fn strict(items: Vec<ItemResult>) -> Result<Vec<Vec<f32>>, JinaError> {
+    items.into_iter()
+        .enumerate()
+        .map(|(i, item)| item.map_err(|e| {
+            let message = match e {
+                ItemError::Failed { message } => message,
+                other => other.to_string(),
+            };
+            JinaError::Parse(format!("data entry {} failed: {}", i, message))
+        }))
+        .collect()
+}
+
+fn decode_base64_f32(s: &str) -> Result<Vec<f32>, JinaError> {
+    let bytes = base64::engine::general_purpose::STANDARD.decode(s)
+        .map_err(|e| JinaError::Parse(format!("base64 embedding: {}", e)))?;
+    if !bytes.len().is_multiple_of(4) {
+        return Err(JinaError::Parse(format!("base64 embedding has {} bytes, not a multiple of 4", bytes.len())));
+    }
+    Ok(bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::HttpResponse;
+    use std::sync::Mutex;
+    
+    const FIXTURE: &str = include_str!("../fixtures/openai/embeddings.json");
+    const FIXTURE_BASE64: &str = include_str!("../fixtures/openai/embeddings_base64.json");
+    
+    fn serve(body: &'static str) -> impl Transport {
+        move |_: &HttpRequest| Ok(HttpResponse { status: 200, headers: Vec::new(), body: body.to_string() })
+    }
+    
+    #[test]
+    fn test_parse_orders_by_index() {
+        let parsed = parse_response(FIXTURE, 2).unwrap();
+        assert_eq!(parsed.embeddings, vec![vec![0.5, 0.5, -0.5, 0.5], vec![0.0, 0.6, 0.0, -0.8]]);
+        assert_eq!(parsed.usage, Usage { prompt_tokens: 7, total_tokens: 7 });
+        
+        assert_eq!(parse_response(FIXTURE_BASE64, 1).unwrap().embeddings, vec![vec![0.5, 0.5, -0.5, 0.5]]);
+        
+        assert_eq!(parse_response(FIXTURE, 3).unwrap_err(), JinaError::Mismatch { expected: 3, got: 2 });
+        let duplicate = r#"{"data":[{"index":0,"embedding":[1]},{"index":0,"embedding":[2]}]}"#;
+        assert!(matches!(parse_response(duplicate, 2), Err(JinaError::Parse(_))));
+        assert!(matches!(parse_response("<html>", 1), Err(JinaError::Parse(_))));
+    }
+    
+    #[test]
+    fn test_failed_entries_stay_aligned() {
+        const NULL_ITEM: &str = include_str!("../fixtures/openai/embeddings_null_item.json");
+        const ERROR_ITEM: &str = include_str!("../fixtures/openai/embeddings_error_item.json");
+        let client = OpenAiCompatClient::new("http://gateway/v1", "m").with_transport(serve(NULL_ITEM));
+        let results = client.embed_batch_partial(&["a", "b", "c"]).unwrap();
+        assert_eq!(results, [Ok(vec![0.6, 0.8]), Err(ItemError::Failed { message: "null entry".to_string() }), Ok(vec![0.0, 1.0])]);
+        assert_eq!(client.dimensions(), 2);
+        assert_eq!(client.embed_batch(&["a", "b", "c"]).unwrap_err(), JinaError::Parse("data entry 1 failed: null entry".to_string()));
+        
+        let client = OpenAiCompatClient::new("http://gateway/v1", "m").with_transport(serve(ERROR_ITEM));
+        let results = client.embed_batch_partial(&["long", "short"]).unwrap();
+        assert_eq!(results[0], Err(ItemError::Failed {
+            message: "This model's maximum context length is 8192 tokens, however you requested 9120 tokens".to_string(),
+        }));
+        assert_eq!(results[1], Ok(vec![1.0, 0.0]));
+        assert!(matches!(client.embed_batch_full(&["long", "short"]), Err(JinaError::Parse(m)) if m.starts_with("data entry 0 failed: This model's")));
+        
+        let (items, _) = parse_items(r#"{"data":[{"index":0,"error":{"code":500}},{"index":1}]}"#, 2).unwrap();
+        assert_eq!(items, [Err(ItemError::Failed { message: r#"{"code":500}"#.to_string() }),
+                           Err(ItemError::Failed { message: "no embedding".to_string() })]);
+        let duplicate = r#"{"data":[null,{"index":0,"embedding":[1]}]}"#;
+        assert!(matches!(parse_items(duplicate, 2), Err(JinaError::Parse(m)) if m.contains("duplicate")));
+    }
+    
+    #[test]
+    fn test_request_and_dimension_discovery() {
+        let seen: Arc<Mutex<Vec<HttpRequest>>> = Arc::default();
+        let log = seen.clone();
+        let client = OpenAiCompatClient::new("http://gateway:4000/v1/", "text-embedding-3-small")
+            .with_api_key("sk-test")
+            .with_header("api-key", "azure")
+            .with_transport(move |request: &HttpRequest| {
+                log.lock().unwrap().push(request.clone());
+                Ok(HttpResponse { status: 200, headers: Vec::new(), body: FIXTURE.to_string() })
+            });
+        
+        assert_eq!(client.dimensions(), 0);
+        let full = client.embed_batch_full(&["first", "second"]).unwrap();
+        assert_eq!(full.embeddings[1], vec![0.0, 0.6, 0.0, -0.8]);
+        assert_eq!(client.dimensions(), 4);
+        
+        let request = seen.lock().unwrap()[0].clone();
+        assert_eq!(request.url, "http://gateway:4000/v1/embeddings");
+        assert!(request.headers.contains(&("Authorization".to_string(), "Bearer sk-test".to_string())));
+        assert!(request.headers.contains(&("api-key".to_string(), "azure".to_string())));
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body, json!({"model": "text-embedding-3-small", "input": ["first", "second"], "encoding_format": "float"}));
+        
+        // Requested dimensions are sent and enforced
+        let client = OpenAiCompatClient::new("http://gateway/v1", "m").with_dimensions(8).with_transport(serve(FIXTURE));
+        assert_eq!(client.request_body(&["x"])["dimensions"], 8);
+        assert_eq!(client.embed_batch(&["a", "b"]).unwrap_err(), JinaError::Mismatch { expected: 8, got: 4 });
+    }
+    
+    #[test]
+    fn test_batches_split_and_usage_sums() {
+        let client = OpenAiCompatClient::new("http://gateway/v1", "m")
+            .with_encoding_format(EncodingFormat::Base64)
+            .with_max_batch_size(1)
+            .with_transport(serve(FIXTURE_BASE64));
+        let full = client.embed_batch_full(&["a", "b", "c"]).unwrap();
+        assert_eq!(full.embeddings.len(), 3);
+        assert_eq!(full.usage.total_tokens, 6);
+        
+        let api_error = |_: &HttpRequest| Ok(HttpResponse {
+            status: 401,
+            headers: Vec::new(),
+            body: r#"{"error":{"message":"Incorrect API key provided","type":"invalid_request_error"}}"#.to_string(),
+        });
+        let client = OpenAiCompatClient::new("http://gateway/v1", "m").with_transport(api_error);
+        assert_eq!(client.embed("x").unwrap_err(),
+                   JinaError::Api { status: 401, message: "Incorrect API key provided".to_string() });
+    }
+}
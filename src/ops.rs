@@ -0,0 +1,97 @@
+//! Embedding arithmetic: weighted sums, analogies and Rocchio expansion
+//!
+//! Everything is built on `combine`, which sums `weight * vector` in f64 so
+//! long sums of near-cancelling terms keep their precision. All vectors
+//! must have the same size. Unit-norm embeddings stop being unit-norm once
+//! added; `combine_normalized` (or `search::normalize`) rescales the result
+//! before cosine search against normalized corpora.
+
+use crate::search::normalize;
+
+/// `Σ weight · vector`, accumulated in f64
+pub fn combine(terms: &[(f32, &[f32])]) -> Result<Vec<f32>, String> {
+    let Some(&(_, first)) = terms.first() else {
+        return Err("No vectors to combine".to_string());
+    };
+    let mut sum = vec![0f64; first.len()];
+    for (i, &(weight, vector)) in terms.iter().enumerate() {
+        if vector.len() != sum.len() {
+            return Err(format!("Dimension mismatch: term {} has {}, expected {}", i, vector.len(), sum.len()));
+        }
+        for (s, &x) in sum.iter_mut().zip(vector) {
+            *s += weight as f64 * x as f64;
+        }
+    }
+    Ok(sum.into_iter().map(|s| s as f32).collect())
+}
+
+/// `combine` scaled to unit L2 norm; an all-zero sum stays zero
+pub fn combine_normalized(terms: &[(f32, &[f32])]) -> Result<Vec<f32>, String> {
+    let mut v = combine(terms)?;
+    normalize(&mut v);
+    Ok(v)
+}
+
+pub fn add(a: &[f32], b: &[f32]) -> Result<Vec<f32>, String> {
+    combine(&[(1.0, a), (1.0, b)])
+}
+
+/// `a - b`
+pub fn sub(a: &[f32], b: &[f32]) -> Result<Vec<f32>, String> {
+    combine(&[(1.0, a), (-1.0, b)])
+}
+
+/// Rocchio query expansion: `alpha · query + beta · mean(relevant) - gamma · mean(irrelevant)`.
+///
+/// An empty feedback set contributes nothing. The result is not
+/// renormalized.
+pub fn rocchio(query: &[f32], relevant: &[&[f32]], irrelevant: &[&[f32]], alpha: f32, beta: f32, gamma: f32)
+               -> Result<Vec<f32>, String> {
+    let mut terms = vec![(alpha, query)];
+    terms.extend(relevant.iter().map(|&v| (beta / relevant.len() as f32, v)));
+    terms.extend(irrelevant.iter().map(|&v| (-gamma / irrelevant.len() as f32, v)));
+    combine(&terms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_weighted_sums() {
+        let (a, b, c) = ([1.0, 2.0, 3.0], [0.5, -1.0, 0.0], [0.0, 0.0, 4.0]);
+        assert_eq!(combine(&[(2.0, &a), (-4.0, &b), (0.25, &c)]).unwrap(), [0.0, 8.0, 7.0]);
+        assert_eq!(add(&a, &b).unwrap(), [1.5, 1.0, 3.0]);
+        assert_eq!(sub(&a, &c).unwrap(), [1.0, 2.0, -1.0]);
+        assert_eq!(combine_normalized(&[(1.0, &[3.0, 4.0])]).unwrap(), [0.6, 0.8]);
+        assert_eq!(combine_normalized(&[(1.0, &[1.0, 1.0]), (-1.0, &[1.0, 1.0])]).unwrap(), [0.0, 0.0]);
+        
+        // f64 accumulation: the large terms cancel exactly, the small one survives
+        let big = [1.0e8f32];
+        let small = [1.0f32];
+        assert_eq!(combine(&[(1.0, &big), (1.0, &small), (-1.0, &big)]).unwrap(), [1.0]);
+    }
+    
+    #[test]
+    fn test_dimension_checks() {
+        assert!(combine(&[]).unwrap_err().contains("No vectors"));
+        let err = combine(&[(1.0, &[1.0, 2.0]), (1.0, &[1.0])]).unwrap_err();
+        assert_eq!(err, "Dimension mismatch: term 1 has 1, expected 2");
+        assert!(add(&[1.0], &[1.0, 2.0]).is_err());
+        assert!(rocchio(&[1.0, 0.0], &[&[1.0]], &[], 1.0, 0.5, 0.0).is_err());
+        assert!(rocchio(&[1.0, 0.0], &[], &[&[1.0, 0.0, 0.0]], 1.0, 0.5, 0.5).is_err());
+    }
+    
+    #[test]
+    fn test_rocchio() {
+        let query = [1.0, 0.0];
+        let (r1, r2) = ([0.0, 1.0], [1.0, 1.0]);
+        // gamma 0 and alpha + beta = 1: the mean weighting the query by alpha, each document by beta / 2
+        let expanded = rocchio(&query, &[&r1, &r2], &[&[5.0, 5.0]], 0.5, 0.5, 0.0).unwrap();
+        assert_eq!(expanded, [0.5 * 1.0 + 0.25 * (0.0 + 1.0), 0.25 * (1.0 + 1.0)]);
+        
+        let pushed = rocchio(&query, &[&r1], &[&[0.0, -1.0], &[2.0, 0.0]], 1.0, 0.75, 0.5).unwrap();
+        assert_eq!(pushed, [1.0 - 0.25 * 2.0, 0.75 + 0.25]);
+        assert_eq!(rocchio(&query, &[], &[], 2.0, 1.0, 1.0).unwrap(), [2.0, 0.0]);
+    }
+}
@@ -0,0 +1,573 @@
+//! Embedding every matching file under a directory
+//!
+//! `embed_files` walks a tree in name order, reads matching files as UTF-8,
+//! chunks them and embeds the chunks in batches, returning one `FileChunk`
+//! per chunk with its path, position and byte offsets. Caching is up to the
+//! provider (e.g. `JinaClient::with_cache`).
+//!
+//! Problems with one file or directory — unreadable, too large, not UTF-8,
+//! a symlink cycle, a failed embedding request — become a `FileError` for
+//! that path and the walk carries on.
+//!
+//! `sync_directory` keeps a `CrystalIndex` in step with a tree across runs.
+//! A JSON manifest beside the index records each file's SHA-256, size,
+//! mtime and index ids. Files whose size and mtime are unchanged are not
+//! read, files whose content is unchanged are not embedded, and a new file
+//! with the content of a vanished one is taken as a rename and keeps its
+//! vectors. Entries of deleted files are removed.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::chunk::{Chunk, Chunking};
+use crate::hash::{sha256, to_hex};
+use crate::index::CrystalIndex;
+use crate::io::atomic_write;
+use crate::jina_api::EmbedOptions;
+use crate::metadata::Metadata;
+use crate::provider::{EmbedError, EmbeddingProvider};
+
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+pub const DEFAULT_MAX_CHUNK_SIZE: usize = 2000;
+pub const DEFAULT_FILE_BATCH_SIZE: usize = 64;
+
+/// What to do with files that are not valid UTF-8
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InvalidUtf8 {
+    /// Report `FileErrorKind::InvalidUtf8` and leave the file out
+    #[default]
+    Skip,
+    /// Replace invalid sequences with U+FFFD; offsets refer to the replaced text
+    Lossy,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileOptions {
+    /// Patterns files must match, e.g. `*.md` or `docs/**/*.txt`; every file when empty.
+    ///
+    /// `*` and `?` stay within a path component, `**/` spans directories.
+    /// Patterns without a `/` match the file name, others the path
+    /// relative to the root.
+    pub globs: Vec<String>,
+    /// Larger files are reported as `TooLarge`
+    pub max_file_size: u64,
+    pub chunking: Chunking,
+    /// Per-chunk budget in `chunking`'s unit; `Segmenter` cuts locally here
+    pub max_chunk_size: usize,
+    /// Descend into symlinked directories and read symlinked files
+    pub follow_symlinks: bool,
+    pub invalid_utf8: InvalidUtf8,
+    /// Chunks per embedding request
+    pub batch_size: usize,
+    pub embed_options: EmbedOptions,
+}
+
+impl Default for FileOptions {
+    fn default() -> Self {
+        Self {
+            globs: Vec::new(),
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            chunking: Chunking::Local,
+            max_chunk_size: DEFAULT_MAX_CHUNK_SIZE,
+            follow_symlinks: false,
+            invalid_utf8: InvalidUtf8::Skip,
+            batch_size: DEFAULT_FILE_BATCH_SIZE,
+            embed_options: EmbedOptions::passage(),
+        }
+    }
+}
+
+impl FileOptions {
+    pub fn new() -> Self { Self::default() }
+    
+    pub fn with_glob(mut self, pattern: &str) -> Self {
+        self.globs.push(pattern.to_string());
+        self
+    }
+    
+    pub fn with_max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = bytes;
+        self
+    }
+    
+    pub fn with_chunking(mut self, chunking: Chunking, max_chunk_size: usize) -> Self {
+        self.chunking = chunking;
+        self.max_chunk_size = max_chunk_size;
+        self
+    }
+    
+    pub fn with_follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+    
+    pub fn with_invalid_utf8(mut self, invalid: InvalidUtf8) -> Self {
+        self.invalid_utf8 = invalid;
+        self
+    }
+    
+    /// Chunks per embedding request (at least 1)
+    pub fn with_batch_size(mut self, n: usize) -> Self {
+        self.batch_size = n.max(1);
+        self
+    }
+    
+    /// Options for every request; passage embeddings by default
+    pub fn with_embed_options(mut self, options: EmbedOptions) -> Self {
+        self.embed_options = options;
+        self
+    }
+}
+
+/// One embedded chunk of a file
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FileChunk {
+    /// The root joined with the path below it, as walked
+    pub path: PathBuf,
+    /// Position of the chunk within its file
+    pub chunk_index: usize,
+    /// Byte range of the chunk in the file's text
+    pub start: usize,
+    pub end: usize,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FileErrorKind {
+    /// Reading the file or listing the directory failed, e.g. permission denied
+    Io { kind: io::ErrorKind, message: String },
+    /// A followed symlink leads back to a directory being walked
+    SymlinkCycle,
+    TooLarge { size: u64, limit: u64 },
+    InvalidUtf8,
+    /// The request embedding some of the file's chunks failed
+    Embed(EmbedError),
+}
+
+/// A file or directory left out of the results
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileError {
+    pub path: PathBuf,
+    pub kind: FileErrorKind,
+}
+
+impl FileError {
+    fn io(path: &Path, e: io::Error) -> Self {
+        FileError { path: path.to_path_buf(), kind: FileErrorKind::Io { kind: e.kind(), message: e.to_string() } }
+    }
+}
+
+/// Records and per-path errors of one `embed_files` run
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileEmbeddings {
+    pub records: Vec<FileChunk>,
+    pub errors: Vec<FileError>,
+}
+
+/// Embed the chunks of every matching file under `root`
+pub fn embed_files<P: EmbeddingProvider + ?Sized>(provider: &P, root: impl AsRef<Path>, options: &FileOptions)
+                                                  -> FileEmbeddings {
+    let mut records = Vec::new();
+    let errors = embed_files_with(provider, root, options, |record| records.push(record));
+    FileEmbeddings { records, errors }
+}
+
+/// `embed_files`, handing records to `on_record` batch by batch instead of
+/// collecting them; returns the errors.
+///
+/// A file's records arrive in chunk order. When a request fails, the files
+/// with chunks in it are reported and any of their records already handed
+/// out stay handed out.
+pub fn embed_files_with<P, F>(provider: &P, root: impl AsRef<Path>, options: &FileOptions, on_record: F) -> Vec<FileError>
+where
+    P: EmbeddingProvider + ?Sized,
+    F: FnMut(FileChunk),
+{
+    let root = root.as_ref();
+    let mut run = Run { provider, options, pending: Vec::new(), errors: Vec::new(), on_record };
+    let mut ancestors = Vec::new();
+    match fs::metadata(root) {
+        Ok(meta) if meta.is_dir() => run.walk(root, Path::new(""), &mut ancestors, &mut |run, path, _| run.file(path)),
+        Ok(_) => run.file(root),
+        Err(e) => run.errors.push(FileError::io(root, e)),
+    }
+    run.flush();
+    run.errors
+}
+
+struct Run<'a, P: ?Sized, F> {
+    provider: &'a P,
+    options: &'a FileOptions,
+    /// Chunks waiting for the next request, with their file's path
+    pending: Vec<(PathBuf, usize, Chunk)>,
+    errors: Vec<FileError>,
+    on_record: F,
+}
+
+impl<P: EmbeddingProvider + ?Sized, F: FnMut(FileChunk)> Run<'_, P, F> {
+    /// Call `visit` with the path and relative path of each matching file;
+    /// `dir` is `rel` below the root, `ancestors` the canonical directories being walked
+    fn walk(&mut self, dir: &Path, rel: &Path, ancestors: &mut Vec<PathBuf>, visit: &mut dyn FnMut(&mut Self, &Path, &Path)) {
+        let canonical = match fs::canonicalize(dir) {
+            Ok(path) => path,
+            Err(e) => return self.errors.push(FileError::io(dir, e)),
+        };
+        if ancestors.contains(&canonical) {
+            return self.errors.push(FileError { path: dir.to_path_buf(), kind: FileErrorKind::SymlinkCycle });
+        }
+        let mut entries: Vec<fs::DirEntry> = match fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(|e| e.map_err(|e| self.errors.push(FileError::io(dir, e))).ok()).collect(),
+            Err(e) => return self.errors.push(FileError::io(dir, e)),
+        };
+        entries.sort_by_key(|e| e.file_name());
+        
+        ancestors.push(canonical);
+        for entry in entries {
+            let path = entry.path();
+            let rel = rel.join(entry.file_name());
+            let link = entry.file_type().is_ok_and(|t| t.is_symlink());
+            if link && !self.options.follow_symlinks {
+                continue;
+            }
+            match fs::metadata(&path) {
+                Ok(meta) if meta.is_dir() => self.walk(&path, &rel, ancestors, visit),
+                Ok(_) if matches_globs(&self.options.globs, &rel) => visit(self, &path, &rel),
+                Ok(_) => {}
+                Err(e) => self.errors.push(FileError::io(&path, e)),
+            }
+        }
+        ancestors.pop();
+    }
+    
+    fn file(&mut self, path: &Path) {
+        let text = match self.read(path) {
+            Ok(text) => text,
+            Err(kind) => return self.errors.push(FileError { path: path.to_path_buf(), kind }),
+        };
+        let chunks = self.options.chunking.chunk_local(&text, self.options.max_chunk_size.max(1));
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            self.pending.push((path.to_path_buf(), i, chunk));
+            if self.pending.len() >= self.options.batch_size {
+                self.flush();
+            }
+        }
+    }
+    
+    fn read(&self, path: &Path) -> Result<String, FileErrorKind> {
+        let io = |e: io::Error| FileErrorKind::Io { kind: e.kind(), message: e.to_string() };
+        let size = fs::metadata(path).map_err(io)?.len();
+        if size > self.options.max_file_size {
+            return Err(FileErrorKind::TooLarge { size, limit: self.options.max_file_size });
+        }
+        let bytes = fs::read(path).map_err(io)?;
+        match (String::from_utf8(bytes), self.options.invalid_utf8) {
+            (Ok(text), _) => Ok(text),
+            (Err(e), InvalidUtf8::Lossy) => Ok(String::from_utf8_lossy(e.as_bytes()).into_owned()),
+            (Err(_), InvalidUtf8::Skip) => Err(FileErrorKind::InvalidUtf8),
+        }
+    }
+    
+    /// Embed the pending chunks and hand out their records
+    fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let pending = std::mem::take(&mut self.pending);
+        let texts: Vec<&str> = pending.iter().map(|(_, _, c)| c.text.as_str()).collect();
+        let embedded = self.provider.embed_batch_with(&texts, &self.options.embed_options).and_then(|vectors| {
+            if vectors.len() == texts.len() { Ok(vectors) } else { Err(EmbedError::Mismatch { expected: texts.len(), got: vectors.len() }) }
+        });
+        match embedded {
+            Ok(vectors) => {
+                for ((path, chunk_index, chunk), embedding) in pending.into_iter().zip(vectors) {
+                    (self.on_record)(FileChunk { path, chunk_index, start: chunk.start, end: chunk.end, embedding });
+                }
+            }
+            Err(e) => {
+                let mut paths: Vec<PathBuf> = pending.into_iter().map(|(path, _, _)| path).collect();
+                paths.dedup();
+                for path in paths {
+                    self.errors.push(FileError { path, kind: FileErrorKind::Embed(e.clone()) });
+                }
+            }
+        }
+    }
+}
+
+/// Suffix `SyncOptions::new` adds to the index path for the manifest
+pub const MANIFEST_SUFFIX: &str = ".manifest.json";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyncOptions {
+    /// What to read and how to chunk and embed it
+    pub files: FileOptions,
+    /// Where the manifest of ingested files is kept between runs
+    pub manifest_path: PathBuf,
+}
+
+impl SyncOptions {
+    /// Default `FileOptions`, the manifest at `index_path` plus `MANIFEST_SUFFIX`
+    pub fn new(index_path: impl AsRef<Path>) -> Self {
+        let mut manifest_path = index_path.as_ref().as_os_str().to_owned();
+        manifest_path.push(MANIFEST_SUFFIX);
+        Self { files: FileOptions::default(), manifest_path: manifest_path.into() }
+    }
+    
+    pub fn with_files(mut self, files: FileOptions) -> Self {
+        self.files = files;
+        self
+    }
+}
+
+/// Files of one `sync_directory` run by what happened to them
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SyncReport {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    /// New paths with the content of a vanished file, moved without embedding
+    pub renamed: usize,
+    /// Unchanged since the last run
+    pub skipped: usize,
+    /// Files left as the last run recorded them; the next run tries again
+    pub errors: Vec<FileError>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Manifest {
+    next_id: u64,
+    /// By path relative to the root, `/`-separated
+    files: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct ManifestEntry {
+    sha256: String,
+    size: u64,
+    mtime_ms: u64,
+    /// One per chunk, in chunk order
+    ids: Vec<u64>,
+}
+
+impl Manifest {
+    fn load(path: &Path) -> Result<Self, String> {
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| format!("Invalid manifest {}: {}", path.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("Cannot read {}: {}", path.display(), e)),
+        }
+    }
+    
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())?;
+        atomic_write(path, |file| file.write_all(&json)).map_err(|e| format!("Write failed for {}: {}", path.display(), e))
+    }
+}
+
+/// Bring `index` up to date with the matching files under the directory
+/// `root`, embedding only new and changed ones, and rewrite the manifest.
+///
+/// Each chunk is an entry with `path` (relative to `root`), `chunk_index`,
+/// `start` and `end` metadata, under ids the manifest hands out. Save the
+/// index after every sync: the manifest describes the index as it is when
+/// `sync_directory` returns.
+pub fn sync_directory<P: EmbeddingProvider + ?Sized>(provider: &P, index: &mut CrystalIndex, root: impl AsRef<Path>,
+                                                     options: &SyncOptions) -> Result<SyncReport, String> {
+    let root = root.as_ref();
+    match fs::metadata(root) {
+        Ok(meta) if meta.is_dir() => {}
+        Ok(_) => return Err(format!("{} is not a directory", root.display())),
+        Err(e) => return Err(format!("Cannot read {}: {}", root.display(), e)),
+    }
+    let mut manifest = Manifest::load(&options.manifest_path)?;
+    // Ids the index holds from elsewhere, or from a run whose manifest was lost
+    manifest.next_id = manifest.next_id.max(index.ids().max().map_or(0, |id| id + 1));
+    let mut report = SyncReport::default();
+    
+    let mut run = Run { provider, options: &options.files, pending: Vec::new(), errors: Vec::new(), on_record: |_| {} };
+    let mut found: Vec<(PathBuf, String)> = Vec::new();
+    run.walk(root, Path::new(""), &mut Vec::new(), &mut |_, path, rel| {
+        found.push((path.to_path_buf(), rel.to_string_lossy().replace(std::path::MAIN_SEPARATOR, "/")));
+    });
+    report.errors = run.errors;
+    
+    // Files to embed, and new files that may be renames
+    let mut changed: Vec<(PathBuf, String, ManifestEntry)> = Vec::new();
+    let mut fresh: Vec<(PathBuf, String, ManifestEntry)> = Vec::new();
+    for (path, key) in &found {
+        let meta = match fs::metadata(path) {
+            Ok(meta) => meta,
+            Err(e) => {
+                report.errors.push(FileError::io(path, e));
+                continue;
+            }
+        };
+        let mtime_ms = meta.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map_or(0, |d| d.as_millis() as u64);
+        let recorded = manifest.files.get_mut(key);
+        if recorded.as_ref().is_some_and(|r| r.size == meta.len() && r.mtime_ms == mtime_ms) {
+            report.skipped += 1;
+            continue;
+        }
+        let hash = match fs::read(path) {
+            Ok(bytes) => to_hex(&sha256(&bytes)),
+            Err(e) => {
+                report.errors.push(FileError::io(path, e));
+                continue;
+            }
+        };
+        let entry = ManifestEntry { sha256: hash, size: meta.len(), mtime_ms, ids: Vec::new() };
+        match recorded {
+            // Touched, not changed
+            Some(recorded) if recorded.sha256 == entry.sha256 => {
+                recorded.size = entry.size;
+                recorded.mtime_ms = entry.mtime_ms;
+                report.skipped += 1;
+            }
+            Some(_) => changed.push((path.clone(), key.clone(), entry)),
+            None => fresh.push((path.clone(), key.clone(), entry)),
+        }
+    }
+    
+    let present: HashSet<&str> = found.iter().map(|(_, key)| key.as_str()).collect();
+    let mut vanished: Vec<String> = manifest.files.keys().filter(|key| !present.contains(key.as_str())).cloned().collect();
+    for (path, key, entry) in fresh {
+        let Some(at) = vanished.iter().position(|old| manifest.files[old].sha256 == entry.sha256) else {
+            changed.push((path, key, entry));
+            continue;
+        };
+        let old = manifest.files.remove(&vanished.remove(at)).unwrap();
+        for &id in &old.ids {
+            move_entry(index, id, &key)?;
+        }
+        manifest.files.insert(key, ManifestEntry { ids: old.ids, ..entry });
+        report.renamed += 1;
+    }
+    for key in vanished {
+        for id in manifest.files.remove(&key).unwrap().ids {
+            index.remove(id);
+        }
+        report.removed += 1;
+    }
+    
+    // Embed what changed; a file that fails keeps its old entries
+    let mut chunks: BTreeMap<PathBuf, Vec<FileChunk>> = BTreeMap::new();
+    let mut run = Run { provider, options: &options.files, pending: Vec::new(), errors: Vec::new(),
+                        on_record: |chunk: FileChunk| chunks.entry(chunk.path.clone()).or_default().push(chunk) };
+    for (path, _, _) in &changed {
+        run.file(path);
+    }
+    run.flush();
+    let failed: HashSet<PathBuf> = run.errors.iter().map(|e| e.path.clone()).collect();
+    report.errors.extend(run.errors);
+    for (path, key, mut entry) in changed {
+        if failed.contains(&path) {
+            continue;
+        }
+        let previous = manifest.files.remove(&key);
+        for chunk in chunks.remove(&path).unwrap_or_default() {
+            let id = manifest.next_id;
+            let metadata = Metadata::new()
+                .with("path", key.as_str())
+                .with("chunk_index", chunk.chunk_index as i64)
+                .with("start", chunk.start as i64)
+                .with("end", chunk.end as i64);
+            index.add_with_metadata(id, &chunk.embedding, metadata).map_err(|e| format!("{}: {}", path.display(), e))?;
+            manifest.next_id += 1;
+            entry.ids.push(id);
+        }
+        match previous {
+            Some(previous) => {
+                for id in previous.ids {
+                    index.remove(id);
+                }
+                report.updated += 1;
+            }
+            None => report.added += 1,
+        }
+        manifest.files.insert(key, entry);
+    }
+    
+    manifest.save(&options.manifest_path)?;
+    Ok(report)
+}
+
+/// Re-add entry `id` with its `path` metadata set to `path`
+fn move_entry(index: &mut CrystalIndex, id: u64, path: &str) -> Result<(), String> {
+    let Some(vector) = index.get(id).map(<[f32]>::to_vec) else { return Ok(()) };
+    let mut metadata = index.metadata(id).cloned().unwrap_or_default();
+    metadata.insert("path", path)?;
+    let sparse = index.sparse(id).cloned();
+    index.remove(id);
+    match sparse {
+        Some(sparse) => index.add_with_sparse(id, &vector, sparse, metadata),
+        None => index.add_with_metadata(id, &vector, metadata),
+    }
+}
+
+fn matches_globs(globs: &[String], rel: &Path) -> bool {
+    if globs.is_empty() {
+        return true;
+    }
+    let rel = rel.to_string_lossy().replace(std::path::MAIN_SEPARATOR, "/");
+    let name = rel.rsplit('/').next().unwrap_or(&rel);
+    globs.iter().any(|g| if g.contains('/') { glob_match(g.as_bytes(), rel.as_bytes()) } else { glob_match(g.as_bytes(), name.as_bytes()) })
+}
+
+/// `*` and `?` within a component, `**/` across any number of them
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            glob_match(rest, text) || text.iter().enumerate()
+                .any(|(i, &c)| c == b'/' && glob_match(rest, &text[i + 1..]))
+        }
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != b'/')
+            .any(|i| glob_match(rest, &text[i..])),
+        [b'?', rest @ ..] => matches!(text, [c, tail @ ..] if *c != b'/' && glob_match(rest, tail)),
+        [p, rest @ ..] => matches!(text, [c, tail @ ..] if c == p && glob_match(rest, tail)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_glob_match() {
+        let m = |p: &str, t: &str| glob_match(p.as_bytes(), t.as_bytes());
+        assert!(m("*.md", "notes.md"));
+        assert!(!m("*.md", "notes.mdx"));
+        assert!(!m("*.md", "docs/notes.md"));
+        assert!(m("docs/**/*.md", "docs/notes.md"));
+        assert!(m("docs/**/*.md", "docs/a/b/notes.md"));
+        assert!(!m("docs/**/*.md", "src/notes.md"));
+        assert!(m("file?.txt", "file1.txt"));
+        assert!(!m("file?.txt", "file/.txt"));
+        
+        let globs = vec!["*.md".to_string(), "src/*.txt".to_string()];
+        assert!(matches_globs(&globs, Path::new("a/b/readme.md")));
+        assert!(matches_globs(&globs, Path::new("src/notes.txt")));
+        assert!(!matches_globs(&globs, Path::new("a/src/notes.txt")));
+        assert!(matches_globs(&[], Path::new("anything")));
+    }
+    
+    #[test]
+    fn test_failed_request_reports_each_file() {
+        let error = EmbedError::Api { status: 503, message: "down".to_string() };
+        let mock = crate::mock::MockProvider::new(2).with_default(vec![1.0, 0.0]).fail_on_call(1, error.clone());
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(dir.path().join(name), "some words").unwrap();
+        }
+        let result = embed_files(&mock, dir.path(), &FileOptions::new().with_batch_size(2));
+        let failed: Vec<&Path> = result.errors.iter().map(|e| e.path.as_path()).collect();
+        assert_eq!(failed, [dir.path().join("a.txt"), dir.path().join("b.txt")]);
+        assert!(result.errors.iter().all(|e| e.kind == FileErrorKind::Embed(error.clone())));
+        assert_eq!(result.records.len(), 1);
+        assert_eq!(result.records[0].path, dir.path().join("c.txt"));
+    }
+}
@@ -0,0 +1,493 @@
+//! Post-processing of parsed embedding batches
+//!
+//! `PostProcess` reshapes vectors before they are cached or returned:
+//! vectors whose norm drifted from 1 are renormalized, MRL truncation keeps
+//! the first components, and int8 rounding gives the values an int8 index
+//! (or a cache sized like one) would hold.
+//!
+//! Batches of at least `parallel_threshold` vectors are processed with
+//! rayon. Every vector goes through the same steps on either path, so the
+//! results are bit-identical and stay in input order.
+//!
+//! A `VectorPipeline` runs `VectorStage`s after that: truncation, random
+//! projection, `LinearAdapter` mapping, renormalization and storage
+//! quantization, or stages of the caller's own. Each stage declares the
+//! dims it takes and makes, so a pipeline whose stages do not fit together
+//! fails as it is built. A client `with_vector_pipeline` reports the output
+//! dims from `dimensions()` and records the stages in its provenance, so an
+//! index refuses query vectors processed differently from its own.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+use rayon::prelude::*;
+
+use crate::adapt::LinearAdapter;
+use crate::error::JinaError;
+use crate::index::Quantization;
+use crate::provenance::MAX_FIELD_LEN;
+use crate::reduce::{ProjectionSpec, RandomProjection};
+use crate::search::{norm, normalize};
+
+/// Smallest batch processed in parallel
+pub const DEFAULT_PARALLEL_THRESHOLD: usize = 256;
+/// Norms within this of 1 are left alone
+pub const NORM_TOLERANCE: f32 = 1e-3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PostProcess {
+    normalize: bool,
+    truncate: Option<usize>,
+    quantization: Quantization,
+    parallel_threshold: usize,
+}
+
+impl Default for PostProcess {
+    fn default() -> Self {
+        Self { normalize: true, truncate: None, quantization: Quantization::None, parallel_threshold: DEFAULT_PARALLEL_THRESHOLD }
+    }
+}
+
+impl PostProcess {
+    /// Renormalization only
+    pub fn new() -> Self { Self::default() }
+    
+    /// Whether to renormalize vectors whose norm is off by more than `NORM_TOLERANCE`
+    pub fn with_normalization(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+    
+    /// Keep the first `dims` components, renormalized
+    pub fn with_truncation(mut self, dims: usize) -> Self {
+        self.truncate = Some(dims);
+        self
+    }
+    
+    /// Round vectors through `quantization`
+    pub fn with_quantization(mut self, quantization: Quantization) -> Self {
+        self.quantization = quantization;
+        self
+    }
+    
+    /// Batches smaller than `n` are processed sequentially
+    pub fn with_parallel_threshold(mut self, n: usize) -> Self {
+        self.parallel_threshold = n;
+        self
+    }
+    
+    /// Whether processed vectors are renormalized
+    pub fn normalizes(&self) -> bool { self.normalize || self.truncate.is_some() }
+    
+    /// Size of processed `dims`-component vectors
+    pub fn output_dims(&self, dims: usize) -> usize { self.truncate.map_or(dims, |t| t.min(dims)) }
+    
+    /// Process `vectors` in place, in parallel for large batches
+    pub fn apply(&self, vectors: &mut [Vec<f32>]) {
+        if vectors.len() >= self.parallel_threshold {
+            vectors.par_iter_mut().for_each(|v| self.apply_one(v));
+        } else {
+            vectors.iter_mut().for_each(|v| self.apply_one(v));
+        }
+    }
+    
+    fn apply_one(&self, v: &mut Vec<f32>) {
+        match self.truncate {
+            Some(dims) if dims < v.len() => {
+                v.truncate(dims);
+                normalize(v);
+            }
+            _ if self.normalize && (norm(v) - 1.0).abs() > NORM_TOLERANCE => normalize(v),
+            _ => {}
+        }
+        if self.quantization != Quantization::None {
+            *v = self.quantization.round(v);
+        }
+    }
+}
+
+/// One step of a `VectorPipeline`
+pub trait VectorStage: Send + Sync {
+    /// Dims the stage accepts; `None` for any
+    fn in_dims(&self) -> Option<usize>;
+    
+    /// Dims of the output for `in_dims` input
+    fn out_dims(&self, in_dims: usize) -> usize;
+    
+    /// Whether outputs are unit vectors: `Some(true)` always, `Some(false)`
+    /// not in general, `None` when inputs' norms are kept
+    fn normalizes(&self) -> Option<bool>;
+    
+    fn apply(&self, v: Vec<f32>) -> Vec<f32>;
+    
+    /// What the stage does, for provenance; stages that map differently must differ here
+    fn describe(&self) -> String;
+    
+    /// How a config rebuilds the stage; `None` for stages only code can build
+    fn spec(&self) -> Option<StageSpec> { None }
+}
+
+/// A built-in stage as a config writes it
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum StageSpec {
+    /// Keep the first `dims` components (MRL), without renormalizing
+    Truncate { dims: usize },
+    Normalize,
+    Project(ProjectionSpec),
+    /// A `LinearAdapter` saved at `path`
+    Adapter { path: String },
+    Quantize { quantization: Quantization },
+}
+
+impl StageSpec {
+    pub fn build(&self) -> Result<Arc<dyn VectorStage>, JinaError> {
+        Ok(match self {
+            StageSpec::Truncate { dims } => Arc::new(Truncate(*dims)),
+            StageSpec::Normalize => Arc::new(Normalize),
+            StageSpec::Project(spec) => Arc::new(RandomProjection::from_spec(*spec)),
+            StageSpec::Adapter { path } => {
+                let adapter = LinearAdapter::load(path).map_err(JinaError::InvalidInput)?;
+                Arc::new(AdapterFile { path: path.clone(), adapter })
+            }
+            StageSpec::Quantize { quantization } => Arc::new(Quantize(*quantization)),
+        })
+    }
+}
+
+/// MRL truncation to this many dims; shorter vectors pass unchanged
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Truncate(pub usize);
+
+impl VectorStage for Truncate {
+    fn in_dims(&self) -> Option<usize> { None }
+    fn out_dims(&self, in_dims: usize) -> usize { in_dims.min(self.0) }
+    fn normalizes(&self) -> Option<bool> { Some(false) }
+    fn apply(&self, mut v: Vec<f32>) -> Vec<f32> {
+        v.truncate(self.0);
+        v
+    }
+    fn describe(&self) -> String { format!("truncate {}", self.0) }
+    fn spec(&self) -> Option<StageSpec> { Some(StageSpec::Truncate { dims: self.0 }) }
+}
+
+/// Scale to unit norm; zero vectors stay zero
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Normalize;
+
+impl VectorStage for Normalize {
+    fn in_dims(&self) -> Option<usize> { None }
+    fn out_dims(&self, in_dims: usize) -> usize { in_dims }
+    fn normalizes(&self) -> Option<bool> { Some(true) }
+    fn apply(&self, mut v: Vec<f32>) -> Vec<f32> {
+        normalize(&mut v);
+        v
+    }
+    fn describe(&self) -> String { "normalize".to_string() }
+    fn spec(&self) -> Option<StageSpec> { Some(StageSpec::Normalize) }
+}
+
+/// Round through a storage quantization
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quantize(pub Quantization);
+
+impl VectorStage for Quantize {
+    fn in_dims(&self) -> Option<usize> { None }
+    fn out_dims(&self, in_dims: usize) -> usize { in_dims }
+    fn normalizes(&self) -> Option<bool> { None }
+    fn apply(&self, v: Vec<f32>) -> Vec<f32> { self.0.round(&v) }
+    fn describe(&self) -> String { format!("quantize {}", self.0.as_str()) }
+    fn spec(&self) -> Option<StageSpec> { Some(StageSpec::Quantize { quantization: self.0 }) }
+}
+
+impl VectorStage for RandomProjection {
+    fn in_dims(&self) -> Option<usize> { Some(RandomProjection::in_dims(self)) }
+    fn out_dims(&self, _: usize) -> usize { RandomProjection::out_dims(self) }
+    fn normalizes(&self) -> Option<bool> { Some(false) }
+    fn apply(&self, v: Vec<f32>) -> Vec<f32> { self.transform(&v).unwrap_or(v) }
+    fn describe(&self) -> String {
+        let spec = self.spec();
+        format!("project {}>{} {:?} seed {}", spec.in_dims, spec.out_dims, spec.mode, spec.seed).to_lowercase()
+    }
+    fn spec(&self) -> Option<StageSpec> { Some(StageSpec::Project(RandomProjection::spec(self))) }
+}
+
+impl VectorStage for LinearAdapter {
+    fn in_dims(&self) -> Option<usize> { Some(LinearAdapter::in_dims(self)) }
+    fn out_dims(&self, _: usize) -> usize { LinearAdapter::out_dims(self) }
+    fn normalizes(&self) -> Option<bool> { Some(false) }
+    fn apply(&self, v: Vec<f32>) -> Vec<f32> { self.transform(&v).unwrap_or(v) }
+    fn describe(&self) -> String {
+        // FNV-1a over the weights, so adapters of one shape still differ
+        let mut h = 0xcbf29ce484222325u64;
+        for w in self.weights().iter().flatten() {
+            for b in w.to_bits().to_le_bytes() {
+                h ^= b as u64;
+                h = h.wrapping_mul(0x100000001b3);
+            }
+        }
+        format!("adapt {}>{} {:016x}", LinearAdapter::in_dims(self), LinearAdapter::out_dims(self), h)
+    }
+}
+
+/// A `LinearAdapter` loaded from a file, which a config can name again
+struct AdapterFile {
+    path: String,
+    adapter: LinearAdapter,
+}
+
+impl VectorStage for AdapterFile {
+    fn in_dims(&self) -> Option<usize> { Some(self.adapter.in_dims()) }
+    fn out_dims(&self, in_dims: usize) -> usize { VectorStage::out_dims(&self.adapter, in_dims) }
+    fn normalizes(&self) -> Option<bool> { Some(false) }
+    fn apply(&self, v: Vec<f32>) -> Vec<f32> { VectorStage::apply(&self.adapter, v) }
+    fn describe(&self) -> String { VectorStage::describe(&self.adapter) }
+    fn spec(&self) -> Option<StageSpec> { Some(StageSpec::Adapter { path: self.path.clone() }) }
+}
+
+/// Stages every vector a client returns goes through, in order
+///
+/// Serializes as its stages' `StageSpec`s, so a config holds the one
+/// pipeline both indexing and querying use; one with a stage only code
+/// can build fails to serialize.
+#[derive(Clone, Default)]
+pub struct VectorPipeline {
+    stages: Vec<Arc<dyn VectorStage>>,
+    parallel_threshold: usize,
+}
+
+impl fmt::Debug for VectorPipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "VectorPipeline({})", self.describe())
+    }
+}
+
+impl VectorPipeline {
+    pub fn new() -> Self { Self { stages: Vec::new(), parallel_threshold: DEFAULT_PARALLEL_THRESHOLD } }
+    
+    /// The pipeline with `stage` appended; fails if `stage` takes other dims
+    /// than the stages before it can produce
+    pub fn then(self, stage: impl VectorStage + 'static) -> Result<Self, JinaError> { self.then_shared(Arc::new(stage)) }
+    
+    pub fn then_shared(mut self, stage: Arc<dyn VectorStage>) -> Result<Self, JinaError> {
+        if let Some(takes) = stage.in_dims() {
+            // Stages of any input still cap it, as truncation does
+            let most = self.stages.iter().fold(usize::MAX, |dims, stage| stage.out_dims(dims));
+            let err = match self.fixed_out_dims() {
+                Some(gets) if gets != takes => format!("produce {}", gets),
+                None if takes > most => format!("produce at most {}", most),
+                _ => String::new(),
+            };
+            if !err.is_empty() {
+                return Err(JinaError::InvalidInput(format!("Stage {} takes {} dims, but the stages before it {}", stage.describe(), takes, err)));
+            }
+        }
+        self.stages.push(stage);
+        Ok(self)
+    }
+    
+    /// The pipeline of `specs`, in order
+    pub fn from_specs(specs: &[StageSpec]) -> Result<Self, JinaError> {
+        specs.iter().try_fold(Self::new(), |pipeline, spec| pipeline.then_shared(spec.build()?))
+    }
+    
+    /// Specs of every stage; fails on a stage only code can build
+    pub fn specs(&self) -> Result<Vec<StageSpec>, JinaError> {
+        self.stages.iter().map(|stage| stage.spec().ok_or_else(|| {
+            JinaError::InvalidInput(format!("Stage {} has no config form", stage.describe()))
+        })).collect()
+    }
+    
+    /// Batches smaller than `n` are processed sequentially
+    pub fn with_parallel_threshold(mut self, n: usize) -> Self {
+        self.parallel_threshold = n;
+        self
+    }
+    
+    pub fn is_empty(&self) -> bool { self.stages.is_empty() }
+    
+    /// Stages as `describe` gives them, joined by ` | `
+    pub fn describe(&self) -> String {
+        self.stages.iter().map(|stage| stage.describe()).collect::<Vec<_>>().join(" | ")
+    }
+    
+    /// `describe`, cut to fit a provenance with a hash of the whole in its place
+    pub fn provenance_tag(&self) -> String {
+        let description = self.describe();
+        if description.len() <= MAX_FIELD_LEN {
+            return description;
+        }
+        let mut cut = MAX_FIELD_LEN - 20;
+        while !description.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        let hash = description.bytes().fold(0xcbf29ce484222325u64, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3));
+        format!("{}..{:016x}", &description[..cut], hash)
+    }
+    
+    /// Dims the first stage to declare its input needs, once earlier stages ran
+    pub fn in_dims(&self) -> Option<usize> { self.stages.iter().find_map(|stage| stage.in_dims()) }
+    
+    /// Dims of processed `in_dims`-component vectors; fails where a stage
+    /// takes other dims than its predecessors produce
+    pub fn output_dims(&self, in_dims: usize) -> Result<usize, JinaError> {
+        self.stages.iter().try_fold(in_dims, |dims, stage| match stage.in_dims() {
+            Some(takes) if takes != dims => Err(JinaError::InvalidInput(format!(
+                "Stage {} takes {} dims, got {}; request dimensions the pipeline accepts", stage.describe(), takes, dims))),
+            _ => Ok(stage.out_dims(dims)),
+        })
+    }
+    
+    /// `output_dims`, or where `in_dims` does not fit, what the stages make of the dims they do take
+    pub(crate) fn reported_dims(&self, in_dims: usize) -> usize {
+        self.output_dims(in_dims).ok().or_else(|| self.fixed_out_dims()).unwrap_or(in_dims)
+    }
+    
+    /// Whether outputs are unit vectors, given whether inputs are
+    pub fn normalizes(&self, inputs_normalized: bool) -> bool {
+        self.stages.iter().fold(inputs_normalized, |normalized, stage| stage.normalizes().unwrap_or(normalized))
+    }
+    
+    /// Run every vector through the stages, in parallel for large batches;
+    /// fails, changing nothing, unless each vector's dims fit
+    pub fn apply(&self, vectors: &mut [Vec<f32>]) -> Result<(), JinaError> {
+        if self.stages.is_empty() {
+            return Ok(());
+        }
+        let mut checked = HashSet::new();
+        for v in vectors.iter() {
+            if checked.insert(v.len()) {
+                self.output_dims(v.len())?;
+            }
+        }
+        let run = |v: &mut Vec<f32>| *v = self.stages.iter().fold(std::mem::take(v), |v, stage| stage.apply(v));
+        if vectors.len() >= self.parallel_threshold {
+            vectors.par_iter_mut().for_each(run);
+        } else {
+            vectors.iter_mut().for_each(run);
+        }
+        Ok(())
+    }
+    
+    /// Output dims once known from the stages alone
+    fn fixed_out_dims(&self) -> Option<usize> {
+        let first = self.stages.iter().position(|stage| stage.in_dims().is_some())?;
+        let start = self.stages[first].in_dims().unwrap();
+        Some(self.stages[first..].iter().fold(start, |dims, stage| stage.out_dims(dims)))
+    }
+}
+
+impl serde::Serialize for VectorPipeline {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.specs().map_err(serde::ser::Error::custom)?.serialize(serializer)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for VectorPipeline {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let specs = Vec::<StageSpec>::deserialize(deserializer)?;
+        Self::from_specs(&specs).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jina_api::{EmbedOptions, JinaClient};
+    use crate::pseudo::PseudoEmbedder;
+    use crate::quantize::Int8Vector;
+    
+    /// Pseudo embeddings with some scaled off unit norm
+    fn batch(n: usize, dims: usize) -> Vec<Vec<f32>> {
+        let embedder = PseudoEmbedder::new(dims);
+        (0..n)
+            .map(|i| {
+                let scale = 1.0 + (i % 5) as f32 * 0.1;
+                embedder.embed(&format!("vector number {}", i)).iter().map(|x| x * scale).collect()
+            })
+            .collect()
+    }
+    
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let configs = [
+            PostProcess::new(),
+            PostProcess::new().with_truncation(128),
+            PostProcess::new().with_quantization(Quantization::Int8),
+            PostProcess::new().with_normalization(false).with_truncation(64).with_quantization(Quantization::Int8),
+        ];
+        for config in configs {
+            let mut sequential = batch(600, 256);
+            let mut parallel = sequential.clone();
+            config.with_parallel_threshold(usize::MAX).apply(&mut sequential);
+            config.with_parallel_threshold(0).apply(&mut parallel);
+            let bits = |vs: &[Vec<f32>]| -> Vec<Vec<u32>> { vs.iter().map(|v| v.iter().map(|x| x.to_bits()).collect()).collect() };
+            assert_eq!(bits(&parallel), bits(&sequential), "{:?}", config);
+        }
+    }
+    
+    #[test]
+    fn test_steps() {
+        let original = batch(5, 64);
+        let mut vectors = original.clone();
+        PostProcess::new().apply(&mut vectors);
+        assert!(vectors.iter().all(|v| (norm(v) - 1.0).abs() <= NORM_TOLERANCE));
+        // Already unit vectors are untouched, order is kept
+        assert_eq!(vectors[0], original[0]);
+        assert_eq!(vectors[3], crate::search::truncate_mrl(&original[3], 64));
+        
+        let mut vectors = original.clone();
+        PostProcess::new().with_truncation(16).with_quantization(Quantization::Int8).apply(&mut vectors);
+        let expected = Int8Vector::quantize(&crate::search::truncate_mrl(&original[2], 16)).dequantize();
+        assert_eq!(vectors[2], expected);
+        assert!(vectors.iter().all(|v| v.len() == 16));
+    }
+    
+    #[test]
+    fn test_client_applies_post_processing() {
+        let client = JinaClient::new("").with_post_process(PostProcess::new().with_truncation(32));
+        let embeddings = client.embed_batch_full(&["Ada", "Lovelace"], &EmbedOptions::default().with_dimensions(128)).unwrap().embeddings;
+        assert!(embeddings.iter().all(|v| v.len() == 32));
+        assert_eq!(embeddings[0], crate::search::truncate_mrl(&PseudoEmbedder::new(128).embed("Ada"), 32));
+    }
+    
+    #[test]
+    fn test_client_vector_pipeline_dims_provenance_and_specs() {
+        use crate::provider::EmbeddingProvider;
+        let projection = RandomProjection::new(256, 64, 7);
+        let pipeline = VectorPipeline::new().then(Truncate(256)).unwrap()
+            .then(projection.clone()).unwrap()
+            .then(Normalize).unwrap();
+        assert_eq!(pipeline.output_dims(1024).unwrap(), 64);
+        let client = JinaClient::new("").with_vector_pipeline(pipeline.clone());
+        assert_eq!(client.dimensions(), 64);
+        // Truncating post-processing counts too, as in provenance
+        let truncated = JinaClient::new("").with_post_process(PostProcess::new().with_truncation(256));
+        assert_eq!(truncated.dimensions(), 256);
+        assert_eq!(truncated.provenance(&EmbedOptions::default()).dimensions, 256);
+        
+        let response = client.embed_batch_full(&["Ada", "Lovelace"], &EmbedOptions::default().with_dimensions(1024)).unwrap();
+        let provenance = response.provenance.clone().unwrap();
+        assert_eq!(provenance.dimensions, 64);
+        assert!(provenance.normalized);
+        assert_eq!(provenance.pipeline.as_deref(), Some(pipeline.describe().as_str()));
+        assert_eq!(pipeline.describe(), format!("truncate 256 | {} | normalize", VectorStage::describe(&projection)));
+        
+        // The same as running each stage by hand
+        let mut manual = vec![PseudoEmbedder::new(1024).embed("Ada")];
+        PostProcess::new().apply(&mut manual);
+        let mut manual = projection.transform(&manual[0][..256]).unwrap();
+        normalize(&mut manual);
+        assert_eq!(response.embeddings[0], manual);
+        
+        // Stages that do not fit fail as the pipeline is built
+        let err = VectorPipeline::new().then(Truncate(128)).unwrap().then(RandomProjection::new(512, 64, 7)).unwrap_err();
+        assert!(err.to_string().contains("takes 512 dims, but the stages before it produce at most 128"), "{}", err);
+        assert!(VectorPipeline::new().then(projection).unwrap().output_dims(1024).is_err());
+        
+        let json = serde_json::to_string(&pipeline).unwrap();
+        assert!(json.starts_with(r#"[{"stage":"truncate","dims":256},{"stage":"project","#), "{}", json);
+        assert_eq!(serde_json::from_str::<VectorPipeline>(&json).unwrap().describe(), pipeline.describe());
+    }
+}
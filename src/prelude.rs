@@ -0,0 +1,21 @@
+//! The canonical types, in one import
+//!
+//! `use spo_crystal::prelude::*;` brings in the client, its options, the
+//! provider trait, the index and the typed error. Each `vN` module is
+//! frozen once released: a later release that changes the set adds `v2`
+//! and points the bare prelude at it, so code pinned to
+//! `spo_crystal::prelude::v1::*` keeps compiling.
+//!
+//! The trait is what gives `JinaClient` typed-error `embed` and
+//! `embed_batch`; its inherent methods of the same names fail with a
+//! `String` and are deprecated.
+
+/// The prelude as of 0.2
+pub mod v1 {
+    pub use crate::error::JinaError;
+    pub use crate::index::CrystalIndex;
+    pub use crate::jina_api::{EmbedOptions, JinaClient};
+    pub use crate::provider::{Embedding, EmbeddingProvider, EmbeddingResponse};
+}
+
+pub use self::v1::*;
@@ -0,0 +1,358 @@
+//! Text cleanup before embedding
+//!
+//! Steps are plain `&str -> String` functions; a `Pipeline` runs a list
+//! of them in order. Set one on `EmbedOptions::preprocess` and the client
+//! cleans every input before dedup, caching and sending, so cache keys are
+//! the cleaned text.
+//!
+//! `BoilerplateDetector::fit` learns the lines a corpus repeats — headers,
+//! footers, disclaimers — from a sample of its documents: a line is
+//! boilerplate when, after collapsing whitespace and lowercasing, it occurs
+//! in at least `min_fraction` of the documents (and in two or more). Paragraphs
+//! repeated whole are stripped line by line. Fit the detector on texts as
+//! they reach its step, e.g. after `StripHtml`, since it matches lines exactly.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::sync::Arc;
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::io::atomic_write;
+
+/// Share of sampled documents a line must occur in to count as boilerplate
+pub const DEFAULT_MIN_FRACTION: f64 = 0.3;
+/// Version of the saved JSON; `load` refuses newer files
+pub const DETECTOR_VERSION: u32 = 1;
+
+/// One preprocessing step
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Step {
+    StripHtml,
+    CollapseWhitespace,
+    NfcNormalize,
+    Lowercase,
+    /// Drop the lines a `BoilerplateDetector` learned
+    StripBoilerplate(Arc<BoilerplateDetector>),
+}
+
+impl Step {
+    pub fn apply(&self, text: &str) -> String {
+        match self {
+            Step::StripHtml => strip_html(text),
+            Step::CollapseWhitespace => collapse_whitespace(text),
+            Step::NfcNormalize => nfc_normalize(text),
+            Step::Lowercase => lowercase(text),
+            Step::StripBoilerplate(detector) => detector.strip(text).text,
+        }
+    }
+}
+
+/// A text after cleanup, with the bytes boilerplate steps removed from it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stripped {
+    pub text: String,
+    pub bytes_stripped: usize,
+}
+
+/// Steps applied in order
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pipeline {
+    pub steps: Vec<Step>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self { Self::default() }
+    
+    /// Scraped pages: strip HTML, collapse whitespace, NFC-normalize
+    pub fn html() -> Self {
+        Self::new().with(Step::StripHtml).with(Step::CollapseWhitespace).with(Step::NfcNormalize)
+    }
+    
+    pub fn with(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
+    
+    pub fn apply(&self, text: &str) -> String {
+        self.steps.iter().fold(text.to_string(), |text, step| step.apply(&text))
+    }
+    
+    /// `apply`, counting the bytes `StripBoilerplate` steps removed
+    pub fn apply_stripped(&self, text: &str) -> Stripped {
+        let start = Stripped { text: text.to_string(), bytes_stripped: 0 };
+        self.steps.iter().fold(start, |done, step| match step {
+            Step::StripBoilerplate(detector) => {
+                let stripped = detector.strip(&done.text);
+                Stripped { text: stripped.text, bytes_stripped: done.bytes_stripped + stripped.bytes_stripped }
+            }
+            step => Stripped { text: step.apply(&done.text), ..done },
+        })
+    }
+}
+
+/// Lines repeated across a corpus, learned from a sample of it
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BoilerplateDetector {
+    version: u32,
+    /// Documents in the fitted sample
+    documents: usize,
+    /// Normalized lines
+    patterns: BTreeSet<String>,
+}
+
+impl BoilerplateDetector {
+    /// Lines in at least `DEFAULT_MIN_FRACTION` of `corpus_sample`
+    pub fn fit(corpus_sample: &[&str]) -> Self { Self::fit_with(corpus_sample, DEFAULT_MIN_FRACTION) }
+    
+    /// Lines in at least `min_fraction` of `corpus_sample`, and in no fewer than two documents
+    pub fn fit_with(corpus_sample: &[&str], min_fraction: f64) -> Self {
+        let mut frequency: HashMap<String, usize> = HashMap::new();
+        for document in corpus_sample {
+            let lines: HashSet<String> = document.lines().map(normalize_line).filter(|l| !l.is_empty()).collect();
+            lines.into_iter().for_each(|line| *frequency.entry(line).or_default() += 1);
+        }
+        let min_documents = ((corpus_sample.len() as f64 * min_fraction).ceil() as usize).max(2);
+        let patterns = frequency.into_iter().filter(|&(_, n)| n >= min_documents).map(|(line, _)| line).collect();
+        Self { version: DETECTOR_VERSION, documents: corpus_sample.len(), patterns }
+    }
+    
+    /// Documents the detector was fitted on
+    pub fn documents(&self) -> usize { self.documents }
+    
+    /// Learned lines, normalized and sorted
+    pub fn patterns(&self) -> impl Iterator<Item = &str> + '_ { self.patterns.iter().map(String::as_str) }
+    
+    pub fn is_boilerplate(&self, line: &str) -> bool { self.patterns.contains(&normalize_line(line)) }
+    
+    /// `text` without its boilerplate lines; other lines, line breaks
+    /// included, are kept byte for byte
+    pub fn strip(&self, text: &str) -> Stripped {
+        let mut kept = String::with_capacity(text.len());
+        for line in text.split_inclusive('\n') {
+            if !self.is_boilerplate(line) {
+                kept.push_str(line);
+            }
+        }
+        Stripped { bytes_stripped: text.len() - kept.len(), text: kept }
+    }
+    
+    /// The detector as a pipeline step
+    pub fn into_step(self) -> Step { Step::StripBoilerplate(Arc::new(self)) }
+    
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        atomic_write(path, |file| file.write_all(json.as_bytes())).map_err(|e| format!("Write failed for {}: {}", path, e))
+    }
+    
+    pub fn load(path: &str) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path).map_err(|e| format!("Cannot open {}: {}", path, e))?;
+        let detector: Self = serde_json::from_str(&json).map_err(|e| format!("Cannot parse {}: {}", path, e))?;
+        if detector.version > DETECTOR_VERSION {
+            return Err(format!("{} is a version {} detector; this build reads up to version {}", path, detector.version, DETECTOR_VERSION));
+        }
+        Ok(detector)
+    }
+}
+
+/// A line as the detector compares it: whitespace collapsed and lowercased
+fn normalize_line(line: &str) -> String {
+    line.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Elements that end a line of text
+const BLOCK_TAGS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "br", "dd", "div", "dl", "dt", "figcaption", "figure",
+    "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "li", "main", "nav", "ol", "p",
+    "pre", "section", "table", "td", "th", "tr", "ul",
+];
+
+/// Elements dropped with their content
+const RAW_TAGS: &[&str] = &["script", "style", "noscript", "template"];
+
+/// Remove tags and comments, turn block elements into newlines, drop
+/// script and style content and decode character entities
+///
+/// Malformed markup never fails: a `<` that opens no tag is kept as text
+/// and an unterminated comment or script runs to the end.
+pub fn strip_html(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(lt) = rest.find(['<', '&']) {
+        out.push_str(&rest[..lt]);
+        rest = &rest[lt..];
+        if rest.starts_with('&') {
+            let (decoded, len) = decode_entity(rest);
+            out.push_str(&decoded);
+            rest = &rest[len..];
+            continue;
+        }
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(end) = tag_end(rest) else {
+            out.push('<');
+            rest = &rest[1..];
+            continue;
+        };
+        let name = tag_name(&rest[1..end]);
+        rest = &rest[end + 1..];
+        if RAW_TAGS.contains(&name.as_str()) {
+            let close = format!("</{}", name);
+            rest = find_ascii_ci(rest, &close).map_or("", |at| &rest[at..]);
+            rest = rest.find('>').map_or("", |gt| &rest[gt + 1..]);
+        } else if BLOCK_TAGS.contains(&name.as_str()) {
+            out.push('\n');
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Index of the `>` closing the tag `s` starts with, skipping quoted attribute values
+fn tag_end(s: &str) -> Option<usize> {
+    let next = s[1..].chars().next()?;
+    if !(next.is_ascii_alphabetic() || next == '/' || next == '!' || next == '?') {
+        return None;
+    }
+    let mut quote = None;
+    for (i, c) in s.char_indices().skip(1) {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '>') => return Some(i),
+            (None, '<') => return None,
+            _ => {}
+        }
+    }
+    // Unbalanced quote: fall back to the first `>`
+    s.find('>')
+}
+
+/// Lowercased element name of a tag body like `/DIV class="x"`
+fn tag_name(body: &str) -> String {
+    body.trim_start_matches('/').chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn find_ascii_ci(haystack: &str, needle: &str) -> Option<usize> {
+    haystack.as_bytes().windows(needle.len()).position(|w| w.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// Decoded text and byte length of the entity `s` starts with; a lone `&` decodes to itself
+fn decode_entity(s: &str) -> (String, usize) {
+    let Some(semi) = s[1..].find(';').map(|i| i + 1).filter(|&i| i <= 12) else {
+        return ("&".to_string(), 1);
+    };
+    let name = &s[1..semi];
+    let decoded = match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => name.strip_prefix('#').and_then(|n| match n.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => n.parse().ok(),
+        }).and_then(char::from_u32),
+    };
+    match decoded {
+        Some(c) => (c.to_string(), semi + 1),
+        None => ("&".to_string(), 1),
+    }
+}
+
+/// Trim, and shrink each whitespace run to one newline if it holds one, else one space
+pub fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut run: Option<bool> = None;  // Some(has_newline) inside a whitespace run
+    for c in text.trim().chars() {
+        if c.is_whitespace() {
+            run = Some(run.unwrap_or(false) || c == '\n');
+            continue;
+        }
+        if let Some(newline) = run.take() {
+            out.push(if newline { '\n' } else { ' ' });
+        }
+        out.push(c);
+    }
+    out
+}
+
+pub fn nfc_normalize(text: &str) -> String { text.nfc().collect() }
+
+pub fn lowercase(text: &str) -> String { text.to_lowercase() }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_strip_html() {
+        let html = "<html><head><style>p { color: red }</style><script>if (a < b) alert(1)</script></head>\
+                    <body><h1>Title</h1><p class=\"x>y\">Fish &amp; chips &lt;3 &#233;&#x41;</p><!-- note --><br/>end</body>";
+        assert_eq!(strip_html(html), "\nTitle\n\nFish & chips <3 éA\n\nend");
+        assert_eq!(strip_html("<SCRIPT type=x>var s = '</b>';</Script>after"), "after");
+        assert_eq!(strip_html("a<b>b</b>c"), "abc");
+    }
+    
+    #[test]
+    fn test_strip_html_malformed() {
+        assert_eq!(strip_html("1 < 2 && 3 > 2"), "1 < 2 && 3 > 2");
+        assert_eq!(strip_html("<p unterminated"), "<p unterminated");
+        assert_eq!(strip_html("x<!-- never closed"), "x");
+        assert_eq!(strip_html("<script>no end"), "");
+        assert_eq!(strip_html("<a href='x>"), "");
+        assert_eq!(strip_html("&bogus; &#xZZ; &#99999999; &"), "&bogus; &#xZZ; &#99999999; &");
+        assert_eq!(strip_html("<<<>>>"), "<<<>>>");
+        assert_eq!(strip_html("<é>ü"), "<é>ü");
+    }
+    
+    #[test]
+    fn test_whitespace_normalization_and_pipeline() {
+        assert_eq!(collapse_whitespace("  a \t b\n\n  c  "), "a b\nc");
+        assert_eq!(collapse_whitespace(" \n "), "");
+        assert_eq!(nfc_normalize("e\u{301}"), "\u{e9}");
+        assert_eq!(lowercase("ÀB İ"), "àb i\u{307}");
+        
+        let pipeline = Pipeline::html().with(Step::Lowercase);
+        assert_eq!(pipeline.apply("<div>  Crème\n<b>BRÛLÉE</b> </div><p>e\u{301}</p>"), "crème\nbrûlée\né");
+        assert_eq!(Pipeline::new().apply(" x "), " x ");
+    }
+    
+    #[test]
+    fn test_boilerplate_planted_footer_stripped() {
+        let corpus: Vec<String> = (0..20).map(|i| {
+            format!("Report {} on topic {}\nFindings differ in case {}.\n\n--\nACME Corp  |  Unsubscribe at acme.example\n", i, i * 7, i)
+        }).collect();
+        let sample: Vec<&str> = corpus.iter().map(String::as_str).collect();
+        let detector = BoilerplateDetector::fit(&sample);
+        assert_eq!(detector.patterns().collect::<Vec<_>>(), ["--", "acme corp | unsubscribe at acme.example"]);
+        
+        // A new document with the footer, respaced and recased
+        let new = "Quarterly numbers\nRevenue grew.\n--\nacme corp | UNSUBSCRIBE at acme.example";
+        let stripped = detector.strip(new);
+        assert_eq!(stripped.text, "Quarterly numbers\nRevenue grew.\n");
+        assert_eq!(stripped.bytes_stripped, new.len() - stripped.text.len());
+        let unique = "Only this text\n  is unique,   odd spacing kept\n";
+        assert_eq!(detector.strip(unique), Stripped { text: unique.to_string(), bytes_stripped: 0 });
+        
+        // As a pipeline step, and persisted
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("boilerplate.json").to_str().unwrap().to_string();
+        detector.save(&path).unwrap();
+        let loaded = BoilerplateDetector::load(&path).unwrap();
+        assert_eq!((&loaded, loaded.documents()), (&detector, 20));
+        let pipeline = Pipeline::new().with(loaded.into_step()).with(Step::CollapseWhitespace);
+        assert_eq!(pipeline.apply(new), "Quarterly numbers\nRevenue grew.");
+        assert_eq!(pipeline.apply_stripped(new).bytes_stripped, stripped.bytes_stripped);
+        
+        // One document alone repeats nothing
+        assert_eq!(BoilerplateDetector::fit(&[new]).patterns().count(), 0);
+    }
+}
@@ -0,0 +1,292 @@
+//! Backend capability discovery
+//!
+//! The Jina API, self-hosted gateways, TEI and OpenAI-compatible servers
+//! accept different request options, and a server that does not know one
+//! answers 400 or 422. `JinaClient::probe` finds out up front with tiny
+//! requests: one plain embedding, whose length is the default size, then
+//! one with every option and, only when that one is refused, one per
+//! option. `GET /info` (served by TEI) supplies a batch limit and the
+//! model's input limit when there are any.
+//!
+//! Probing is optional and lazy: nothing is sent until `probe()` is called,
+//! or until the first embedding request of a client built `with_probe()`.
+//! Once probed, the client fails options the backend refused with
+//! `InvalidInput` before sending anything, expects the probed default size
+//! from requests without `dimensions`, and keeps batches within the limit.
+
+use serde::Deserialize;
+
+use crate::error::{truncate_for_display, JinaError, MAX_DISPLAY_CHARS};
+use crate::jina_api::{write_request_body, EmbedOptions, JinaClient, DEFAULT_DIMS};
+use crate::transport::HttpRequest;
+
+/// Size the option probe asks for; servers that honor `dimensions` return it
+const PROBE_DIMS: usize = 32;
+const PROBE_TEXT: &str = "probe";
+
+/// The limits of a TEI `/info` response
+#[derive(Default, Deserialize)]
+struct Info {
+    max_client_batch_size: Option<usize>,
+    max_input_length: Option<usize>,
+}
+
+/// What a backend accepts
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Capabilities {
+    pub supports_task: bool,
+    /// False also when the server takes `dimensions` but ignores it
+    pub supports_dimensions: bool,
+    pub supports_late_chunking: bool,
+    /// Texts per request: the client's `max_batch_size`, or the server's limit if lower
+    pub max_batch: usize,
+    /// Size of vectors requested without `dimensions`
+    pub default_dims: usize,
+    /// Tokens an input may have, where the server says (TEI's `/info`)
+    #[serde(default)]
+    pub max_input_tokens: Option<usize>,
+}
+
+impl Capabilities {
+    /// `InvalidInput` naming the first option of `options` the backend does not support
+    pub fn check(&self, options: &EmbedOptions) -> Result<(), JinaError> {
+        let unsupported = [
+            (options.task.is_some() && !self.supports_task, "task"),
+            (options.dimensions.is_some() && !self.supports_dimensions, "dimensions"),
+            (options.late_chunking && !self.supports_late_chunking, "late_chunking"),
+        ];
+        match unsupported.iter().find(|(refused, _)| *refused) {
+            Some((_, option)) => Err(JinaError::InvalidInput(format!("This backend doesn't support {}", option))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl JinaClient {
+    /// Probe the backend before the first embedding request
+    pub fn with_probe(mut self) -> Self {
+        self.auto_probe = true;
+        self
+    }
+    
+    /// Capabilities of the backend, probed on the first call and cached.
+    ///
+    /// A failed probe is not cached, so the next call tries again. Offline
+    /// and `with_backend` clients answer without sending requests.
+    pub fn probe(&self) -> Result<Capabilities, JinaError> {
+        if let Some(capabilities) = self.capabilities.get() {
+            return Ok(capabilities.clone());
+        }
+        let found = self.discover()?;
+        Ok(self.capabilities.get_or_init(|| found).clone())
+    }
+    
+    /// What `probe` found, once it has run
+    pub fn capabilities(&self) -> Option<&Capabilities> { self.capabilities.get() }
+    
+    /// Refuse options the probed backend does not support; probes first `with_probe`
+    pub(crate) fn check_capabilities(&self, options: &EmbedOptions) -> Result<(), JinaError> {
+        if self.auto_probe {
+            self.probe()?;
+        }
+        self.capabilities.get().map_or(Ok(()), |c| c.check(options))
+    }
+    
+    fn discover(&self) -> Result<Capabilities, JinaError> {
+        let local = |default_dims| Capabilities {
+            supports_task: true,
+            supports_dimensions: true,
+            supports_late_chunking: false,
+            max_batch: self.settings().max_batch_size,
+            default_dims,
+            max_input_tokens: None,
+        };
+        if let Some(backend) = &self.backend {
+            return Ok(local(backend.dimensions()));
+        }
+        if !self.is_online() {
+            return Ok(local(DEFAULT_DIMS));
+        }
+        
+        let default_dims = self.probe_embed(&EmbedOptions::default())?;
+        let accepts = |options: &EmbedOptions| match self.probe_embed(options) {
+            Ok(dims) => Ok(Some(dims)),
+            Err(JinaError::Api { status: 400 | 422, .. }) => Ok(None),
+            Err(e) => Err(e),
+        };
+        let everything = EmbedOptions::query().with_dimensions(PROBE_DIMS).with_late_chunking();
+        let (supports_task, supports_dimensions, supports_late_chunking) = match accepts(&everything)? {
+            Some(dims) => (true, dims == PROBE_DIMS, true),
+            None => (
+                accepts(&EmbedOptions::query())?.is_some(),
+                accepts(&EmbedOptions::default().with_dimensions(PROBE_DIMS))? == Some(PROBE_DIMS),
+                accepts(&EmbedOptions::default().with_late_chunking())?.is_some(),
+            ),
+        };
+        let info = self.info().unwrap_or_default();
+        let max_batch_size = self.settings().max_batch_size;
+        let max_batch = info.max_client_batch_size.map_or(max_batch_size, |n| n.clamp(1, max_batch_size));
+        let max_input_tokens = info.max_input_length.filter(|&n| n > 0);
+        Ok(Capabilities { supports_task, supports_dimensions, supports_late_chunking, max_batch, default_dims, max_input_tokens })
+    }
+    
+    /// Length of the vector the server returns for one short text under `options`
+    fn probe_embed(&self, options: &EmbedOptions) -> Result<usize, JinaError> {
+        let mut body = String::new();
+        write_request_body(&mut body, &self.model, &[PROBE_TEXT], options);
+        let request = HttpRequest {
+            method: "POST",
+            url: self.embeddings_url(),
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: body.into_bytes(),
+            timeout: None,
+        };
+        let body = self.send(request, &self.settings().retry)?.unwrap_or_default();
+        
+        #[derive(Deserialize)]
+        struct Item { embedding: Vec<f32> }
+        #[derive(Deserialize)]
+        struct Body { data: Vec<Item> }
+        let parsed: Body = serde_json::from_str(&body).map_err(|e| JinaError::Parse(format!("Probe response: {}", truncate_for_display(&e.to_string(), MAX_DISPLAY_CHARS))))?;
+        match parsed.data.first() {
+            Some(item) if !item.embedding.is_empty() => Ok(item.embedding.len()),
+            _ => Err(JinaError::Parse("Probe response has no embedding".to_string())),
+        }
+    }
+    
+    /// A TEI-style `GET /info`; `None` when the server has no such route
+    fn info(&self) -> Option<Info> {
+        let body = self.send(HttpRequest::get(format!("{}/info", self.base_url)), &self.settings().retry).ok()??;
+        serde_json::from_str(&body).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    
+    use crate::transport::{HttpResponse, RetryPolicy};
+    
+    /// How an emulated server treats each option
+    #[derive(Clone, Copy)]
+    enum Treatment { Honor, Ignore, Refuse }
+    
+    struct Server {
+        task: Treatment,
+        dimensions: Treatment,
+        late_chunking: Treatment,
+        default_dims: usize,
+        /// TEI's `/info` limit
+        info_batch: Option<usize>,
+    }
+    
+    /// Client against `server`, logging each request as `"GET /info"` or its JSON body
+    fn client(server: Server) -> (JinaClient, Arc<Mutex<Vec<String>>>) {
+        let log: Arc<Mutex<Vec<String>>> = Arc::default();
+        let seen = log.clone();
+        let client = JinaClient::new("jina_test").with_retry(RetryPolicy::none()).with_transport(move |request: &HttpRequest| {
+            let respond = |status, body: String| Ok(HttpResponse { status, headers: Vec::new(), body });
+            if request.method == "GET" {
+                seen.lock().unwrap().push("GET /info".to_string());
+                return match server.info_batch {
+                    Some(n) => respond(200, serde_json::json!({ "max_client_batch_size": n, "max_input_length": 512 }).to_string()),
+                    None => respond(404, "Not Found".to_string()),
+                };
+            }
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            seen.lock().unwrap().push(body.to_string());
+            let mut dims = server.default_dims;
+            for (field, treatment) in [("task", server.task), ("dimensions", server.dimensions), ("late_chunking", server.late_chunking)] {
+                match (body.get(field), treatment) {
+                    (Some(_), Treatment::Refuse) => {
+                        return respond(422, format!(r#"{{"error":"unknown field `{}`"}}"#, field));
+                    }
+                    (Some(value), Treatment::Honor) if field == "dimensions" => dims = value.as_u64().unwrap() as usize,
+                    _ => {}
+                }
+            }
+            let data: Vec<_> = body["input"].as_array().unwrap().iter()
+                .map(|_| serde_json::json!({ "embedding": vec![0.1f32; dims] }))
+                .collect();
+            respond(200, serde_json::json!({ "data": data }).to_string())
+        });
+        (client, log)
+    }
+    
+    #[test]
+    fn test_probe_three_backends() {
+        // The Jina API: every option, no /info
+        let (jina, log) = client(Server {
+            task: Treatment::Honor, dimensions: Treatment::Honor, late_chunking: Treatment::Honor,
+            default_dims: 1024, info_batch: None,
+        });
+        let expected = Capabilities {
+            supports_task: true, supports_dimensions: true, supports_late_chunking: true, max_batch: 2048, default_dims: 1024,
+            max_input_tokens: None,
+        };
+        assert_eq!(jina.probe().unwrap(), expected);
+        assert_eq!(log.lock().unwrap().len(), 3);
+        // Cached: no further requests
+        assert_eq!(jina.capabilities(), Some(&expected));
+        jina.probe().unwrap();
+        assert_eq!(log.lock().unwrap().len(), 3);
+        
+        // TEI: none of the options, 768 dims and a batch limit from /info
+        let (tei, log) = client(Server {
+            task: Treatment::Refuse, dimensions: Treatment::Refuse, late_chunking: Treatment::Refuse,
+            default_dims: 768, info_batch: Some(32),
+        });
+        assert_eq!(tei.probe().unwrap(), Capabilities {
+            supports_task: false, supports_dimensions: false, supports_late_chunking: false, max_batch: 32, default_dims: 768,
+            max_input_tokens: Some(512),
+        });
+        // A model's window in the server's words beats the table's
+        assert_eq!((tei.context_limit(), jina.context_limit()), (Some(512), Some(8192)));
+        assert_eq!(log.lock().unwrap().len(), 6);
+        
+        // An OpenAI-style gateway: takes task, silently ignores dimensions, refuses late chunking
+        let (gateway, _) = client(Server {
+            task: Treatment::Ignore, dimensions: Treatment::Ignore, late_chunking: Treatment::Refuse,
+            default_dims: 1536, info_batch: None,
+        });
+        assert_eq!(gateway.probe().unwrap(), Capabilities {
+            supports_task: true, supports_dimensions: false, supports_late_chunking: false, max_batch: 2048, default_dims: 1536,
+            max_input_tokens: None,
+        });
+    }
+    
+    #[test]
+    fn test_probed_client_fails_fast_and_adapts() {
+        let (tei, log) = client(Server {
+            task: Treatment::Refuse, dimensions: Treatment::Refuse, late_chunking: Treatment::Refuse,
+            default_dims: 768, info_batch: Some(2),
+        });
+        let tei = tei.with_probe();
+        // The probe runs before the first request; refused options never go out
+        let error = tei.embed_batch_full(&["a"], &EmbedOptions::passage()).unwrap_err();
+        assert_eq!(error, JinaError::InvalidInput("This backend doesn't support task".to_string()));
+        let probed = log.lock().unwrap().len();
+        assert_eq!(tei.embed_batch_full(&["a"], &EmbedOptions::default().with_late_chunking()).unwrap_err(),
+                   JinaError::InvalidInput("This backend doesn't support late_chunking".to_string()));
+        assert_eq!(log.lock().unwrap().len(), probed);
+        
+        // Plain requests expect the probed size and respect the probed batch limit
+        let response = tei.embed_batch_full(&["a", "b", "c"], &EmbedOptions::default()).unwrap();
+        assert!(response.embeddings.iter().all(|v| v.len() == 768));
+        assert_eq!(response.provenance.unwrap().dimensions, 768);
+        assert_eq!(log.lock().unwrap().len(), probed + 2);
+        
+        // Without a probe nothing is checked or sent up front
+        let (lazy, log) = client(Server {
+            task: Treatment::Refuse, dimensions: Treatment::Honor, late_chunking: Treatment::Honor,
+            default_dims: 1024, info_batch: None,
+        });
+        assert!(matches!(lazy.embed_batch_full(&["a"], &EmbedOptions::passage()), Err(JinaError::Api { status: 422, .. })));
+        assert_eq!(log.lock().unwrap().len(), 1);
+        assert_eq!(lazy.capabilities(), None);
+        
+        let offline = JinaClient::new("").probe().unwrap();
+        assert_eq!((offline.default_dims, offline.supports_late_chunking), (1024, false));
+    }
+}
@@ -0,0 +1,208 @@
+//! Where embeddings came from
+//!
+//! Vectors are only comparable when they were produced by the same model,
+//! at the same size, for the same task and with the same normalization.
+//! A `Provenance` records those. `JinaClient` attaches one to every
+//! `EmbeddingResponse`, and a `CrystalIndex` created `with_provenance`
+//! stores it in its file. `add_checked` and `search_checked` refuse
+//! vectors with another provenance unless told to `Override`.
+//!
+//! Pseudo-embeddings from the offline embedder are `synthetic`: the flag
+//! feeds the fingerprint like every other field, so synthetic and real
+//! vectors never pass for each other. So do the client's `VectorPipeline`
+//! stages: vectors truncated, projected or adapted on the way out are
+//! another space than the same model's raw ones.
+
+use std::fmt;
+
+/// Version of this crate's embedding pipeline, bumped when the same inputs
+/// would embed differently
+pub const SCHEMA_VERSION: u32 = 1;
+/// Longest model, task or pipeline name a file can record
+pub const MAX_FIELD_LEN: usize = 255;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct Provenance {
+    pub model: String,
+    pub dimensions: usize,
+    /// Task adapter, e.g. `retrieval.passage`
+    #[serde(default)]
+    pub task: Option<String>,
+    /// Vectors were renormalized on the client
+    #[serde(default)]
+    pub normalized: bool,
+    /// Pseudo-embeddings, not a model's vectors
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub synthetic: bool,
+    /// Client-side `VectorPipeline` stages, as it describes them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pipeline: Option<String>,
+    /// Payloads from before it was recorded are schema 1
+    #[serde(default = "first_schema_version")]
+    pub schema_version: u32,
+}
+
+fn first_schema_version() -> u32 { 1 }
+
+impl Provenance {
+    pub fn new(model: &str, dimensions: usize) -> Self {
+        Self {
+            model: model.to_string(),
+            dimensions,
+            task: None,
+            normalized: false,
+            synthetic: false,
+            pipeline: None,
+            schema_version: SCHEMA_VERSION,
+        }
+    }
+    
+    pub fn with_task(mut self, task: &str) -> Self {
+        self.task = Some(task.to_string());
+        self
+    }
+    
+    pub fn with_normalized(mut self, normalized: bool) -> Self {
+        self.normalized = normalized;
+        self
+    }
+    
+    pub fn with_synthetic(mut self, synthetic: bool) -> Self {
+        self.synthetic = synthetic;
+        self
+    }
+    
+    pub fn with_pipeline(mut self, pipeline: &str) -> Self {
+        self.pipeline = Some(pipeline.to_string());
+        self
+    }
+    
+    /// FNV-1a over the encoded fields; equal provenances have equal fingerprints
+    pub fn fingerprint(&self) -> u64 {
+        let mut h = 0xcbf29ce484222325u64;
+        for &b in &self.encode() {
+            h ^= b as u64;
+            h = h.wrapping_mul(0x100000001b3);
+        }
+        h
+    }
+    
+    /// Serialize for file headers; fails on names over `MAX_FIELD_LEN` bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let task = self.task.as_deref().unwrap_or("");
+        let pipeline = self.pipeline.as_deref().unwrap_or("");
+        for (field, value) in [("Model name", self.model.as_str()), ("Task", task), ("Pipeline", pipeline)] {
+            if value.len() > MAX_FIELD_LEN {
+                return Err(format!("{} is {} bytes, over the {} byte limit", field, value.len(), MAX_FIELD_LEN));
+            }
+        }
+        Ok(self.encode())
+    }
+    
+    /// Deserialize from the start of `bytes`; returns the provenance and bytes consumed
+    pub fn from_bytes(bytes: &[u8]) -> Option<(Self, usize)> {
+        let fixed = bytes.get(..9)?;
+        let schema_version = u32::from_le_bytes(fixed[..4].try_into().ok()?);
+        let dimensions = u32::from_le_bytes(fixed[4..8].try_into().ok()?) as usize;
+        // Flags: bit 0 normalized, bit 1 synthetic, bit 2 a pipeline name follows the task
+        let (normalized, synthetic, piped) = (fixed[8] & 1 != 0, fixed[8] & 2 != 0, fixed[8] & 4 != 0);
+        let mut pos = 9;
+        let mut name = || -> Option<String> {
+            let len = *bytes.get(pos)? as usize;
+            let s = std::str::from_utf8(bytes.get(pos + 1..pos + 1 + len)?).ok()?.to_string();
+            pos += 1 + len;
+            Some(s)
+        };
+        let model = name()?;
+        let task = Some(name()?).filter(|t| !t.is_empty());
+        let pipeline = if piped { Some(name()?) } else { None };
+        Some((Self { model, dimensions, task, normalized, synthetic, pipeline, schema_version }, pos))
+    }
+    
+    /// Bytes `from_bytes` may need, at most
+    pub(crate) const MAX_ENCODED_LEN: usize = 9 + 3 * (1 + MAX_FIELD_LEN);
+    
+    fn encode(&self) -> Vec<u8> {
+        let task = self.task.as_deref().unwrap_or("");
+        let mut bytes = Vec::with_capacity(12 + self.model.len() + task.len() + self.pipeline.as_ref().map_or(0, String::len));
+        bytes.extend_from_slice(&self.schema_version.to_le_bytes());
+        bytes.extend_from_slice(&(self.dimensions as u32).to_le_bytes());
+        bytes.push(self.normalized as u8 | (self.synthetic as u8) << 1 | (self.pipeline.is_some() as u8) << 2);
+        for name in [self.model.as_str(), task].into_iter().chain(self.pipeline.as_deref()) {
+            let name = &name.as_bytes()[..name.len().min(MAX_FIELD_LEN)];
+            bytes.push(name.len() as u8);
+            bytes.extend_from_slice(name);
+        }
+        bytes
+    }
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {} dims", self.model, self.dimensions)?;
+        if let Some(task) = &self.task {
+            write!(f, " for {}", task)?;
+        }
+        if self.normalized {
+            write!(f, ", normalized")?;
+        }
+        if self.synthetic {
+            write!(f, ", synthetic")?;
+        }
+        if let Some(pipeline) = &self.pipeline {
+            write!(f, ", via {}", pipeline)?;
+        }
+        write!(f, " (schema {})", self.schema_version)
+    }
+}
+
+/// Whether checked index calls enforce provenance
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProvenanceCheck {
+    /// Refuse vectors whose provenance differs from the index's, or is unknown
+    #[default]
+    Enforce,
+    /// Accept them anyway
+    Override,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_bytes_roundtrip_and_fingerprint() {
+        let provenance = Provenance::new("jina-embeddings-v3", 1024).with_task("retrieval.passage").with_normalized(true)
+            .with_synthetic(true).with_pipeline("truncate 256 | normalize");
+        let bytes = provenance.to_bytes().unwrap();
+        assert_eq!(Provenance::from_bytes(&bytes), Some((provenance.clone(), bytes.len())));
+        let untasked = Provenance::new("jina-embeddings-v3", 1024);
+        assert_eq!(Provenance::from_bytes(&untasked.to_bytes().unwrap()).unwrap().0, untasked);
+        for cut in 0..bytes.len() {
+            assert_eq!(Provenance::from_bytes(&bytes[..cut]), None);
+        }
+        
+        // Every field feeds the fingerprint
+        let variants = [
+            untasked.clone(),
+            Provenance::new("jina-embeddings-v3", 512),
+            Provenance::new("jina-embeddings-v2-base-en", 1024),
+            untasked.clone().with_task("retrieval.query"),
+            untasked.clone().with_normalized(true),
+            untasked.clone().with_synthetic(true),
+            untasked.clone().with_pipeline("normalize"),
+            Provenance { schema_version: SCHEMA_VERSION + 1, ..untasked.clone() },
+        ];
+        let fingerprints: std::collections::HashSet<u64> = variants.iter().map(Provenance::fingerprint).collect();
+        assert_eq!(fingerprints.len(), variants.len());
+        assert_eq!(untasked.fingerprint(), Provenance::new("jina-embeddings-v3", 1024).fingerprint());
+    }
+    
+    #[test]
+    fn test_long_names_are_refused() {
+        let provenance = Provenance::new(&"m".repeat(MAX_FIELD_LEN + 1), 8);
+        assert!(provenance.to_bytes().unwrap_err().contains("over the 255 byte limit"));
+        assert!(Provenance::new(&"m".repeat(MAX_FIELD_LEN), 8).to_bytes().is_ok());
+        assert_eq!(provenance.to_string(), format!("{} at 8 dims (schema 1)", "m".repeat(256)));
+    }
+}
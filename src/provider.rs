@@ -0,0 +1,206 @@
+//! Embedding backends behind one object-safe trait
+//!
+//! Search and cache helpers take `&dyn EmbeddingProvider` (or a generic
+//! `P: EmbeddingProvider + ?Sized`), so `JinaClient`, the offline
+//! `PseudoEmbedder` or a test double can be swapped without feature flags,
+//! and providers can be boxed in configs.
+//!
+//! Providers whose vectors are not semantic (the pseudo-embedder) say so
+//! through `is_synthetic`; responses record it as `BackendKind::Offline`
+//! and provenances as `synthetic`, so synthetic vectors are never mistaken
+//! for real ones downstream.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+pub use crate::error::EmbedError;
+use crate::jina_api::EmbedOptions;
+use crate::provenance::Provenance;
+use crate::sparse::{HybridEmbedding, SparseVector};
+use crate::transport::Diagnostics;
+
+/// One dense vector, as `EmbeddingProvider::embed` returns it
+pub type Embedding = Vec<f32>;
+
+/// Token accounting reported by a backend
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Usage {
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub total_tokens: u64,
+}
+
+impl Usage {
+    pub fn add(&mut self, other: &Usage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.total_tokens += other.total_tokens;
+    }
+}
+
+/// What produced a response's vectors
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// An embedding service over HTTP
+    #[default]
+    Remote,
+    /// An `EmbeddingProvider` set `with_backend`
+    Provider,
+    /// Pseudo-embeddings: deterministic, but not semantic
+    Offline,
+}
+
+impl BackendKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BackendKind::Remote => "remote",
+            BackendKind::Provider => "provider",
+            BackendKind::Offline => "offline",
+        }
+    }
+    
+    /// Whether the vectors are pseudo-embeddings
+    pub fn is_synthetic(self) -> bool { self == BackendKind::Offline }
+}
+
+/// Vectors in input order plus the usage they cost
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EmbeddingResponse {
+    pub embeddings: Vec<Vec<f32>>,
+    #[serde(default)]
+    pub usage: Usage,
+    /// Set by `JinaClient::embed_batch_diagnosed`
+    #[serde(default)]
+    pub diagnostics: Option<Diagnostics>,
+    /// How the vectors were embedded, checked by `CrystalIndex::add_response`; set by `JinaClient`
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+    /// Inputs over the context budget whose vector pools their chunks
+    /// (`LongInputStrategy::ChunkAndPool`), in input order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pooled: Vec<usize>,
+    /// Set by `JinaClient`
+    #[serde(default)]
+    pub backend: BackendKind,
+}
+
+/// Vector size learned from a backend's first response; later responses must match
+#[derive(Debug, Default)]
+pub(crate) struct LearnedDims(AtomicUsize);
+
+impl LearnedDims {
+    /// Already known size; responses are checked against it
+    pub fn known(dims: usize) -> Self { Self(AtomicUsize::new(dims)) }
+    
+    /// Learned size, 0 before any response
+    pub fn get(&self) -> usize { self.0.load(Ordering::Relaxed) }
+    
+    pub fn check(&self, embeddings: &[Vec<f32>]) -> Result<(), EmbedError> {
+        let Some(first) = embeddings.first() else { return Ok(()) };
+        let expected = match self.0.compare_exchange(0, first.len(), Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => first.len(),
+            Err(known) => known,
+        };
+        check_dims(embeddings, expected)
+    }
+}
+
+/// Every vector has `expected` components
+pub(crate) fn check_dims(embeddings: &[Vec<f32>], expected: usize) -> Result<(), EmbedError> {
+    match embeddings.iter().find(|v| v.len() != expected) {
+        Some(v) => Err(EmbedError::Mismatch { expected, got: v.len() }),
+        None => Ok(()),
+    }
+}
+
+pub trait EmbeddingProvider: Send + Sync {
+    fn embed(&self, text: &str) -> Result<Vec<f32>, EmbedError> {
+        self.embed_batch(&[text])?
+            .pop()
+            .ok_or(EmbedError::Mismatch { expected: 1, got: 0 })
+    }
+    
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbedError>;
+    
+    /// Batch embed with per-request options; backends without task or size
+    /// support ignore them
+    fn embed_batch_with(&self, texts: &[&str], options: &EmbedOptions) -> Result<Vec<Vec<f32>>, EmbedError> {
+        let _ = options;
+        self.embed_batch(texts)
+    }
+    
+    /// Length of the vectors `embed_batch` returns
+    fn dimensions(&self) -> usize;
+    
+    /// Whether the vectors are pseudo-embeddings rather than a model's
+    fn is_synthetic(&self) -> bool { false }
+    
+    /// Sparse lexical-weight vectors, for backends that return them
+    fn embed_sparse(&self, texts: &[&str]) -> Result<Vec<SparseVector>, EmbedError> {
+        let _ = texts;
+        Err(EmbedError::InvalidInput("This backend doesn't return sparse embeddings".to_string()))
+    }
+    
+    /// Dense and sparse vector of each text
+    fn embed_hybrid(&self, texts: &[&str]) -> Result<Vec<HybridEmbedding>, EmbedError> {
+        let sparse = self.embed_sparse(texts)?;
+        let dense = self.embed_batch(texts)?;
+        if dense.len() != sparse.len() {
+            return Err(EmbedError::Mismatch { expected: sparse.len(), got: dense.len() });
+        }
+        Ok(dense.into_iter().zip(sparse).map(|(dense, sparse)| HybridEmbedding { dense, sparse }).collect())
+    }
+}
+
+/// Forward through smart pointers, so shared or boxed providers are providers too
+macro_rules! forward_provider {
+    ($($ptr:ident),*) => {$(
+        impl<P: EmbeddingProvider + ?Sized> EmbeddingProvider for $ptr<P> {
+            fn embed(&self, text: &str) -> Result<Vec<f32>, EmbedError> { (**self).embed(text) }
+            
+            fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbedError> { (**self).embed_batch(texts) }
+            
+            fn embed_batch_with(&self, texts: &[&str], options: &EmbedOptions) -> Result<Vec<Vec<f32>>, EmbedError> {
+                (**self).embed_batch_with(texts, options)
+            }
+            
+            fn dimensions(&self) -> usize { (**self).dimensions() }
+            
+            fn is_synthetic(&self) -> bool { (**self).is_synthetic() }
+            
+            fn embed_sparse(&self, texts: &[&str]) -> Result<Vec<SparseVector>, EmbedError> { (**self).embed_sparse(texts) }
+            
+            fn embed_hybrid(&self, texts: &[&str]) -> Result<Vec<HybridEmbedding>, EmbedError> { (**self).embed_hybrid(texts) }
+        }
+    )*};
+}
+
+forward_provider!(Box, Arc);
+
+/// Test double: offline embeddings plus a count of texts embedded
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jina_api::JinaClient;
+    use crate::pseudo::PseudoEmbedder;
+    
+    #[test]
+    fn test_providers_are_interchangeable() {
+        let providers: Vec<Box<dyn EmbeddingProvider>> = vec![
+            Box::new(JinaClient::new("test_key")),
+            Box::new(PseudoEmbedder::new(256)),
+            Box::new(crate::mock::MockProvider::new(64).with_default(vec![0.125; 64])),
+        ];
+        for provider in &providers {
+            let batch = provider.embed_batch(&["Ada", "Jan"]).unwrap();
+            assert_eq!(batch.len(), 2);
+            assert!(batch.iter().all(|v| v.len() == provider.dimensions()));
+            assert_eq!(provider.embed("Jan").unwrap(), batch[1]);
+        }
+        
+        // Boxed providers are providers too
+        let boxed: Box<dyn EmbeddingProvider> = Box::new(PseudoEmbedder::new(32));
+        assert_eq!(crate::search::semantic(&boxed, "Ada", &["Jan", "Ada"], 1).unwrap()[0].0, 1);
+    }
+}
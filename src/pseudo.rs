@@ -0,0 +1,335 @@
+//! Offline pseudo-embeddings
+//!
+//! `PseudoEmbedder` stands in for a real embedding API in tests and CI:
+//! texts sharing words and character 3-grams land close in cosine space,
+//! unrelated texts land near-orthogonal.
+//!
+//! Text is NFC-normalized first, so composed and decomposed forms embed
+//! identically. Words come from Unicode (UAX #29) word segmentation, so
+//! scripts written without spaces (CJK) still yield per-word features,
+//! and 3-grams are over `char`s rather than bytes.
+//!
+//! Stability: for a given `(dims, seed, text)` the output is bit-identical
+//! on every platform and Rust release (for characters assigned in the
+//! Unicode version of the normalization and segmentation tables in use).
+//! Hashing is explicit (FNV-1a plus a splitmix64 finalizer), features are
+//! accumulated in a fixed order, and only correctly rounded float ops
+//! (`+`, `*`, `/`, `sqrt`) are used. Changing the output for an existing
+//! `(dims, seed, text)` is a breaking change; the golden-vector tests
+//! below guard it.
+//!
+//! Breaking changes so far:
+//! - Unicode features: text is NFC-normalized, and whitespace tokens are
+//!   split into UAX #29 words instead of trimmed of punctuation at their
+//!   ends. Tokens joined by inner punctuation (`well-known`, `a/b`, `x,y`)
+//!   now give one word per part, and non-NFC text embeds as its NFC form;
+//!   vectors of such text differ from releases before the change. Tokens
+//!   that are words already (`cat.`, `don't`, `Ada`) embed as before.
+
+use std::borrow::Cow;
+
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::provider::{EmbedError, EmbeddingProvider};
+use crate::sparse::SparseVector;
+
+pub(crate) const WORD_WEIGHT: f32 = 1.0;
+pub(crate) const TRIGRAM_WEIGHT: f32 = 0.5;
+pub(crate) const KIND_WORD: u8 = b'w';
+pub(crate) const KIND_TRIGRAM: u8 = b'g';
+
+/// Deterministic, seedable offline embedder
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PseudoEmbedder {
+    dims: usize,
+    seed: u64,
+    normalize: bool,
+}
+
+impl PseudoEmbedder {
+    /// Embedder producing `dims`-dimensional vectors (at least 1)
+    pub fn new(dims: usize) -> Self {
+        Self { dims: dims.max(1), seed: 0, normalize: true }
+    }
+    
+    /// Independent embedding space: different seeds hash features differently
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+    
+    /// `false` returns the raw accumulated feature weights instead of unit vectors
+    pub fn with_normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+    
+    pub fn dimensions(&self) -> usize { self.dims }
+    
+    pub fn seed(&self) -> u64 { self.seed }
+    
+    /// Embed one text; whitespace-only text gives the zero vector, anything else is unit norm
+    /// (barring exact sign cancellation between colliding features, possible only at tiny `dims`)
+    pub fn embed(&self, text: &str) -> Vec<f32> {
+        let mut embedding = vec![0.0f32; self.dims];
+        self.generate_into(&mut embedding, text);
+        embedding
+    }
+    
+    pub fn embed_batch(&self, texts: &[&str]) -> Vec<Vec<f32>> {
+        texts.iter().map(|t| self.embed(t)).collect()
+    }
+    
+    /// `embed` into a caller-owned buffer of `dimensions()` floats, overwriting it
+    pub fn generate_into(&self, out: &mut [f32], text: &str) {
+        assert_eq!(out.len(), self.dims, "output buffer must have {} dims", self.dims);
+        
+        out.fill(0.0);
+        for (i, weight) in self.features(text) {
+            out[i] += weight;
+        }
+        
+        if self.normalize {
+            let norm: f32 = out.iter().map(|x| x * x).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                for x in out.iter_mut() { *x /= norm; }
+            }
+        }
+    }
+    
+    /// Sparse `(bucket, signed weight)` per distinct feature, before scattering.
+    ///
+    /// Buckets repeat when features collide; summing the pairs in order into
+    /// a zero vector gives exactly the un-normalized dense embedding.
+    pub fn features(&self, text: &str) -> Vec<(usize, f32)> {
+        self.feature_keys(text)
+            .chunk_by(|a, b| a == b)
+            .map(|run| {
+                let (h, kind) = run[0];
+                let weight = if kind == KIND_WORD { WORD_WEIGHT } else { TRIGRAM_WEIGHT };
+                let sign = if h >> 63 == 0 { 1.0 } else { -1.0 };
+                ((h % self.dims as u64) as usize, sign * weight * (run.len() as f32).sqrt())
+            })
+            .collect()
+    }
+    
+    /// Sparse lexical vector at unit norm: each distinct word is hashed to a
+    /// `u32` index and weighted by its count. Without 3-grams, only shared
+    /// words match.
+    pub fn embed_sparse(&self, text: &str) -> SparseVector {
+        let words = self.feature_keys(text).into_iter().filter(|&(_, kind)| kind == KIND_WORD);
+        SparseVector::from_pairs(words.map(|(h, _)| (h as u32, WORD_WEIGHT))).normalized()
+    }
+    
+    /// Feature hashes + kinds, one per occurrence, sorted so the
+    /// accumulation order is fixed; equal keys are one feature
+    fn feature_keys(&self, text: &str) -> Vec<(u64, u8)> {
+        let mut features: Vec<(u64, u8)> = Vec::with_capacity(text.len() + text.len() / 4);
+        for_each_feature(text, |kind, feature| features.push((self.hash(kind, feature), kind)));
+        features.sort_unstable();
+        features
+    }
+    
+    /// FNV-1a over kind byte + feature, mixed with the seed
+    fn hash(&self, kind: u8, feature: &[u8]) -> u64 {
+        let mut h: u64 = 0xcbf29ce484222325;
+        for &b in std::iter::once(&kind).chain(feature) {
+            h ^= b as u64;
+            h = h.wrapping_mul(0x100000001b3);
+        }
+        splitmix64(h ^ splitmix64(self.seed))
+    }
+}
+
+/// Call `f` with the kind and bytes of every feature occurrence of `text`:
+/// each word, then its `char` 3-grams with `<` and `>` marking the word ends
+pub(crate) fn for_each_feature(text: &str, mut f: impl FnMut(u8, &[u8])) {
+    let mut lower = String::new();
+    let mut chars: Vec<char> = Vec::new();
+    let text = nfc(text);
+    for token in text.split_whitespace() {
+        lower.clear();
+        if token.is_ascii() {
+            lower.push_str(token);
+            lower.make_ascii_lowercase();
+        } else {
+            lower.push_str(&token.to_lowercase());
+        }
+        
+        // Tokens without any word (punctuation, emoji) count as one word
+        let mut words = lower.unicode_words().peekable();
+        let fallback = words.peek().is_none().then_some(lower.as_str());
+        for word in words.chain(fallback) {
+            f(KIND_WORD, word.as_bytes());
+            
+            chars.clear();
+            chars.push('<');
+            chars.extend(word.chars());
+            chars.push('>');
+            for gram in chars.windows(3) {
+                let mut bytes = [0u8; 12];
+                let mut len = 0;
+                for c in gram {
+                    len += c.encode_utf8(&mut bytes[len..]).len();
+                }
+                f(KIND_TRIGRAM, &bytes[..len]);
+            }
+        }
+    }
+}
+
+/// NFC form of `text`, borrowed when it already is NFC
+fn nfc(text: &str) -> Cow<'_, str> {
+    match is_nfc_quick(text.chars()) {
+        IsNormalized::Yes => Cow::Borrowed(text),
+        _ => Cow::Owned(text.nfc().collect()),
+    }
+}
+
+impl EmbeddingProvider for PseudoEmbedder {
+    fn embed(&self, text: &str) -> Result<Vec<f32>, EmbedError> { Ok(PseudoEmbedder::embed(self, text)) }
+    
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        Ok(PseudoEmbedder::embed_batch(self, texts))
+    }
+    
+    fn dimensions(&self) -> usize { self.dims }
+    
+    fn is_synthetic(&self) -> bool { true }
+    
+    fn embed_sparse(&self, texts: &[&str]) -> Result<Vec<SparseVector>, EmbedError> {
+        Ok(texts.iter().map(|t| PseudoEmbedder::embed_sparse(self, t)).collect())
+    }
+}
+
+/// splitmix64 finalizer: spreads every input bit over the low (bucket) bits
+pub(crate) fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    
+    fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b).map(|(x, y)| x * y).sum()
+    }
+    
+    #[test]
+    fn test_golden_vectors() {
+        // Locks the output format: update only with a deliberate breaking change
+        let e = PseudoEmbedder::new(8).embed("the cat sat");
+        let bits: Vec<u32> = e.iter().map(|x| x.to_bits()).collect();
+        assert_eq!(bits, GOLDEN_SEED_0);
+        
+        let e = PseudoEmbedder::new(8).with_seed(42).embed("the cat sat");
+        let bits: Vec<u32> = e.iter().map(|x| x.to_bits()).collect();
+        assert_eq!(bits, GOLDEN_SEED_42);
+        
+        // Inner punctuation, CJK and decomposed text, as segmented since the Unicode features
+        for (text, golden) in [("well-known, e.g. don't: a/b", GOLDEN_PUNCTUATED), ("知识图谱", GOLDEN_CJK), ("cafe\u{301}", GOLDEN_NFD)] {
+            let bits: Vec<u32> = PseudoEmbedder::new(8).embed(text).iter().map(|x| x.to_bits()).collect();
+            assert_eq!(bits, golden, "{}", text);
+        }
+    }
+    
+    #[test]
+    fn test_generate_into_reuses_buffer() {
+        let embedder = PseudoEmbedder::new(64).with_seed(3);
+        let mut buffer = vec![0.0f32; 64];
+        for text in ["the cat sat", "ΣΟΦΊΑ Ὀδυσσεύς", "  ", "naïve café!"] {
+            embedder.generate_into(&mut buffer, text);
+            assert_eq!(buffer, embedder.embed(text));
+        }
+        assert!(buffer.iter().any(|&x| x != 0.0));
+    }
+    
+    const GOLDEN_SEED_0: [u32; 8] = [0xbeb31be0, 0x00000000, 0x00000000, 0x3f331be0, 0xbe1460ef, 0xbeb31be0, 0xbeb31be0, 0xbeb31be0];
+    const GOLDEN_SEED_42: [u32; 8] = [0x00000000, 0xbece18e0, 0xbece18e0, 0xbe2abc8a, 0x00000000, 0x00000000, 0x00000000, 0x3f4e18e0];
+    const GOLDEN_PUNCTUATED: [u32; 8] = [0xbe05503e, 0x3f05503e, 0xbf05503e, 0xbe05503e, 0xbe85503e, 0xbf05503e, 0x3e85503e, 0xbe05503e];
+    const GOLDEN_CJK: [u32; 8] = [0x00000000, 0xbf2f9d54, 0xbeaf9d54, 0x3eaf9d54, 0x3f03b5ff, 0x00000000, 0x3e2f9d54, 0x00000000];
+    const GOLDEN_NFD: [u32; 8] = [0x00000000, 0x3eb504f3, 0x3eb504f3, 0x00000000, 0xbeb504f3, 0x3f3504f3, 0x00000000, 0x3eb504f3];
+    
+    #[test]
+    fn test_sparse_features_rebuild_dense() {
+        let text = "the cat sat on the mat, the end";
+        for embedder in [PseudoEmbedder::new(16), PseudoEmbedder::new(1024).with_seed(9)] {
+            let raw = embedder.clone().with_normalize(false);
+            let features = raw.features(text);
+            assert_eq!(features, embedder.features(text));
+            
+            let mut dense = vec![0.0f32; raw.dimensions()];
+            for &(i, weight) in &features {
+                dense[i] += weight;
+            }
+            assert_eq!(raw.embed(text), dense);
+            
+            // "the" appears three times: one feature with weight sqrt(3)
+            assert!(features.iter().any(|&(_, w)| w.abs() == 3f32.sqrt()));
+            
+            crate::search::normalize(&mut dense);
+            assert_eq!(embedder.embed(text), dense);
+        }
+        assert!(PseudoEmbedder::new(8).features("  ").is_empty());
+    }
+    
+    #[test]
+    fn test_unicode_features() {
+        let embedder = PseudoEmbedder::new(256);
+        
+        // Composed (U+00E9) and decomposed (e + U+0301) forms are the same text
+        assert_eq!(embedder.embed("caf\u{e9} cr\u{e8}me"), embedder.embed("cafe\u{301} cre\u{300}me"));
+        assert_eq!(embedder.embed("CAFÉ"), embedder.embed("café"));
+        
+        // CJK without spaces still spreads over many buckets
+        let cjk = embedder.embed("知识图谱把主语谓语宾语三元组存进晶体结构里");
+        let nonzero = cjk.iter().filter(|&&x| x != 0.0).count();
+        let peak = cjk.iter().fold(0.0f32, |m, &x| m.max(x.abs()));
+        assert!(nonzero > 30, "nonzero = {}", nonzero);
+        assert!(peak < 0.5, "peak = {}", peak);
+        
+        // ...and shares structure with overlapping CJK text only
+        let related = cosine(&cjk, &embedder.embed("知识图谱的三元组"));
+        let unrelated = cosine(&cjk, &embedder.embed("今天天气很好"));
+        assert!(related > unrelated + 0.3, "related {} unrelated {}", related, unrelated);
+    }
+    
+    #[test]
+    fn test_seeds_give_independent_spaces() {
+        let a = PseudoEmbedder::new(1024);
+        let b = PseudoEmbedder::new(1024).with_seed(7);
+        let text = "Ada loves Jan";
+        
+        assert_eq!(a.embed(text), a.clone().embed(text));
+        assert_eq!(b.embed(text), PseudoEmbedder::new(1024).with_seed(7).embed(text));
+        assert!(cosine(&a.embed(text), &b.embed(text)).abs() < 0.2);
+        
+        // Locality holds within every seed
+        for embedder in [a, b] {
+            let similar = cosine(&embedder.embed("the cat sat on the mat"), &embedder.embed("a cat sat on a mat"));
+            let unrelated = cosine(&embedder.embed("the cat sat on the mat"), &embedder.embed("quarterly revenue grew sharply"));
+            assert!(similar - unrelated > 0.4, "similar {} unrelated {}", similar, unrelated);
+        }
+    }
+    
+    proptest! {
+        #![proptest_config(crate::proptest_config(128))]
+        
+        #[test]
+        fn prop_unit_norm_or_whitespace(text in "\\PC{0,40}|[ \t\n]{0,5}", dims in 16usize..=1024, seed in any::<u64>()) {
+            let e = PseudoEmbedder::new(dims).with_seed(seed).embed(&text);
+            prop_assert_eq!(e.len(), dims);
+            let norm = e.iter().map(|x| x * x).sum::<f32>().sqrt();
+            if text.trim().is_empty() {
+                prop_assert_eq!(norm, 0.0);
+            } else {
+                prop_assert!((norm - 1.0).abs() < 1e-4, "norm {} for {:?}", norm, text);
+            }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Scalar int8 quantization for compact vector storage
+//!
+//! Each vector keeps one f32 scale (`max |x| / 127`) plus one i8 per
+//! component, a 4x saving over f32. Every dequantized component is within
+//! `scale / 2` of the original (`max_error`), up to f32 rounding.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Int8Vector {
+    pub scale: f32,
+    pub values: Vec<i8>,
+}
+
+impl Int8Vector {
+    /// Quantize finite values; an all-zero vector gets scale 0
+    pub fn quantize(v: &[f32]) -> Self {
+        let max = v.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+        let scale = max / 127.0;
+        let values = if scale > 0.0 {
+            v.iter().map(|&x| (x / scale).round().clamp(-127.0, 127.0) as i8).collect()
+        } else {
+            vec![0; v.len()]
+        };
+        Self { scale, values }
+    }
+    
+    pub fn dequantize(&self) -> Vec<f32> {
+        self.values.iter().map(|&q| q as f32 * self.scale).collect()
+    }
+    
+    /// Bound on |original - dequantized| per component
+    pub fn max_error(&self) -> f32 { self.scale / 2.0 }
+    
+    pub fn len(&self) -> usize { self.values.len() }
+    
+    pub fn is_empty(&self) -> bool { self.values.is_empty() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    
+    #[test]
+    fn test_quantize_extremes() {
+        let q = Int8Vector::quantize(&[1.0, -1.0, 0.5, 0.0]);
+        assert_eq!(q.values, vec![127, -127, 64, 0]);
+        assert_eq!(q.dequantize()[..2], [1.0, -1.0]);
+        assert_eq!(Int8Vector::quantize(&[0.0; 3]).dequantize(), vec![0.0; 3]);
+    }
+    
+    proptest! {
+        #![proptest_config(crate::proptest_config(256))]
+        
+        #[test]
+        fn prop_roundtrip_within_bound(v in prop::collection::vec(-1e6f32..1e6, 0..300)) {
+            let q = Int8Vector::quantize(&v);
+            let bound = q.max_error() * (1.0 + 1e-5) + f32::MIN_POSITIVE;
+            for (x, y) in v.iter().zip(q.dequantize()) {
+                prop_assert!((x - y).abs() <= bound, "{} -> {} (bound {})", x, y, bound);
+            }
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! Search-result cache for `CrystalIndex`
+//!
+//! An index built `with_query_cache` answers a search it has already run
+//! from memory. Entries are keyed by the query vector rounded to multiples
+//! of `quantum` (so float noise from re-embedding the same text still hits),
+//! `k` and the filter's name, compared in full so different vectors never
+//! share an entry. Every mutation of the index bumps its generation, and a
+//! lookup under a new generation drops every entry first. Entries expire
+//! after `ttl`, and past `max_entries` the least recently used go first.
+//!
+//! Unfiltered `search` and `search_filtered` go through the cache, and so
+//! does `search_filtered_as`, whose caller names the filter: closures
+//! cannot be compared, so filters under one name must pass the same
+//! entries. Other filtered and hybrid searches are not cached.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::transport::{Clock, SystemClock};
+
+pub const DEFAULT_MAX_ENTRIES: usize = 1024;
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60);
+/// Query components closer than this may share an entry
+pub const DEFAULT_QUANTUM: f32 = 1e-6;
+
+/// Hit and miss counts and current size of a `QueryCache`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueryCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Times the index changed and every entry was dropped
+    pub invalidations: u64,
+    pub entries: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Key {
+    /// Components as multiples of the quantum
+    query: Vec<i64>,
+    k: usize,
+    filter: Option<String>,
+}
+
+struct Entry {
+    hits: Vec<(u64, f32)>,
+    expires: Instant,
+    /// Position in `State::order`
+    used: u64,
+}
+
+#[derive(Default)]
+struct State {
+    generation: u64,
+    entries: HashMap<Key, Entry>,
+    /// Keys by last use, oldest first
+    order: BTreeMap<u64, Key>,
+    tick: u64,
+    stats: QueryCacheStats,
+}
+
+/// LRU and TTL bounded search results of one index
+pub struct QueryCache {
+    max_entries: usize,
+    ttl: Duration,
+    quantum: f32,
+    clock: Arc<dyn Clock>,
+    state: Mutex<State>,
+}
+
+impl Default for QueryCache {
+    fn default() -> Self { Self::new() }
+}
+
+/// A copy has the same bounds and no entries: a cloned index changes apart
+/// from the original, under generations of its own
+impl Clone for QueryCache {
+    fn clone(&self) -> Self {
+        Self { clock: self.clock.clone(), state: Mutex::default(), ..*self }
+    }
+}
+
+impl QueryCache {
+    pub fn new() -> Self {
+        Self {
+            max_entries: DEFAULT_MAX_ENTRIES,
+            ttl: DEFAULT_TTL,
+            quantum: DEFAULT_QUANTUM,
+            clock: Arc::new(SystemClock),
+            state: Mutex::default(),
+        }
+    }
+    
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+    
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+    
+    /// Round query components to multiples of `quantum` for the key
+    pub fn with_quantum(mut self, quantum: f32) -> Self {
+        self.quantum = quantum;
+        self
+    }
+    
+    /// Expire entries by `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+    
+    pub fn stats(&self) -> QueryCacheStats {
+        let state = self.state.lock().unwrap();
+        QueryCacheStats { entries: state.entries.len(), ..state.stats.clone() }
+    }
+    
+    /// The cached hits of `query` at index `generation`, or those `search`
+    /// returns, stored for next time
+    pub(crate) fn get_or_search(&self, generation: u64, query: &[f32], k: usize, filter: Option<&str>,
+                                search: impl FnOnce() -> Vec<(u64, f32)>) -> Vec<(u64, f32)> {
+        let key = Key { query: query.iter().map(|&x| (x / self.quantum).round() as i64).collect(), k, filter: filter.map(str::to_string) };
+        let now = self.clock.now();
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.generation != generation {
+                if !state.entries.is_empty() {
+                    state.stats.invalidations += 1;
+                }
+                state.entries.clear();
+                state.order.clear();
+                state.generation = generation;
+            }
+            state.tick += 1;
+            let tick = state.tick;
+            let State { entries, order, stats, .. } = &mut *state;
+            match entries.get_mut(&key) {
+                Some(entry) if entry.expires > now => {
+                    order.remove(&entry.used);
+                    entry.used = tick;
+                    order.insert(tick, key);
+                    stats.hits += 1;
+                    return entry.hits.clone();
+                }
+                Some(entry) => {
+                    order.remove(&entry.used);
+                    entries.remove(&key);
+                }
+                None => {}
+            }
+            stats.misses += 1;
+        }
+        
+        let hits = search();
+        if self.max_entries == 0 || self.ttl.is_zero() {
+            return hits;
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.generation == generation {
+            state.tick += 1;
+            let tick = state.tick;
+            state.order.insert(tick, key.clone());
+            if let Some(old) = state.entries.insert(key, Entry { hits: hits.clone(), expires: now + self.ttl, used: tick }) {
+                state.order.remove(&old.used);
+            }
+            while state.entries.len() > self.max_entries {
+                let (_, oldest) = state.order.pop_first().unwrap();
+                state.entries.remove(&oldest);
+            }
+        }
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::CrystalIndex;
+    use crate::metadata::Metadata;
+    use crate::mock::ManualClock;
+    
+    fn index(cache: QueryCache) -> CrystalIndex {
+        let mut index = CrystalIndex::new(3).with_query_cache(cache);
+        for id in 0..50u64 {
+            let v = [1.0, id as f32 / 50.0, (id % 7) as f32 / 7.0];
+            index.add_with_metadata(id, &v, Metadata::new().with("even", id % 2 == 0)).unwrap();
+        }
+        index
+    }
+    
+    #[test]
+    fn test_hits_match_and_mutations_invalidate() {
+        let mut index = index(QueryCache::new());
+        let query = [1.0, 0.3, 0.2];
+        let first = index.search(&query, 5);
+        assert_eq!(index.search(&query, 5), first);
+        assert_eq!(index.search(&query, 5), index.search_exhaustive(&query, 5));
+        let stats = index.query_cache_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (2, 1, 1));
+        
+        // Another k or a named filter is another entry
+        let even = |m: &Metadata| m.get_bool("even") == Some(true);
+        let filtered = index.search_filtered_as(&query, 5, &even, "even");
+        assert!(filtered.iter().all(|(id, _)| id % 2 == 0));
+        assert_eq!(index.search_filtered_as(&query, 5, &even, "even"), filtered);
+        assert_eq!(index.search(&query, 3), first[..3]);
+        assert_eq!(index.query_cache_stats().unwrap().entries, 3);
+        
+        // A closer entry arrives: the old hit list must not be served
+        let generation = index.generation();
+        index.add(99, &query).unwrap();
+        assert!(index.generation() > generation);
+        assert_eq!(index.search(&query, 5)[0].0, 99);
+        index.remove(99);
+        assert_eq!(index.search(&query, 5), first);
+        let stats = index.query_cache_stats().unwrap();
+        assert_eq!((stats.invalidations, stats.entries), (2, 1));
+        index.compact();
+        index.search(&query, 5);
+        assert_eq!(index.query_cache_stats().unwrap().invalidations, 3);
+    }
+    
+    #[test]
+    fn test_near_queries_share_only_within_quantum_and_entries_expire() {
+        let clock = Arc::new(ManualClock::new());
+        let index = index(QueryCache::new().with_clock(clock.clone()).with_ttl(Duration::from_secs(5)).with_max_entries(2));
+        let query = [1.0, 0.5, 0.25];
+        let hits = index.search(&query, 4);
+        // Float noise hits; a change of 1e-4 in one component is another query
+        assert_eq!(index.search(&[1.0, 0.5 + 1e-8, 0.25], 4), hits);
+        let near = [1.0, 0.5, 0.25 + 1e-4];
+        assert_eq!(index.search(&near, 4), index.search_exhaustive(&near, 4));
+        let stats = index.query_cache_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 2, 2));
+        
+        // A third entry evicts the least recently used, `near`'s
+        index.search(&query, 4);
+        index.search(&[0.0, 1.0, 0.0], 4);
+        index.search(&query, 4);
+        assert_eq!(index.query_cache_stats().unwrap().hits, 3);
+        index.search(&near, 4);
+        assert_eq!(index.query_cache_stats().unwrap().misses, 4);
+        
+        clock.advance(Duration::from_secs(6));
+        index.search(&query, 4);
+        assert_eq!(index.query_cache_stats().unwrap().misses, 5);
+    }
+}
@@ -0,0 +1,146 @@
+//! Jina Reader (`https://r.jina.ai/<url>`): web pages as clean text
+//!
+//! The Reader answers with markdown; `Accept: application/json` wraps it
+//! with title and final URL. Its rate limits are far lower than the
+//! embedding API's, so it retries on its own, slower policy.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::chunk::{chunk_text, EmbeddedChunk};
+use crate::error::{truncate_for_display, JinaError, MAX_DISPLAY_CHARS};
+use crate::jina_api::{EmbedOptions, JinaClient};
+use crate::transport::{HttpRequest, RetryPolicy};
+
+const READER_URL: &str = "https://r.jina.ai/";
+/// Local chunk size for `embed_url`, in characters
+const URL_CHUNK_CHARS: usize = 2000;
+
+/// Reader default: a few retries spaced for per-minute limits
+pub fn reader_retry_policy() -> RetryPolicy {
+    RetryPolicy { max_retries: 3, base_delay: Duration::from_secs(2), max_delay: Duration::from_secs(60) }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ReadResult {
+    pub title: String,
+    /// Page content as markdown
+    pub content: String,
+    /// URL after redirects
+    pub url: String,
+}
+
+impl JinaClient {
+    pub fn with_reader_retry(mut self, retry: RetryPolicy) -> Self {
+        self.settings_mut().reader_retry = retry;
+        self
+    }
+    
+    /// Fetch `url` through the Reader; needs an online client
+    pub fn read_url(&self, url: &str) -> Result<ReadResult, JinaError> {
+        if url.trim().is_empty() {
+            return Err(JinaError::InvalidInput("empty URL".to_string()));
+        }
+        let request = HttpRequest::get(format!("{}{}", READER_URL, url)).header("Accept", "application/json");
+        match self.send(request, &self.settings().reader_retry)? {
+            Some(body) => parse_reader(&body),
+            None => Err(JinaError::InvalidInput("read_url needs the Jina API (with_http)".to_string())),
+        }
+    }
+    
+    /// Read `url`, chunk its content locally and embed the chunks as passages
+    pub fn embed_url(&self, url: &str) -> Result<Vec<EmbeddedChunk>, JinaError> {
+        let page = self.read_url(url)?;
+        let chunks = chunk_text(&page.content, URL_CHUNK_CHARS);
+        let texts: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+        let embeddings = self.embed_batch_full(&texts, &EmbedOptions::passage())?.embeddings;
+        Ok(chunks.into_iter().zip(embeddings)
+            .map(|(chunk, embedding)| EmbeddedChunk { chunk, embedding })
+            .collect())
+    }
+}
+
+#[derive(Deserialize)]
+struct Response {
+    data: Page,
+}
+
+#[derive(Deserialize)]
+struct Page {
+    #[serde(default)]
+    title: String,
+    content: String,
+    #[serde(default)]
+    url: String,
+}
+
+fn parse_reader(body: &str) -> Result<ReadResult, JinaError> {
+    let response: Response = serde_json::from_str(body)
+        .map_err(|e| JinaError::Parse(format!("reader response: {}", truncate_for_display(&e.to_string(), MAX_DISPLAY_CHARS))))?;
+    let page = response.data;
+    Ok(ReadResult { title: page.title, content: page.content, url: page.url })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::HttpResponse;
+    use std::sync::{Arc, Mutex};
+    
+    const FIXTURE: &str = include_str!("../fixtures/jina/reader.json");
+    
+    /// Embeddings response with one 1024-dim vector per input
+    fn embeddings_for(request: &HttpRequest) -> String {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        let data: Vec<serde_json::Value> = (0..body["input"].as_array().unwrap().len())
+            .map(|i| serde_json::json!({ "index": i, "embedding": vec![i as f32 + 0.5; 1024] }))
+            .collect();
+        serde_json::json!({ "model": "jina-embeddings-v3", "data": data }).to_string()
+    }
+    
+    #[test]
+    fn test_parse_reader_fixture() {
+        let page = parse_reader(FIXTURE).unwrap();
+        assert_eq!(page.title, "Example Domain");
+        assert_eq!(page.url, "https://example.com/");
+        assert!(page.content.starts_with("This domain is for use"));
+        assert!(matches!(parse_reader(r#"{"code":422,"name":"X"}"#), Err(JinaError::Parse(_))));
+        assert!(JinaClient::new("test_key").read_url("https://example.com").is_err());
+    }
+    
+    #[test]
+    fn test_embed_url_reads_chunks_and_embeds() {
+        let seen: Arc<Mutex<Vec<HttpRequest>>> = Arc::default();
+        let log = seen.clone();
+        let client = JinaClient::new("jina_test")
+            .with_retry(RetryPolicy::none())
+            .with_reader_retry(RetryPolicy { max_retries: 1, base_delay: Duration::ZERO, max_delay: Duration::ZERO })
+            .with_transport(move |request: &HttpRequest| {
+                let mut log = log.lock().unwrap();
+                log.push(request.clone());
+                let (status, body) = if request.url.starts_with(READER_URL) {
+                    // First Reader call is rate limited
+                    if log.len() == 1 { (429, r#"{"detail":"slow down"}"#.to_string()) } else { (200, FIXTURE.to_string()) }
+                } else {
+                    (200, embeddings_for(request))
+                };
+                Ok(HttpResponse { status, headers: Vec::new(), body })
+            });
+        
+        let chunks = client.embed_url("https://example.com").unwrap();
+        let content = parse_reader(FIXTURE).unwrap().content;
+        assert_eq!(chunks.len(), 1);
+        assert_eq!((chunks[0].chunk.start, chunks[0].chunk.end), (0, content.len()));
+        assert_eq!(chunks[0].embedding.len(), 1024);
+        
+        let requests = seen.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].url, "https://r.jina.ai/https://example.com");
+        assert_eq!(requests[1].method, "GET");
+        assert!(requests[1].headers.contains(&("Accept".to_string(), "application/json".to_string())));
+        let body: serde_json::Value = serde_json::from_slice(&requests[2].body).unwrap();
+        assert_eq!(body["task"], "retrieval.passage");
+    }
+}
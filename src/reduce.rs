@@ -0,0 +1,241 @@
+//! Seeded random projection for cheap dimension reduction
+//!
+//! `RandomProjection` maps `in_dims` vectors to `out_dims` through a random
+//! matrix drawn from its seed, without a training pass over the corpus. The
+//! matrix is a pure function of `(in_dims, out_dims, seed, mode)`: it is
+//! drawn with `sample::SampleRng` and exact IEEE arithmetic only, so every
+//! machine builds the same bits and only those four values are persisted.
+//!
+//! - `Gaussian`: entries N(0, 1/out_dims), each the sum of 12 uniforms less 6
+//!   (Irwin–Hall, which avoids the platform-dependent `ln` and `cos`)
+//! - `Sparse`: Achlioptas' ±√(3/out_dims) with probability 1/6 each and 0
+//!   otherwise; two thirds of the products are skipped
+//!
+//! By Johnson–Lindenstrauss, projecting `n` points to
+//! `out_dims >= 4 ln(n) / (ε²/2 − ε³/3)` keeps every pairwise squared
+//! distance within a factor 1 ± ε with high probability (about 1900 dims for
+//! ε = 0.1 and a million points). Per pair of unit vectors, the projected
+//! cosine differs from the original by about `(1 − cos²) / √out_dims`
+//! (0.09 at 128 dims): good enough to shortlist candidates from the reduced
+//! vectors (`project_index`) and rescore them in full, not to rank alone.
+
+use rayon::prelude::*;
+
+use crate::index::CrystalIndex;
+use crate::sample::SampleRng;
+
+/// How projection matrix entries are drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectionMode {
+    #[default]
+    Gaussian,
+    /// Achlioptas: a third of the entries nonzero
+    Sparse,
+}
+
+/// Everything that determines a projection, as persisted
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ProjectionSpec {
+    pub in_dims: usize,
+    pub out_dims: usize,
+    pub seed: u64,
+    pub mode: ProjectionMode,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Matrix {
+    /// Row-major, `out_dims` rows of `in_dims`
+    Dense(Vec<f32>),
+    /// The nonzero columns of each output row, `+scale` or `-scale`
+    Sparse { plus: Vec<Vec<u32>>, minus: Vec<Vec<u32>>, scale: f32 },
+}
+
+/// Serializes as its `ProjectionSpec`; the matrix is drawn again on load
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(from = "ProjectionSpec", into = "ProjectionSpec")]
+pub struct RandomProjection {
+    spec: ProjectionSpec,
+    matrix: Matrix,
+}
+
+impl RandomProjection {
+    /// Gaussian projection from `in_dims` to `out_dims` drawn from `seed`
+    pub fn new(in_dims: usize, out_dims: usize, seed: u64) -> Self {
+        Self::from_spec(ProjectionSpec { in_dims, out_dims, seed, mode: ProjectionMode::Gaussian })
+    }
+    
+    /// The same projection with entries drawn as `mode`
+    pub fn with_mode(self, mode: ProjectionMode) -> Self { Self::from_spec(ProjectionSpec { mode, ..self.spec }) }
+    
+    pub fn from_spec(spec: ProjectionSpec) -> Self {
+        let mut rng = SampleRng::new(spec.seed);
+        let len = spec.in_dims * spec.out_dims;
+        let matrix = match spec.mode {
+            ProjectionMode::Gaussian => {
+                let scale = 1.0 / (spec.out_dims.max(1) as f64).sqrt();
+                Matrix::Dense((0..len).map(|_| ((0..12).map(|_| rng.unit()).sum::<f64>() - 6.0) * scale).map(|x| x as f32).collect())
+            }
+            ProjectionMode::Sparse => {
+                let (mut plus, mut minus) = (vec![Vec::new(); spec.out_dims], vec![Vec::new(); spec.out_dims]);
+                for row in 0..spec.out_dims {
+                    for col in 0..spec.in_dims as u32 {
+                        match rng.below(6) {
+                            0 => plus[row].push(col),
+                            1 => minus[row].push(col),
+                            _ => {}
+                        }
+                    }
+                }
+                Matrix::Sparse { plus, minus, scale: (3.0 / spec.out_dims.max(1) as f64).sqrt() as f32 }
+            }
+        };
+        Self { spec, matrix }
+    }
+    
+    pub fn spec(&self) -> ProjectionSpec { self.spec }
+    
+    pub fn in_dims(&self) -> usize { self.spec.in_dims }
+    
+    pub fn out_dims(&self) -> usize { self.spec.out_dims }
+    
+    /// `v` projected to `out_dims`
+    pub fn transform(&self, v: &[f32]) -> Result<Vec<f32>, String> {
+        if v.len() != self.spec.in_dims {
+            return Err(format!("Dimension mismatch: expected {}, got {}", self.spec.in_dims, v.len()));
+        }
+        Ok(match &self.matrix {
+            Matrix::Dense(matrix) => (0..self.spec.out_dims)
+                .map(|row| matrix[row * v.len()..(row + 1) * v.len()].iter().zip(v).map(|(a, b)| a * b).sum())
+                .collect(),
+            Matrix::Sparse { plus, minus, scale } => plus.iter().zip(minus)
+                .map(|(plus, minus)| {
+                    let sum = |cols: &[u32]| cols.iter().map(|&c| v[c as usize]).sum::<f32>();
+                    (sum(plus) - sum(minus)) * scale
+                })
+                .collect(),
+        })
+    }
+    
+    /// `transform` of every vector, in parallel and in input order
+    pub fn transform_batch(&self, vectors: &[Vec<f32>]) -> Result<Vec<Vec<f32>>, String> {
+        vectors.par_iter().map(|v| self.transform(v)).collect()
+    }
+    
+    /// An index of `index`'s live entries projected, under the same ids,
+    /// metadata, schema and metric, for shortlisting before a full-size
+    /// rescore. The projected index records no provenance: no model made its vectors.
+    pub fn project_index(&self, index: &CrystalIndex) -> Result<CrystalIndex, String> {
+        let mut projected = CrystalIndex::new(self.spec.out_dims).with_metric(index.metric());
+        if let Some(schema) = index.schema() {
+            projected = projected.with_schema(schema.clone());
+        }
+        for id in index.ids() {
+            let vector = self.transform(index.get(id).unwrap())?;
+            projected.add_with_metadata(id, &vector, index.metadata(id).cloned().unwrap_or_default())?;
+        }
+        Ok(projected)
+    }
+}
+
+impl From<ProjectionSpec> for RandomProjection {
+    fn from(spec: ProjectionSpec) -> Self { Self::from_spec(spec) }
+}
+
+impl From<RandomProjection> for ProjectionSpec {
+    fn from(projection: RandomProjection) -> Self { projection.spec }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::{cosine, normalize};
+    
+    fn unit_vectors(n: usize, dims: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut rng = SampleRng::new(seed);
+        (0..n).map(|_| {
+            let mut v: Vec<f32> = (0..dims).map(|_| rng.unit() as f32 - 0.5).collect();
+            normalize(&mut v);
+            v
+        }).collect()
+    }
+    
+    #[test]
+    fn test_deterministic_from_seed_and_persisted_as_spec() {
+        for mode in [ProjectionMode::Gaussian, ProjectionMode::Sparse] {
+            let a = RandomProjection::new(64, 16, 42).with_mode(mode);
+            let b = RandomProjection::new(64, 16, 42).with_mode(mode);
+            assert_eq!(a, b);
+            assert_ne!(a, RandomProjection::new(64, 16, 43).with_mode(mode));
+            let json = serde_json::to_string(&a).unwrap();
+            assert_eq!(json, format!(r#"{{"in_dims":64,"out_dims":16,"seed":42,"mode":"{}"}}"#,
+                                     if mode == ProjectionMode::Sparse { "sparse" } else { "gaussian" }));
+            assert_eq!(serde_json::from_str::<RandomProjection>(&json).unwrap(), a);
+        }
+        // Pinned output: a change here changes every persisted projection
+        let v: Vec<f32> = (0..64).map(|i| i as f32 / 64.0).collect();
+        let first = |mode| RandomProjection::new(64, 16, 42).with_mode(mode).transform(&v).unwrap()[0].to_bits();
+        assert_eq!((first(ProjectionMode::Gaussian), first(ProjectionMode::Sparse)), (3220695842, 1066896880));
+        assert!(RandomProjection::new(64, 16, 42).transform(&v[..63]).unwrap_err().contains("expected 64, got 63"));
+    }
+    
+    #[test]
+    fn test_pairwise_cosines_roughly_preserved() {
+        let base = unit_vectors(40, 512, 1);
+        // Correlated pairs as well as near-orthogonal ones
+        let vectors: Vec<Vec<f32>> = base.iter().zip(base.iter().skip(1)).map(|(a, b)| {
+            let mut v: Vec<f32> = a.iter().zip(b).map(|(x, y)| x + 0.6 * y).collect();
+            normalize(&mut v);
+            v
+        }).chain(base.iter().cloned()).collect();
+        for mode in [ProjectionMode::Gaussian, ProjectionMode::Sparse] {
+            let out_dims = 256;
+            let projection = RandomProjection::new(512, out_dims, 9).with_mode(mode);
+            let projected = projection.transform_batch(&vectors).unwrap();
+            let mut errors = Vec::new();
+            for i in 0..vectors.len() {
+                for j in i + 1..vectors.len() {
+                    errors.push((cosine(&vectors[i], &vectors[j]) - cosine(&projected[i], &projected[j])).abs());
+                }
+            }
+            // |error| is about |N(0, 1/out_dims)|: mean √(2/π)/16 ≈ 0.05
+            let bound = 1.0 / (out_dims as f32).sqrt();
+            let mean = errors.iter().sum::<f32>() / errors.len() as f32;
+            assert!(mean < bound, "{:?}: mean error {}", mode, mean);
+            assert!(errors.iter().all(|&e| e < 5.0 * bound), "{:?}: max error {}", mode, errors.iter().cloned().fold(0.0, f32::max));
+        }
+    }
+    
+    #[test]
+    fn test_projected_index_shortlists_for_full_rescore() {
+        // 30 clusters of 10, and queries near corpus entries, as with real embeddings
+        let centers = unit_vectors(30, 256, 5);
+        let noise = unit_vectors(320, 256, 6);
+        let blend = |a: &[f32], b: &[f32], w: f32| -> Vec<f32> {
+            let mut v: Vec<f32> = a.iter().zip(b).map(|(x, y)| x + w * y).collect();
+            normalize(&mut v);
+            v
+        };
+        let vectors: Vec<Vec<f32>> = (0..300).map(|i| blend(&centers[i / 10], &noise[i], 0.7)).collect();
+        let mut index = CrystalIndex::new(256);
+        for (id, v) in vectors.iter().enumerate() {
+            index.add_with_metadata(id as u64, v, crate::metadata::Metadata::new().with("id", id as i64)).unwrap();
+        }
+        let projection = RandomProjection::new(256, 64, 3).with_mode(ProjectionMode::Sparse);
+        let reduced = projection.project_index(&index).unwrap();
+        assert_eq!((reduced.len(), reduced.dims()), (300, 64));
+        assert_eq!(reduced.metadata(7).unwrap().get_num("id"), Some(7.0));
+        
+        let (mut found, mut total) = (0, 0);
+        for (i, noise) in noise[300..].iter().enumerate() {
+            let query = blend(&vectors[i * 15], noise, 0.5);
+            let shortlist = reduced.search(&projection.transform(&query).unwrap(), 40);
+            let mut rescored: Vec<(u64, f32)> = shortlist.iter().map(|&(id, _)| (id, cosine(&query, index.get(id).unwrap()))).collect();
+            rescored.sort_by(|a, b| b.1.total_cmp(&a.1));
+            let exact: Vec<u64> = index.search(&query, 5).into_iter().map(|(id, _)| id).collect();
+            found += rescored.iter().take(5).filter(|(id, _)| exact.contains(id)).count();
+            total += 5;
+        }
+        assert!(found * 10 >= total * 9, "recall@5 {}/{}", found, total);
+    }
+}
@@ -0,0 +1,245 @@
+//! Relation arithmetic over component embeddings
+//!
+//! `fit` learns one offset per predicate, the mean of `embed(o) - embed(s)`
+//! over its known triples. `RelationModel::predict_object` then ranks
+//! candidate objects for `(s, p)` by cosine to `embed(s) + offset(p)`.
+//! Predicates with fewer than `min_examples` triples have no offset and
+//! answer `RelationError::InsufficientData`.
+//!
+//! Models save as JSON; the provider that embeds queries must be the one
+//! the model was fitted with.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::Write;
+
+use crate::io::atomic_write;
+use crate::provider::{EmbedError, EmbeddingProvider};
+use crate::search::top_k;
+use crate::triples::Triple;
+
+/// Triples a predicate needs before it gets an offset
+pub const DEFAULT_MIN_EXAMPLES: usize = 2;
+/// Version of the saved JSON; `load` refuses newer files
+pub const MODEL_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RelationError {
+    /// `predicate` has `examples` known triples, fewer than `required`
+    InsufficientData { predicate: String, examples: usize, required: usize },
+    Embed(EmbedError),
+}
+
+impl fmt::Display for RelationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelationError::InsufficientData { predicate, examples, required } => {
+                write!(f, "Predicate {:?} has {} examples, {} needed", predicate, examples, required)
+            }
+            RelationError::Embed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RelationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RelationError::Embed(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<EmbedError> for RelationError {
+    fn from(e: EmbedError) -> Self { RelationError::Embed(e) }
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Relation {
+    examples: usize,
+    /// Empty below `min_examples`
+    offset: Vec<f32>,
+}
+
+/// Learned per-predicate offsets
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RelationModel {
+    /// Files saved before models were versioned are version 1
+    #[serde(default = "first_version")]
+    version: u32,
+    dims: usize,
+    min_examples: usize,
+    relations: BTreeMap<String, Relation>,
+}
+
+fn first_version() -> u32 { 1 }
+
+/// Fit offsets for every predicate with at least `DEFAULT_MIN_EXAMPLES` triples
+pub fn fit<P: EmbeddingProvider + ?Sized>(triples: &[Triple], provider: &P) -> Result<RelationModel, EmbedError> {
+    fit_with(triples, provider, DEFAULT_MIN_EXAMPLES)
+}
+
+/// `fit` with a custom example threshold (at least 1)
+pub fn fit_with<P: EmbeddingProvider + ?Sized>(triples: &[Triple], provider: &P, min_examples: usize)
+                                               -> Result<RelationModel, EmbedError> {
+    // Each distinct entity is embedded once
+    let mut slots: HashMap<&str, usize> = HashMap::new();
+    let mut entities: Vec<&str> = Vec::new();
+    for t in triples {
+        for entity in [t.subject.as_str(), t.object.as_str()] {
+            slots.entry(entity).or_insert_with(|| {
+                entities.push(entity);
+                entities.len() - 1
+            });
+        }
+    }
+    let embeddings = if entities.is_empty() { vec![] } else { provider.embed_batch(&entities)? };
+    if embeddings.len() != entities.len() {
+        return Err(EmbedError::Mismatch { expected: entities.len(), got: embeddings.len() });
+    }
+    let dims = embeddings.first().map_or(provider.dimensions(), Vec::len);
+    
+    let mut sums: BTreeMap<String, (usize, Vec<f32>)> = BTreeMap::new();
+    for t in triples {
+        let (count, sum) = sums.entry(t.predicate.clone()).or_insert_with(|| (0, vec![0.0; dims]));
+        *count += 1;
+        let (s, o) = (&embeddings[slots[t.subject.as_str()]], &embeddings[slots[t.object.as_str()]]);
+        for ((x, s), o) in sum.iter_mut().zip(s).zip(o) {
+            *x += o - s;
+        }
+    }
+    let min_examples = min_examples.max(1);
+    let relations = sums.into_iter()
+        .map(|(predicate, (examples, sum))| {
+            let offset = if examples >= min_examples { sum.iter().map(|x| x / examples as f32).collect() } else { vec![] };
+            (predicate, Relation { examples, offset })
+        })
+        .collect();
+    Ok(RelationModel { version: MODEL_VERSION, dims, min_examples, relations })
+}
+
+impl RelationModel {
+    pub fn dims(&self) -> usize { self.dims }
+    
+    /// Predicates with an offset, sorted
+    pub fn predicates(&self) -> impl Iterator<Item = &str> + '_ {
+        self.relations.iter().filter(|(_, r)| !r.offset.is_empty()).map(|(p, _)| p.as_str())
+    }
+    
+    /// Learned `embed(o) - embed(s)` mean of `predicate`
+    pub fn offset(&self, predicate: &str) -> Result<&[f32], RelationError> {
+        match self.relations.get(predicate) {
+            Some(r) if !r.offset.is_empty() => Ok(&r.offset),
+            r => Err(RelationError::InsufficientData {
+                predicate: predicate.to_string(),
+                examples: r.map_or(0, |r| r.examples),
+                required: self.min_examples,
+            }),
+        }
+    }
+    
+    /// `subject + offset(predicate)`: where objects of the relation should lie
+    pub fn target(&self, subject: &[f32], predicate: &str) -> Result<Vec<f32>, RelationError> {
+        let offset = self.offset(predicate)?;
+        if subject.len() != offset.len() {
+            return Err(EmbedError::Mismatch { expected: offset.len(), got: subject.len() }.into());
+        }
+        Ok(subject.iter().zip(offset).map(|(s, o)| s + o).collect())
+    }
+    
+    /// Top-k `candidates` as objects of `(subject, predicate)`, best first,
+    /// as (candidate index, cosine to the target)
+    pub fn predict_object<P: EmbeddingProvider + ?Sized>(&self, provider: &P, subject: &str, predicate: &str,
+                                                         candidates: &[&str], k: usize)
+                                                         -> Result<Vec<(usize, f32)>, RelationError> {
+        // Check the predicate before spending a request
+        self.offset(predicate)?;
+        let mut texts = vec![subject];
+        texts.extend_from_slice(candidates);
+        let mut embeddings = provider.embed_batch(&texts)?;
+        if embeddings.len() != texts.len() {
+            return Err(EmbedError::Mismatch { expected: texts.len(), got: embeddings.len() }.into());
+        }
+        let candidates = embeddings.split_off(1);
+        let target = self.target(&embeddings[0], predicate)?;
+        Ok(top_k(&target, &candidates, k))
+    }
+    
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        atomic_write(path, |file| file.write_all(json.as_bytes())).map_err(|e| format!("Write failed for {}: {}", path, e))
+    }
+    
+    pub fn load(path: &str) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path).map_err(|e| format!("Cannot open {}: {}", path, e))?;
+        let model: Self = serde_json::from_str(&json).map_err(|e| format!("Cannot parse {}: {}", path, e))?;
+        if model.version > MODEL_VERSION {
+            return Err(format!("{} is a version {} model; this build reads up to version {}", path, model.version, MODEL_VERSION));
+        }
+        Ok(model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pseudo::PseudoEmbedder;
+    
+    const NAMES: [&str; 6] = ["alpha", "bravo", "charlie", "delta", "echo", "foxtrot"];
+    
+    /// Objects are the subject plus a per-predicate word, so offsets are consistent
+    fn triples() -> Vec<Triple> {
+        NAMES.iter()
+            .flat_map(|n| [Triple::new(*n, "capital", format!("{} city", n)), Triple::new(*n, "river", format!("{} river", n))])
+            .collect()
+    }
+    
+    #[test]
+    fn test_predicts_held_out_objects() {
+        let provider = PseudoEmbedder::new(256);
+        let model = fit(&triples(), &provider).unwrap();
+        assert_eq!(model.predicates().collect::<Vec<_>>(), ["capital", "river"]);
+        
+        let candidates = ["golf city", "golf river", "hotel city", "hotel river", "golf"];
+        let capital = model.predict_object(&provider, "golf", "capital", &candidates, 2).unwrap();
+        assert_eq!(capital[0].0, 0);
+        let river = model.predict_object(&provider, "golf", "river", &candidates, 2).unwrap();
+        assert_eq!(river[0].0, 1);
+        assert_eq!(model.predict_object(&provider, "hotel", "river", &candidates, 1).unwrap()[0].0, 3);
+    }
+    
+    #[test]
+    fn test_insufficient_data() {
+        let provider = PseudoEmbedder::new(64);
+        let mut triples = triples();
+        triples.push(Triple::new("alpha", "anthem", "alpha song"));
+        let model = fit_with(&triples, &provider, 2).unwrap();
+        let expected = |predicate: &str, examples| RelationError::InsufficientData { predicate: predicate.to_string(), examples, required: 2 };
+        assert_eq!(model.predict_object(&provider, "golf", "anthem", &["golf song"], 1).unwrap_err(), expected("anthem", 1));
+        assert_eq!(model.offset("unknown").unwrap_err(), expected("unknown", 0));
+        assert_eq!(fit_with(&triples, &provider, 1).unwrap().offset("anthem").unwrap().len(), 64);
+    }
+    
+    #[test]
+    fn test_save_load_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("relations.json");
+        let path = path.to_str().unwrap();
+        let model = fit(&triples(), &PseudoEmbedder::new(32)).unwrap();
+        model.save(path).unwrap();
+        assert_eq!(RelationModel::load(path).unwrap(), model);
+        
+        std::fs::write(path, "{").unwrap();
+        assert!(RelationModel::load(path).unwrap_err().contains("Cannot parse"));
+        
+        // Unversioned files are version 1; files from a newer build are refused
+        let mut json: serde_json::Value = serde_json::to_value(&model).unwrap();
+        assert_eq!(json["version"], 1);
+        json.as_object_mut().unwrap().remove("version");
+        std::fs::write(path, json.to_string()).unwrap();
+        assert_eq!(RelationModel::load(path).unwrap(), model);
+        json["version"] = (MODEL_VERSION + 1).into();
+        std::fs::write(path, json.to_string()).unwrap();
+        assert!(RelationModel::load(path).unwrap_err().contains("version 2 model"));
+    }
+}
@@ -0,0 +1,221 @@
+//! Record-and-replay transports for testing against real API responses
+//!
+//! With `SPO_CRYSTAL_RECORD=<dir>` set, `transport::for_url` wraps the real
+//! transport in a `RecordingTransport`, which writes every response to
+//! `<dir>/<method>-<host-and-path>-<fingerprint>.json`. `ReplayTransport`
+//! serves those files back by the same fingerprint and errors on a miss,
+//! so tests parse real responses without an API key.
+//!
+//! The fingerprint covers method, URL and the JSON body with keys sorted
+//! and `VOLATILE_FIELDS` removed; headers (including `Authorization`) are
+//! not part of it, so re-recording with another key or at another time
+//! produces the same file names. Credentials never reach the files.
+
+use std::borrow::Cow;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+use crate::error::JinaError;
+use crate::io::atomic_write;
+use crate::transport::{HttpRequest, HttpResponse, Transport};
+
+/// Environment variable naming the directory to record into
+pub const RECORD_ENV: &str = "SPO_CRYSTAL_RECORD";
+
+/// Request body fields that vary between otherwise identical requests
+const VOLATILE_FIELDS: &[&str] = &["request_id", "user", "timestamp"];
+
+/// Request headers never written to fixtures
+const SECRET_HEADERS: &[&str] = &["authorization", "api-key", "x-api-key"];
+
+/// Response headers that change on every call and would churn fixtures
+const VOLATILE_HEADERS: &[&str] = &["date", "cf-ray", "x-request-id", "set-cookie", "age", "server-timing"];
+
+/// Stable 64-bit FNV-1a fingerprint of a request, ignoring headers and volatile fields
+pub fn fingerprint(request: &HttpRequest) -> u64 {
+    // Compressed and plain sends of one request share a fixture
+    let decoded = request.decoded_body().unwrap_or(Cow::Borrowed(&request.body));
+    let body = match serde_json::from_slice::<Value>(&decoded) {
+        Ok(Value::Object(mut fields)) => {
+            for field in VOLATILE_FIELDS {
+                fields.remove(*field);
+            }
+            // serde_json maps are sorted, so this is canonical
+            Value::Object(fields).to_string().into_bytes()
+        }
+        Ok(other) => other.to_string().into_bytes(),
+        Err(_) => decoded.into_owned(),
+    };
+    
+    let mut h: u64 = 0xcbf29ce484222325;
+    for part in [request.method.as_bytes(), request.url.as_bytes(), &body] {
+        for &b in part.iter().chain(&[0xff]) {
+            h ^= b as u64;
+            h = h.wrapping_mul(0x100000001b3);
+        }
+    }
+    h
+}
+
+/// Fixture file name for a request: readable prefix plus fingerprint
+pub fn fixture_name(request: &HttpRequest) -> String {
+    let target = request.url.split_once("://").map_or(request.url.as_str(), |(_, rest)| rest);
+    let slug: String = target.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' { c } else { '-' })
+        .collect();
+    format!("{}-{}-{:016x}.json", request.method.to_ascii_lowercase(), slug.trim_matches('-'), fingerprint(request))
+}
+
+/// Writes each response it passes through to a fixture directory
+pub struct RecordingTransport {
+    inner: Arc<dyn Transport>,
+    dir: PathBuf,
+}
+
+impl RecordingTransport {
+    pub fn new(inner: Arc<dyn Transport>, dir: impl Into<PathBuf>) -> Self {
+        Self { inner, dir: dir.into() }
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse, JinaError> {
+        let response = self.inner.send(request)?;
+        
+        let request_headers: Vec<&(String, String)> = request.headers.iter()
+            .filter(|(n, _)| !SECRET_HEADERS.contains(&n.to_ascii_lowercase().as_str()))
+            .collect();
+        let response_headers: Vec<&(String, String)> = response.headers.iter()
+            .filter(|(n, _)| !VOLATILE_HEADERS.contains(&n.to_ascii_lowercase().as_str()))
+            .collect();
+        let fixture = json!({
+            "request": {
+                "method": request.method,
+                "url": request.url,
+                "headers": request_headers,
+                "body": String::from_utf8_lossy(&request.decoded_body().unwrap_or(Cow::Borrowed(&request.body))),
+            },
+            "response": {
+                "status": response.status,
+                "headers": response_headers,
+                "body": response.body,
+            },
+        });
+        
+        let path = self.dir.join(fixture_name(request));
+        let write = std::fs::create_dir_all(&self.dir)
+            .and_then(|_| atomic_write(&path, |file| file.write_all((serde_json::to_string_pretty(&fixture).unwrap() + "\n").as_bytes())));
+        write.map_err(|e| JinaError::Transport(format!("recording {}: {}", path.display(), e)))?;
+        Ok(response)
+    }    
+    fn label(&self) -> &'static str { self.inner.label() }
+}
+
+/// Serves recorded fixtures by request fingerprint; never touches the network
+pub struct ReplayTransport {
+    dir: PathBuf,
+}
+
+impl ReplayTransport {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+    
+    fn load(path: &Path) -> Result<HttpResponse, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let fixture: Value = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        let response = &fixture["response"];
+        let headers = serde_json::from_value(response["headers"].clone()).map_err(|e| e.to_string())?;
+        Ok(HttpResponse {
+            status: response["status"].as_u64().ok_or("missing response.status")? as u16,
+            headers,
+            body: response["body"].as_str().ok_or("missing response.body")?.to_string(),
+        })
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse, JinaError> {
+        let path = self.dir.join(fixture_name(request));
+        if !path.exists() {
+            return Err(JinaError::Transport(format!(
+                "no recorded fixture {} for {} {}; record it with {}={}",
+                path.display(), request.method, request.url, RECORD_ENV, self.dir.display())));
+        }
+        Self::load(&path).map_err(|e| JinaError::Transport(format!("bad fixture {}: {}", path.display(), e)))
+    }    
+    fn label(&self) -> &'static str { "replay" }
+}
+
+/// `transport` wrapped in a `RecordingTransport` if `SPO_CRYSTAL_RECORD` is set
+pub fn record_from_env(transport: Arc<dyn Transport>) -> Arc<dyn Transport> {
+    match std::env::var_os(RECORD_ENV) {
+        Some(dir) if !dir.is_empty() => Arc::new(RecordingTransport::new(transport, dir)),
+        _ => transport,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jina_api::{EmbedOptions, JinaClient};
+    use crate::transport::RetryPolicy;
+    
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/replay/jina");
+    
+    fn replay_client() -> JinaClient {
+        JinaClient::new("unused").with_transport(ReplayTransport::new(FIXTURES)).with_retry(RetryPolicy::none())
+    }
+    
+    #[test]
+    fn test_replay_committed_fixtures() {
+        let options = EmbedOptions::query().with_dimensions(8);
+        let embeddings = replay_client().embed_batch_full(&["Ada loves Jan", "Jan loves Ada"], &options).unwrap().embeddings;
+        assert_eq!(embeddings.len(), 2);
+        assert!(embeddings.iter().all(|v| v.len() == 8));
+        assert_eq!(embeddings[0][0], 0.0423);
+        
+        let err = replay_client().embed_batch_full(&[""], &options).unwrap_err().to_string();
+        assert!(err.starts_with("API error 422: "), "{}", err);
+        
+        let err = replay_client().embed_batch_full(&["rate limited"], &options).unwrap_err().to_string();
+        assert_eq!(err, "API error 429: Rate limit exceeded, please retry in 60 seconds");
+        
+        let err = replay_client().embed_batch_full(&["never recorded"], &options).unwrap_err().to_string();
+        assert!(err.contains("no recorded fixture") && err.contains(RECORD_ENV), "{}", err);
+    }
+    
+    #[test]
+    fn test_record_strips_secrets_and_replays() {
+        let dir = std::env::temp_dir().join(format!("spo-crystal-record-{}", std::process::id()));
+        let upstream: Arc<dyn Transport> = Arc::new(|_: &HttpRequest| Ok(HttpResponse {
+            status: 200,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string()),
+                          ("Date".to_string(), "Tue, 14 Oct 2025 09:00:00 GMT".to_string())],
+            body: r#"{"ok":true}"#.to_string(),
+        }));
+        let recorder = RecordingTransport::new(upstream, &dir);
+        let request = HttpRequest::post_json("https://api.example.com/v1/x", &json!({"input": ["a"], "request_id": "r-1"}))
+            .bearer(Some("secret-key"));
+        let recorded = recorder.send(&request).unwrap();
+        
+        let text = std::fs::read_to_string(dir.join(fixture_name(&request))).unwrap();
+        assert!(!text.contains("secret-key") && !text.contains("Date"), "{}", text);
+        
+        // Another key and request id fingerprint the same; the input does not
+        let rerun = HttpRequest::post_json("https://api.example.com/v1/x", &json!({"request_id": "r-2", "input": ["a"]}))
+            .bearer(Some("other-key"));
+        assert_eq!(fingerprint(&rerun), fingerprint(&request));
+        assert_ne!(fingerprint(&HttpRequest::post_json("https://api.example.com/v1/x", &json!({"input": ["b"]}))),
+                   fingerprint(&request));
+        
+        let replayed = ReplayTransport::new(&dir).send(&rerun).unwrap();
+        assert_eq!(replayed.body, recorded.body);
+        assert_eq!(replayed.header("content-type"), Some("application/json"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
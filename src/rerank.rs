@@ -0,0 +1,176 @@
+//! Jina reranker (`/v1/rerank`)
+//!
+//! Cross-encoder scores for (query, document) pairs, for reranking what
+//! embedding search recalled. Offline clients rank by pseudo-embedding
+//! cosine instead, so pipelines run end to end without a key.
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::error::{truncate_for_display, JinaError, MAX_DISPLAY_CHARS};
+use crate::jina_api::JinaClient;
+use crate::pseudo::PseudoEmbedder;
+use crate::search::cosine;
+
+pub const DEFAULT_RERANK_MODEL: &str = "jina-reranker-v2-base-multilingual";
+
+/// One reranked document, best first
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RerankHit {
+    /// Position in the `documents` passed to `rerank`
+    pub index: usize,
+    pub score: f32,
+    #[serde(default)]
+    pub document: Option<String>,
+}
+
+impl JinaClient {
+    /// Reranker model, e.g. `jina-reranker-v1-turbo-en`
+    pub fn with_rerank_model(mut self, model: &str) -> Self {
+        self.rerank_model = model.to_string();
+        self
+    }
+    
+    /// Score `documents` against `query`, best first, keeping the `top_n` best if set
+    pub fn rerank(&self, query: &str, documents: &[&str], top_n: Option<usize>) -> Result<Vec<RerankHit>, JinaError> {
+        if top_n == Some(0) {
+            return Err(JinaError::InvalidInput("top_n must be at least 1".to_string()));
+        }
+        if documents.is_empty() {
+            return Ok(Vec::new());
+        }
+        match self.post("/v1/rerank", &rerank_body(&self.rerank_model, query, documents, top_n))? {
+            Some(body) => parse_rerank(&body, documents.len()),
+            None => Ok(rerank_offline(query, documents, top_n)),
+        }
+    }
+}
+
+fn rerank_body(model: &str, query: &str, documents: &[&str], top_n: Option<usize>) -> serde_json::Value {
+    let mut body = json!({
+        "model": model,
+        "query": query,
+        "documents": documents,
+        "return_documents": true,
+    });
+    if let Some(n) = top_n {
+        body["top_n"] = json!(n);
+    }
+    body
+}
+
+#[derive(Deserialize)]
+struct Response {
+    results: Vec<Item>,
+}
+
+#[derive(Deserialize)]
+struct Item {
+    index: usize,
+    relevance_score: f32,
+    #[serde(default)]
+    document: Option<Document>,
+}
+
+/// `{"text": ...}` from the API, a bare string from some gateways
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Document {
+    Object { text: String },
+    Text(String),
+}
+
+fn parse_rerank(body: &str, documents: usize) -> Result<Vec<RerankHit>, JinaError> {
+    let response: Response = serde_json::from_str(body)
+        .map_err(|e| JinaError::Parse(format!("rerank response: {}", truncate_for_display(&e.to_string(), MAX_DISPLAY_CHARS))))?;
+    let mut hits = Vec::with_capacity(response.results.len());
+    for item in response.results {
+        if item.index >= documents {
+            return Err(JinaError::Parse(format!("rerank index {} out of range for {} documents", item.index, documents)));
+        }
+        hits.push(RerankHit {
+            index: item.index,
+            score: item.relevance_score,
+            document: item.document.map(|d| match d {
+                Document::Object { text } | Document::Text(text) => text,
+            }),
+        });
+    }
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    Ok(hits)
+}
+
+fn rerank_offline(query: &str, documents: &[&str], top_n: Option<usize>) -> Vec<RerankHit> {
+    let embedder = PseudoEmbedder::new(1024);
+    let q = embedder.embed(query);
+    let mut hits: Vec<RerankHit> = documents.iter().enumerate()
+        .map(|(index, d)| RerankHit { index, score: cosine(&q, &embedder.embed(d)), document: Some(d.to_string()) })
+        .collect();
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    hits.truncate(top_n.unwrap_or(documents.len()));
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{HttpRequest, HttpResponse, RetryPolicy};
+    use std::sync::{Arc, Mutex};
+    
+    const FIXTURE: &str = include_str!("../fixtures/jina/rerank.json");
+    
+    #[test]
+    fn test_parse_rerank_fixture() {
+        let hits = parse_rerank(FIXTURE, 3).unwrap();
+        assert_eq!(hits, vec![
+            RerankHit { index: 2, score: 0.8313, document: Some("Cats sleep up to sixteen hours a day".to_string()) },
+            RerankHit { index: 0, score: 0.4127, document: Some("Where cats like to nap".to_string()) },
+        ]);
+        assert!(matches!(parse_rerank(FIXTURE, 2), Err(JinaError::Parse(_))));
+        
+        let bare = r#"{"results":[{"index":0,"relevance_score":0.1},{"index":1,"relevance_score":0.9,"document":"b"}]}"#;
+        let hits = parse_rerank(bare, 2).unwrap();
+        assert_eq!((hits[0].index, hits[0].document.as_deref()), (1, Some("b")));
+        assert_eq!(hits[1].document, None);
+    }
+    
+    #[test]
+    fn test_rerank_request_body() {
+        let seen: Arc<Mutex<Vec<HttpRequest>>> = Arc::default();
+        let log = seen.clone();
+        let client = JinaClient::new("jina_test")
+            .with_rerank_model("jina-reranker-v1-turbo-en")
+            .with_retry(RetryPolicy::none())
+            .with_transport(move |request: &HttpRequest| {
+                log.lock().unwrap().push(request.clone());
+                Ok(HttpResponse { status: 200, headers: Vec::new(), body: FIXTURE.to_string() })
+            });
+        
+        let docs = ["Where cats like to nap", "Dog food prices", "Cats sleep up to sixteen hours a day"];
+        let hits = client.rerank("where do cats sleep", &docs, Some(2)).unwrap();
+        assert_eq!(hits.iter().map(|h| h.index).collect::<Vec<_>>(), vec![2, 0]);
+        
+        let request = seen.lock().unwrap()[0].clone();
+        assert_eq!(request.url, "https://api.jina.ai/v1/rerank");
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body, json!({
+            "model": "jina-reranker-v1-turbo-en",
+            "query": "where do cats sleep",
+            "documents": docs,
+            "top_n": 2,
+            "return_documents": true,
+        }));
+        assert!(rerank_body(DEFAULT_RERANK_MODEL, "q", &["d"], None).get("top_n").is_none());
+    }
+    
+    #[test]
+    fn test_rerank_offline() {
+        let client = JinaClient::new("test_key");
+        let docs = ["stock market news", "the cat sat on the mat", "a cat sat"];
+        let hits = client.rerank("the cat sat", &docs, Some(2)).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().all(|h| h.index != 0));
+        assert!(hits[0].score >= hits[1].score);
+        assert!(client.rerank("q", &docs, Some(0)).is_err());
+    }
+}
@@ -0,0 +1,348 @@
+//! Whole-response cache in front of a transport
+//!
+//! `TransportCache` wraps any `Transport` and answers a request it has
+//! already seen from memory until that request's TTL runs out, so layers
+//! that retry whole jobs don't pay twice for a GET (`/info`, Reader pages)
+//! or an identical POST made seconds apart. This is apart from the per-text
+//! embedding cache (`JinaClient::with_cache`), which survives any TTL.
+//!
+//! Requests are keyed by `replay::fingerprint` (method, URL and the body
+//! without volatile fields) and the `Authorization` value, so clients with
+//! different keys never share an entry. The TTL is that of the longest
+//! matching `with_route_ttl` prefix, else `DEFAULT_GET_TTL` or
+//! `DEFAULT_POST_TTL` by method; other methods and zero TTLs are not cached.
+//! Only 2xx responses are stored, and a request carrying
+//! `Cache-Control: no-cache` (`no_cache`) is neither answered from nor
+//! stored in the cache. Entries over `max_bytes` in total are evicted least
+//! recently used first.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::JinaError;
+use crate::replay::fingerprint;
+use crate::transport::{Clock, HttpRequest, HttpResponse, SystemClock, Timings, Transport};
+
+pub const DEFAULT_GET_TTL: Duration = Duration::from_secs(60);
+pub const DEFAULT_POST_TTL: Duration = Duration::from_secs(10);
+pub const DEFAULT_MAX_BYTES: usize = 16 << 20;
+
+/// `request` with `Cache-Control: no-cache`, which `TransportCache` passes straight through
+pub fn no_cache(request: HttpRequest) -> HttpRequest {
+    request.header("Cache-Control", "no-cache")
+}
+
+/// Hit and miss counts and current size of a `TransportCache`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransportCacheStats {
+    pub hits: u64,
+    /// Cacheable requests sent on; bypassed ones are not counted
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+struct Entry {
+    response: HttpResponse,
+    expires: Instant,
+    bytes: usize,
+    /// Position in `Lru::order`
+    used: u64,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<u64, Entry>,
+    /// Keys by last use, oldest first
+    order: BTreeMap<u64, u64>,
+    tick: u64,
+    bytes: usize,
+    hits: u64,
+    misses: u64,
+}
+
+impl Lru {
+    fn touch(&mut self, key: u64) {
+        self.tick += 1;
+        let entry = self.entries.get_mut(&key).unwrap();
+        self.order.remove(&entry.used);
+        entry.used = self.tick;
+        self.order.insert(self.tick, key);
+    }
+    
+    fn remove(&mut self, key: u64) {
+        if let Some(entry) = self.entries.remove(&key) {
+            self.order.remove(&entry.used);
+            self.bytes -= entry.bytes;
+        }
+    }
+}
+
+pub struct TransportCache {
+    inner: Arc<dyn Transport>,
+    routes: Vec<(String, Duration)>,
+    get_ttl: Duration,
+    post_ttl: Duration,
+    max_bytes: usize,
+    clock: Arc<dyn Clock>,
+    lru: Mutex<Lru>,
+}
+
+impl TransportCache {
+    pub fn new(inner: Arc<dyn Transport>) -> Self {
+        Self {
+            inner,
+            routes: Vec::new(),
+            get_ttl: DEFAULT_GET_TTL,
+            post_ttl: DEFAULT_POST_TTL,
+            max_bytes: DEFAULT_MAX_BYTES,
+            clock: Arc::new(SystemClock),
+            lru: Mutex::default(),
+        }
+    }
+    
+    /// TTL of requests whose URL starts with `prefix`; zero to never cache them
+    pub fn with_route_ttl(mut self, prefix: &str, ttl: Duration) -> Self {
+        self.routes.push((prefix.to_string(), ttl));
+        self
+    }
+    
+    /// TTL of GETs no route matches
+    pub fn with_get_ttl(mut self, ttl: Duration) -> Self {
+        self.get_ttl = ttl;
+        self
+    }
+    
+    /// TTL of POSTs no route matches
+    pub fn with_post_ttl(mut self, ttl: Duration) -> Self {
+        self.post_ttl = ttl;
+        self
+    }
+    
+    /// Cap on the bytes of cached bodies, headers and URLs
+    pub fn with_max_bytes(mut self, bytes: usize) -> Self {
+        self.max_bytes = bytes;
+        self
+    }
+    
+    /// Expire entries by `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+    
+    pub fn stats(&self) -> TransportCacheStats {
+        let lru = self.lru.lock().unwrap();
+        TransportCacheStats { hits: lru.hits, misses: lru.misses, entries: lru.entries.len(), bytes: lru.bytes }
+    }
+    
+    /// Drop every entry, keeping the counts
+    pub fn clear(&self) {
+        let mut lru = self.lru.lock().unwrap();
+        lru.entries.clear();
+        lru.order.clear();
+        lru.bytes = 0;
+    }
+    
+    /// How long `request`'s response may be served again; `None` if not at all
+    fn ttl(&self, request: &HttpRequest) -> Option<Duration> {
+        let bypass = request.headers.iter().any(|(name, value)| {
+            name.eq_ignore_ascii_case("cache-control") && ["no-cache", "no-store"].iter().any(|d| value.contains(d))
+        });
+        if bypass {
+            return None;
+        }
+        let routed = self.routes.iter()
+            .filter(|(prefix, _)| request.url.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, ttl)| *ttl);
+        let ttl = match (routed, request.method) {
+            (Some(ttl), _) => ttl,
+            (None, "GET") => self.get_ttl,
+            (None, "POST") => self.post_ttl,
+            (None, _) => return None,
+        };
+        Some(ttl).filter(|ttl| !ttl.is_zero() && matches!(request.method, "GET" | "POST"))
+    }
+    
+    fn key(request: &HttpRequest) -> u64 {
+        let auth = request.headers.iter().find(|(name, _)| name.eq_ignore_ascii_case("authorization")).map_or("", |(_, v)| v);
+        let mut h = fingerprint(request);
+        for &b in auth.as_bytes() {
+            h ^= b as u64;
+            h = h.wrapping_mul(0x100000001b3);
+        }
+        h
+    }
+    
+    fn lookup(&self, key: u64) -> Option<HttpResponse> {
+        let now = self.clock.now();
+        let mut lru = self.lru.lock().unwrap();
+        match lru.entries.get(&key) {
+            Some(entry) if entry.expires > now => {
+                let response = entry.response.clone();
+                lru.touch(key);
+                lru.hits += 1;
+                Some(response)
+            }
+            expired => {
+                if expired.is_some() {
+                    lru.remove(key);
+                }
+                lru.misses += 1;
+                None
+            }
+        }
+    }
+    
+    fn store(&self, key: u64, request: &HttpRequest, response: &HttpResponse, ttl: Duration) {
+        let bytes = request.url.len() + response.body.len() + response.headers.iter().map(|(n, v)| n.len() + v.len()).sum::<usize>();
+        if bytes > self.max_bytes {
+            return;
+        }
+        let expires = self.clock.now() + ttl;
+        let mut lru = self.lru.lock().unwrap();
+        lru.remove(key);
+        while lru.bytes + bytes > self.max_bytes {
+            let Some((_, oldest)) = lru.order.pop_first() else { break };
+            let entry = lru.entries.remove(&oldest).unwrap();
+            lru.bytes -= entry.bytes;
+        }
+        lru.entries.insert(key, Entry { response: response.clone(), expires, bytes, used: 0 });
+        lru.bytes += bytes;
+        lru.touch(key);
+    }
+}
+
+impl Transport for TransportCache {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse, JinaError> {
+        self.send_timed(request).0
+    }
+    
+    fn send_timed(&self, request: &HttpRequest) -> (Result<HttpResponse, JinaError>, Timings) {
+        let Some(ttl) = self.ttl(request) else { return self.inner.send_timed(request) };
+        let key = Self::key(request);
+        if let Some(response) = self.lookup(key) {
+            return (Ok(response), Timings::default());
+        }
+        let (result, timings) = self.inner.send_timed(request);
+        if let Ok(response) = &result {
+            if (200..300).contains(&response.status) {
+                self.store(key, request, response, ttl);
+            }
+        }
+        (result, timings)
+    }
+    
+    fn label(&self) -> &'static str { self.inner.label() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::ManualClock;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    
+    /// Transport answering `status` with a body naming the request's URL and send count
+    fn counting(status: u16) -> (Arc<AtomicUsize>, Arc<dyn Transport>) {
+        let sent = Arc::new(AtomicUsize::new(0));
+        let count = sent.clone();
+        let transport = move |request: &HttpRequest| {
+            let n = count.fetch_add(1, Ordering::Relaxed) + 1;
+            Ok(HttpResponse { status, headers: Vec::new(), body: format!("{} #{}", request.url, n) })
+        };
+        (sent, Arc::new(transport))
+    }
+    
+    #[test]
+    fn test_hits_misses_and_ttl_expiry() {
+        let clock = Arc::new(ManualClock::new());
+        let (sent, inner) = counting(200);
+        let cache = TransportCache::new(inner).with_clock(clock.clone()).with_route_ttl("https://r.jina.ai/", Duration::from_secs(600));
+        let info = HttpRequest::get("https://api.jina.ai/info").bearer(Some("key-a"));
+        let body = serde_json::json!({ "input": ["a"], "request_id": "r-1" });
+        let post = HttpRequest::post_json("https://api.jina.ai/v1/embeddings", &body).bearer(Some("key-a"));
+        
+        assert_eq!(cache.send(&info).unwrap().body, "https://api.jina.ai/info #1");
+        assert_eq!(cache.send(&info).unwrap().body, "https://api.jina.ai/info #1");
+        // Volatile fields aside, an identical POST is a hit; another key is not
+        let retried = HttpRequest::post_json(post.url.clone(), &serde_json::json!({ "input": ["a"], "request_id": "r-2" }))
+            .bearer(Some("key-a"));
+        cache.send(&post).unwrap();
+        assert_eq!(cache.send(&retried).unwrap().body, cache.send(&post).unwrap().body);
+        cache.send(&HttpRequest::get(info.url.clone()).bearer(Some("key-b"))).unwrap();
+        assert_eq!(sent.load(Ordering::Relaxed), 3);
+        assert_eq!(cache.stats(), TransportCacheStats { hits: 3, misses: 3, entries: 3, bytes: cache.stats().bytes });
+        
+        // POSTs expire after 10s, GETs after 60s, the Reader route after 10 minutes
+        let page = HttpRequest::get("https://r.jina.ai/https://example.com");
+        cache.send(&page).unwrap();
+        clock.advance(Duration::from_secs(11));
+        assert!(cache.send(&post).unwrap().body.ends_with("#5"));
+        assert!(cache.send(&info).unwrap().body.ends_with("#1"));
+        clock.advance(Duration::from_secs(50));
+        assert!(cache.send(&info).unwrap().body.ends_with("#6"));
+        assert!(cache.send(&page).unwrap().body.ends_with("#4"));
+        clock.advance(Duration::from_secs(600));
+        assert!(cache.send(&page).unwrap().body.ends_with("#7"));
+    }
+    
+    #[test]
+    fn test_errors_and_no_cache_bypass() {
+        let (sent, inner) = counting(503);
+        let cache = TransportCache::new(inner);
+        let info = HttpRequest::get("https://api.jina.ai/info");
+        cache.send(&info).unwrap();
+        cache.send(&info).unwrap();
+        assert_eq!((sent.load(Ordering::Relaxed), cache.stats().entries), (2, 0));
+        let failing = TransportCache::new(Arc::new(|_: &HttpRequest| Err(JinaError::Transport("down".to_string()))));
+        assert!(failing.send(&info).is_err());
+        assert_eq!(failing.stats().entries, 0);
+        
+        let (sent, inner) = counting(200);
+        let cache = TransportCache::new(inner).with_route_ttl("https://api.jina.ai/v1/rerank", Duration::ZERO);
+        cache.send(&info).unwrap();
+        let fresh = no_cache(info.clone());
+        assert!(cache.send(&fresh).unwrap().body.ends_with("#2"));
+        assert!(cache.send(&info).unwrap().body.ends_with("#1"));
+        let rerank = HttpRequest::post_json("https://api.jina.ai/v1/rerank", &serde_json::json!({ "query": "q" }));
+        cache.send(&rerank).unwrap();
+        cache.send(&rerank).unwrap();
+        let delete = HttpRequest { method: "DELETE", ..info.clone() };
+        cache.send(&delete).unwrap();
+        cache.send(&delete).unwrap();
+        assert_eq!(sent.load(Ordering::Relaxed), 6);
+        assert_eq!((cache.stats().hits, cache.stats().misses, cache.stats().entries), (1, 1, 1));
+    }
+    
+    #[test]
+    fn test_lru_eviction_stays_under_the_byte_cap() {
+        let (sent, inner) = counting(200);
+        let url = |i: usize| format!("https://api.jina.ai/doc/{}", i);
+        let size = 2 * url(0).len() + " #1".len();
+        let cache = TransportCache::new(inner).with_max_bytes(3 * size);
+        for i in 0..3 {
+            cache.send(&HttpRequest::get(url(i))).unwrap();
+        }
+        // Using doc 0 makes doc 1 the least recent, evicted by doc 3
+        cache.send(&HttpRequest::get(url(0))).unwrap();
+        cache.send(&HttpRequest::get(url(3))).unwrap();
+        assert_eq!(cache.stats().entries, 3);
+        assert!(cache.stats().bytes <= 3 * size);
+        let before = sent.load(Ordering::Relaxed);
+        for i in [0, 2, 3] {
+            cache.send(&HttpRequest::get(url(i))).unwrap();
+        }
+        assert_eq!(sent.load(Ordering::Relaxed), before);
+        cache.send(&HttpRequest::get(url(1))).unwrap();
+        assert_eq!(sent.load(Ordering::Relaxed), before + 1);
+        
+        // Too large to ever fit: sent, not stored
+        let tiny = TransportCache::new(counting(200).1).with_max_bytes(10);
+        tiny.send(&HttpRequest::get(url(0))).unwrap();
+        assert_eq!(tiny.stats(), TransportCacheStats { hits: 0, misses: 1, entries: 0, bytes: 0 });
+        cache.clear();
+        assert_eq!((cache.stats().entries, cache.stats().bytes), (0, 0));
+    }
+}
@@ -0,0 +1,243 @@
+//! Composite provider routing each text to one of several backends
+//!
+//! Routes are tried in order and the first whose predicate accepts the text
+//! embeds it; a route without a predicate accepts everything, so it belongs
+//! last. A mixed batch is split per route, sent as one call per route and
+//! reassembled in input order. All routes must produce the same vector size:
+//! sizes known up front are checked in `RoutingProvider::new`, sizes learned
+//! from responses are checked on every call.
+
+use std::sync::Arc;
+
+use crate::error::JinaError;
+use crate::jina_api::EmbedOptions;
+use crate::lang;
+use crate::provider::{check_dims, EmbedError, EmbeddingProvider, LearnedDims};
+
+/// Text plus hints routing predicates can look at
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RoutedText<'a> {
+    pub text: &'a str,
+    /// Language tag such as `"de"`, if the caller knows it
+    pub language: Option<&'a str>,
+}
+
+impl<'a> RoutedText<'a> {
+    pub fn new(text: &'a str) -> Self { Self { text, language: None } }
+    
+    pub fn with_language(mut self, language: &'a str) -> Self {
+        self.language = Some(language);
+        self
+    }
+}
+
+type Predicate = Box<dyn Fn(&RoutedText) -> bool + Send + Sync>;
+
+/// A named backend and the texts it accepts
+pub struct Route {
+    name: String,
+    provider: Arc<dyn EmbeddingProvider>,
+    predicate: Option<Predicate>,
+}
+
+impl Route {
+    /// Route accepting every text until `when` narrows it
+    pub fn new(name: &str, provider: impl EmbeddingProvider + 'static) -> Self {
+        Self { name: name.to_string(), provider: Arc::new(provider), predicate: None }
+    }
+    
+    pub fn when(mut self, predicate: impl Fn(&RoutedText) -> bool + Send + Sync + 'static) -> Self {
+        self.predicate = Some(Box::new(predicate));
+        self
+    }
+    
+    /// Accept texts of fewer than `n` characters
+    pub fn shorter_than(self, n: usize) -> Self {
+        self.when(move |t| t.text.chars().count() < n)
+    }
+    
+    /// Accept texts tagged with `language`
+    pub fn language(self, language: &'static str) -> Self {
+        self.when(move |t| t.language == Some(language))
+    }
+    
+    /// Accept texts tagged with `language`, or untagged texts `lang::detect` takes for it
+    pub fn detected_language(self, language: &'static str) -> Self {
+        self.when(move |t| t.language.unwrap_or_else(|| lang::detect(t.text).lang) == language)
+    }
+    
+    pub fn name(&self) -> &str { &self.name }
+    
+    fn accepts(&self, text: &RoutedText) -> bool {
+        self.predicate.as_ref().is_none_or(|p| p(text))
+    }
+}
+
+pub struct RoutingProvider {
+    routes: Vec<Route>,
+    dims: LearnedDims,
+}
+
+impl RoutingProvider {
+    /// Errors if no routes are given or two routes report different sizes
+    pub fn new(routes: Vec<Route>) -> Result<Self, JinaError> {
+        if routes.is_empty() {
+            return Err(JinaError::InvalidInput("RoutingProvider needs at least one route".to_string()));
+        }
+        // 0 means the backend learns its size from the first response
+        let mut known: Option<(&str, usize)> = None;
+        for route in &routes {
+            let dims = route.provider.dimensions();
+            match known {
+                _ if dims == 0 => {}
+                None => known = Some((&route.name, dims)),
+                Some((first, expected)) if dims != expected => {
+                    return Err(JinaError::InvalidInput(format!(
+                        "route {} produces {} dimensions but route {} produces {}", route.name, dims, first, expected)));
+                }
+                Some(_) => {}
+            }
+        }
+        let dims = known.map_or_else(LearnedDims::default, |(_, dims)| LearnedDims::known(dims));
+        Ok(Self { routes, dims })
+    }
+    
+    /// Name of the route `text` would take
+    pub fn route_for(&self, text: &RoutedText) -> Option<&str> {
+        self.routes.iter().find(|r| r.accepts(text)).map(|r| r.name.as_str())
+    }
+    
+    /// Embed texts with routing hints; results are in input order
+    pub fn embed_routed(&self, texts: &[RoutedText], options: &EmbedOptions) -> Result<Vec<Vec<f32>>, JinaError> {
+        // Input positions per route
+        let mut groups: Vec<Vec<usize>> = vec![Vec::new(); self.routes.len()];
+        for (i, text) in texts.iter().enumerate() {
+            let route = self.routes.iter().position(|r| r.accepts(text))
+                .ok_or_else(|| JinaError::InvalidInput(format!("no route accepts text #{}", i)))?;
+            groups[route].push(i);
+        }
+        
+        let mut out: Vec<Vec<f32>> = vec![Vec::new(); texts.len()];
+        for (route, positions) in self.routes.iter().zip(&groups) {
+            if positions.is_empty() {
+                continue;
+            }
+            let batch: Vec<&str> = positions.iter().map(|&i| texts[i].text).collect();
+            let embeddings = route.provider.embed_batch_with(&batch, options)
+                .and_then(|e| {
+                    if e.len() != batch.len() {
+                        return Err(JinaError::Mismatch { expected: batch.len(), got: e.len() });
+                    }
+                    // Per-call dimensions override the shared size
+                    match options.dimensions {
+                        Some(dims) => check_dims(&e, dims)?,
+                        None => self.dims.check(&e)?,
+                    }
+                    Ok(e)
+                })
+                .map_err(|e| JinaError::Route { route: route.name.clone(), source: Box::new(e) })?;
+            for (&i, embedding) in positions.iter().zip(embeddings) {
+                out[i] = embedding;
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl EmbeddingProvider for RoutingProvider {
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        self.embed_batch_with(texts, &EmbedOptions::default())
+    }
+    
+    fn embed_batch_with(&self, texts: &[&str], options: &EmbedOptions) -> Result<Vec<Vec<f32>>, EmbedError> {
+        let routed: Vec<RoutedText> = texts.iter().map(|t| RoutedText::new(t)).collect();
+        self.embed_routed(&routed, options)
+    }
+    
+    /// Shared size of all routes (0 until known)
+    fn dimensions(&self) -> usize { self.dims.get() }
+    
+    /// Whether any route is synthetic, such as a pseudo-embedder of last resort:
+    /// a batch may then mix real and synthetic vectors
+    fn is_synthetic(&self) -> bool { self.routes.iter().any(|r| r.provider.is_synthetic()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockProvider;
+    
+    fn local_and_remote() -> (Arc<MockProvider>, Arc<MockProvider>, RoutingProvider) {
+        let local = Arc::new(MockProvider::new(2).with_default(vec![1.0, 0.0]));
+        let remote = Arc::new(MockProvider::new(2).with_default(vec![0.0, 1.0]));
+        let router = RoutingProvider::new(vec![
+            Route::new("tei", local.clone()).shorter_than(12),
+            Route::new("jina", remote.clone()),
+        ]).unwrap();
+        (local, remote, router)
+    }
+    
+    #[test]
+    fn test_routes_by_length_and_preserves_order() {
+        let (local, remote, router) = local_and_remote();
+        let texts = ["short", "a much longer document", "tiny", "another long document"];
+        let embeddings = router.embed_batch(&texts).unwrap();
+        
+        assert_eq!(embeddings, vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![1.0, 0.0], vec![0.0, 1.0]]);
+        assert_eq!(local.calls(), vec![vec!["short", "tiny"]]);
+        assert_eq!(remote.calls(), vec![vec!["a much longer document", "another long document"]]);
+        assert_eq!(router.route_for(&RoutedText::new("short")), Some("tei"));
+        assert_eq!(router.dimensions(), 2);
+    }
+    
+    #[test]
+    fn test_language_routes_and_validation() {
+        let german = Arc::new(MockProvider::new(2).with_default(vec![0.5, 0.5]));
+        let router = RoutingProvider::new(vec![
+            Route::new("de", german.clone()).language("de"),
+            Route::new("default", MockProvider::new(2).with_default(vec![1.0, 0.0])),
+        ]).unwrap();
+        let texts = [RoutedText::new("Hallo Welt").with_language("de"), RoutedText::new("Hello world")];
+        let embeddings = router.embed_routed(&texts, &EmbedOptions::default()).unwrap();
+        assert_eq!(embeddings, vec![vec![0.5, 0.5], vec![1.0, 0.0]]);
+        assert_eq!(german.calls(), vec![vec!["Hallo Welt"]]);
+        
+        let mismatched = RoutingProvider::new(vec![
+            Route::new("small", MockProvider::new(2)),
+            Route::new("big", MockProvider::new(3)),
+        ]);
+        assert!(matches!(mismatched, Err(JinaError::InvalidInput(msg)) if msg.contains("big")));
+        
+        // Without a catch-all route, unmatched texts are rejected
+        let narrow = RoutingProvider::new(vec![Route::new("de", MockProvider::new(2)).language("de")]).unwrap();
+        assert!(matches!(narrow.embed("x"), Err(JinaError::InvalidInput(_))));
+        
+        // Untagged texts route on the detected language; tags still win
+        let cjk = RoutingProvider::new(vec![
+            Route::new("ja", MockProvider::new(2)).detected_language("ja"),
+            Route::new("de", MockProvider::new(2)).detected_language("de"),
+            Route::new("default", MockProvider::new(2)),
+        ]).unwrap();
+        assert_eq!(cjk.route_for(&RoutedText::new("今日はいい天気ですね。")), Some("ja"));
+        assert_eq!(cjk.route_for(&RoutedText::new("Das ist nicht der Weg zum Bahnhof.")), Some("de"));
+        assert_eq!(cjk.route_for(&RoutedText::new("今日はいい天気ですね。").with_language("en")), Some("default"));
+        assert_eq!(cjk.route_for(&RoutedText::new("12345")), Some("default"));
+    }
+    
+    #[test]
+    fn test_failure_names_the_route() {
+        let local = MockProvider::new(2).with_default(vec![1.0, 0.0]);
+        let remote = MockProvider::new(2).fail_on_call(1, JinaError::Api { status: 503, message: "busy".to_string() });
+        let router = RoutingProvider::new(vec![
+            Route::new("tei", local).shorter_than(12),
+            Route::new("jina", remote),
+        ]).unwrap();
+        
+        let err = router.embed_batch(&["short", "a much longer document"]).unwrap_err();
+        assert_eq!(err, JinaError::Route {
+            route: "jina".to_string(),
+            source: Box::new(JinaError::Api { status: 503, message: "busy".to_string() }),
+        });
+        assert_eq!(err.to_string(), "Route jina: API error 503: busy");
+    }
+}
@@ -0,0 +1,199 @@
+//! Deterministic seeded sampling of corpora
+//!
+//! Drift reports, evaluation sets and clustering all need "a representative
+//! N of these"; this module gives them one source of randomness. For a
+//! fixed seed and input every function returns the same sample on every
+//! platform and release: the generator is a plain splitmix64 stream (not
+//! `rand`, whose `StdRng` may change between versions), and changing its
+//! output for an existing seed is a breaking change.
+//!
+//! - `reservoir`: `n` items of a stream of unknown length, each equally likely
+//! - `stratified`: `n` records split over strata in proportion to their size
+//! - `kmeans_pp_seeds`: k-means++ initial centers, spread out by distance
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::pseudo::splitmix64;
+
+const GOLDEN_GAMMA: u64 = 0x9e3779b97f4a7c15;
+
+/// splitmix64 generator
+#[derive(Clone, Debug)]
+pub struct SampleRng {
+    state: u64,
+}
+
+impl SampleRng {
+    pub fn new(seed: u64) -> Self { Self { state: seed } }
+    
+    pub fn next_u64(&mut self) -> u64 {
+        let out = splitmix64(self.state);
+        self.state = self.state.wrapping_add(GOLDEN_GAMMA);
+        out
+    }
+    
+    /// Uniform in `0..n`; `n` must be nonzero
+    pub fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+    
+    /// Uniform in [0, 1)
+    pub fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// `n` items of `items`, each equally likely to be kept, in source order.
+///
+/// Reads the whole iterator once and holds only `n` items (Algorithm R).
+pub fn reservoir<T>(items: impl IntoIterator<Item = T>, n: usize, seed: u64) -> Vec<T> {
+    let mut rng = SampleRng::new(seed);
+    let mut kept: Vec<(usize, T)> = Vec::with_capacity(n);
+    for (i, item) in items.into_iter().enumerate() {
+        if kept.len() < n {
+            kept.push((i, item));
+        } else if n > 0 {
+            let j = rng.below(i + 1);
+            if j < n {
+                kept[j] = (i, item);
+            }
+        }
+    }
+    kept.sort_by_key(|&(i, _)| i);
+    kept.into_iter().map(|(_, item)| item).collect()
+}
+
+/// `n` of `records` (all of them if fewer), each stratum of `key` getting
+/// its proportional share; returned in input order.
+///
+/// Shares are rounded by largest remainder, ties to the stratum seen first.
+pub fn stratified<T, K: Eq + Hash>(records: &[T], key: impl Fn(&T) -> K, n: usize, seed: u64) -> Vec<&T> {
+    if n >= records.len() {
+        return records.iter().collect();
+    }
+    // Record indices per stratum, strata in order of first appearance
+    let mut slots: HashMap<K, usize> = HashMap::new();
+    let mut strata: Vec<Vec<usize>> = Vec::new();
+    for (i, record) in records.iter().enumerate() {
+        let slot = *slots.entry(key(record)).or_insert_with(|| {
+            strata.push(Vec::new());
+            strata.len() - 1
+        });
+        strata[slot].push(i);
+    }
+    
+    let total = records.len();
+    let mut quotas: Vec<usize> = strata.iter().map(|s| s.len() * n / total).collect();
+    let mut by_remainder: Vec<usize> = (0..strata.len()).collect();
+    by_remainder.sort_by_key(|&s| std::cmp::Reverse(strata[s].len() * n % total));
+    for &s in by_remainder.iter().take(n - quotas.iter().sum::<usize>()) {
+        quotas[s] += 1;
+    }
+    
+    let mut picked: Vec<usize> = strata.iter().zip(&quotas).enumerate()
+        .flat_map(|(s, (members, &quota))| reservoir(members.iter().copied(), quota, splitmix64(seed ^ s as u64)))
+        .collect();
+    picked.sort_unstable();
+    picked.into_iter().map(|i| &records[i]).collect()
+}
+
+/// Indices of `k` k-means++ initial centers (fewer if there are fewer
+/// distinct vectors): the first uniformly, each next one with probability
+/// proportional to its squared distance from the nearest chosen center.
+pub fn kmeans_pp_seeds(vectors: &[Vec<f32>], k: usize, seed: u64) -> Vec<usize> {
+    if vectors.is_empty() || k == 0 {
+        return Vec::new();
+    }
+    let mut rng = SampleRng::new(seed);
+    let mut centers = vec![rng.below(vectors.len())];
+    let mut nearest: Vec<f64> = vectors.iter().map(|v| squared_distance(v, &vectors[centers[0]])).collect();
+    while centers.len() < k.min(vectors.len()) {
+        let total: f64 = nearest.iter().sum();
+        if total <= 0.0 {
+            break;
+        }
+        let target = rng.unit() * total;
+        let mut acc = 0.0;
+        let chosen = nearest.iter().position(|&d| {
+            acc += d;
+            d > 0.0 && acc > target
+        }).unwrap_or_else(|| nearest.iter().rposition(|&d| d > 0.0).unwrap());
+        centers.push(chosen);
+        for (d, v) in nearest.iter_mut().zip(vectors) {
+            *d = d.min(squared_distance(v, &vectors[chosen]));
+        }
+    }
+    centers
+}
+
+fn squared_distance(a: &[f32], b: &[f32]) -> f64 {
+    a.iter().zip(b).map(|(&x, &y)| (x as f64 - y as f64).powi(2)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_deterministic_for_a_seed() {
+        let items: Vec<u32> = (0..1000).collect();
+        assert_eq!(reservoir(items.iter().copied(), 20, 7), reservoir(items.iter().copied(), 20, 7));
+        assert_ne!(reservoir(items.iter().copied(), 20, 7), reservoir(items.iter().copied(), 20, 8));
+        // Pinned: a change here changes every seeded sample
+        assert_eq!(SampleRng::new(0).next_u64(), 0xe220a8397b1dcdaf);
+        let sample = reservoir(items.iter().copied(), 5, 42);
+        assert!(sample.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(reservoir(0..3, 5, 1), [0, 1, 2]);
+        assert!(reservoir(0..3, 0, 1).is_empty());
+        
+        let vectors: Vec<Vec<f32>> = (0..50).map(|i| vec![(i as f32).sin(), (i as f32).cos()]).collect();
+        assert_eq!(kmeans_pp_seeds(&vectors, 5, 3), kmeans_pp_seeds(&vectors, 5, 3));
+        let records: Vec<(u8, u32)> = (0..300).map(|i| ((i % 3) as u8, i)).collect();
+        assert_eq!(stratified(&records, |r| r.0, 30, 9), stratified(&records, |r| r.0, 30, 9));
+    }
+    
+    #[test]
+    fn test_stratum_proportions() {
+        let mut records: Vec<(&str, usize)> = Vec::new();
+        for (lang, count) in [("en", 600), ("de", 300), ("ja", 100)] {
+            records.extend((0..count).map(|i| (lang, i)));
+        }
+        let sample = stratified(&records, |r| r.0, 100, 5);
+        let count = |lang| sample.iter().filter(|r| r.0 == lang).count();
+        assert_eq!((count("en"), count("de"), count("ja")), (60, 30, 10));
+        
+        // 5 of sizes 5, 3, 2: exact shares 2.5, 1.5, 1; the tied remainder goes to the first stratum
+        let records: Vec<char> = "aaaaabbbcc".chars().collect();
+        let sample = stratified(&records, |&c| c, 5, 1);
+        assert_eq!(sample.iter().filter(|&&&c| c == 'a').count(), 3);
+        assert_eq!(sample.len(), 5);
+        assert_eq!(stratified(&records, |&c| c, 50, 1).len(), 10);
+    }
+    
+    #[test]
+    fn test_reservoir_uniformity_and_kmeans_spread() {
+        // Every item of 100 should be kept about 10% of the time over 2000 seeds (sd ~13.4)
+        let mut counts = [0usize; 100];
+        for seed in 0..2000 {
+            for i in reservoir(0..100usize, 10, seed) {
+                counts[i] += 1;
+            }
+        }
+        assert!(counts.iter().all(|&c| (140..=260).contains(&c)), "{:?}", counts);
+        let chi_square: f64 = counts.iter().map(|&c| (c as f64 - 200.0).powi(2) / 200.0).sum();
+        assert!(chi_square < 150.0, "chi-square {}", chi_square);
+        
+        // Two far-apart clusters: the two seeds land one in each
+        let mut vectors: Vec<Vec<f32>> = (0..20).map(|i| vec![i as f32 * 0.01, 0.0]).collect();
+        vectors.extend((0..20).map(|i| vec![100.0 + i as f32 * 0.01, 0.0]));
+        for seed in 0..20 {
+            let centers = kmeans_pp_seeds(&vectors, 2, seed);
+            assert_eq!(centers.iter().filter(|&&c| c < 20).count(), 1, "seed {}", seed);
+        }
+        // Only two distinct points: no more than two centers
+        let twins = vec![vec![1.0], vec![1.0], vec![2.0]];
+        assert_eq!(kmeans_pp_seeds(&twins, 3, 0).len(), 2);
+        assert!(kmeans_pp_seeds(&[], 3, 0).is_empty());
+    }
+}
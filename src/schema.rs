@@ -0,0 +1,309 @@
+//! Typed metadata schemas for index entries
+//!
+//! A `MetadataSchema` declares the fields entries may carry: a name, a type
+//! and whether the field is required. An index created `with_schema` checks
+//! every insert against it and refuses metadata that lacks a required field,
+//! holds a value of the wrong type or has a field the schema does not
+//! declare. `check_filter` catches a filter on an undeclared field (a typo
+//! such as `langg`) before a search scores anything.
+//!
+//! The schema is saved with the index. `CrystalIndex::migrate_schema` moves
+//! existing entries to a new schema; a `SchemaMigration` renames fields and
+//! supplies the defaults that newly required fields need.
+
+use std::fmt;
+
+use crate::metadata::{MetaValue, Metadata};
+
+/// Type of a declared field
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    String,
+    /// A whole number, held as `MetaValue::Num`
+    Int,
+    Float,
+    Bool,
+    /// One of a fixed set of strings
+    Enum(Vec<String>),
+}
+
+impl FieldType {
+    pub fn accepts(&self, value: &MetaValue) -> bool {
+        match (self, value) {
+            (FieldType::String, MetaValue::Str(_)) | (FieldType::Float, MetaValue::Num(_)) | (FieldType::Bool, MetaValue::Bool(_)) => true,
+            (FieldType::Int, MetaValue::Num(n)) => n.fract() == 0.0,
+            (FieldType::Enum(values), MetaValue::Str(s)) => values.contains(s),
+            _ => false,
+        }
+    }
+    
+    /// `text`, as given on a command line, as a value of this type
+    pub fn parse(&self, text: &str) -> Option<MetaValue> {
+        let value = match self {
+            FieldType::String | FieldType::Enum(_) => MetaValue::Str(text.to_string()),
+            FieldType::Int | FieldType::Float => MetaValue::Num(text.parse().ok()?),
+            FieldType::Bool => MetaValue::Bool(text.parse().ok()?),
+        };
+        self.accepts(&value).then_some(value)
+    }
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldType::String => write!(f, "a string"),
+            FieldType::Int => write!(f, "an int"),
+            FieldType::Float => write!(f, "a float"),
+            FieldType::Bool => write!(f, "a bool"),
+            FieldType::Enum(values) => write!(f, "one of {}", values.join(", ")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FieldSpec {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    pub required: bool,
+}
+
+/// Metadata that breaks a schema, naming the field
+#[derive(Clone, Debug, PartialEq)]
+pub enum SchemaError {
+    /// Not declared; `suggestion` is a declared field one or two edits away
+    UnknownField { field: String, suggestion: Option<String> },
+    MissingField(String),
+    WrongType { field: String, expected: FieldType, found: MetaValue },
+    /// A migration makes the field required without a default for entries that lack it
+    NoDefault(String),
+    /// The metadata of entry `id` breaks the schema being migrated to
+    Entry { id: u64, error: Box<SchemaError> },
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::UnknownField { field, suggestion: Some(s) } => write!(f, "Unknown field '{}' (did you mean '{}'?)", field, s),
+            SchemaError::UnknownField { field, suggestion: None } => write!(f, "Unknown field '{}'", field),
+            SchemaError::MissingField(field) => write!(f, "Missing required field '{}'", field),
+            SchemaError::WrongType { field, expected, found } => {
+                let found = serde_json::to_string(found).unwrap_or_default();
+                write!(f, "Field '{}' must be {}, got {}", field, expected, found)
+            }
+            SchemaError::NoDefault(field) => write!(f, "Field '{}' becomes required but has no default", field),
+            SchemaError::Entry { id, error } => write!(f, "Id {}: {}", id, error),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl From<SchemaError> for String {
+    fn from(e: SchemaError) -> Self { e.to_string() }
+}
+
+/// Declared fields, in declaration order
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct MetadataSchema {
+    fields: Vec<FieldSpec>,
+}
+
+impl MetadataSchema {
+    pub fn new() -> Self { Self::default() }
+    
+    /// Declare a field every entry must carry, replacing any of that name
+    pub fn required(self, name: &str, field_type: FieldType) -> Self { self.with_field(name, field_type, true) }
+    
+    /// Declare a field entries may carry, replacing any of that name
+    pub fn optional(self, name: &str, field_type: FieldType) -> Self { self.with_field(name, field_type, false) }
+    
+    fn with_field(mut self, name: &str, field_type: FieldType, required: bool) -> Self {
+        self.fields.retain(|f| f.name != name);
+        self.fields.push(FieldSpec { name: name.to_string(), field_type, required });
+        self
+    }
+    
+    pub fn fields(&self) -> &[FieldSpec] { &self.fields }
+    
+    pub fn field(&self, name: &str) -> Option<&FieldSpec> { self.fields.iter().find(|f| f.name == name) }
+    
+    /// Whether `metadata` has every required field, only declared ones, each of its type
+    pub fn validate(&self, metadata: &Metadata) -> Result<(), SchemaError> {
+        for (name, value) in metadata.iter() {
+            self.check_value(name, value)?;
+        }
+        match self.fields.iter().find(|f| f.required && metadata.get(&f.name).is_none()) {
+            Some(missing) => Err(SchemaError::MissingField(missing.name.clone())),
+            None => Ok(()),
+        }
+    }
+    
+    /// Whether `value` may be stored under `name`
+    pub fn check_value(&self, name: &str, value: &MetaValue) -> Result<(), SchemaError> {
+        let spec = self.declared(name)?;
+        if !spec.field_type.accepts(value) {
+            return Err(SchemaError::WrongType { field: name.to_string(), expected: spec.field_type.clone(), found: value.clone() });
+        }
+        Ok(())
+    }
+    
+    /// Whether a filter reading `fields` reads only declared ones
+    pub fn check_filter<'a>(&self, fields: impl IntoIterator<Item = &'a str>) -> Result<(), SchemaError> {
+        fields.into_iter().try_for_each(|name| self.declared(name).map(|_| ()))
+    }
+    
+    /// `text` as the value of `name`, for `KEY=VALUE` filters
+    pub fn parse_value(&self, name: &str, text: &str) -> Result<MetaValue, SchemaError> {
+        let spec = self.declared(name)?;
+        spec.field_type.parse(text).ok_or_else(|| SchemaError::WrongType {
+            field: name.to_string(),
+            expected: spec.field_type.clone(),
+            found: MetaValue::Str(text.to_string()),
+        })
+    }
+    
+    pub fn to_json(&self) -> String { serde_json::to_string(self).unwrap() }
+    
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("Invalid metadata schema: {}", e))
+    }
+    
+    fn declared(&self, name: &str) -> Result<&FieldSpec, SchemaError> {
+        self.field(name).ok_or_else(|| SchemaError::UnknownField {
+            field: name.to_string(),
+            suggestion: self.fields.iter()
+                .map(|f| (edit_distance(name, &f.name), &f.name))
+                .filter(|&(d, _)| d <= 2 && d < name.len())
+                .min()
+                .map(|(_, s)| s.clone()),
+        })
+    }
+}
+
+/// How entries move to a new schema in `CrystalIndex::migrate_schema`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SchemaMigration {
+    renames: Vec<(String, String)>,
+    defaults: Metadata,
+}
+
+impl SchemaMigration {
+    pub fn new() -> Self { Self::default() }
+    
+    /// Move the value of field `from` to `to`
+    pub fn with_rename(mut self, from: &str, to: &str) -> Self {
+        self.renames.push((from.to_string(), to.to_string()));
+        self
+    }
+    
+    /// Give entries without field `name` this value
+    pub fn with_default(mut self, name: &str, value: impl Into<MetaValue>) -> Self {
+        self.defaults = self.defaults.with(name, value);
+        self
+    }
+    
+    /// Errors unless every field `to` requires is either required by `from`,
+    /// renamed to, or given a default, and every default fits `to`
+    pub(crate) fn check(&self, from: Option<&MetadataSchema>, to: &MetadataSchema) -> Result<(), SchemaError> {
+        for (name, value) in self.defaults.iter() {
+            to.check_value(name, value)?;
+        }
+        for spec in to.fields().iter().filter(|f| f.required) {
+            let was_required = from.and_then(|s| s.field(&spec.name)).is_some_and(|f| f.required);
+            let renamed = self.renames.iter().any(|(_, target)| *target == spec.name);
+            if !was_required && !renamed && self.defaults.get(&spec.name).is_none() {
+                return Err(SchemaError::NoDefault(spec.name.clone()));
+            }
+        }
+        Ok(())
+    }
+    
+    /// `metadata` with renames and defaults applied
+    pub(crate) fn apply(&self, metadata: &Metadata) -> Metadata {
+        let mut migrated = metadata.clone();
+        for (from, to) in &self.renames {
+            if let Some(value) = migrated.remove(from) {
+                migrated.set(to, value);
+            }
+        }
+        for (name, value) in self.defaults.iter() {
+            if migrated.get(name).is_none() {
+                migrated.set(name, value.clone());
+            }
+        }
+        migrated
+    }
+}
+
+/// Levenshtein distance over chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + (ca != cb) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn papers() -> MetadataSchema {
+        MetadataSchema::new()
+            .required("lang", FieldType::Enum(vec!["en".to_string(), "de".to_string()]))
+            .required("year", FieldType::Int)
+            .optional("score", FieldType::Float)
+            .optional("draft", FieldType::Bool)
+    }
+    
+    #[test]
+    fn test_validate_names_the_offending_field() {
+        let schema = papers();
+        let ok = Metadata::new().with("lang", "de").with("year", 2021).with("draft", true);
+        assert_eq!(schema.validate(&ok), Ok(()));
+        
+        let cases = [
+            (Metadata::new().with("lang", "de"), "Missing required field 'year'"),
+            (ok.clone().with("year", 2021.5), "Field 'year' must be an int, got 2021.5"),
+            (ok.clone().with("lang", "fr"), "Field 'lang' must be one of en, de, got \"fr\""),
+            (ok.clone().with("draft", "no"), "Field 'draft' must be a bool, got \"no\""),
+            (ok.clone().with("langg", "en"), "Unknown field 'langg' (did you mean 'lang'?)"),
+            (ok.clone().with("venue", "ACL"), "Unknown field 'venue'"),
+        ];
+        for (metadata, message) in cases {
+            assert_eq!(schema.validate(&metadata).unwrap_err().to_string(), message);
+        }
+        
+        assert_eq!(schema.check_filter(["lang", "year"]), Ok(()));
+        assert!(matches!(schema.check_filter(["year", "langg"]),
+                         Err(SchemaError::UnknownField { field, suggestion: Some(s) }) if field == "langg" && s == "lang"));
+        assert_eq!(schema.parse_value("year", "2021"), Ok(MetaValue::Num(2021.0)));
+        assert!(schema.parse_value("draft", "maybe").is_err());
+        assert_eq!(MetadataSchema::from_json(&schema.to_json()), Ok(schema));
+    }
+    
+    #[test]
+    fn test_migration_needs_defaults_for_new_required_fields() {
+        let old = MetadataSchema::new().required("language", FieldType::String);
+        let new = old.clone().required("year", FieldType::Int).optional("draft", FieldType::Bool);
+        assert_eq!(SchemaMigration::new().check(Some(&old), &new), Err(SchemaError::NoDefault("year".to_string())));
+        assert!(SchemaMigration::new().with_default("year", "unknown").check(Some(&old), &new).is_err());
+        
+        let renamed = MetadataSchema::new().required("lang", FieldType::String).required("year", FieldType::Int);
+        let migration = SchemaMigration::new().with_rename("language", "lang").with_default("year", 2000);
+        migration.check(Some(&old), &renamed).unwrap();
+        let migrated = migration.apply(&Metadata::new().with("language", "en"));
+        assert_eq!(migrated, Metadata::new().with("lang", "en").with("year", 2000));
+        assert_eq!(migration.apply(&Metadata::new().with("language", "de").with("year", 1999)).get_num("year"), Some(1999.0));
+    }
+}
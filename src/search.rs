@@ -0,0 +1,554 @@
+//! Brute-force similarity search over embedding slices
+//!
+//! `semantic` is the one-call path for small scripts: embed the corpus as
+//! passages, embed the query, return the top-k corpus entries by cosine.
+//!
+//! `top_k_batch` is the evaluation path: corpus norms are computed once,
+//! queries are scored in blocks against blocks of corpus rows (so each row
+//! is loaded once per query block), and query blocks run in parallel.
+//!
+//! `SearchOptions` caps results at `k` and can drop hits under a
+//! `min_score` after ranking, so a search may return fewer than `k` hits or
+//! none. Its `Hit`s carry the raw cosine and, when `normalized`, the
+//! `cosine_score` that the threshold is compared with. With an
+//! `ExpansionConfig` the query is expanded by pseudo-relevance feedback:
+//! moved toward its first `m` hits and searched once more.
+//!
+//! `Metric` picks what "similar" means: cosine (the default), the raw dot
+//! product for unnormalized vectors, or Euclidean distance. Every score is
+//! higher-is-better, so Euclidean scores are negated distances: hits sort
+//! the same way under every metric, and a raw `min_score` of -0.5 keeps
+//! hits within distance 0.5. `Metric::score` maps each into [0, 1] for
+//! `normalized` searches.
+
+use std::collections::BinaryHeap;
+
+use rayon::prelude::*;
+
+use crate::jina_api::EmbedOptions;
+use crate::ops::rocchio;
+use crate::provider::{EmbedError, EmbeddingProvider};
+
+const QUERY_BLOCK: usize = 8;
+const ROW_BLOCK: usize = 256;
+
+/// Row-addressable vectors usable as a search corpus
+pub trait Corpus: Sync {
+    fn rows(&self) -> usize;
+    fn row(&self, i: usize) -> &[f32];
+}
+
+impl Corpus for [Vec<f32>] {
+    fn rows(&self) -> usize { self.len() }
+    fn row(&self, i: usize) -> &[f32] { &self[i] }
+}
+
+impl Corpus for Vec<Vec<f32>> {
+    fn rows(&self) -> usize { self.len() }
+    fn row(&self, i: usize) -> &[f32] { &self[i] }
+}
+
+/// Contiguous row-major vectors, e.g. a memory-mapped embedding file
+pub struct FlatCorpus<'a> {
+    data: &'a [f32],
+    dims: usize,
+}
+
+impl<'a> FlatCorpus<'a> {
+    pub fn new(data: &'a [f32], dims: usize) -> Result<Self, String> {
+        if dims == 0 || !data.len().is_multiple_of(dims) {
+            return Err(format!("{} floats is not a whole number of {}-dim rows", data.len(), dims));
+        }
+        Ok(Self { data, dims })
+    }
+}
+
+impl Corpus for FlatCorpus<'_> {
+    fn rows(&self) -> usize { self.data.len() / self.dims }
+    fn row(&self, i: usize) -> &[f32] { &self.data[i * self.dims..(i + 1) * self.dims] }
+}
+
+#[inline]
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[inline]
+pub fn norm(v: &[f32]) -> f32 {
+    dot(v, v).sqrt()
+}
+
+/// Cosine similarity; 0.0 if either vector is all-zero
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let denom = norm(a) * norm(b);
+    if denom > 0.0 { dot(a, b) / denom } else { 0.0 }
+}
+
+/// Scale to unit L2 norm in place; all-zero vectors are left as is
+pub fn normalize(v: &mut [f32]) {
+    let n = norm(v);
+    if n > 0.0 {
+        for x in v.iter_mut() { *x /= n; }
+    }
+}
+
+/// Cosine in [-1, 1] as a score in [0, 1]
+pub fn cosine_score(cosine: f32) -> f32 {
+    ((cosine + 1.0) / 2.0).clamp(0.0, 1.0)
+}
+
+/// Distance in [0, inf) as a score in (0, 1]: 1 at distance 0, 0.5 at distance 1
+pub fn distance_score(distance: f32) -> f32 {
+    1.0 / (1.0 + distance.max(0.0))
+}
+
+/// How vectors are compared; scores are higher-is-better under each
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    #[default]
+    Cosine,
+    /// Raw dot product; ranks as cosine on normalized vectors
+    Dot,
+    /// Scored as the negated L2 distance
+    Euclidean,
+}
+
+impl Metric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Metric::Cosine => "cosine",
+            Metric::Dot => "dot",
+            Metric::Euclidean => "euclidean",
+        }
+    }
+    
+    /// Score of `b` for query `a`
+    pub fn similarity(self, a: &[f32], b: &[f32]) -> f32 {
+        self.similarity_with_norms(a, b, norm(a), norm(b))
+    }
+    
+    /// `similarity` given the norms of `a` and `b`, which cosine alone reads
+    pub(crate) fn similarity_with_norms(self, a: &[f32], b: &[f32], norm_a: f32, norm_b: f32) -> f32 {
+        match self {
+            Metric::Cosine => {
+                let denom = norm_a * norm_b;
+                if denom > 0.0 { dot(a, b) / denom } else { 0.0 }
+            }
+            Metric::Dot => dot(a, b),
+            Metric::Euclidean => -a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt(),
+        }
+    }
+    
+    /// A similarity in [0, 1]: `cosine_score` of a cosine, `distance_score`
+    /// of a distance, and the logistic function of a dot product
+    pub fn score(self, similarity: f32) -> f32 {
+        match self {
+            Metric::Cosine => cosine_score(similarity),
+            Metric::Dot => 1.0 / (1.0 + (-similarity).exp()),
+            Metric::Euclidean => distance_score(-similarity),
+        }
+    }
+}
+
+impl std::str::FromStr for Metric {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, String> {
+        [Metric::Cosine, Metric::Dot, Metric::Euclidean].into_iter()
+            .find(|metric| metric.as_str() == s)
+            .ok_or_else(|| format!("Unknown metric {} (cosine, dot or euclidean)", s))
+    }
+}
+
+/// Matryoshka (MRL) truncation: keep the first `dims` components, renormalized
+pub fn truncate_mrl(v: &[f32], dims: usize) -> Vec<f32> {
+    let mut out = v[..dims.min(v.len())].to_vec();
+    normalize(&mut out);
+    out
+}
+
+/// How many hits a search keeps, and of what score
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SearchOptions {
+    /// At most this many hits
+    pub k: usize,
+    /// Hits scoring below this are dropped after ranking, leaving fewer than `k` or none
+    pub min_score: Option<f32>,
+    /// Score hits by `cosine_score` in [0, 1] rather than the raw cosine
+    pub normalized: bool,
+    /// Search again with the query expanded by its first hits; off by default
+    pub expansion: Option<ExpansionConfig>,
+}
+
+/// Pseudo-relevance feedback: the query moved toward its top `m` hits.
+///
+/// The expanded query is `ops::rocchio(query, top m, [], alpha, beta, 0)`;
+/// the second pass ranks everything again, first-pass hits included, and
+/// its hits carry cosines to the expanded query.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExpansionConfig {
+    /// First-pass hits taken as relevant
+    pub m: usize,
+    /// Weight of the original query
+    pub alpha: f32,
+    /// Weight of the mean of the feedback vectors
+    pub beta: f32,
+}
+
+impl Default for ExpansionConfig {
+    fn default() -> Self { Self { m: 3, alpha: 1.0, beta: 0.75 } }
+}
+
+impl SearchOptions {
+    pub fn top(k: usize) -> Self { Self { k, min_score: None, normalized: false, expansion: None } }
+    
+    pub fn with_min_score(mut self, min_score: f32) -> Self {
+        self.min_score = Some(min_score);
+        self
+    }
+    
+    pub fn with_normalized(mut self, normalized: bool) -> Self {
+        self.normalized = normalized;
+        self
+    }
+    
+    pub fn with_expansion(mut self, expansion: ExpansionConfig) -> Self {
+        self.expansion = Some(expansion);
+        self
+    }
+    
+    /// Best-first `(id, cosine)` pairs for `query` from `search(query, n)`,
+    /// expanded as configured: one extra `search` call at most
+    pub(crate) fn ranked<'a, T: Copy>(&self, query: &[f32], search: impl Fn(&[f32], usize) -> Vec<(T, f32)>,
+                                      vector: impl Fn(T) -> Option<&'a [f32]>) -> Vec<(T, f32)> {
+        let Some(expansion) = self.expansion.filter(|e| e.m > 0) else {
+            return search(query, self.k);
+        };
+        let first = search(query, expansion.m);
+        let feedback: Vec<&[f32]> = first.iter().filter_map(|&(id, _)| vector(id)).collect();
+        match rocchio(query, &feedback, &[], expansion.alpha, expansion.beta, 0.0) {
+            Ok(expanded) if !feedback.is_empty() => search(&expanded, self.k),
+            _ => search(query, self.k),
+        }
+    }
+    
+    /// Whether a hit of this cosine clears `min_score`
+    pub fn passes(&self, cosine: f32) -> bool { self.passes_with(cosine, Metric::Cosine) }
+    
+    /// Whether a hit of this `metric` similarity clears `min_score`
+    pub fn passes_with(&self, similarity: f32, metric: Metric) -> bool {
+        self.min_score.is_none_or(|min| self.score(similarity, metric) >= min)
+    }
+    
+    fn score(&self, similarity: f32, metric: Metric) -> f32 {
+        if self.normalized { metric.score(similarity) } else { similarity }
+    }
+    
+    /// Best-first `(id, cosine)` pairs as hits: at most `k`, all clearing `min_score`
+    pub fn apply<T>(&self, ranked: Vec<(T, f32)>) -> Vec<Hit<T>> { self.apply_with(ranked, Metric::Cosine) }
+    
+    /// `apply` to pairs scored by `metric`
+    pub fn apply_with<T>(&self, ranked: Vec<(T, f32)>, metric: Metric) -> Vec<Hit<T>> {
+        ranked.into_iter()
+            .take(self.k)
+            .take_while(|&(_, similarity)| self.passes_with(similarity, metric))
+            .map(|(id, cosine)| Hit { id, cosine, normalized: self.normalized.then(|| metric.score(cosine)) })
+            .collect()
+    }
+}
+
+/// One search result: an index id or a corpus position
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit<T = u64> {
+    pub id: T,
+    /// The cosine, or the similarity of the search's `Metric`
+    pub cosine: f32,
+    /// `Metric::score(cosine)` when the search was `normalized`
+    pub normalized: Option<f32>,
+}
+
+impl<T> Hit<T> {
+    /// The score `min_score` was compared with
+    pub fn score(&self) -> f32 { self.normalized.unwrap_or(self.cosine) }
+}
+
+/// Top-k corpus rows by cosine similarity, best first (ties by index)
+pub fn top_k(query: &[f32], corpus: &[Vec<f32>], k: usize) -> Vec<(usize, f32)> {
+    top_k_with(query, corpus, k, Metric::Cosine)
+}
+
+/// `top_k` by `metric`
+pub fn top_k_with(query: &[f32], corpus: &[Vec<f32>], k: usize, metric: Metric) -> Vec<(usize, f32)> {
+    let mut hits: Vec<(usize, f32)> = corpus.iter()
+        .enumerate()
+        .map(|(i, v)| (i, metric.similarity(query, v)))
+        .collect();
+    sort_hits(&mut hits);
+    hits.truncate(k);
+    hits
+}
+
+/// Top-k for many queries against one corpus; per-query hits in input order.
+///
+/// Results are identical to calling `top_k` once per query.
+pub fn top_k_batch<C: Corpus + ?Sized>(queries: &[Vec<f32>], corpus: &C, k: usize) -> Vec<Vec<(usize, f32)>> {
+    top_k_batch_with(queries, corpus, k, Metric::Cosine)
+}
+
+/// `top_k_batch` by `metric`, identical to `top_k_with` per query
+pub fn top_k_batch_with<C: Corpus + ?Sized>(queries: &[Vec<f32>], corpus: &C, k: usize, metric: Metric)
+                                            -> Vec<Vec<(usize, f32)>> {
+    let n = corpus.rows();
+    let corpus_norms: Vec<f32> = (0..n).into_par_iter().map(|i| norm(corpus.row(i))).collect();
+    
+    queries.par_chunks(QUERY_BLOCK)
+        .flat_map_iter(|block| {
+            let query_norms: Vec<f32> = block.iter().map(|q| norm(q)).collect();
+            // The best k so far per query, worst on top
+            let mut tops: Vec<BinaryHeap<Worst>> = block.iter().map(|_| BinaryHeap::with_capacity(k.min(n) + 1)).collect();
+            
+            for start in (0..n).step_by(ROW_BLOCK) {
+                let end = (start + ROW_BLOCK).min(n);
+                for (i, &row_norm) in corpus_norms.iter().enumerate().take(end).skip(start) {
+                    let row = corpus.row(i);
+                    for (q, query) in block.iter().enumerate() {
+                        let hit = Worst((i, metric.similarity_with_norms(query, row, query_norms[q], row_norm)));
+                        let top = &mut tops[q];
+                        if top.len() < k {
+                            top.push(hit);
+                        } else if top.peek().is_some_and(|worst| hit < *worst) {
+                            top.pop();
+                            top.push(hit);
+                        }
+                    }
+                }
+            }
+            
+            tops.into_iter().map(|top| {
+                let mut hits: Vec<(usize, f32)> = top.into_vec().into_iter().map(|w| w.0).collect();
+                sort_hits(&mut hits);
+                hits
+            })
+        })
+        .collect()
+}
+
+/// Embed query + corpus and return the top-k `(index, score, text)` matches
+pub fn semantic<'a, P: EmbeddingProvider + ?Sized>(provider: &P, query: &str, corpus: &[&'a str], k: usize)
+    -> Result<Vec<(usize, f32, &'a str)>, EmbedError>
+{
+    let corpus_embeddings = provider.embed_batch_with(corpus, &EmbedOptions::passage())?;
+    semantic_precomputed(provider, query, corpus, &corpus_embeddings, k)
+}
+
+/// `semantic` with corpus embeddings computed once up front
+pub fn semantic_precomputed<'a, P: EmbeddingProvider + ?Sized>(provider: &P, query: &str, corpus: &[&'a str],
+                                                                corpus_embeddings: &[Vec<f32>], k: usize)
+    -> Result<Vec<(usize, f32, &'a str)>, EmbedError>
+{
+    if corpus.len() != corpus_embeddings.len() {
+        return Err(EmbedError::InvalidInput(format!("Corpus has {} texts but {} embeddings",
+                                                    corpus.len(), corpus_embeddings.len())));
+    }
+    
+    let query_embedding = provider.embed_batch_with(&[query], &EmbedOptions::query())?
+        .pop()
+        .ok_or(EmbedError::Mismatch { expected: 1, got: 0 })?;
+    
+    Ok(top_k(&query_embedding, corpus_embeddings, k)
+        .into_iter()
+        .map(|(i, score)| (i, score, corpus[i]))
+        .collect())
+}
+
+/// `semantic` under `options`, expanded if it says so; hit ids are corpus positions
+pub fn semantic_with<P: EmbeddingProvider + ?Sized>(provider: &P, query: &str, corpus: &[&str], options: &SearchOptions)
+    -> Result<Vec<Hit<usize>>, EmbedError>
+{
+    let corpus_embeddings = provider.embed_batch_with(corpus, &EmbedOptions::passage())?;
+    let query_embedding = provider.embed_batch_with(&[query], &EmbedOptions::query())?
+        .pop()
+        .ok_or(EmbedError::Mismatch { expected: 1, got: 0 })?;
+    let ranked = options.ranked(&query_embedding, |q, n| top_k(q, &corpus_embeddings, n),
+                                |i| corpus_embeddings.get(i).map(Vec::as_slice));
+    Ok(options.apply(ranked))
+}
+
+fn hit_order(a: &(usize, f32), b: &(usize, f32)) -> std::cmp::Ordering {
+    score_order(a.1, b.1).then(a.0.cmp(&b.0))
+}
+
+/// Higher score first; NaN (degenerate vectors) ranks last and 0.0 and -0.0
+/// tie, a total order so sorts never panic
+pub(crate) fn score_order(a: f32, b: f32) -> std::cmp::Ordering {
+    let key = |score: f32| if score.is_nan() { f32::NEG_INFINITY } else { score };
+    key(b).partial_cmp(&key(a)).unwrap_or(std::cmp::Ordering::Equal)
+}
+
+fn sort_hits(hits: &mut [(usize, f32)]) {
+    hits.sort_by(hit_order);
+}
+
+/// A hit ordered so the heap's greatest is the worst by `hit_order`
+#[derive(PartialEq)]
+struct Worst((usize, f32));
+
+impl Eq for Worst {}
+
+impl PartialOrd for Worst {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for Worst {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering { hit_order(&self.0, &other.0) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    
+    #[test]
+    fn test_top_k_order() {
+        let corpus = vec![vec![0.0, 1.0], vec![1.0, 0.0], vec![1.0, 1.0], vec![-1.0, 0.0]];
+        let hits = top_k(&[1.0, 0.1], &corpus, 3);
+        assert_eq!(hits.iter().map(|h| h.0).collect::<Vec<_>>(), vec![1, 2, 0]);
+        assert!((cosine(&[1.0, 0.0], &[-1.0, 0.0]) + 1.0).abs() < 1e-6);
+        assert_eq!(cosine(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+    }
+    
+    #[test]
+    fn test_top_k_batch_matches_top_k() {
+        use rand::prelude::*;
+        let mut rng = StdRng::seed_from_u64(7);
+        let dims = 16;
+        let corpus: Vec<Vec<f32>> = (0..600)
+            .map(|_| (0..dims).map(|_| rng.gen_range(-1.0..1.0)).collect())
+            .collect();
+        let mut queries: Vec<Vec<f32>> = (0..21)
+            .map(|_| (0..dims).map(|_| rng.gen_range(-1.0..1.0)).collect())
+            .collect();
+        queries.push(vec![0.0; dims]);
+        
+        for k in [0, 1, 10, 1000] {
+            let batch = top_k_batch(&queries, &corpus, k);
+            assert_eq!(batch.len(), queries.len());
+            for (q, hits) in queries.iter().zip(&batch) {
+                assert_eq!(hits, &top_k(q, &corpus, k));
+            }
+        }
+        
+        // Flat (mmap-style) corpus gives the same answer
+        let flat: Vec<f32> = corpus.iter().flatten().copied().collect();
+        let flat_corpus = FlatCorpus::new(&flat, dims).unwrap();
+        assert_eq!(top_k_batch(&queries, &flat_corpus, 5), top_k_batch(&queries, &corpus, 5));
+        assert!(FlatCorpus::new(&flat[..dims + 1], dims).is_err());
+    }
+    
+    proptest::proptest! {
+        #![proptest_config(crate::proptest_config(256))]
+        
+        #[test]
+        fn prop_cosine_symmetric_and_bounded(
+            pair in (1usize..64).prop_flat_map(|d| (vec(-1e3f32..1e3, d), vec(-1e3f32..1e3, d))),
+        ) {
+            let (a, b) = pair;
+            let ab = cosine(&a, &b);
+            prop_assert_eq!(ab, cosine(&b, &a));
+            prop_assert!((-1.0 - 1e-5..=1.0 + 1e-5).contains(&ab), "cosine = {}", ab);
+        }
+        
+        #[test]
+        fn prop_mrl_truncation_unit_norm(v in vec(-1.0f32..1.0, 1..1024), keep in 1usize..1024) {
+            let mut v = v;
+            normalize(&mut v);
+            let t = truncate_mrl(&v, keep);
+            prop_assert_eq!(t.len(), keep.min(v.len()));
+            let n = norm(&t);
+            prop_assert!((n - 1.0).abs() < 1e-4 || t.iter().all(|&x| x == 0.0), "norm = {}", n);
+        }
+    }
+    
+    #[test]
+    fn test_semantic_offline() {
+        use crate::jina_api::JinaClient;
+        let client = JinaClient::new("test_key").with_cache();
+        let corpus = ["Ada loves Jan", "Jan builds systems", "the weather is mild"];
+        
+        let hits = semantic(&client, "Jan builds systems", &corpus, 2).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!((hits[0].0, hits[0].2), (1, "Jan builds systems"));
+        assert!((hits[0].1 - 1.0).abs() < 1e-5);
+        assert!(hits[0].1 >= hits[1].1);
+        
+        // Deterministic, and the corpus comes from the cache the second time
+        let before = client.stats();
+        assert_eq!(semantic(&client, "Jan builds systems", &corpus, 2).unwrap(), hits);
+        assert_eq!(client.stats().texts_sent, before.texts_sent);
+        
+        // Precomputed corpus path agrees
+        let embeddings = client.embed_batch_full(&corpus, &EmbedOptions::passage()).unwrap().embeddings;
+        let pre = semantic_precomputed(&client, "Jan builds systems", &corpus, &embeddings, 2).unwrap();
+        assert_eq!(pre, hits);
+        assert!(semantic_precomputed(&client, "x", &corpus, &embeddings[..1], 2).is_err());
+    }
+    
+    #[test]
+    fn test_min_score_thresholds() {
+        let ranked = vec![(0, 0.9), (1, 0.5), (2, -0.2)];
+        let ids = |hits: Vec<Hit<usize>>| hits.iter().map(|h| h.id).collect::<Vec<_>>();
+        assert_eq!(ids(SearchOptions::top(3).apply(ranked.clone())), [0, 1, 2]);
+        assert_eq!(ids(SearchOptions::top(3).with_min_score(0.4).apply(ranked.clone())), [0, 1]);
+        assert_eq!(ids(SearchOptions::top(3).with_min_score(0.3).with_normalized(true).apply(ranked.clone())), [0, 1, 2]);
+        assert!(SearchOptions::top(3).with_min_score(0.95).apply(ranked.clone()).is_empty());
+        assert!(SearchOptions::top(0).apply(ranked).is_empty());
+        
+        use crate::jina_api::JinaClient;
+        let client = JinaClient::new("");
+        let corpus = ["Ada loves Jan", "Jan builds systems", "the weather is mild"];
+        let hits = semantic_with(&client, "Jan builds systems", &corpus, &SearchOptions::top(3).with_min_score(0.99)).unwrap();
+        assert_eq!(ids(hits.clone()), [1]);
+        assert!((hits[0].cosine - 1.0).abs() < 1e-5);
+        let all = semantic_with(&client, "Jan builds systems", &corpus, &SearchOptions::top(3).with_normalized(true)).unwrap();
+        assert_eq!(all.len(), 3);
+        assert!(all.iter().all(|h| (0.0..=1.0).contains(&h.score())));
+    }
+    
+    #[test]
+    fn test_expansion_recovers_a_missed_document() {
+        use crate::index::CrystalIndex;
+        use crate::mock::MockProvider;
+        // "b" shares the query's topic only through "a"; "c" is closer to the bare query
+        let corpus = ["a", "b", "c"];
+        let provider = MockProvider::new(3)
+            .with_vector("q", vec![1.0, 0.0, 0.0])
+            .with_vector("a", vec![0.8, 0.6, 0.0])
+            .with_vector("b", vec![0.2, 0.98, 0.0])
+            .with_vector("c", vec![0.6, 0.0, 0.8]);
+        let ids = |hits: Vec<Hit<usize>>| hits.iter().map(|h| h.id).collect::<Vec<_>>();
+        let plain = SearchOptions::top(2);
+        let expanded = plain.with_expansion(ExpansionConfig { m: 1, alpha: 0.5, beta: 1.0 });
+        assert_eq!(ids(semantic_with(&provider, "q", &corpus, &plain).unwrap()), [0, 2]);
+        assert_eq!(ids(semantic_with(&provider, "q", &corpus, &expanded).unwrap()), [0, 1]);
+        
+        let mut index = CrystalIndex::new(3);
+        for (id, text) in corpus.iter().enumerate() {
+            index.add(id as u64, &provider.embed_batch(&[text]).unwrap()[0]).unwrap();
+        }
+        let hits = index.search_with(&[1.0, 0.0, 0.0], &expanded, None);
+        assert_eq!(hits.iter().map(|h| h.id).collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(index.search_with(&[1.0, 0.0, 0.0], &plain, None).iter().map(|h| h.id).collect::<Vec<_>>(), [0, 2]);
+        
+        // One extra search at most, none when off
+        let searches = std::cell::Cell::new(0);
+        let search = |q: &[f32], n: usize| { searches.set(searches.get() + 1); index.search(q, n) };
+        expanded.ranked(&[1.0, 0.0, 0.0], search, |id| index.get(id));
+        assert_eq!(searches.replace(0), 2);
+        plain.ranked(&[1.0, 0.0, 0.0], search, |id| index.get(id));
+        assert_eq!(searches.replace(0), 1);
+        let empty = CrystalIndex::new(3);
+        assert!(empty.search_with(&[1.0, 0.0, 0.0], &expanded, None).is_empty());
+    }
+}
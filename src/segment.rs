@@ -0,0 +1,142 @@
+//! Jina segmenter (`/v1/segment`)
+//!
+//! Splits text with the tokenizer the embedding models use, so chunk
+//! sizes are in real tokens. `JinaClient::chunk` uses it for
+//! `Chunking::Segmenter` when the client is online and falls back to the
+//! local `chunk_text` otherwise.
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::chunk::{chunk_text, locate_chunks, Chunk, Chunking};
+use crate::error::{truncate_for_display, JinaError, MAX_DISPLAY_CHARS};
+use crate::jina_api::JinaClient;
+
+/// Segmenter request options
+#[derive(Clone, Debug, PartialEq)]
+pub struct SegmentOptions {
+    /// Upper bound per chunk, in tokens online and characters offline
+    pub max_chunk_length: usize,
+    pub return_tokens: bool,
+    pub return_chunks: bool,
+}
+
+impl Default for SegmentOptions {
+    fn default() -> Self { Self { max_chunk_length: 1000, return_tokens: false, return_chunks: true } }
+}
+
+impl SegmentOptions {
+    /// Chunks of at most `n` tokens
+    pub fn chunks(n: usize) -> Self { Self { max_chunk_length: n, ..Self::default() } }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Segmentation {
+    /// Empty unless `return_chunks` was set
+    pub chunks: Vec<String>,
+    pub num_tokens: usize,
+}
+
+impl JinaClient {
+    /// Segment `text` server-side; offline, chunks come from `chunk_text` and the
+    /// token count from the client's `TokenCounter`
+    pub fn segment(&self, text: &str, opts: &SegmentOptions) -> Result<Segmentation, JinaError> {
+        if opts.max_chunk_length == 0 {
+            return Err(JinaError::InvalidInput("max_chunk_length must be at least 1".to_string()));
+        }
+        match self.post("/v1/segment", &segment_body(text, opts))? {
+            Some(body) => parse_segment(&body),
+            None => Ok(Segmentation {
+                chunks: if opts.return_chunks {
+                    chunk_text(text, opts.max_chunk_length).into_iter().map(|c| c.text).collect()
+                } else {
+                    Vec::new()
+                },
+                num_tokens: self.count_tokens(text),
+            }),
+        }
+    }
+    
+    /// Chunks of `text` with byte offsets, cut by `strategy`
+    pub fn chunk(&self, text: &str, max_chunk_length: usize, strategy: Chunking) -> Result<Vec<Chunk>, JinaError> {
+        match strategy {
+            Chunking::Segmenter if self.is_online() => {
+                let segmentation = self.segment(text, &SegmentOptions::chunks(max_chunk_length))?;
+                Ok(locate_chunks(text, segmentation.chunks))
+            }
+            _ => Ok(strategy.chunk_local(text, max_chunk_length)),
+        }
+    }
+}
+
+fn segment_body(text: &str, opts: &SegmentOptions) -> serde_json::Value {
+    json!({
+        "content": text,
+        "max_chunk_length": opts.max_chunk_length,
+        "return_tokens": opts.return_tokens,
+        "return_chunks": opts.return_chunks,
+    })
+}
+
+#[derive(Deserialize)]
+struct Response {
+    num_tokens: usize,
+    #[serde(default)]
+    chunks: Vec<String>,
+}
+
+fn parse_segment(body: &str) -> Result<Segmentation, JinaError> {
+    let response: Response = serde_json::from_str(body)
+        .map_err(|e| JinaError::Parse(format!("segment response: {}", truncate_for_display(&e.to_string(), MAX_DISPLAY_CHARS))))?;
+    Ok(Segmentation { chunks: response.chunks, num_tokens: response.num_tokens })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{HttpRequest, HttpResponse, RetryPolicy};
+    use std::sync::{Arc, Mutex};
+    
+    const FIXTURE: &str = include_str!("../fixtures/jina/segment.json");
+    const TEXT: &str = "Jina AI builds search foundation models. The segmenter splits text into chunks. It uses a real tokenizer.";
+    
+    #[test]
+    fn test_parse_segment_fixture() {
+        let segmentation = parse_segment(FIXTURE).unwrap();
+        assert_eq!(segmentation.num_tokens, 29);
+        assert_eq!(segmentation.chunks.len(), 3);
+        assert_eq!(segmentation.chunks[2], "It uses a real tokenizer.");
+        
+        assert_eq!(parse_segment(r#"{"num_tokens":5,"tokenizer":"cl100k_base"}"#).unwrap().chunks, Vec::<String>::new());
+        assert!(matches!(parse_segment("{}"), Err(JinaError::Parse(_))));
+    }
+    
+    #[test]
+    fn test_chunking_uses_segmenter_only_online() {
+        let seen: Arc<Mutex<Vec<HttpRequest>>> = Arc::default();
+        let log = seen.clone();
+        let online = JinaClient::new("jina_test")
+            .with_retry(RetryPolicy::none())
+            .with_transport(move |request: &HttpRequest| {
+                log.lock().unwrap().push(request.clone());
+                Ok(HttpResponse { status: 200, headers: Vec::new(), body: FIXTURE.to_string() })
+            });
+        
+        let chunks = online.chunk(TEXT, 16, Chunking::Segmenter).unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!((chunks[1].start, chunks[1].end), (41, 79));
+        assert_eq!(&TEXT[chunks[2].start..chunks[2].end], "It uses a real tokenizer.");
+        
+        let request = seen.lock().unwrap()[0].clone();
+        assert_eq!(request.url, "https://api.jina.ai/v1/segment");
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body, json!({"content": TEXT, "max_chunk_length": 16, "return_tokens": false, "return_chunks": true}));
+        
+        // Local strategy never calls out; offline clients fall back
+        online.chunk(TEXT, 40, Chunking::Local).unwrap();
+        assert_eq!(seen.lock().unwrap().len(), 1);
+        let offline = JinaClient::new("test_key").chunk(TEXT, 40, Chunking::Segmenter).unwrap();
+        assert_eq!(offline, chunk_text(TEXT, 40));
+    }
+}
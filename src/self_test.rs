@@ -0,0 +1,332 @@
+//! Startup connectivity self-test
+//!
+//! `JinaClient::self_test` answers "is embedding going to work?" in one
+//! call, checking in order:
+//!
+//! 1. `Dns`: the base URL's host resolves
+//! 2. `Connect`: the server answers a `GET` of the base URL, so TCP and,
+//!    for `https`, the TLS handshake work (any status counts)
+//! 3. `Auth`: the key is accepted
+//! 4. `Model`: the configured model exists
+//! 5. `Embed`: a one-word embedding comes back at the expected size
+//!
+//! The last three share one embeddings request; its status says which of
+//! them failed (401/403 the key, 404 or a 400/422 naming the model the
+//! model). The first failure ends the test, and no request is retried. The
+//! whole test stays within the client's timeout, `DEFAULT_SELF_TEST_TIMEOUT`
+//! without one: whatever stage runs past it fails with a timeout error.
+//!
+//! Offline and `with_backend` clients only run the `Embed` stage.
+
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::error::{truncate_for_display, JinaError, MAX_DISPLAY_CHARS};
+use crate::jina_api::{write_request_body, EmbedOptions, JinaClient};
+use crate::transport::{check_status, millis, HttpRequest};
+
+/// Budget of a self-test on clients without `with_timeout`
+pub const DEFAULT_SELF_TEST_TIMEOUT: Duration = Duration::from_secs(10);
+const SELF_TEST_TEXT: &str = "ok";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage { Dns, Connect, Auth, Model, Embed }
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct StageReport {
+    pub stage: Stage,
+    pub ms: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct StageFailure {
+    pub stage: Stage,
+    pub error: JinaError,
+    pub ms: f64,
+}
+
+/// Outcome of `self_test`, serializable for health endpoints
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SelfTestReport {
+    /// Stages that passed, in order
+    pub passed: Vec<StageReport>,
+    /// The stage that failed, if any; later stages were not attempted
+    pub failure: Option<StageFailure>,
+    /// Size of the test embedding, once `Embed` passed
+    pub dimensions: Option<usize>,
+    pub total_ms: f64,
+}
+
+impl SelfTestReport {
+    pub fn is_ok(&self) -> bool { self.failure.is_none() }
+}
+
+/// Stages run so far, against one deadline
+struct Run {
+    start: Instant,
+    deadline: Instant,
+    budget: Duration,
+    last: Instant,
+    report: SelfTestReport,
+}
+
+impl Run {
+    /// Time left, or the timeout error once there is none
+    fn remaining(&self) -> Result<Duration, JinaError> {
+        match self.deadline.checked_duration_since(Instant::now()) {
+            Some(left) if !left.is_zero() => Ok(left),
+            _ => Err(JinaError::Transport(format!("Self-test timed out after {} ms", self.budget.as_millis()))),
+        }
+    }
+    
+    /// Record `stage`, failing it when `result` is an error or came too late; false on failure
+    fn finish(&mut self, stage: Stage, result: Result<(), JinaError>) -> bool {
+        let now = Instant::now();
+        let ms = millis(now - self.last);
+        self.last = now;
+        match result.and_then(|()| self.remaining().map(|_| ())) {
+            Ok(()) => {
+                self.report.passed.push(StageReport { stage, ms });
+                true
+            }
+            Err(error) => {
+                self.report.failure = Some(StageFailure { stage, error, ms });
+                false
+            }
+        }
+    }
+}
+
+impl JinaClient {
+    /// Check resolution, connection, key, model and a test embedding, stopping at the first failure
+    pub fn self_test(&self) -> SelfTestReport {
+        let budget = self.settings().timeout.unwrap_or(DEFAULT_SELF_TEST_TIMEOUT);
+        let start = Instant::now();
+        let mut run = Run {
+            start,
+            deadline: start + budget,
+            budget,
+            last: start,
+            report: SelfTestReport { passed: Vec::new(), failure: None, dimensions: None, total_ms: 0.0 },
+        };
+        self.run_stages(&mut run);
+        run.report.total_ms = millis(run.start.elapsed());
+        run.report
+    }
+    
+    fn run_stages(&self, run: &mut Run) {
+        if self.backend.is_some() || !self.is_online() {
+            let expected = self.response_dims(&EmbedOptions::default());
+            let result = self.embed_batch_full(&[SELF_TEST_TEXT], &EmbedOptions::default())
+                .and_then(|response| expect_dims(response.embeddings.first().map_or(0, Vec::len),
+                                                 self.backend.as_ref().map_or(expected, |b| b.dimensions())));
+            if let Ok(dims) = result {
+                run.report.dimensions = Some(dims);
+            }
+            run.finish(Stage::Embed, result.map(|_| ()));
+            return;
+        }
+        
+        let dns = run.remaining().and_then(|left| resolve(&self.base_url, left));
+        if !run.finish(Stage::Dns, dns) {
+            return;
+        }
+        let connect = run.remaining()
+            .and_then(|left| self.send_once(HttpRequest::get(format!("{}/", self.base_url)), left))
+            .map(|_| ());
+        if !run.finish(Stage::Connect, connect) {
+            return;
+        }
+        
+        let mut body = String::new();
+        write_request_body(&mut body, &self.model, &[SELF_TEST_TEXT], &EmbedOptions::default());
+        let request = HttpRequest {
+            method: "POST",
+            url: self.embeddings_url(),
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: body.into_bytes(),
+            timeout: None,
+        };
+        let response = run.remaining()
+            .and_then(|left| self.send_once(request, left))
+            .and_then(check_status);
+        let failed_at = match &response {
+            Err(JinaError::Api { status: 401 | 403, .. }) => Stage::Auth,
+            Err(e) if self.names_model(e) => Stage::Model,
+            Err(JinaError::Api { .. }) | Ok(_) => Stage::Embed,
+            // No answer: nothing past the connection was checked
+            Err(_) => Stage::Auth,
+        };
+        for stage in [Stage::Auth, Stage::Model] {
+            if stage == failed_at || !run.finish(stage, Ok(())) {
+                break;
+            }
+        }
+        if run.report.failure.is_some() {
+            return;
+        }
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                run.finish(failed_at, Err(e));
+                return;
+            }
+        };
+        
+        #[derive(Deserialize)]
+        struct Item { embedding: Vec<f32> }
+        #[derive(Deserialize)]
+        struct Body { data: Vec<Item> }
+        let embed = serde_json::from_str::<Body>(&response.body)
+            .map_err(|e| JinaError::Parse(format!("Self-test response: {}", truncate_for_display(&e.to_string(), MAX_DISPLAY_CHARS))))
+            .and_then(|body| expect_dims(body.data.first().map_or(0, |item| item.embedding.len()),
+                                         self.response_dims(&EmbedOptions::default())));
+        if let Ok(dims) = embed {
+            run.report.dimensions = Some(dims);
+        }
+        run.finish(Stage::Embed, embed.map(|_| ()));
+    }
+    
+    /// 404, or a 400/422 whose message names the model
+    fn names_model(&self, error: &JinaError) -> bool {
+        match error {
+            JinaError::Api { status: 404, .. } => true,
+            JinaError::Api { status: 400 | 422, message } => {
+                message.contains(&self.model) || message.to_ascii_lowercase().contains("model")
+            }
+            _ => false,
+        }
+    }
+}
+
+fn expect_dims(got: usize, expected: usize) -> Result<usize, JinaError> {
+    if got == expected { Ok(got) } else { Err(JinaError::Mismatch { expected, got }) }
+}
+
+/// Resolve the host of `base_url` within `timeout`
+#[cfg(not(target_arch = "wasm32"))]
+fn resolve(base_url: &str, timeout: Duration) -> Result<(), JinaError> {
+    use std::net::ToSocketAddrs;
+    
+    let (scheme, rest) = base_url.split_once("://").unwrap_or(("https", base_url));
+    let authority = rest.split('/').next().unwrap_or_default();
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let has_port = authority.rsplit_once(':').is_some_and(|(_, port)| port.chars().all(|c| c.is_ascii_digit()));
+    let address = if has_port { authority.to_string() } else { format!("{}:{}", authority, if scheme == "http" { 80 } else { 443 }) };
+    
+    // Resolution cannot be cancelled; a thread lets the deadline win
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let lookup = address.clone();
+    std::thread::spawn(move || {
+        let _ = sender.send(lookup.to_socket_addrs().map(|mut addresses| addresses.next().is_some()));
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(Ok(true)) => Ok(()),
+        Ok(Ok(false)) => Err(JinaError::Connect(format!("{}: no address", address))),
+        Ok(Err(e)) => Err(JinaError::Connect(format!("{}: {}", address, e))),
+        Err(_) => Err(JinaError::Connect(format!("{}: resolution timed out", address))),
+    }
+}
+
+/// Browsers resolve inside `fetch`; the `Connect` stage covers it
+#[cfg(target_arch = "wasm32")]
+fn resolve(_base_url: &str, _timeout: Duration) -> Result<(), JinaError> { Ok(()) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    
+    use crate::transport::HttpResponse;
+    
+    /// What the emulated server answers a `GET`: a status, or no answer
+    type Get = Result<u16, JinaError>;
+    /// What it answers an embeddings request: status and body
+    type Post = Result<(u16, String), JinaError>;
+    
+    /// Client whose server answers `GET` with `get` and embeddings with `post`, logging methods
+    fn client(base_url: &str, get: Get, post: Post)
+              -> (JinaClient, Arc<Mutex<Vec<&'static str>>>) {
+        let log: Arc<Mutex<Vec<&'static str>>> = Arc::default();
+        let seen = log.clone();
+        let client = JinaClient::new("jina_test").with_base_url(base_url).with_transport(move |request: &HttpRequest| {
+            seen.lock().unwrap().push(request.method);
+            let (status, body) = match request.method {
+                "GET" => (get.clone()?, String::new()),
+                _ => post.clone()?,
+            };
+            Ok(HttpResponse { status, headers: Vec::new(), body })
+        });
+        (client, log)
+    }
+    
+    fn embedding(dims: usize) -> String {
+        serde_json::json!({ "data": [{ "embedding": vec![0.1f32; dims] }] }).to_string()
+    }
+    
+    fn stages(report: &SelfTestReport) -> Vec<Stage> { report.passed.iter().map(|s| s.stage).collect() }
+    
+    #[test]
+    fn test_each_stage_fails_alone() {
+        use Stage::*;
+        let local = "http://127.0.0.1:9";
+        let ok = Ok((200, embedding(1024)));
+        
+        let (healthy, log) = client(local, Ok(404), ok.clone());
+        let report = healthy.self_test();
+        assert!(report.is_ok(), "{:?}", report);
+        assert_eq!((stages(&report), report.dimensions), (vec![Dns, Connect, Auth, Model, Embed], Some(1024)));
+        assert_eq!(*log.lock().unwrap(), ["GET", "POST"]);
+        
+        // RFC 2606 reserves .invalid: it never resolves, and nothing is sent
+        let (unresolvable, log) = client("https://jina.invalid", Ok(200), ok.clone());
+        let report = unresolvable.self_test();
+        assert_eq!((report.failure.as_ref().map(|f| f.stage), stages(&report)), (Some(Dns), vec![]));
+        assert!(log.lock().unwrap().is_empty());
+        
+        let cases: [(Get, Post, Stage, usize); 5] = [
+            (Err(JinaError::Connect("refused".into())), ok.clone(), Connect, 1),
+            (Ok(200), Ok((401, r#"{"detail":"Invalid API key"}"#.into())), Auth, 2),
+            (Ok(200), Ok((422, r#"{"detail":"Model jina-embeddings-v3 not found"}"#.into())), Model, 3),
+            (Ok(200), Ok((500, "boom".into())), Embed, 4),
+            (Ok(200), Ok((200, embedding(768))), Embed, 4),
+        ];
+        for (get, post, stage, reached) in cases {
+            let (client, log) = client(local, get, post);
+            let report = client.self_test();
+            let failure = report.failure.clone().unwrap();
+            assert_eq!(failure.stage, stage, "{:?}", report);
+            assert_eq!(stages(&report), [Dns, Connect, Auth, Model, Embed][..reached]);
+            assert_eq!(log.lock().unwrap().len(), if stage == Connect { 1 } else { 2 });
+        }
+        let (wrong_size, _) = client(local, Ok(200), Ok((200, embedding(768))));
+        assert_eq!(wrong_size.self_test().failure.unwrap().error, JinaError::Mismatch { expected: 1024, got: 768 });
+        
+        // Offline clients only embed
+        let report = JinaClient::new("").self_test();
+        assert_eq!((stages(&report), report.dimensions), (vec![Embed], Some(1024)));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["passed"][0]["stage"], "embed");
+    }
+    
+    #[test]
+    fn test_stays_within_timeout() {
+        let (slow, log) = client("http://127.0.0.1:9", Ok(200), Ok((200, embedding(1024))));
+        let slow = slow.with_timeout(Duration::from_millis(50)).with_transport({
+            let log = log.clone();
+            move |request: &HttpRequest| {
+                log.lock().unwrap().push(request.method);
+                assert!(request.timeout.unwrap() <= Duration::from_millis(50));
+                std::thread::sleep(Duration::from_millis(80));
+                Ok(HttpResponse { status: 200, headers: Vec::new(), body: String::new() })
+            }
+        });
+        let report = slow.self_test();
+        let failure = report.failure.unwrap();
+        assert_eq!(failure.stage, Stage::Connect);
+        assert_eq!(failure.error, JinaError::Transport("Self-test timed out after 50 ms".to_string()));
+        assert_eq!(*log.lock().unwrap(), ["GET"]);
+    }
+}
@@ -0,0 +1,345 @@
+//! `CrystalIndex` split across shard files
+//!
+//! A `ShardedIndex` is a directory of independent `CrystalIndex` files and a
+//! small `manifest.json` listing them. Entries go to shard `shard_of(id, n)`
+//! by a hash of their id, so shards fill evenly and an id names its shard.
+//! Searches run on the shards in parallel (rayon) and merge their top-k
+//! hits, ranked as `CrystalIndex::search` ranks them: results equal those of
+//! one index holding every entry.
+//!
+//! `open` checks that each file the manifest lists is there but reads none
+//! of them. A shard is loaded the first time something needs it, so
+//! `search_shards` over some shards or `get` of one id leaves the rest on
+//! disk. Every shard must record the manifest's provenance and dims to load.
+//!
+//! `add_shard` grows the index offline: it loads every shard and moves the
+//! entries the new shard count routes elsewhere. `save` writes the shards
+//! changed since the last save, then the manifest.
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use rayon::prelude::*;
+
+use crate::index::{CrystalIndex, Quantization};
+use crate::io::atomic_write;
+use crate::metadata::Metadata;
+use crate::provenance::Provenance;
+use crate::search::{score_order, Metric};
+use crate::shared_index::empty_like;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// Search filter that shards can run on their own threads
+pub type SyncFilter<'a> = &'a (dyn Fn(&Metadata) -> bool + Sync);
+
+/// What `manifest.json` records
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ShardManifest {
+    pub version: u32,
+    pub dims: usize,
+    pub metric: Metric,
+    pub quantization: Quantization,
+    pub provenance: Option<Provenance>,
+    pub shards: Vec<ShardEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ShardEntry {
+    /// Relative to the manifest's directory
+    pub file: String,
+    /// Live entries at the last save
+    pub vectors: usize,
+}
+
+/// Shard of `id` among `shards`. Going from n to n + 1 shards moves only
+/// the ids the new shard takes, about 1 in n + 1 (jump consistent hashing).
+pub fn shard_of(id: u64, shards: usize) -> usize {
+    // splitmix64 first, so consecutive ids spread over every shard
+    let mut key = id.wrapping_add(0x9e3779b97f4a7c15);
+    key = (key ^ (key >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    key = (key ^ (key >> 27)).wrapping_mul(0x94d049bb133111eb);
+    key ^= key >> 31;
+    let (mut shard, mut next) = (-1i64, 0i64);
+    while next < shards.max(1) as i64 {
+        shard = next;
+        key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+        next = ((shard + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+    shard as usize
+}
+
+/// `CrystalIndex` entries partitioned across shard files
+pub struct ShardedIndex {
+    dir: PathBuf,
+    manifest: ShardManifest,
+    shards: Vec<OnceLock<CrystalIndex>>,
+    /// Shards changed since the last save
+    dirty: Vec<bool>,
+}
+
+impl ShardedIndex {
+    /// `shards` empty shards configured as `template` (dims, metric,
+    /// quantization, provenance, schema), to be saved under `dir`
+    pub fn new(dir: impl AsRef<Path>, template: &CrystalIndex, shards: usize) -> Self {
+        let shards = shards.max(1);
+        let manifest = ShardManifest {
+            version: MANIFEST_VERSION,
+            dims: template.dims(),
+            metric: template.metric(),
+            quantization: template.quantization(),
+            provenance: template.provenance().cloned(),
+            shards: (0..shards).map(|i| ShardEntry { file: shard_file(i), vectors: 0 }).collect(),
+        };
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            manifest,
+            shards: (0..shards).map(|_| OnceLock::from(empty_like(template))).collect(),
+            dirty: vec![true; shards],
+        }
+    }
+    
+    /// The index saved under `dir`; fails if its manifest lists a file that is not there
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, String> {
+        let dir = dir.as_ref().to_path_buf();
+        let path = dir.join(MANIFEST_FILE);
+        let text = std::fs::read_to_string(&path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        let manifest: ShardManifest = serde_json::from_str(&text).map_err(|e| format!("Bad manifest {}: {}", path.display(), e))?;
+        if manifest.version > MANIFEST_VERSION {
+            return Err(format!("Manifest version {} is newer than this build reads ({})", manifest.version, MANIFEST_VERSION));
+        }
+        if manifest.shards.is_empty() {
+            return Err(format!("Manifest {} lists no shards", path.display()));
+        }
+        for entry in &manifest.shards {
+            if !dir.join(&entry.file).is_file() {
+                return Err(format!("Shard file {} listed in {} is missing", entry.file, path.display()));
+            }
+        }
+        let shards = manifest.shards.len();
+        Ok(Self { dir, manifest, shards: (0..shards).map(|_| OnceLock::new()).collect(), dirty: vec![false; shards] })
+    }
+    
+    pub fn manifest(&self) -> &ShardManifest { &self.manifest }
+    
+    pub fn shard_count(&self) -> usize { self.shards.len() }
+    
+    /// Shards read or created so far
+    pub fn loaded_shards(&self) -> Vec<usize> {
+        (0..self.shards.len()).filter(|&i| self.shards[i].get().is_some()).collect()
+    }
+    
+    /// Live entries: counted in loaded shards, as last saved in the others
+    pub fn len(&self) -> usize {
+        self.shards.iter().zip(&self.manifest.shards).map(|(shard, entry)| shard.get().map_or(entry.vectors, CrystalIndex::len)).sum()
+    }
+    
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+    
+    /// Shard `i`, loaded first if need be
+    pub fn shard(&self, i: usize) -> Result<&CrystalIndex, String> {
+        let slot = self.shards.get(i).ok_or_else(|| format!("No shard {} of {}", i, self.shards.len()))?;
+        if let Some(shard) = slot.get() {
+            return Ok(shard);
+        }
+        let entry = &self.manifest.shards[i];
+        let path = self.dir.join(&entry.file);
+        let shard = CrystalIndex::load(path.to_str().ok_or("Shard path is not UTF-8")?)?;
+        if shard.dims() != self.manifest.dims {
+            return Err(format!("Shard {} has {} dims, the manifest {}", entry.file, shard.dims(), self.manifest.dims));
+        }
+        if shard.provenance() != self.manifest.provenance.as_ref() {
+            let describe = |p: Option<&Provenance>| p.map_or("no provenance".to_string(), |p| p.to_string());
+            return Err(format!("Shard {} records {}, the manifest {}", entry.file, describe(shard.provenance()),
+                               describe(self.manifest.provenance.as_ref())));
+        }
+        // Another thread may have loaded it meanwhile; either copy will do
+        let _ = slot.set(shard);
+        Ok(slot.get().unwrap())
+    }
+    
+    fn shard_mut(&mut self, i: usize) -> Result<&mut CrystalIndex, String> {
+        self.shard(i)?;
+        self.dirty[i] = true;
+        Ok(self.shards[i].get_mut().unwrap())
+    }
+    
+    /// Add to the shard of `id`; fails as `CrystalIndex::add_with_metadata` does
+    pub fn add(&mut self, id: u64, vector: &[f32], metadata: Metadata) -> Result<(), String> {
+        let i = shard_of(id, self.shards.len());
+        self.shard_mut(i)?.add_with_metadata(id, vector, metadata)
+    }
+    
+    pub fn remove(&mut self, id: u64) -> Result<bool, String> {
+        let i = shard_of(id, self.shards.len());
+        Ok(self.shard_mut(i)?.remove(id))
+    }
+    
+    /// Vector of `id`, loading only its shard
+    pub fn get(&self, id: u64) -> Result<Option<&[f32]>, String> {
+        Ok(self.shard(shard_of(id, self.shards.len()))?.get(id))
+    }
+    
+    pub fn search(&self, query: &[f32], k: usize) -> Result<Vec<(u64, f32)>, String> {
+        self.search_filtered(query, k, None)
+    }
+    
+    /// Top-k over every shard, loading those not yet loaded
+    pub fn search_filtered(&self, query: &[f32], k: usize, filter: Option<SyncFilter>) -> Result<Vec<(u64, f32)>, String> {
+        let all: Vec<usize> = (0..self.shards.len()).collect();
+        self.search_shards(&all, query, k, filter)
+    }
+    
+    /// Top-k over `shards` only, as when the caller knows where its
+    /// matches are; the other shards stay unloaded
+    pub fn search_shards(&self, shards: &[usize], query: &[f32], k: usize, filter: Option<SyncFilter>)
+                         -> Result<Vec<(u64, f32)>, String> {
+        let per_shard = shards.par_iter()
+            .map(|&i| Ok(self.shard(i)?.search_filtered(query, k, filter.map(|f| f as _))))
+            .collect::<Result<Vec<_>, String>>()?;
+        let mut hits: Vec<(u64, f32)> = per_shard.into_iter().flatten().collect();
+        // As `CrystalIndex` ranks: best score first, ties by lower id
+        hits.sort_by(|a, b| score_order(a.1, b.1).then(a.0.cmp(&b.0)));
+        hits.truncate(k);
+        Ok(hits)
+    }
+    
+    /// Add an empty shard and move into it the entries it now owns; returns
+    /// the number moved. Loads every shard.
+    pub fn add_shard(&mut self) -> Result<usize, String> {
+        let template = empty_like(self.shard(0)?);
+        let count = self.shards.len() + 1;
+        self.shards.push(OnceLock::from(template));
+        self.dirty.push(true);
+        self.manifest.shards.push(ShardEntry { file: shard_file(count - 1), vectors: 0 });
+        let mut moved = 0;
+        for i in 0..count - 1 {
+            let shard = self.shard_mut(i)?;
+            let leaving: Vec<u64> = shard.ids().filter(|&id| shard_of(id, count) != i).collect();
+            let entries: Vec<_> = leaving.iter()
+                .map(|&id| (id, shard.get(id).unwrap().to_vec(), shard.metadata(id).cloned().unwrap_or_default(), shard.sparse(id).cloned()))
+                .collect();
+            for &id in &leaving {
+                shard.remove(id);
+            }
+            shard.compact();
+            for (id, vector, metadata, sparse) in entries {
+                let target = self.shard_mut(shard_of(id, count))?;
+                match sparse {
+                    Some(sparse) => target.add_with_sparse(id, &vector, sparse, metadata)?,
+                    None => target.add_with_metadata(id, &vector, metadata)?,
+                }
+                moved += 1;
+            }
+        }
+        Ok(moved)
+    }
+    
+    /// Snapshot every shard changed since the last save, then the manifest,
+    /// each file replaced atomically
+    pub fn save(&mut self) -> Result<(), String> {
+        std::fs::create_dir_all(&self.dir).map_err(|e| format!("Cannot create {}: {}", self.dir.display(), e))?;
+        for i in 0..self.shards.len() {
+            if !self.dirty[i] {
+                continue;
+            }
+            let path = self.dir.join(&self.manifest.shards[i].file);
+            let shard = self.shards[i].get_mut().unwrap();
+            shard.save(path.to_str().ok_or("Shard path is not UTF-8")?)?;
+            self.manifest.shards[i].vectors = shard.len();
+            self.dirty[i] = false;
+        }
+        let json = serde_json::to_string_pretty(&self.manifest).map_err(|e| e.to_string())?;
+        let path = self.dir.join(MANIFEST_FILE);
+        atomic_write(&path, |file| std::io::Write::write_all(file, json.as_bytes()))
+            .map_err(|e| format!("Write failed for {}: {}", path.display(), e))
+    }
+}
+
+fn shard_file(i: usize) -> String { format!("shard-{:04}.idx", i) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::SampleRng;
+    
+    fn corpus(n: u64, dims: usize) -> Vec<(u64, Vec<f32>, Metadata)> {
+        let mut rng = SampleRng::new(11);
+        (0..n).map(|id| {
+            let v: Vec<f32> = (0..dims).map(|_| rng.unit() as f32 - 0.5).collect();
+            (id * 7 + 3, v, Metadata::new().with("group", (id % 5) as i64))
+        }).collect()
+    }
+    
+    #[test]
+    fn test_sharded_search_equals_one_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let provenance = Provenance::new("jina-embeddings-v3", 24);
+        let template = CrystalIndex::new(24).with_provenance(provenance);
+        let (mut single, mut sharded) = (empty_like(&template), ShardedIndex::new(dir.path(), &template, 4));
+        for (id, v, metadata) in corpus(600, 24) {
+            single.add_with_metadata(id, &v, metadata.clone()).unwrap();
+            sharded.add(id, &v, metadata).unwrap();
+        }
+        assert!((0..4).all(|i| sharded.shard(i).unwrap().len() > 100));
+        let queries: Vec<Vec<f32>> = corpus(20, 24).into_iter().map(|(_, v, _)| v.iter().map(|x| -x).collect()).collect();
+        let even = |m: &Metadata| m.get_num("group").unwrap() as i64 % 2 == 0;
+        let check = |sharded: &ShardedIndex, single: &CrystalIndex| {
+            for query in &queries {
+                for k in [1, 10, 200] {
+                    assert_eq!(sharded.search(query, k).unwrap(), single.search(query, k));
+                }
+                assert_eq!(sharded.search_filtered(query, 10, Some(&even)).unwrap(), single.search_filtered(query, 10, Some(&even)));
+            }
+        };
+        check(&sharded, &single);
+        
+        sharded.save().unwrap();
+        let mut opened = ShardedIndex::open(dir.path()).unwrap();
+        assert_eq!((opened.len(), opened.loaded_shards()), (600, vec![]));
+        check(&opened, &single);
+        
+        // A fifth shard takes its share, and every entry is where its id says
+        let moved = opened.add_shard().unwrap();
+        assert!(moved > 60 && moved < 200, "{}", moved);
+        assert!((0..5).all(|i| opened.shard(i).unwrap().ids().all(|id| shard_of(id, 5) == i)));
+        assert_eq!(opened.len(), 600);
+        check(&opened, &single);
+        opened.save().unwrap();
+        check(&ShardedIndex::open(dir.path()).unwrap(), &single);
+    }
+    
+    #[test]
+    fn test_partial_loads_and_broken_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = CrystalIndex::new(8).with_provenance(Provenance::new("jina-embeddings-v3", 8));
+        let mut sharded = ShardedIndex::new(dir.path(), &template, 3);
+        let mut single = empty_like(&template);
+        for (id, v, metadata) in corpus(90, 8) {
+            if shard_of(id, 3) == 1 {
+                single.add_with_metadata(id, &v, metadata.clone()).unwrap();
+            }
+            sharded.add(id, &v, metadata).unwrap();
+        }
+        sharded.save().unwrap();
+        
+        let opened = ShardedIndex::open(dir.path()).unwrap();
+        let group = |m: &Metadata| m.get_num("group") == Some(2.0);
+        let query = vec![0.3; 8];
+        assert_eq!(opened.search_shards(&[1], &query, 5, Some(&group)).unwrap(), single.search_filtered(&query, 5, Some(&group)));
+        assert_eq!(opened.loaded_shards(), [1]);
+        let id = single.ids().next().unwrap();
+        assert_eq!(opened.get(id).unwrap(), single.get(id));
+        assert_eq!(opened.loaded_shards(), [1]);
+        
+        // A shard of other provenance fails to load; a missing one fails the open
+        let mut other = CrystalIndex::new(8).with_provenance(Provenance::new("jina-embeddings-v2-base-en", 8));
+        other.save(dir.path().join("shard-0002.idx").to_str().unwrap()).unwrap();
+        let err = ShardedIndex::open(dir.path()).unwrap().search(&query, 5).unwrap_err();
+        assert!(err.contains("shard-0002.idx records") && err.contains("jina-embeddings-v2-base-en"), "{}", err);
+        std::fs::remove_file(dir.path().join("shard-0000.idx")).unwrap();
+        let err = ShardedIndex::open(dir.path()).err().unwrap();
+        assert!(err.contains("Shard file shard-0000.idx listed in") && err.contains("is missing"), "{}", err);
+    }
+}
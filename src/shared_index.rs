@@ -0,0 +1,305 @@
+//! Snapshot-isolated `CrystalIndex` for concurrent readers and writers
+//!
+//! Readers take an `IndexSnapshot` (an `Arc` cloned under a lock held only
+//! for the swap) and search it without blocking writers. Writers stage
+//! adds and removes in a `Batch`; `Batch::commit` applies the whole batch
+//! to a copy of the current delta and publishes a new snapshot in one swap,
+//! so a search sees all of a batch or none of it. Commits are serialized,
+//! each building on the last published snapshot, so none is lost.
+//!
+//! A snapshot is an immutable base index shared between snapshots plus a
+//! small delta: the entries added since the base and the base ids removed.
+//! Once the delta outgrows `with_merge_at`, the committing writer folds it
+//! into a new base; readers keep the old one until they drop it.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::index::{CrystalIndex, Filter};
+use crate::metadata::Metadata;
+use crate::search::score_order;
+use crate::sparse::SparseVector;
+
+/// Delta size (added rows, their tombstones and removed base ids) folded into the base
+pub const DEFAULT_MERGE_AT: usize = 4096;
+
+/// `CrystalIndex` searched through snapshots while batches are committed
+pub struct SharedIndex {
+    current: RwLock<Arc<IndexSnapshot>>,
+    /// Held for the whole of a commit
+    writer: Mutex<()>,
+    merge_at: usize,
+}
+
+/// What readers search: a base index and the committed delta over it
+#[derive(Clone)]
+pub struct IndexSnapshot {
+    base: Arc<CrystalIndex>,
+    added: CrystalIndex,
+    removed: HashSet<u64>,
+    version: u64,
+}
+
+enum Staged {
+    Add(u64, Vec<f32>, Metadata, Option<SparseVector>),
+    Remove(u64),
+}
+
+/// Changes published together by `commit`
+pub struct Batch<'a> {
+    index: &'a SharedIndex,
+    ops: Vec<Staged>,
+}
+
+impl SharedIndex {
+    pub fn new(index: CrystalIndex) -> Self {
+        let added = empty_like(&index);
+        let snapshot = IndexSnapshot { base: Arc::new(index), added, removed: HashSet::new(), version: 0 };
+        Self { current: RwLock::new(Arc::new(snapshot)), writer: Mutex::new(()), merge_at: DEFAULT_MERGE_AT }
+    }
+    
+    /// Fold the delta into a new base once it holds more than `merge_at` entries
+    pub fn with_merge_at(mut self, merge_at: usize) -> Self {
+        self.merge_at = merge_at;
+        self
+    }
+    
+    /// The latest committed state; unaffected by later commits
+    pub fn snapshot(&self) -> Arc<IndexSnapshot> {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+    
+    pub fn batch(&self) -> Batch<'_> {
+        Batch { index: self, ops: Vec::new() }
+    }
+    
+    fn commit(&self, ops: Vec<Staged>) -> Result<u64, String> {
+        let _writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let current = self.snapshot();
+        let mut next = IndexSnapshot { version: current.version + 1, ..(*current).clone() };
+        for op in ops {
+            match op {
+                Staged::Add(id, vector, metadata, sparse) => {
+                    if next.contains(id) {
+                        return Err(format!("Id {} already present", id));
+                    }
+                    match sparse {
+                        Some(sparse) => next.added.add_with_sparse(id, &vector, sparse, metadata)?,
+                        None => next.added.add_with_metadata(id, &vector, metadata)?,
+                    }
+                }
+                Staged::Remove(id) => {
+                    if !next.added.remove(id) && next.base.contains(id) {
+                        next.removed.insert(id);
+                    }
+                }
+            }
+        }
+        if next.added.len() + next.added.tombstones() + next.removed.len() > self.merge_at {
+            let base = next.to_index();
+            next = IndexSnapshot { added: empty_like(&base), base: Arc::new(base), removed: HashSet::new(), version: next.version };
+        }
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(next);
+        Ok(current.version + 1)
+    }
+}
+
+impl IndexSnapshot {
+    /// Commits published before this snapshot; 0 for the initial index
+    pub fn version(&self) -> u64 { self.version }
+    
+    pub fn len(&self) -> usize { self.base.len() - self.removed.len() + self.added.len() }
+    
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+    
+    pub fn contains(&self, id: u64) -> bool {
+        self.added.contains(id) || (self.base.contains(id) && !self.removed.contains(&id))
+    }
+    
+    pub fn get(&self, id: u64) -> Option<&[f32]> {
+        if self.removed.contains(&id) { return self.added.get(id); }
+        self.added.get(id).or_else(|| self.base.get(id))
+    }
+    
+    pub fn search(&self, query: &[f32], k: usize) -> Vec<(u64, f32)> {
+        self.search_filtered(query, k, None)
+    }
+    
+    /// `CrystalIndex::search_filtered` over base and delta, same order and tie-breaks
+    pub fn search_filtered(&self, query: &[f32], k: usize, filter: Option<Filter>) -> Vec<(u64, f32)> {
+        let mut results = self.base.search_where(query, k, |id, metadata| {
+            !self.removed.contains(&id) && filter.is_none_or(|f| f(metadata))
+        });
+        results.extend(self.added.search_filtered(query, k, filter));
+        results.sort_by(|a, b| score_order(a.1, b.1).then(a.0.cmp(&b.0)));
+        results.truncate(k);
+        results
+    }
+    
+    /// The snapshot as one compacted index, e.g. to `save`
+    pub fn to_index(&self) -> CrystalIndex {
+        let mut index = (*self.base).clone();
+        for &id in &self.removed {
+            index.remove(id);
+        }
+        for id in self.added.ids() {
+            let (vector, metadata) = (self.added.get(id).unwrap(), self.added.metadata(id).unwrap().clone());
+            let added = match self.added.sparse(id) {
+                Some(sparse) => index.add_with_sparse(id, vector, sparse.clone(), metadata),
+                None => index.add_with_metadata(id, vector, metadata),
+            };
+            added.expect("delta ids are not live in the base");
+        }
+        index.compact();
+        index
+    }
+}
+
+impl Batch<'_> {
+    pub fn add(&mut self, id: u64, vector: &[f32]) -> Result<(), String> {
+        self.stage(id, vector, Metadata::new(), None)
+    }
+    
+    pub fn add_with_metadata(&mut self, id: u64, vector: &[f32], metadata: Metadata) -> Result<(), String> {
+        self.stage(id, vector, metadata, None)
+    }
+    
+    pub fn add_with_sparse(&mut self, id: u64, vector: &[f32], sparse: SparseVector, metadata: Metadata) -> Result<(), String> {
+        self.stage(id, vector, metadata, Some(sparse))
+    }
+    
+    pub fn remove(&mut self, id: u64) {
+        self.ops.push(Staged::Remove(id));
+    }
+    
+    /// Staged changes
+    pub fn len(&self) -> usize { self.ops.len() }
+    
+    pub fn is_empty(&self) -> bool { self.ops.is_empty() }
+    
+    /// Publish every staged change at once; returns the new snapshot's version.
+    ///
+    /// A duplicate id fails the whole batch and nothing is published.
+    pub fn commit(self) -> Result<u64, String> {
+        self.index.commit(self.ops)
+    }
+    
+    fn stage(&mut self, id: u64, vector: &[f32], metadata: Metadata, sparse: Option<SparseVector>) -> Result<(), String> {
+        let snapshot = self.index.snapshot();
+        let dims = snapshot.base.dims();
+        if vector.len() != dims {
+            return Err(format!("Dimension mismatch: expected {}, got {}", dims, vector.len()));
+        }
+        if let Some(schema) = snapshot.base.schema() {
+            schema.validate(&metadata)?;
+        }
+        self.ops.push(Staged::Add(id, vector.to_vec(), metadata, sparse));
+        Ok(())
+    }
+}
+
+/// Empty index with the dims, quantization, provenance and schema of `index`
+pub(crate) fn empty_like(index: &CrystalIndex) -> CrystalIndex {
+    let mut empty = CrystalIndex::new(index.dims()).with_quantization(index.quantization()).with_metric(index.metric());
+    if let Some(provenance) = index.provenance() {
+        empty = empty.with_provenance(provenance.clone());
+    }
+    match index.schema() {
+        Some(schema) => empty.with_schema(schema.clone()),
+        None => empty,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    
+    #[test]
+    fn test_batches_publish_atomically() {
+        let mut base = CrystalIndex::new(2);
+        base.add(1, &[1.0, 0.0]).unwrap();
+        base.add(2, &[0.0, 1.0]).unwrap();
+        let shared = SharedIndex::new(base).with_merge_at(4);
+        let mut batch = shared.batch();
+        batch.add(3, &[0.8, 0.6]).unwrap();
+        batch.add(4, &[0.6, 0.8]).unwrap();
+        assert!(batch.add(5, &[1.0]).is_err());
+        
+        let before = shared.snapshot();
+        assert_eq!(batch.commit(), Ok(1));
+        assert_eq!((before.version(), before.len()), (0, 2));
+        let first = shared.snapshot();
+        assert_eq!((first.version(), first.len()), (1, 4));
+        
+        // A base id removed and re-added, over the same base
+        let mut batch = shared.batch();
+        batch.remove(1);
+        batch.add(1, &[-1.0, 0.0]).unwrap();
+        batch.commit().unwrap();
+        let second = shared.snapshot();
+        assert_eq!((second.removed.len(), second.added.len(), second.len()), (1, 3, 4));
+        assert_eq!((second.get(1), first.get(1)), (Some(&[-1.0, 0.0][..]), Some(&[1.0, 0.0][..])));
+        assert_eq!(second.search(&[1.0, 0.0], 4), second.to_index().search(&[1.0, 0.0], 4));
+        
+        // Past `merge_at` the delta folds into the base; results are unchanged
+        let mut batch = shared.batch();
+        batch.add(5, &[0.0, -1.0]).unwrap();
+        batch.commit().unwrap();
+        let merged = shared.snapshot();
+        assert_eq!((merged.added.len(), merged.removed.len(), merged.base.len()), (0, 0, 5));
+        assert_eq!(merged.search(&[1.0, 0.0], 3), second.search(&[1.0, 0.0], 3));
+        
+        // A duplicate fails the batch and publishes nothing
+        let mut batch = shared.batch();
+        batch.add(6, &[1.0, 1.0]).unwrap();
+        batch.add(2, &[1.0, 1.0]).unwrap();
+        assert!(batch.commit().unwrap_err().contains("Id 2"));
+        assert_eq!((shared.snapshot().version(), shared.snapshot().contains(6)), (3, false));
+    }
+    
+    #[test]
+    fn test_concurrent_readers_see_whole_batches() {
+        const BATCH: u64 = 8;
+        const BATCHES: u64 = 40;
+        let shared = Arc::new(SharedIndex::new(CrystalIndex::new(2)).with_merge_at(50));
+        let done = Arc::new(AtomicBool::new(false));
+        
+        let readers: Vec<_> = (0..3).map(|_| {
+            let (shared, done) = (shared.clone(), done.clone());
+            std::thread::spawn(move || {
+                let mut last = 0;
+                while !done.load(Ordering::Relaxed) {
+                    let snapshot = shared.snapshot();
+                    assert!(snapshot.version() >= last);
+                    last = snapshot.version();
+                    let hits = snapshot.search(&[1.0, 1.0], usize::MAX);
+                    assert_eq!(hits.len(), snapshot.len());
+                    assert_eq!(hits.len() as u64 % BATCH, 0, "torn batch at version {}", last);
+                }
+            })
+        }).collect();
+        
+        // Two writers, each committing whole batches of its own ids
+        let writers: Vec<_> = (0..2).map(|w| {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                for b in 0..BATCHES / 2 {
+                    let mut batch = shared.batch();
+                    for i in 0..BATCH {
+                        let id = (w * BATCHES / 2 + b) * BATCH + i;
+                        batch.add(id, &[1.0, id as f32]).unwrap();
+                    }
+                    batch.commit().unwrap();
+                }
+            })
+        }).collect();
+        writers.into_iter().for_each(|w| w.join().unwrap());
+        done.store(true, Ordering::Relaxed);
+        readers.into_iter().for_each(|r| r.join().unwrap());
+        
+        let last = shared.snapshot();
+        assert_eq!((last.version(), last.len() as u64), (BATCHES, BATCHES * BATCH));
+        assert!((0..BATCHES * BATCH).all(|id| last.contains(id)));
+    }
+}
@@ -0,0 +1,245 @@
+//! Sparse (lexical-weight) vectors for hybrid search
+//!
+//! A `SparseVector` holds the nonzero weights of a vocabulary-sized vector,
+//! indices ascending. Backends that have them return them beside dense
+//! vectors (TEI's `/embed_sparse`, embeddings requests with
+//! `"embedding_type":"sparse"`); offline, `PseudoEmbedder::embed_sparse`
+//! weights each distinct word.
+//!
+//! Hybrid search blends the two similarities with `hybrid_score`:
+//! `alpha` 1 is dense only, 0 sparse only. Keyword-heavy queries gain from
+//! the sparse side, which only matches shared terms.
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::error::{truncate_for_display, JinaError, MAX_DISPLAY_CHARS};
+use crate::jina_api::{write_typed_request_body, EmbedOptions, JinaClient};
+use crate::pseudo::PseudoEmbedder;
+use crate::transport::HttpRequest;
+
+/// Nonzero weights by vocabulary index, ascending and distinct
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "SparseWire")]
+pub struct SparseVector {
+    pub indices: Vec<u32>,
+    pub values: Vec<f32>,
+}
+
+/// Dense and sparse vector of one text
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct HybridEmbedding {
+    pub dense: Vec<f32>,
+    pub sparse: SparseVector,
+}
+
+impl SparseVector {
+    /// From `(index, weight)` pairs in any order: repeated indices are summed, zeros dropped
+    pub fn from_pairs(pairs: impl IntoIterator<Item = (u32, f32)>) -> Self {
+        let mut summed: BTreeMap<u32, f32> = BTreeMap::new();
+        for (index, value) in pairs {
+            *summed.entry(index).or_insert(0.0) += value;
+        }
+        summed.retain(|_, v| *v != 0.0);
+        Self { indices: summed.keys().copied().collect(), values: summed.into_values().collect() }
+    }
+    
+    /// Number of nonzero weights
+    pub fn len(&self) -> usize { self.indices.len() }
+    
+    pub fn is_empty(&self) -> bool { self.indices.is_empty() }
+    
+    pub fn norm(&self) -> f32 { self.values.iter().map(|v| v * v).sum::<f32>().sqrt() }
+    
+    /// Scale to unit norm; the zero vector stays zero
+    pub fn normalized(mut self) -> Self {
+        let norm = self.norm();
+        if norm > 0.0 {
+            self.values.iter_mut().for_each(|v| *v /= norm);
+        }
+        self
+    }
+    
+    pub(crate) fn to_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.len() as u32).to_le_bytes());
+        for (index, value) in self.indices.iter().zip(&self.values) {
+            out.extend_from_slice(&index.to_le_bytes());
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    
+    /// Decode what `to_bytes` wrote; `None` when truncated. Returns bytes used.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<(Self, usize)> {
+        let count = u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?) as usize;
+        let used = 4 + count.checked_mul(8)?;
+        let body = bytes.get(4..used)?;
+        let (indices, values) = body.chunks_exact(8)
+            .map(|pair| (u32::from_le_bytes(pair[..4].try_into().unwrap()), f32::from_le_bytes(pair[4..].try_into().unwrap())))
+            .unzip();
+        Some((Self { indices, values }, used))
+    }
+}
+
+/// The two shapes sparse vectors arrive in: columns, or TEI's `[{index, value}]`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SparseWire {
+    Columns { indices: Vec<u32>, values: Vec<f32> },
+    Pairs(Vec<SparsePair>),
+}
+
+#[derive(Deserialize)]
+struct SparsePair {
+    index: u32,
+    value: f32,
+}
+
+impl TryFrom<SparseWire> for SparseVector {
+    type Error = String;
+    
+    fn try_from(wire: SparseWire) -> Result<Self, String> {
+        match wire {
+            SparseWire::Columns { indices, values } if indices.len() != values.len() => {
+                Err(format!("{} sparse indices for {} values", indices.len(), values.len()))
+            }
+            SparseWire::Columns { indices, values } => Ok(Self::from_pairs(indices.into_iter().zip(values))),
+            SparseWire::Pairs(pairs) => Ok(Self::from_pairs(pairs.into_iter().map(|p| (p.index, p.value)))),
+        }
+    }
+}
+
+impl JinaClient {
+    /// Sparse vectors of `texts`, requested with `"embedding_type":"sparse"`.
+    ///
+    /// `with_backend` clients ask their backend, offline clients use
+    /// `PseudoEmbedder::embed_sparse`. Sparse vectors are not cached, and
+    /// late chunking has none.
+    pub fn embed_sparse_with(&self, texts: &[&str], options: &EmbedOptions) -> Result<Vec<SparseVector>, JinaError> {
+        if options.late_chunking {
+            return Err(JinaError::InvalidInput("Sparse embeddings don't support late chunking".to_string()));
+        }
+        let cleaned = options.prepare(texts, self.tokens.as_ref());
+        let texts: Vec<&str> = cleaned.as_ref().map_or_else(|| texts.to_vec(), |c| c.iter().map(String::as_str).collect());
+        if let Some(backend) = &self.backend {
+            return backend.embed_sparse(&texts);
+        }
+        if !self.is_online() {
+            let pseudo = PseudoEmbedder::new(options.dims());
+            return Ok(texts.iter().map(|t| pseudo.embed_sparse(t)).collect());
+        }
+        
+        let mut out = Vec::with_capacity(texts.len());
+        for chunk in texts.chunks(self.batch_limit().max(1)) {
+            let mut body = String::new();
+            write_typed_request_body(&mut body, &self.model, chunk, options, Some("sparse"));
+            let request = HttpRequest {
+                method: "POST",
+                url: self.embeddings_url(),
+                headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+                body: body.into_bytes(),
+                timeout: None,
+            };
+            let body = self.send(request, &self.settings().retry)?.unwrap_or_default();
+            out.extend(parse_sparse_response(&body, chunk.len())?);
+        }
+        Ok(out)
+    }
+    
+    /// Dense and sparse vector of each text: `embed_batch_full` and `embed_sparse_with`
+    pub fn embed_hybrid_with(&self, texts: &[&str], options: &EmbedOptions) -> Result<Vec<HybridEmbedding>, JinaError> {
+        let sparse = self.embed_sparse_with(texts, options)?;
+        let dense = self.embed_batch_full(texts, options)?.embeddings;
+        Ok(dense.into_iter().zip(sparse).map(|(dense, sparse)| HybridEmbedding { dense, sparse }).collect())
+    }
+}
+
+/// Dot product over the indices both vectors share
+pub fn sparse_dot(a: &SparseVector, b: &SparseVector) -> f32 {
+    let (mut i, mut j, mut sum) = (0, 0, 0.0);
+    while i < a.indices.len() && j < b.indices.len() {
+        match a.indices[i].cmp(&b.indices[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                sum += a.values[i] * b.values[j];
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    sum
+}
+
+/// Cosine of two sparse vectors; 0 when either is zero
+pub fn sparse_cosine(a: &SparseVector, b: &SparseVector) -> f32 {
+    let denom = a.norm() * b.norm();
+    if denom > 0.0 { sparse_dot(a, b) / denom } else { 0.0 }
+}
+
+/// `alpha * dense_sim + (1 - alpha) * sparse_sim`, `alpha` clamped to [0, 1]
+pub fn hybrid_score(dense_sim: f32, sparse_sim: f32, alpha: f32) -> f32 {
+    let alpha = alpha.clamp(0.0, 1.0);
+    alpha * dense_sim + (1.0 - alpha) * sparse_sim
+}
+
+/// Sparse vectors of an embeddings response, in input order: `data[i].embedding`
+/// holds one in either wire shape
+pub(crate) fn parse_sparse_response(body: &str, expected: usize) -> Result<Vec<SparseVector>, JinaError> {
+    #[derive(Deserialize)]
+    struct Item {
+        index: Option<usize>,
+        embedding: SparseVector,
+    }
+    #[derive(Deserialize)]
+    struct Body { data: Vec<Item> }
+    let mut data = serde_json::from_str::<Body>(body)
+        .map_err(|e| JinaError::Parse(format!("Sparse embeddings response: {}", truncate_for_display(&e.to_string(), MAX_DISPLAY_CHARS))))?
+        .data;
+    if data.len() != expected {
+        return Err(JinaError::Mismatch { expected, got: data.len() });
+    }
+    data.sort_by_key(|item| item.index);
+    Ok(data.into_iter().map(|item| item.embedding).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_scorers() {
+        let a = SparseVector::from_pairs([(7, 2.0), (1, 1.0), (7, 1.0), (4, 0.0)]);
+        assert_eq!(a, SparseVector { indices: vec![1, 7], values: vec![1.0, 3.0] });
+        let b = SparseVector::from_pairs([(7, 0.5), (9, 4.0), (2, 1.0)]);
+        assert_eq!(sparse_dot(&a, &b), 1.5);
+        assert_eq!(sparse_dot(&a, &SparseVector::default()), 0.0);
+        assert!((sparse_cosine(&a, &a) - 1.0).abs() < 1e-6);
+        assert_eq!(sparse_cosine(&a, &SparseVector::default()), 0.0);
+        assert!((a.clone().normalized().norm() - 1.0).abs() < 1e-6);
+        
+        assert_eq!(hybrid_score(0.8, 0.2, 1.0), 0.8);
+        assert_eq!(hybrid_score(0.8, 0.2, 0.0), 0.2);
+        assert!((hybrid_score(0.8, 0.2, 0.25) - 0.35).abs() < 1e-6);
+        assert_eq!(hybrid_score(0.8, 0.2, 7.0), 0.8);
+        
+        let mut bytes = Vec::new();
+        a.to_bytes(&mut bytes);
+        assert_eq!(SparseVector::from_bytes(&bytes), Some((a, 20)));
+        assert_eq!(SparseVector::from_bytes(&bytes[..19]), None);
+    }
+    
+    #[test]
+    fn test_parse_sparse_fixture() {
+        let parsed = parse_sparse_response(include_str!("../fixtures/jina/sparse.json"), 2).unwrap();
+        // Out-of-order items are put back in input order; both wire shapes load
+        assert_eq!(parsed, [
+            SparseVector { indices: vec![3, 1017, 20511], values: vec![0.5, 1.25, 0.75] },
+            SparseVector { indices: vec![3, 12], values: vec![0.25, 2.0] },
+        ]);
+        assert!(matches!(parse_sparse_response(include_str!("../fixtures/jina/sparse.json"), 3),
+                         Err(JinaError::Mismatch { expected: 3, got: 2 })));
+        let ragged = r#"{"data":[{"embedding":{"indices":[1,2],"values":[1.0]}}]}"#;
+        assert!(matches!(parse_sparse_response(ragged, 1), Err(JinaError::Parse(_))));
+    }
+}
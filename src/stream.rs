@@ -0,0 +1,161 @@
+//! Pull-based embedding of large text iterators
+//!
+//! `JinaClient::embed_stream` reads texts from an iterator only as the
+//! consumer pulls vectors, a batch at a time, so a file reader feeding it is
+//! never read further ahead than one batch. Each batch goes through
+//! `embed_batch_full`, with its cache, retry policy and backend, and its
+//! vectors come out tagged with the index of their text in the input.
+//!
+//! A failed batch yields its error and ends the stream; texts after it are
+//! not read. `min_interval` spaces requests out for quotas measured in
+//! requests per second (ignored on wasm32, which has no blocking sleep).
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::error::JinaError;
+use crate::jina_api::{EmbedOptions, JinaClient};
+
+/// How `embed_stream` batches and paces its requests
+#[derive(Clone, Debug)]
+pub struct StreamConfig {
+    /// Texts per request
+    pub batch_size: usize,
+    /// Cap on texts read from the iterator but not yet yielded, embedded or not
+    pub max_pending: usize,
+    /// Least time between the starts of two requests
+    pub min_interval: Option<Duration>,
+    pub options: EmbedOptions,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self { Self { batch_size: 64, max_pending: 256, min_interval: None, options: EmbedOptions::default() } }
+}
+
+/// Iterator returned by `JinaClient::embed_stream`
+pub struct EmbedStream<'a, I> {
+    client: &'a JinaClient,
+    texts: I,
+    config: StreamConfig,
+    ready: VecDeque<(usize, Vec<f32>)>,
+    /// Index of the next text read
+    next_index: usize,
+    done: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    last_request: Option<std::time::Instant>,
+}
+
+impl JinaClient {
+    /// Embed `texts` lazily, yielding `(input index, vector)` in input order.
+    ///
+    /// Nothing is read or sent until the first `next`.
+    pub fn embed_stream<I: Iterator<Item = String>>(&self, texts: I, config: StreamConfig) -> EmbedStream<'_, I> {
+        EmbedStream {
+            client: self,
+            texts,
+            config,
+            ready: VecDeque::new(),
+            next_index: 0,
+            done: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            last_request: None,
+        }
+    }
+}
+
+impl<I: Iterator<Item = String>> EmbedStream<'_, I> {
+    /// Read and embed the next batch into `ready`
+    fn fill(&mut self) -> Result<(), JinaError> {
+        let size = self.config.batch_size.min(self.config.max_pending).max(1);
+        let batch: Vec<String> = self.texts.by_ref().take(size).collect();
+        if batch.is_empty() {
+            self.done = true;
+            return Ok(());
+        }
+        let first = self.next_index;
+        self.next_index += batch.len();
+        self.pace();
+        
+        let refs: Vec<&str> = batch.iter().map(String::as_str).collect();
+        let embeddings = self.client.embed_batch_full(&refs, &self.config.options)?.embeddings;
+        if embeddings.len() != batch.len() {
+            return Err(JinaError::Mismatch { expected: batch.len(), got: embeddings.len() });
+        }
+        self.ready.extend((first..).zip(embeddings));
+        Ok(())
+    }
+    
+    #[cfg(not(target_arch = "wasm32"))]
+    fn pace(&mut self) {
+        let Some(interval) = self.config.min_interval else { return };
+        if let Some(last) = self.last_request {
+            std::thread::sleep(interval.saturating_sub(last.elapsed()));
+        }
+        self.last_request = Some(std::time::Instant::now());
+    }
+    
+    #[cfg(target_arch = "wasm32")]
+    fn pace(&mut self) {}
+}
+
+impl<I: Iterator<Item = String>> Iterator for EmbedStream<'_, I> {
+    type Item = Result<(usize, Vec<f32>), JinaError>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.ready.is_empty() && !self.done {
+            if let Err(e) = self.fill() {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+        self.ready.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockProvider;
+    use std::cell::Cell;
+    use std::sync::Arc;
+    
+    #[test]
+    fn test_stream_is_lazy_bounded_and_ordered() {
+        let mock = Arc::new(MockProvider::new(2).with_default(vec![1.0, 0.0]).with_vector("t7", vec![0.0, 1.0]));
+        let client = JinaClient::new("test_key").with_backend(mock.clone());
+        let read = Cell::new(0);
+        let texts = (0..10).map(|i| {
+            read.set(read.get() + 1);
+            format!("t{}", i)
+        });
+        let config = StreamConfig { batch_size: 4, max_pending: 3, ..StreamConfig::default() };
+        let stream = client.embed_stream(texts, config);
+        assert_eq!((read.get(), mock.call_count()), (0, 0));
+        
+        let mut yielded = Vec::new();
+        for item in stream {
+            yielded.push(item.unwrap());
+            assert!(read.get() - yielded.len() <= 3, "{} read, {} yielded", read.get(), yielded.len());
+        }
+        assert_eq!(yielded.iter().map(|y| y.0).collect::<Vec<_>>(), (0..10).collect::<Vec<_>>());
+        assert_eq!(yielded[7].1, [0.0, 1.0]);
+        assert_eq!(mock.calls().iter().map(Vec::len).collect::<Vec<_>>(), [3, 3, 3, 1]);
+        assert_eq!(mock.calls()[1], ["t3", "t4", "t5"]);
+    }
+    
+    #[test]
+    fn test_error_ends_stream_mid_way() {
+        let error = JinaError::Api { status: 400, message: "bad batch".to_string() };
+        let mock = Arc::new(MockProvider::new(1).with_default(vec![1.0]).fail_on_call(2, error.clone()));
+        let client = JinaClient::new("test_key").with_backend(mock.clone());
+        let config = StreamConfig { batch_size: 2, min_interval: Some(Duration::from_millis(15)), ..StreamConfig::default() };
+        let start = std::time::Instant::now();
+        let items: Vec<_> = client.embed_stream((0..9).map(|i| format!("t{}", i)), config).collect();
+        assert!(start.elapsed() >= Duration::from_millis(15));
+        
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[1].as_ref().unwrap().0, 1);
+        assert_eq!(items[2], Err(error));
+        assert_eq!(mock.call_count(), 2);
+    }
+}
@@ -0,0 +1,241 @@
+//! Hugging Face Text Embeddings Inference (TEI) backend
+//!
+//! POSTs `{inputs, truncate}` to `<base_url>/embed`, which answers with a
+//! bare array of vectors. Batches start at TEI's default client batch limit;
+//! a 413 from a server configured lower halves the batch and retries, and
+//! the size that worked is kept for later calls. `embed_sparse` posts to
+//! `<base_url>/embed_sparse` the same way.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use serde_json::json;
+
+use crate::error::{truncate_for_display, JinaError, MAX_DISPLAY_CHARS};
+use crate::provider::{EmbedError, EmbeddingProvider, LearnedDims};
+use crate::search::normalize;
+use crate::sparse::SparseVector;
+use crate::transport::{self, check_status, send_with_retry, HttpRequest, RetryPolicy, Transport};
+
+const MAX_BATCH_SIZE: usize = 32;  // TEI's default --max-client-batch-size
+
+pub struct TeiClient {
+    base_url: String,
+    api_key: Option<String>,
+    truncate: bool,
+    normalize: bool,
+    batch_size: AtomicUsize,
+    transport: Arc<dyn Transport>,
+    retry: RetryPolicy,
+    dims: LearnedDims,
+}
+
+impl TeiClient {
+    /// Client for `<base_url>/embed`, e.g. `http://gpu-box:8080`
+    pub fn new(base_url: &str) -> Self {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        Self {
+            transport: transport::for_url(&base_url),
+            base_url,
+            api_key: None,
+            truncate: true,
+            normalize: false,
+            batch_size: AtomicUsize::new(MAX_BATCH_SIZE),
+            retry: RetryPolicy::default(),
+            dims: LearnedDims::default(),
+        }
+    }
+    
+    /// Sent as `Authorization: Bearer <token>` (Inference Endpoints, auth proxies)
+    pub fn with_api_key(mut self, token: &str) -> Self {
+        crate::error::register_secret(token);
+        self.api_key = Some(token.to_string());
+        self
+    }
+    
+    /// Let the server cut inputs at the model's max length instead of rejecting them (default on)
+    pub fn with_truncate(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
+    }
+    
+    /// L2-normalize vectors client-side, for servers started without normalization
+    pub fn with_normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+    
+    /// Starting batch size; shrinks on its own when the server answers 413
+    pub fn with_max_batch_size(self, n: usize) -> Self {
+        self.batch_size.store(n.max(1), Ordering::Relaxed);
+        self
+    }
+    
+    pub fn with_transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Arc::new(transport);
+        self
+    }
+    
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+    
+    /// Current batch size, lowered by any 413 seen so far
+    pub fn batch_size(&self) -> usize { self.batch_size.load(Ordering::Relaxed) }
+    
+    fn embed_chunk(&self, texts: &[&str], out: &mut Vec<Vec<f32>>) -> Result<(), JinaError> {
+        self.post_chunk("embed", texts, &|body, n| {
+            let mut embeddings = parse_response(body, n)?;
+            self.dims.check(&embeddings)?;
+            if self.normalize {
+                embeddings.iter_mut().for_each(|v| normalize(v));
+            }
+            Ok(embeddings)
+        }, out)
+    }
+    
+    /// POST `texts` to `<base_url>/<endpoint>` and append what `parse` makes
+    /// of the answer; a 413 halves the batch and retries
+    fn post_chunk<T>(&self, endpoint: &str, texts: &[&str], parse: &ParseFn<'_, T>,
+                     out: &mut Vec<T>) -> Result<(), JinaError> {
+        let body = json!({ "inputs": texts, "truncate": self.truncate });
+        let request = HttpRequest::post_json(format!("{}/{}", self.base_url, endpoint), &body)
+            .bearer(self.api_key.as_deref());
+        
+        let response = send_with_retry(self.transport.as_ref(), &request, &self.retry)?;
+        if response.status == 413 && texts.len() > 1 {
+            let half = texts.len().div_ceil(2);
+            self.batch_size.fetch_min(half, Ordering::Relaxed);
+            self.post_chunk(endpoint, &texts[..half], parse, out)?;
+            return self.post_chunk(endpoint, &texts[half..], parse, out);
+        }
+        out.extend(parse(&check_status(response)?.body, texts.len())?);
+        Ok(())
+    }
+    
+    /// Batches of `texts` at the current batch size through `post`
+    fn batched<T>(&self, texts: &[&str], post: impl Fn(&[&str], &mut Vec<T>) -> Result<(), JinaError>) -> Result<Vec<T>, JinaError> {
+        let mut out = Vec::with_capacity(texts.len());
+        let mut rest = texts;
+        while !rest.is_empty() {
+            let (chunk, tail) = rest.split_at(self.batch_size().min(rest.len()));
+            post(chunk, &mut out)?;
+            rest = tail;
+        }
+        Ok(out)
+    }
+}
+
+/// Response body and expected count to parsed items
+type ParseFn<'a, T> = dyn Fn(&str, usize) -> Result<Vec<T>, JinaError> + 'a;
+
+impl EmbeddingProvider for TeiClient {
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        self.batched(texts, |chunk, out| self.embed_chunk(chunk, out))
+    }
+    
+    /// Size seen in the first response (0 before any)
+    fn dimensions(&self) -> usize { self.dims.get() }
+    
+    /// `<base_url>/embed_sparse`, served by SPLADE-style models
+    fn embed_sparse(&self, texts: &[&str]) -> Result<Vec<SparseVector>, EmbedError> {
+        self.batched(texts, |chunk, out| self.post_chunk("embed_sparse", chunk, &parse_sparse, out))
+    }
+}
+
+fn parse_response(body: &str, expected: usize) -> Result<Vec<Vec<f32>>, JinaError> {
+    let embeddings: Vec<Vec<f32>> = serde_json::from_str(body)
+        .map_err(|e| JinaError::Parse(format!("TEI response: {}", truncate_for_display(&e.to_string(), MAX_DISPLAY_CHARS))))?;
+    if embeddings.len() != expected {
+        return Err(JinaError::Mismatch { expected, got: embeddings.len() });
+    }
+    Ok(embeddings)
+}
+
+/// `/embed_sparse` answers one `[{index, value}]` list per input
+fn parse_sparse(body: &str, expected: usize) -> Result<Vec<SparseVector>, JinaError> {
+    let vectors: Vec<SparseVector> = serde_json::from_str(body)
+        .map_err(|e| JinaError::Parse(format!("TEI sparse response: {}", truncate_for_display(&e.to_string(), MAX_DISPLAY_CHARS))))?;
+    if vectors.len() != expected {
+        return Err(JinaError::Mismatch { expected, got: vectors.len() });
+    }
+    Ok(vectors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::HttpResponse;
+    use std::sync::Mutex;
+    
+    const FIXTURE: &str = include_str!("../fixtures/tei/embed.json");
+    const FIXTURE_413: &str = include_str!("../fixtures/tei/error_413.json");
+    
+    /// Answers 413 above two inputs, else the two-vector fixture; logs batch sizes
+    fn tei_limited_to_two(log: Arc<Mutex<Vec<usize>>>) -> impl Transport {
+        move |request: &HttpRequest| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            let n = body["inputs"].as_array().unwrap().len();
+            log.lock().unwrap().push(n);
+            let (status, body) = if n > 2 { (413, FIXTURE_413) } else { (200, FIXTURE) };
+            Ok(HttpResponse { status, headers: Vec::new(), body: body.to_string() })
+        }
+    }
+    
+    #[test]
+    fn test_parse_and_normalize() {
+        let seen: Arc<Mutex<Vec<HttpRequest>>> = Arc::default();
+        let log = seen.clone();
+        let client = TeiClient::new("http://gpu-box:8080/")
+            .with_api_key("hf-test")
+            .with_transport(move |request: &HttpRequest| {
+                log.lock().unwrap().push(request.clone());
+                Ok(HttpResponse { status: 200, headers: Vec::new(), body: FIXTURE.to_string() })
+            });
+        assert_eq!(client.embed_batch(&["a", "b"]).unwrap(), vec![vec![3.0, 0.0, 4.0], vec![0.0, 0.6, 0.8]]);
+        assert_eq!(client.dimensions(), 3);
+        
+        let request = seen.lock().unwrap()[0].clone();
+        assert_eq!(request.url, "http://gpu-box:8080/embed");
+        assert!(request.headers.contains(&("Authorization".to_string(), "Bearer hf-test".to_string())));
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body, json!({"inputs": ["a", "b"], "truncate": true}));
+        
+        let client = client.with_normalize(true);
+        assert_eq!(client.embed_batch(&["a", "b"]).unwrap()[0], vec![0.6, 0.0, 0.8]);
+        
+        let client = TeiClient::new("http://gpu-box:8080").with_transport(|request: &HttpRequest| {
+            assert_eq!(request.url, "http://gpu-box:8080/embed_sparse");
+            Ok(HttpResponse { status: 200, headers: Vec::new(), body: include_str!("../fixtures/tei/embed_sparse.json").to_string() })
+        });
+        assert_eq!(client.embed_sparse(&["a", "b"]).unwrap(), [
+            SparseVector { indices: vec![17, 2003], values: vec![0.5, 1.5] },
+            SparseVector { indices: vec![17], values: vec![2.0] },
+        ]);
+        assert_eq!(parse_response(FIXTURE, 3).unwrap_err(), JinaError::Mismatch { expected: 3, got: 2 });
+    }
+    
+    #[test]
+    fn test_413_splits_batches() {
+        let sizes: Arc<Mutex<Vec<usize>>> = Arc::default();
+        let client = TeiClient::new("http://gpu-box:8080").with_transport(tei_limited_to_two(sizes.clone()));
+        
+        assert_eq!(client.embed_batch(&["a", "b", "c", "d"]).unwrap().len(), 4);
+        assert_eq!(*sizes.lock().unwrap(), vec![4, 2, 2]);
+        assert_eq!(client.batch_size(), 2);
+        
+        // The learned size is used up front on the next call
+        sizes.lock().unwrap().clear();
+        client.embed_batch(&["a", "b", "c", "d"]).unwrap();
+        assert_eq!(*sizes.lock().unwrap(), vec![2, 2]);
+        
+        // A single input that is still too large surfaces the server's message
+        let client = TeiClient::new("http://gpu-box:8080")
+            .with_transport(|_: &HttpRequest| Ok(HttpResponse { status: 413, headers: Vec::new(), body: FIXTURE_413.to_string() }));
+        assert_eq!(client.embed("x").unwrap_err(), JinaError::Api {
+            status: 413,
+            message: "batch size 4 > maximum allowed batch size 2".to_string(),
+        });
+    }
+}
@@ -0,0 +1,348 @@
+//! Client-side token counting
+//!
+//! `estimate` approximates jina-embeddings-v3 (XLM-RoBERTa) token counts
+//! without a vocabulary: text is split into runs of one script class and
+//! each run costs its length over that class's typical characters per
+//! token. That keeps CJK (about a token per character or two) and code
+//! (about a token per symbol) within reach of the real count, where a flat
+//! chars/4 is off by 2-3x.
+//!
+//! With the `tokenizers` feature, `Tokenizer` loads the Unigram vocabulary
+//! from the model's `tokenizer.json` and counts the pieces of a Viterbi
+//! segmentation. It is a small reimplementation, not the `tokenizers`
+//! crate, and its counts have not been checked against it. Anything that
+//! budgets tokens takes a `TokenCounter`, so the two are interchangeable.
+//!
+//! `ContextLimits` holds the context window of each model, from
+//! `MODEL_CONTEXT_TOKENS` and overrides, for checking inputs before they
+//! are sent (`document::LongInputStrategy`).
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// Counts tokens in a text, excluding the `<s>`/`</s>` specials
+pub trait TokenCounter: Send + Sync {
+    fn count(&self, text: &str) -> usize;
+}
+
+impl<F> TokenCounter for F
+where
+    F: Fn(&str) -> usize + Send + Sync,
+{
+    fn count(&self, text: &str) -> usize { self(text) }
+}
+
+/// The vocabulary-free estimator behind `estimate`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Approximate;
+
+impl TokenCounter for Approximate {
+    fn count(&self, text: &str) -> usize { estimate(text) }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Class {
+    Space,
+    Latin,
+    /// Alphabetic scripts the vocabulary covers more thinly (Cyrillic, Greek, Arabic, ...)
+    Alphabet,
+    Han,
+    Hangul,
+    Digit,
+    Symbol,
+}
+
+impl Class {
+    fn of(c: char) -> Class {
+        match c {
+            _ if c.is_whitespace() => Class::Space,
+            _ if c.is_ascii_digit() => Class::Digit,
+            _ if c.is_ascii_alphabetic() => Class::Latin,
+            '\u{00c0}'..='\u{024f}' | '\u{1e00}'..='\u{1eff}' => Class::Latin,
+            '\u{1100}'..='\u{11ff}' | '\u{3130}'..='\u{318f}' | '\u{ac00}'..='\u{d7af}' => Class::Hangul,
+            '\u{2e80}'..='\u{9fff}' | '\u{f900}'..='\u{faff}' | '\u{ff66}'..='\u{ff9f}' | '\u{20000}'..='\u{2fa1f}' => Class::Han,
+            _ if c.is_alphabetic() => Class::Alphabet,
+            _ => Class::Symbol,
+        }
+    }
+    
+    /// Typical characters per token for a run of this class
+    fn chars_per_token(self) -> f32 {
+        match self {
+            Class::Space => f32::INFINITY,
+            Class::Latin => 4.0,
+            Class::Alphabet => 3.0,
+            Class::Han => 1.5,
+            Class::Hangul => 2.0,
+            Class::Digit => 3.0,
+            Class::Symbol => 1.0,
+        }
+    }
+}
+
+/// Approximate token count of `text` for jina-embeddings-v3
+pub fn estimate(text: &str) -> usize {
+    let mut tokens = 0;
+    let mut run: Option<(Class, usize)> = None;
+    for class in text.chars().map(Class::of) {
+        run = match run {
+            Some((c, n)) if c == class => Some((c, n + 1)),
+            Some((c, n)) => {
+                tokens += run_cost(c, n);
+                Some((class, 1))
+            }
+            None => Some((class, 1)),
+        };
+    }
+    if let Some((c, n)) = run {
+        tokens += run_cost(c, n);
+    }
+    tokens
+}
+
+fn run_cost(class: Class, chars: usize) -> usize {
+    (chars as f32 / class.chars_per_token()).ceil() as usize
+}
+
+/// Greedily pack texts into consecutive batches of at most `max_items`
+/// texts and `max_tokens` tokens
+///
+/// A text over `max_tokens` on its own gets a batch to itself rather than
+/// being dropped; truncating it is the caller's call.
+pub fn pack(texts: &[&str], counter: &dyn TokenCounter, max_items: usize, max_tokens: usize) -> Vec<Range<usize>> {
+    let max_items = max_items.max(1);
+    let mut batches = Vec::new();
+    let (mut start, mut tokens) = (0, 0);
+    for (i, text) in texts.iter().enumerate() {
+        let n = counter.count(text);
+        if i > start && (i - start == max_items || tokens + n > max_tokens) {
+            batches.push(start..i);
+            (start, tokens) = (i, 0);
+        }
+        tokens += n;
+    }
+    if start < texts.len() {
+        batches.push(start..texts.len());
+    }
+    batches
+}
+
+/// Context windows of known models, in tokens
+pub const MODEL_CONTEXT_TOKENS: &[(&str, usize)] = &[
+    ("jina-embeddings-v4", 32768),
+    ("jina-embeddings-v3", 8192),
+    ("jina-embeddings-v2-base-en", 8192),
+    ("jina-embeddings-v2-base-de", 8192),
+    ("jina-embeddings-v2-base-es", 8192),
+    ("jina-embeddings-v2-base-zh", 8192),
+    ("jina-embeddings-v2-base-code", 8192),
+    ("jina-colbert-v2", 8192),
+    ("jina-clip-v2", 8192),
+    ("jina-clip-v1", 77),
+];
+
+/// Per-model context windows: `MODEL_CONTEXT_TOKENS` under overrides
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ContextLimits {
+    overrides: BTreeMap<String, usize>,
+    fallback: Option<usize>,
+}
+
+impl ContextLimits {
+    pub fn new() -> Self { Self::default() }
+    
+    /// Take `tokens` as the window of `model`, known or not
+    pub fn with_limit(mut self, model: &str, tokens: usize) -> Self {
+        self.overrides.insert(model.to_string(), tokens);
+        self
+    }
+    
+    /// Window of models neither overridden nor in the table; without one
+    /// their inputs go unchecked
+    pub fn with_fallback(mut self, tokens: usize) -> Self {
+        self.fallback = Some(tokens);
+        self
+    }
+    
+    /// Window of `model`, by name with any `org/` prefix dropped
+    pub fn limit(&self, model: &str) -> Option<usize> {
+        let name = model.rsplit('/').next().unwrap_or(model);
+        self.overridden(model)
+            .or_else(|| MODEL_CONTEXT_TOKENS.iter().find(|(known, _)| *known == name).map(|&(_, tokens)| tokens))
+            .or(self.fallback)
+    }
+    
+    /// The override of `model`, which wins over anything a server says
+    pub(crate) fn overridden(&self, model: &str) -> Option<usize> {
+        self.overrides.get(model).or_else(|| self.overrides.get(model.rsplit('/').next().unwrap_or(model))).copied()
+    }
+}
+
+#[cfg(feature = "tokenizers")]
+pub use exact::Tokenizer;
+
+#[cfg(feature = "tokenizers")]
+mod exact {
+    use std::collections::HashMap;
+    
+    use serde::Deserialize;
+    use unicode_normalization::UnicodeNormalization;
+    
+    use super::TokenCounter;
+    use crate::error::JinaError;
+    
+    /// Score given to a character no vocabulary piece covers
+    const UNK_PENALTY: f64 = 10.0;
+    
+    /// Unigram (SentencePiece) tokenizer loaded from a Hugging Face `tokenizer.json`
+    ///
+    /// Splits NFKC-normalized text on whitespace, marks each word with `▁`
+    /// and segments it by Viterbi over the vocabulary scores. The file's
+    /// own normalizer and pre-tokenizer are not read, so counts can differ
+    /// from the reference tokenizer.
+    #[derive(Clone, Debug)]
+    pub struct Tokenizer {
+        scores: HashMap<String, f64>,
+        max_piece_chars: usize,
+        unk_score: f64,
+    }
+    
+    #[derive(Deserialize)]
+    struct File {
+        model: Model,
+    }
+    
+    #[derive(Deserialize)]
+    struct Model {
+        #[serde(rename = "type")]
+        kind: String,
+        vocab: Vec<(String, f64)>,
+    }
+    
+    impl Tokenizer {
+        /// Load e.g. jina-embeddings-v3's `tokenizer.json`
+        pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self, JinaError> {
+            let path = path.as_ref();
+            let json = std::fs::read_to_string(path)
+                .map_err(|e| JinaError::InvalidInput(format!("{}: {}", path.display(), e)))?;
+            Self::from_json(&json)
+        }
+        
+        pub fn from_json(json: &str) -> Result<Self, JinaError> {
+            let file: File = serde_json::from_str(json).map_err(|e| JinaError::Parse(format!("tokenizer.json: {}", e)))?;
+            if file.model.kind != "Unigram" {
+                return Err(JinaError::InvalidInput(format!("unsupported tokenizer model {}", file.model.kind)));
+            }
+            let min = file.model.vocab.iter().map(|(_, s)| *s).fold(0.0, f64::min);
+            let max_piece_chars = file.model.vocab.iter().map(|(p, _)| p.chars().count()).max().unwrap_or(1);
+            Ok(Tokenizer {
+                scores: file.model.vocab.into_iter().collect(),
+                max_piece_chars,
+                unk_score: min - UNK_PENALTY,
+            })
+        }
+        
+        /// Best-scoring pieces for `text`, `▁` marking word starts
+        pub fn tokenize(&self, text: &str) -> Vec<String> {
+            let normalized: String = text.nfkc().collect();
+            normalized.split_whitespace()
+                .flat_map(|word| self.viterbi(&format!("\u{2581}{}", word)))
+                .collect()
+        }
+        
+        fn viterbi(&self, word: &str) -> Vec<String> {
+            let bounds: Vec<usize> = word.char_indices().map(|(i, _)| i).chain([word.len()]).collect();
+            let n = bounds.len() - 1;
+            // best[i]: (score, start of the last piece) of the best cut of word[..bounds[i]]
+            let mut best: Vec<(f64, usize)> = vec![(f64::NEG_INFINITY, 0); n + 1];
+            best[0].0 = 0.0;
+            for end in 1..=n {
+                for start in end.saturating_sub(self.max_piece_chars)..end {
+                    let piece = &word[bounds[start]..bounds[end]];
+                    let score = match self.scores.get(piece) {
+                        Some(&s) => s,
+                        None if end - start == 1 => self.unk_score,
+                        None => continue,
+                    };
+                    let total = best[start].0 + score;
+                    if total > best[end].0 {
+                        best[end] = (total, start);
+                    }
+                }
+            }
+            let mut pieces = Vec::new();
+            let mut end = n;
+            while end > 0 {
+                let start = best[end].1;
+                pieces.push(word[bounds[start]..bounds[end]].to_string());
+                end = start;
+            }
+            pieces.reverse();
+            pieces
+        }
+    }
+    
+    impl TokenCounter for Tokenizer {
+        fn count(&self, text: &str) -> usize { self.tokenize(text).len() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_estimate_by_script() {
+        assert_eq!(estimate(""), 0);
+        assert_eq!(estimate("   \n"), 0);
+        assert_eq!(estimate("the cat sat"), 3);
+        assert_eq!(estimate("internationalization"), 5);
+        // CJK: far more tokens than chars/4 would say
+        assert_eq!(estimate("自然语言处理"), 4);
+        assert_eq!(estimate("안녕하세요"), 3);
+        // Code: symbols cost a token each
+        assert_eq!(estimate("fn f(x: u8) {}"), 10);
+        assert_eq!(estimate("2024"), 2);
+    }
+    
+    #[test]
+    fn test_pack_respects_items_and_tokens() {
+        let texts = ["a b", "c d e", "f", "g h i j k l m n", "o"];
+        let words = |t: &str| t.split_whitespace().count();
+        assert_eq!(pack(&texts, &words, 10, 6), vec![0..3, 3..4, 4..5]);
+        assert_eq!(pack(&texts, &words, 2, 100), vec![0..2, 2..4, 4..5]);
+        assert_eq!(pack(&texts, &Approximate, 10, 1000), vec![0..5]);
+        assert!(pack(&[], &Approximate, 10, 10).is_empty());
+    }
+    
+    #[cfg(feature = "tokenizers")]
+    #[test]
+    fn test_unigram_tokenizer() {
+        let tokenizer = Tokenizer::from_json(include_str!("../fixtures/tokens/unigram.json")).unwrap();
+        assert_eq!(tokenizer.tokenize("the cathedral"), vec!["\u{2581}the", "\u{2581}cat", "hedral"]);
+        assert_eq!(tokenizer.tokenize("cat猫"), vec!["\u{2581}cat", "猫"]);
+        assert_eq!(tokenizer.count("  tea \n"), 3);
+        assert!(Tokenizer::from_json(r#"{"model":{"type":"BPE","vocab":[]}}"#).is_err());
+    }
+    
+    /// Opt-in: `JINA_TOKENIZER=path/to/tokenizer.json cargo test --features tokenizers tokens -- --ignored`
+    #[cfg(feature = "tokenizers")]
+    #[test]
+    #[ignore = "needs JINA_TOKENIZER, the jina-embeddings-v3 tokenizer.json"]
+    fn test_estimate_error_bound() {
+        let path = std::env::var("JINA_TOKENIZER").expect("JINA_TOKENIZER is the path of the model's tokenizer.json");
+        let tokenizer = Tokenizer::from_file(path).unwrap();
+        let texts: Vec<String> = serde_json::from_str(include_str!("../fixtures/tokens/multilingual.json")).unwrap();
+        for text in &texts {
+            // Relative error allowed by the text's main class: chars/4 is off by 2-3x on CJK and code
+            let classes: Vec<Class> = text.chars().map(Class::of).filter(|&c| c != Class::Space).collect();
+            let share = |class: Class| classes.iter().filter(|&&c| c == class).count() as f32 / classes.len() as f32;
+            let code = share(Class::Symbol) + share(Class::Digit) >= 0.25;
+            let cjk = share(Class::Han) + share(Class::Hangul) >= 0.5;
+            let tolerance = if code || cjk { 0.3 } else { 0.25 };
+            let (exact, approx) = (tokenizer.count(text) as f32, estimate(text) as f32);
+            assert!((approx - exact).abs() <= tolerance * exact + 1.0,
+                    "{:?}: estimated {} vs {}, over {:.0}%", text, approx, exact, tolerance * 100.0);
+        }
+    }
+}
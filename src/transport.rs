@@ -0,0 +1,1072 @@
+//! HTTP transports shared by the API backends
+//!
+//! Backends build an `HttpRequest` and hand it to a `Transport`:
+//! - `CurlTransport` shells out to `curl`, so HTTPS works without a TLS dependency
+//! - `PlainHttpTransport` speaks HTTP/1.1 over a `TcpStream` (local servers, `http://` only)
+//! - any `Fn(&HttpRequest) -> Result<HttpResponse, JinaError>` closure is a
+//!   transport too, which is how fixtures are served in tests
+//!
+//! `send_with_retry` retries transport failures, 429 and 5xx with
+//! exponential backoff (honoring `Retry-After`); `check_status` turns an
+//! error status into `JinaError::Api` carrying the server's message.
+//! `send_with_hooks` also runs request and response `Hooks` per attempt,
+//! and `send_diagnosed` sums each attempt's `Timings` into `Diagnostics`.
+//! `send_until` also stops at a `Deadline`: each attempt's timeout shrinks
+//! to the time left, and no attempt starts, nor retry delay is slept, past
+//! it; the call fails with `JinaError::DeadlineExceeded` instead.
+//!
+//! Request and response bodies come from a shared `BufferPool`: each call
+//! checks a buffer out and hands it back when done, so steady-state calls
+//! reuse capacity instead of growing fresh buffers.
+//!
+//! `HttpRequest::gzip` compresses a body and marks it `Content-Encoding: gzip`.
+//! Both transports send bodies as raw bytes (curl reads them from stdin via
+//! `--data-binary @-`), so `Content-Length` is always the compressed size.
+//!
+//! wasm32 has neither sockets nor subprocesses: both transports are left out
+//! there and `for_url` gives one that always fails. Use
+//! `async_client::AsyncJinaClient` with a `FetchTransport` instead.
+
+use std::borrow::Cow;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::error::{truncate_for_display, JinaError, MAX_DISPLAY_CHARS};
+
+/// Idle buffers the pool keeps
+const POOL_BUFFERS: usize = 16;
+/// Larger buffers are freed rather than pooled
+const POOL_MAX_CAPACITY: usize = 16 << 20;
+
+/// Byte buffers shared across calls and threads. A buffer belongs to one
+/// call between `take` and `give`, so concurrent calls never share one.
+pub(crate) struct BufferPool {
+    free: Mutex<Vec<Vec<u8>>>,
+}
+
+impl BufferPool {
+    pub(crate) const fn new() -> Self { Self { free: Mutex::new(Vec::new()) } }
+    
+    /// An empty buffer, with capacity left from earlier calls when one is idle
+    pub(crate) fn take(&self) -> Vec<u8> {
+        self.free.lock().map_or_else(|_| Vec::new(), |mut free| free.pop().unwrap_or_default())
+    }
+    
+    /// Return `buffer` for reuse; dropped when the pool is full or it is too large
+    pub(crate) fn give(&self, mut buffer: Vec<u8>) {
+        if buffer.capacity() == 0 || buffer.capacity() > POOL_MAX_CAPACITY {
+            return;
+        }
+        buffer.clear();
+        if let Ok(mut free) = self.free.lock() {
+            if free.len() < POOL_BUFFERS {
+                free.push(buffer);
+            }
+        }
+    }
+}
+
+/// Pool for request and response bodies
+pub(crate) static BUFFERS: BufferPool = BufferPool::new();
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpRequest {
+    pub method: &'static str,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Overrides the transport's own timeout
+    pub timeout: Option<Duration>,
+}
+
+impl HttpRequest {
+    pub fn get(url: impl Into<String>) -> Self {
+        Self { method: "GET", url: url.into(), headers: Vec::new(), body: Vec::new(), timeout: None }
+    }
+    
+    /// POST with a JSON body and matching Content-Type
+    pub fn post_json(url: impl Into<String>, body: &serde_json::Value) -> Self {
+        Self {
+            method: "POST",
+            url: url.into(),
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: body.to_string().into_bytes(),
+            timeout: None,
+        }
+    }
+    
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+    
+    /// `Authorization: Bearer <key>` if a key is set
+    pub fn bearer(self, key: Option<&str>) -> Self {
+        match key {
+            Some(key) => self.header("Authorization", &format!("Bearer {}", key)),
+            None => self,
+        }
+    }
+    
+    /// Gzip the body and mark it `Content-Encoding: gzip`
+    pub fn gzip(mut self) -> Self {
+        let mut encoder = GzEncoder::new(Vec::with_capacity(self.body.len() / 4), Compression::fast());
+        // Writing to a Vec cannot fail
+        encoder.write_all(&self.body).expect("in-memory gzip");
+        self.body = encoder.finish().expect("in-memory gzip");
+        self.header("Content-Encoding", "gzip")
+    }
+    
+    pub fn is_gzipped(&self) -> bool {
+        self.headers.iter().any(|(n, v)| n.eq_ignore_ascii_case("content-encoding") && v.eq_ignore_ascii_case("gzip"))
+    }
+    
+    /// The body as the server sees it once inflated; `None` if a gzipped body is corrupt
+    pub fn decoded_body(&self) -> Option<Cow<'_, [u8]>> {
+        if !self.is_gzipped() {
+            return Some(Cow::Borrowed(&self.body));
+        }
+        let mut inflated = Vec::new();
+        GzDecoder::new(self.body.as_slice()).read_to_end(&mut inflated).ok()?;
+        Some(Cow::Owned(inflated))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl HttpResponse {
+    /// First header named `name`, case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+    
+    pub fn is_success(&self) -> bool { (200..300).contains(&self.status) }
+}
+
+/// Sends one request; errors only when no HTTP response was received
+pub trait Transport: Send + Sync {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse, JinaError>;
+    
+    /// `send` with phase timings; transports that cannot see phases report the total
+    fn send_timed(&self, request: &HttpRequest) -> (Result<HttpResponse, JinaError>, Timings) {
+        let start = Instant::now();
+        let result = self.send(request);
+        (result, Timings { total_ms: millis(start.elapsed()), ..Timings::default() })
+    }
+    
+    /// Name reported as `Diagnostics::backend`
+    fn label(&self) -> &'static str { "custom" }
+}
+
+/// Phases of one attempt in milliseconds from its start; `None` where unknown
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Timings {
+    /// Until the TCP connection was up, DNS included
+    pub connect_ms: Option<f64>,
+    /// TLS handshake, after connecting
+    pub tls_ms: Option<f64>,
+    /// Until the first response byte
+    pub ttfb_ms: Option<f64>,
+    pub total_ms: f64,
+}
+
+/// Where the time and bytes of a call went, summed over its requests and attempts
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Diagnostics {
+    pub connect_ms: Option<f64>,
+    pub tls_ms: Option<f64>,
+    pub ttfb_ms: Option<f64>,
+    pub total_ms: f64,
+    /// Attempts sent, retries included
+    pub attempts: u32,
+    /// What served the call: a transport label such as `"curl"` or `"http"`, or
+    /// `"provider"` and `"offline"`; empty when every text came from the cache
+    pub backend: String,
+    /// Request body bytes, over all attempts
+    pub bytes_sent: u64,
+    /// Response body bytes, over all attempts
+    pub bytes_received: u64,
+}
+
+impl Diagnostics {
+    /// Add one attempt through a transport
+    pub(crate) fn record(&mut self, backend: &str, request: &HttpRequest, response: Option<&HttpResponse>, timings: &Timings) {
+        self.connect_ms = add_ms(self.connect_ms, timings.connect_ms);
+        self.tls_ms = add_ms(self.tls_ms, timings.tls_ms);
+        self.ttfb_ms = add_ms(self.ttfb_ms, timings.ttfb_ms);
+        self.record_call(backend, timings.total_ms);
+        self.bytes_sent += request.body.len() as u64;
+        self.bytes_received += response.map_or(0, |r| r.body.len()) as u64;
+    }
+    
+    /// Add the requests summed in `other`
+    pub(crate) fn merge(&mut self, other: &Diagnostics) {
+        self.connect_ms = add_ms(self.connect_ms, other.connect_ms);
+        self.tls_ms = add_ms(self.tls_ms, other.tls_ms);
+        self.ttfb_ms = add_ms(self.ttfb_ms, other.ttfb_ms);
+        self.total_ms += other.total_ms;
+        self.attempts += other.attempts;
+        if !other.backend.is_empty() && self.backend != other.backend {
+            self.backend = other.backend.clone();
+        }
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_received += other.bytes_received;
+    }
+    
+    /// Add one attempt that did not go through a transport
+    pub(crate) fn record_call(&mut self, backend: &str, total_ms: f64) {
+        self.total_ms += total_ms;
+        self.attempts += 1;
+        if self.backend != backend {
+            self.backend = backend.to_string();
+        }
+    }
+}
+
+/// Sum of two phase times, unknown only when both are
+fn add_ms(total: Option<f64>, ms: Option<f64>) -> Option<f64> {
+    match (total, ms) {
+        (None, None) => None,
+        (total, ms) => Some(total.unwrap_or(0.0) + ms.unwrap_or(0.0)),
+    }
+}
+
+pub(crate) fn millis(duration: Duration) -> f64 { duration.as_secs_f64() * 1000.0 }
+
+impl<F> Transport for F
+where
+    F: Fn(&HttpRequest) -> Result<HttpResponse, JinaError> + Send + Sync,
+{
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse, JinaError> { self(request) }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use native::{CurlTransport, PlainHttpTransport};
+
+/// Blocking transports over sockets and subprocesses, which wasm32 has neither of
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use std::fmt::Write as _;
+    use std::io::{Read, Write};
+    use std::net::{TcpStream, ToSocketAddrs};
+    use std::path::PathBuf;
+    use std::process::{Child, Command, Stdio};
+    use std::time::{Duration, Instant};
+    
+    use super::{millis, HttpRequest, HttpResponse, Timings, Transport, BUFFERS};
+    use crate::error::{truncate_for_display, JinaError, MAX_DISPLAY_CHARS};
+    
+    const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+    
+    /// `curl` subprocess transport; the body goes over stdin
+    #[derive(Clone, Debug)]
+    pub struct CurlTransport {
+        timeout: Duration,
+        program: PathBuf,
+    }
+    
+    impl Default for CurlTransport {
+        fn default() -> Self { Self { timeout: DEFAULT_TIMEOUT, program: PathBuf::from("curl") } }
+    }
+    
+    impl CurlTransport {
+        pub fn new() -> Self { Self::default() }
+        
+        pub fn with_timeout(mut self, timeout: Duration) -> Self {
+            self.timeout = timeout;
+            self
+        }
+        
+        /// Run `program` instead of the `curl` on `PATH`
+        pub fn with_program(mut self, program: impl Into<PathBuf>) -> Self {
+            self.program = program.into();
+            self
+        }
+    }
+    
+    /// Starts curl's `-w` output, after the response
+    const WRITE_OUT_MARKER: &[u8] = b"\n--spo-crystal-timings-- ";
+    
+    impl Transport for CurlTransport {
+        fn send(&self, request: &HttpRequest) -> Result<HttpResponse, JinaError> {
+            self.run(request, false).map(|(response, _)| response)
+        }
+        
+        fn send_timed(&self, request: &HttpRequest) -> (Result<HttpResponse, JinaError>, Timings) {
+            let start = Instant::now();
+            match self.run(request, true) {
+                Ok((response, Some(timings))) => (Ok(response), timings),
+                other => (other.map(|(response, _)| response), Timings { total_ms: millis(start.elapsed()), ..Timings::default() }),
+            }
+        }
+        
+        fn label(&self) -> &'static str { "curl" }
+    }
+    
+    impl CurlTransport {
+        /// Run curl; with `timed`, phase timings come from its `-w` write-out
+        fn run(&self, request: &HttpRequest, timed: bool) -> Result<(HttpResponse, Option<Timings>), JinaError> {
+            let mut command = Command::new(&self.program);
+            command.args(["-s", "-S", "-D", "-", "-X", request.method])
+                .args(["--max-time", &format!("{:.3}", request.timeout.unwrap_or(self.timeout).as_secs_f64())]);
+            if timed {
+                let marker = String::from_utf8_lossy(WRITE_OUT_MARKER);
+                command.arg("-w").arg(format!("{}%{{time_connect}} %{{time_appconnect}} %{{time_starttransfer}} %{{time_total}}", marker));
+            }
+            for (name, value) in &request.headers {
+                command.arg("-H").arg(format!("{}: {}", name, value));
+            }
+            if !request.body.is_empty() {
+                command.args(["--data-binary", "@-"]);
+            }
+            command.arg(&request.url)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+            
+            let mut child = command.spawn().map_err(|e| JinaError::Transport(format!("curl failed to start: {}", e)))?;
+            let mut raw = BUFFERS.take();
+            let mut stderr = Vec::new();
+            let exchanged = exchange(&mut child, &request.body, &mut raw, &mut stderr);
+            // Reap the child whatever happened, so failed calls leave no zombies
+            if exchanged.is_err() {
+                let _ = child.kill();
+            }
+            let failed = |e: std::io::Error| JinaError::Transport(format!("curl failed: {}", e));
+            let status = child.wait().map_err(failed)?;
+            if let Err(e) = exchanged {
+                BUFFERS.give(raw);
+                return Err(failed(e));
+            }
+            
+            if !status.success() {
+                BUFFERS.give(raw);
+                let message = truncate_for_display(String::from_utf8_lossy(&stderr).trim(), MAX_DISPLAY_CHARS);
+                // curl exit codes 6 (resolve) and 7 (connect)
+                return Err(match status.code() {
+                    Some(6 | 7) => JinaError::Connect(message),
+                    _ => JinaError::Transport(message),
+                });
+            }
+            let timings = if timed { split_write_out(&mut raw) } else { None };
+            Ok((parse_raw_response(raw)?, timings))
+        }
+    }
+    
+    /// Write `body` to the child's stdin, then read its stdout and stderr to the end
+    fn exchange(child: &mut Child, body: &[u8], stdout: &mut Vec<u8>, stderr: &mut Vec<u8>) -> std::io::Result<()> {
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(body)?;
+        }
+        // stderr stays small (curl writes it on failure), so reading stdout first cannot block on it
+        if let Some(mut pipe) = child.stdout.take() {
+            pipe.read_to_end(stdout)?;
+        }
+        if let Some(mut pipe) = child.stderr.take() {
+            pipe.read_to_end(stderr)?;
+        }
+        Ok(())
+    }
+    
+    /// Strip curl's `-w` timings off the end of `raw` and convert them
+    pub(super) fn split_write_out(raw: &mut Vec<u8>) -> Option<Timings> {
+        let at = raw.windows(WRITE_OUT_MARKER.len()).rposition(|w| w == WRITE_OUT_MARKER)?;
+        let text = String::from_utf8_lossy(&raw[at + WRITE_OUT_MARKER.len()..]).into_owned();
+        raw.truncate(at);
+        let seconds: Vec<f64> = text.split_whitespace().filter_map(|x| x.parse().ok()).collect();
+        let &[connect, appconnect, first_byte, total] = seconds.as_slice() else { return None };
+        let ms = |s: f64| s * 1000.0;
+        Some(Timings {
+            connect_ms: Some(ms(connect)),
+            // 0 when there was no TLS
+            tls_ms: (appconnect > 0.0).then(|| ms(appconnect - connect)),
+            ttfb_ms: Some(ms(first_byte)),
+            total_ms: ms(total),
+        })
+    }
+    
+    /// HTTP/1.1 over a plain `TcpStream`, one connection per request
+    #[derive(Clone, Debug)]
+    pub struct PlainHttpTransport {
+        timeout: Duration,
+    }
+    
+    impl Default for PlainHttpTransport {
+        fn default() -> Self { Self { timeout: DEFAULT_TIMEOUT } }
+    }
+    
+    impl PlainHttpTransport {
+        pub fn new() -> Self { Self::default() }
+        
+        /// Applies to connecting and to each read/write
+        pub fn with_timeout(mut self, timeout: Duration) -> Self {
+            self.timeout = timeout;
+            self
+        }
+    }
+    
+    impl Transport for PlainHttpTransport {
+        fn send(&self, request: &HttpRequest) -> Result<HttpResponse, JinaError> {
+            self.send_timed(request).0
+        }
+        
+        fn send_timed(&self, request: &HttpRequest) -> (Result<HttpResponse, JinaError>, Timings) {
+            let start = Instant::now();
+            let mut timings = Timings::default();
+            let result = self.exchange(request, start, &mut timings);
+            timings.total_ms = millis(start.elapsed());
+            (result, timings)
+        }
+        
+        fn label(&self) -> &'static str { "http" }
+    }
+    
+    impl PlainHttpTransport {
+        fn exchange(&self, request: &HttpRequest, start: Instant, timings: &mut Timings) -> Result<HttpResponse, JinaError> {
+            let rest = request.url.strip_prefix("http://")
+                .ok_or_else(|| JinaError::InvalidInput(format!("PlainHttpTransport needs an http:// URL, got {}", request.url)))?;
+            let (authority, path) = match rest.find('/') {
+                Some(i) => (&rest[..i], &rest[i..]),
+                None => (rest, "/"),
+            };
+            let address = if authority.contains(':') { authority.to_string() } else { format!("{}:80", authority) };
+            
+            let connect_error = |e: std::io::Error| JinaError::Connect(format!("{}: {}", authority, e));
+            let socket = address.to_socket_addrs().map_err(connect_error)?
+                .next()
+                .ok_or_else(|| JinaError::Connect(format!("{}: no address", authority)))?;
+            let timeout = request.timeout.unwrap_or(self.timeout);
+            let mut stream = TcpStream::connect_timeout(&socket, timeout).map_err(connect_error)?;
+            timings.connect_ms = Some(millis(start.elapsed()));
+            let io_error = |e: std::io::Error| JinaError::Transport(format!("{}: {}", authority, e));
+            stream.set_read_timeout(Some(timeout)).map_err(io_error)?;
+            stream.set_write_timeout(Some(timeout)).map_err(io_error)?;
+            
+            let mut head = format!("{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+                                   request.method, path, authority, request.body.len());
+            for (name, value) in &request.headers {
+                let _ = write!(head, "{}: {}\r\n", name, value);
+            }
+            head.push_str("\r\n");
+            stream.write_all(head.as_bytes()).map_err(io_error)?;
+            stream.write_all(&request.body).map_err(io_error)?;
+            
+            let mut raw = BUFFERS.take();
+            let mut first = [0u8; 1];
+            let n = stream.read(&mut first).map_err(io_error)?;
+            timings.ttfb_ms = Some(millis(start.elapsed()));
+            raw.extend_from_slice(&first[..n]);
+            stream.read_to_end(&mut raw).map_err(io_error)?;
+            let (status, headers, body) = split_raw_response(&raw)?;
+            let chunked = headers.iter()
+                .any(|(n, v)| n.eq_ignore_ascii_case("Transfer-Encoding") && v.eq_ignore_ascii_case("chunked"));
+            if chunked {
+                let body = decode_chunked(body)?;
+                BUFFERS.give(raw);
+                return Ok(HttpResponse { status, headers, body: body_text(body) });
+            }
+            let head = raw.len() - body.len();
+            raw.drain(..head);
+            Ok(HttpResponse { status, headers, body: body_text(raw) })
+        }
+    }
+    
+    /// Decode a `Transfer-Encoding: chunked` body
+    pub(super) fn decode_chunked(body: &[u8]) -> Result<Vec<u8>, JinaError> {
+        let malformed = || JinaError::Transport("Malformed chunked response body".to_string());
+        let mut out = Vec::with_capacity(body.len());
+        let mut rest = body;
+        loop {
+            let line_end = find(rest, b"\r\n").ok_or_else(malformed)?;
+            let size_line = String::from_utf8_lossy(&rest[..line_end]);
+            let size_hex = size_line.split(';').next().unwrap_or("").trim();
+            let size = usize::from_str_radix(size_hex, 16).map_err(|_| malformed())?;
+            if size == 0 {
+                return Ok(out);
+            }
+            let after = &rest[line_end + 2..];
+            out.extend_from_slice(after.get(..size).ok_or_else(malformed)?);
+            rest = after[size..].strip_prefix(b"\r\n").ok_or_else(malformed)?;
+        }
+    }
+    
+    /// Split `curl -D -` output (header blocks, then body) into a response.
+    ///
+    /// Interim blocks (`100 Continue`, proxy `CONNECT`) are skipped; the last
+    /// header block belongs to the body. The body reuses `raw`'s buffer.
+    pub(crate) fn parse_raw_response(mut raw: Vec<u8>) -> Result<HttpResponse, JinaError> {
+        let (status, headers, body) = split_raw_response(&raw)?;
+        let head = raw.len() - body.len();
+        raw.drain(..head);
+        Ok(HttpResponse { status, headers, body: body_text(raw) })
+    }
+    
+    /// Body bytes as text: validated once and kept in place when UTF-8, copied
+    /// with replacement characters otherwise
+    fn body_text(bytes: Vec<u8>) -> String {
+        String::from_utf8(bytes).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+    }
+    
+    /// Status, headers and raw body bytes
+    type RawResponse<'a> = (u16, Vec<(String, String)>, &'a [u8]);
+    
+    /// Parts of the final response in `raw`
+    fn split_raw_response(raw: &[u8]) -> Result<RawResponse<'_>, JinaError> {
+        let mut rest = raw;
+        let mut head: Option<&[u8]> = None;
+        while rest.starts_with(b"HTTP/") {
+            let (end, sep) = match find(rest, b"\r\n\r\n") {
+                Some(i) => (i, 4),
+                None => match find(rest, b"\n\n") {
+                    Some(i) => (i, 2),
+                    None => (rest.len(), 0),
+                },
+            };
+            head = Some(&rest[..end]);
+            rest = &rest[end + sep..];
+        }
+        let head = head.ok_or_else(|| JinaError::Transport("No HTTP status line in response".to_string()))?;
+        let head = String::from_utf8_lossy(head);
+        
+        let mut lines = head.lines();
+        let status = lines.next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| JinaError::Transport("Malformed HTTP status line".to_string()))?;
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect();
+        
+        Ok((status, headers, rest))
+    }
+    
+    fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        haystack.windows(needle.len()).position(|w| w == needle)
+    }
+}
+
+/// Plain HTTP for `http://` URLs, curl for everything else; recording if `SPO_CRYSTAL_RECORD` is set
+#[cfg(not(target_arch = "wasm32"))]
+pub fn for_url(url: &str) -> Arc<dyn Transport> {
+    let transport: Arc<dyn Transport> = if url.starts_with("http://") {
+        Arc::new(PlainHttpTransport::new())
+    } else {
+        Arc::new(CurlTransport::new())
+    };
+    crate::replay::record_from_env(transport)
+}
+
+/// On wasm32 there is no blocking transport: every request fails, pointing
+/// to `AsyncJinaClient`
+#[cfg(target_arch = "wasm32")]
+pub fn for_url(url: &str) -> Arc<dyn Transport> {
+    let url = url.to_string();
+    Arc::new(move |_: &HttpRequest| -> Result<HttpResponse, JinaError> {
+        Err(JinaError::Transport(format!("No blocking HTTP transport on wasm32 for {}; use async_client::AsyncJinaClient", url)))
+    })
+}
+
+
+/// Retry budget for transient failures
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub max_retries: u32,
+    /// Delay before the first retry; doubles each retry
+    pub base_delay: Duration,
+    /// Cap on any single delay, including `Retry-After`
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self { max_retries: 3, base_delay: Duration::from_millis(250), max_delay: Duration::from_secs(8) }
+    }
+}
+
+impl RetryPolicy {
+    /// Single attempt, no retries
+    pub fn none() -> Self { Self { max_retries: 0, ..Self::default() } }
+    
+    pub(crate) fn delay(&self, retry: u32) -> Duration {
+        self.base_delay.saturating_mul(1 << retry.min(16)).min(self.max_delay)
+    }
+}
+
+/// Time source of deadlines and the delays slept before their retries
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+/// The system's monotonic clock and `thread::sleep`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant { Instant::now() }
+    fn sleep(&self, duration: Duration) { std::thread::sleep(duration) }
+}
+
+/// When a call must be done by, on `clock`
+#[derive(Clone, Copy)]
+pub struct Deadline<'a> {
+    pub at: Instant,
+    pub clock: &'a dyn Clock,
+}
+
+impl Deadline<'_> {
+    /// Time left, or `DeadlineExceeded` once there is none
+    pub fn remaining(&self) -> Result<Duration, JinaError> {
+        match self.at.checked_duration_since(self.clock.now()) {
+            Some(left) if !left.is_zero() => Ok(left),
+            _ => Err(JinaError::DeadlineExceeded),
+        }
+    }
+}
+
+/// Value hooks see in place of the `Authorization` header
+pub const REDACTED: &str = "[REDACTED]";
+
+pub type RequestHook = dyn Fn(&mut HttpRequest, u32) + Send + Sync;
+pub type ResponseHook = dyn Fn(&HttpResponse, u32) + Send + Sync;
+pub type AttemptHook = dyn Fn(&Attempt<'_>) + Send + Sync;
+
+/// One finished attempt as attempt hooks see it: no headers, so no key
+pub struct Attempt<'a> {
+    pub url: &'a str,
+    pub body: &'a [u8],
+    pub result: Result<&'a HttpResponse, &'a JinaError>,
+    pub total_ms: f64,
+    /// 1 for the first try
+    pub attempt: u32,
+    /// `Hooks::with_tag` of the call
+    pub tag: Option<&'a str>,
+}
+
+/// Callbacks run on the calling thread around each attempt of a request,
+/// with the attempt number (1 for the first try).
+///
+/// Request hooks may change a copy of the request before it is sent; they
+/// see the `Authorization` value as `REDACTED` unless `with_auth_visible`,
+/// and a redacted value left in place is restored before sending.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    request: Option<Arc<RequestHook>>,
+    response: Option<Arc<ResponseHook>>,
+    attempt: Option<Arc<AttemptHook>>,
+    auth_visible: bool,
+    tag: Option<Arc<str>>,
+}
+
+impl Hooks {
+    pub fn new() -> Self { Self::default() }
+    
+    pub fn with_request(mut self, hook: impl Fn(&mut HttpRequest, u32) + Send + Sync + 'static) -> Self {
+        self.request = Some(Arc::new(hook));
+        self
+    }
+    
+    pub fn with_response(mut self, hook: impl Fn(&HttpResponse, u32) + Send + Sync + 'static) -> Self {
+        self.response = Some(Arc::new(hook));
+        self
+    }
+    
+    /// Run `hook` after every attempt, failed ones included, with its timing
+    pub fn with_attempt(mut self, hook: impl Fn(&Attempt<'_>) + Send + Sync + 'static) -> Self {
+        self.attempt = Some(Arc::new(hook));
+        self
+    }
+    
+    /// Show request hooks the real `Authorization` value
+    pub fn with_auth_visible(mut self) -> Self {
+        self.auth_visible = true;
+        self
+    }
+    
+    /// Label the attempts attempt hooks see with `tag`
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.into());
+        self
+    }
+    
+    /// `request` as changed by `hook` for this attempt
+    fn prepare(&self, hook: &RequestHook, request: &HttpRequest, attempt: u32) -> HttpRequest {
+        let mut request = request.clone();
+        if self.auth_visible {
+            hook(&mut request, attempt);
+            return request;
+        }
+        let mut secrets = Vec::new();
+        for (_, value) in request.headers.iter_mut().filter(|(n, _)| n.eq_ignore_ascii_case("Authorization")) {
+            secrets.push(std::mem::replace(value, REDACTED.to_string()));
+        }
+        hook(&mut request, attempt);
+        let mut secrets = secrets.into_iter();
+        for (_, value) in request.headers.iter_mut().filter(|(n, _)| n.eq_ignore_ascii_case("Authorization")) {
+            match secrets.next() {
+                Some(secret) if value == REDACTED => *value = secret,
+                Some(_) => {}
+                None => break,
+            }
+        }
+        request
+    }
+}
+
+pub(crate) fn retryable_status(status: u16) -> bool {
+    status == 429 || (500..600).contains(&status) && status != 501
+}
+
+/// Send, retrying transport errors, 429 and 5xx per `policy`.
+///
+/// Returns the last response even if its status is an error; pair with
+/// `check_status`.
+pub fn send_with_retry(transport: &dyn Transport, request: &HttpRequest, policy: &RetryPolicy)
+    -> Result<HttpResponse, JinaError>
+{
+    send_with_hooks(transport, request, policy, &Hooks::default())
+}
+
+/// `send_with_retry`, running `hooks` around every attempt
+pub fn send_with_hooks(transport: &dyn Transport, request: &HttpRequest, policy: &RetryPolicy, hooks: &Hooks)
+    -> Result<HttpResponse, JinaError>
+{
+    send_diagnosed(transport, request, policy, hooks, None)
+}
+
+/// `send_with_hooks`, adding every attempt to `diagnostics` if given
+pub fn send_diagnosed(transport: &dyn Transport, request: &HttpRequest, policy: &RetryPolicy, hooks: &Hooks,
+                      diagnostics: Option<&mut Diagnostics>) -> Result<HttpResponse, JinaError> {
+    send_until(transport, request, policy, hooks, diagnostics, None)
+}
+
+/// `send_diagnosed`, done by `deadline` if given: attempts get at most the
+/// time left as their timeout, and `DeadlineExceeded` is returned rather
+/// than starting an attempt or sleeping a retry delay that ends past it
+pub fn send_until(transport: &dyn Transport, request: &HttpRequest, policy: &RetryPolicy, hooks: &Hooks,
+                  mut diagnostics: Option<&mut Diagnostics>, deadline: Option<Deadline<'_>>) -> Result<HttpResponse, JinaError> {
+    let mut retry = 0;
+    loop {
+        let attempt = retry + 1;
+        let bounded;
+        let request = match deadline {
+            Some(deadline) => {
+                let left = deadline.remaining()?;
+                bounded = HttpRequest { timeout: Some(request.timeout.map_or(left, |t| t.min(left))), ..request.clone() };
+                &bounded
+            }
+            None => request,
+        };
+        let prepared;
+        let request = match &hooks.request {
+            Some(hook) => {
+                prepared = hooks.prepare(hook.as_ref(), request, attempt);
+                &prepared
+            }
+            None => request,
+        };
+        let (result, timings) = if diagnostics.is_some() || hooks.attempt.is_some() {
+            let (result, timings) = transport.send_timed(request);
+            (result, Some(timings))
+        } else {
+            (transport.send(request), None)
+        };
+        if let (Some(diagnostics), Some(timings)) = (diagnostics.as_deref_mut(), &timings) {
+            diagnostics.record(transport.label(), request, result.as_ref().ok(), timings);
+        }
+        if let (Some(hook), Some(timings)) = (&hooks.attempt, &timings) {
+            hook(&Attempt { url: &request.url, body: &request.body, result: result.as_ref(), total_ms: timings.total_ms, attempt,
+                             tag: hooks.tag.as_deref() });
+        }
+        if let (Some(hook), Ok(response)) = (&hooks.response, &result) {
+            hook(response, attempt);
+        }
+        let delay = match &result {
+            Ok(response) if retryable_status(response.status) => response.header("Retry-After")
+                .and_then(|secs| secs.trim().parse::<u64>().ok())
+                .map(Duration::from_secs)
+                .unwrap_or_else(|| policy.delay(retry)),
+            Err(JinaError::Transport(_) | JinaError::Connect(_)) => policy.delay(retry),
+            _ => return result,
+        };
+        if retry >= policy.max_retries {
+            return result;
+        }
+        let delay = delay.min(policy.max_delay);
+        match deadline {
+            Some(deadline) if deadline.remaining()? <= delay => return Err(JinaError::DeadlineExceeded),
+            Some(deadline) => deadline.clock.sleep(delay),
+            None => std::thread::sleep(delay),
+        }
+        retry += 1;
+    }
+}
+
+/// Pass through 2xx responses; map anything else to `JinaError::Api`
+pub fn check_status(response: HttpResponse) -> Result<HttpResponse, JinaError> {
+    if response.is_success() {
+        return Ok(response);
+    }
+    Err(JinaError::Api { status: response.status, message: error_message(&response.body) })
+}
+
+/// Server error message from common JSON error shapes, else the (shortened) body
+fn error_message(body: &str) -> String {
+    if let Ok(json) = serde_json::from_str::<serde_json::Value>(body) {
+        let candidates = [&json["detail"], &json["message"], &json["error"]["message"], &json["error"]];
+        if let Some(msg) = candidates.iter().find_map(|v| v.as_str()) {
+            return truncate_for_display(msg, MAX_DISPLAY_CHARS);
+        }
+    }
+    truncate_for_display(body.trim(), MAX_DISPLAY_CHARS)
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use super::native::{decode_chunked, parse_raw_response, split_write_out};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    
+    fn response(status: u16, body: &str) -> HttpResponse {
+        HttpResponse { status, headers: Vec::new(), body: body.to_string() }
+    }
+    
+    #[test]
+    fn test_parse_raw_response() {
+        let raw = b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 429 Too Many Requests\r\nRetry-After: 2\r\nContent-Type: application/json\r\n\r\n{\"detail\":\"slow down\"}";
+        let parsed = parse_raw_response(raw.to_vec()).unwrap();
+        assert_eq!(parsed.status, 429);
+        assert_eq!(parsed.header("retry-after"), Some("2"));
+        assert_eq!(parsed.body, "{\"detail\":\"slow down\"}");
+        assert_eq!(check_status(parsed), Err(JinaError::Api { status: 429, message: "slow down".to_string() }));
+        
+        assert!(parse_raw_response(b"not http".to_vec()).is_err());
+        assert_eq!(error_message(&"é".repeat(300)), format!("{}… (100 more chars)", "é".repeat(200)));
+        
+        // The body keeps the raw buffer; invalid UTF-8 is replaced, not fatal
+        let raw = b"HTTP/1.1 200 OK\r\n\r\n{\"data\":[]}".to_vec();
+        let buffer = raw.as_ptr();
+        assert_eq!(parse_raw_response(raw).unwrap().body.as_ptr(), buffer);
+        assert_eq!(parse_raw_response(b"HTTP/1.1 200 OK\r\n\r\nok \xff".to_vec()).unwrap().body, "ok \u{fffd}");
+    }
+    
+    #[test]
+    fn test_buffer_pool_checks_out_per_call() {
+        let pool = BufferPool::new();
+        let mut buffer = pool.take();
+        buffer.extend_from_slice(&[1; 4096]);
+        let (ptr, capacity) = (buffer.as_ptr(), buffer.capacity());
+        pool.give(buffer);
+        let reused = pool.take();
+        assert!(reused.is_empty());
+        assert_eq!((reused.as_ptr(), reused.capacity()), (ptr, capacity));
+        // Checked out buffers are never handed out twice
+        assert_eq!(pool.take().capacity(), 0);
+        
+        pool.give(Vec::with_capacity(POOL_MAX_CAPACITY + 1));
+        assert_eq!(pool.take().capacity(), 0);
+        std::thread::scope(|scope| {
+            for i in 0..8u8 {
+                let pool = &pool;
+                scope.spawn(move || {
+                    for _ in 0..100 {
+                        let mut buffer = pool.take();
+                        buffer.resize(64, i);
+                        assert!(buffer.iter().all(|&b| b == i));
+                        pool.give(buffer);
+                    }
+                });
+            }
+        });
+        assert!(pool.free.lock().unwrap().len() <= POOL_BUFFERS);
+    }
+    
+    #[test]
+    fn test_decode_chunked_splits_multibyte() {
+        // "grüße" split inside the two-byte "ü"
+        let body = b"3\r\ngr\xc3\r\n4\r\n\xbc\xc3\x9fe\r\n0\r\n\r\n";
+        assert_eq!(String::from_utf8(decode_chunked(body).unwrap()).unwrap(), "grüße");
+        assert!(decode_chunked(b"zz\r\n").is_err());
+        assert!(decode_chunked(b"9\r\nshort\r\n").is_err());
+    }
+    
+    #[test]
+    fn test_retry_transient_failures() {
+        let calls = AtomicUsize::new(0);
+        let flaky = |_: &HttpRequest| -> Result<HttpResponse, JinaError> {
+            match calls.fetch_add(1, Ordering::Relaxed) {
+                0 => Err(JinaError::Transport("connection reset".to_string())),
+                1 => Ok(response(503, "busy")),
+                _ => Ok(response(200, "ok")),
+            }
+        };
+        let policy = RetryPolicy { base_delay: Duration::ZERO, ..RetryPolicy::default() };
+        let request = HttpRequest::get("http://localhost/");
+        
+        assert_eq!(send_with_retry(&flaky, &request, &policy).unwrap().body, "ok");
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+        
+        // Client errors are not retried; exhausted budgets return the last response
+        let bad = |_: &HttpRequest| Ok(response(422, r#"{"error":{"message":"bad input"}}"#));
+        let result = send_with_retry(&bad, &request, &policy).and_then(check_status);
+        assert_eq!(result, Err(JinaError::Api { status: 422, message: "bad input".to_string() }));
+        let down = |_: &HttpRequest| Ok(response(500, "down"));
+        assert_eq!(send_with_retry(&down, &request, &RetryPolicy::none()).unwrap().status, 500);
+    }
+    
+    #[test]
+    fn test_deadline_bounds_attempts_and_retries() {
+        let clock = crate::mock::ManualClock::new();
+        let policy = RetryPolicy { max_retries: 5, base_delay: Duration::from_millis(100), ..RetryPolicy::default() };
+        let request = HttpRequest::get("http://localhost/");
+        let latency = Duration::from_millis(700);
+        let start = clock.now();
+        let deadline = Some(Deadline { at: start + Duration::from_secs(2), clock: &clock });
+        
+        // Each attempt gets the time left as its timeout and gives up when it runs out
+        let timeouts: Mutex<Vec<Duration>> = Mutex::default();
+        let busy = |req: &HttpRequest| -> Result<HttpResponse, JinaError> {
+            let timeout = req.timeout.unwrap();
+            timeouts.lock().unwrap().push(timeout);
+            clock.advance(latency.min(timeout));
+            if timeout < latency { Err(JinaError::Transport("timed out".to_string())) } else { Ok(response(503, "busy")) }
+        };
+        let result = send_until(&busy, &request, &policy, &Hooks::default(), None, deadline);
+        assert_eq!(result, Err(JinaError::DeadlineExceeded));
+        let millis: Vec<u128> = timeouts.lock().unwrap().iter().map(Duration::as_millis).collect();
+        assert_eq!(millis, [2000, 1200, 300]);
+        assert_eq!(clock.now() - start, Duration::from_secs(2));
+        
+        // A transport ignoring timeouts overruns by at most its last attempt
+        let start = clock.now();
+        let deadline = Some(Deadline { at: start + Duration::from_secs(2), clock: &clock });
+        let deaf = |_: &HttpRequest| {
+            clock.advance(latency);
+            Ok(response(503, "busy"))
+        };
+        let result = send_until(&deaf, &request, &policy, &Hooks::default(), None, deadline);
+        assert_eq!(result, Err(JinaError::DeadlineExceeded));
+        assert!(clock.now() - start <= Duration::from_secs(2) + latency);
+        
+        // Past the deadline nothing is sent
+        let calls = AtomicUsize::new(0);
+        let counted = |_: &HttpRequest| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            Ok(response(200, "ok"))
+        };
+        let past = Some(Deadline { at: clock.now(), clock: &clock });
+        assert_eq!(send_until(&counted, &request, &policy, &Hooks::default(), None, past), Err(JinaError::DeadlineExceeded));
+        assert_eq!(calls.load(Ordering::Relaxed), 0);
+    }
+    
+    #[test]
+    fn test_hooks_redact_auth_unless_visible() {
+        let seen: Arc<Mutex<Vec<String>>> = Arc::default();
+        let echo = |request: &HttpRequest| {
+            let auth = request.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case("Authorization")).map(|(_, v)| v.clone());
+            Ok(response(200, &auth.unwrap_or_default()))
+        };
+        let request = HttpRequest::get("http://localhost/").bearer(Some("key"));
+        let log = seen.clone();
+        let record = move |request: &mut HttpRequest, _: u32| log.lock().unwrap().push(request.headers[0].1.clone());
+        
+        let hooks = Hooks::new().with_request(record.clone());
+        assert_eq!(send_with_hooks(&echo, &request, &RetryPolicy::none(), &hooks).unwrap().body, "Bearer key");
+        let hooks = Hooks::new().with_request(record).with_auth_visible();
+        assert_eq!(send_with_hooks(&echo, &request, &RetryPolicy::none(), &hooks).unwrap().body, "Bearer key");
+        assert_eq!(*seen.lock().unwrap(), [REDACTED, "Bearer key"]);
+        
+        // A hook may replace or drop the header
+        let replace = Hooks::new().with_request(|request: &mut HttpRequest, _| request.headers[0].1 = "Bearer other".to_string());
+        assert_eq!(send_with_hooks(&echo, &request, &RetryPolicy::none(), &replace).unwrap().body, "Bearer other");
+        let drop = Hooks::new().with_request(|request: &mut HttpRequest, _| request.headers.clear());
+        assert_eq!(send_with_hooks(&echo, &request, &RetryPolicy::none(), &drop).unwrap().body, "");
+    }
+    
+    #[test]
+    fn test_phase_timings() {
+        let mut raw = b"HTTP/1.1 200 OK\r\n\r\nbody\n--spo-crystal-timings-- 0.010 0.030 0.050 0.060".to_vec();
+        let timings = split_write_out(&mut raw).unwrap();
+        assert_eq!(parse_raw_response(raw).unwrap().body, "body");
+        let close = |a: Option<f64>, b: f64| (a.unwrap() - b).abs() < 1e-9;
+        assert!(close(timings.connect_ms, 10.0) && close(timings.tls_ms, 20.0) && close(timings.ttfb_ms, 50.0));
+        assert!((timings.total_ms - 60.0).abs() < 1e-9);
+        let mut plain = b"HTTP/1.1 200 OK\r\n\r\nbody\n--spo-crystal-timings-- 0.010 0.000 0.050 0.060".to_vec();
+        assert_eq!(split_write_out(&mut plain).unwrap().tls_ms, None);
+        
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                let mut byte = [0u8; 1];
+                stream.read_exact(&mut byte).unwrap();
+                head.push(byte[0]);
+            }
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok").unwrap();
+        });
+        let (result, timings) = PlainHttpTransport::new().send_timed(&HttpRequest::get(url));
+        server.join().unwrap();
+        assert_eq!(result.unwrap().body, "ok");
+        assert!(timings.connect_ms.unwrap() <= timings.ttfb_ms.unwrap() && timings.ttfb_ms.unwrap() <= timings.total_ms);
+        assert_eq!(PlainHttpTransport::new().label(), "http");
+    }
+    
+    #[test]
+    fn test_gzipped_body_inflates_on_the_server() {
+        let json = serde_json::json!({ "input": vec!["repeated text"; 500] });
+        let request = HttpRequest::post_json("http://localhost/", &json);
+        assert!(!request.is_gzipped());
+        let gzipped = request.clone().gzip();
+        assert!(gzipped.is_gzipped() && gzipped.body.len() < request.body.len() / 10);
+        assert_eq!(gzipped.decoded_body().unwrap(), request.body.as_slice());
+        
+        // The server reads exactly Content-Length bytes and inflates them back to the JSON
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                let mut byte = [0u8; 1];
+                stream.read_exact(&mut byte).unwrap();
+                head.push(byte[0]);
+            }
+            let head = String::from_utf8(head).unwrap().to_ascii_lowercase();
+            let header = |name: &str| head.lines().find_map(|l| l.strip_prefix(name)).map(|v| v.trim().to_string());
+            let length: usize = header("content-length:").unwrap().parse().unwrap();
+            let mut body = vec![0u8; length];
+            stream.read_exact(&mut body).unwrap();
+            let mut inflated = String::new();
+            GzDecoder::new(body.as_slice()).read_to_string(&mut inflated).unwrap();
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok").unwrap();
+            (header("content-encoding:"), length, inflated)
+        });
+        let sent = HttpRequest { url, ..gzipped.clone() };
+        assert_eq!(PlainHttpTransport::new().send(&sent).unwrap().body, "ok");
+        let (encoding, length, inflated) = server.join().unwrap();
+        assert_eq!(encoding.as_deref(), Some("gzip"));
+        assert_eq!(length, gzipped.body.len());
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&inflated).unwrap(), json);
+        
+        let corrupt = HttpRequest { body: b"not gzip".to_vec(), ..gzipped };
+        assert_eq!(corrupt.decoded_body(), None);
+    }
+}
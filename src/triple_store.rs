@@ -0,0 +1,1122 @@
+//! Similarity-searchable store of facts
+//!
+//! `TripleStore` embeds each verbalized triple as a passage into a
+//! `CrystalIndex`, so `query_text` finds facts for a question and
+//! `similar_triples` finds related facts. Exact repeats of a triple are
+//! stored once. Each fact keeps its provenance (source id and confidence).
+//!
+//! `preview_entities` groups entity spellings whose embeddings are close
+//! ("Acme Corp." and "Acme Corporation"); `apply_merges` rewrites facts to
+//! each group's canonical form and remembers the other forms as aliases,
+//! which later inserts and `facts_about` lookups go through.
+//! `resolve_entities` does both.
+//!
+//! `score_triple` rates how plausible a new fact is, from its similarity to
+//! stored facts with the same predicate and, with a `RelationModel`
+//! attached, from how close the object lies to `embed(s) + offset(p)`.
+//!
+//! `save(path)` writes the index to `path`, the facts to a JSONL sidecar at
+//! `path` + `.triples.jsonl`, the aliases to `path` + `.aliases.json` and
+//! the templates to `path` + `.templates.json`; `load` reads them back, so
+//! a loaded store verbalizes new facts as the saved one did.
+//! `set_templates` re-embeds the facts whose template changed.
+//!
+//! `retrieve_subgraph` pulls the facts around a question for prompts: the
+//! best matching facts, then the facts sharing their entities, hop by hop.
+//!
+//! `export` writes the facts for other tools as N-Triples, CSV or JSONL;
+//! `import` reads the JSONL form back into an equal store. `export_with`
+//! rounds scores and vectors to an `ExportPrecision` for smaller files,
+//! which `import` reads the same way.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+use crate::index::CrystalIndex;
+use crate::io::{atomic_write, ExportPrecision};
+use crate::jina_api::{EmbedOptions, Task};
+use crate::metadata::Metadata;
+use crate::provider::{EmbedError, EmbeddingProvider};
+use crate::relations::{RelationError, RelationModel};
+use crate::search::{cosine, cosine_score, distance_score, norm};
+use crate::triples::{TemplateRegistry, Triple};
+
+/// Suffix of the facts file next to the index file
+pub const SIDECAR_SUFFIX: &str = ".triples.jsonl";
+
+/// Suffix of the alias table next to the index file
+pub const ALIASES_SUFFIX: &str = ".aliases.json";
+
+/// Suffix of the `TemplateRegistry` next to the index file
+pub const TEMPLATES_SUFFIX: &str = ".templates.json";
+
+/// Prefix of the skolem IRIs N-Triples export gives entities and predicates
+pub const SKOLEM_BASE: &str = "urn:spo-crystal:genid:";
+
+const RDFS_LABEL: &str = "http://www.w3.org/2000/01/rdf-schema#label";
+
+/// Interchange formats of `export`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// One statement per fact over skolem IRIs, plus an `rdfs:label` per IRI
+    NTriples,
+    /// `subject,predicate,object,score` with the fact confidence as score
+    Csv,
+    /// One fact per line, then one line per alias; `import` reads it back
+    Jsonl { with_vectors: bool },
+}
+
+/// One line of the JSONL export
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+enum ExportLine {
+    Fact {
+        #[serde(flatten)]
+        fact: Fact,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        embedding: Option<Vec<f32>>,
+    },
+    Alias { alias: String, canonical: String },
+}
+
+/// Share of the relation-model score in `score_triple`
+pub const DEFAULT_RELATION_WEIGHT: f32 = 0.5;
+
+/// Where a fact came from
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Provenance {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+}
+
+impl Provenance {
+    pub fn new() -> Self { Self::default() }
+    
+    pub fn with_source(mut self, source: &str) -> Self {
+        self.source = Some(source.to_string());
+        self
+    }
+    
+    pub fn with_confidence(mut self, confidence: f64) -> Self {
+        self.confidence = Some(confidence);
+        self
+    }
+}
+
+/// A stored triple, its index id and provenance
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Fact {
+    pub id: u64,
+    #[serde(flatten)]
+    pub triple: Triple,
+    #[serde(flatten)]
+    pub provenance: Provenance,
+}
+
+/// Which spelling of a merged entity the facts keep
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CanonicalForm {
+    /// The form in the most facts; ties go to the longer form
+    #[default]
+    MostFrequent,
+    /// The longest form; ties go to the more frequent form
+    Longest,
+}
+
+/// Spellings of one entity: `aliases` merge into `canonical`
+#[derive(Clone, Debug, PartialEq)]
+pub struct MergeGroup {
+    pub canonical: String,
+    /// Sorted
+    pub aliases: Vec<String>,
+}
+
+/// Bounds of `retrieve_subgraph_with` expansion
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubgraphOptions {
+    /// Facts in the result at most, seeds included
+    pub max_facts: usize,
+    /// New facts one entity may add per hop, the most relevant first
+    pub fan_out: usize,
+}
+
+impl Default for SubgraphOptions {
+    fn default() -> Self { Self { max_facts: 50, fan_out: 10 } }
+}
+
+impl SubgraphOptions {
+    pub fn with_max_facts(mut self, n: usize) -> Self {
+        self.max_facts = n;
+        self
+    }
+    
+    pub fn with_fan_out(mut self, n: usize) -> Self {
+        self.fan_out = n;
+        self
+    }
+}
+
+/// Facts around a question, most relevant first, and the entities they touch
+#[derive(Clone, Debug, PartialEq)]
+pub struct Subgraph<'a> {
+    /// Each fact once, with its cosine to the question
+    pub facts: Vec<(&'a Fact, f32)>,
+    /// Subjects and objects of `facts`
+    pub entities: BTreeSet<&'a str>,
+}
+
+pub struct TripleStore {
+    provider: Box<dyn EmbeddingProvider>,
+    templates: TemplateRegistry,
+    canonical_form: CanonicalForm,
+    relations: Option<RelationModel>,
+    relation_weight: f32,
+    index: CrystalIndex,
+    facts: HashMap<u64, Fact>,
+    ids: HashMap<Triple, u64>,
+    /// Merged spelling → canonical form
+    aliases: BTreeMap<String, String>,
+    next_id: u64,
+}
+
+impl TripleStore {
+    pub fn new(provider: impl EmbeddingProvider + 'static) -> Self {
+        let index = CrystalIndex::new(provider.dimensions());
+        Self {
+            provider: Box::new(provider),
+            templates: TemplateRegistry::default(),
+            canonical_form: CanonicalForm::default(),
+            relations: None,
+            relation_weight: DEFAULT_RELATION_WEIGHT,
+            index,
+            facts: HashMap::new(),
+            ids: HashMap::new(),
+            aliases: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+    
+    /// Verbalize triples with `templates`; see `set_templates` for a store with facts
+    pub fn with_templates(mut self, templates: TemplateRegistry) -> Self {
+        self.templates = templates;
+        self
+    }
+    
+    pub fn templates(&self) -> &TemplateRegistry { &self.templates }
+    
+    /// Switch to `templates`, embedding again the facts whose predicate's
+    /// template changed (by `TemplateRegistry::fingerprint`); returns how many.
+    ///
+    /// On error the store keeps its old templates and vectors.
+    pub fn set_templates(&mut self, templates: TemplateRegistry) -> Result<usize, EmbedError> {
+        let stale: Vec<Fact> = self.facts().into_iter()
+            .filter(|f| templates.fingerprint(&f.triple.predicate) != self.templates.fingerprint(&f.triple.predicate))
+            .cloned()
+            .collect();
+        let old = std::mem::replace(&mut self.templates, templates);
+        let triples: Vec<&Triple> = stale.iter().map(|f| &f.triple).collect();
+        let embeddings = match self.embed_facts(&triples) {
+            Ok(embeddings) => embeddings,
+            Err(e) => {
+                self.templates = old;
+                return Err(e);
+            }
+        };
+        for (fact, embedding) in stale.iter().zip(&embeddings) {
+            self.index.remove(fact.id);
+            self.index.add_with_metadata(fact.id, embedding, metadata(fact)).map_err(EmbedError::InvalidInput)?;
+        }
+        Ok(stale.len())
+    }
+    
+    pub fn with_canonical_form(mut self, form: CanonicalForm) -> Self {
+        self.canonical_form = form;
+        self
+    }
+    
+    /// Use `model` in `score_triple`; it must be fitted with this store's provider
+    pub fn with_relations(mut self, model: RelationModel) -> Self {
+        self.relations = Some(model);
+        self
+    }
+    
+    /// Share of the relation-model score in `score_triple`, clamped to [0, 1]
+    pub fn with_relation_weight(mut self, weight: f32) -> Self {
+        self.relation_weight = weight.clamp(0.0, 1.0);
+        self
+    }
+    
+    pub fn len(&self) -> usize { self.facts.len() }
+    
+    pub fn is_empty(&self) -> bool { self.facts.is_empty() }
+    
+    pub fn get(&self, id: u64) -> Option<&Fact> { self.facts.get(&id) }
+    
+    /// All facts in id order
+    pub fn facts(&self) -> Vec<&Fact> {
+        let mut facts: Vec<&Fact> = self.facts.values().collect();
+        facts.sort_by_key(|f| f.id);
+        facts
+    }
+    
+    /// Id of an exact triple, if stored (after alias resolution)
+    pub fn id_of(&self, triple: &Triple) -> Option<u64> { self.ids.get(&self.canonical_triple(triple)).copied() }
+    
+    /// Canonical form of an entity spelling; unmerged spellings are their own
+    pub fn canonical<'a>(&'a self, entity: &'a str) -> &'a str {
+        self.aliases.get(entity).map_or(entity, String::as_str)
+    }
+    
+    /// Merged spellings and their canonical forms, sorted by spelling
+    pub fn aliases(&self) -> impl Iterator<Item = (&str, &str)> + '_ {
+        self.aliases.iter().map(|(alias, canonical)| (alias.as_str(), canonical.as_str()))
+    }
+    
+    /// Store one fact; an exact repeat keeps the first provenance and returns its id
+    pub fn insert(&mut self, triple: Triple, provenance: Provenance) -> Result<u64, EmbedError> {
+        let triple = self.canonical_triple(&triple);
+        if let Some(id) = self.id_of(&triple) {
+            return Ok(id);
+        }
+        self.extend(vec![(triple.clone(), provenance)])?;
+        Ok(self.ids[&triple])
+    }
+    
+    /// Store many facts in one embedding batch; returns how many were new
+    pub fn extend(&mut self, facts: Vec<(Triple, Provenance)>) -> Result<usize, EmbedError> {
+        let mut new: Vec<(Triple, Provenance)> = Vec::new();
+        for (triple, provenance) in facts {
+            let triple = self.canonical_triple(&triple);
+            if !self.ids.contains_key(&triple) && !new.iter().any(|(t, _)| *t == triple) {
+                new.push((triple, provenance));
+            }
+        }
+        let triples: Vec<&Triple> = new.iter().map(|(t, _)| t).collect();
+        let embeddings = self.embed_facts(&triples)?;
+        
+        let added = new.len();
+        for ((triple, provenance), embedding) in new.into_iter().zip(&embeddings) {
+            let fact = Fact { id: self.next_id, triple, provenance };
+            self.index.add_with_metadata(fact.id, embedding, metadata(&fact)).map_err(EmbedError::InvalidInput)?;
+            self.add_fact(fact);
+        }
+        Ok(added)
+    }
+    
+    /// Forget a fact; returns false if `id` was not stored
+    pub fn remove(&mut self, id: u64) -> bool {
+        let Some(fact) = self.facts.remove(&id) else { return false };
+        self.ids.remove(&fact.triple);
+        self.index.remove(id)
+    }
+    
+    /// Facts whose subject is exactly `subject` (or its canonical form), in insertion order
+    pub fn facts_about(&self, subject: &str) -> Vec<&Fact> {
+        let subject = self.canonical(subject);
+        let mut facts: Vec<&Fact> = self.facts.values().filter(|f| f.triple.subject == subject).collect();
+        facts.sort_by_key(|f| f.id);
+        facts
+    }
+    
+    /// Top-k facts for a question or statement, best first
+    pub fn query_text(&self, query: &str, k: usize) -> Result<Vec<(&Fact, f32)>, EmbedError> {
+        let embedding = self.provider.embed_batch_with(&[query], &EmbedOptions::query())?
+            .pop()
+            .ok_or(EmbedError::Mismatch { expected: 1, got: 0 })?;
+        Ok(self.hits(self.index.search(&embedding, k)))
+    }
+    
+    /// `retrieve_subgraph_with` under the default bounds
+    pub fn retrieve_subgraph(&self, question: &str, k: usize, hops: usize) -> Result<Subgraph<'_>, EmbedError> {
+        self.retrieve_subgraph_with(question, k, hops, &SubgraphOptions::default())
+    }
+    
+    /// The top-k facts for `question`, expanded `hops` times along shared
+    /// subjects and objects
+    ///
+    /// Each hop adds, for every entity reached by the previous hop, up to
+    /// `fan_out` of its not yet included facts, the most relevant to the
+    /// question first, until `max_facts` are collected.
+    pub fn retrieve_subgraph_with(&self, question: &str, k: usize, hops: usize, options: &SubgraphOptions)
+                                  -> Result<Subgraph<'_>, EmbedError> {
+        let embedding = self.provider.embed_batch_with(&[question], &EmbedOptions::query())?
+            .pop()
+            .ok_or(EmbedError::Mismatch { expected: 1, got: 0 })?;
+        let relevance = |fact: &Fact| self.index.get(fact.id).map_or(0.0, |v| cosine(&embedding, v));
+        
+        let mut neighbours: HashMap<&str, Vec<&Fact>> = HashMap::new();
+        for fact in self.facts() {
+            neighbours.entry(&fact.triple.subject).or_default().push(fact);
+            if fact.triple.object != fact.triple.subject {
+                neighbours.entry(&fact.triple.object).or_default().push(fact);
+            }
+        }
+        
+        let mut included: HashSet<u64> = HashSet::new();
+        let mut facts: Vec<(&Fact, f32)> = Vec::new();
+        let mut entities: BTreeSet<&str> = BTreeSet::new();
+        let mut frontier: Vec<&str> = Vec::new();
+        for (fact, score) in self.hits(self.index.search(&embedding, k.min(options.max_facts))) {
+            included.insert(fact.id);
+            facts.push((fact, score));
+        }
+        for (fact, _) in &facts {
+            for entity in [fact.triple.subject.as_str(), fact.triple.object.as_str()] {
+                if entities.insert(entity) {
+                    frontier.push(entity);
+                }
+            }
+        }
+        
+        for _ in 0..hops {
+            let mut next: Vec<&str> = Vec::new();
+            for entity in frontier {
+                let mut candidates: Vec<(&Fact, f32)> = neighbours.get(entity).into_iter().flatten()
+                    .filter(|fact| !included.contains(&fact.id))
+                    .map(|fact| (*fact, relevance(fact)))
+                    .collect();
+                candidates.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.id.cmp(&b.0.id)));
+                for (fact, score) in candidates.into_iter().take(options.fan_out) {
+                    if facts.len() >= options.max_facts {
+                        break;
+                    }
+                    included.insert(fact.id);
+                    facts.push((fact, score));
+                    for entity in [fact.triple.subject.as_str(), fact.triple.object.as_str()] {
+                        if entities.insert(entity) {
+                            next.push(entity);
+                        }
+                    }
+                }
+            }
+            frontier = next;
+        }
+        
+        facts.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.id.cmp(&b.0.id)));
+        Ok(Subgraph { facts, entities })
+    }
+    
+    /// One verbalized sentence per line, in the subgraph's order, for prompts
+    pub fn render(&self, subgraph: &Subgraph) -> String {
+        subgraph.facts.iter()
+            .map(|(fact, _)| self.templates.verbalize(&fact.triple) + "\n")
+            .collect()
+    }
+    
+    /// Top-k stored facts most similar to `triple`, excluding `triple` itself
+    pub fn similar_triples(&self, triple: &Triple, k: usize) -> Result<Vec<(&Fact, f32)>, EmbedError> {
+        let text = self.templates.verbalize(triple);
+        let embedding = self.provider.embed_batch_with(&[text.as_str()], &EmbedOptions::passage())?
+            .pop()
+            .ok_or(EmbedError::Mismatch { expected: 1, got: 0 })?;
+        let own = self.id_of(triple);
+        let mut hits = self.index.search(&embedding, k + own.is_some() as usize);
+        hits.retain(|&(id, _)| Some(id) != own);
+        hits.truncate(k);
+        Ok(self.hits(hits))
+    }
+    
+    /// Plausibility of `triple` in [0, 1]
+    ///
+    /// The similarity part is the best cosine between the verbalized triple
+    /// and a stored fact with the same predicate (0 without such facts). With
+    /// a relation model that knows the predicate, the score blends in
+    /// `distance_score(|embed(o) - (embed(s) + offset(p))|)` at the relation weight.
+    pub fn score_triple(&self, triple: &Triple) -> Result<f32, EmbedError> {
+        let scores = self.plausibility(&triple.subject, &triple.predicate, &[triple.object.as_str()])?;
+        Ok(scores[0])
+    }
+    
+    /// `candidate_objects` as objects of `(subject, predicate)`, best first,
+    /// as (candidate index, `score_triple` score)
+    pub fn rank_candidates(&self, subject: &str, predicate: &str, candidate_objects: &[&str])
+                           -> Result<Vec<(usize, f32)>, EmbedError> {
+        let mut ranked: Vec<(usize, f32)> = self.plausibility(subject, predicate, candidate_objects)?
+            .into_iter()
+            .enumerate()
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        Ok(ranked)
+    }
+    
+    fn plausibility(&self, subject: &str, predicate: &str, objects: &[&str]) -> Result<Vec<f32>, EmbedError> {
+        if objects.is_empty() {
+            return Ok(vec![]);
+        }
+        let subject = self.canonical(subject);
+        let triples: Vec<Triple> = objects.iter().map(|o| Triple::new(subject, predicate, self.canonical(o))).collect();
+        let embeddings = self.embed_facts(&triples.iter().collect::<Vec<_>>())?;
+        let same_predicate = |m: &Metadata| m.get_str("predicate") == Some(predicate);
+        let mut scores: Vec<f32> = embeddings.iter()
+            .map(|e| self.index.search_filtered(e, 1, Some(&same_predicate)).first().map_or(0.0, |&(_, c)| cosine_score(c)))
+            .collect();
+        
+        let Some(model) = self.relations.as_ref().filter(|m| m.offset(predicate).is_ok()) else { return Ok(scores) };
+        let mut texts = vec![subject];
+        texts.extend(triples.iter().map(|t| t.object.as_str()));
+        let entities = self.provider.embed_batch(&texts)?;
+        if entities.len() != texts.len() {
+            return Err(EmbedError::Mismatch { expected: texts.len(), got: entities.len() });
+        }
+        let target = model.target(&entities[0], predicate).map_err(|e| match e {
+            RelationError::Embed(e) => e,
+            e => EmbedError::InvalidInput(e.to_string()),
+        })?;
+        for (score, object) in scores.iter_mut().zip(&entities[1..]) {
+            let residual: Vec<f32> = object.iter().zip(&target).map(|(o, t)| o - t).collect();
+            *score = (1.0 - self.relation_weight) * *score + self.relation_weight * distance_score(norm(&residual));
+        }
+        Ok(scores)
+    }
+    
+    /// Groups of subject and object spellings that would merge at `threshold`
+    ///
+    /// Every distinct entity is embedded once; spellings with cosine at least
+    /// `threshold` join the same group (single linkage), so a chain of close
+    /// pairs merges as one. Nothing changes until `apply_merges`.
+    pub fn preview_entities(&self, threshold: f32) -> Result<Vec<MergeGroup>, EmbedError> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        let mut entities: Vec<&str> = Vec::new();
+        for fact in self.facts() {
+            for entity in [fact.triple.subject.as_str(), fact.triple.object.as_str()] {
+                let count = counts.entry(entity).or_insert_with(|| {
+                    entities.push(entity);
+                    0
+                });
+                *count += 1;
+            }
+        }
+        if entities.len() < 2 {
+            return Ok(vec![]);
+        }
+        let options = EmbedOptions::default().with_task(Task::TextMatching);
+        let embeddings = self.provider.embed_batch_with(&entities, &options)?;
+        if embeddings.len() != entities.len() {
+            return Err(EmbedError::Mismatch { expected: entities.len(), got: embeddings.len() });
+        }
+        
+        let mut roots: Vec<usize> = (0..entities.len()).collect();
+        fn root(roots: &mut [usize], mut i: usize) -> usize {
+            while roots[i] != i {
+                roots[i] = roots[roots[i]];
+                i = roots[i];
+            }
+            i
+        }
+        for i in 0..entities.len() {
+            for j in i + 1..entities.len() {
+                if cosine(&embeddings[i], &embeddings[j]) >= threshold {
+                    let (a, b) = (root(&mut roots, i), root(&mut roots, j));
+                    roots[a.max(b)] = a.min(b);
+                }
+            }
+        }
+        let mut members: BTreeMap<usize, Vec<&str>> = BTreeMap::new();
+        for (i, entity) in entities.iter().enumerate() {
+            members.entry(root(&mut roots, i)).or_default().push(entity);
+        }
+        
+        let rank = |e: &&str| {
+            let (count, len) = (counts[e], e.chars().count());
+            match self.canonical_form {
+                CanonicalForm::MostFrequent => (count, len),
+                CanonicalForm::Longest => (len, count),
+            }
+        };
+        let mut groups: Vec<MergeGroup> = members.into_values()
+            .filter(|group| group.len() > 1)
+            .map(|mut group| {
+                // Best rank first; ties by spelling, for a stable choice
+                group.sort_by(|a, b| rank(b).cmp(&rank(a)).then(a.cmp(b)));
+                let canonical = group.remove(0).to_string();
+                let mut aliases: Vec<String> = group.into_iter().map(str::to_string).collect();
+                aliases.sort();
+                MergeGroup { canonical, aliases }
+            })
+            .collect();
+        groups.sort_by(|a, b| a.canonical.cmp(&b.canonical));
+        Ok(groups)
+    }
+    
+    /// Rewrite facts to the canonical forms of `groups` and record the aliases;
+    /// returns how many facts changed
+    ///
+    /// Facts that become exact repeats of another fact are dropped, keeping
+    /// the earlier fact's provenance.
+    pub fn apply_merges(&mut self, groups: &[MergeGroup]) -> Result<usize, EmbedError> {
+        let mut aliases = self.aliases.clone();
+        for group in groups {
+            let canonical = aliases.get(&group.canonical).cloned().unwrap_or_else(|| group.canonical.clone());
+            for alias in group.aliases.iter().filter(|a| **a != canonical) {
+                for target in aliases.values_mut().filter(|t| *t == alias) {
+                    *target = canonical.clone();
+                }
+                aliases.insert(alias.clone(), canonical.clone());
+            }
+        }
+        let resolve = |e: &str| aliases.get(e).cloned().unwrap_or_else(|| e.to_string());
+        
+        // Plan first, so a failed request leaves the store unchanged
+        let mut ids: HashMap<Triple, u64> = self.ids.clone();
+        let mut rewrites: Vec<(u64, Triple)> = Vec::new();
+        let mut dropped: Vec<u64> = Vec::new();
+        for fact in self.facts() {
+            let triple = Triple::new(resolve(&fact.triple.subject), fact.triple.predicate.as_str(), resolve(&fact.triple.object));
+            if triple == fact.triple {
+                continue;
+            }
+            ids.remove(&fact.triple);
+            if ids.contains_key(&triple) {
+                dropped.push(fact.id);
+            } else {
+                ids.insert(triple.clone(), fact.id);
+                rewrites.push((fact.id, triple));
+            }
+        }
+        let triples: Vec<&Triple> = rewrites.iter().map(|(_, t)| t).collect();
+        let embeddings = self.embed_facts(&triples)?;
+        
+        for id in &dropped {
+            self.facts.remove(id);
+            self.index.remove(*id);
+        }
+        for ((id, triple), embedding) in rewrites.into_iter().zip(&embeddings) {
+            let fact = self.facts.get_mut(&id).unwrap();
+            fact.triple = triple;
+            self.index.remove(id);
+            self.index.add_with_metadata(id, embedding, metadata(fact)).map_err(EmbedError::InvalidInput)?;
+        }
+        self.ids = ids;
+        self.aliases = aliases;
+        Ok(embeddings.len() + dropped.len())
+    }
+    
+    /// `preview_entities` then `apply_merges`; returns the applied groups
+    pub fn resolve_entities(&mut self, threshold: f32) -> Result<Vec<MergeGroup>, EmbedError> {
+        let groups = self.preview_entities(threshold)?;
+        self.apply_merges(&groups)?;
+        Ok(groups)
+    }
+    
+    /// Write the index to `path`, the facts to the JSONL sidecar, the aliases and the templates
+    pub fn save(&mut self, path: &str) -> Result<(), String> {
+        self.index.save(path)?;
+        let sidecar = format!("{}{}", path, SIDECAR_SUFFIX);
+        atomic_write(&sidecar, |file| {
+            let mut out = BufWriter::new(file);
+            for fact in self.facts() {
+                writeln!(out, "{}", serde_json::to_string(fact)?)?;
+            }
+            out.flush()
+        }).map_err(|e| format!("Write failed for {}: {}", sidecar, e))?;
+        
+        let aliases = format!("{}{}", path, ALIASES_SUFFIX);
+        let json = serde_json::to_string(&self.aliases).map_err(|e| e.to_string())?;
+        atomic_write(&aliases, |file| file.write_all(json.as_bytes())).map_err(|e| format!("Write failed for {}: {}", aliases, e))?;
+        
+        let templates = format!("{}{}", path, TEMPLATES_SUFFIX);
+        atomic_write(&templates, |file| file.write_all(self.templates.to_json().as_bytes()))
+            .map_err(|e| format!("Write failed for {}: {}", templates, e))
+    }
+    
+    /// Read a store written by `save`; `provider` must embed like the one that built it.
+    ///
+    /// A missing alias table means no aliases, missing templates the default ones.
+    pub fn load(path: &str, provider: impl EmbeddingProvider + 'static) -> Result<Self, String> {
+        let index = CrystalIndex::load(path)?;
+        let sidecar = format!("{}{}", path, SIDECAR_SUFFIX);
+        let file = File::open(&sidecar).map_err(|e| format!("Cannot open {}: {}", sidecar, e))?;
+        let mut store = TripleStore::new(provider);
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| format!("Read failed for {}: {}", sidecar, e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fact: Fact = serde_json::from_str(&line).map_err(|e| format!("{} line {}: {}", sidecar, i + 1, e))?;
+            if !index.contains(fact.id) {
+                return Err(format!("{} line {}: fact {} is not in the index", sidecar, i + 1, fact.id));
+            }
+            store.add_fact(fact);
+        }
+        if store.facts.len() != index.len() {
+            return Err(format!("{} has {} facts but the index has {} entries", sidecar, store.facts.len(), index.len()));
+        }
+        store.index = index;
+        
+        let aliases = format!("{}{}", path, ALIASES_SUFFIX);
+        if let Ok(json) = std::fs::read_to_string(&aliases) {
+            store.aliases = serde_json::from_str(&json).map_err(|e| format!("Cannot parse {}: {}", aliases, e))?;
+        }
+        let templates = format!("{}{}", path, TEMPLATES_SUFFIX);
+        if let Ok(json) = std::fs::read_to_string(&templates) {
+            store.templates = TemplateRegistry::from_json(&json).map_err(|e| format!("{}: {}", templates, e))?;
+        }
+        Ok(store)
+    }
+    
+    /// Write all facts to `path` as `format`
+    pub fn export(&self, path: &str, format: Format) -> Result<(), String> {
+        self.export_with(path, format, ExportPrecision::Full)
+    }
+    
+    /// `export` with scores and vectors written at `precision`
+    pub fn export_with(&self, path: &str, format: Format, precision: ExportPrecision) -> Result<(), String> {
+        atomic_write(path, |file| {
+            let mut out = BufWriter::new(file);
+            match format {
+                Format::NTriples => self.write_ntriples(&mut out),
+                Format::Csv => self.write_csv(&mut out, precision),
+                Format::Jsonl { with_vectors } => self.write_jsonl(&mut out, with_vectors, precision),
+            }?;
+            out.flush()
+        }).map_err(|e| format!("Write failed for {}: {}", path, e))
+    }
+    
+    /// Read a `Format::Jsonl` export; facts exported without vectors are embedded again
+    pub fn import(path: &str, provider: impl EmbeddingProvider + 'static) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Cannot open {}: {}", path, e))?;
+        let mut store = TripleStore::new(provider);
+        let mut unembedded: Vec<Fact> = Vec::new();
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| format!("Read failed for {}: {}", path, e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let line: ExportLine = serde_json::from_str(&line).map_err(|e| format!("{} line {}: {}", path, i + 1, e))?;
+            match line {
+                ExportLine::Fact { fact, .. } if store.facts.contains_key(&fact.id) || unembedded.iter().any(|f| f.id == fact.id) => {
+                    return Err(format!("{} line {}: duplicate fact id {}", path, i + 1, fact.id));
+                }
+                ExportLine::Fact { fact, embedding: Some(embedding) } => {
+                    if store.index.is_empty() && embedding.len() != store.index.dims() {
+                        store.index = CrystalIndex::new(embedding.len());
+                    }
+                    store.index.add_with_metadata(fact.id, &embedding, metadata(&fact))
+                        .map_err(|e| format!("{} line {}: {}", path, i + 1, e))?;
+                    store.add_fact(fact);
+                }
+                ExportLine::Fact { fact, embedding: None } => unembedded.push(fact),
+                ExportLine::Alias { alias, canonical } => {
+                    store.aliases.insert(alias, canonical);
+                }
+            }
+        }
+        let triples: Vec<&Triple> = unembedded.iter().map(|f| &f.triple).collect();
+        let embeddings = store.embed_facts(&triples).map_err(|e| format!("Embedding {} failed: {}", path, e))?;
+        for (fact, embedding) in unembedded.into_iter().zip(&embeddings) {
+            store.index.add_with_metadata(fact.id, embedding, metadata(&fact)).map_err(|e| format!("{}: {}", path, e))?;
+            store.add_fact(fact);
+        }
+        Ok(store)
+    }
+    
+    fn write_ntriples(&self, out: &mut impl Write) -> std::io::Result<()> {
+        let mut labelled: HashSet<&str> = HashSet::new();
+        for fact in self.facts() {
+            let Triple { subject, predicate, object } = &fact.triple;
+            writeln!(out, "{} {} {} .", skolem_iri(subject), skolem_iri(predicate), skolem_iri(object))?;
+            for name in [subject, predicate, object] {
+                if labelled.insert(name) {
+                    writeln!(out, "{} <{}> {} .", skolem_iri(name), RDFS_LABEL, ntriples_literal(name))?;
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    fn write_csv(&self, out: &mut impl Write, precision: ExportPrecision) -> std::io::Result<()> {
+        writeln!(out, "subject,predicate,object,score")?;
+        for fact in self.facts() {
+            // Confidences are f64; only a reduced precision narrows them
+            let score = match fact.provenance.confidence {
+                Some(c) if precision != ExportPrecision::Full => precision.format(c as f32).map_err(std::io::Error::other)?,
+                Some(c) => c.to_string(),
+                None => String::new(),
+            };
+            writeln!(out, "{},{},{},{}", csv_field(&fact.triple.subject), csv_field(&fact.triple.predicate),
+                     csv_field(&fact.triple.object), score)?;
+        }
+        Ok(())
+    }
+    
+    fn write_jsonl(&self, out: &mut impl Write, with_vectors: bool, precision: ExportPrecision) -> std::io::Result<()> {
+        for fact in self.facts() {
+            let embedding = match with_vectors {
+                true => Some(self.index.get(fact.id).unwrap_or_default().iter()
+                    .map(|&x| precision.round(x))
+                    .collect::<Result<Vec<f32>, String>>()
+                    .map_err(|e| std::io::Error::other(format!("Fact {}: {}", fact.id, e)))?),
+                false => None,
+            };
+            let line = ExportLine::Fact { fact: fact.clone(), embedding };
+            writeln!(out, "{}", serde_json::to_string(&line)?)?;
+        }
+        for (alias, canonical) in &self.aliases {
+            let line = ExportLine::Alias { alias: alias.clone(), canonical: canonical.clone() };
+            writeln!(out, "{}", serde_json::to_string(&line)?)?;
+        }
+        Ok(())
+    }
+    
+    fn canonical_triple(&self, triple: &Triple) -> Triple {
+        Triple::new(self.canonical(&triple.subject), triple.predicate.as_str(), self.canonical(&triple.object))
+    }
+    
+    /// Passage embeddings of the verbalized triples
+    fn embed_facts(&self, triples: &[&Triple]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        if triples.is_empty() {
+            return Ok(vec![]);
+        }
+        let texts: Vec<String> = triples.iter().map(|t| self.templates.verbalize(t)).collect();
+        let refs: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let embeddings = self.provider.embed_batch_with(&refs, &EmbedOptions::passage())?;
+        if embeddings.len() != triples.len() {
+            return Err(EmbedError::Mismatch { expected: triples.len(), got: embeddings.len() });
+        }
+        Ok(embeddings)
+    }
+    
+    fn add_fact(&mut self, fact: Fact) {
+        self.next_id = self.next_id.max(fact.id + 1);
+        self.ids.insert(fact.triple.clone(), fact.id);
+        self.facts.insert(fact.id, fact);
+    }
+    
+    fn hits(&self, hits: Vec<(u64, f32)>) -> Vec<(&Fact, f32)> {
+        hits.into_iter().filter_map(|(id, score)| Some((self.facts.get(&id)?, score))).collect()
+    }
+}
+
+/// `<SKOLEM_BASE + name>`, with everything but unreserved ASCII percent-encoded
+fn skolem_iri(name: &str) -> String {
+    let mut iri = format!("<{}", SKOLEM_BASE);
+    for b in name.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            iri.push(b as char);
+        } else {
+            iri.push_str(&format!("%{:02X}", b));
+        }
+    }
+    iri.push('>');
+    iri
+}
+
+/// Quoted N-Triples string literal; non-ASCII stays UTF-8, which N-Triples allows
+fn ntriples_literal(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Index metadata of a fact, for filtered search
+fn metadata(fact: &Fact) -> Metadata {
+    let mut metadata = Metadata::new()
+        .with("subject", fact.triple.subject.as_str())
+        .with("predicate", fact.triple.predicate.as_str())
+        .with("object", fact.triple.object.as_str());
+    if let Some(source) = &fact.provenance.source {
+        metadata = metadata.with("source", source.as_str());
+    }
+    if let Some(confidence) = fact.provenance.confidence {
+        metadata = metadata.with("confidence", confidence);
+    }
+    metadata
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pseudo::PseudoEmbedder;
+    
+    fn facts() -> Vec<(Triple, Provenance)> {
+        let source = |s: &str| Provenance::new().with_source(s).with_confidence(0.9);
+        vec![
+            (Triple::new("Wile E. Coyote", "founded", "Acme Corporation"), source("wiki:acme")),
+            (Triple::new("Acme Corporation", "makes", "anvils and rocket skates"), source("wiki:acme")),
+            (Triple::new("Marie Curie", "discovered", "polonium"), source("wiki:curie")),
+            (Triple::new("Marie Curie", "won", "the Nobel Prize in Physics"), source("wiki:curie")),
+            (Triple::new("The Danube", "flows into", "the Black Sea"), source("wiki:danube")),
+        ]
+    }
+    
+    fn store() -> TripleStore {
+        let mut store = TripleStore::new(PseudoEmbedder::new(256));
+        assert_eq!(store.extend(facts()).unwrap(), 5);
+        store
+    }
+    
+    #[test]
+    fn test_queries_rank_expected_facts_first() {
+        let store = store();
+        let top = |query| store.query_text(query, 3).unwrap()[0].0.triple.clone();
+        assert_eq!(top("who founded Acme Corporation"), facts()[0].0);
+        assert_eq!(top("what did Marie Curie discover"), facts()[2].0);
+        assert_eq!(top("where does the Danube flow"), facts()[4].0);
+        
+        let about: Vec<&str> = store.facts_about("Marie Curie").iter().map(|f| f.triple.predicate.as_str()).collect();
+        assert_eq!(about, ["discovered", "won"]);
+        assert!(store.facts_about("marie curie").is_empty());
+        
+        // The queried triple itself is excluded
+        let similar = store.similar_triples(&facts()[2].0, 2).unwrap();
+        assert_eq!(similar.len(), 2);
+        assert_eq!(similar[0].0.triple, facts()[3].0);
+    }
+    
+    #[test]
+    fn test_repeats_are_stored_once() {
+        let mut store = store();
+        let first = store.id_of(&facts()[0].0).unwrap();
+        let repeat = Provenance::new().with_source("other");
+        assert_eq!(store.insert(facts()[0].0.clone(), repeat.clone()).unwrap(), first);
+        assert_eq!(store.extend(vec![(facts()[1].0.clone(), repeat)]).unwrap(), 0);
+        assert_eq!(store.len(), 5);
+        assert_eq!(store.get(first).unwrap().provenance.source.as_deref(), Some("wiki:acme"));
+        
+        let id = store.insert(Triple::new("Acme Corporation", "sells", "dehydrated boulders"), Provenance::new()).unwrap();
+        assert_eq!(store.len(), 6);
+        assert!(store.remove(id));
+        assert!(store.id_of(&Triple::new("Acme Corporation", "sells", "dehydrated boulders")).is_none());
+    }
+    
+    #[test]
+    fn test_resolve_entities() {
+        let mut store = TripleStore::new(PseudoEmbedder::new(256));
+        let none = Provenance::new;
+        store.extend(vec![
+            (Triple::new("Marie Curie", "discovered", "polonium"), none()),
+            (Triple::new("Marie Curie", "born in", "Warsaw"), none()),
+            (Triple::new("Marie Skłodowska Curie", "discovered", "polonium"), none().with_source("late")),
+            (Triple::new("Marie Skłodowska Curie", "won", "the Nobel Prize"), none()),
+            (Triple::new("Pierre Curie", "married", "Marie Curie"), none()),
+            (Triple::new("Marie Antoinette", "born in", "Vienna"), none()),
+        ]).unwrap();
+        
+        // Preview changes nothing; related but distinct entities stay apart
+        let groups = store.preview_entities(0.75).unwrap();
+        let expected = MergeGroup { canonical: "Marie Curie".to_string(), aliases: vec!["Marie Skłodowska Curie".to_string()] };
+        assert_eq!(groups, std::slice::from_ref(&expected));
+        assert_eq!(store.facts_about("Marie Curie").len(), 2);
+        let mut longest = TripleStore::new(PseudoEmbedder::new(256)).with_canonical_form(CanonicalForm::Longest);
+        longest.extend(vec![(Triple::new("Marie Curie", "a", "b"), none()), (Triple::new("Marie Skłodowska Curie", "c", "d"), none())]).unwrap();
+        assert_eq!(longest.preview_entities(0.75).unwrap()[0].canonical, "Marie Skłodowska Curie");
+        
+        assert_eq!(store.resolve_entities(0.75).unwrap(), [expected]);
+        // The rewritten "discovered" fact repeats an earlier one and is dropped
+        assert_eq!(store.len(), 5);
+        let about: Vec<&str> = store.facts_about("Marie Skłodowska Curie").iter().map(|f| f.triple.predicate.as_str()).collect();
+        assert_eq!(about, ["discovered", "born in", "won"]);
+        assert_eq!(store.facts_about("Marie Curie")[0].provenance.source, None);
+        assert_eq!(store.facts_about("Marie Antoinette").len(), 1);
+        
+        // Later inserts go through the aliases, and queries see rewritten facts
+        let id = store.insert(Triple::new("Marie Skłodowska Curie", "won", "the Nobel Prize"), none()).unwrap();
+        assert_eq!(store.get(id).unwrap().triple.subject, "Marie Curie");
+        assert_eq!(store.len(), 5);
+        assert_eq!(store.query_text("which prize Marie Curie won", 1).unwrap()[0].0.id, id);
+    }
+    
+    #[test]
+    fn test_true_objects_outrank_distractors() {
+        let names = ["alpha", "bravo", "charlie", "delta", "echo", "foxtrot", "golf", "hotel"];
+        let fact = |n: &str, p: &str| Triple::new(n, p, format!("{} {}", n, if p == "capital" { "city" } else { "river" }));
+        let known: Vec<Triple> = names[..6].iter().flat_map(|n| [fact(n, "capital"), fact(n, "river")]).collect();
+        let model = crate::relations::fit(&known, &PseudoEmbedder::new(256)).unwrap();
+        let mut store = TripleStore::new(PseudoEmbedder::new(256)).with_relations(model);
+        store.extend(known.into_iter().map(|t| (t, Provenance::new())).collect()).unwrap();
+        
+        for name in &names[6..] {
+            let truth = format!("{} city", name);
+            let candidates = [format!("{} river", name), "anvils".to_string(), truth.clone(), "hotel golf".to_string()];
+            let refs: Vec<&str> = candidates.iter().map(String::as_str).collect();
+            let ranked = store.rank_candidates(name, "capital", &refs).unwrap();
+            assert_eq!(ranked[0].0, 2, "{:?}", ranked);
+            assert!(ranked.iter().all(|&(_, s)| (0.0..=1.0).contains(&s)));
+            assert_eq!(store.score_triple(&Triple::new(*name, "capital", truth)).unwrap(), ranked[0].1);
+        }
+        
+        // Unknown predicates have no support
+        assert_eq!(store.with_relation_weight(0.0).score_triple(&Triple::new("golf", "anthem", "golf song")).unwrap(), 0.0);
+    }
+    
+    #[test]
+    fn test_subgraph_hops_and_fan_out() {
+        let mut store = TripleStore::new(PseudoEmbedder::new(256));
+        let mut facts = vec![
+            Triple::new("Ada", "founded", "Acme"),
+            Triple::new("Acme", "based in", "Berlin"),
+            Triple::new("Berlin", "capital of", "Germany"),
+            Triple::new("Germany", "member of", "the EU"),
+        ];
+        // Berlin is a hub
+        facts.extend((0..8).map(|i| Triple::new(format!("resident {}", i), "lives in", "Berlin")));
+        store.extend(facts.into_iter().map(|t| (t, Provenance::new())).collect()).unwrap();
+        let question = "who founded Acme";
+        let subjects = |g: &Subgraph| g.facts.iter().map(|(f, _)| f.triple.subject.clone()).collect::<Vec<_>>();
+        
+        let seeds = store.retrieve_subgraph(question, 1, 0).unwrap();
+        assert_eq!(subjects(&seeds), ["Ada"]);
+        assert_eq!(seeds.entities.iter().copied().collect::<Vec<_>>(), ["Acme", "Ada"]);
+        assert_eq!(store.render(&seeds), "Ada founded Acme\n");
+        
+        let one = store.retrieve_subgraph(question, 1, 1).unwrap();
+        assert_eq!(one.facts.len(), 2);
+        assert!(one.entities.contains("Berlin"));
+        
+        // Berlin adds at most three of its nine other facts
+        let options = SubgraphOptions::default().with_fan_out(3);
+        let two = store.retrieve_subgraph_with(question, 1, 2, &options).unwrap();
+        assert_eq!(two.facts.len(), 5);
+        assert!(!subjects(&two).contains(&"Germany".to_string()));
+        let ids: HashSet<u64> = two.facts.iter().map(|(f, _)| f.id).collect();
+        assert_eq!(ids.len(), two.facts.len());
+        assert!(two.facts.windows(2).all(|w| w[0].1 >= w[1].1));
+        
+        let all = store.retrieve_subgraph(question, 1, 3).unwrap();
+        assert_eq!(all.facts.len(), 12);
+        assert_eq!(store.retrieve_subgraph_with(question, 1, 3, &options.with_max_facts(4)).unwrap().facts.len(), 4);
+    }
+    
+    #[test]
+    fn test_export_escapes_literals() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("facts");
+        let path = path.to_str().unwrap();
+        let mut store = TripleStore::new(PseudoEmbedder::new(32));
+        store.insert(Triple::new("Say \"hi\"\nnow", "located in", "Zürich, CH\\"), Provenance::new().with_confidence(0.5)).unwrap();
+        
+        store.export(path, Format::NTriples).unwrap();
+        let nt = std::fs::read_to_string(path).unwrap();
+        let lines: Vec<&str> = nt.lines().collect();
+        assert_eq!(lines.len(), 4);
+        let (s, p, o) = ("<urn:spo-crystal:genid:Say%20%22hi%22%0Anow>", "<urn:spo-crystal:genid:located%20in>",
+                         "<urn:spo-crystal:genid:Z%C3%BCrich%2C%20CH%5C>");
+        assert_eq!(lines[0], format!("{} {} {} .", s, p, o));
+        assert_eq!(lines[1], format!("{} <{}> \"Say \\\"hi\\\"\\nnow\" .", s, RDFS_LABEL));
+        assert_eq!(lines[3], format!("{} <{}> \"Zürich, CH\\\\\" .", o, RDFS_LABEL));
+        
+        store.export(path, Format::Csv).unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(),
+                   "subject,predicate,object,score\n\"Say \"\"hi\"\"\nnow\",located in,\"Zürich, CH\\\",0.5\n");
+    }
+    
+    #[test]
+    fn test_jsonl_export_import_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("facts.jsonl");
+        let path = path.to_str().unwrap();
+        let mut store = store();
+        store.insert(Triple::new("Zoë \"Z\" Ünal", "said", "line one\nline two"), Provenance::new()).unwrap();
+        store.remove(0);
+        store.apply_merges(&[MergeGroup { canonical: "Marie Curie".to_string(), aliases: vec!["M. Curie".to_string()] }]).unwrap();
+        
+        let same = |a: &TripleStore, b: &TripleStore| {
+            assert_eq!(a.facts(), b.facts());
+            assert_eq!(a.aliases().collect::<Vec<_>>(), b.aliases().collect::<Vec<_>>());
+            for fact in a.facts() {
+                assert_eq!(a.index.get(fact.id), b.index.get(fact.id));
+            }
+        };
+        for with_vectors in [true, false] {
+            store.export(path, Format::Jsonl { with_vectors }).unwrap();
+            let imported = TripleStore::import(path, PseudoEmbedder::new(256)).unwrap();
+            same(&store, &imported);
+        }
+        
+        // Rounded vectors are smaller and still read back, close to the originals
+        store.export(path, Format::Jsonl { with_vectors: true }).unwrap();
+        let full = std::fs::metadata(path).unwrap().len();
+        store.export_with(path, Format::Jsonl { with_vectors: true }, ExportPrecision::SignificantDigits(4)).unwrap();
+        assert!(std::fs::metadata(path).unwrap().len() < full);
+        let rounded = TripleStore::import(path, PseudoEmbedder::new(256)).unwrap();
+        assert_eq!(rounded.facts(), store.facts());
+        for fact in store.facts() {
+            let (a, b) = (store.index.get(fact.id).unwrap(), rounded.index.get(fact.id).unwrap());
+            assert!(a.iter().zip(b).all(|(x, y)| (x - y).abs() <= x.abs() * 5e-4), "fact {}", fact.id);
+        }
+        
+        let line = std::fs::read_to_string(path).unwrap().lines().next().unwrap().to_string();
+        std::fs::write(path, format!("{}\n{}\n", line, line)).unwrap();
+        let Err(e) = TripleStore::import(path, PseudoEmbedder::new(256)) else { panic!("duplicate ids imported") };
+        assert!(e.contains("line 2: duplicate fact id"), "{}", e);
+    }
+    
+    #[test]
+    fn test_save_load_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("facts.idx");
+        let path = path.to_str().unwrap();
+        let mut store = store();
+        store.remove(store.id_of(&facts()[4].0).unwrap());
+        store.apply_merges(&[MergeGroup { canonical: "Acme Corporation".to_string(), aliases: vec!["Acme Corp.".to_string()] }]).unwrap();
+        store.save(path).unwrap();
+        
+        let loaded = TripleStore::load(path, PseudoEmbedder::new(256)).unwrap();
+        assert_eq!(loaded.len(), 4);
+        let fact = loaded.get(loaded.id_of(&facts()[3].0).unwrap()).unwrap();
+        assert_eq!(fact.provenance, facts()[3].1);
+        assert_eq!(loaded.query_text("who founded Acme Corporation", 1).unwrap()[0].0.triple, facts()[0].0);
+        assert_eq!(loaded.aliases().collect::<Vec<_>>(), [("Acme Corp.", "Acme Corporation")]);
+        // New ids do not reuse stored ones
+        let mut loaded = loaded;
+        let id = loaded.insert(Triple::new("a", "b", "c"), Provenance::new()).unwrap();
+        assert!(loaded.facts_about("Marie Curie").iter().all(|f| f.id != id));
+        
+        std::fs::write(format!("{}{}", path, SIDECAR_SUFFIX), "{\"id\": 99, \"subject\": \"x\"}\n").unwrap();
+        let Err(e) = TripleStore::load(path, PseudoEmbedder::new(256)) else { panic!("bad sidecar loaded") };
+        assert!(e.contains("line 1"), "{}", e);
+    }
+    
+    #[test]
+    fn test_templates_persist_and_changes_re_embed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("facts.idx");
+        let path = path.to_str().unwrap();
+        let provider = PseudoEmbedder::new(256);
+        let templates = TemplateRegistry::new().with_template("founded", "{o} was founded by {s}").unwrap();
+        let mut store = TripleStore::new(provider.clone()).with_templates(templates.clone());
+        store.extend(facts()).unwrap();
+        let id = store.id_of(&facts()[0].0).unwrap();
+        assert_eq!(store.index.get(id).unwrap(), provider.embed("Acme Corporation was founded by Wile E. Coyote"));
+        store.save(path).unwrap();
+        
+        let mut loaded = TripleStore::load(path, provider.clone()).unwrap();
+        assert_eq!(loaded.templates(), &templates);
+        let new = loaded.insert(Triple::new("Initech", "founded", "Bill"), Provenance::new()).unwrap();
+        assert_eq!(loaded.index.get(new).unwrap(), provider.embed("Bill was founded by Initech"));
+        
+        // Only the facts of the changed predicate are embedded again
+        let won = loaded.id_of(&facts()[3].0).unwrap();
+        let before = loaded.index.get(won).unwrap().to_vec();
+        let changed = templates.clone().with_template("founded", "{s} founded {o}").unwrap();
+        assert_eq!(loaded.set_templates(changed).unwrap(), 2);
+        assert_eq!(loaded.index.get(id).unwrap(), provider.embed("Wile E. Coyote founded Acme Corporation"));
+        assert_eq!(loaded.index.get(won).unwrap(), before);
+        assert_eq!(loaded.query_text("who founded Acme Corporation", 1).unwrap()[0].0.id, id);
+        assert_eq!(loaded.set_templates(loaded.templates().clone()).unwrap(), 0);
+        
+        std::fs::write(format!("{}{}", path, TEMPLATES_SUFFIX), r#"{"default":"{s}","templates":{}}"#).unwrap();
+        let Err(e) = TripleStore::load(path, provider) else { panic!("bad templates loaded") };
+        assert!(e.contains("{o} appears 0 times"), "{}", e);
+    }
+}
@@ -0,0 +1,341 @@
+//! Subject–predicate–object facts and their embeddings
+//!
+//! A `Triple` embeds three ways:
+//!
+//! - `Verbalized`: one vector for a sentence rendered from the triple,
+//!   `"{s} {p} {o}"` unless a `TemplateRegistry` has a template for the predicate;
+//! - `PerComponent`: one vector each for subject, predicate and object;
+//! - `Concatenated`: the three component vectors truncated to a third of the
+//!   embedding size (Matryoshka style) and stitched into one vector of the
+//!   provider's size, so component matches add up under cosine.
+
+use std::collections::BTreeMap;
+
+use crate::provider::{EmbedError, EmbeddingProvider};
+use crate::search::{normalize, truncate_mrl};
+
+/// Template used for predicates without one of their own
+pub const DEFAULT_TEMPLATE: &str = "{s} {p} {o}";
+
+/// One fact
+#[derive(Clone, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct Triple {
+    pub subject: String,
+    pub predicate: String,
+    pub object: String,
+}
+
+impl Triple {
+    pub fn new(subject: impl Into<String>, predicate: impl Into<String>, object: impl Into<String>) -> Self {
+        Self { subject: subject.into(), predicate: predicate.into(), object: object.into() }
+    }
+}
+
+/// How `embed_triple` turns a triple into vectors
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TripleEmbedding {
+    /// Truncated component vectors stitched into one vector
+    Concatenated,
+    /// One vector for the verbalized sentence
+    #[default]
+    Verbalized,
+    /// Separate subject, predicate and object vectors
+    PerComponent,
+}
+
+/// Vectors of one triple
+#[derive(Clone, Debug, PartialEq)]
+pub enum TripleVectors {
+    /// `Verbalized` and `Concatenated`
+    Single(Vec<f32>),
+    PerComponent { subject: Vec<f32>, predicate: Vec<f32>, object: Vec<f32> },
+}
+
+impl TripleVectors {
+    /// The single vector; `None` for per-component vectors
+    pub fn single(&self) -> Option<&[f32]> {
+        match self {
+            TripleVectors::Single(v) => Some(v),
+            TripleVectors::PerComponent { .. } => None,
+        }
+    }
+    
+    pub fn into_single(self) -> Option<Vec<f32>> {
+        match self {
+            TripleVectors::Single(v) => Some(v),
+            TripleVectors::PerComponent { .. } => None,
+        }
+    }
+}
+
+/// Per-predicate templates that render triples as sentences
+///
+/// A template substitutes `{s}` and `{o}`, each exactly once, and `{p}`
+/// anywhere; `register` refuses anything else, so a registry always renders.
+/// `"{s} was born on {o}"` reads better to an embedding model than the
+/// default `"Alice birth_date 1990"`.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TemplateRegistry {
+    default: String,
+    templates: BTreeMap<String, String>,
+}
+
+impl Default for TemplateRegistry {
+    fn default() -> Self { Self { default: DEFAULT_TEMPLATE.to_string(), templates: BTreeMap::new() } }
+}
+
+impl TemplateRegistry {
+    pub fn new() -> Self { Self::default() }
+    
+    /// Template for predicates without their own
+    pub fn with_default(mut self, template: &str) -> Result<Self, String> {
+        validate_template(template)?;
+        self.default = template.to_string();
+        Ok(self)
+    }
+    
+    /// Template for `predicate`, e.g. `"{o} was founded by {s}"`
+    pub fn with_template(mut self, predicate: &str, template: &str) -> Result<Self, String> {
+        self.register(predicate, template)?;
+        Ok(self)
+    }
+    
+    /// Set the template for `predicate`, replacing any earlier one
+    pub fn register(&mut self, predicate: &str, template: &str) -> Result<(), String> {
+        validate_template(template).map_err(|e| format!("Template for {}: {}", predicate, e))?;
+        self.templates.insert(predicate.to_string(), template.to_string());
+        Ok(())
+    }
+    
+    /// The template `predicate` renders with
+    pub fn template(&self, predicate: &str) -> &str {
+        self.templates.get(predicate).unwrap_or(&self.default)
+    }
+    
+    pub fn verbalize(&self, triple: &Triple) -> String {
+        let mut out = String::new();
+        for piece in pieces(self.template(&triple.predicate)) {
+            out.push_str(match piece {
+                Piece::Text(text) => text,
+                Piece::Subject => &triple.subject,
+                Piece::Predicate => &triple.predicate,
+                Piece::Object => &triple.object,
+            });
+        }
+        out
+    }
+    
+    /// FNV-1a of the template `predicate` renders with; a fact whose
+    /// fingerprint changes needs embedding again
+    pub fn fingerprint(&self, predicate: &str) -> u64 {
+        let mut h = 0xcbf29ce484222325u64;
+        for &b in self.template(predicate).as_bytes() {
+            h ^= b as u64;
+            h = h.wrapping_mul(0x100000001b3);
+        }
+        h
+    }
+    
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("templates always serialize")
+    }
+    
+    /// Parse `to_json` output, validating every template
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let registry: Self = serde_json::from_str(json).map_err(|e| format!("Invalid templates: {}", e))?;
+        validate_template(&registry.default).map_err(|e| format!("Default template: {}", e))?;
+        for (predicate, template) in &registry.templates {
+            validate_template(template).map_err(|e| format!("Template for {}: {}", predicate, e))?;
+        }
+        Ok(registry)
+    }
+}
+
+/// Ok if `template` has `{s}` and `{o}` exactly once and no placeholders but `{s}`, `{p}`, `{o}`
+pub fn validate_template(template: &str) -> Result<(), String> {
+    let (mut subjects, mut objects) = (0, 0);
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}') else {
+            return Err(format!("Unclosed placeholder in {:?}", template));
+        };
+        match &rest[open + 1..open + close] {
+            "s" => subjects += 1,
+            "o" => objects += 1,
+            "p" => {}
+            name => return Err(format!("Unknown placeholder {{{}}} in {:?}", name, template)),
+        }
+        rest = &rest[open + close + 1..];
+    }
+    for (name, count) in [("s", subjects), ("o", objects)] {
+        if count != 1 {
+            return Err(format!("{{{}}} appears {} times in {:?}, expected once", name, count, template));
+        }
+    }
+    Ok(())
+}
+
+enum Piece<'a> {
+    Text(&'a str),
+    Subject,
+    Predicate,
+    Object,
+}
+
+/// A validated template split at its placeholders, so substituted text is never rescanned
+fn pieces(template: &str) -> Vec<Piece<'_>> {
+    let mut out = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let close = open + rest[open..].find('}').expect("validated template");
+        out.push(Piece::Text(&rest[..open]));
+        out.push(match &rest[open + 1..close] {
+            "s" => Piece::Subject,
+            "p" => Piece::Predicate,
+            _ => Piece::Object,
+        });
+        rest = &rest[close + 1..];
+    }
+    out.push(Piece::Text(rest));
+    out
+}
+
+/// Embed one triple with the default template
+pub fn embed_triple<P: EmbeddingProvider + ?Sized>(provider: &P, triple: &Triple, mode: TripleEmbedding)
+                                                   -> Result<TripleVectors, EmbedError> {
+    embed_triple_with(provider, triple, mode, &TemplateRegistry::default())
+}
+
+/// Embed one triple, verbalizing with `templates`
+pub fn embed_triple_with<P: EmbeddingProvider + ?Sized>(provider: &P, triple: &Triple, mode: TripleEmbedding,
+                                                        templates: &TemplateRegistry) -> Result<TripleVectors, EmbedError> {
+    let mut vectors = embed_triples(provider, std::slice::from_ref(triple), mode, templates)?;
+    vectors.pop().ok_or(EmbedError::Mismatch { expected: 1, got: 0 })
+}
+
+/// Embed many triples in one batch request; vectors in input order
+pub fn embed_triples<P: EmbeddingProvider + ?Sized>(provider: &P, triples: &[Triple], mode: TripleEmbedding,
+                                                    templates: &TemplateRegistry) -> Result<Vec<TripleVectors>, EmbedError> {
+    if mode == TripleEmbedding::Verbalized {
+        let texts: Vec<String> = triples.iter().map(|t| templates.verbalize(t)).collect();
+        let refs: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let embeddings = provider.embed_batch(&refs)?;
+        if embeddings.len() != triples.len() {
+            return Err(EmbedError::Mismatch { expected: triples.len(), got: embeddings.len() });
+        }
+        return Ok(embeddings.into_iter().map(TripleVectors::Single).collect());
+    }
+    
+    let texts: Vec<&str> = triples.iter()
+        .flat_map(|t| [t.subject.as_str(), t.predicate.as_str(), t.object.as_str()])
+        .collect();
+    let embeddings = provider.embed_batch(&texts)?;
+    if embeddings.len() != texts.len() {
+        return Err(EmbedError::Mismatch { expected: texts.len(), got: embeddings.len() });
+    }
+    let mut embeddings = embeddings.into_iter();
+    let mut out = Vec::with_capacity(triples.len());
+    while let (Some(subject), Some(predicate), Some(object)) = (embeddings.next(), embeddings.next(), embeddings.next()) {
+        out.push(match mode {
+            TripleEmbedding::Concatenated => TripleVectors::Single(concatenate(&subject, &predicate, &object)),
+            _ => TripleVectors::PerComponent { subject, predicate, object },
+        });
+    }
+    Ok(out)
+}
+
+/// Each vector truncated to a third of its size, stitched, zero-padded back
+/// to full size and normalized
+fn concatenate(subject: &[f32], predicate: &[f32], object: &[f32]) -> Vec<f32> {
+    let dims = subject.len();
+    let part = dims / 3;
+    let mut out = Vec::with_capacity(dims);
+    for v in [subject, predicate, object] {
+        out.extend(truncate_mrl(v, part));
+    }
+    out.resize(dims, 0.0);
+    normalize(&mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pseudo::PseudoEmbedder;
+    use crate::search::{cosine, norm};
+    
+    #[test]
+    fn test_modes_have_provider_dimensions() {
+        let provider = PseudoEmbedder::new(64);
+        let triple = Triple::new("Ada Lovelace", "wrote", "the first program");
+        for mode in [TripleEmbedding::Verbalized, TripleEmbedding::Concatenated] {
+            let v = embed_triple(&provider, &triple, mode).unwrap().into_single().unwrap();
+            assert_eq!(v.len(), 64);
+            assert!((norm(&v) - 1.0).abs() < 1e-5);
+            // Deterministic
+            assert_eq!(embed_triple(&provider, &triple, mode).unwrap().into_single().unwrap(), v);
+        }
+        let TripleVectors::PerComponent { subject, predicate, object } =
+            embed_triple(&provider, &triple, TripleEmbedding::PerComponent).unwrap() else { panic!("per component") };
+        assert_eq!(subject, provider.embed("Ada Lovelace"));
+        assert_eq!(predicate, provider.embed("wrote"));
+        assert_eq!(object, provider.embed("the first program"));
+        
+        // 64 / 3 leaves one padding component
+        let v = embed_triple(&provider, &triple, TripleEmbedding::Concatenated).unwrap().into_single().unwrap();
+        assert_eq!(v[63], 0.0);
+    }
+    
+    #[test]
+    fn test_shared_subject_is_more_similar() {
+        let provider = PseudoEmbedder::new(256);
+        let embed = |s, p, o| embed_triple(&provider, &Triple::new(s, p, o), TripleEmbedding::Verbalized)
+            .unwrap().into_single().unwrap();
+        let a = embed("Marie Curie", "discovered", "polonium");
+        let b = embed("Marie Curie", "won", "the Nobel Prize");
+        let c = embed("the Danube", "flows into", "the Black Sea");
+        assert!(cosine(&a, &b) > cosine(&a, &c));
+    }
+    
+    #[test]
+    fn test_templates_per_predicate() {
+        let templates = TemplateRegistry::new().with_template("founded_by", "{o} founded {s}").unwrap()
+            .with_template("birth_date", "{s} was born on {o}").unwrap();
+        assert_eq!(templates.verbalize(&Triple::new("Acme", "founded_by", "Wile")), "Wile founded Acme");
+        assert_eq!(templates.verbalize(&Triple::new("Alice", "birth_date", "1990")), "Alice was born on 1990");
+        assert_eq!(templates.verbalize(&Triple::new("Acme", "makes", "anvils")), "Acme makes anvils");
+        // Substituted text is not scanned for placeholders
+        assert_eq!(templates.verbalize(&Triple::new("{o}", "founded_by", "{s}")), "{s} founded {o}");
+        let fallback = TemplateRegistry::new().with_default("{p}: {s} -> {o}").unwrap();
+        assert_eq!(fallback.verbalize(&Triple::new("a", "links", "b")), "links: a -> b");
+        
+        let provider = PseudoEmbedder::new(32);
+        let triple = Triple::new("Acme", "founded_by", "Wile");
+        let v = embed_triple_with(&provider, &triple, TripleEmbedding::Verbalized, &templates).unwrap();
+        assert_eq!(v.single().unwrap(), provider.embed("Wile founded Acme"));
+        
+        let decoded = TemplateRegistry::from_json(&templates.to_json()).unwrap();
+        assert_eq!(decoded, templates);
+        assert_eq!(decoded.fingerprint("birth_date"), templates.fingerprint("birth_date"));
+        assert_ne!(templates.fingerprint("birth_date"), templates.fingerprint("makes"));
+        assert_eq!(templates.fingerprint("makes"), TemplateRegistry::new().fingerprint("other"));
+    }
+    
+    #[test]
+    fn test_invalid_templates_are_refused() {
+        let err = |template| validate_template(template).unwrap_err();
+        assert_eq!(err("{s} was born"), r#"{o} appears 0 times in "{s} was born", expected once"#);
+        assert!(err("{s} and {s} met {o}").contains("{s} appears 2 times"));
+        assert_eq!(err("{s} {verb} {o}"), r#"Unknown placeholder {verb} in "{s} {verb} {o}""#);
+        assert!(err("{s} {o").contains("Unclosed"));
+        assert!(validate_template("{s} ({p}) {o}, {p}").is_ok());
+        
+        let mut templates = TemplateRegistry::new();
+        assert!(templates.register("born", "{s} was born").unwrap_err().starts_with("Template for born: "));
+        assert!(TemplateRegistry::new().with_default("{o}").is_err());
+        assert_eq!(templates, TemplateRegistry::new());
+        // Hand-edited files are validated on load
+        let json = r#"{"default":"{s} {p} {o}","templates":{"born":"{s} born {x}"}}"#;
+        assert!(TemplateRegistry::from_json(json).unwrap_err().contains("Unknown placeholder {x}"));
+    }
+}
@@ -0,0 +1,322 @@
+//! Scatter plots of an embedding space
+//!
+//! `scatter_svg` projects embeddings to 2D and writes an SVG scatter plot:
+//! one circle per embedding, titled (shown on hover) with a metadata field,
+//! and colored by a categorical field with a legend. With `html` the SVG is
+//! wrapped in a standalone HTML page instead.
+//!
+//! The projection is a `Projector`; `Pca` (the default) keeps the two
+//! directions of largest variance, found by power iteration, so the same
+//! input always gives the same plot. Labels and categories are escaped for
+//! XML; characters XML cannot hold at all become U+FFFD.
+
+use std::io::Write;
+
+use crate::io::atomic_write;
+use crate::metadata::{MetaValue, Metadata};
+
+/// Categorical colors, reused past ten categories
+const PALETTE: [&str; 10] = ["#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd",
+                             "#8c564b", "#e377c2", "#7f7f7f", "#bcbd22", "#17becf"];
+const UNCOLORED: &str = "#4c78a8";
+const MARGIN: f64 = 20.0;
+const RADIUS: f64 = 4.0;
+/// Room right of the points for the legend
+const LEGEND_WIDTH: f64 = 100.0;
+/// Legend entry for rows without the `color_by` field
+const MISSING: &str = "(none)";
+
+/// Reduction of embeddings to plot coordinates
+pub trait Projector {
+    /// One `[x, y]` per embedding; the embeddings are non-empty, equally
+    /// sized and finite
+    fn project(&self, embeddings: &[Vec<f32>]) -> Result<Vec<[f64; 2]>, String>;
+}
+
+/// Principal component analysis onto the first two components
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Pca;
+
+impl Projector for Pca {
+    fn project(&self, embeddings: &[Vec<f32>]) -> Result<Vec<[f64; 2]>, String> {
+        let dims = embeddings[0].len();
+        let mut mean = vec![0.0f64; dims];
+        for v in embeddings {
+            for (m, &x) in mean.iter_mut().zip(v) { *m += x as f64; }
+        }
+        for m in mean.iter_mut() { *m /= embeddings.len() as f64; }
+        let centered: Vec<Vec<f64>> = embeddings.iter()
+            .map(|v| v.iter().zip(&mean).map(|(&x, m)| x as f64 - m).collect())
+            .collect();
+        
+        let first = principal_component(&centered, None);
+        let second = principal_component(&centered, first.as_deref());
+        let coordinate = |row: &[f64], axis: &Option<Vec<f64>>| axis.as_ref().map_or(0.0, |axis| dot(row, axis));
+        Ok(centered.iter().map(|row| [coordinate(row, &first), coordinate(row, &second)]).collect())
+    }
+}
+
+/// Unit direction of largest variance of `rows`, orthogonal to `deflate`;
+/// `None` when nothing varies. Its largest component is positive.
+fn principal_component(rows: &[Vec<f64>], deflate: Option<&[f64]>) -> Option<Vec<f64>> {
+    let residual = |row: &[f64]| -> Vec<f64> {
+        match deflate {
+            Some(axis) => {
+                let along = dot(row, axis);
+                row.iter().zip(axis).map(|(x, a)| x - along * a).collect()
+            }
+            None => row.to_vec(),
+        }
+    };
+    let rows: Vec<Vec<f64>> = rows.iter().map(|row| residual(row)).collect();
+    // Start from the row furthest from the mean: never orthogonal to the answer
+    let start = rows.iter().max_by(|a, b| dot(a, a).total_cmp(&dot(b, b)))?;
+    let mut v = unit(start)?;
+    for _ in 0..500 {
+        let mut next = vec![0.0f64; v.len()];
+        for row in &rows {
+            let along = dot(row, &v);
+            for (n, x) in next.iter_mut().zip(row) { *n += along * x; }
+        }
+        let next = unit(&residual(&next))?;
+        let change: f64 = next.iter().zip(&v).map(|(a, b)| (a - b).abs()).sum();
+        v = next;
+        if change < 1e-12 {
+            break;
+        }
+    }
+    let peak = v.iter().copied().max_by(|a, b| a.abs().total_cmp(&b.abs())).unwrap_or(0.0);
+    if peak < 0.0 {
+        v.iter_mut().for_each(|x| *x = -*x);
+    }
+    Some(v)
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 { a.iter().zip(b).map(|(x, y)| x * y).sum() }
+
+fn unit(v: &[f64]) -> Option<Vec<f64>> {
+    let norm = dot(v, v).sqrt();
+    (norm > 1e-12).then(|| v.iter().map(|x| x / norm).collect())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VizOptions {
+    /// Metadata field whose values color the points; `None` draws one color
+    pub color_by: Option<String>,
+    /// Metadata field shown on hover; rows without it show their index
+    pub title_by: String,
+    pub width: u32,
+    pub height: u32,
+    /// Write a standalone HTML page around the SVG
+    pub html: bool,
+}
+
+impl Default for VizOptions {
+    fn default() -> Self {
+        Self { color_by: None, title_by: "label".to_string(), width: 800, height: 600, html: false }
+    }
+}
+
+/// Project `embeddings` with `Pca` and write the plot to `path`; `labels`
+/// holds one metadata row per embedding
+pub fn scatter_svg(embeddings: &[Vec<f32>], labels: &[Metadata], path: &str, options: &VizOptions) -> Result<(), String> {
+    scatter_svg_with(&Pca, embeddings, labels, path, options)
+}
+
+/// `scatter_svg` with another projection
+pub fn scatter_svg_with(projector: &dyn Projector, embeddings: &[Vec<f32>], labels: &[Metadata], path: &str,
+                        options: &VizOptions) -> Result<(), String> {
+    let document = render(projector, embeddings, labels, options)?;
+    atomic_write(path, |file| file.write_all(document.as_bytes())).map_err(|e| format!("Write failed for {}: {}", path, e))
+}
+
+/// The SVG, or HTML page, `scatter_svg_with` writes
+pub fn render(projector: &dyn Projector, embeddings: &[Vec<f32>], labels: &[Metadata], options: &VizOptions)
+              -> Result<String, String> {
+    check(embeddings, labels, options)?;
+    let points = projector.project(embeddings)?;
+    if points.len() != embeddings.len() {
+        return Err(format!("Projector returned {} points for {} embeddings", points.len(), embeddings.len()));
+    }
+    if points.iter().flatten().any(|c| !c.is_finite()) {
+        return Err("Projector returned non-finite coordinates".to_string());
+    }
+    
+    let categories: Vec<String> = labels.iter()
+        .map(|m| options.color_by.as_deref().map_or_else(String::new, |field| category(m.get(field))))
+        .collect();
+    let mut legend: Vec<&str> = categories.iter().map(String::as_str).collect();
+    legend.sort_unstable();
+    legend.dedup();
+    let color = |category: &str| match &options.color_by {
+        Some(_) => PALETTE[legend.binary_search(&category).unwrap() % PALETTE.len()],
+        None => UNCOLORED,
+    };
+    
+    let (width, height) = (options.width as f64, options.height as f64);
+    let right = width - MARGIN - if options.color_by.is_some() { LEGEND_WIDTH } else { 0.0 };
+    let scale = |values: &mut dyn Iterator<Item = f64>, from: f64, to: f64| -> Box<dyn Fn(f64) -> f64> {
+        let (lo, hi) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+        if hi - lo < 1e-12 {
+            Box::new(move |_| (from + to) / 2.0)
+        } else {
+            Box::new(move |v| from + (v - lo) / (hi - lo) * (to - from))
+        }
+    };
+    let x = scale(&mut points.iter().map(|p| p[0]), MARGIN, right);
+    // SVG y grows downwards
+    let y = scale(&mut points.iter().map(|p| p[1]), height - MARGIN, MARGIN);
+    
+    let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+                          w = options.width, h = options.height);
+    svg.push_str("<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n<g class=\"points\">\n");
+    for (i, (point, metadata)) in points.iter().zip(labels).enumerate() {
+        let title = metadata.get(&options.title_by).map_or_else(|| i.to_string(), |v| category(Some(v)));
+        svg.push_str(&format!("<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{}\" fill=\"{}\"><title>{}</title></circle>\n",
+                              x(point[0]), y(point[1]), RADIUS, color(&categories[i]), escape(&title)));
+    }
+    svg.push_str("</g>\n");
+    if options.color_by.is_some() {
+        svg.push_str("<g class=\"legend\" font-family=\"sans-serif\" font-size=\"12\">\n");
+        for (row, category) in legend.iter().enumerate() {
+            let top = MARGIN + 16.0 * row as f64;
+            svg.push_str(&format!("<rect x=\"{:.1}\" y=\"{:.1}\" width=\"10\" height=\"10\" fill=\"{}\"/><text x=\"{:.1}\" y=\"{:.1}\">{}</text>\n",
+                                  right + MARGIN, top, color(category), right + MARGIN + 14.0, top + 9.0, escape(category)));
+        }
+        svg.push_str("</g>\n");
+    }
+    svg.push_str("</svg>\n");
+    
+    if !options.html {
+        return Ok(svg);
+    }
+    let title = options.color_by.as_deref().map_or("Embeddings".to_string(), |field| format!("Embeddings by {}", field));
+    Ok(format!("<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{}</title></head>\n<body>\n{}</body>\n</html>\n",
+               escape(&title), svg))
+}
+
+fn check(embeddings: &[Vec<f32>], labels: &[Metadata], options: &VizOptions) -> Result<(), String> {
+    if embeddings.is_empty() {
+        return Err("No embeddings to plot".to_string());
+    }
+    if labels.len() != embeddings.len() {
+        return Err(format!("{} labels for {} embeddings", labels.len(), embeddings.len()));
+    }
+    let legend = if options.color_by.is_some() { LEGEND_WIDTH } else { 0.0 };
+    if options.width as f64 <= 2.0 * MARGIN + legend || options.height as f64 <= 2.0 * MARGIN {
+        return Err(format!("Plot of {}x{} leaves no room for points inside its margins", options.width, options.height));
+    }
+    let dims = embeddings[0].len();
+    if dims == 0 {
+        return Err("Embeddings are empty".to_string());
+    }
+    for (i, v) in embeddings.iter().enumerate() {
+        if v.len() != dims {
+            return Err(format!("Embedding {} has {} dimensions, expected {}", i, v.len(), dims));
+        }
+        if v.iter().any(|x| !x.is_finite()) {
+            return Err(format!("Embedding {} is not finite", i));
+        }
+    }
+    Ok(())
+}
+
+fn category(value: Option<&MetaValue>) -> String {
+    match value {
+        Some(MetaValue::Str(s)) => s.clone(),
+        Some(MetaValue::Num(n)) => n.to_string(),
+        Some(MetaValue::Bool(b)) => b.to_string(),
+        None => MISSING.to_string(),
+    }
+}
+
+/// `text` as XML character data or attribute value
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            '\t' | '\n' | '\r' => out.push(c),
+            c if c < ' ' || c == '\u{fffe}' || c == '\u{ffff}' => out.push('\u{fffd}'),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const GOLDEN: &str = "fixtures/viz/scatter.svg";
+    const UPDATE_ENV: &str = "SPO_CRYSTAL_UPDATE_SNAPSHOTS";
+    
+    /// Two clusters in 3D, one label needing escapes
+    fn dataset() -> (Vec<Vec<f32>>, Vec<Metadata>) {
+        let rows = [
+            ([1.0, 0.0, 0.1], "Ada", "people"),
+            ([0.9, 0.1, 0.0], "<b>\"Jan\" & 'Kai'</b>", "people"),
+            ([1.1, -0.1, 0.0], "Lin", "people"),
+            ([0.0, 1.0, 0.9], "Rust", "languages"),
+            ([0.1, 0.9, 1.0], "Go", "languages"),
+            ([0.0, 1.1, 1.1], "bell\u{7}", "languages"),
+        ];
+        let embeddings = rows.iter().map(|r| r.0.to_vec()).collect();
+        let labels = rows.iter().map(|r| Metadata::new().with("label", r.1).with("kind", r.2)).collect();
+        (embeddings, labels)
+    }
+    
+    #[test]
+    fn test_scatter_matches_golden_file() {
+        let (embeddings, labels) = dataset();
+        let options = VizOptions { color_by: Some("kind".to_string()), width: 300, height: 120, ..VizOptions::default() };
+        let svg = render(&Pca, &embeddings, &labels, &options).unwrap();
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(GOLDEN);
+        if std::env::var_os(UPDATE_ENV).is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, &svg).unwrap();
+        }
+        assert_eq!(svg, std::fs::read_to_string(&path).unwrap(), "plot changed; if intended, rerun with {}=1 and commit {}", UPDATE_ENV, GOLDEN);
+        assert!(svg.contains("<title>&lt;b&gt;&quot;Jan&quot; &amp; &#39;Kai&#39;&lt;/b&gt;</title>"));
+        assert!(svg.contains("<title>bell\u{fffd}</title>"));
+        
+        // The clusters land on opposite halves of the first axis
+        let points = Pca.project(&embeddings).unwrap();
+        assert!(points[..3].iter().all(|p| p[0] < 0.0) && points[3..].iter().all(|p| p[0] > 0.0)
+                || points[..3].iter().all(|p| p[0] > 0.0) && points[3..].iter().all(|p| p[0] < 0.0));
+        
+        let dir = tempfile::tempdir().unwrap();
+        let page = dir.path().join("plot.html").to_string_lossy().to_string();
+        scatter_svg(&embeddings, &labels, &page, &VizOptions { html: true, ..options }).unwrap();
+        let html = std::fs::read_to_string(&page).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>") && html.contains(&svg) && html.contains("<title>Embeddings by kind</title>"));
+    }
+    
+    #[test]
+    fn test_bad_input_writes_nothing() {
+        let (embeddings, labels) = dataset();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plot.svg").to_string_lossy().to_string();
+        let options = VizOptions::default();
+        
+        assert_eq!(scatter_svg(&[], &[], &path, &options).unwrap_err(), "No embeddings to plot");
+        assert_eq!(scatter_svg(&embeddings, &labels[..2], &path, &options).unwrap_err(), "2 labels for 6 embeddings");
+        let mut ragged = embeddings.clone();
+        ragged[4].pop();
+        assert_eq!(scatter_svg(&ragged, &labels, &path, &options).unwrap_err(), "Embedding 4 has 2 dimensions, expected 3");
+        let mut nan = embeddings.clone();
+        nan[1][0] = f32::NAN;
+        assert_eq!(scatter_svg(&nan, &labels, &path, &options).unwrap_err(), "Embedding 1 is not finite");
+        let tiny = VizOptions { width: 30, ..VizOptions::default() };
+        assert!(scatter_svg(&embeddings, &labels, &path, &tiny).unwrap_err().contains("no room"));
+        assert!(!std::path::Path::new(&path).exists());
+        
+        // A single point, or identical ones, sit in the middle
+        let svg = render(&Pca, &embeddings[..1], &labels[..1], &options).unwrap();
+        assert!(svg.contains("<circle cx=\"400.0\" cy=\"300.0\""));
+    }
+}
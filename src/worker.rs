@@ -0,0 +1,260 @@
+//! Background embedding worker fed through channels
+//!
+//! `EmbeddingWorker::spawn` starts a thread that reads `Job`s from a bounded
+//! queue (senders block while it is full), embeds them in batches and sends
+//! one `JobResult` per job, in submission order. A batch goes out when it
+//! holds `batch_size` jobs or its oldest job has waited `max_delay`.
+//!
+//! A failed request turns into an error result for every job of its batch;
+//! the worker carries on with the next one. `close` stops the worker after
+//! embedding the jobs already queued, including the last partial batch. The
+//! worker also stops once every sender is gone or the results are no longer
+//! received.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::jina_api::EmbedOptions;
+use crate::provider::{EmbedError, EmbeddingProvider};
+
+pub const DEFAULT_WORKER_BATCH_SIZE: usize = 50;
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_millis(200);
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+/// How often an idle worker checks for `close`
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkerConfig {
+    batch_size: usize,
+    max_delay: Duration,
+    queue_capacity: usize,
+    options: EmbedOptions,
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: DEFAULT_WORKER_BATCH_SIZE,
+            max_delay: DEFAULT_MAX_DELAY,
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            options: EmbedOptions::passage(),
+        }
+    }
+}
+
+impl WorkerConfig {
+    pub fn new() -> Self { Self::default() }
+    
+    /// Jobs per request (at least 1)
+    pub fn with_batch_size(mut self, n: usize) -> Self {
+        self.batch_size = n.max(1);
+        self
+    }
+    
+    /// Longest a job waits for its batch to fill
+    pub fn with_max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+    
+    /// Queued jobs before senders block (at least 1)
+    pub fn with_queue_capacity(mut self, n: usize) -> Self {
+        self.queue_capacity = n.max(1);
+        self
+    }
+    
+    /// Options for every request; passage embeddings by default
+    pub fn with_options(mut self, options: EmbedOptions) -> Self {
+        self.options = options;
+        self
+    }
+}
+
+/// Text to embed, tagged with a caller-chosen id
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Job {
+    pub id: u64,
+    pub text: String,
+}
+
+impl Job {
+    pub fn new(id: u64, text: impl Into<String>) -> Self { Self { id, text: text.into() } }
+}
+
+/// Embedding of one job, or the error of the request it was part of
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct JobResult {
+    pub id: u64,
+    pub result: Result<Vec<f32>, EmbedError>,
+}
+
+pub struct EmbeddingWorker {
+    jobs: Option<SyncSender<Job>>,
+    closed: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl EmbeddingWorker {
+    /// Start a worker embedding with `provider`; results arrive on the receiver
+    pub fn spawn<P: EmbeddingProvider + 'static>(provider: P, config: WorkerConfig) -> (Self, Receiver<JobResult>) {
+        let (jobs, queue) = mpsc::sync_channel(config.queue_capacity);
+        let (results, received) = mpsc::channel();
+        let closed = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&closed);
+        let handle = std::thread::spawn(move || run(&provider, &config, &queue, &results, &flag));
+        (Self { jobs: Some(jobs), closed, handle: Some(handle) }, received)
+    }
+    
+    /// Sender for the job queue; `send` blocks while the queue is full
+    pub fn sender(&self) -> SyncSender<Job> {
+        self.jobs.clone().expect("open until closed")
+    }
+    
+    /// Queue `job`, blocking while the queue is full; the job back if the worker stopped
+    pub fn submit(&self, job: Job) -> Result<(), Job> {
+        self.sender().send(job).map_err(|e| e.0)
+    }
+    
+    /// Embed the jobs already queued, then stop the worker and wait for it.
+    ///
+    /// Later sends through `sender` clones fail.
+    pub fn close(mut self) { self.shutdown(); }
+    
+    fn shutdown(&mut self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.jobs = None;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for EmbeddingWorker {
+    fn drop(&mut self) { self.shutdown(); }
+}
+
+fn run<P: EmbeddingProvider>(provider: &P, config: &WorkerConfig, queue: &Receiver<Job>,
+                             results: &Sender<JobResult>, closed: &AtomicBool) {
+    let mut batch: Vec<Job> = Vec::with_capacity(config.batch_size);
+    let mut deadline = None;
+    loop {
+        if closed.load(Ordering::SeqCst) {
+            batch.extend(queue.try_iter());
+            for chunk in batch.chunks(config.batch_size) {
+                if !flush(provider, config, chunk, results) {
+                    break;
+                }
+            }
+            return;
+        }
+        let wait = deadline.map_or(POLL_INTERVAL, |d: Instant| d.saturating_duration_since(Instant::now()).min(POLL_INTERVAL));
+        match queue.recv_timeout(wait) {
+            Ok(job) => {
+                if batch.is_empty() {
+                    deadline = Some(Instant::now() + config.max_delay);
+                }
+                batch.push(job);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                flush(provider, config, &batch, results);
+                return;
+            }
+        }
+        let due = deadline.is_some_and(|d| Instant::now() >= d);
+        if batch.len() >= config.batch_size || (due && !batch.is_empty()) {
+            if !flush(provider, config, &batch, results) {
+                return;
+            }
+            batch.clear();
+            deadline = None;
+        }
+    }
+}
+
+/// Embed `jobs` and send their results; false once nobody receives them
+fn flush<P: EmbeddingProvider>(provider: &P, config: &WorkerConfig, jobs: &[Job], results: &Sender<JobResult>) -> bool {
+    if jobs.is_empty() {
+        return true;
+    }
+    let texts: Vec<&str> = jobs.iter().map(|j| j.text.as_str()).collect();
+    let embedded = provider.embed_batch_with(&texts, &config.options).and_then(|vectors| {
+        if vectors.len() == jobs.len() { Ok(vectors) } else { Err(EmbedError::Mismatch { expected: jobs.len(), got: vectors.len() }) }
+    });
+    match embedded {
+        Ok(vectors) => jobs.iter().zip(vectors)
+            .all(|(job, v)| results.send(JobResult { id: job.id, result: Ok(v) }).is_ok()),
+        Err(e) => jobs.iter()
+            .all(|job| results.send(JobResult { id: job.id, result: Err(e.clone()) }).is_ok()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::JinaError;
+    use crate::mock::MockProvider;
+    use std::sync::mpsc::TrySendError;
+    
+    fn mock() -> Arc<MockProvider> { Arc::new(MockProvider::new(2).with_default(vec![1.0, 0.0])) }
+    
+    fn sizes(mock: &MockProvider) -> Vec<usize> { mock.calls().iter().map(Vec::len).collect() }
+    
+    #[test]
+    fn test_batches_by_size_and_flushes_on_close() {
+        let mock = mock();
+        let config = WorkerConfig::new().with_batch_size(3).with_max_delay(Duration::from_secs(60));
+        let (worker, results) = EmbeddingWorker::spawn(Arc::clone(&mock), config);
+        for id in 0..7 {
+            worker.submit(Job::new(id, format!("text {}", id))).unwrap();
+        }
+        let sender = worker.sender();
+        worker.close();
+        assert_eq!(sizes(&mock), [3, 3, 1]);
+        let results: Vec<JobResult> = results.iter().collect();
+        assert_eq!(results.iter().map(|r| r.id).collect::<Vec<_>>(), (0..7).collect::<Vec<_>>());
+        assert!(results.iter().all(|r| r.result == Ok(vec![1.0, 0.0])));
+        assert!(sender.send(Job::new(7, "late")).is_err());
+    }
+    
+    #[test]
+    fn test_flushes_partial_batch_after_max_delay() {
+        let mock = mock();
+        let config = WorkerConfig::new().with_batch_size(50).with_max_delay(Duration::from_millis(30));
+        let (worker, results) = EmbeddingWorker::spawn(Arc::clone(&mock), config);
+        worker.submit(Job::new(1, "a")).unwrap();
+        worker.submit(Job::new(2, "b")).unwrap();
+        let timeout = Duration::from_secs(5);
+        assert_eq!(results.recv_timeout(timeout).unwrap().id, 1);
+        assert_eq!(results.recv_timeout(timeout).unwrap().id, 2);
+        assert_eq!(sizes(&mock), [2]);
+        
+        // Dropping the worker closes it
+        drop(worker);
+        assert!(results.recv().is_err());
+    }
+    
+    #[test]
+    fn test_errors_per_job_and_back_pressure() {
+        let error = JinaError::Api { status: 503, message: "down".to_string() };
+        let mock = Arc::new(MockProvider::new(2).with_default(vec![0.0, 1.0]).fail_on_call(1, error.clone()));
+        let (worker, results) = EmbeddingWorker::spawn(Arc::clone(&mock), WorkerConfig::new().with_batch_size(2));
+        for id in 0..4 {
+            worker.submit(Job::new(id, "x")).unwrap();
+        }
+        worker.close();
+        let results: Vec<JobResult> = results.iter().collect();
+        assert_eq!(results.iter().map(|r| r.result.clone()).collect::<Vec<_>>(),
+                   [Err(error.clone()), Err(error), Ok(vec![0.0, 1.0]), Ok(vec![0.0, 1.0])]);
+        
+        let slow = Arc::new(MockProvider::new(2).with_default(vec![0.0, 1.0]).with_latency(Duration::from_millis(200)));
+        let config = WorkerConfig::new().with_batch_size(1).with_queue_capacity(1);
+        let (worker, _results) = EmbeddingWorker::spawn(slow, config);
+        let sender = worker.sender();
+        let full = (0..3).filter(|&id| matches!(sender.try_send(Job::new(id, "x")), Err(TrySendError::Full(_)))).count();
+        assert!(full >= 1);
+    }
+}
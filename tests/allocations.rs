@@ -0,0 +1,100 @@
+//! Allocations per `embed_batch_full` call against a local HTTP server.
+//!
+//! A counting global allocator tallies the calling thread's allocations;
+//! `cargo test --test allocations -- --nocapture` prints allocations/op.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+
+use spo_crystal::jina_api::{EmbedOptions, JinaClient};
+
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+    
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) { System.dealloc(ptr, layout) }
+    
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const TEXTS: usize = 32;
+const DIMS: usize = 256;
+
+fn allocations() -> usize { ALLOCATIONS.with(Cell::get) }
+
+/// Serves the same embeddings response to every request, on its own thread
+fn serve() -> String {
+    let data: Vec<String> = (0..TEXTS)
+        .map(|i| {
+            let values: Vec<String> = (0..DIMS).map(|d| format!("{:.6}", ((i * DIMS + d) as f32).sin())).collect();
+            format!(r#"{{"object":"embedding","index":{},"embedding":[{}]}}"#, i, values.join(","))
+        })
+        .collect();
+    let body = format!(r#"{{"data":[{}],"usage":{{"total_tokens":64,"prompt_tokens":64}}}}"#, data.join(","));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("Content-Length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            reader.read_exact(&mut vec![0; length]).unwrap();
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                   body.len(), body).unwrap();
+        }
+    });
+    format!("http://{}", address)
+}
+
+#[test]
+fn test_steady_state_allocations() {
+    let client = JinaClient::new("test-key").with_base_url(&serve()).with_http();
+    let texts: Vec<String> = (0..TEXTS).map(|i| format!("text number {} with \"quotes\"\n", i)).collect();
+    let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+    let options = EmbedOptions::passage().with_dimensions(DIMS);
+    
+    let mut per_call = Vec::new();
+    for _ in 0..20 {
+        let before = allocations();
+        let response = client.embed_batch_full(&texts, &options).unwrap();
+        per_call.push(allocations() - before);
+        assert_eq!(response.embeddings.len(), TEXTS);
+        assert!(response.embeddings.iter().all(|e| e.len() == DIMS));
+    }
+    let first = per_call[0];
+    let steady = *per_call[5..].iter().max().unwrap();
+    println!("allocations/op: first call {}, steady state {} ({} of them the returned vectors)", first, steady, TEXTS + 1);
+    
+    // The returned vectors plus a fixed amount for headers and the connection,
+    // independent of the request and response sizes
+    assert!(steady < first, "first {} steady {}", first, steady);
+    assert!(steady <= TEXTS + 1 + 64, "steady state {} allocations/op", steady);
+}
@@ -0,0 +1,133 @@
+//! `bundle::export` and `import` of an offline workspace in temporary directories
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use spo_crystal::bundle::{self, ArtifactKind, BundleContents};
+use spo_crystal::index::CrystalIndex;
+use spo_crystal::jina_api::JinaClient;
+use spo_crystal::jina_cache::JinaCache;
+use spo_crystal::pipeline::{sync_directory, SyncOptions};
+use spo_crystal::provenance::{Provenance, ProvenanceCheck};
+use spo_crystal::pseudo::PseudoEmbedder;
+use spo_crystal::triple_store::{self, TripleStore};
+use spo_crystal::triples::{TemplateRegistry, Triple};
+
+/// Every kind of artifact, built offline under `root`
+fn workspace(root: &Path) -> BundleContents {
+    let client = JinaClient::new("");
+    let docs = root.join("docs");
+    fs::create_dir_all(&docs).unwrap();
+    fs::write(docs.join("a.md"), "Ada Lovelace wrote the first program.").unwrap();
+    fs::write(docs.join("b.md"), "Grace Hopper built the first compiler.").unwrap();
+    
+    let index_path = root.join("docs.idx");
+    let provenance = Provenance::new("jina-embeddings-v3", 1024).with_task("retrieval.passage");
+    let mut index = CrystalIndex::new(1024).with_provenance(provenance);
+    let options = SyncOptions::new(&index_path);
+    sync_directory(&client, &mut index, &docs, &options).unwrap();
+    index.save(index_path.to_str().unwrap()).unwrap();
+    
+    let cache_path = root.join("fingerprints.cache");
+    let mut cache = JinaCache::new("").with_provider(client).with_persistence(cache_path.to_str().unwrap());
+    cache.get_fingerprint("Ada").unwrap();
+    cache.get_fingerprint("Grace").unwrap();
+    
+    let triples_path = root.join("facts.idx");
+    let mut store = TripleStore::new(PseudoEmbedder::new(64));
+    store.insert(Triple::new("Ada Lovelace", "wrote", "the first program"), Default::default()).unwrap();
+    store.save(triples_path.to_str().unwrap()).unwrap();
+    
+    let templates_path = root.join("templates.json");
+    let templates = TemplateRegistry::default().with_template("wrote", "{s} is the author of {o}").unwrap();
+    fs::write(&templates_path, templates.to_json()).unwrap();
+    
+    BundleContents {
+        index: Some(index_path),
+        cache: Some(cache_path),
+        triples: Some(triples_path),
+        templates: Some(templates_path),
+        manifest: Some(options.manifest_path),
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+#[test]
+fn test_workspace_roundtrips_byte_for_byte() {
+    let dir = tempfile::tempdir().unwrap();
+    let original = workspace(&dir.path().join("original"));
+    let bundle_path = dir.path().join("workspace.tar.gz");
+    let manifest = bundle::export(&bundle_path, &original).unwrap();
+    let kinds: Vec<ArtifactKind> = manifest.artifacts.iter().map(|a| a.kind).collect();
+    assert_eq!(kinds, [ArtifactKind::Index, ArtifactKind::Cache, ArtifactKind::Triples, ArtifactKind::Triples,
+                       ArtifactKind::Triples, ArtifactKind::Triples, ArtifactKind::Templates, ArtifactKind::Manifest]);
+    assert_eq!(manifest.artifacts[0].provenance.as_ref().unwrap().model, "jina-embeddings-v3");
+    
+    let restored_root = dir.path().join("restored");
+    let restored = bundle::import(&bundle_path, &restored_root, ProvenanceCheck::Enforce).unwrap();
+    let pairs = [
+        (&original.index, &restored.index),
+        (&original.cache, &restored.cache),
+        (&original.triples, &restored.triples),
+        (&original.templates, &restored.templates),
+        (&original.manifest, &restored.manifest),
+    ];
+    for (from, to) in pairs {
+        let (from, to) = (from.as_ref().unwrap(), to.as_ref().unwrap());
+        assert_eq!(fs::read(from).unwrap(), fs::read(to).unwrap(), "{}", to.display());
+    }
+    for suffix in [triple_store::SIDECAR_SUFFIX, triple_store::ALIASES_SUFFIX, triple_store::TEMPLATES_SUFFIX] {
+        let (from, to) = (with_suffix(original.triples.as_ref().unwrap(), suffix), with_suffix(restored.triples.as_ref().unwrap(), suffix));
+        assert_eq!(fs::read(from).unwrap(), fs::read(to).unwrap());
+    }
+    
+    // The restored pieces load as what they were
+    let index = CrystalIndex::load(restored.index.as_ref().unwrap().to_str().unwrap()).unwrap();
+    assert_eq!(index.len(), CrystalIndex::load(original.index.as_ref().unwrap().to_str().unwrap()).unwrap().len());
+    let store = TripleStore::load(restored.triples.as_ref().unwrap().to_str().unwrap(), PseudoEmbedder::new(64)).unwrap();
+    assert_eq!(store.len(), 1);
+    
+    // Exporting the restored workspace gives the same bundle
+    let again = dir.path().join("again.tar.gz");
+    bundle::export(&again, &restored).unwrap();
+    assert_eq!(fs::read(&bundle_path).unwrap(), fs::read(&again).unwrap());
+}
+
+#[test]
+fn test_partial_bundle_and_provenance_conflicts() {
+    let dir = tempfile::tempdir().unwrap();
+    let original = workspace(&dir.path().join("original"));
+    let bundle_path = dir.path().join("index.tar.gz");
+    let index_only = BundleContents { index: original.index.clone(), ..BundleContents::default() };
+    assert_eq!(bundle::export(&bundle_path, &index_only).unwrap().artifacts.len(), 1);
+    
+    let target = dir.path().join("target");
+    let restored = bundle::import(&bundle_path, &target, ProvenanceCheck::Enforce).unwrap();
+    assert_eq!(restored, BundleContents { index: Some(target.join("index/docs.idx")), ..BundleContents::default() });
+    // An index of the same provenance may be replaced
+    bundle::import(&bundle_path, &target, ProvenanceCheck::Enforce).unwrap();
+    
+    // One from another model may not, unless overridden
+    let index_path = restored.index.unwrap();
+    let mut other = CrystalIndex::new(768).with_provenance(Provenance::new("jina-embeddings-v2-base-en", 768));
+    other.save(index_path.to_str().unwrap()).unwrap();
+    let err = bundle::import(&bundle_path, &target, ProvenanceCheck::Enforce).unwrap_err();
+    assert!(err.contains("jina-embeddings-v2-base-en") && err.contains("Override"), "{}", err);
+    assert_eq!(CrystalIndex::load(index_path.to_str().unwrap()).unwrap().dims(), 768);
+    bundle::import(&bundle_path, &target, ProvenanceCheck::Override).unwrap();
+    assert_eq!(fs::read(&index_path).unwrap(), fs::read(original.index.as_ref().unwrap()).unwrap());
+    
+    // Nor may an index without provenance replace one with it
+    let bare_path = dir.path().join("docs.idx");
+    CrystalIndex::new(1024).save(bare_path.to_str().unwrap()).unwrap();
+    let bare_bundle = dir.path().join("bare.tar.gz");
+    bundle::export(&bare_bundle, &BundleContents { index: Some(bare_path), ..BundleContents::default() }).unwrap();
+    let err = bundle::import(&bare_bundle, &target, ProvenanceCheck::Enforce).unwrap_err();
+    assert!(err.contains("the bundle no provenance"), "{}", err);
+    assert_eq!(fs::read(&index_path).unwrap(), fs::read(original.index.unwrap()).unwrap());
+}
@@ -0,0 +1,317 @@
+//! End-to-end tests of the `spo-crystal` binary: `cargo test --features cli`
+#![cfg(feature = "cli")]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+use serde_json::Value;
+use spo_crystal::index::CrystalIndex;
+
+fn spo_crystal(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_spo-crystal"))
+        .args(args)
+        .env("JINA_API_KEY", "jina_test")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Commands that never read stdin may exit before the write: ignore the broken pipe
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+fn jsonl(path: &Path) -> Vec<Value> {
+    std::fs::read_to_string(path).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+}
+
+/// Answer one HTTP request per `(status, body)`, in order, on a local port
+fn serve(responses: Vec<(u16, &'static str)>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for (status, body) in responses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(n) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    content_length = n.trim().parse().unwrap();
+                }
+                if line == "\r\n" { break; }
+            }
+            reader.by_ref().take(content_length).read_to_end(&mut Vec::new()).unwrap();
+            let response = format!("HTTP/1.1 {} X\r\nContent-Length: {}\r\n\r\n{}", status, body.len(), body);
+            reader.into_inner().write_all(response.as_bytes()).unwrap();
+        }
+    });
+    url
+}
+
+const EMBEDDING: &str = r#"{"data":[{"object":"embedding","index":0,"embedding":[0.6,0.8]}],"usage":{"total_tokens":2}}"#;
+
+#[test]
+fn test_embed_offline_lines_and_jsonl() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("out.jsonl");
+    let output = spo_crystal(&["embed", "--backend", "offline", "--dimensions", "8", "--out", out.to_str().unwrap()],
+                             "first text\n\nsecond text\nfirst text\n");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let records = jsonl(&out);
+    assert_eq!(records.iter().map(|r| r["id"].clone()).collect::<Vec<_>>(), [0, 2, 3]);
+    assert_eq!(records[0]["embedding"].as_array().unwrap().len(), 8);
+    assert_eq!(records[0]["embedding"], records[2]["embedding"]);
+    assert!(records.iter().all(|r| r["synthetic"] == true));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("3 embedded, 0 already in output, 0 failed"));
+    
+    // JSONL input to stdout, deterministic across runs
+    let input = dir.path().join("in.jsonl");
+    std::fs::write(&input, "{\"id\":\"a\",\"text\":\"first text\"}\n{\"text\":\"other\"}\n").unwrap();
+    let run = || spo_crystal(&["embed", "--backend", "offline", "--dimensions", "8", "-q", input.to_str().unwrap()], "");
+    let stdout = run().stdout;
+    assert_eq!(stdout, run().stdout);
+    let records: Vec<Value> = String::from_utf8(stdout).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!((records[0]["id"].as_str(), records[1]["id"].as_u64()), (Some("a"), Some(1)));
+    assert_eq!(records[0]["embedding"], jsonl(&out)[0]["embedding"]);
+    
+    let bad = spo_crystal(&["embed", "--backend", "offline", "--input-format", "jsonl"], "not json\n");
+    assert_eq!(bad.status.code(), Some(1));
+    assert_eq!(spo_crystal(&["embed", "--backend", "offline", "--task", "bogus"], "x").status.code(), Some(2));
+}
+
+#[test]
+fn test_embed_index_output_and_resume() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("texts.idx");
+    let out = path.to_str().unwrap();
+    let args = ["embed", "--backend", "offline", "--dimensions", "16", "--batch-size", "2", "--format", "index", "--out", out];
+    assert!(spo_crystal(&args, "a\nb\nc\n").status.success());
+    let index = CrystalIndex::load(out).unwrap();
+    assert_eq!((index.len(), index.dims()), (3, 16));
+    
+    let mut resumed = args.to_vec();
+    resumed.push("--resume");
+    let output = spo_crystal(&resumed, "a\nb\nc\nd\ne\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("2 embedded, 3 already in output"));
+    let index = CrystalIndex::load(out).unwrap();
+    assert_eq!(index.len(), 5);
+    assert!(index.contains(4));
+    
+    let ids = spo_crystal(&["embed", "--backend", "offline", "--format", "index", "--input-format", "jsonl", "--out", out],
+                          "{\"id\":\"x\",\"text\":\"t\"}\n");
+    assert_eq!(ids.status.code(), Some(1));
+}
+
+#[test]
+fn test_embed_exit_codes() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("out.jsonl");
+    let out = out.to_str().unwrap();
+    
+    // No key: auth failure before anything is read
+    let output = Command::new(env!("CARGO_BIN_EXE_spo-crystal"))
+        .args(["embed", "--out", out])
+        .env_remove("JINA_API_KEY")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    
+    let url = serve(vec![(401, r#"{"detail":"Invalid API key"}"#)]);
+    let output = spo_crystal(&["embed", "--backend", "native", "--url", &url, "--out", out], "a\n");
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid API key"));
+    
+    // One batch of two fails: partial, and --resume retries only that one
+    let native = ["embed", "--backend", "native", "--dimensions", "2", "--batch-size", "1", "--out", out];
+    let url = serve(vec![(200, EMBEDDING), (400, r#"{"detail":"bad input"}"#)]);
+    let output = spo_crystal(&[&native[..], &["--url", &url]].concat(), "a\nb\n");
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(jsonl(Path::new(out)).len(), 1);
+    
+    let url = serve(vec![(200, EMBEDDING)]);
+    let output = spo_crystal(&[&native[..], &["--url", &url, "--resume"]].concat(), "a\nb\n");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let records = jsonl(Path::new(out));
+    assert_eq!(records.iter().map(|r| r["id"].clone()).collect::<Vec<_>>(), [0, 1]);
+    
+    let output = spo_crystal(&["embed", "--backend", "native", "--url", "https://api.jina.ai"], "a\n");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+fn stdout(output: Output) -> String {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_sim_offline() {
+    let sim = |args: &[&str], stdin: &str| stdout(spo_crystal(&[&["sim", "--backend", "offline"], args].concat(), stdin));
+    let score = sim(&["the cat sat", "a cat was sitting"], "");
+    assert_eq!(score, sim(&["the cat sat", "a cat was sitting"], ""));
+    let (digits, value) = (score.trim_end().split_once('.').unwrap().1, score.trim().parse::<f32>().unwrap());
+    assert!(score.ends_with('\n') && digits.len() == 6 && (0.0..1.0).contains(&value), "{:?}", score);
+    assert_eq!(sim(&["same text", "same text"], ""), "1.000000\n");
+    
+    // Files and stdin give the same texts the same score
+    let dir = tempfile::tempdir().unwrap();
+    let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+    std::fs::write(&a, "the cat sat\n").unwrap();
+    std::fs::write(&b, "a cat was sitting\n").unwrap();
+    assert_eq!(sim(&["--files", a.to_str().unwrap(), b.to_str().unwrap()], ""), score);
+    assert_eq!(sim(&["-", "-"], "the cat sat\n---\na cat was sitting\n"), score);
+    assert_eq!(sim(&["-", "a cat was sitting"], "the cat sat"), score);
+    assert_eq!(spo_crystal(&["sim", "--backend", "offline", "-", "-"], "no separator").status.code(), Some(2));
+}
+
+#[test]
+fn test_matrix_offline_formats() {
+    let matrix = |format: &str| stdout(spo_crystal(&["matrix", "--backend", "offline", "--format", format],
+                                                   "the cat sat\n\na cat, sitting\nstock prices fell\n"));
+    let csv = matrix("csv");
+    assert_eq!(csv, matrix("csv"));
+    let rows: Vec<Vec<&str>> = csv.lines().map(|l| l.split(',').collect()).collect();
+    assert_eq!(rows[0], ["text", "the cat sat", "\"a cat", " sitting\"", "stock prices fell"]);
+    let scores: Vec<Vec<&str>> = csv.lines().skip(1)
+        .map(|l| l.rsplitn(4, ',').take(3).collect::<Vec<_>>().into_iter().rev().collect())
+        .collect();
+    assert!(scores.iter().flatten().all(|c| c.len() == 8 && c.parse::<f32>().is_ok()), "{:?}", scores);
+    for (i, row) in scores.iter().enumerate() {
+        assert_eq!(row[i], "1.000000");
+        assert!(row.iter().enumerate().all(|(j, score)| *score == scores[j][i]));
+    }
+    
+    let table = matrix("table");
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(lines[0], "       0      1      2");
+    assert!(lines[1].starts_with("0  1.000 ") && lines[1].ends_with("  the cat sat"), "{:?}", lines[1]);
+    assert_eq!(lines.len(), 4);
+}
+
+const CORPUS: &str = "{\"id\":1,\"text\":\"the cat sat on the mat\",\"lang\":\"en\"}\n\
+                      {\"id\":2,\"text\":\"a dog barked at the mailman\",\"lang\":\"en\"}\n\
+                      {\"id\":3,\"text\":\"die Katze sitzt auf der Matte\",\"lang\":\"de\"}\n";
+
+#[test]
+fn test_search_corpus_offline() {
+    let dir = tempfile::tempdir().unwrap();
+    let corpus = dir.path().join("corpus.jsonl");
+    std::fs::write(&corpus, CORPUS).unwrap();
+    let cache = dir.path().join("corpus.cache");
+    let args = ["search", "cat on a mat", "--backend", "offline", "-k", "2", "--snippets",
+                "--corpus", corpus.to_str().unwrap(), "--cache", cache.to_str().unwrap()];
+    let table = stdout(spo_crystal(&args, ""));
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(lines[0], "query: cat on a mat");
+    assert!(lines[1].starts_with("   1  1  0.") && lines[1].ends_with("  the cat sat on the mat"), "{:?}", lines[1]);
+    assert_eq!(lines.len(), 3);
+    
+    // The second run reads every corpus embedding from the cache
+    assert_eq!(std::fs::read_to_string(&cache).unwrap().lines().count(), 3);
+    assert_eq!(stdout(spo_crystal(&args, "")), table);
+    assert_eq!(std::fs::read_to_string(&cache).unwrap().lines().count(), 3);
+    let entry: Value = serde_json::from_str(std::fs::read_to_string(&cache).unwrap().lines().next().unwrap()).unwrap();
+    assert_eq!((entry["version"].as_u64(), entry["key"].as_str().unwrap().len()), (Some(2), 64));
+    
+    // Lines from before content keys are ignored and re-embedded
+    std::fs::write(&cache, "{\"key\":\"offline//retrieval.passage/1024\",\"text\":\"the cat sat on the mat\",\"embedding\":[1.0]}\n").unwrap();
+    assert_eq!(stdout(spo_crystal(&args, "")), table);
+    assert_eq!(std::fs::read_to_string(&cache).unwrap().lines().count(), 4);
+    
+    let from_stdin = ["search", "--queries", "-", "--backend", "offline", "--format", "jsonl", "-k", "1",
+                      "--corpus", corpus.to_str().unwrap()];
+    let hit: Value = serde_json::from_str(&stdout(spo_crystal(&from_stdin, "cat on a mat\n"))).unwrap();
+    assert_eq!((hit["rank"].as_u64(), hit["id"].as_u64()), (Some(1), Some(1)));
+    assert_eq!(spo_crystal(&from_stdin, "\n").status.code(), Some(2));
+}
+
+#[test]
+fn test_search_index_offline() {
+    let dir = tempfile::tempdir().unwrap();
+    let index = dir.path().join("corpus.idx");
+    let index = index.to_str().unwrap();
+    assert!(spo_crystal(&["embed", "--backend", "offline", "--input-format", "jsonl", "--format", "index", "--out", index], CORPUS)
+        .status.success());
+    let queries = dir.path().join("queries.txt");
+    std::fs::write(&queries, "cat on a mat\nbarking dog\n").unwrap();
+    let search = |args: &[&str]| spo_crystal(&[&["search", "--backend", "offline", "--index", index], args].concat(), "");
+    
+    let hits: Vec<Value> = stdout(search(&["--queries", queries.to_str().unwrap(), "--format", "jsonl", "-k", "1"]))
+        .lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(hits.iter().map(|h| (h["query"].as_str().unwrap(), h["id"].as_u64().unwrap())).collect::<Vec<_>>(),
+               [("cat on a mat", 1), ("barking dog", 2)]);
+    assert!(hits[0]["score"].as_f64().unwrap() > 0.0 && hits[0].get("text").is_none());
+    
+    let german = stdout(search(&["cat", "--filter", "lang=de", "--snippets"]));
+    assert_eq!(german.lines().skip(1).collect::<Vec<_>>().len(), 1);
+    assert!(german.contains("  3  ") && german.ends_with("  die Katze sitzt auf der Matte\n"), "{:?}", german);
+    
+    let reranked = stdout(search(&["cat on a mat", "--rerank", "-k", "2", "--format", "jsonl"]));
+    assert_eq!(reranked.lines().count(), 2);
+    assert_eq!(search(&["cat", "--min-score", "0.99"]).status.code(), Some(5));
+    assert_eq!(search(&["cat", "--filter", "lang"]).status.code(), Some(2));
+}
+
+#[test]
+fn test_index_build_info_update_search() {
+    let dir = tempfile::tempdir().unwrap();
+    let index = dir.path().join("corpus.idx");
+    let index = index.to_str().unwrap();
+    let offline = |args: &[&str], stdin: &str| spo_crystal(&[args, &["--backend", "offline"]].concat(), stdin);
+    let build = offline(&["index", "build", "-", "--input-format", "jsonl", "--quantize", "int8", "--dimensions", "32", "--out", index], CORPUS);
+    assert!(build.status.success(), "{}", String::from_utf8_lossy(&build.stderr));
+    
+    let info = stdout(spo_crystal(&["index", "info", index, "--sample", "2"], ""));
+    for line in ["entries:       3", "dimensions:    32", "quantization:  int8", "sample ids:    1, 2"] {
+        assert!(info.contains(line), "{:?} not in {}", line, info);
+    }
+    
+    let add = offline(&["index", "add", index, "-", "--input-format", "jsonl"],
+                      "{\"id\":4,\"text\":\"birds sing at dawn\"}\n{\"id\":2,\"text\":\"a dog howled at the moon\"}\n");
+    assert!(String::from_utf8_lossy(&add.stderr).contains("added 1, replaced 1, 4 entries"));
+    let remove = spo_crystal(&["index", "remove", index, "1", "99"], "");
+    assert!(remove.status.success());
+    assert!(String::from_utf8_lossy(&remove.stderr).contains("not in the index: 99"));
+    assert!(stdout(spo_crystal(&["index", "info", index], "")).contains("entries:       3"));
+    
+    let hits = stdout(offline(&["search", "a dog howled at the moon", "--index", index, "-k", "1", "--snippets"], ""));
+    assert!(hits.ends_with("   1  2  1.0000  a dog howled at the moon\n"), "{:?}", hits);
+    assert_eq!(offline(&["index", "add", index, "-", "--dimensions", "8"], "x\n").status.code(), Some(2));
+    // Only flat indexes exist; anything else is a usage error
+    assert_eq!(offline(&["index", "build", "-", "--type", "hnsw", "--out", index], "x\n").status.code(), Some(2));
+    
+    // Damaged files: a readable error and exit 1, not a panic
+    let mut bytes = std::fs::read(index).unwrap();
+    bytes[7] = b'9';
+    std::fs::write(index, &bytes).unwrap();
+    let info = spo_crystal(&["index", "info", index], "");
+    assert_eq!(info.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&info.stderr).contains("Unsupported index format version 09"));
+    std::fs::write(index, &bytes[..12]).unwrap();
+    assert_eq!(spo_crystal(&["index", "info", index], "").status.code(), Some(1));
+}
+
+#[test]
+fn test_bench_tiny_matrix_report_schema() {
+    let args = ["bench", "--vectors", "200", "--queries", "5", "--dims", "16", "--quantize", "none,int8", "--index", "flat,sharded-2", "--k", "1,5"];
+    let report: Value = serde_json::from_str(&stdout(spo_crystal(&args, ""))).unwrap();
+    let keys = |v: &Value| v.as_object().unwrap().keys().cloned().collect::<Vec<_>>();
+    assert_eq!(keys(&report), ["dims", "embed_texts_per_sec", "queries", "results", "vectors"]);
+    let results = report["results"].as_array().unwrap();
+    assert_eq!(results.len(), 8);
+    assert_eq!(keys(&results[0]), ["build_ms", "index", "k", "memory_bytes", "metric", "p50_us", "p95_us", "p99_us", "quantization",
+                                   "queries_per_sec", "recall"]);
+    assert_eq!((&results[0]["metric"], &results[0]["index"], &results[0]["recall"]), (&Value::from("cosine"), &Value::from("flat"), &Value::from(1.0)));
+    assert_eq!(results[2]["index"], serde_json::json!({"sharded": {"shards": 2}}));
+    
+    let csv = stdout(spo_crystal(&[&args[..], &["--format", "csv"]].concat(), ""));
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], spo_crystal::eval::bench::CSV_HEADER);
+    assert!(lines[3].starts_with("cosine,none,sharded-2,1,"), "{}", lines[3]);
+    assert_eq!(lines.len(), 9);
+    assert_eq!(spo_crystal(&["bench", "--index", "hnsw"], "").status.code(), Some(2));
+}
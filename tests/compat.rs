@@ -0,0 +1,43 @@
+//! Code written against the String-error API still compiles, with
+//! deprecation warnings, and agrees with the prelude's typed calls
+
+#![allow(deprecated)]
+
+use spo_crystal::index::CrystalIndex;
+use spo_crystal::jina_api::JinaClient;
+
+fn old_embed(client: &JinaClient, text: &str) -> Result<Vec<f32>, String> {
+    let vector = client.embed(text)?;
+    Ok(vector)
+}
+
+fn old_index(client: &JinaClient, texts: &[&str]) -> Result<CrystalIndex, String> {
+    let vectors = client.embed_batch(texts)?;
+    let mut index = CrystalIndex::new(vectors[0].len());
+    for (id, vector) in vectors.iter().enumerate() {
+        index.add(id as u64, vector)?;
+    }
+    Ok(index)
+}
+
+#[test]
+fn test_old_calls_match_prelude_calls() {
+    let client = JinaClient::new("");
+    let texts = ["Ada Lovelace", "Jan Hübener"];
+    let options = EmbedOptions::query().with_dimensions(64);
+    let old_vector = old_embed(&client, texts[0]).unwrap();
+    let old_batch = client.embed_batch_with(&texts, &options).unwrap();
+    let index = old_index(&client, &texts).unwrap();
+    
+    use spo_crystal::prelude::v1::*;
+    let vector: Embedding = EmbeddingProvider::embed(&client, texts[0]).unwrap();
+    assert_eq!(old_vector, vector);
+    assert_eq!(old_batch, client.embed_batch_full(&texts, &options).unwrap().embeddings);
+    assert_eq!(index.search(&vector, 1)[0].0, 0);
+    
+    // The shims' String is the typed error's message
+    let zero = EmbedOptions::default().with_dimensions(0);
+    let typed: JinaError = EmbeddingProvider::embed_batch_with(&client, &texts, &zero).unwrap_err();
+    assert!(matches!(typed, JinaError::InvalidInput(_)));
+    assert_eq!(client.embed_batch_with(&texts, &zero).unwrap_err(), typed.to_string());
+}
@@ -0,0 +1,94 @@
+//! Golden files for the Qdrant and pgvector exports over offline embeddings
+
+use spo_crystal::io::{export_pgvector_copy, export_pgvector_copy_with, export_qdrant_points, export_qdrant_points_with,
+                      EmbeddingRecord, ExportPrecision};
+use spo_crystal::jina_api::{EmbedOptions, JinaClient};
+use spo_crystal::metadata::Metadata;
+use spo_crystal::search::cosine;
+
+const QDRANT: &str = include_str!("../fixtures/export/points.ndjson");
+const PGVECTOR: &str = include_str!("../fixtures/export/rows.tsv");
+
+/// Three records whose texts and metadata need escaping, embedded offline at 4 dims
+fn corpus() -> Vec<EmbeddingRecord> {
+    let texts = ["Ada wrote \"the first\" program", "tab\there,\nnewline\r\nand \\ backslash", "it's 'quoted'"];
+    let embeddings = JinaClient::new("").embed_batch_full(&texts, &EmbedOptions::passage().with_dimensions(4)).unwrap().embeddings;
+    let ids = ["1", "0f8fad5b-d9cb-469f-a165-70867728950e", "3"];
+    ids.iter().zip(texts).zip(embeddings).enumerate()
+        .map(|(i, ((id, text), embedding))| {
+            let record = EmbeddingRecord::new(id, embedding)
+                .with_metadata(Metadata::new().with("n", i as f64).with("note", "a\tb \"c\"\nd"));
+            if i == 2 { record } else { record.with_text(text) }
+        })
+        .collect()
+}
+
+#[test]
+fn test_qdrant_points_match_golden_file() {
+    let mut out = Vec::new();
+    export_qdrant_points(&mut out, &corpus()).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), QDRANT);
+    let line: serde_json::Value = serde_json::from_str(QDRANT.lines().next().unwrap()).unwrap();
+    assert_eq!(line["points"][1]["payload"]["text"], "tab\there,\nnewline\r\nand \\ backslash");
+}
+
+#[test]
+fn test_pgvector_rows_match_golden_file() {
+    let mut out = Vec::new();
+    export_pgvector_copy(&mut out, &corpus()).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), PGVECTOR);
+    // One line per record with four columns, whatever the texts hold
+    assert!(PGVECTOR.lines().all(|l| l.split('\t').count() == 4));
+    assert_eq!(PGVECTOR.lines().count(), 3);
+}
+
+#[test]
+fn test_reduced_precision_shrinks_fixture_and_reads_back() {
+    let mut out = Vec::new();
+    export_qdrant_points_with(&mut out, &corpus(), ExportPrecision::SignificantDigits(3)).unwrap();
+    let reduced = String::from_utf8(out).unwrap();
+    assert!(reduced.len() + 40 < QDRANT.len(), "{} vs {} bytes", reduced.len(), QDRANT.len());
+    let line: serde_json::Value = serde_json::from_str(&reduced).unwrap();
+    assert_eq!(line["points"][0]["vector"], serde_json::json!([0.524, 0.734, -0.105, 0.419]));
+    
+    let mut out = Vec::new();
+    export_pgvector_copy_with(&mut out, &corpus(), ExportPrecision::FixedDecimals(2)).unwrap();
+    assert!(String::from_utf8(out).unwrap().starts_with("1\tAda wrote \"the first\" program\t[0.52,0.73,-0.1,0.42]\t"));
+}
+
+/// Vectors from a pgvector export's `[x,y,z]` column
+fn read_vectors(rows: &str) -> Vec<Vec<f32>> {
+    rows.lines()
+        .map(|row| row.split('\t').nth(2).unwrap().trim_matches(['[', ']']).split(',').map(|x| x.parse().unwrap()).collect())
+        .collect()
+}
+
+#[test]
+fn test_six_digits_shrink_dense_vectors_and_keep_cosines() {
+    // Dense unit vectors, like a model's, unlike the sparse offline ones
+    let records: Vec<EmbeddingRecord> = (0..20)
+        .map(|i| {
+            let v: Vec<f32> = (0..1024).map(|d| ((i * 1031 + d * 7919) as f32).sin()).collect();
+            let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+            EmbeddingRecord::new(&i.to_string(), v.iter().map(|x| x / norm).collect())
+        })
+        .collect();
+    let export = |precision| {
+        let mut out = Vec::new();
+        export_pgvector_copy_with(&mut out, &records, precision).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+    let (full, reduced) = (export(ExportPrecision::Full), export(ExportPrecision::SignificantDigits(6)));
+    assert_eq!(full, export(ExportPrecision::default()));
+    assert!(reduced.len() * 10 < full.len() * 9, "{} vs {} bytes", reduced.len(), full.len());
+    
+    // Full precision reads back exactly; 6 digits within the documented 1e-5
+    let (exact, rounded) = (read_vectors(&full), read_vectors(&reduced));
+    assert!(exact.iter().zip(&records).all(|(v, r)| v == &r.embedding));
+    for i in 0..records.len() {
+        for j in 0..records.len() {
+            let (want, got) = (cosine(&exact[i], &exact[j]), cosine(&rounded[i], &rounded[j]));
+            assert!((want - got).abs() <= 1e-5, "{} {}: {} vs {}", i, j, want, got);
+        }
+    }
+}
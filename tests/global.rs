@@ -0,0 +1,22 @@
+//! The process-wide client under `SPO_CRYSTAL_OFFLINE`, in its own process
+
+use spo_crystal::global;
+use spo_crystal::jina_api::JinaClient;
+use spo_crystal::provider::EmbeddingProvider;
+
+#[test]
+fn test_offline_env_serves_the_global_client() {
+    // Set before any test in this binary touches the client
+    std::env::set_var(global::OFFLINE_ENV, "1");
+    std::env::remove_var("JINA_API_KEY");
+    
+    let expected = EmbeddingProvider::embed(&JinaClient::new(""), "hello world").unwrap();
+    let vectors: Vec<Vec<f32>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..4).map(|_| scope.spawn(|| spo_crystal::embed("hello world").unwrap())).collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    assert!(vectors.iter().all(|v| v == &expected));
+    assert_eq!(spo_crystal::embed_batch(&["a", "hello world"]).unwrap()[1], expected);
+    assert!(!global::client().unwrap().is_online());
+    assert!(global::configure(JinaClient::new("")).is_err());
+}
@@ -0,0 +1,52 @@
+//! Golden checks against the real Jina API, to catch response changes.
+//!
+//! Ignored by default and skipped without a key; they cost a few tokens:
+//! `JINA_API_KEY=... cargo test --test jina_live -- --ignored`
+//! Failure messages have the key redacted.
+
+use spo_crystal::error::JinaError;
+use spo_crystal::jina_api::{EmbedOptions, JinaClient};
+use spo_crystal::search::norm;
+
+fn api_key() -> Option<String> {
+    std::env::var("JINA_API_KEY").ok().filter(|key| !key.is_empty())
+}
+
+/// `result`'s value, panicking with the key redacted from the error
+fn redacted<T, E: std::fmt::Debug>(result: Result<T, E>, key: &str) -> T {
+    result.unwrap_or_else(|e| panic!("{}", format!("{:?}", e).replace(key, "[REDACTED]")))
+}
+
+#[test]
+#[ignore = "calls the Jina API; needs JINA_API_KEY"]
+fn test_live_embed_shape_and_usage() {
+    let Some(key) = api_key() else { return };
+    let client = JinaClient::new(&key).with_http();
+    let options = EmbedOptions::passage().with_dimensions(128);
+    let response = redacted(client.embed_batch_full(&["Ada Lovelace", "Analytical Engine"], &options), &key);
+    
+    assert_eq!(response.embeddings.len(), 2);
+    for embedding in &response.embeddings {
+        assert_eq!(embedding.len(), 128);
+        assert!((norm(embedding) - 1.0).abs() < 1e-3, "norm {}", norm(embedding));
+    }
+    assert_ne!(response.embeddings[0], response.embeddings[1]);
+    assert!(response.usage.total_tokens > 0);
+    assert!(response.usage.prompt_tokens > 0);
+}
+
+#[test]
+#[ignore = "calls the Jina API; needs JINA_API_KEY"]
+fn test_live_bad_model_maps_to_api_error() {
+    let Some(key) = api_key() else { return };
+    let client = JinaClient::new(&key).with_http().with_model("jina-embeddings-does-not-exist");
+    let result = client.embed_batch_full(&["Ada"], &EmbedOptions::default().with_dimensions(128));
+    match result {
+        Err(JinaError::Api { status, message }) => {
+            let message = message.replace(&key, "[REDACTED]");
+            assert!((400..500).contains(&status) && status != 401 && status != 403, "status {}: {}", status, message);
+            assert!(!message.is_empty());
+        }
+        other => panic!("{}", format!("expected an API error, got {:?}", other).replace(&key, "[REDACTED]")),
+    }
+}
@@ -0,0 +1,32 @@
+//! Parquet interop through a committed fixture (`arrow` feature)
+#![cfg(feature = "arrow")]
+
+use spo_crystal::io::{read_parquet, write_parquet, EmbeddingRecord};
+use spo_crystal::metadata::Metadata;
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/parquet/records.parquet");
+
+/// The records in `fixtures/parquet/records.parquet`, written by `write_parquet`
+fn expected() -> Vec<EmbeddingRecord> {
+    vec![
+        EmbeddingRecord::new("ada", vec![0.5, -0.25, 0.125, 1.0])
+            .with_text("Ada Lovelace wrote the first program.")
+            .with_metadata(Metadata::new().with("lang", "en").with("year", 1843).with("draft", false)),
+        EmbeddingRecord::new("grüße", vec![0.0, 1e-7, -3.5, 2.0])
+            .with_metadata(Metadata::new().with("lang", "de")),
+        EmbeddingRecord::new("empty-meta", vec![1.0, 2.0, 3.0, 4.0]).with_text(""),
+    ]
+}
+
+#[test]
+fn test_fixture_reads_back_and_rewrites_equal() {
+    let bytes = std::fs::read(FIXTURE).unwrap();
+    assert!(bytes.starts_with(b"PAR1") && bytes.ends_with(b"PAR1"));
+    assert_eq!(read_parquet(FIXTURE).unwrap(), expected());
+    
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("rewritten.parquet").to_str().unwrap().to_string();
+    write_parquet(&path, &read_parquet(FIXTURE).unwrap()).unwrap();
+    assert_eq!(read_parquet(&path).unwrap(), expected());
+    assert!(read_parquet(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml")).unwrap_err().starts_with("Read failed"));
+}
@@ -0,0 +1,169 @@
+//! `embed_files` and `sync_directory` over temporary directory trees with the offline embedder
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use spo_crystal::chunk::chunk_text;
+use spo_crystal::index::CrystalIndex;
+use spo_crystal::jina_api::{EmbedOptions, JinaClient};
+use spo_crystal::pipeline::{embed_files, embed_files_with, sync_directory, FileErrorKind, FileOptions, InvalidUtf8, SyncOptions};
+
+/// docs/{a.md, b.txt, nested/c.md}, a binary file, an oversized file and a skipped .rs file
+fn tree(root: &Path) {
+    fs::create_dir_all(root.join("docs/nested")).unwrap();
+    fs::write(root.join("docs/a.md"), "Ada Lovelace wrote the first program for the Analytical Engine.").unwrap();
+    fs::write(root.join("docs/b.txt"), "short note").unwrap();
+    fs::write(root.join("docs/nested/c.md"), "Grace Hopper built the first compiler.").unwrap();
+    fs::write(root.join("docs/binary.txt"), [0x66, 0x6f, 0xff, 0xfe, 0x6f]).unwrap();
+    fs::write(root.join("docs/huge.txt"), "word ".repeat(400)).unwrap();
+    fs::write(root.join("main.rs"), "fn main() {}").unwrap();
+}
+
+fn options() -> FileOptions {
+    FileOptions::new()
+        .with_glob("*.md")
+        .with_glob("*.txt")
+        .with_max_file_size(1000)
+        .with_chunking(Default::default(), 20)
+        .with_batch_size(3)
+        .with_embed_options(EmbedOptions::passage().with_dimensions(32))
+}
+
+#[test]
+fn test_records_map_to_file_chunks() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    tree(root);
+    let client = JinaClient::new("");
+    let result = embed_files(&client, root, &options());
+    
+    // Every chunk of every readable matching file, in name order
+    let a = fs::read_to_string(root.join("docs/a.md")).unwrap();
+    let expected: Vec<(&str, &str)> = chunk_text(&a, 20).iter().map(|c| ("docs/a.md", &a[c.start..c.end])).collect::<Vec<_>>()
+        .into_iter()
+        .chain([("docs/b.txt", "short note")])
+        .chain(chunk_text("Grace Hopper built the first compiler.", 20).into_iter()
+            .map(|c| ("docs/nested/c.md", &"Grace Hopper built the first compiler."[c.start..c.end])))
+        .collect();
+    assert_eq!(result.records.len(), expected.len());
+    let embed_options = EmbedOptions::passage().with_dimensions(32);
+    let mut index_in_file = 0;
+    for (i, (record, (path, text))) in result.records.iter().zip(&expected).enumerate() {
+        assert_eq!(record.path, root.join(path));
+        if i > 0 && result.records[i - 1].path != record.path {
+            index_in_file = 0;
+        }
+        assert_eq!(record.chunk_index, index_in_file);
+        index_in_file += 1;
+        let source = fs::read_to_string(&record.path).unwrap();
+        assert_eq!(&source[record.start..record.end], *text);
+        assert_eq!(record.embedding, client.embed_batch_full(&[text], &embed_options).unwrap().embeddings[0]);
+    }
+    
+    // The binary and oversized files are reported, the .rs file is not matched
+    let mut errors: Vec<(String, &FileErrorKind)> = result.errors.iter()
+        .map(|e| (e.path.strip_prefix(root).unwrap().to_string_lossy().into_owned(), &e.kind))
+        .collect();
+    errors.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(errors, [
+        ("docs/binary.txt".to_string(), &FileErrorKind::InvalidUtf8),
+        ("docs/huge.txt".to_string(), &FileErrorKind::TooLarge { size: 2000, limit: 1000 }),
+    ]);
+    
+    // Lossy decoding embeds the binary file; streaming sees the same records
+    let mut streamed = Vec::new();
+    let errors = embed_files_with(&client, root, &options().with_invalid_utf8(InvalidUtf8::Lossy), |r| streamed.push(r));
+    assert_eq!(errors.len(), 1);
+    assert!(streamed.iter().any(|r| r.path == root.join("docs/binary.txt")));
+    assert_eq!(streamed.len(), result.records.len() + 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlink_cycles_and_unreadable_paths_are_reported_per_file() {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+    
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    tree(root);
+    symlink(root.join("docs"), root.join("docs/nested/loop")).unwrap();
+    symlink(root.join("missing.md"), root.join("dangling.md")).unwrap();
+    let locked = root.join("docs/locked.md");
+    fs::write(&locked, "secret").unwrap();
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+    // Root reads files whatever their mode
+    let unreadable = fs::read(&locked).is_err();
+    let client = JinaClient::new("");
+    
+    // Not followed, symlinks are skipped
+    let plain = embed_files(&client, root, &options());
+    assert!(plain.errors.iter().all(|e| !matches!(e.kind, FileErrorKind::SymlinkCycle)));
+    assert!(plain.records.iter().all(|r| !r.path.starts_with(root.join("docs/nested/loop"))));
+    
+    let followed = embed_files(&client, root, &options().with_follow_symlinks(true));
+    let error = |path: &str| followed.errors.iter().find(|e| e.path == root.join(path)).map(|e| &e.kind);
+    assert_eq!(error("docs/nested/loop"), Some(&FileErrorKind::SymlinkCycle));
+    assert!(matches!(error("dangling.md"), Some(FileErrorKind::Io { kind: std::io::ErrorKind::NotFound, .. })));
+    if unreadable {
+        assert!(matches!(error("docs/locked.md"), Some(FileErrorKind::Io { kind: std::io::ErrorKind::PermissionDenied, .. })));
+    }
+    // The rest of the tree is still embedded, once
+    assert!(followed.records.iter().any(|r| r.path == root.join("docs/nested/c.md")));
+    assert_eq!(followed.records, plain.records);
+}
+
+/// Paths of the index entries, with how many chunks each has
+fn indexed_paths(index: &CrystalIndex) -> BTreeMap<String, usize> {
+    let mut paths = BTreeMap::new();
+    for id in index.ids() {
+        *paths.entry(index.metadata(id).unwrap().get_str("path").unwrap().to_string()).or_default() += 1;
+    }
+    paths
+}
+
+#[test]
+fn test_sync_embeds_only_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("tree");
+    tree(&root);
+    let client = JinaClient::new("");
+    let mut index = CrystalIndex::new(32);
+    let sync = SyncOptions::new(dir.path().join("corpus.idx")).with_files(options());
+    assert_eq!(sync.manifest_path, dir.path().join("corpus.idx.manifest.json"));
+    
+    let report = sync_directory(&client, &mut index, &root, &sync).unwrap();
+    assert_eq!((report.added, report.updated, report.removed, report.renamed, report.skipped), (3, 0, 0, 0, 0));
+    assert_eq!(report.errors.len(), 2);
+    let first = indexed_paths(&index);
+    assert_eq!(first.keys().collect::<Vec<_>>(), ["docs/a.md", "docs/b.txt", "docs/nested/c.md"]);
+    
+    // Nothing changed: nothing is embedded
+    let sent = client.stats().texts_sent;
+    let report = sync_directory(&client, &mut index, &root, &sync).unwrap();
+    assert_eq!((report.added, report.updated, report.removed, report.skipped), (0, 0, 0, 3));
+    assert_eq!(client.stats().texts_sent, sent);
+    
+    // One edit, one deletion, one rename
+    fs::write(root.join("docs/a.md"), "Ada Lovelace, annotated.").unwrap();
+    fs::remove_file(root.join("docs/b.txt")).unwrap();
+    fs::rename(root.join("docs/nested/c.md"), root.join("docs/d.md")).unwrap();
+    let c_ids: Vec<u64> = index.ids().filter(|&id| index.metadata(id).unwrap().get_str("path") == Some("docs/nested/c.md")).collect();
+    let c_vector = index.get(c_ids[0]).unwrap().to_vec();
+    let report = sync_directory(&client, &mut index, &root, &sync).unwrap();
+    assert_eq!((report.added, report.updated, report.removed, report.renamed, report.skipped), (0, 1, 1, 1, 0));
+    
+    // Only the new text of a.md was embedded
+    let chunks = chunk_text("Ada Lovelace, annotated.", 20).len() as u64;
+    assert_eq!(client.stats().texts_sent, sent + chunks);
+    let paths = indexed_paths(&index);
+    assert_eq!(paths.keys().collect::<Vec<_>>(), ["docs/a.md", "docs/d.md"]);
+    assert_eq!((paths["docs/a.md"], paths["docs/d.md"]), (chunks as usize, first["docs/nested/c.md"]));
+    assert_eq!(index.get(c_ids[0]).unwrap(), c_vector);
+    assert_eq!(index.metadata(c_ids[0]).unwrap().get_str("path"), Some("docs/d.md"));
+    
+    // The manifest is all that persists between runs
+    let manifest: serde_json::Value = serde_json::from_slice(&fs::read(&sync.manifest_path).unwrap()).unwrap();
+    assert_eq!(manifest["files"].as_object().unwrap().len(), 2);
+    assert!(sync_directory(&client, &mut index, root.join("docs/a.md"), &sync).unwrap_err().contains("not a directory"));
+}
@@ -0,0 +1,193 @@
+//! Snapshots of embeddings request bodies over a matrix of options.
+//!
+//! Every combination of model, task, `dimensions` and `late_chunking` is
+//! serialized through `JinaClient` and compared byte for byte with
+//! `fixtures/requests/embeddings.snap`; the async client, curl and the
+//! plain HTTP transport must send the very same bytes. A change to request
+//! serialization, or a new option, shows up as a snapshot diff. After
+//! checking that the new bodies are right, rewrite the file with
+//!
+//! ```text
+//! SPO_CRYSTAL_UPDATE_SNAPSHOTS=1 cargo test --test request_bodies
+//! ```
+//!
+//! and commit it with the change.
+
+use std::future::Future;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use spo_crystal::async_client::AsyncJinaClient;
+use spo_crystal::jina_api::{EmbedOptions, JinaClient, Task};
+use spo_crystal::transport::{self, HttpRequest, HttpResponse, RetryPolicy};
+
+const SNAPSHOT: &str = "fixtures/requests/embeddings.snap";
+const UPDATE_ENV: &str = "SPO_CRYSTAL_UPDATE_SNAPSHOTS";
+/// Quotes, escapes, control characters and non-ASCII text
+const TEXTS: [&str; 3] = ["Ada Lovelace", "line\nbreak \"quoted\" \\ tab\t bell\u{7}", "日本語 émoji 🦀"];
+
+/// Every option combination, named as in the snapshot
+fn matrix() -> Vec<(String, String, EmbedOptions)> {
+    let mut cases = Vec::new();
+    let tasks = [None, Some(Task::RetrievalQuery), Some(Task::RetrievalPassage), Some(Task::TextMatching),
+                 Some(Task::Classification), Some(Task::Separation)];
+    for model in ["jina-embeddings-v3", "jina-embeddings-v2-base-en"] {
+        for task in tasks {
+            for dimensions in [None, Some(256)] {
+                for late_chunking in [false, true] {
+                    let mut options = EmbedOptions::default();
+                    if let Some(task) = task {
+                        options = options.with_task(task);
+                    }
+                    if let Some(dims) = dimensions {
+                        options = options.with_dimensions(dims);
+                    }
+                    if late_chunking {
+                        options = options.with_late_chunking();
+                    }
+                    let name = format!("model={} task={} dimensions={} late_chunking={}", model,
+                                       task.map_or("-", |t| t.as_str()),
+                                       dimensions.map_or("-".to_string(), |d| d.to_string()),
+                                       late_chunking);
+                    cases.push((name, model.to_string(), options));
+                }
+            }
+        }
+    }
+    cases
+}
+
+/// Body of the one request `client` sends for `TEXTS`; the response is an error and ignored
+fn capture(client: JinaClient, options: &EmbedOptions) {
+    let _ = client.with_retry(RetryPolicy::none()).embed_batch_full(&TEXTS, options);
+}
+
+/// Client recording what its transport is handed
+fn recording(model: &str) -> (JinaClient, Arc<Mutex<Vec<Vec<u8>>>>) {
+    let bodies: Arc<Mutex<Vec<Vec<u8>>>> = Arc::default();
+    let seen = bodies.clone();
+    let client = JinaClient::new("test-key").with_model(model).with_transport(move |request: &HttpRequest| {
+        seen.lock().unwrap().push(request.body.clone());
+        Ok(HttpResponse { status: 500, headers: Vec::new(), body: String::new() })
+    });
+    (client, bodies)
+}
+
+fn snapshot() -> String {
+    let mut out = String::new();
+    for (name, model, options) in matrix() {
+        let (client, bodies) = recording(&model);
+        capture(client, &options);
+        let bodies = bodies.lock().unwrap();
+        assert_eq!(bodies.len(), 1, "{}", name);
+        out.push_str(&format!("--- {}\n{}\n", name, String::from_utf8(bodies[0].clone()).unwrap()));
+    }
+    out
+}
+
+#[test]
+fn test_request_bodies_match_snapshot() {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(SNAPSHOT);
+    let actual = snapshot();
+    if std::env::var_os(UPDATE_ENV).is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_default();
+    for (expected, actual) in expected.split("--- ").zip(actual.split("--- ")) {
+        assert_eq!(actual, expected, "request body changed; if intended, rerun with {}=1 and commit {}", UPDATE_ENV, SNAPSHOT);
+    }
+    assert_eq!(actual, expected, "snapshot cases changed; if intended, rerun with {}=1 and commit {}", UPDATE_ENV, SNAPSHOT);
+}
+
+/// Poll to completion; the test transport is ready at once
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+}
+
+/// HTTP server keeping each request body and answering 500, on its own thread
+fn serve(bodies: Arc<Mutex<Vec<Vec<u8>>>>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("Content-Length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            bodies.lock().unwrap().push(body);
+            write!(stream, "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n").unwrap();
+        }
+    });
+    format!("http://{}", address)
+}
+
+#[test]
+fn test_clients_and_transports_send_identical_bodies() {
+    let http: Arc<Mutex<Vec<Vec<u8>>>> = Arc::default();
+    let base_url = serve(http.clone());
+    #[cfg(unix)]
+    let curl_dir = fake_curl();
+    
+    for (name, model, options) in matrix() {
+        let (client, direct) = recording(&model);
+        capture(client, &options);
+        let expected = direct.lock().unwrap().pop().unwrap();
+        
+        let asynchronous: Arc<Mutex<Vec<Vec<u8>>>> = Arc::default();
+        let seen = asynchronous.clone();
+        let client = AsyncJinaClient::new("test-key").with_model(&model).with_transport(move |request: HttpRequest| {
+            seen.lock().unwrap().push(request.body);
+            async { Ok(HttpResponse { status: 500, headers: Vec::new(), body: String::new() }) }
+        });
+        let _ = block_on(client.embed_batch_with(&TEXTS, &options));
+        assert_eq!(asynchronous.lock().unwrap().pop().unwrap(), expected, "async client, {}", name);
+        
+        let client = JinaClient::new("test-key").with_model(&model).with_base_url(&base_url)
+            .with_transport(transport::PlainHttpTransport::new());
+        capture(client, &options);
+        assert_eq!(http.lock().unwrap().pop().unwrap(), expected, "plain HTTP, {}", name);
+        
+        #[cfg(unix)]
+        {
+            let program = curl_dir.path().join("curl");
+            let client = JinaClient::new("test-key").with_model(&model)
+                .with_transport(transport::CurlTransport::new().with_program(&program));
+            capture(client, &options);
+            assert_eq!(std::fs::read(curl_dir.path().join("body")).unwrap(), expected, "curl, {}", name);
+        }
+    }
+}
+
+/// Stands in for curl: keeps the body it reads from stdin, answers 500
+#[cfg(unix)]
+fn fake_curl() -> tempfile::TempDir {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("curl");
+    std::fs::write(&script, "#!/bin/sh\ncat > \"$(dirname \"$0\")/body\"\nprintf 'HTTP/1.1 500 Internal Server Error\\r\\n\\r\\n'\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    dir
+}
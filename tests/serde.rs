@@ -0,0 +1,168 @@
+//! JSON round trips of the public result types, and payloads written in
+//! older field layouts (`fixtures/serde/v0.json`) that must keep loading
+
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use spo_crystal::align::{Alignment, Match};
+use spo_crystal::chunk::{Chunk, EmbeddedChunk};
+use spo_crystal::document::{BlendParts, DocumentEmbedding};
+use spo_crystal::drift::{DriftReport, TextDrift};
+use spo_crystal::error::{DiagnosedError, ItemError, JinaError};
+use spo_crystal::io::EmbeddingRecord;
+use spo_crystal::jina_api::{ClientStats, Task};
+use spo_crystal::metadata::Metadata;
+use spo_crystal::pipeline::FileChunk;
+use spo_crystal::provenance::{Provenance, SCHEMA_VERSION};
+use spo_crystal::provider::{BackendKind, EmbeddingResponse, Usage};
+use spo_crystal::reader::ReadResult;
+use spo_crystal::relations::RelationModel;
+use spo_crystal::rerank::RerankHit;
+use spo_crystal::segment::Segmentation;
+use spo_crystal::transport::{Diagnostics, Timings};
+use spo_crystal::triples::Triple;
+use spo_crystal::worker::{Job, JobResult};
+
+const V0: &str = include_str!("../fixtures/serde/v0.json");
+
+fn roundtrip<T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug>(value: T) {
+    let json = serde_json::to_string(&value).unwrap();
+    assert_eq!(serde_json::from_str::<T>(&json).unwrap(), value, "{}", json);
+}
+
+fn diagnostics() -> Diagnostics {
+    Diagnostics {
+        connect_ms: Some(1.5),
+        tls_ms: None,
+        ttfb_ms: Some(8.0),
+        total_ms: 12.5,
+        attempts: 2,
+        backend: "curl".into(),
+        bytes_sent: 120,
+        bytes_received: 4096,
+    }
+}
+
+fn chunk() -> Chunk {
+    Chunk {
+        text: "First sentence.".into(),
+        start: 0,
+        end: 15,
+        metadata: Metadata::new().with("lang", "en").with("page", 3.0).with("draft", true),
+    }
+}
+
+#[test]
+fn test_result_types_roundtrip() {
+    let usage = Usage { prompt_tokens: 3, total_tokens: 5 };
+    let provenance = Provenance::new("jina-embeddings-v3", 2).with_task("retrieval.passage");
+    roundtrip(EmbeddingResponse {
+        embeddings: vec![vec![0.6, 0.8]],
+        usage: usage.clone(),
+        diagnostics: Some(diagnostics()),
+        provenance: Some(provenance.clone()),
+        pooled: vec![0],
+        backend: BackendKind::Offline,
+    });
+    roundtrip(provenance.clone().with_synthetic(true));
+    roundtrip(Timings { connect_ms: Some(1.0), tls_ms: None, ttfb_ms: Some(2.5), total_ms: 3.0 });
+    roundtrip(chunk());
+    roundtrip(EmbeddedChunk { chunk: chunk(), embedding: vec![0.0, 1.0] });
+    roundtrip(RerankHit { index: 1, score: 0.25, document: Some("doc".into()) });
+    roundtrip(Segmentation { chunks: vec!["a".into(), "b".into()], num_tokens: 2 });
+    roundtrip(DocumentEmbedding {
+        vector: vec![1.0, 0.0],
+        chunk_vectors: vec![vec![1.0, 0.0]],
+        chunks: vec![chunk()],
+        usage,
+        parts: Some(BlendParts { document: vec![0.0, 1.0], chunks: vec![vec![1.0, 0.0]] }),
+    });
+    roundtrip(ReadResult { title: "T".into(), content: "# T".into(), url: "https://example.com".into() });
+    roundtrip(FileChunk { path: PathBuf::from("docs/a.md"), chunk_index: 1, start: 10, end: 20, embedding: vec![0.5] });
+    roundtrip(Alignment {
+        matches: vec![Match { left: "l1".into(), right: "r1".into(), score: 0.9 }],
+        unmatched_left: vec!["l2".into()],
+        unmatched_right: vec![],
+    });
+    roundtrip(DriftReport {
+        k: 1,
+        dimensions_a: 2,
+        dimensions_b: 3,
+        mean_cosine_between_versions: None,
+        topk_overlap_at_k: 1.0,
+        per_text: vec![TextDrift { text: "a".into(), cosine_between_versions: None, topk_overlap: 1.0 }],
+    });
+    roundtrip(ClientStats { requests: 1, texts_sent: 2, cache_hits: 3, hedges: 4 });
+    roundtrip(EmbeddingRecord::new("7", vec![1.0]).with_text("seven").with_metadata(chunk().metadata));
+    roundtrip(Triple::new("Ada", "wrote", "the first program"));
+    roundtrip(Job::new(4, "text"));
+    roundtrip(JobResult { id: 4, result: Ok(vec![0.5]) });
+    roundtrip(vec![Ok(vec![0.5]), Err(ItemError::Empty), Err(ItemError::Rejected { status: 422, message: "bad".into() })]);
+    roundtrip(provenance);
+}
+
+#[test]
+fn test_field_and_variant_names_are_stable() {
+    let names: Vec<String> = [Task::RetrievalQuery, Task::RetrievalPassage, Task::TextMatching, Task::Classification, Task::Separation]
+        .iter()
+        .map(|t| serde_json::to_string(t).unwrap())
+        .collect();
+    assert_eq!(names, ["\"retrieval.query\"", "\"retrieval.passage\"", "\"text-matching\"", "\"classification\"", "\"separation\""]);
+    assert_eq!(serde_json::to_value(chunk().metadata).unwrap(), serde_json::json!({"draft": true, "lang": "en", "page": 3.0}));
+    
+    let errors = [
+        JinaError::InvalidInput("empty".into()),
+        JinaError::Api { status: 429, message: "slow down".into() },
+        JinaError::InputTooLarge { index: 1, size: 9000, limit: Some(8192) },
+        JinaError::InputTooLarge { index: 1, size: 9000, limit: None },
+        JinaError::InputTooLong { index: 0, estimated: 9000, limit: 8192 },
+        JinaError::Mismatch { expected: 2, got: 1 },
+        JinaError::Route { route: "fast".into(), source: Box::new(JinaError::Connect("refused".into())) },
+        JinaError::Other("legacy".into()),
+    ];
+    for error in errors {
+        roundtrip(JobResult { id: 1, result: Err(error.clone()) });
+        roundtrip(DiagnosedError { error, diagnostics: Box::new(diagnostics()) });
+    }
+    assert_eq!(serde_json::to_value(JinaError::Api { status: 500, message: "boom".into() }).unwrap(),
+               serde_json::json!({"Api": {"status": 500, "message": "boom"}}));
+}
+
+#[test]
+fn test_older_layouts_still_load() {
+    let v0: serde_json::Value = serde_json::from_str(V0).unwrap();
+    let load = |key: &str| v0[key].clone();
+    
+    // Written before diagnostics, provenance and prompt token counts were recorded
+    let response: EmbeddingResponse = serde_json::from_value(load("embedding_response")).unwrap();
+    assert_eq!(response.usage, Usage { prompt_tokens: 0, total_tokens: 3 });
+    assert_eq!((response.diagnostics, response.provenance), (None, None));
+    
+    let provenance: Provenance = serde_json::from_value(load("provenance")).unwrap();
+    assert_eq!(provenance, Provenance::new("jina-embeddings-v3", 1024));
+    assert_eq!(provenance.schema_version, SCHEMA_VERSION);
+    
+    let chunk: Chunk = serde_json::from_value(load("chunk")).unwrap();
+    assert!(chunk.metadata.is_empty());
+    let record: EmbeddingRecord = serde_json::from_value(load("embedding_record")).unwrap();
+    assert_eq!(record, EmbeddingRecord::new("7", vec![1.0, 0.0]));
+    let hit: RerankHit = serde_json::from_value(load("rerank_hit")).unwrap();
+    assert_eq!(hit.document, None);
+    
+    let document: DocumentEmbedding = serde_json::from_value(load("document_embedding")).unwrap();
+    assert_eq!((document.usage, document.parts, document.chunks[0].metadata.len()), (Usage::default(), None, 0));
+    let diagnostics: Diagnostics = serde_json::from_value(load("diagnostics")).unwrap();
+    assert_eq!((diagnostics.bytes_sent, diagnostics.connect_ms, diagnostics.attempts), (0, None, 1));
+    let stats: ClientStats = serde_json::from_value(load("client_stats")).unwrap();
+    assert_eq!(stats, ClientStats { requests: 4, texts_sent: 9, cache_hits: 0, hedges: 0 });
+    let segmentation: Segmentation = serde_json::from_value(load("segmentation")).unwrap();
+    assert!(segmentation.chunks.is_empty());
+    
+    // Unversioned relation models load as version 1
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("model.json");
+    std::fs::write(&path, load("relation_model").to_string()).unwrap();
+    let model = RelationModel::load(path.to_str().unwrap()).unwrap();
+    assert_eq!(model.offset("capital").unwrap(), [0.5, -0.5]);
+}
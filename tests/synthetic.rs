@@ -0,0 +1,82 @@
+//! Pseudo-embeddings flagged as synthetic from response through index to export
+
+use spo_crystal::index::CrystalIndex;
+use spo_crystal::io::{export_qdrant_points, EmbeddingRecord, SYNTHETIC_FIELD};
+use spo_crystal::jina_api::{EmbedOptions, JinaClient};
+use spo_crystal::provenance::ProvenanceCheck;
+use spo_crystal::provider::{BackendKind, EmbedError, EmbeddingProvider};
+use spo_crystal::pseudo::PseudoEmbedder;
+use spo_crystal::routing::{Route, RoutingProvider};
+
+/// Stands in for a real model: one fixed direction per text length
+struct FixedModel;
+
+impl EmbeddingProvider for FixedModel {
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        Ok(texts.iter().map(|t| vec![1.0, t.len() as f32, 0.0, 0.0]).collect())
+    }
+    
+    fn dimensions(&self) -> usize { 4 }
+}
+
+#[test]
+fn test_synthetic_flag_reaches_index_and_export() {
+    let options = EmbedOptions::passage().with_dimensions(4);
+    let offline = JinaClient::new("");
+    assert!(offline.is_offline());
+    let response = offline.embed_batch_full(&["Ada", "Grace"], &options).unwrap();
+    assert_eq!(response.backend, BackendKind::Offline);
+    let provenance = response.provenance.clone().unwrap();
+    assert!(provenance.synthetic && provenance.to_string().contains("synthetic"));
+    
+    // The index keeps the flag through a save
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("synthetic.idx");
+    let mut index = CrystalIndex::new(4).with_provenance(provenance.clone());
+    index.add_response(&[1, 2], &response, ProvenanceCheck::Enforce).unwrap();
+    index.save(path.to_str().unwrap()).unwrap();
+    let loaded = CrystalIndex::load(path.to_str().unwrap()).unwrap();
+    assert!(loaded.provenance().unwrap().synthetic);
+    
+    // And the export carries it in every payload
+    let records = EmbeddingRecord::from_response(&["1", "2"], &response).unwrap();
+    let mut out = Vec::new();
+    export_qdrant_points(&mut out, &records).unwrap();
+    let line: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    let points = line["points"].as_array().unwrap();
+    assert_eq!(points.len(), 2);
+    assert!(points.iter().all(|p| p["payload"][SYNTHETIC_FIELD] == true));
+    
+    let real = JinaClient::new("").with_backend(FixedModel).embed_batch_full(&["Ada"], &options).unwrap();
+    assert_eq!(real.backend, BackendKind::Provider);
+    let records = EmbeddingRecord::from_response(&["3"], &real).unwrap();
+    assert_eq!(records[0].metadata.get_bool(SYNTHETIC_FIELD), Some(false));
+}
+
+#[test]
+fn test_mixing_synthetic_and_real_vectors_needs_override() {
+    let options = EmbedOptions::passage().with_dimensions(4);
+    let real_client = JinaClient::new("").with_backend(FixedModel);
+    assert!(!real_client.is_offline());
+    let real = real_client.embed_batch_full(&["Ada"], &options).unwrap();
+    let mut index = CrystalIndex::new(4).with_provenance(real.provenance.clone().unwrap());
+    index.add_response(&[1], &real, ProvenanceCheck::Enforce).unwrap();
+    
+    let synthetic = JinaClient::new("").embed_batch_full(&["Grace"], &options).unwrap();
+    let err = index.add_response(&[2], &synthetic, ProvenanceCheck::Enforce).unwrap_err().to_string();
+    assert!(err.contains("real vectors") && err.contains("synthetic") && err.contains("refusing to mix"), "{}", err);
+    assert_eq!(index.len(), 1);
+    index.add_response(&[2], &synthetic, ProvenanceCheck::Override).unwrap();
+    assert_eq!(index.len(), 2);
+    
+    // A pseudo-embedder of last resort behind a router makes the client synthetic
+    let router = RoutingProvider::new(vec![
+        Route::new("model", FixedModel).shorter_than(10),
+        Route::new("pseudo", PseudoEmbedder::new(4)),
+    ]).unwrap();
+    let routed = JinaClient::new("").with_backend(router);
+    assert!(routed.is_offline());
+    let response = routed.embed_batch_full(&["short", "a much longer text"], &options).unwrap();
+    assert_eq!(response.backend, BackendKind::Offline);
+    assert!(index.add_response(&[3, 4], &response, ProvenanceCheck::Enforce).is_err());
+}
@@ -0,0 +1,48 @@
+//! Pure-compute modules under wasm32, run in Node by wasm-bindgen-test:
+//!
+//!     cargo test --target wasm32-unknown-unknown --test wasm
+//!
+//! (`.cargo/config.toml` sets `wasm-bindgen-test-runner` as the runner; install
+//! it with `cargo install wasm-bindgen-cli` at the crate's wasm-bindgen version.)
+#![cfg(target_arch = "wasm32")]
+
+use spo_crystal::async_client::AsyncJinaClient;
+use spo_crystal::chunk::chunk_text;
+use spo_crystal::pseudo::PseudoEmbedder;
+use spo_crystal::quantize::Int8Vector;
+use spo_crystal::search::{cosine, top_k, top_k_batch};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn test_pseudo_embedder_and_similarity() {
+    let embedder = PseudoEmbedder::new(256);
+    let texts = ["the cat sat on the mat", "a cat sat on a mat", "stock prices fell sharply"];
+    let embeddings = embedder.embed_batch(&texts);
+    // Same vectors as on native targets: deterministic and unit norm
+    assert_eq!(embeddings[0], embedder.embed(texts[0]));
+    assert!((cosine(&embeddings[0], &embeddings[0]) - 1.0).abs() < 1e-5);
+    assert!(cosine(&embeddings[0], &embeddings[1]) > cosine(&embeddings[0], &embeddings[2]));
+    
+    // The rayon paths fall back to the calling thread
+    let hits = top_k(&embeddings[0], &embeddings, 2);
+    assert_eq!(hits.iter().map(|&(i, _)| i).collect::<Vec<_>>(), [0, 1]);
+    assert_eq!(top_k_batch(&embeddings[..1], &embeddings, 2), [hits]);
+}
+
+#[wasm_bindgen_test]
+fn test_chunking_and_quantization() {
+    let text = "First sentence here. Second one follows. A third closes the paragraph.";
+    let chunks = chunk_text(text, 30);
+    assert!(chunks.len() > 1);
+    assert!(chunks.iter().all(|c| text[c.start..c.end] == c.text));
+    
+    let v = PseudoEmbedder::new(64).embed("quantize me");
+    let q = Int8Vector::quantize(&v);
+    assert!(q.dequantize().iter().zip(&v).all(|(a, b)| (a - b).abs() <= q.max_error() + 1e-6));
+}
+
+#[wasm_bindgen_test]
+async fn test_async_client_embeds_offline() {
+    let embedding = AsyncJinaClient::new("").embed("hello").await.unwrap();
+    assert_eq!(embedding, PseudoEmbedder::new(1024).embed("hello"));
+}